@@ -0,0 +1,201 @@
+//! Integer constant expressions, for `#if`, enum values, array sizes and
+//! `#define`s
+//!
+//! Values are computed in `i128` and wrapped to the width of a cast or a
+//! suffixed literal, so `~0u` is `0xffffffff` as in C.
+
+use crate::lex::{Spanned, Token};
+use crate::parse::CType;
+
+/// What names and casts mean where the expression appears
+pub(crate) trait Scope {
+    fn value(&self, name: &str) -> Option<i128>;
+
+    /// The type named by the tokens between a cast's parentheses
+    fn cast(&self, _tokens: &[Spanned]) -> Option<CType> {
+        None
+    }
+
+    /// The bit width and signedness of an integer type
+    fn width(&self, _ty: &CType) -> Option<(u32, bool)> {
+        None
+    }
+}
+
+/// A value, with the type of the cast or literal suffix that gave it one
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Value {
+    pub value: i128,
+    pub ty: Option<CType>,
+}
+
+/// Evaluate `tokens`, which must form exactly one expression
+pub(crate) fn evaluate(tokens: &[Spanned], scope: &dyn Scope) -> Result<(i128, Option<CType>), String> {
+    let mut eval = Eval { tokens, pos: 0, scope };
+    let value = eval.ternary()?;
+    match tokens.get(eval.pos) {
+        None => Ok((value.value, value.ty)),
+        Some(extra) => Err(format!("unexpected {} in constant expression", describe(&extra.token))),
+    }
+}
+
+pub(crate) fn describe(token: &Token) -> String {
+    match token {
+        Token::Ident(name) => format!("`{}`", name),
+        Token::Int(value, _) => format!("`{}`", value),
+        Token::Float => "floating-point literal".to_string(),
+        Token::Str(_) => "string literal".to_string(),
+        Token::Punct(p) => format!("`{}`", p),
+    }
+}
+
+struct Eval<'t, 's> {
+    tokens: &'t [Spanned],
+    pos: usize,
+    scope: &'s dyn Scope,
+}
+
+/// Binary operators by precedence, loosest first
+const LEVELS: &[&[&str]] =
+    &[&["||"], &["&&"], &["|"], &["^"], &["&"], &["==", "!="], &["<", ">", "<=", ">="], &["<<", ">>"], &["+", "-"], &[
+        "*", "/", "%",
+    ]];
+
+impl Eval<'_, '_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|s| &s.token)
+    }
+
+    fn eat(&mut self, punct: &str) -> bool {
+        let found = self.peek().is_some_and(|t| t.is(punct));
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn ternary(&mut self) -> Result<Value, String> {
+        let condition = self.binary(0)?;
+        if !self.eat("?") {
+            return Ok(condition);
+        }
+        let then = self.ternary()?;
+        if !self.eat(":") {
+            return Err("expected `:` in conditional expression".to_string());
+        }
+        let otherwise = self.ternary()?;
+        Ok(if condition.value != 0 { then } else { otherwise })
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Value, String> {
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+        let mut lhs = self.binary(level + 1)?;
+        while let Some(op) = LEVELS[level].iter().find(|op| self.peek().is_some_and(|t| t.is(op))) {
+            self.pos += 1;
+            let rhs = self.binary(level + 1)?;
+            let (l, r) = (lhs.value, rhs.value);
+            let value = match *op {
+                "||" => Some(i128::from(l != 0 || r != 0)),
+                "&&" => Some(i128::from(l != 0 && r != 0)),
+                "|" => Some(l | r),
+                "^" => Some(l ^ r),
+                "&" => Some(l & r),
+                "==" => Some(i128::from(l == r)),
+                "!=" => Some(i128::from(l != r)),
+                "<" => Some(i128::from(l < r)),
+                ">" => Some(i128::from(l > r)),
+                "<=" => Some(i128::from(l <= r)),
+                ">=" => Some(i128::from(l >= r)),
+                "<<" => u32::try_from(r).ok().and_then(|r| l.checked_shl(r)),
+                ">>" => u32::try_from(r).ok().and_then(|r| l.checked_shr(r)),
+                "+" => l.checked_add(r),
+                "-" => l.checked_sub(r),
+                "*" => l.checked_mul(r),
+                "/" => l.checked_div(r),
+                "%" => l.checked_rem(r),
+                _ => unreachable!("every operator in LEVELS is handled"),
+            };
+            let value = value.ok_or_else(|| format!("`{} {} {}` overflows or divides by zero", l, op, r))?;
+            let ty = match (lhs.ty, rhs.ty) {
+                _ if matches!(*op, "||" | "&&" | "==" | "!=" | "<" | ">" | "<=" | ">=") => None,
+                (Some(ty), _) | (None, Some(ty)) => Some(ty),
+                (None, None) => None,
+            };
+            lhs = self.typed(value, ty);
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Value, String> {
+        let Some(token) = self.peek().cloned() else {
+            return Err("expected a constant expression".to_string());
+        };
+        self.pos += 1;
+        match token {
+            Token::Punct("-") => {
+                let operand = self.unary()?;
+                let value = operand.value.checked_neg().ok_or("negation overflows")?;
+                Ok(self.typed(value, operand.ty))
+            }
+            Token::Punct("+") => self.unary(),
+            Token::Punct("~") => {
+                let operand = self.unary()?;
+                Ok(self.typed(!operand.value, operand.ty))
+            }
+            Token::Punct("!") => Ok(Value { value: i128::from(self.unary()?.value == 0), ty: None }),
+            Token::Punct("(") => {
+                let start = self.pos;
+                let mut depth = 1;
+                while depth > 0 {
+                    match self.peek() {
+                        Some(t) if t.is("(") => depth += 1,
+                        Some(t) if t.is(")") => depth -= 1,
+                        Some(_) => {}
+                        None => return Err("expected `)`".to_string()),
+                    }
+                    self.pos += 1;
+                }
+                if let Some(ty) = self.scope.cast(&self.tokens[start..self.pos - 1]) {
+                    let operand = self.unary()?;
+                    return Ok(self.typed(operand.value, Some(ty)));
+                }
+                self.pos = start;
+                let inner = self.ternary()?;
+                if !self.eat(")") {
+                    return Err("expected `)`".to_string());
+                }
+                Ok(inner)
+            }
+            Token::Int(value, suffix) => {
+                let ty = match (suffix.unsigned, suffix.long) {
+                    (false, false) => None,
+                    (true, false) => Some(CType::UInt),
+                    (false, true) => Some(CType::Long),
+                    (true, true) => Some(CType::ULong),
+                };
+                let value = i128::try_from(value).map_err(|_| format!("`{}` is too large", value))?;
+                Ok(self.typed(value, ty))
+            }
+            Token::Ident(name) => match self.scope.value(&name) {
+                Some(value) => Ok(Value { value, ty: None }),
+                None => Err(format!("`{}` is not an integer constant", name)),
+            },
+            other => Err(format!("unexpected {} in constant expression", describe(&other))),
+        }
+    }
+
+    /// `value` wrapped to the width of `ty`, as a C conversion would
+    fn typed(&self, value: i128, ty: Option<CType>) -> Value {
+        let value = match ty.as_ref().and_then(|ty| self.scope.width(ty)) {
+            Some((bits, signed)) => {
+                let modulus = 1i128 << bits;
+                let wrapped = value.rem_euclid(modulus);
+                if signed && wrapped >= modulus / 2 { wrapped - modulus } else { wrapped }
+            }
+            None => value,
+        };
+        Value { value, ty }
+    }
+}
@@ -0,0 +1,447 @@
+//! Tokens and the preprocessor subset
+//!
+//! Comments and line continuations are removed, `#if`/`#ifdef`/`#ifndef`
+//! blocks are resolved the way a C compiler would see them (identifiers the
+//! header never defines count as 0, so `__cplusplus` sections drop out) and
+//! object-like macros are expanded. `#include`, `#pragma` and function-like
+//! macros are ignored. Each object-like `#define` is also kept as a
+//! [`Define`] so the parser can try to read it as a constant.
+
+use std::collections::{HashMap, HashSet};
+
+use fig_sema::diagnostics::Diagnostic;
+
+use crate::expr::{self, Scope};
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Token {
+    Ident(String),
+    Int(u128, IntSuffix),
+    /// Floating-point literals only ever need skipping
+    Float,
+    Str(String),
+    Punct(&'static str),
+}
+
+/// The integer suffix, which decides the type of an uncast `#define`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct IntSuffix {
+    pub unsigned: bool,
+    pub long: bool,
+}
+
+/// A token and the 1-based line it starts on
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Spanned {
+    pub token: Token,
+    pub line: usize,
+}
+
+/// An object-like `#define NAME body`, with the body macro-expanded as far
+/// as the macros defined before it allow
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Define {
+    pub name: String,
+    pub body: Vec<Spanned>,
+    pub line: usize,
+}
+
+/// A header after preprocessing
+pub(crate) struct Preprocessed {
+    pub tokens: Vec<Spanned>,
+    pub defines: Vec<Define>,
+}
+
+impl Token {
+    pub(crate) fn is(&self, punct: &str) -> bool {
+        matches!(self, Token::Punct(p) if *p == punct)
+    }
+
+    pub(crate) fn is_ident(&self, name: &str) -> bool {
+        matches!(self, Token::Ident(n) if n == name)
+    }
+}
+
+/// Longest first, so that `<<=` is not read as `<<` `=`
+const PUNCTUATORS: &[&str] = &[
+    "...", "<<=", ">>=", "->", "++", "--", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "*=", "/=", "%=", "+=",
+    "-=", "&=", "^=", "|=", "##", "{", "}", "(", ")", "[", "]", ";", ",", "*", "=", ":", "?", "+", "-", "~", "!",
+    "/", "%", "<", ">", "&", "^", "|", ".", "#",
+];
+
+/// Preprocess and tokenise a header
+pub(crate) fn tokenize(src: &str) -> Result<Preprocessed, Vec<Diagnostic>> {
+    let mut pp = Preprocessor::default();
+    let lines = logical_lines(src).map_err(|d| vec![d])?;
+    for (line, text) in lines {
+        pp.line(line, &text).map_err(|d| vec![d])?;
+    }
+    if let Some(line) = pp.conditions.last().map(|c| c.line) {
+        return Err(vec![Diagnostic::error(format!("line {}: `#if` without `#endif`", line))]);
+    }
+    Ok(Preprocessed { tokens: pp.out, defines: pp.defines })
+}
+
+/// Join continued lines and blank out comments, keeping line numbers
+fn logical_lines(src: &str) -> Result<Vec<(usize, String)>, Diagnostic> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    let mut start = 1;
+    let mut line = 1;
+    let mut chars = src.chars().peekable();
+    let mut in_block_comment = false;
+    while let Some(c) = chars.next() {
+        if in_block_comment {
+            if c == '*' && chars.peek() == Some(&'/') {
+                chars.next();
+                in_block_comment = false;
+                current.push(' ');
+            } else if c == '\n' {
+                line += 1;
+            }
+            continue;
+        }
+        match c {
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                in_block_comment = true;
+            }
+            '/' if chars.peek() == Some(&'/') => {
+                while chars.peek().is_some_and(|&c| c != '\n') {
+                    chars.next();
+                }
+            }
+            '\\' if chars.peek() == Some(&'\n') => {
+                chars.next();
+                line += 1;
+            }
+            '"' | '\'' => {
+                current.push(c);
+                while let Some(inner) = chars.next() {
+                    current.push(inner);
+                    if inner == '\\' {
+                        if let Some(escaped) = chars.next() {
+                            current.push(escaped);
+                        }
+                    } else if inner == c {
+                        break;
+                    } else if inner == '\n' {
+                        return Err(Diagnostic::error(format!("line {}: unterminated literal", line)));
+                    }
+                }
+            }
+            '\n' => {
+                lines.push((start, std::mem::take(&mut current)));
+                line += 1;
+                start = line;
+            }
+            c => current.push(c),
+        }
+    }
+    if in_block_comment {
+        return Err(Diagnostic::error(format!("line {}: unterminated comment", line)));
+    }
+    lines.push((start, current));
+    Ok(lines)
+}
+
+/// Split one logical line into tokens
+fn lex_line(line: usize, text: &str) -> Result<Vec<Spanned>, Diagnostic> {
+    let error = |message: String| Diagnostic::error(format!("line {}: {}", line, message));
+    let bytes = text.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        if c.is_ascii_whitespace() {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        let token = if c.is_ascii_alphabetic() || c == b'_' {
+            while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                i += 1;
+            }
+            Token::Ident(text[start..i].to_string())
+        } else if c.is_ascii_digit() || (c == b'.' && bytes.get(i + 1).is_some_and(u8::is_ascii_digit)) {
+            while i < bytes.len()
+                && (bytes[i].is_ascii_alphanumeric()
+                    || bytes[i] == b'.'
+                    || (matches!(bytes[i], b'+' | b'-') && matches!(bytes[i - 1], b'e' | b'E' | b'p' | b'P')))
+            {
+                i += 1;
+            }
+            number(&text[start..i]).map_err(error)?
+        } else if c == b'"' || c == b'\'' {
+            i += 1;
+            let mut value = String::new();
+            while i < bytes.len() && bytes[i] != c {
+                if bytes[i] == b'\\' && i + 1 < bytes.len() {
+                    i += 1;
+                    value.push(match bytes[i] {
+                        b'n' => '\n',
+                        b't' => '\t',
+                        b'r' => '\r',
+                        b'0' => '\0',
+                        other => other as char,
+                    });
+                } else {
+                    value.push(bytes[i] as char);
+                }
+                i += 1;
+            }
+            i += 1;
+            if c == b'"' {
+                Token::Str(value)
+            } else {
+                let mut chars = value.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(ch), None) => Token::Int(u128::from(u32::from(ch)), IntSuffix { unsigned: false, long: false }),
+                    _ => return Err(error(format!("unsupported character constant `{}`", &text[start..i]))),
+                }
+            }
+        } else {
+            let Some(punct) = PUNCTUATORS.iter().find(|p| text[i..].starts_with(**p)) else {
+                return Err(error(format!("unexpected character `{}`", c as char)));
+            };
+            i += punct.len();
+            Token::Punct(punct)
+        };
+        tokens.push(Spanned { token, line });
+    }
+    Ok(tokens)
+}
+
+/// An integer or floating-point literal
+fn number(text: &str) -> Result<Token, String> {
+    let lower = text.to_ascii_lowercase();
+    let is_hex = lower.starts_with("0x");
+    if (!is_hex && (lower.contains('.') || lower.contains('e'))) || (is_hex && lower.contains('p')) {
+        return Ok(Token::Float);
+    }
+    let digits_end = lower.trim_end_matches(['u', 'l']).len();
+    let (digits, suffix) = lower.split_at(digits_end);
+    let suffix = IntSuffix { unsigned: suffix.contains('u'), long: suffix.contains('l') };
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        u128::from_str_radix(hex, 16)
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        u128::from_str_radix(binary, 2)
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        u128::from_str_radix(&digits[1..], 8)
+    } else {
+        digits.parse()
+    };
+    value.map(|value| Token::Int(value, suffix)).map_err(|_| format!("invalid integer literal `{}`", text))
+}
+
+/// One level of `#if` nesting
+struct Condition {
+    line: usize,
+    /// Whether the lines of the current branch are kept
+    active: bool,
+    /// Whether an earlier branch was taken, so later ones are not
+    taken: bool,
+    /// Whether the enclosing block is active at all
+    parent: bool,
+}
+
+#[derive(Default)]
+struct Preprocessor {
+    macros: HashMap<String, Vec<Spanned>>,
+    /// Function-like macros, which are only ever tested with `defined`
+    function_macros: HashSet<String>,
+    conditions: Vec<Condition>,
+    out: Vec<Spanned>,
+    defines: Vec<Define>,
+}
+
+impl Preprocessor {
+    fn active(&self) -> bool {
+        self.conditions.last().is_none_or(|c| c.active)
+    }
+
+    fn line(&mut self, line: usize, text: &str) -> Result<(), Diagnostic> {
+        let trimmed = text.trim_start();
+        let Some(directive) = trimmed.strip_prefix('#') else {
+            if self.active() {
+                let tokens = lex_line(line, text)?;
+                let expanded = self.expand(tokens, &mut Vec::new());
+                self.out.extend(expanded);
+            }
+            return Ok(());
+        };
+        let directive = directive.trim_start();
+        let name_end = directive.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(directive.len());
+        let (name, rest) = directive.split_at(name_end);
+        let error = |message: &str| Diagnostic::error(format!("line {}: {}", line, message));
+        match name {
+            "if" | "ifdef" | "ifndef" => {
+                let parent = self.active();
+                let active = parent && self.condition(line, name, rest)?;
+                self.conditions.push(Condition { line, active, taken: active, parent });
+            }
+            "elif" => {
+                let active = {
+                    let Some(c) = self.conditions.last() else { return Err(error("`#elif` without `#if`")) };
+                    c.parent && !c.taken
+                };
+                let active = active && self.condition(line, "if", rest)?;
+                let c = self.conditions.last_mut().expect("checked above");
+                c.active = active;
+                c.taken |= active;
+            }
+            "else" => {
+                let Some(c) = self.conditions.last_mut() else { return Err(error("`#else` without `#if`")) };
+                c.active = c.parent && !c.taken;
+                c.taken = true;
+            }
+            "endif" => {
+                self.conditions.pop().ok_or_else(|| error("`#endif` without `#if`"))?;
+            }
+            _ if !self.active() => {}
+            "define" => {
+                let rest = rest.trim_start();
+                let name_end = rest.find(|c: char| !c.is_ascii_alphanumeric() && c != '_').unwrap_or(rest.len());
+                let (macro_name, body) = rest.split_at(name_end);
+                if macro_name.is_empty() {
+                    return Err(error("`#define` without a name"));
+                }
+                if body.starts_with('(') {
+                    self.function_macros.insert(macro_name.to_string());
+                    return Ok(());
+                }
+                let body = lex_line(line, body)?;
+                let expanded = self.expand(body.clone(), &mut vec![macro_name.to_string()]);
+                self.macros.insert(macro_name.to_string(), body);
+                self.defines.push(Define { name: macro_name.to_string(), body: expanded, line });
+            }
+            "undef" => {
+                let macro_name = rest.trim();
+                self.macros.remove(macro_name);
+                self.function_macros.remove(macro_name);
+            }
+            "error" => return Err(error(&format!("#error{}", rest))),
+            // `#include`, `#pragma`, `#line`, `#warning` and null directives
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Evaluate the condition of an `#if`, `#ifdef` or `#ifndef`
+    fn condition(&self, line: usize, directive: &str, rest: &str) -> Result<bool, Diagnostic> {
+        let defined = |name: &str| self.macros.contains_key(name) || self.function_macros.contains(name);
+        match directive {
+            "ifdef" => Ok(defined(rest.trim())),
+            "ifndef" => Ok(!defined(rest.trim())),
+            _ => {
+                // `defined` is resolved before expansion, so `defined(X)`
+                // sees the name rather than X's body
+                let mut tokens = Vec::new();
+                let mut lexed = lex_line(line, rest)?.into_iter().peekable();
+                while let Some(spanned) = lexed.next() {
+                    if !spanned.token.is_ident("defined") {
+                        tokens.push(spanned);
+                        continue;
+                    }
+                    let parenthesised = lexed.next_if(|s| s.token.is("(")).is_some();
+                    let Some(Spanned { token: Token::Ident(name), .. }) = lexed.next() else {
+                        return Err(Diagnostic::error(format!("line {}: `defined` needs a macro name", line)));
+                    };
+                    if parenthesised && lexed.next_if(|s| s.token.is(")")).is_none() {
+                        return Err(Diagnostic::error(format!("line {}: expected `)` after `defined(`", line)));
+                    }
+                    let value = u128::from(defined(&name));
+                    tokens.push(Spanned { token: Token::Int(value, IntSuffix { unsigned: false, long: false }), line });
+                }
+                let tokens = self.expand(tokens, &mut Vec::new());
+                expr::evaluate(&tokens, &Undefined)
+                    .map(|(value, _)| value != 0)
+                    .map_err(|message| Diagnostic::error(format!("line {}: in `#if`: {}", line, message)))
+            }
+        }
+    }
+
+    /// Replace object-like macros by their bodies. `hidden` holds the macros
+    /// being expanded, which C does not expand again inside themselves.
+    fn expand(&self, tokens: Vec<Spanned>, hidden: &mut Vec<String>) -> Vec<Spanned> {
+        let mut out = Vec::with_capacity(tokens.len());
+        for spanned in tokens {
+            match &spanned.token {
+                Token::Ident(name) if !hidden.contains(name) && self.macros.contains_key(name) => {
+                    hidden.push(name.clone());
+                    let body = self.macros[name].iter().map(|s| Spanned { token: s.token.clone(), line: spanned.line });
+                    out.extend(self.expand(body.collect(), hidden));
+                    hidden.pop();
+                }
+                _ => out.push(spanned),
+            }
+        }
+        out
+    }
+}
+
+/// The scope of an `#if`: every identifier left after expansion is 0
+struct Undefined;
+
+impl Scope for Undefined {
+    fn value(&self, _: &str) -> Option<i128> {
+        Some(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn idents(src: &str) -> Vec<String> {
+        tokenize(src)
+            .unwrap()
+            .tokens
+            .into_iter()
+            .filter_map(|s| match s.token {
+                Token::Ident(name) => Some(name),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_resolves_conditionals() {
+        let src = "\
+#ifndef GUARD_H
+#define GUARD_H
+#ifdef __cplusplus
+extern \"C\" {
+#endif
+#if defined(GUARD_H) && VERSION >= 2
+two
+#elif !defined(MISSING)
+one
+#else
+zero
+#endif
+#if 0 /* a comment */
+skipped
+#endif
+#endif
+";
+        assert_eq!(idents(src), vec!["one"]);
+    }
+
+    #[test]
+    fn test_expands_object_like_macros() {
+        let src = "#define PACKED __attribute__((packed))\n#define MAX(a, b) a\nstruct PACKED s; // PACKED\nMAX\n";
+        assert_eq!(idents(src), vec!["struct", "__attribute__", "packed", "s", "MAX"]);
+        let defines = tokenize("#define A (B + 1)\n#define B 0x10u\n").unwrap().defines;
+        assert_eq!((defines[0].name.as_str(), defines[0].body.len()), ("A", 5));
+        let literal = Token::Int(16, IntSuffix { unsigned: true, long: false });
+        assert_eq!(defines[1].body, vec![Spanned { token: literal, line: 2 }]);
+    }
+
+    #[test]
+    fn test_reports_unbalanced_conditionals() {
+        assert!(tokenize("#if 1\nint x;\n").is_err());
+        assert!(tokenize("#endif\n").is_err());
+        assert!(tokenize("/* open").is_err());
+    }
+}
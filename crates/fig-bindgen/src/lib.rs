@@ -0,0 +1,174 @@
+//! Fig declarations from C headers
+//!
+//! [`import_header`] reads the subset of C found in library headers —
+//! function prototypes, structs, unions, enums, typedefs and integer
+//! `#define`s — and translates it into Fig items: `extern func!`
+//! declarations, (`packed`) structs, enums, type aliases and consts. The
+//! result renders through [`fig_parser::format::format_item`], so it parses
+//! back as ordinary Fig source. Integer widths and the layout of imported
+//! unions follow the [`Target`] the bindings are for.
+//!
+//! ```ignore
+//! let bindings = import_header("size_t strlen(const char *s);", Target::X86_64)?;
+//! assert_eq!(bindings.to_source(), "// Generated by fig bindgen\n\nextern func! strlen(s: ?*u8) -> usize\n");
+//! ```
+//!
+//! The preprocessor handles comments, `#define` and conditional compilation;
+//! `#include` is ignored, so the types a header takes from the system (apart
+//! from the `<stdint.h>` and `<stddef.h>` ones) must be declared in it.
+//! Declarations that cannot be expressed in Fig are dropped with a warning
+//! rather than failing the import.
+
+mod expr;
+mod lex;
+mod parse;
+mod translate;
+
+use fig_parser::ast::{NamespaceItem, SourceFile};
+use fig_parser::format::format_item;
+use fig_sema::diagnostics::Diagnostic;
+use fig_sema::layout::Target;
+
+/// The Fig translation of a header
+#[derive(Debug)]
+pub struct Bindings {
+    pub source_file: SourceFile,
+    /// Declarations left out or imported approximately
+    pub warnings: Vec<Diagnostic>,
+}
+
+impl Bindings {
+    /// Fig source for the bindings, one item per declaration in header order
+    pub fn to_source(&self) -> String {
+        let mut out = String::from("// Generated by fig bindgen\n");
+        let mut previous: Option<(std::mem::Discriminant<NamespaceItem>, bool)> = None;
+        for item in &self.source_file.items {
+            let Some(text) = format_item(item) else { continue };
+            let single_line = text.lines().count() == 1;
+            // Runs of one-line items of the same kind stay together
+            let kind = std::mem::discriminant(item);
+            if previous != Some((kind, true)) || !single_line {
+                out.push('\n');
+            }
+            out.push_str(&text);
+            previous = Some((kind, single_line));
+        }
+        out
+    }
+}
+
+/// Translate the C header `src` into Fig declarations for `target`
+pub fn import_header(src: &str, target: Target) -> Result<Bindings, Vec<Diagnostic>> {
+    let preprocessed = lex::tokenize(src)?;
+    let decls = parse::parse(preprocessed.tokens, preprocessed.defines, target);
+    let (items, warnings) = translate::translate(&decls, target);
+    Ok(Bindings { source_file: SourceFile::new(items), warnings })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn import(src: &str) -> Bindings {
+        import_for(src, Target::X86_64)
+    }
+
+    fn import_for(src: &str, target: Target) -> Bindings {
+        let bindings = import_header(src, target).unwrap_or_else(|diags| panic!("{:?}", diags));
+        let source = bindings.to_source();
+        let reparsed = fig_parser::SourceFileParser::new()
+            .parse(fig_parser::Lexer::new(&source))
+            .unwrap_or_else(|e| panic!("{:?} in\n{}", e, source));
+        assert_eq!(reparsed.items.len(), bindings.source_file.items.len(), "{}", source);
+        bindings
+    }
+
+    #[test]
+    fn test_functions_and_typedefs() {
+        let b = import(
+            "\
+#include <stddef.h>
+typedef unsigned int mode_t;
+void *malloc(size_t size);
+void free(void *ptr);
+int open(const char *path, int flags, ...);
+mode_t umask(mode_t mask);
+static inline int twice(int x) { return 2 * x; }
+",
+        );
+        let source = b.to_source();
+        assert!(source.contains("type mode_t = u32\n"), "{}", source);
+        assert!(source.contains("extern func! malloc(size: usize) -> ?*mut u8\n"), "{}", source);
+        assert!(source.contains("extern func! free(ptr: ?*mut u8) -> ok\n"), "{}", source);
+        assert!(source.contains("extern func! open(path: ?*u8, flags: i32) -> i32\n"), "{}", source);
+        assert!(source.contains("extern func! umask(mask: mode_t) -> mode_t\n"), "{}", source);
+        assert!(!source.contains("twice"), "{}", source);
+        let warnings: Vec<_> = b.warnings.iter().map(|w| w.message.as_str()).collect();
+        assert_eq!(warnings.len(), 2, "{:?}", warnings);
+        assert!(warnings[0].starts_with("line 5: `open` is variadic"), "{:?}", warnings);
+        assert!(warnings[1].contains("`twice`"), "{:?}", warnings);
+    }
+
+    #[test]
+    fn test_records_enums_and_constants() {
+        let b = import(
+            "\
+#define VERSION 3
+#define FLAG_A (1u << 4)
+#define LIMIT ((uint16_t)0x100)
+#define BIG 0x100000000
+#define NAME \"png\"
+typedef struct {
+    uint8_t tag;
+    uint32_t len;
+} __attribute__((packed)) header_t;
+struct point { int x, y; };
+union value { int i; double d; };
+struct node { struct node *next; struct handle *owner; char name[16]; };
+enum color { RED, GREEN = 4, BLUE };
+enum { MAX_DEPTH = 8 };
+",
+        );
+        let source = b.to_source();
+        for expected in [
+            "const VERSION = 3\n",
+            "const FLAG_A: u32 = 16\n",
+            "const LIMIT: u16 = 256\n",
+            "const BIG: i64 = 4294967296\n",
+            "packed struct header_t\n    tag: u8\n    len: u32\n",
+            "struct point\n    x: i32\n    y: i32\n",
+            "#align(8)\nstruct value\n    bytes: [u8; 8]\n",
+            "struct node\n    next: ?*mut node\n    owner: ?*mut handle\n    name: [u8; 16]\n",
+            "enum[i32] color\n    RED\n    GREEN = 4\n    BLUE\n",
+            "const MAX_DEPTH: i32 = 8\n",
+            "struct handle\n",
+        ] {
+            assert!(source.contains(expected), "missing {:?} in\n{}", expected, source);
+        }
+        assert!(!source.contains("NAME"), "{}", source);
+        assert!(b.warnings.iter().any(|w| w.message.contains("`value` is imported as 8 opaque bytes")), "{:?}", b.warnings);
+    }
+
+    #[test]
+    fn test_sizes_follow_the_target() {
+        let src = "#define MASK ((unsigned long)-1)\nunion value { long l; void *p; };\nlong tell(void);\n";
+        let source = import_for(src, Target::X86_64).to_source();
+        assert!(source.contains("const MASK: u64 = 18446744073709551615\n"), "{}", source);
+        assert!(source.contains("#align(8)\nstruct value\n    bytes: [u8; 8]\n"), "{}", source);
+        assert!(source.contains("extern func! tell() -> i64\n"), "{}", source);
+        let source = import_for(src, Target::WASM32).to_source();
+        assert!(source.contains("const MASK: u32 = 4294967295\n"), "{}", source);
+        assert!(source.contains("#align(4)\nstruct value\n    bytes: [u8; 4]\n"), "{}", source);
+        assert!(source.contains("extern func! tell() -> i32\n"), "{}", source);
+    }
+
+    #[test]
+    fn test_keywords_are_escaped() {
+        let b = import("struct range { int in; int type; };\nint find(int ok, int match);\nint match(int x);\n");
+        let source = b.to_source();
+        assert!(source.contains("    in_: i32\n    type_: i32\n"), "{}", source);
+        assert!(source.contains("extern func! find(ok_: i32, match_: i32) -> i32\n"), "{}", source);
+        assert!(!source.contains("func! match"), "{}", source);
+        assert!(b.warnings[0].message.contains("`match`: the name is a Fig keyword"), "{:?}", b.warnings);
+    }
+}
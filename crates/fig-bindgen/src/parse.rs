@@ -0,0 +1,873 @@
+//! Declarations from a preprocessed header
+//!
+//! The parser reads what headers declare at file scope: functions, records,
+//! enums, typedefs and variables. A declaration it cannot read, such as one
+//! with a bit-field or an unknown type name, is skipped up to its `;` and
+//! reported as [`DeclKind::Skipped`] rather than failing the whole header.
+//! Inline function bodies are skipped by matching braces.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use fig_sema::layout::Target;
+
+use crate::expr::{self, Scope, describe};
+use crate::lex::{Define, Spanned, Token};
+
+/// Typedefs from `<stdint.h>`, `<stddef.h>` and `<sys/types.h>` that headers
+/// use without declaring, with their width and signedness. A width of `None`
+/// is that of a pointer on the target.
+pub(crate) const BUILTIN_TYPEDEFS: &[(&str, Option<u32>, bool)] = &[
+    ("int8_t", Some(8), true),
+    ("int16_t", Some(16), true),
+    ("int32_t", Some(32), true),
+    ("int64_t", Some(64), true),
+    ("uint8_t", Some(8), false),
+    ("uint16_t", Some(16), false),
+    ("uint32_t", Some(32), false),
+    ("uint64_t", Some(64), false),
+    ("intptr_t", None, true),
+    ("uintptr_t", None, false),
+    ("intmax_t", Some(64), true),
+    ("uintmax_t", Some(64), false),
+    ("size_t", None, false),
+    ("ssize_t", None, true),
+    ("ptrdiff_t", None, true),
+    ("wchar_t", Some(32), true),
+];
+
+/// The width and signedness of one of the [`BUILTIN_TYPEDEFS`] on `target`
+pub(crate) fn builtin_width(name: &str, target: Target) -> Option<(u32, bool)> {
+    let (_, bits, signed) = BUILTIN_TYPEDEFS.iter().find(|(n, _, _)| *n == name)?;
+    Some((bits.unwrap_or(target.pointer_width as u32 * 8), *signed))
+}
+
+/// A C type as declared
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum CType {
+    Void,
+    Bool,
+    Char,
+    SChar,
+    UChar,
+    Short,
+    UShort,
+    Int,
+    UInt,
+    Long,
+    ULong,
+    LongLong,
+    ULongLong,
+    Float,
+    Double,
+    LongDouble,
+    /// A typedef name, including the [`BUILTIN_TYPEDEFS`]
+    Named(String),
+    /// `struct tag` or `union tag`. Anonymous records get a tag starting
+    /// with `#`, which no C name can.
+    Record(RecordKind, String),
+    Enum(String),
+    Pointer {
+        pointee: Box<CType>,
+        /// Whether the pointee is `const`
+        is_const: bool,
+    },
+    Array(Box<CType>, Option<u64>),
+    Function {
+        ret: Box<CType>,
+        params: Vec<Param>,
+        variadic: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RecordKind {
+    Struct,
+    Union,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Param {
+    pub name: Option<String>,
+    pub ty: CType,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Field {
+    pub name: String,
+    pub ty: CType,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Record {
+    pub kind: RecordKind,
+    pub tag: String,
+    /// `None` for a declaration without a body, `struct tag;`
+    pub fields: Option<Vec<Field>>,
+    pub packed: bool,
+    /// From `__attribute__((aligned(N)))` on the record or `_Alignas` on a field
+    pub align: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct EnumDef {
+    pub tag: String,
+    /// C23 `enum tag : type`
+    pub repr: Option<CType>,
+    pub variants: Vec<(String, i128)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum DeclKind {
+    Record(Record),
+    Enum(EnumDef),
+    Typedef { name: String, ty: CType },
+    Function { name: String, ty: CType, has_body: bool, is_static: bool },
+    Variable { name: String },
+    /// A `#define` that is an integer constant expression, with the type of
+    /// its outermost cast or literal suffix
+    Constant { name: String, value: i128, ty: Option<CType> },
+    /// A declaration that could not be read, and why
+    Skipped { reason: String },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Decl {
+    pub line: usize,
+    pub kind: DeclKind,
+}
+
+/// Declaration specifiers: the type and everything said about it before
+/// the declarators
+struct Specs {
+    ty: CType,
+    is_const: bool,
+    is_typedef: bool,
+    is_static: bool,
+}
+
+#[derive(Default)]
+struct Attributes {
+    packed: bool,
+    align: Option<u64>,
+}
+
+/// Parse the declarations of a header, followed by its integer `#define`s,
+/// sizing integer types for `target`
+pub(crate) fn parse(tokens: Vec<Spanned>, defines: Vec<Define>, target: Target) -> Vec<Decl> {
+    let mut parser = Parser {
+        target,
+        tokens,
+        pos: 0,
+        typedefs: HashMap::new(),
+        constants: HashMap::new(),
+        defines: defines.iter().map(|d| (d.name.clone(), d.body.clone())).collect(),
+        anonymous: 0,
+        decls: Vec::new(),
+    };
+    parser.translation_unit();
+    for define in &defines {
+        let scope = ConstScope { parser: &parser, evaluating: RefCell::new(vec![define.name.clone()]) };
+        // Macros that are not integer constants, such as attribute
+        // shorthands, are not declarations and are left out silently
+        if define.body.is_empty() {
+            continue;
+        }
+        if let Ok((value, ty)) = expr::evaluate(&define.body, &scope) {
+            parser.decls.push(Decl { line: define.line, kind: DeclKind::Constant { name: define.name.clone(), value, ty } });
+        }
+    }
+    parser.decls.sort_by_key(|d| d.line);
+    parser.decls
+}
+
+struct Parser {
+    target: Target,
+    tokens: Vec<Spanned>,
+    pos: usize,
+    /// Typedef names declared so far and what they stand for
+    typedefs: HashMap<String, CType>,
+    /// Enum constants declared so far
+    constants: HashMap<String, i128>,
+    defines: HashMap<String, Vec<Spanned>>,
+    anonymous: usize,
+    decls: Vec<Decl>,
+}
+
+type Result<T> = std::result::Result<T, String>;
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|s| &s.token)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<&Token> {
+        self.tokens.get(self.pos + offset).map(|s| &s.token)
+    }
+
+    fn line(&self) -> usize {
+        self.tokens.get(self.pos).or(self.tokens.last()).map_or(1, |s| s.line)
+    }
+
+    fn eat(&mut self, punct: &str) -> bool {
+        let found = self.peek().is_some_and(|t| t.is(punct));
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn eat_ident(&mut self, name: &str) -> bool {
+        let found = self.peek().is_some_and(|t| t.is_ident(name));
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, punct: &str) -> Result<()> {
+        if self.eat(punct) {
+            return Ok(());
+        }
+        Err(match self.peek() {
+            Some(token) => format!("expected `{}`, found {}", punct, describe(token)),
+            None => format!("expected `{}` at the end of the header", punct),
+        })
+    }
+
+    /// Skip a balanced `(...)`, `[...]` or `{...}` starting at the current token
+    fn skip_group(&mut self) -> Result<()> {
+        let mut depth = 0usize;
+        loop {
+            let Some(token) = self.peek() else { return Err("unbalanced brackets".to_string()) };
+            if token.is("(") || token.is("[") || token.is("{") {
+                depth += 1;
+            } else if token.is(")") || token.is("]") || token.is("}") {
+                depth = depth.checked_sub(1).ok_or("unbalanced brackets")?;
+            }
+            self.pos += 1;
+            if depth == 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    /// After an error, move from the start of the declaration past its end:
+    /// its `;`, or the body of a function definition
+    fn recover(&mut self) {
+        while let Some(token) = self.peek() {
+            if token.is(";") {
+                self.pos += 1;
+                return;
+            }
+            if token.is("{") || token.is("(") || token.is("[") {
+                let is_body = token.is("{") && self.pos > 0 && self.tokens[self.pos - 1].token.is(")");
+                if self.skip_group().is_err() {
+                    self.pos = self.tokens.len();
+                }
+                if is_body {
+                    return;
+                }
+            } else {
+                self.pos += 1;
+            }
+        }
+    }
+
+    fn push(&mut self, line: usize, kind: DeclKind) {
+        self.decls.push(Decl { line, kind });
+    }
+
+    fn translation_unit(&mut self) {
+        let mut extern_blocks = 0;
+        while let Some(token) = self.peek() {
+            if token.is(";") {
+                self.pos += 1;
+            } else if token.is_ident("extern")
+                && matches!(self.peek_at(1), Some(Token::Str(_)))
+                && self.peek_at(2).is_some_and(|t| t.is("{"))
+            {
+                self.pos += 3;
+                extern_blocks += 1;
+            } else if token.is("}") && extern_blocks > 0 {
+                self.pos += 1;
+                extern_blocks -= 1;
+            } else {
+                let line = self.line();
+                let start = self.pos;
+                if let Err(reason) = self.declaration() {
+                    self.pos = start;
+                    self.recover();
+                    self.push(line, DeclKind::Skipped { reason });
+                }
+            }
+        }
+    }
+
+    fn declaration(&mut self) -> Result<()> {
+        if self.eat_ident("_Static_assert") || self.eat_ident("static_assert") {
+            self.skip_group()?;
+            return self.expect(";");
+        }
+        let line = self.line();
+        let specs = self.specifiers()?;
+        if self.eat(";") {
+            return Ok(());
+        }
+        loop {
+            let (name, ty) = self.declarator(specs.ty.clone(), specs.is_const)?;
+            self.trailing_attributes()?;
+            let Some(name) = name else { return Err("expected a declarator name".to_string()) };
+            if specs.is_typedef {
+                self.typedefs.insert(name.clone(), ty.clone());
+                self.push(line, DeclKind::Typedef { name, ty });
+            } else if matches!(ty, CType::Function { .. }) {
+                if self.peek().is_some_and(|t| t.is("{")) {
+                    self.skip_group()?;
+                    self.push(line, DeclKind::Function { name, ty, has_body: true, is_static: specs.is_static });
+                    return Ok(());
+                }
+                self.push(line, DeclKind::Function { name, ty, has_body: false, is_static: specs.is_static });
+            } else {
+                if self.eat("=") {
+                    while !self.peek().is_none_or(|t| t.is(",") || t.is(";")) {
+                        self.skip_group()?;
+                    }
+                }
+                self.push(line, DeclKind::Variable { name });
+            }
+            if !self.eat(",") {
+                return self.expect(";");
+            }
+        }
+    }
+
+    /// `__attribute__`, `__asm__` and friends between a declarator and its `;`
+    fn trailing_attributes(&mut self) -> Result<()> {
+        loop {
+            if self.peek().is_some_and(|t| t.is_ident("__attribute__") || t.is_ident("__attribute")) {
+                self.attributes()?;
+            } else if self.eat_ident("__asm__") || self.eat_ident("__asm") || self.eat_ident("asm") {
+                self.skip_group()?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    /// `__attribute__((a, b(args)))`, keeping what affects layout
+    fn attributes(&mut self) -> Result<Attributes> {
+        let mut attrs = Attributes::default();
+        self.pos += 1;
+        self.expect("(")?;
+        self.expect("(")?;
+        while !self.eat(")") {
+            let name = match self.peek().cloned() {
+                Some(Token::Ident(name)) => {
+                    self.pos += 1;
+                    name
+                }
+                Some(token) if token.is(",") => {
+                    self.pos += 1;
+                    continue;
+                }
+                _ => return Err("malformed `__attribute__`".to_string()),
+            };
+            let has_args = self.peek().is_some_and(|t| t.is("("));
+            match name.trim_matches('_') {
+                "packed" => attrs.packed = true,
+                "aligned" if has_args => {
+                    self.pos += 1;
+                    let start = self.pos;
+                    self.pos -= 1;
+                    self.skip_group()?;
+                    let scope = ConstScope { parser: self, evaluating: RefCell::new(Vec::new()) };
+                    let (value, _) = expr::evaluate(&self.tokens[start..self.pos - 1], &scope)?;
+                    attrs.align = Some(u64::try_from(value).map_err(|_| "negative alignment".to_string())?);
+                    continue;
+                }
+                "aligned" => attrs.align = Some(16),
+                _ => {}
+            }
+            if has_args {
+                self.skip_group()?;
+            }
+        }
+        self.expect(")")?;
+        Ok(attrs)
+    }
+
+    /// Whether the current token can start a type
+    fn starts_type(&self) -> bool {
+        match self.peek() {
+            Some(Token::Ident(name)) => {
+                TYPE_WORDS.contains(&name.as_str())
+                    || QUALIFIERS.contains(&name.as_str())
+                    || matches!(name.as_str(), "struct" | "union" | "enum")
+                    || self.typedefs.contains_key(name)
+                    || BUILTIN_TYPEDEFS.iter().any(|(n, _, _)| n == name)
+            }
+            _ => false,
+        }
+    }
+
+    fn specifiers(&mut self) -> Result<Specs> {
+        let mut words: Vec<String> = Vec::new();
+        let mut ty = None;
+        let mut specs = Specs { ty: CType::Int, is_const: false, is_typedef: false, is_static: false };
+        while let Some(Token::Ident(name)) = self.peek().cloned() {
+            match name.as_str() {
+                "typedef" => specs.is_typedef = true,
+                "static" => specs.is_static = true,
+                "const" | "__const" | "__const__" => specs.is_const = true,
+                "__attribute__" | "__attribute" => {
+                    self.attributes()?;
+                    continue;
+                }
+                "__declspec" | "_Alignas" | "alignas" => {
+                    self.pos += 1;
+                    self.skip_group()?;
+                    continue;
+                }
+                "struct" | "union" if ty.is_none() && words.is_empty() => {
+                    self.pos += 1;
+                    let kind = if name == "struct" { RecordKind::Struct } else { RecordKind::Union };
+                    ty = Some(self.record(kind)?);
+                    continue;
+                }
+                "enum" if ty.is_none() && words.is_empty() => {
+                    self.pos += 1;
+                    ty = Some(self.enumeration()?);
+                    continue;
+                }
+                word if TYPE_WORDS.contains(&word) && ty.is_none() => words.push(name),
+                word if QUALIFIERS.contains(&word) => {}
+                _ if ty.is_none()
+                    && words.is_empty()
+                    && (self.typedefs.contains_key(&name) || BUILTIN_TYPEDEFS.iter().any(|(n, _, _)| *n == name)) =>
+                {
+                    ty = Some(CType::Named(name))
+                }
+                _ if ty.is_none() && words.is_empty() => {
+                    if matches!(self.peek_at(1), Some(Token::Ident(_)) | Some(Token::Punct("*"))) {
+                        return Err(format!("unknown type name `{}`", name));
+                    }
+                    return Err(format!("expected a type, found `{}`", name));
+                }
+                _ => break,
+            }
+            self.pos += 1;
+        }
+        specs.ty = match ty {
+            Some(ty) => ty,
+            None if words.is_empty() => {
+                return Err(match self.peek() {
+                    Some(token) => format!("expected a type, found {}", describe(token)),
+                    None => "expected a type at the end of the header".to_string(),
+                });
+            }
+            None => base_type(&words)?,
+        };
+        Ok(specs)
+    }
+
+    /// A `struct` or `union` specifier, after the keyword
+    fn record(&mut self, kind: RecordKind) -> Result<CType> {
+        let line = self.line();
+        let mut attrs = Attributes::default();
+        while self.peek().is_some_and(|t| t.is_ident("__attribute__") || t.is_ident("__attribute")) {
+            let more = self.attributes()?;
+            attrs.packed |= more.packed;
+            attrs.align = attrs.align.max(more.align);
+        }
+        let tag = match self.peek().cloned() {
+            Some(Token::Ident(name)) => {
+                self.pos += 1;
+                name
+            }
+            _ => {
+                self.anonymous += 1;
+                format!("#{}", self.anonymous)
+            }
+        };
+        if !self.eat("{") {
+            if tag.starts_with('#') {
+                return Err("expected a record tag or `{`".to_string());
+            }
+            // `struct tag;` on its own declares the tag
+            if self.peek().is_some_and(|t| t.is(";")) {
+                let record = Record { kind, tag: tag.clone(), fields: None, packed: false, align: None };
+                self.push(line, DeclKind::Record(record));
+            }
+            return Ok(CType::Record(kind, tag));
+        }
+        let mut fields = Vec::new();
+        while !self.eat("}") {
+            if self.peek().is_some_and(|t| t.is_ident("_Alignas") || t.is_ident("alignas")) {
+                self.pos += 1;
+                let start = self.pos + 1;
+                self.skip_group()?;
+                let scope = ConstScope { parser: self, evaluating: RefCell::new(Vec::new()) };
+                let (value, _) = expr::evaluate(&self.tokens[start..self.pos - 1], &scope)?;
+                let value = u64::try_from(value).map_err(|_| "negative alignment".to_string())?;
+                attrs.align = attrs.align.max(Some(value));
+            }
+            if !self.starts_type() {
+                return Err(match self.peek() {
+                    Some(token) => format!("expected a field, found {}", describe(token)),
+                    None => "unterminated record".to_string(),
+                });
+            }
+            let specs = self.specifiers()?;
+            if self.peek().is_some_and(|t| t.is(";")) {
+                return Err("anonymous members are not supported".to_string());
+            }
+            loop {
+                let (name, ty) = self.declarator(specs.ty.clone(), specs.is_const)?;
+                if self.peek().is_some_and(|t| t.is(":")) {
+                    return Err("bit-fields are not supported".to_string());
+                }
+                self.trailing_attributes()?;
+                let Some(name) = name else { return Err("expected a field name".to_string()) };
+                fields.push(Field { name, ty });
+                if !self.eat(",") {
+                    break;
+                }
+            }
+            self.expect(";")?;
+        }
+        while self.peek().is_some_and(|t| t.is_ident("__attribute__") || t.is_ident("__attribute")) {
+            let more = self.attributes()?;
+            attrs.packed |= more.packed;
+            attrs.align = attrs.align.max(more.align);
+        }
+        let record = Record { kind, tag: tag.clone(), fields: Some(fields), packed: attrs.packed, align: attrs.align };
+        self.push(line, DeclKind::Record(record));
+        Ok(CType::Record(kind, tag))
+    }
+
+    /// An `enum` specifier, after the keyword
+    fn enumeration(&mut self) -> Result<CType> {
+        let line = self.line();
+        while self.peek().is_some_and(|t| t.is_ident("__attribute__") || t.is_ident("__attribute")) {
+            self.attributes()?;
+        }
+        let tag = match self.peek().cloned() {
+            Some(Token::Ident(name)) => {
+                self.pos += 1;
+                name
+            }
+            _ => {
+                self.anonymous += 1;
+                format!("#{}", self.anonymous)
+            }
+        };
+        let repr = if self.eat(":") { Some(self.specifiers()?.ty) } else { None };
+        if !self.eat("{") {
+            return Ok(CType::Enum(tag));
+        }
+        let mut variants = Vec::new();
+        let mut next = 0i128;
+        while !self.eat("}") {
+            let Some(Token::Ident(name)) = self.peek().cloned() else {
+                return Err("expected an enumerator name".to_string());
+            };
+            self.pos += 1;
+            if self.eat("=") {
+                let start = self.pos;
+                while !self.peek().is_none_or(|t| t.is(",") || t.is("}")) {
+                    self.skip_group()?;
+                }
+                let scope = ConstScope { parser: self, evaluating: RefCell::new(Vec::new()) };
+                next = expr::evaluate(&self.tokens[start..self.pos], &scope)
+                    .map_err(|message| format!("enumerator `{}`: {}", name, message))?
+                    .0;
+            }
+            self.constants.insert(name.clone(), next);
+            variants.push((name, next));
+            next += 1;
+            if !self.eat(",") {
+                self.expect("}")?;
+                break;
+            }
+        }
+        while self.peek().is_some_and(|t| t.is_ident("__attribute__") || t.is_ident("__attribute")) {
+            self.attributes()?;
+        }
+        self.push(line, DeclKind::Enum(EnumDef { tag: tag.clone(), repr, variants }));
+        Ok(CType::Enum(tag))
+    }
+
+    /// A possibly abstract declarator applied to `base`. `is_const` is
+    /// whether `base` itself was declared `const`.
+    fn declarator(&mut self, base: CType, is_const: bool) -> Result<(Option<String>, CType)> {
+        let mut ty = base;
+        let mut pointee_const = is_const;
+        while self.eat("*") {
+            ty = CType::Pointer { pointee: Box::new(ty), is_const: pointee_const };
+            pointee_const = false;
+            while let Some(Token::Ident(word)) = self.peek() {
+                if matches!(word.as_str(), "const" | "__const" | "__const__") {
+                    pointee_const = true;
+                } else if !QUALIFIERS.contains(&word.as_str()) {
+                    break;
+                }
+                self.pos += 1;
+            }
+        }
+        // `(*name)(...)`: the inner declarator applies to what the
+        // suffixes make of `ty`, so parse it against a placeholder
+        let mut inner = None;
+        let mut name = None;
+        if self.peek().is_some_and(|t| t.is("(")) && self.peek_at(1).is_some_and(|t| t.is("*") || t.is("(")) {
+            self.pos += 1;
+            inner = Some(self.declarator(CType::Named("#hole".to_string()), false)?);
+            self.expect(")")?;
+        } else if let Some(Token::Ident(ident)) = self.peek().cloned()
+            && !self.starts_type()
+        {
+            self.pos += 1;
+            name = Some(ident);
+        }
+        let mut dimensions = Vec::new();
+        loop {
+            if self.eat("[") {
+                let start = self.pos;
+                while !self.peek().is_none_or(|t| t.is("]")) {
+                    self.skip_group()?;
+                }
+                let size = if self.pos == start {
+                    None
+                } else {
+                    let scope = ConstScope { parser: self, evaluating: RefCell::new(Vec::new()) };
+                    let (value, _) = expr::evaluate(&self.tokens[start..self.pos], &scope)?;
+                    Some(u64::try_from(value).map_err(|_| "negative array size".to_string())?)
+                };
+                self.expect("]")?;
+                dimensions.push(size);
+            } else if self.peek().is_some_and(|t| t.is("(")) {
+                self.pos += 1;
+                let (params, variadic) = self.parameters()?;
+                ty = CType::Function { ret: Box::new(ty), params, variadic };
+            } else {
+                break;
+            }
+        }
+        for size in dimensions.into_iter().rev() {
+            ty = CType::Array(Box::new(ty), size);
+        }
+        match inner {
+            Some((inner_name, inner_ty)) => Ok((inner_name, substitute(inner_ty, &ty))),
+            None => Ok((name, ty)),
+        }
+    }
+
+    /// A parameter list, after the `(`
+    fn parameters(&mut self) -> Result<(Vec<Param>, bool)> {
+        let mut params = Vec::new();
+        if self.eat(")") {
+            return Ok((params, false));
+        }
+        if self.peek().is_some_and(|t| t.is_ident("void")) && self.peek_at(1).is_some_and(|t| t.is(")")) {
+            self.pos += 2;
+            return Ok((params, false));
+        }
+        loop {
+            if self.eat("...") {
+                self.expect(")")?;
+                return Ok((params, true));
+            }
+            let specs = self.specifiers()?;
+            let (name, ty) = self.declarator(specs.ty, specs.is_const)?;
+            self.trailing_attributes()?;
+            // Array and function parameters are pointers
+            let ty = match ty {
+                CType::Array(element, _) => CType::Pointer { pointee: element, is_const: specs.is_const },
+                ty @ CType::Function { .. } => CType::Pointer { pointee: Box::new(ty), is_const: false },
+                ty => ty,
+            };
+            params.push(Param { name, ty });
+            if !self.eat(",") {
+                self.expect(")")?;
+                return Ok((params, false));
+            }
+        }
+    }
+}
+
+/// Keywords that combine into a base type, e.g. `unsigned long int`
+const TYPE_WORDS: &[&str] =
+    &["void", "char", "short", "int", "long", "signed", "unsigned", "float", "double", "_Bool", "bool", "__signed__"];
+
+/// Words that say nothing about a declaration's type or layout
+const QUALIFIERS: &[&str] = &[
+    "const", "__const", "__const__", "volatile", "__volatile__", "restrict", "__restrict", "__restrict__", "extern",
+    "inline", "__inline", "__inline__", "_Noreturn", "register", "auto", "_Thread_local", "__extension__", "_Atomic",
+];
+
+fn base_type(words: &[String]) -> Result<CType> {
+    let count = |word: &str| words.iter().filter(|w| *w == word).count();
+    let unsigned = count("unsigned") > 0;
+    Ok(match () {
+        _ if count("void") > 0 => CType::Void,
+        _ if count("_Bool") + count("bool") > 0 => CType::Bool,
+        _ if count("char") > 0 && unsigned => CType::UChar,
+        _ if count("char") > 0 && count("signed") + count("__signed__") > 0 => CType::SChar,
+        _ if count("char") > 0 => CType::Char,
+        _ if count("float") > 0 => CType::Float,
+        _ if count("double") > 0 && count("long") > 0 => CType::LongDouble,
+        _ if count("double") > 0 => CType::Double,
+        _ if count("short") > 0 => {
+            if unsigned { CType::UShort } else { CType::Short }
+        }
+        _ if count("long") >= 2 => {
+            if unsigned { CType::ULongLong } else { CType::LongLong }
+        }
+        _ if count("long") == 1 => {
+            if unsigned { CType::ULong } else { CType::Long }
+        }
+        _ if unsigned => CType::UInt,
+        _ => CType::Int,
+    })
+}
+
+/// Replace the placeholder of a nested declarator by `ty`
+fn substitute(inner: CType, ty: &CType) -> CType {
+    match inner {
+        CType::Named(name) if name == "#hole" => ty.clone(),
+        CType::Pointer { pointee, is_const } => CType::Pointer { pointee: Box::new(substitute(*pointee, ty)), is_const },
+        CType::Array(element, size) => CType::Array(Box::new(substitute(*element, ty)), size),
+        CType::Function { ret, params, variadic } => {
+            CType::Function { ret: Box::new(substitute(*ret, ty)), params, variadic }
+        }
+        other => other,
+    }
+}
+
+/// Names in constant expressions: enum constants, then other `#define`s
+struct ConstScope<'p> {
+    parser: &'p Parser,
+    /// Macros being evaluated, to stop on a cycle
+    evaluating: RefCell<Vec<String>>,
+}
+
+impl Scope for ConstScope<'_> {
+    fn value(&self, name: &str) -> Option<i128> {
+        if let Some(value) = self.parser.constants.get(name) {
+            return Some(*value);
+        }
+        let body = self.parser.defines.get(name)?;
+        if self.evaluating.borrow().iter().any(|n| n == name) {
+            return None;
+        }
+        self.evaluating.borrow_mut().push(name.to_string());
+        let value = expr::evaluate(body, self).ok().map(|(value, _)| value);
+        self.evaluating.borrow_mut().pop();
+        value
+    }
+
+    fn cast(&self, tokens: &[Spanned]) -> Option<CType> {
+        let mut parser = Parser {
+            target: self.parser.target,
+            tokens: tokens.to_vec(),
+            pos: 0,
+            typedefs: self.parser.typedefs.clone(),
+            constants: HashMap::new(),
+            defines: HashMap::new(),
+            anonymous: 0,
+            decls: Vec::new(),
+        };
+        if !parser.starts_type() {
+            return None;
+        }
+        let specs = parser.specifiers().ok()?;
+        let (name, ty) = parser.declarator(specs.ty, specs.is_const).ok()?;
+        (name.is_none() && parser.pos == tokens.len()).then_some(ty)
+    }
+
+    fn width(&self, ty: &CType) -> Option<(u32, bool)> {
+        match ty {
+            CType::Named(name) => match builtin_width(name, self.parser.target) {
+                Some(width) => Some(width),
+                None => self.width(self.parser.typedefs.get(name)?),
+            },
+            ty => int_width(ty, self.parser.target),
+        }
+    }
+}
+
+/// The width and signedness of a built-in integer type on `target`. `long`
+/// is as wide as a pointer, as on LP64 and ILP32 targets.
+pub(crate) fn int_width(ty: &CType, target: Target) -> Option<(u32, bool)> {
+    let long = target.pointer_width as u32 * 8;
+    Some(match ty {
+        CType::Bool | CType::UChar => (8, false),
+        CType::Char | CType::SChar => (8, true),
+        CType::Short => (16, true),
+        CType::UShort => (16, false),
+        CType::Int | CType::Enum(_) => (32, true),
+        CType::UInt => (32, false),
+        CType::Long => (long, true),
+        CType::ULong => (long, false),
+        CType::LongLong => (64, true),
+        CType::ULongLong => (64, false),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lex::tokenize;
+
+    fn decls(src: &str) -> Vec<DeclKind> {
+        let pre = tokenize(src).unwrap();
+        parse(pre.tokens, pre.defines, Target::X86_64).into_iter().map(|d| d.kind).collect()
+    }
+
+    #[test]
+    fn test_parses_declarators() {
+        let kinds = decls("const char *const *names[4];\nint (*handler)(int, ...);\nunsigned long long f(void);\n");
+        let char_ptr = CType::Pointer { pointee: Box::new(CType::Char), is_const: true };
+        let names = CType::Array(Box::new(CType::Pointer { pointee: Box::new(char_ptr), is_const: true }), Some(4));
+        assert_eq!(kinds[0], DeclKind::Variable { name: "names".into() });
+        assert!(matches!(&kinds[1], DeclKind::Variable { name } if name == "handler"));
+        let DeclKind::Function { ty: CType::Function { ret, params, .. }, .. } = &kinds[2] else { panic!("{:?}", kinds) };
+        assert_eq!((ret.as_ref(), params.len()), (&CType::ULongLong, 0));
+
+        let kinds = decls("typedef const char *const *names_t[4];\n");
+        assert_eq!(kinds[0], DeclKind::Typedef { name: "names_t".into(), ty: names });
+    }
+
+    #[test]
+    fn test_parses_records_and_enums() {
+        let kinds = decls(
+            "typedef struct __attribute__((packed)) { uint8_t tag; uint32_t len; } header_t;\n\
+             enum color { RED = 1 << 2, GREEN, BLUE = GREEN + 10 };\n\
+             #define MASK ((uint16_t)~0u)\n\
+             #define LATER (BLUE * 2)\n\
+             #define PACKED __attribute__((packed))\n",
+        );
+        let DeclKind::Record(record) = &kinds[0] else { panic!("{:?}", kinds) };
+        assert!(record.packed && record.tag.starts_with('#'));
+        assert_eq!(record.fields.as_ref().unwrap()[1], Field { name: "len".into(), ty: CType::Named("uint32_t".into()) });
+        let DeclKind::Enum(e) = &kinds[2] else { panic!("{:?}", kinds) };
+        assert_eq!(e.variants, vec![("RED".into(), 4), ("GREEN".into(), 5), ("BLUE".into(), 15)]);
+        let mask = DeclKind::Constant { name: "MASK".into(), value: 0xffff, ty: Some(CType::Named("uint16_t".into())) };
+        assert_eq!(kinds[3], mask);
+        assert_eq!(kinds[4], DeclKind::Constant { name: "LATER".into(), value: 30, ty: None });
+        assert_eq!(kinds.len(), 5);
+    }
+
+    #[test]
+    fn test_skips_what_it_cannot_read() {
+        let kinds = decls(
+            "struct flags { unsigned a : 1; };\nFILE *open_log(void);\n\
+             static inline int twice(int x) { return x * 2; }\nint after(void);\n",
+        );
+        assert!(matches!(&kinds[0], DeclKind::Skipped { reason } if reason.contains("bit-fields")), "{:?}", kinds);
+        assert!(matches!(&kinds[1], DeclKind::Skipped { reason } if reason.contains("`FILE`")), "{:?}", kinds);
+        assert!(matches!(&kinds[2], DeclKind::Function { has_body: true, is_static: true, .. }), "{:?}", kinds);
+        assert!(matches!(&kinds[3], DeclKind::Function { name, .. } if name == "after"), "{:?}", kinds);
+    }
+}
@@ -0,0 +1,512 @@
+//! C declarations as Fig items
+//!
+//! | C                                | Fig                                           |
+//! |----------------------------------|-----------------------------------------------|
+//! | `int f(const char *s, void *p);` | `extern func! f(s: ?*u8, p: ?*mut u8) -> i32` |
+//! | `void g(void);`                  | `extern func! g() -> ok`                      |
+//! | `struct s { ... };`              | `struct s`, `packed` and `#align` kept        |
+//! | `union u { ... };`               | `#align(A)` `struct u` of `[u8; N]`           |
+//! | `enum e { ... };`                | `enum[i32] e`                                 |
+//! | `typedef T name;`                | `type name = T`                               |
+//! | `#define NAME ((uint16_t)4)`     | `const NAME: u16 = 4`                         |
+//!
+//! Foreign code can do anything, so functions are declared `func!`. Nothing
+//! in a C declaration says a pointer cannot be `NULL`, so every pointer is a
+//! nullable `?*T`. Sizes
+//! are those of the [`Target`]: `long`, `size_t` and pointers are as wide as
+//! a pointer, as on LP64 and ILP32 targets. Fig unions are tagged, so a C union
+//! comes through as opaque bytes with the union's size and alignment; so does
+//! a struct with a member Fig cannot express, such as a function pointer. An
+//! anonymous `enum` without a typedef becomes one `const` per enumerator.
+//! Anything else Fig cannot express is left out with a warning.
+
+use std::collections::{HashMap, HashSet};
+
+use fig_lexer::IntegerLiteral;
+use fig_parser::ast::*;
+use fig_sema::diagnostics::Diagnostic;
+use fig_sema::layout::Target;
+
+use crate::parse::{CType, Decl, DeclKind, EnumDef, Record, RecordKind, builtin_width, int_width};
+
+/// Fig keywords, which C names get a trailing `_` to avoid
+const FIG_KEYWORDS: &[&str] = &[
+    "func", "fn", "let", "mut", "const", "type", "struct", "enum", "union", "interface", "ext", "impl", "true",
+    "false", "ok", "null", "raw", "super", "self", "if", "else", "elif", "for", "while", "break", "continue", "match",
+    "return", "mutable", "Self", "in", "where", "requires", "extends", "namespace", "pass", "block", "using", "extern",
+    "packed", "public", "export", "private", "as", "sizeof", "alignof", "offsetof", "std", "core", "alloc", "u8", "u16",
+    "u32", "u64", "usize", "isize", "i8", "i16", "i32", "i64", "f32", "f64", "bool",
+];
+
+/// A C name usable as a Fig identifier
+fn ident(name: &str) -> String {
+    if FIG_KEYWORDS.contains(&name) { format!("{}_", name) } else { name.to_string() }
+}
+
+fn integer(value: i128) -> Expression {
+    let literal = Expression::IntegerLiteral(
+        IntegerLiteral::builder().digits(value.unsigned_abs().to_string()).build().expect("digits are set"),
+    );
+    if value < 0 {
+        Expression::UnaryOp(UnaryOpExpr { op: UnaryOperator::Negate, operand: Box::new(literal) })
+    } else {
+        literal
+    }
+}
+
+fn path(name: &str) -> Type {
+    Type::Path(Path { segments: vec![name.to_string()], generic_args: vec![] })
+}
+
+/// Translate `decls` for `target`, returning the items and a warning for
+/// everything left out or imported approximately
+pub(crate) fn translate(decls: &[Decl], target: Target) -> (Vec<NamespaceItem>, Vec<Diagnostic>) {
+    let mut t = Translator::new(decls, target);
+    for decl in decls {
+        t.decl(decl);
+    }
+    // Tags only ever used behind a pointer, e.g. `struct handle *open(void);`
+    let mut referenced: Vec<String> = t.referenced.iter().cloned().collect();
+    referenced.sort();
+    for tag in referenced {
+        let name = t.record_name(&tag);
+        if !t.records.contains_key(&tag) && t.emitted.insert(name.clone()) {
+            t.items.push(opaque(&name, None));
+        }
+    }
+    (t.items, t.warnings)
+}
+
+/// A struct with no fields, for a type only used behind pointers, or one
+/// holding the bytes of a type Fig cannot spell, with its size and alignment
+fn opaque(name: &str, layout: Option<(u64, u64)>) -> NamespaceItem {
+    let (fields, annotations) = match layout {
+        Some((size, align)) => {
+            let bytes = Type::Array { element_type: Box::new(Type::U8), size: Some(Box::new(integer(size.into()))) };
+            let annotations = if align > 1 {
+                vec![Annotation { name: "align".to_string(), args: vec![integer(align.into())] }]
+            } else {
+                vec![]
+            };
+            (vec![StructField { name: "bytes".to_string(), ty: bytes }], annotations)
+        }
+        None => (vec![], vec![]),
+    };
+    NamespaceItem::Struct(Struct {
+        visibility: Visibility::Default,
+        annotations,
+        is_packed: false,
+        name: name.to_string(),
+        generic_params: vec![],
+        unbound_constraints: vec![],
+        requires: vec![],
+        fields,
+    })
+}
+
+struct Translator<'d> {
+    target: Target,
+    /// Record definitions by tag; a definition wins over `struct tag;`
+    records: HashMap<String, &'d Record>,
+    enums: HashMap<String, &'d EnumDef>,
+    typedefs: HashMap<String, &'d CType>,
+    /// Fig names of anonymous records and enums, taken from the first
+    /// typedef that names them
+    anonymous: HashMap<String, String>,
+    /// Typedefs that only name a record or enum, and so need no alias
+    consumed: HashSet<String>,
+    referenced: HashSet<String>,
+    /// Type names, functions and consts emitted so far
+    emitted: HashSet<String>,
+    items: Vec<NamespaceItem>,
+    warnings: Vec<Diagnostic>,
+}
+
+impl<'d> Translator<'d> {
+    fn new(decls: &'d [Decl], target: Target) -> Self {
+        let mut t = Translator {
+            target,
+            records: HashMap::new(),
+            enums: HashMap::new(),
+            typedefs: HashMap::new(),
+            anonymous: HashMap::new(),
+            consumed: HashSet::new(),
+            referenced: HashSet::new(),
+            emitted: HashSet::new(),
+            items: Vec::new(),
+            warnings: Vec::new(),
+        };
+        for decl in decls {
+            match &decl.kind {
+                DeclKind::Record(record) if record.fields.is_some() || !t.records.contains_key(&record.tag) => {
+                    t.records.insert(record.tag.clone(), record);
+                }
+                DeclKind::Enum(e) => {
+                    t.enums.insert(e.tag.clone(), e);
+                }
+                DeclKind::Typedef { name, ty } => {
+                    t.typedefs.entry(name.clone()).or_insert(ty);
+                    let (CType::Record(_, tag) | CType::Enum(tag)) = ty else { continue };
+                    if tag == name {
+                        t.consumed.insert(name.clone());
+                    } else if tag.starts_with('#') && !t.anonymous.contains_key(tag) {
+                        t.anonymous.insert(tag.clone(), name.clone());
+                        t.consumed.insert(name.clone());
+                    }
+                }
+                _ => {}
+            }
+        }
+        t
+    }
+
+    fn warn(&mut self, line: usize, message: String) {
+        self.warnings.push(Diagnostic::warning(format!("line {}: {}", line, message)));
+    }
+
+    fn record_name(&self, tag: &str) -> String {
+        match self.anonymous.get(tag) {
+            Some(name) => ident(name),
+            None if tag.starts_with('#') => format!("Anonymous{}", &tag[1..]),
+            None => ident(tag),
+        }
+    }
+
+    fn decl(&mut self, decl: &Decl) {
+        let line = decl.line;
+        match &decl.kind {
+            DeclKind::Record(record) => {
+                // Only the definition is translated; a lone `struct tag;`
+                // becomes an opaque struct at the end if it is used
+                if record.fields.is_some() && std::ptr::eq(self.records[&record.tag], record) {
+                    self.record(line, record);
+                }
+            }
+            DeclKind::Enum(e) => self.enumeration(line, e),
+            DeclKind::Typedef { name, ty } => {
+                if self.consumed.contains(name) || !std::ptr::eq(self.typedefs[name], ty) {
+                    return;
+                }
+                let fig_name = ident(name);
+                match self.fig_type(ty) {
+                    Ok(aliased_type) if self.emitted.insert(fig_name.clone()) => {
+                        self.items.push(NamespaceItem::TypeAlias(TypeAlias {
+                            visibility: Visibility::Default,
+                            annotations: vec![],
+                            name: fig_name,
+                            generic_params: vec![],
+                            unbound_constraints: vec![],
+                            aliased_type,
+                        }))
+                    }
+                    Ok(_) => self.warn(line, format!("typedef `{}` clashes with another type", name)),
+                    Err(reason) => self.warn(line, format!("skipped typedef `{}`: {}", name, reason)),
+                }
+            }
+            DeclKind::Function { name, ty, has_body, is_static } => {
+                if *has_body || *is_static {
+                    self.warn(line, format!("skipped `{}`: it is defined in the header, so there is no symbol to link", name));
+                } else if FIG_KEYWORDS.contains(&name.as_str()) {
+                    self.warn(line, format!("skipped `{}`: the name is a Fig keyword", name));
+                } else if self.emitted.insert(format!("{}()", name)) {
+                    match self.function(line, name, ty) {
+                        Ok(item) => self.items.push(item),
+                        Err(reason) => self.warn(line, format!("skipped function `{}`: {}", name, reason)),
+                    }
+                }
+            }
+            DeclKind::Variable { name } => {
+                self.warn(line, format!("skipped variable `{}`: only functions can be imported", name))
+            }
+            DeclKind::Constant { name, value, ty } => self.constant(line, name, *value, ty.as_ref()),
+            DeclKind::Skipped { reason } => self.warn(line, format!("skipped a declaration: {}", reason)),
+        }
+    }
+
+    fn record(&mut self, line: usize, record: &Record) {
+        let name = self.record_name(&record.tag);
+        if !self.emitted.insert(name.clone()) {
+            self.warn(line, format!("`{}` clashes with another type", name));
+            return;
+        }
+        let fields: Result<Vec<StructField>, String> = match record.kind {
+            RecordKind::Union => Err("Fig unions carry a tag".to_string()),
+            RecordKind::Struct => record
+                .fields
+                .iter()
+                .flatten()
+                .map(|field| Ok(StructField { name: ident(&field.name), ty: self.fig_type(&field.ty)? }))
+                .collect(),
+        };
+        match fields {
+            Ok(fields) => {
+                let annotations = match record.align {
+                    Some(align) => vec![Annotation { name: "align".to_string(), args: vec![integer(align.into())] }],
+                    None => vec![],
+                };
+                self.items.push(NamespaceItem::Struct(Struct {
+                    visibility: Visibility::Default,
+                    annotations,
+                    is_packed: record.packed,
+                    name,
+                    generic_params: vec![],
+                    unbound_constraints: vec![],
+                    requires: vec![],
+                    fields,
+                }));
+            }
+            Err(reason) => match self.layout(&CType::Record(record.kind, record.tag.clone())) {
+                Ok((size, align)) => {
+                    self.warn(line, format!("`{}` is imported as {} opaque bytes: {}", name, size, reason));
+                    self.items.push(opaque(&name, Some((size, align))));
+                }
+                Err(layout) => self.warn(line, format!("skipped `{}`: {}; {}", name, reason, layout)),
+            },
+        }
+    }
+
+    fn enumeration(&mut self, line: usize, e: &EnumDef) {
+        if e.tag.starts_with('#') && !self.anonymous.contains_key(&e.tag) {
+            for (name, value) in &e.variants {
+                self.constant(line, name, *value, Some(&CType::Int));
+            }
+            return;
+        }
+        let name = self.record_name(&e.tag);
+        if !self.emitted.insert(name.clone()) {
+            self.warn(line, format!("`{}` clashes with another type", name));
+            return;
+        }
+        let representation = match &e.repr {
+            Some(repr) => match self.fig_type(repr) {
+                Ok(ty) => ty,
+                Err(reason) => return self.warn(line, format!("skipped enum `{}`: {}", name, reason)),
+            },
+            None => enum_repr(e),
+        };
+        let mut next = 0;
+        let variants = e
+            .variants
+            .iter()
+            .map(|(variant, value)| {
+                let explicit = *value != next;
+                next = value + 1;
+                EnumVariant { name: ident(variant), value: explicit.then(|| integer(*value)) }
+            })
+            .collect();
+        self.items.push(NamespaceItem::Enum(Enum {
+            visibility: Visibility::Default,
+            annotations: vec![],
+            name,
+            representation: Some(representation),
+            generic_params: vec![],
+            unbound_constraints: vec![],
+            requires: vec![],
+            variants,
+        }));
+    }
+
+    fn function(&mut self, line: usize, name: &str, ty: &CType) -> Result<NamespaceItem, String> {
+        let CType::Function { ret, params, variadic } = ty else { unreachable!("function declarations have function types") };
+        if *variadic {
+            self.warn(line, format!("`{}` is variadic; only its fixed parameters are imported", name));
+        }
+        let params = params
+            .iter()
+            .enumerate()
+            .map(|(i, param)| {
+                let name = param.name.as_deref().map_or_else(|| format!("arg{}", i), ident);
+                Ok(FunctionParameter { name, ty: self.fig_type(&param.ty)? })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        let ret = if **ret == CType::Void { Type::Ok } else { self.fig_type(ret)? };
+        Ok(NamespaceItem::FunctionDeclaration(FunctionDeclaration {
+            signature: FunctionSignature {
+                visibility: Visibility::Default,
+                annotations: vec![],
+                is_extern: true,
+                is_effect: true,
+                receiver: None,
+                name: name.to_string(),
+                generic_params: vec![],
+                unbound_constraints: vec![],
+                self_param: None,
+                params,
+                return_types: vec![ret],
+            },
+        }))
+    }
+
+    fn constant(&mut self, line: usize, name: &str, value: i128, ty: Option<&CType>) {
+        let fig_name = ident(name);
+        if !self.emitted.insert(format!("const {}", fig_name)) {
+            return;
+        }
+        let (ty, value) = match ty {
+            Some(ty) => match (self.resolve(ty), self.fig_type(ty)) {
+                (CType::Bool, Ok(fig)) => (Some(fig), Expression::BooleanLiteral(value != 0)),
+                (resolved, Ok(fig)) if self.width(&resolved).is_some() => (Some(fig), integer(value)),
+                // Casts to pointers and floats are not integer constants
+                _ => return,
+            },
+            None if i32::try_from(value).is_ok() => (None, integer(value)),
+            None if i64::try_from(value).is_ok() => (Some(Type::I64), integer(value)),
+            None if u64::try_from(value).is_ok() => (Some(Type::U64), integer(value)),
+            None => return self.warn(line, format!("skipped `{}`: {} does not fit in 64 bits", name, value)),
+        };
+        self.items.push(NamespaceItem::Const(ConstStatement {
+            visibility: Visibility::Default,
+            annotations: vec![],
+            generic_params: vec![],
+            receiver: vec![],
+            name: fig_name,
+            ty,
+            value: Box::new(value),
+        }));
+    }
+
+    /// Follow typedefs to the type they stand for
+    fn resolve(&self, ty: &CType) -> CType {
+        match ty {
+            CType::Named(name) => match self.typedefs.get(name) {
+                Some(target) => self.resolve(target),
+                None => ty.clone(),
+            },
+            ty => ty.clone(),
+        }
+    }
+
+    fn width(&self, ty: &CType) -> Option<(u32, bool)> {
+        match self.resolve(ty) {
+            CType::Named(name) => builtin_width(&name, self.target),
+            CType::Enum(tag) => match self.enums.get(&tag).and_then(|e| e.repr.as_ref()) {
+                Some(repr) => self.width(repr),
+                None => self.enums.get(&tag).map(|e| enum_width(e)).or(Some((32, true))),
+            },
+            ty => int_width(&ty, self.target),
+        }
+    }
+
+    fn fig_type(&mut self, ty: &CType) -> Result<Type, String> {
+        Ok(match ty {
+            CType::Void => return Err("`void` is only allowed as a return type or behind a pointer".to_string()),
+            CType::Bool => Type::Bool,
+            CType::Char | CType::UChar => Type::U8,
+            CType::SChar => Type::I8,
+            CType::Short => Type::I16,
+            CType::UShort => Type::U16,
+            CType::Int => Type::I32,
+            CType::UInt => Type::U32,
+            CType::Long if self.target.pointer_width == 4 => Type::I32,
+            CType::ULong if self.target.pointer_width == 4 => Type::U32,
+            CType::Long | CType::LongLong => Type::I64,
+            CType::ULong | CType::ULongLong => Type::U64,
+            CType::Float => Type::F32,
+            CType::Double => Type::F64,
+            CType::LongDouble => return Err("`long double` has no Fig equivalent".to_string()),
+            CType::Named(name) => {
+                if let Some((bits, signed)) = builtin_width(name, self.target) {
+                    return Ok(match (name.as_str(), bits, signed) {
+                        ("size_t" | "uintptr_t", _, _) => Type::USize,
+                        ("ssize_t" | "ptrdiff_t" | "intptr_t", _, _) => Type::ISize,
+                        (_, 8, true) => Type::I8,
+                        (_, 8, false) => Type::U8,
+                        (_, 16, true) => Type::I16,
+                        (_, 16, false) => Type::U16,
+                        (_, 32, true) => Type::I32,
+                        (_, 32, false) => Type::U32,
+                        (_, _, true) => Type::I64,
+                        (_, _, false) => Type::U64,
+                    });
+                }
+                let target = self.typedefs.get(name).copied().ok_or_else(|| format!("unknown type `{}`", name))?;
+                if self.consumed.contains(name) {
+                    return self.fig_type(target);
+                }
+                // Check the alias can be spelled, so its users fail with it
+                self.fig_type(target).map_err(|reason| format!("`{}`: {}", name, reason))?;
+                path(&ident(name))
+            }
+            CType::Record(_, tag) => {
+                self.referenced.insert(tag.clone());
+                path(&self.record_name(tag))
+            }
+            CType::Enum(tag) if tag.starts_with('#') && !self.anonymous.contains_key(tag) => Type::I32,
+            CType::Enum(tag) => path(&self.record_name(tag)),
+            CType::Pointer { pointee, is_const } => {
+                let element_type = match pointee.as_ref() {
+                    CType::Void => Type::U8,
+                    CType::Function { .. } => return Err("function pointers are not supported".to_string()),
+                    pointee => self.fig_type(pointee)?,
+                };
+                Type::Pointer { nullable: true, mutable: !is_const, element_type: Box::new(element_type) }
+            }
+            CType::Array(element, Some(size)) => Type::Array {
+                element_type: Box::new(self.fig_type(element)?),
+                size: Some(Box::new(integer((*size).into()))),
+            },
+            CType::Array(_, None) => return Err("arrays without a size are not supported".to_string()),
+            CType::Function { .. } => return Err("function types are not supported".to_string()),
+        })
+    }
+
+    /// Size and alignment of a C type on the target
+    fn layout(&self, ty: &CType) -> Result<(u64, u64), String> {
+        let round = |n: u64, align: u64| n.div_ceil(align) * align;
+        let pointer = self.target.pointer_width;
+        Ok(match self.resolve(ty) {
+            CType::LongDouble => (16, 16),
+            CType::Pointer { .. } => (pointer, pointer),
+            CType::Float => (4, 4),
+            CType::Double => (8, 8),
+            CType::Array(element, Some(n)) => {
+                let (size, align) = self.layout(&element)?;
+                (size * n, align)
+            }
+            CType::Record(kind, tag) => {
+                let record = self.records.get(&tag).filter(|r| r.fields.is_some());
+                let Some(record) = record else { return Err(format!("`{}` is incomplete", self.record_name(&tag))) };
+                let (mut size, mut max_align) = (0, 1);
+                for field in record.fields.iter().flatten() {
+                    let (field_size, field_align) = self.layout(&field.ty)?;
+                    let field_align = if record.packed { 1 } else { field_align };
+                    max_align = max_align.max(field_align);
+                    size = match kind {
+                        RecordKind::Struct => round(size, field_align) + field_size,
+                        RecordKind::Union => size.max(field_size),
+                    };
+                }
+                let align = max_align.max(record.align.unwrap_or(1));
+                (round(size, align), align)
+            }
+            resolved => match self.width(&resolved) {
+                Some((bits, _)) => (u64::from(bits / 8), u64::from(bits / 8)),
+                None => return Err(format!("the layout of `{:?}` is unknown", resolved)),
+            },
+        })
+    }
+}
+
+/// The width of a C enum without a fixed type: `int` when the values fit,
+/// then `unsigned int`, then 64 bits
+fn enum_width(e: &EnumDef) -> (u32, bool) {
+    let fits = |min: i128, max: i128| e.variants.iter().all(|(_, v)| (min..=max).contains(v));
+    if fits(i32::MIN.into(), i32::MAX.into()) {
+        (32, true)
+    } else if fits(0, u32::MAX.into()) {
+        (32, false)
+    } else if fits(0, u64::MAX.into()) && !fits(i64::MIN.into(), i64::MAX.into()) {
+        (64, false)
+    } else {
+        (64, true)
+    }
+}
+
+fn enum_repr(e: &EnumDef) -> Type {
+    match enum_width(e) {
+        (32, true) => Type::I32,
+        (32, false) => Type::U32,
+        (_, true) => Type::I64,
+        (_, false) => Type::U64,
+    }
+}
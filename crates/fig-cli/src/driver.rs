@@ -0,0 +1,72 @@
+//! Loading sources and reporting diagnostics, shared by the subcommands
+
+use std::path::Path;
+
+use fig_package::MANIFEST;
+use fig_parser::ast::SourceFile;
+use fig_sema::construct::resolve_construction;
+use fig_sema::diagnostics::{Diagnostic, line_column};
+
+/// The source text of `path` and the file parsed from it
+pub fn parse_file(path: &Path) -> Result<(String, SourceFile), String> {
+    let src = std::fs::read_to_string(path).map_err(|e| format!("error: cannot read {}: {}", path.display(), e))?;
+    let sf = fig_package::parse(path, &src).map_err(|diagnostic| diagnostic.to_string())?;
+    Ok((src, sf))
+}
+
+/// What a subcommand compiles: a single source file, or every file of a
+/// package and its dependencies
+pub struct Input {
+    pub file: SourceFile,
+    /// The source text, when the input is a single source file
+    pub text: Option<String>,
+}
+
+/// Load `path`: a package when it is a directory or its `fig.toml`, a
+/// syntax tree when it is a `.json` file, otherwise a source file. Package
+/// warnings are reported here, and constructions are told apart from calls.
+pub fn load(path: &Path) -> Result<Input, String> {
+    let dir = if path.is_dir() {
+        path
+    } else if path.file_name().is_some_and(|name| name == MANIFEST) {
+        path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."))
+    } else if path.extension().is_some_and(|extension| extension == "json") {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("error: cannot read {}: {}", path.display(), e))?;
+        let file = fig_parser::json::from_json(&text).map_err(|e| format!("error: {}: {}", path.display(), e))?;
+        return Ok(Input { file: resolve_construction(file), text: None });
+    } else {
+        let (text, file) = parse_file(path)?;
+        return Ok(Input { file: resolve_construction(file), text: Some(text) });
+    };
+    let unit = fig_package::load(dir).map_err(|diagnostics| {
+        report(&diagnostics);
+        String::new()
+    })?;
+    report(&unit.warnings);
+    Ok(Input { file: resolve_construction(unit.source_file()), text: None })
+}
+
+/// Print `diagnostics` to stderr, returning whether any of them is an error
+pub fn report(diagnostics: &[Diagnostic]) -> bool {
+    for diagnostic in diagnostics {
+        eprintln!("{}", diagnostic);
+    }
+    diagnostics.iter().any(Diagnostic::is_error)
+}
+
+/// Print `diagnostics` found in `input`, loaded from `path`, like [`report`].
+/// In a single source file, those with a span are located by
+/// `path:line:column`.
+pub fn report_in(path: &Path, input: &Input, diagnostics: &[Diagnostic]) -> bool {
+    for diagnostic in diagnostics {
+        match (&input.text, diagnostic.span.as_deref()) {
+            (Some(text), Some(span)) => {
+                let (line, column) = line_column(text, span.start);
+                let message = format!("{}:{}:{}: {}", path.display(), line, column, diagnostic.message);
+                eprintln!("{}", Diagnostic { message, ..diagnostic.clone() });
+            }
+            _ => eprintln!("{}", diagnostic),
+        }
+    }
+    diagnostics.iter().any(Diagnostic::is_error)
+}
@@ -0,0 +1,453 @@
+//! The `fig` command-line driver
+//!
+//! ```text
+//! fig build --emit=c|mir|bytecode|obj|exe|wasm|wat [-O] [-o out.c] file.fig|package
+//! fig run [-O] file.fig|package
+//! fig headers [-o out.h] file.fig|package
+//! fig layout [--target=host|x86-64|aarch64|wasm32] [-o out.txt] file.fig|package
+//! fig bindgen [-o out.fig] header.h
+//! fig highlight [--format=html|ansi] [-o out.html] file.fig
+//! fig doc [--format=html|markdown] [--private] [-o dir] file.fig
+//! fig parse [-o out.json] file.fig
+//! fig parse --schema [-o schema.json]
+//! ```
+//!
+//! Every subcommand but `bindgen`, `highlight`, `doc` and `parse` parses
+//! the file, runs the semantic checks (see [`fig_sema::check`]) and stops
+//! with exit code 1 if they report an error. `bindgen` goes the other way,
+//! from a C header to Fig declarations, `highlight` colors any file, even
+//! one that does not parse, `doc` documents any file that parses, and
+//! `parse` writes the syntax tree as JSON (see [`fig_parser::json`]).
+//!
+//! `build`, `run`, `headers` and `layout` also take a package: a directory with a
+//! `fig.toml`, or the manifest itself. Its files and those of its
+//! dependencies are compiled together; see [`fig_package`]. They take a
+//! syntax tree written by `parse`, or by another tool, as a `.json` file.
+
+mod driver;
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use clap::{Parser, Subcommand, ValueEnum};
+use fig_codegen_c::{CEmitter, EntryPoint, HeaderEmitter};
+use fig_codegen_cranelift::ObjectEmitter;
+use fig_codegen_wasm::WasmEmitter;
+use fig_sema::items::ItemTable;
+use fig_sema::layout::Target;
+
+#[derive(Parser)]
+#[command(name = "fig", version, about = "The Fig compiler")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Compile a source file
+    Build(BuildArgs),
+    /// Compile a source file to bytecode and run its `main`
+    Run(RunArgs),
+    /// Write a C header declaring the source file's `export` items
+    Headers(HeadersArgs),
+    /// Print the size, alignment and field offsets of the source file's types
+    Layout(LayoutArgs),
+    /// Write Fig declarations for the functions, types and constants of a C header
+    Bindgen(BindgenArgs),
+    /// Print a source file with syntax highlighting
+    Highlight(HighlightArgs),
+    /// Write API documentation for the items of a source file
+    Doc(DocArgs),
+    /// Write the syntax tree of a source file as JSON
+    Parse(ParseArgs),
+}
+
+#[derive(clap::Args)]
+struct BuildArgs {
+    /// The source file to compile, or a package directory with a `fig.toml`
+    file: PathBuf,
+    /// What to produce
+    #[arg(long, value_enum)]
+    emit: Emit,
+    /// Where to write the output, `-` for stdout. Defaults to the source
+    /// file with the extension of the output
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// Run the MIR optimisation passes. C output is left to the C compiler
+    /// to optimise.
+    #[arg(short = 'O', long)]
+    optimize: bool,
+}
+
+#[derive(clap::Args)]
+struct RunArgs {
+    /// The source file to run, or a package directory with a `fig.toml`
+    file: PathBuf,
+    /// Run the MIR optimisation passes before compiling to bytecode
+    #[arg(short = 'O', long)]
+    optimize: bool,
+}
+
+#[derive(clap::Args)]
+struct HeadersArgs {
+    /// The source file to describe, or a package directory with a `fig.toml`
+    file: PathBuf,
+    /// Where to write the header, `-` for stdout. Defaults to the source
+    /// file with the extension `h`
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(clap::Args)]
+struct LayoutArgs {
+    /// The source file to describe, or a package directory with a `fig.toml`
+    file: PathBuf,
+    /// The target to lay the types out for
+    #[arg(long, value_enum, default_value = "host")]
+    target: LayoutTarget,
+    /// Where to write the layouts. Defaults to stdout
+    #[arg(short, long, default_value = "-")]
+    output: PathBuf,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum LayoutTarget {
+    /// The machine the compiler runs on
+    Host,
+    /// 64-bit x86
+    #[value(name = "x86-64")]
+    X86_64,
+    /// 64-bit Arm
+    Aarch64,
+    /// 32-bit WebAssembly
+    Wasm32,
+}
+
+impl LayoutTarget {
+    fn target(self) -> Target {
+        match self {
+            LayoutTarget::Host => Target::host(),
+            LayoutTarget::X86_64 => Target::X86_64,
+            LayoutTarget::Aarch64 => Target::AARCH64,
+            LayoutTarget::Wasm32 => Target::WASM32,
+        }
+    }
+}
+
+#[derive(clap::Args)]
+struct BindgenArgs {
+    /// The C header to import
+    header: PathBuf,
+    /// Where to write the declarations, `-` for stdout. Defaults to the
+    /// header with the extension `fig`
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(clap::Args)]
+struct HighlightArgs {
+    /// The source file to highlight
+    file: PathBuf,
+    /// How to mark up the source
+    #[arg(long, value_enum, default_value = "ansi")]
+    format: Format,
+    /// Where to write the highlighted source. Defaults to stdout
+    #[arg(short, long, default_value = "-")]
+    output: PathBuf,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    /// A `<pre class="fig">` block of spans with `fig-` classes
+    Html,
+    /// Terminal color escapes
+    Ansi,
+}
+
+#[derive(clap::Args)]
+struct DocArgs {
+    /// The source file to document
+    file: PathBuf,
+    /// What to write the pages as
+    #[arg(long, value_enum, default_value = "html")]
+    format: DocFormat,
+    /// Also document items that are not `export`
+    #[arg(long)]
+    private: bool,
+    /// The directory to write the pages to. Defaults to `doc` next to the
+    /// source file
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(clap::Args)]
+struct ParseArgs {
+    /// The source file to parse
+    #[arg(required_unless_present = "schema")]
+    file: Option<PathBuf>,
+    /// Write the JSON Schema of the syntax tree instead
+    #[arg(long, conflicts_with = "file")]
+    schema: bool,
+    /// Where to write the JSON. Defaults to stdout
+    #[arg(short, long, default_value = "-")]
+    output: PathBuf,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum DocFormat {
+    /// Static pages with a search box
+    Html,
+    /// Pages for a Markdown site such as the Docusaurus one in `docs/`
+    Markdown,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Emit {
+    /// A C11 translation unit whose `main` calls the program's `main`
+    C,
+    /// The verified mid-level IR of every reachable function, as text
+    Mir,
+    /// The disassembled bytecode `fig run` executes
+    Bytecode,
+    /// An x86-64 ELF object with debug line info, to link with the runtime
+    Obj,
+    /// An x86-64 executable, linked with the runtime by the system C compiler
+    Exe,
+    /// A WebAssembly module that imports the runtime from the host
+    Wasm,
+    /// The same module in the WebAssembly text format
+    Wat,
+}
+
+impl Emit {
+    fn extension(self) -> &'static str {
+        match self {
+            Emit::C => "c",
+            Emit::Mir => "mir",
+            Emit::Bytecode => "dis",
+            Emit::Obj => "o",
+            Emit::Exe => "",
+            Emit::Wasm => "wasm",
+            Emit::Wat => "wat",
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let result = match Cli::parse().command {
+        Command::Build(args) => build(&args).map(|()| ExitCode::SUCCESS),
+        Command::Run(args) => run(&args),
+        Command::Headers(args) => headers(&args).map(|()| ExitCode::SUCCESS),
+        Command::Layout(args) => layout(&args).map(|()| ExitCode::SUCCESS),
+        Command::Bindgen(args) => bindgen(&args).map(|()| ExitCode::SUCCESS),
+        Command::Highlight(args) => highlight(&args).map(|()| ExitCode::SUCCESS),
+        Command::Doc(args) => doc(&args).map(|()| ExitCode::SUCCESS),
+        Command::Parse(args) => parse(&args).map(|()| ExitCode::SUCCESS),
+    };
+    match result {
+        Ok(code) => code,
+        Err(message) => {
+            if !message.is_empty() {
+                eprintln!("{}", message);
+            }
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Write `contents` to `output`, or to stdout when it is `-`
+fn write_output(output: &std::path::Path, contents: &[u8]) -> Result<(), String> {
+    if output.as_os_str() == "-" {
+        use std::io::Write;
+        return std::io::stdout().write_all(contents).map_err(|e| format!("error: cannot write output: {}", e));
+    }
+    std::fs::write(output, contents).map_err(|e| format!("error: cannot write {}: {}", output.display(), e))
+}
+
+/// `fig build`. Errors have already been printed when the message is empty.
+fn build(args: &BuildArgs) -> Result<(), String> {
+    let input = driver::load(&args.file)?;
+    let items = ItemTable::from_source_file(&input.file);
+    if driver::report_in(&args.file, &input, &fig_sema::check(&items)) {
+        return Err(String::new());
+    }
+    let output = args.output.clone().unwrap_or_else(|| args.file.with_extension(args.emit.extension()));
+    if let Emit::Obj | Emit::Exe = args.emit {
+        let program = lower(&items, fig_codegen_cranelift::TARGET, args.optimize)?;
+        let mut emitter = ObjectEmitter::new(&items).with_entry(EntryPoint::ExitCode);
+        // Line tables describe one file, so a package is built without them
+        if let Some(text) = &input.text {
+            emitter = emitter.with_debug_info(args.file.to_string_lossy(), text);
+        }
+        let object = emitter.emit_program(&program).map_err(|diagnostics| {
+            driver::report(&diagnostics);
+            String::new()
+        })?;
+        return match args.emit {
+            Emit::Exe => fig_codegen_cranelift::link(&object, &output).map_err(|message| format!("error: {}", message)),
+            _ => write_output(&output, &object),
+        };
+    }
+    if let Emit::Wasm | Emit::Wat = args.emit {
+        let program = lower(&items, fig_codegen_wasm::TARGET, args.optimize)?;
+        let module =
+            WasmEmitter::new(&items).with_entry(EntryPoint::ExitCode).emit_program(&program).map_err(|diagnostics| {
+                driver::report(&diagnostics);
+                String::new()
+            })?;
+        return match args.emit {
+            Emit::Wat => {
+                let wat = fig_codegen_wasm::to_wat(&module).map_err(|message| format!("error: {}", message))?;
+                write_output(&output, wat.as_bytes())
+            }
+            _ => write_output(&output, &module),
+        };
+    }
+    let contents = match args.emit {
+        Emit::C => CEmitter::new(&items).with_entry(EntryPoint::ExitCode).emit().map_err(|diagnostics| {
+            driver::report(&diagnostics);
+            String::new()
+        })?,
+        Emit::Mir => lower(&items, Target::host(), args.optimize)?.to_string(),
+        Emit::Bytecode => compile(&items, args.optimize)?.to_string(),
+        Emit::Obj | Emit::Exe | Emit::Wasm | Emit::Wat => unreachable!(),
+    };
+    write_output(&output, contents.as_bytes())
+}
+
+/// Lower to MIR, optimise if asked to, and verify, reporting any errors
+fn lower(items: &ItemTable, target: Target, optimize: bool) -> Result<fig_mir::ir::Program, String> {
+    let mut program = fig_mir::lower_program(items, target).map_err(|diagnostics| {
+        driver::report(&diagnostics);
+        String::new()
+    })?;
+    if optimize {
+        fig_mir::opt::optimize(&mut program, target);
+    }
+    if driver::report(&fig_mir::verify(&program)) {
+        return Err(String::new());
+    }
+    Ok(program)
+}
+
+/// Lower, verify and compile to bytecode, reporting any errors
+fn compile(items: &ItemTable, optimize: bool) -> Result<fig_vm::bytecode::Module, String> {
+    let program = lower(items, fig_vm::TARGET, optimize)?;
+    fig_vm::compile(items, &program).map_err(|diagnostics| {
+        driver::report(&diagnostics);
+        String::new()
+    })
+}
+
+/// `fig headers`. The include guard is named after the header, e.g.
+/// `GEO_H` for `geo.h`.
+fn headers(args: &HeadersArgs) -> Result<(), String> {
+    let input = driver::load(&args.file)?;
+    let items = ItemTable::from_source_file(&input.file);
+    if driver::report_in(&args.file, &input, &fig_sema::check(&items)) {
+        return Err(String::new());
+    }
+    let output = args.output.clone().unwrap_or_else(|| args.file.with_extension("h"));
+    let named = if output.as_os_str() == "-" { args.file.with_extension("h") } else { output.clone() };
+    let name = named.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let guard: String =
+        name.chars().map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' }).collect();
+    let header = HeaderEmitter::new(&items).with_guard(guard).emit().map_err(|diagnostics| {
+        driver::report(&diagnostics);
+        String::new()
+    })?;
+    write_output(&output, header.as_bytes())
+}
+
+/// `fig layout`. A type whose layout cannot be computed is listed with the
+/// error instead.
+fn layout(args: &LayoutArgs) -> Result<(), String> {
+    let input = driver::load(&args.file)?;
+    let items = ItemTable::from_source_file(&input.file);
+    if driver::report_in(&args.file, &input, &fig_sema::check(&items)) {
+        return Err(String::new());
+    }
+    let dump = fig_sema::layout::LayoutEngine::new(&items, args.target.target()).dump();
+    write_output(&args.output, dump.as_bytes())
+}
+
+/// `fig bindgen`. Declarations that cannot be imported are reported as
+/// warnings and left out.
+fn bindgen(args: &BindgenArgs) -> Result<(), String> {
+    let src = std::fs::read_to_string(&args.header)
+        .map_err(|e| format!("error: cannot read {}: {}", args.header.display(), e))?;
+    let bindings = fig_bindgen::import_header(&src, Target::host()).map_err(|diagnostics| {
+        driver::report(&diagnostics);
+        String::new()
+    })?;
+    driver::report(&bindings.warnings);
+    let output = args.output.clone().unwrap_or_else(|| args.header.with_extension("fig"));
+    write_output(&output, bindings.to_source().as_bytes())
+}
+
+/// `fig highlight`
+fn highlight(args: &HighlightArgs) -> Result<(), String> {
+    let src =
+        std::fs::read_to_string(&args.file).map_err(|e| format!("error: cannot read {}: {}", args.file.display(), e))?;
+    let highlights = fig_highlight::highlight(&src);
+    let contents = match args.format {
+        Format::Html => {
+            format!("<pre class=\"fig\"><code>{}</code></pre>\n", fig_highlight::to_html(&src, &highlights))
+        }
+        Format::Ansi => fig_highlight::to_ansi(&src, &highlights),
+    };
+    write_output(&args.output, contents.as_bytes())
+}
+
+/// `fig doc`
+fn doc(args: &DocArgs) -> Result<(), String> {
+    let (src, sf) = driver::parse_file(&args.file)?;
+    let options = fig_doc::Options { private: args.private };
+    let documentation = fig_doc::document(&sf, &src, &options);
+    let files = match args.format {
+        DocFormat::Html => fig_doc::to_html(&documentation),
+        DocFormat::Markdown => fig_doc::to_markdown(&documentation),
+    };
+    let output = args.output.clone().unwrap_or_else(|| args.file.with_file_name("doc"));
+    std::fs::create_dir_all(&output).map_err(|e| format!("error: cannot create {}: {}", output.display(), e))?;
+    for (name, contents) in files {
+        write_output(&output.join(name), contents.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// `fig parse`
+fn parse(args: &ParseArgs) -> Result<(), String> {
+    let contents = match &args.file {
+        Some(file) if !args.schema => fig_parser::json::to_json(&driver::parse_file(file)?.1) + "\n",
+        _ => fig_parser::json::schema(),
+    };
+    write_output(&args.output, contents.as_bytes())
+}
+
+/// `fig run`. The exit code is the one the program's C `main` would
+/// return: an integer result, 1 when `main` returns an error, and 101 when
+/// it traps.
+fn run(args: &RunArgs) -> Result<ExitCode, String> {
+    let input = driver::load(&args.file)?;
+    let items = ItemTable::from_source_file(&input.file);
+    if driver::report_in(&args.file, &input, &fig_sema::check(&items)) {
+        return Err(String::new());
+    }
+    let module = compile(&items, args.optimize)?;
+    let mut vm = fig_vm::Vm::new(&module);
+    let result = vm.run_main();
+    write_output(std::path::Path::new("-"), vm.output().as_bytes())?;
+    match result {
+        Ok(exit) if exit.failed => {
+            eprintln!("error: main returned an error");
+            Ok(ExitCode::from(exit.code as u8))
+        }
+        Ok(exit) => Ok(ExitCode::from(exit.code as u8)),
+        Err(trap) => {
+            eprintln!("{}", trap);
+            Ok(ExitCode::from(101))
+        }
+    }
+}
@@ -0,0 +1,281 @@
+// Runs the `fig` binary on small programs written to a scratch directory
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+fn scratch(name: &str, src: &str) -> PathBuf {
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join("fig-cli").join(name);
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(&path, src).unwrap();
+    path
+}
+
+fn fig(args: &[&str], file: &Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_fig")).args(args).arg(file).output().unwrap()
+}
+
+#[test]
+fn test_build_emit_c() {
+    let file = scratch("answer.fig", "func main() -> i32\n    return 40 + 2\n");
+    let output = fig(&["build", "--emit=c", "-o", "-"], &file);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let c = String::from_utf8(output.stdout).unwrap();
+    assert!(c.contains("int32_t fig_main(void) {"), "{}", c);
+    assert!(c.contains("int main(void) {"), "{}", c);
+
+    // Without -o the C file goes next to the source
+    let _ = std::fs::remove_file(file.with_extension("c"));
+    assert!(fig(&["build", "--emit=c"], &file).status.success());
+    assert!(file.with_extension("c").exists());
+}
+
+#[test]
+fn test_build_emit_mir() {
+    let file = scratch("square.fig", "func square(x: i32) -> i32\n    return x * x\n");
+    let output = fig(&["build", "--emit=mir", "-o", "-"], &file);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let mir = String::from_utf8(output.stdout).unwrap();
+    assert!(mir.contains("fn square(_0: i32) -> i32"), "{}", mir);
+    assert!(mir.contains("_1 = mul _0, _0"), "{}", mir);
+}
+
+#[test]
+fn test_build_optimized() {
+    let src = "#inline\nfunc square(x: i32) -> i32\n    return x * x\n\nfunc main() -> i32\n    if square(3) > 5\n        return square(4)\n    return 0\n";
+    let file = scratch("optimized.fig", src);
+    let output = fig(&["build", "--emit=mir", "-O", "-o", "-"], &file);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let mir = String::from_utf8(output.stdout).unwrap();
+    assert!(mir.contains("#inline\nfn square(_0: i32) -> i32"), "{}", mir);
+    assert!(mir.contains("fn main() -> i32\n\n    bb0:\n        return 16_i32\n"), "{}", mir);
+
+    let output = fig(&["run", "-O"], &file);
+    assert_eq!(output.status.code(), Some(16), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(fig(&["build", "--emit=wat", "-O", "-o", "-"], &file).status.success());
+}
+
+#[test]
+fn test_build_emit_bytecode() {
+    let file = scratch("square_vm.fig", "func square(x: i32) -> i32\n    return x * x\n");
+    let output = fig(&["build", "--emit=bytecode", "-o", "-"], &file);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let bytecode = String::from_utf8(output.stdout).unwrap();
+    assert!(bytecode.contains("fn square(i32) -> i32"), "{}", bytecode);
+    assert!(bytecode.contains("mul i32"), "{}", bytecode);
+}
+
+#[test]
+fn test_build_emit_exe() {
+    if !cfg!(all(target_arch = "x86_64", target_os = "linux")) || Command::new("cc").arg("--version").output().is_err() {
+        return;
+    }
+    let src = "extern func! abs(x: i32) -> i32\n\nfunc! main() -> i32\n    println(\"native\")\n    return abs(-40) + 2\n";
+    let file = scratch("native.fig", src);
+    let exe = file.with_extension("");
+    let _ = std::fs::remove_file(&exe);
+    let output = fig(&["build", "--emit=exe"], &file);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let run = Command::new(&exe).output().unwrap();
+    assert_eq!(run.status.code(), Some(42));
+    assert_eq!(String::from_utf8(run.stdout).unwrap(), "native\n");
+
+    // The object alone is an ELF file next to the source
+    assert!(fig(&["build", "--emit=obj"], &file).status.success());
+    assert!(std::fs::read(file.with_extension("o")).unwrap().starts_with(b"\x7fELF"));
+}
+
+#[test]
+fn test_build_emit_wasm() {
+    let src = "extern func! record(x: i32) -> ok\n\nexport func twice(x: i32) -> i32\n    return x * 2\n\nfunc! main() -> i32\n    record(1)\n    return twice(21)\n";
+    let file = scratch("sandbox.fig", src);
+    let _ = std::fs::remove_file(file.with_extension("wasm"));
+    let output = fig(&["build", "--emit=wasm"], &file);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(std::fs::read(file.with_extension("wasm")).unwrap().starts_with(b"\0asm"));
+
+    let output = fig(&["build", "--emit=wat", "-o", "-"], &file);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let wat = String::from_utf8(output.stdout).unwrap();
+    assert!(wat.contains("(import \"env\" \"record\""), "{}", wat);
+    assert!(wat.contains("(export \"twice\""), "{}", wat);
+    assert!(wat.contains("(export \"main\""), "{}", wat);
+}
+
+#[test]
+fn test_headers() {
+    let src = "namespace geo\n\nexport packed struct Point\n    x: i32\n    y: i32\n\nexport enum[u8] Dir\n    N\n    S\n\nexport const ORIGIN_X: i32 = 0\n\nexport func Point::flip(*self) -> Dir\n    return Dir::S\n";
+    let file = scratch("geo.fig", src);
+    let _ = std::fs::remove_file(file.with_extension("h"));
+    let output = fig(&["headers"], &file);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let header = std::fs::read_to_string(file.with_extension("h")).unwrap();
+    assert!(header.contains("#ifndef GEO_H"), "{}", header);
+    assert!(header.contains("struct FIG_PACKED geo__Point {"), "{}", header);
+    assert!(header.contains("#define geo__Dir__S ((geo__Dir)1)"), "{}", header);
+    assert!(header.contains("#define geo__ORIGIN_X ((int32_t)0)"), "{}", header);
+    assert!(header.contains("geo__Dir geo__Point__flip(geo__Point * self);"), "{}", header);
+
+    let output = fig(&["headers", "-o", "-"], &file);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), header);
+}
+
+#[test]
+fn test_layout() {
+    let src = "struct Header\n    tag: u8\n    len: usize\n\nenum[u16] Kind\n    A\n    B\n\nstruct Box[T]\n    value: T\n";
+    let file = scratch("layout.fig", src);
+    let output = fig(&["layout", "--target=x86-64"], &file);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let dump = String::from_utf8(output.stdout).unwrap();
+    assert!(dump.starts_with("target: 64-bit pointers, little-endian\n"), "{}", dump);
+    assert!(dump.contains("struct Header: size 16, align 8"), "{}", dump);
+    assert!(dump.contains("     8  len: usize (size 8, align 8)"), "{}", dump);
+    assert!(dump.contains("enum Kind: size 2, align 2"), "{}", dump);
+    assert!(dump.contains("struct Box: generic"), "{}", dump);
+
+    let output = fig(&["layout", "--target=wasm32"], &file);
+    let dump = String::from_utf8(output.stdout).unwrap();
+    assert!(dump.contains("struct Header: size 8, align 4"), "{}", dump);
+}
+
+#[test]
+fn test_bindgen() {
+    let src = "#include <stddef.h>\n#define BUFSIZ 8192\nvoid *malloc(size_t size);\nint printf(const char *fmt, ...);\n";
+    let file = scratch("libc.h", src);
+    let _ = std::fs::remove_file(file.with_extension("fig"));
+    let output = fig(&["bindgen"], &file);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("warning: line 4: `printf` is variadic"), "{}", stderr);
+    let fig_src = std::fs::read_to_string(file.with_extension("fig")).unwrap();
+    assert!(fig_src.contains("const BUFSIZ = 8192\n"), "{}", fig_src);
+    assert!(fig_src.contains("extern func! malloc(size: usize) -> ?*mut u8\n"), "{}", fig_src);
+    // The declarations pass the semantic checks
+    let output = fig(&["headers", "-o", "-"], &file.with_extension("fig"));
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    // A header written by `fig headers` imports back
+    let src = "export packed struct Point\n    x: i32\n    y: i32\n\nexport func norm(p: *Point) -> i64\n    return 0\n";
+    let file = scratch("point.fig", src);
+    assert!(fig(&["headers"], &file).status.success());
+    let output = fig(&["bindgen", "-o", "-"], &file.with_extension("h"));
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let fig_src = String::from_utf8(output.stdout).unwrap();
+    assert!(fig_src.contains("packed struct Point\n    x: i32\n    y: i32\n"), "{}", fig_src);
+    assert!(fig_src.contains("extern func! norm(p: ?*mut Point) -> i64\n"), "{}", fig_src);
+}
+
+#[test]
+fn test_highlight() {
+    // The file need not parse
+    let file = scratch("colors.fig", "struct P\n    x: i32\n\nfunc f(p: P) -> i32\n    return p.x +\n");
+    let output = fig(&["highlight", "--format=html"], &file);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let html = String::from_utf8(output.stdout).unwrap();
+    assert!(html.starts_with("<pre class=\"fig\"><code><span class=\"fig-keyword\">struct</span>"), "{}", html);
+    assert!(html.contains("<span class=\"fig-parameter\">p</span>.<span class=\"fig-field\">x</span>"), "{}", html);
+
+    let output = fig(&["highlight"], &file);
+    assert!(String::from_utf8(output.stdout).unwrap().starts_with("\x1b[1;35mstruct\x1b[0m \x1b[36mP\x1b[0m\n"));
+}
+
+#[test]
+fn test_run_package() {
+    let _ = std::fs::remove_dir_all(Path::new(env!("CARGO_TARGET_TMPDIR")).join("fig-cli/package"));
+    let dir = scratch("package/fig.toml", "[package]\nname = \"app\"\n").with_file_name("");
+    scratch("package/src/main.fig", "func main() -> i32\n    return app::math::double(21)\n");
+    scratch("package/src/math.fig", "func double(x: i32) -> i32\n    return x * 2\n");
+    let output = fig(&["run"], &dir);
+    assert_eq!(output.status.code(), Some(42), "{}", String::from_utf8_lossy(&output.stderr));
+    let output = fig(&["run"], &dir.join("fig.toml"));
+    assert_eq!(output.status.code(), Some(42), "{}", String::from_utf8_lossy(&output.stderr));
+
+    scratch("package/src/more.fig", "namespace app::math\n\nfunc double(x: i32) -> i32\n    return x + x\n");
+    let output = fig(&["run"], &dir);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("error: `app::math::double` is defined in both "), "{}", stderr);
+}
+
+#[test]
+fn test_doc() {
+    let src = "namespace shapes\n\n/// Something with an area\nexport interface Shape\n    func area(*self) -> i32\n\n\
+               export interface Polygon\n    extends\n        Shape\n\nfunc helper() -> i32\n    return 1\n";
+    let file = scratch("shapes.fig", src);
+    let dir = file.with_file_name("doc");
+    let _ = std::fs::remove_dir_all(&dir);
+    let output = fig(&["doc"], &file);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let page = std::fs::read_to_string(dir.join("shapes.html")).unwrap();
+    assert!(page.contains("<p>Something with an area</p>"), "{}", page);
+    assert!(page.contains("<dt>Extends</dt><dd><a href=\"shapes.html#Shape\"><code>Shape</code></a></dd>"), "{}", page);
+    assert!(!page.contains("helper"), "{}", page);
+    let index = std::fs::read_to_string(dir.join("search-index.json")).unwrap();
+    assert!(index.contains("\"path\":\"shapes::Shape::area\""), "{}", index);
+
+    let markdown = dir.with_file_name("doc-md");
+    let output = fig(&["doc", "--format=markdown", "--private", "-o", markdown.to_str().unwrap()], &file);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let page = std::fs::read_to_string(markdown.join("shapes.md")).unwrap();
+    assert!(page.contains("- Extends: [`Shape`](shapes.md#Shape)"), "{}", page);
+    assert!(page.contains("## function `helper`"), "{}", page);
+}
+
+#[test]
+fn test_parse_json_and_run_it() {
+    let file = scratch("tree.fig", "func main() -> i32\n    return 6 * 7\n");
+    let json = file.with_extension("json");
+    let output = fig(&["parse", "-o", json.to_str().unwrap()], &file);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let tree = std::fs::read_to_string(&json).unwrap();
+    assert!(tree.starts_with("{\n  \"version\": 4,\n  \"file\": {"), "{}", tree);
+    let output = fig(&["run"], &json);
+    assert_eq!(output.status.code(), Some(42), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let newer = scratch("newer.json", &tree.replacen("\"version\": 4", "\"version\": 9", 1));
+    let output = fig(&["run"], &newer);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let expected = "newer.json: the syntax tree is version 9, but this compiler reads version 4";
+    assert!(stderr.contains(expected), "{}", stderr);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_fig")).args(["parse", "--schema"]).output().unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout).unwrap().contains("\"title\": \"Fig syntax tree\""));
+}
+
+#[test]
+fn test_run() {
+    let file = scratch("run.fig", "func! main() -> i32\n    println(\"hello\", 6 * 7)\n    return 3\n");
+    let output = fig(&["run"], &file);
+    assert_eq!(output.status.code(), Some(3), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "hello 42\n");
+
+    let file = scratch("run_trap.fig", "func! main() -> u8\n    println(1)\n    let x = 255u8\n    return x + 1\n");
+    let output = fig(&["run"], &file);
+    assert_eq!(output.status.code(), Some(101));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "1\n");
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("error: `u8` addition overflowed\n  in `main`"), "{}", stderr);
+
+    let file = scratch("run_err.fig", "struct E\n    code: i32\n\nfunc main() -> i32 ! E\n    return E(1)\n");
+    let output = fig(&["run"], &file);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stderr).unwrap().contains("error: main returned an error"));
+}
+
+#[test]
+fn test_build_reports_errors() {
+    let file = scratch("impure.fig", "func set(p: *mut i32) -> ok\n    *p = 1\n");
+    let output = fig(&["build", "--emit=c", "-o", "-"], &file);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let expected = format!("error: {}:2:5: pure function `set` writes through a pointer", file.display());
+    assert!(stderr.contains(&expected), "{}", stderr);
+
+    let file = scratch("syntax.fig", "func main() -> i32\n    return (1\n");
+    let output = fig(&["build", "--emit=c", "-o", "-"], &file);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stderr).unwrap().contains("syntax.fig:2:"));
+}
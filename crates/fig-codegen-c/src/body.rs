@@ -0,0 +1,1188 @@
+//! Lowering of function bodies
+//!
+//! Each expression becomes a C expression. Where that is not enough (the
+//! early return of `callee!(args)`, a union read that checks the active
+//! variant, a temporary whose address is taken) the statements are written
+//! ahead of the statement being lowered, and the expression refers to the
+//! temporaries they declare. A loop condition that needs such statements
+//! becomes `for (;;)` with the statements and a `break` at its top.
+//!
+//! An expression of type `ok` may lower to a `void` C expression, or to
+//! nothing at all when its statements did all the work; [`value`] turns
+//! either into an `fig_ok` where a value is needed.
+//!
+//! [`value`]: FunctionLowering::value
+
+use std::collections::HashMap;
+
+use fig_lexer::IntegerLiteral;
+use fig_parser::ast::*;
+use fig_parser::format::{format_expression, format_type};
+use fig_sema::diagnostics::Diagnostic;
+use fig_sema::items::TypeDef;
+use fig_sema::propagation::ErrorConversion;
+use fig_sema::typeck::{Bindings, Builtin, CallTarget, Instance, Iteration, PathTarget, SelfArg, TypedBody, is_float, is_integer};
+
+use crate::emit::CEmitter;
+use crate::mangle::{self, LIBC_FUNCTIONS};
+
+/// State while lowering one function instance
+pub(crate) struct FunctionLowering<'e, 'b, 'a> {
+    emitter: &'e mut CEmitter<'a>,
+    body: &'b TypedBody<'a>,
+    /// Generic arguments of the instance, for `sizeof` and friends
+    bindings: Bindings,
+    name: String,
+    return_type: Type,
+    out: String,
+    indent: usize,
+    temps: usize,
+    /// Fig local names to C names, innermost scope last
+    scopes: Vec<HashMap<String, String>>,
+    /// How many locals of each name the function has declared
+    declared: HashMap<String, usize>,
+    /// Labels after named blocks, innermost last
+    labels: Vec<(String, String)>,
+}
+
+type Lowered = Option<String>;
+
+fn is_signed(ty: &Type) -> bool {
+    matches!(ty, Type::I8 | Type::I16 | Type::I32 | Type::I64 | Type::ISize)
+}
+
+/// The C text of a string as a string literal, escaped byte by byte
+pub(crate) fn c_string(text: &str) -> String {
+    let mut out = String::from("\"");
+    let mut previous_octal = false;
+    for byte in text.bytes() {
+        let escaped = match byte {
+            b'"' => Some("\\\"".to_string()),
+            b'\\' => Some("\\\\".to_string()),
+            b'\n' => Some("\\n".to_string()),
+            b'\t' => Some("\\t".to_string()),
+            // `??` could start a trigraph
+            b'?' => Some("\\?".to_string()),
+            b' '..=b'~' if !(previous_octal && byte.is_ascii_digit()) => None,
+            _ => {
+                out.push_str(&format!("\\{:03o}", byte));
+                previous_octal = true;
+                continue;
+            }
+        };
+        previous_octal = false;
+        match escaped {
+            Some(escaped) => out.push_str(&escaped),
+            None => out.push(byte as char),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// An integer constant of type `ty`
+fn int_constant(value: i128, ty: &Type) -> String {
+    if is_float(ty) {
+        return format!("{}.0", value);
+    }
+    let text = if value == i64::MIN as i128 {
+        "(-9223372036854775807LL - 1)".to_string()
+    } else if value > i64::MAX as i128 {
+        format!("{}ULL", value)
+    } else if value > i32::MAX as i128 || value < i32::MIN as i128 {
+        format!("{}LL", value)
+    } else {
+        value.to_string()
+    };
+    match ty {
+        Type::I32 => text,
+        Type::U8 => format!("((uint8_t){})", text),
+        Type::U16 => format!("((uint16_t){})", text),
+        Type::U32 => format!("((uint32_t){})", text),
+        Type::U64 => format!("((uint64_t){})", text),
+        Type::USize => format!("((size_t){})", text),
+        Type::I8 => format!("((int8_t){})", text),
+        Type::I16 => format!("((int16_t){})", text),
+        Type::I64 => format!("((int64_t){})", text),
+        Type::ISize => format!("((ptrdiff_t){})", text),
+        _ => text,
+    }
+}
+
+fn literal_value(lit: &IntegerLiteral) -> i128 {
+    lit.as_u64().map(i128::from).unwrap_or(0)
+}
+
+impl<'e, 'b, 'a> FunctionLowering<'e, 'b, 'a> {
+    pub(crate) fn new(
+        emitter: &'e mut CEmitter<'a>,
+        instance: &Instance<'a>,
+        body: &'b TypedBody<'a>,
+        return_type: Type,
+    ) -> Self {
+        let bindings = emitter.tc.bindings_of(instance).unwrap_or_default();
+        FunctionLowering {
+            emitter,
+            body,
+            bindings,
+            name: instance.name(),
+            return_type,
+            out: String::new(),
+            indent: 1,
+            temps: 0,
+            scopes: vec![HashMap::new()],
+            declared: HashMap::new(),
+            labels: Vec::new(),
+        }
+    }
+
+    /// Declare a parameter and return its C declaration
+    pub(crate) fn declare_param(&mut self, name: &str, ty: &Type) -> String {
+        let c_name = self.local(name);
+        format!("{} {}", self.emitter.c_type(ty), c_name)
+    }
+
+    /// Lower the function body, returning the statements between its braces
+    pub(crate) fn lower(mut self, body: &Block) -> String {
+        for stmt in &body.statements {
+            self.statement(stmt);
+        }
+        self.out
+    }
+
+    fn unsupported(&mut self, what: &str, expr: &Expression) -> Lowered {
+        self.emitter.diagnostics.push(
+            Diagnostic::error(format!("{} are not supported by the C backend", what))
+                .in_function(&self.name)
+                .with_snippet(format_expression(expr)),
+        );
+        None
+    }
+
+    fn line(&mut self, text: impl AsRef<str>) {
+        for _ in 0..self.indent {
+            self.out.push_str("    ");
+        }
+        self.out.push_str(text.as_ref());
+        self.out.push('\n');
+    }
+
+    fn temp(&mut self) -> String {
+        self.temps += 1;
+        format!("fig_t{}", self.temps)
+    }
+
+    /// Declare a temporary initialised to `value` and return its name
+    fn store(&mut self, ty: &Type, value: &str) -> String {
+        let temp = self.temp();
+        let c_type = self.emitter.c_type(ty);
+        self.line(format!("{} {} = {};", c_type, temp, value));
+        temp
+    }
+
+    /// Bind a Fig local in the current scope to a fresh C name
+    fn local(&mut self, name: &str) -> String {
+        let count = self.declared.entry(name.to_string()).or_insert(0);
+        *count += 1;
+        let c_name = if *count == 1 { mangle::ident(name) } else { format!("{}_{}", mangle::ident(name), count) };
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), c_name.clone());
+        }
+        c_name
+    }
+
+    fn lookup(&self, name: &str) -> String {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name)).cloned().unwrap_or_else(|| mangle::ident(name))
+    }
+
+    fn type_of(&self, expr: &Expression) -> Type {
+        self.body.type_of(expr).cloned().unwrap_or(Type::Ok)
+    }
+
+    // ========================================================================
+    // Statements
+    // ========================================================================
+
+    fn block(&mut self, block: &Block) {
+        self.scopes.push(HashMap::new());
+        for stmt in &block.statements {
+            self.statement(stmt);
+        }
+        self.scopes.pop();
+    }
+
+    /// Lower `block` between braces on lines of their own
+    fn nested(&mut self, block: &Block) {
+        self.indent += 1;
+        self.block(block);
+        self.indent -= 1;
+    }
+
+    fn statement(&mut self, stmt: &Statement) {
+        match stmt {
+            Statement::Expression(expr) => self.discard(expr),
+            Statement::Let(LetStatement { name, value, .. })
+            | Statement::Mut(MutStatement { name, value, .. })
+            | Statement::Const(ConstStatement { name, value, .. }) => {
+                let Some(ty) = self.body.local(stmt).cloned() else { return };
+                let Some(value) = self.convert(value, &ty) else { return };
+                let c_type = self.emitter.c_type(&ty);
+                let c_name = self.local(name);
+                self.line(format!("{} {} = {};", c_type, c_name, value));
+            }
+            Statement::Return(value) => {
+                let ret = self.return_type.clone();
+                if ret == Type::Ok {
+                    self.discard(value);
+                    self.line("return;");
+                } else if let Some(value) = self.convert(value, &ret) {
+                    self.line(format!("return {};", value));
+                }
+            }
+            Statement::Break(None) => self.line("break;"),
+            Statement::Break(Some(label)) => {
+                match self.labels.iter().rev().find(|(name, _)| name == label).map(|(_, c)| c.clone()) {
+                    Some(c_label) => self.line(format!("goto {};", c_label)),
+                    None => self.emitter.diagnostics.push(
+                        Diagnostic::error(format!("no enclosing block is named `{}`", label)).in_function(&self.name),
+                    ),
+                }
+            }
+            Statement::Continue => self.line("continue;"),
+            Statement::Block(block) => {
+                let label = block.name.as_ref().map(|name| {
+                    self.temps += 1;
+                    let c_label = format!("fig_break_{}_{}", name, self.temps);
+                    self.labels.push((name.clone(), c_label.clone()));
+                    c_label
+                });
+                self.line("{");
+                self.nested(&block.body);
+                self.line("}");
+                if let Some(label) = label {
+                    self.labels.pop();
+                    self.line(format!("{}:;", label));
+                }
+            }
+            Statement::If(stmt) => self.if_chain(&stmt.condition, &stmt.then_body, &stmt.elif_clauses, &stmt.else_body),
+            Statement::While(stmt) => {
+                let mark = self.out.len();
+                let Some(condition) = self.expression(&stmt.condition) else { return };
+                if self.out.len() == mark {
+                    self.line(format!("while ({}) {{", condition));
+                } else {
+                    // The condition needs statements; run them at the top of every iteration
+                    let prelude = self.out.split_off(mark);
+                    self.line("for (;;) {");
+                    for line in prelude.lines() {
+                        self.out.push_str("    ");
+                        self.out.push_str(line);
+                        self.out.push('\n');
+                    }
+                    self.line(format!("    if (!({})) break;", condition));
+                }
+                self.nested(&stmt.body);
+                self.line("}");
+            }
+            Statement::For(for_stmt) => self.for_loop(stmt, for_stmt),
+            // Nested declarations are collected into the item table
+            _ => {}
+        }
+    }
+
+    fn if_chain(&mut self, condition: &Expression, then_body: &Block, elifs: &[ElifClause], else_body: &Option<Block>) {
+        let Some(condition) = self.expression(condition) else { return };
+        self.line(format!("if ({}) {{", condition));
+        self.nested(then_body);
+        match (elifs.split_first(), else_body) {
+            (Some((first, rest)), _) => {
+                self.line("} else {");
+                self.indent += 1;
+                self.if_chain(&first.condition, &first.body, rest, else_body);
+                self.indent -= 1;
+                self.line("}");
+            }
+            (None, Some(body)) => {
+                self.line("} else {");
+                self.nested(body);
+                self.line("}");
+            }
+            (None, None) => self.line("}"),
+        }
+    }
+
+    fn for_loop(&mut self, stmt: &Statement, for_stmt: &ForStatement) {
+        let (Some(item), Some(iteration)) = (self.body.local(stmt).cloned(), self.body.iteration(for_stmt).cloned())
+        else {
+            return;
+        };
+        if let (Iteration::Range, Expression::Range(range)) = (&iteration, for_stmt.iterable.as_ref()) {
+            self.range_loop(for_stmt, range, &item);
+            return;
+        }
+        let iterable_type = self.type_of(&for_stmt.iterable);
+        self.line("{");
+        self.indent += 1;
+        let Some(iterable) = self.expression(&for_stmt.iterable) else { return };
+        let iterable = self.store(&iterable_type, &iterable);
+        let item_type = self.emitter.c_type(&item);
+        self.scopes.push(HashMap::new());
+        let pattern = self.local(&for_stmt.pattern);
+        match iteration {
+            Iteration::Elements => {
+                let index = self.temp();
+                let (len, element) = match &iterable_type {
+                    Type::Array { size: Some(size), .. } => {
+                        (format_expression(size), format!("{}.data[{}]", iterable, index))
+                    }
+                    _ => (format!("{}.len", iterable), format!("{}.ptr[{}]", iterable, index)),
+                };
+                self.line(format!("for (size_t {i} = 0; {i} < {}; {i}++) {{", len, i = index));
+                self.line(format!("    {} {} = {};", item_type, pattern, element));
+            }
+            Iteration::Range => unreachable!("handled by `range_loop`"),
+            Iteration::Iterator { next, self_arg } => {
+                let next_type = match self.emitter.tc.signature(&next) {
+                    Ok(signature) => signature.return_type,
+                    Err(diagnostic) => {
+                        self.emitter.diagnostics.push(diagnostic);
+                        return;
+                    }
+                };
+                let function = self.emitter.function_name(&next);
+                let receiver = match self_arg {
+                    SelfArg::Value | SelfArg::Pointer => iterable,
+                    SelfArg::AddressOf => format!("&{}", iterable),
+                    SelfArg::Deref => format!("*{}", iterable),
+                };
+                let result = self.temp();
+                let result_type = self.emitter.c_type(&next_type);
+                self.line("for (;;) {");
+                self.line(format!("    {} {} = {}({});", result_type, result, function, receiver));
+                if matches!(next_type, Type::Pointer { .. }) {
+                    self.line(format!("    if (!{}) break;", result));
+                    self.line(format!("    {} {} = {};", item_type, pattern, result));
+                } else {
+                    self.line(format!("    if (!{}.some) break;", result));
+                    self.line(format!("    {} {} = {}.value;", item_type, pattern, result));
+                }
+            }
+        }
+        self.nested(&for_stmt.body);
+        self.scopes.pop();
+        self.line("}");
+        self.indent -= 1;
+        self.line("}");
+    }
+
+    /// `for i in start..end` counts a hidden counter, so assigning to `i`
+    /// does not change the iteration. An inclusive range clears a flag at
+    /// its end instead of incrementing past it, which could overflow.
+    fn range_loop(&mut self, for_stmt: &ForStatement, range: &RangeExpr, item: &Type) {
+        let c_type = self.emitter.c_type(item);
+        self.line("{");
+        self.indent += 1;
+        let Some(start) = range.start.as_deref().and_then(|start| self.value(start)) else { return };
+        let counter = self.store(item, &start);
+        let end = match range.end.as_deref() {
+            Some(end) => {
+                let Some(end) = self.value(end) else { return };
+                Some(self.store(item, &end))
+            }
+            None => None,
+        };
+        match (end, range.is_inclusive) {
+            (Some(end), false) => self.line(format!("for (; {c} < {}; {c}++) {{", end, c = counter)),
+            (Some(end), true) => {
+                let more = self.temp();
+                self.line(format!("bool {} = {} <= {};", more, counter, end));
+                self.line(format!("for (; {m}; {m} = {c} != {}, {c} += {m}) {{", end, c = counter, m = more));
+            }
+            (None, _) => {
+                let add = format!("fig_add_{}", mangle::suffix(item));
+                self.line(format!("for (;; {c} = {}({c}, 1)) {{", add, c = counter));
+            }
+        }
+        self.scopes.push(HashMap::new());
+        let pattern = self.local(&for_stmt.pattern);
+        self.line(format!("    {} {} = {};", c_type, pattern, counter));
+        self.nested(&for_stmt.body);
+        self.scopes.pop();
+        self.line("}");
+        self.indent -= 1;
+        self.line("}");
+    }
+
+    // ========================================================================
+    // Expressions
+    // ========================================================================
+
+    /// Lower an expression evaluated only for its effects
+    fn discard(&mut self, expr: &Expression) {
+        let Some(lowered) = self.expression(expr) else { return };
+        match expr {
+            _ if lowered.is_empty() => {}
+            Expression::Call(_) | Expression::Assign(_) => self.line(format!("{};", lowered)),
+            _ => self.line(format!("(void)({});", lowered)),
+        }
+    }
+
+    /// Lower an expression whose result is used as a value
+    fn value(&mut self, expr: &Expression) -> Lowered {
+        let lowered = self.expression(expr)?;
+        if self.type_of(expr) != Type::Ok || matches!(expr, Expression::OkLiteral) {
+            return Some(lowered);
+        }
+        Some(if lowered.is_empty() { "(fig_ok)0".to_string() } else { format!("({}, (fig_ok)0)", lowered) })
+    }
+
+    /// Lower `expr` and convert it to `to`
+    fn convert(&mut self, expr: &Expression, to: &Type) -> Lowered {
+        let from = self.type_of(expr);
+        if let (Expression::StringLiteral(text), Type::Array { size: None, .. }) = (expr, to) {
+            let slice = self.emitter.c_type(to);
+            return Some(format!("(({}){{(uint8_t *){}, {}}})", slice, c_string(text), text.len()));
+        }
+        let value = self.value(expr)?;
+        let place = self.is_place(expr);
+        self.coerce(value, &from, to, place)
+    }
+
+    /// Convert the C value `value` of type `from` to `to`, following the
+    /// implicit conversions the type checker allows. `place` says whether
+    /// `value` is an lvalue.
+    fn coerce(&mut self, value: String, from: &Type, to: &Type, place: bool) -> Lowered {
+        if from == to {
+            return Some(value);
+        }
+        Some(match (from, to) {
+            (Type::Null, Type::Optional(_)) => format!("(({}){{.some = false}})", self.emitter.c_type(to)),
+            (Type::Null, _) | (Type::Pointer { .. }, Type::Pointer { .. }) => value,
+            (from, Type::Optional(inner)) => {
+                let inner = self.coerce(value, from, inner, place)?;
+                format!("(({}){{.some = true, .value = {}}})", self.emitter.c_type(to), inner)
+            }
+            (from, Type::ErrorUnion { ok_type, err_type }) => {
+                let c_type = self.emitter.c_type(to);
+                if self.emitter.tc.assignable(from, ok_type) {
+                    let ok = self.coerce(value, from, ok_type, place)?;
+                    format!("(({}){{.is_err = false, .ok = {}}})", c_type, ok)
+                } else {
+                    let conversion = self.emitter.tc.error_conversion(from, err_type)?;
+                    let err = self.convert_error(value, &conversion, err_type)?;
+                    format!("(({}){{.is_err = true, .err = {}}})", c_type, err)
+                }
+            }
+            (Type::Array { size: Some(size), .. }, Type::Array { size: None, .. }) => {
+                let array = if place { value } else { self.store(from, &value) };
+                format!("(({}){{{}.data, {}}})", self.emitter.c_type(to), array, format_expression(size))
+            }
+            (from, to) if is_integer(from) && is_integer(to) => format!("(({}){})", self.emitter.c_type(to), value),
+            _ => {
+                self.emitter.diagnostics.push(
+                    Diagnostic::error(format!(
+                        "cannot convert `{}` to `{}` in the C backend",
+                        format_type(from),
+                        format_type(to)
+                    ))
+                    .in_function(&self.name),
+                );
+                return None;
+            }
+        })
+    }
+
+    /// Turn an error value into the error type `into` of a `T ! E`
+    fn convert_error(&mut self, value: String, conversion: &ErrorConversion, into: &Path) -> Lowered {
+        let into_type = Type::Path(into.clone());
+        Some(match conversion {
+            ErrorConversion::Identity => value,
+            ErrorConversion::Variant { variant } => {
+                let tag = self.variant_tag(&into_type, variant)?;
+                let c_type = self.emitter.c_type(&into_type);
+                format!("(({}){{.tag = {}, .as.{} = {}}})", c_type, tag, mangle::ident(variant), value)
+            }
+            ErrorConversion::Function { function } => {
+                let path = Path::with_generics(function.split("::").map(String::from).collect(), Vec::new());
+                let items = self.emitter.items;
+                let def = items.lookup_function(&path)?;
+                let name = self.emitter.function_name(&Instance::new(def));
+                format!("{}({})", name, value)
+            }
+        })
+    }
+
+    fn variant_tag(&mut self, union: &Type, variant: &str) -> Option<usize> {
+        let Type::Path(path) = union else { return None };
+        match self.emitter.items.lookup_type(path) {
+            Some(TypeDef::Union(u)) => u.variants.iter().position(|v| v.name == variant),
+            _ => None,
+        }
+    }
+
+    /// Whether `expr` is a place, i.e. lowers to a C lvalue: a local, a
+    /// field or element of a place, an element through a pointer or slice,
+    /// or a dereference
+    fn is_place(&self, expr: &Expression) -> bool {
+        match expr {
+            Expression::SelfValue => true,
+            Expression::Path(_) => matches!(self.body.path(expr), Some(PathTarget::Local)),
+            Expression::Parenthesized(inner) => self.is_place(inner),
+            Expression::FieldAccess(access) if !access.is_propagating => {
+                matches!(self.body.type_of(&access.object), Some(Type::Pointer { .. })) || self.is_place(&access.object)
+            }
+            Expression::Index(index) if matches!(*index.index, Expression::Range(_)) => false,
+            Expression::Index(index) => {
+                !matches!(self.body.type_of(&index.object), Some(Type::Array { size: Some(_), .. }))
+                    || self.is_place(&index.object)
+            }
+            Expression::UnaryOp(op) => op.op == UnaryOperator::Dereference,
+            _ => false,
+        }
+    }
+
+    /// `object[start..end]`: the bounds are checked, then the slice points
+    /// into the array or slice's elements
+    fn subslice(&mut self, index: &IndexExpr, ty: &Type) -> Lowered {
+        let Expression::Range(range) = index.index.as_ref() else { return None };
+        let object_type = self.type_of(&index.object);
+        let object = self.expression(&index.object)?;
+        let object = if self.is_place(&index.object) { object } else { self.store(&object_type, &object) };
+        let (data, len) = match &object_type {
+            Type::Array { size: Some(size), .. } => (format!("{}.data", object), format_expression(size)),
+            _ => (format!("{}.ptr", object), format!("{}.len", object)),
+        };
+        let start = match range.start.as_deref() {
+            Some(start) => {
+                let start = self.value(start)?;
+                self.store(&Type::USize, &format!("(size_t)({})", start))
+            }
+            None => "0".to_string(),
+        };
+        let end = match range.end.as_deref() {
+            Some(end) => {
+                let end = format!("(size_t)({})", self.value(end)?);
+                let end = if range.is_inclusive { format!("fig_add_usize({}, 1)", end) } else { end };
+                self.store(&Type::USize, &end)
+            }
+            None => len.clone(),
+        };
+        self.line(format!("fig_check_slice({}, {}, {});", start, end, len));
+        let slice = self.emitter.c_type(ty);
+        Some(format!("(({}){{{} + {s}, {} - {s}}})", slice, data, end, s = start))
+    }
+
+    /// Lower a place expression and return its lvalue
+    fn lvalue(&mut self, expr: &Expression) -> Lowered {
+        if !self.is_place(expr) {
+            return None;
+        }
+        self.expression(expr)
+    }
+
+    /// The lowered expression, with any statements it needs already written.
+    /// `None` after a diagnostic.
+    fn expression(&mut self, expr: &Expression) -> Lowered {
+        let ty = self.type_of(expr);
+        match expr {
+            Expression::IntegerLiteral(lit) => Some(int_constant(literal_value(lit), &ty)),
+            Expression::FloatLiteral(lit) => {
+                let value = lit.as_f64().unwrap_or(0.0);
+                let text = format!("{:?}", value);
+                Some(if ty == Type::F32 { format!("((float){})", text) } else { text })
+            }
+            Expression::BooleanLiteral(b) => Some(b.to_string()),
+            Expression::CharLiteral(c) => {
+                Some(int_constant(c.chars().next().map_or(0, u32::from) as i128, &ty))
+            }
+            Expression::StringLiteral(text) => match &ty {
+                Type::Pointer { .. } => Some(format!("((uint8_t *){})", c_string(text))),
+                _ => {
+                    let slice = self.emitter.c_type(&ty);
+                    Some(format!("(({}){{(uint8_t *){}, {}}})", slice, c_string(text), text.len()))
+                }
+            },
+            Expression::OkLiteral => Some("(fig_ok)0".to_string()),
+            Expression::NullLiteral => match &ty {
+                Type::Optional(_) => Some(format!("(({}){{.some = false}})", self.emitter.c_type(&ty))),
+                _ => Some("NULL".to_string()),
+            },
+            Expression::SelfValue => Some("self".to_string()),
+            Expression::Path(_) | Expression::TypeAccess(_) | Expression::EnumVariant(_) => self.path(expr, &ty),
+            Expression::StructLiteral(lit) => self.struct_literal(lit, &ty),
+            Expression::VariantLiteral(lit) => self.variant(&ty, &lit.variant, lit.payload.as_deref()),
+            Expression::ArrayLiteral(array) => {
+                let Type::Array { element_type, .. } = &ty else { return None };
+                let mut elements = Vec::with_capacity(array.elements.len());
+                for element in &array.elements {
+                    elements.push(self.convert(element, element_type)?);
+                }
+                let c_type = self.emitter.c_type(&ty);
+                if elements.is_empty() {
+                    return Some(format!("(({}){{0}})", c_type));
+                }
+                Some(format!("(({}){{{{{}}}}})", c_type, elements.join(", ")))
+            }
+            Expression::InterpolatedString(_) => self.unsupported("interpolated strings", expr),
+            Expression::Lambda(_) => self.unsupported("lambdas", expr),
+            Expression::BinaryOp(op) => self.binary(op, &ty),
+            Expression::UnaryOp(op) => self.unary(op, &ty),
+            Expression::FieldAccess(access) => self.field(access),
+            Expression::Call(call) => self.call(call, expr),
+            Expression::Index(index) if matches!(*index.index, Expression::Range(_)) => self.subslice(index, &ty),
+            Expression::Range(_) => self.unsupported("ranges outside `for` loops and slices", expr),
+            Expression::Index(index) => {
+                let object_type = self.type_of(&index.object);
+                let object = self.expression(&index.object)?;
+                let index_type = self.type_of(&index.index);
+                let position = self.value(&index.index)?;
+                let check = |len: String| {
+                    if is_signed(&index_type) {
+                        format!("fig_index_i({}, {})", position, len)
+                    } else {
+                        format!("fig_index_u({}, {})", position, len)
+                    }
+                };
+                Some(match &object_type {
+                    Type::Array { size: Some(size), .. } => {
+                        format!("{}.data[{}]", object, check(format_expression(size)))
+                    }
+                    Type::Array { size: None, .. } => {
+                        let slice = if self.is_place(&index.object) {
+                            object
+                        } else {
+                            self.store(&object_type, &object)
+                        };
+                        let checked = check(format!("{}.len", slice));
+                        format!("{}.ptr[{}]", slice, checked)
+                    }
+                    Type::Pointer { element_type, .. } => {
+                        let element = self.emitter.c_type(element_type);
+                        format!("(({} *)fig_nonnull({}))[{}]", element, object, position)
+                    }
+                    _ => return None,
+                })
+            }
+            Expression::Cast(cast) => self.cast(&cast.expr, &ty),
+            Expression::Sizeof(target) | Expression::Alignof(target) => {
+                let Ok(target) = self.emitter.tc.normalize(target, &self.bindings) else { return None };
+                let c_type = self.emitter.c_type(&target);
+                let operator = if matches!(expr, Expression::Sizeof(_)) { "sizeof" } else { "_Alignof" };
+                Some(format!("((size_t){}({}))", operator, c_type))
+            }
+            Expression::Offsetof(offsetof) => {
+                let Ok(target) = self.emitter.tc.normalize(&offsetof.ty, &self.bindings) else { return None };
+                let c_type = self.emitter.c_type(&target);
+                Some(format!("((size_t)offsetof({}, {}))", c_type, mangle::ident(&offsetof.field)))
+            }
+            Expression::Parenthesized(inner) => {
+                let inner = self.expression(inner)?;
+                Some(if inner.is_empty() { inner } else { format!("({})", inner) })
+            }
+            Expression::Assign(assign) => {
+                self.assign(assign)?;
+                Some(String::new())
+            }
+        }
+    }
+
+    fn path(&mut self, expr: &Expression, ty: &Type) -> Lowered {
+        match self.body.path(expr)?.clone() {
+            PathTarget::Local => match expr {
+                Expression::Path(path) => Some(self.lookup(&path.segments[0])),
+                _ => None,
+            },
+            PathTarget::Const(c) => {
+                let value = self.value(&c.value)?;
+                let from = self.type_of(&c.value);
+                self.coerce(value, &from, ty, false)
+            }
+            PathTarget::EnumVariant { .. } => {
+                let variant = match expr {
+                    Expression::Path(path) => path.segments.last()?.clone(),
+                    Expression::TypeAccess(access) => access.member.clone(),
+                    Expression::EnumVariant(value) => value.variant.clone(),
+                    _ => return None,
+                };
+                Some(format!("{}__{}", self.emitter.c_type(ty), variant))
+            }
+            PathTarget::UnionVariant { variant } => {
+                let tag = self.variant_tag(ty, &variant)?;
+                Some(format!("(({}){{.tag = {}}})", self.emitter.c_type(ty), tag))
+            }
+        }
+    }
+
+    /// A struct literal as a compound literal. Fields not written are read
+    /// from a copy of the base, which is taken after the values written are
+    /// stored.
+    fn struct_literal(&mut self, lit: &StructLiteralExpr, ty: &Type) -> Lowered {
+        let fields = self.emitter.fields_of(ty);
+        let mut values: Vec<Option<String>> = vec![None; fields.len()];
+        for (i, init) in lit.fields.iter().enumerate() {
+            let index = match &init.name {
+                Some(name) => fields.iter().position(|(field, _)| field == name)?,
+                None => i,
+            };
+            let value = self.convert(&init.value, &fields[index].1)?;
+            values[index] = Some(if lit.base.is_some() { self.store(&fields[index].1, &value) } else { value });
+        }
+        let base = match &lit.base {
+            Some(base) => {
+                let value = self.value(base)?;
+                Some(self.store(ty, &value))
+            }
+            None => None,
+        };
+        let mut initialisers = Vec::with_capacity(fields.len());
+        for ((name, _), value) in fields.iter().zip(values) {
+            initialisers.push(match (value, &base) {
+                (Some(value), _) => value,
+                (None, Some(base)) => format!("{}.{}", base, mangle::ident(name)),
+                (None, None) => return None,
+            });
+        }
+        let c_type = self.emitter.c_type(ty);
+        if initialisers.is_empty() {
+            Some(format!("(({}){{0}})", c_type))
+        } else {
+            Some(format!("(({}){{{}}})", c_type, initialisers.join(", ")))
+        }
+    }
+
+    /// A value of the union `union` holding `variant`
+    fn variant(&mut self, union: &Type, variant: &str, payload: Option<&Expression>) -> Lowered {
+        let tag = self.variant_tag(union, variant)?;
+        let c_type = self.emitter.c_type(union);
+        let payload_type = self.emitter.fields_of(union).into_iter().find(|(name, _)| name == variant)?.1;
+        Some(match payload {
+            Some(arg) if payload_type != Type::Ok => {
+                let value = self.convert(arg, &payload_type)?;
+                format!("(({}){{.tag = {}, .as.{} = {}}})", c_type, tag, mangle::ident(variant), value)
+            }
+            _ => format!("(({}){{.tag = {}}})", c_type, tag),
+        })
+    }
+
+    fn binary(&mut self, op: &BinaryOpExpr, ty: &Type) -> Lowered {
+        use BinaryOperator::*;
+        if matches!(op.op, LogicalAnd | LogicalOr) {
+            let lhs = self.expression(&op.lhs)?;
+            let mark = self.out.len();
+            let rhs = self.expression(&op.rhs)?;
+            if self.out.len() == mark {
+                let operator = if op.op == LogicalAnd { "&&" } else { "||" };
+                return Some(format!("({} {} {})", lhs, operator, rhs));
+            }
+            // The right operand needs statements, which must only run when it is evaluated
+            let prelude = self.out.split_off(mark);
+            let temp = self.store(&Type::Bool, &lhs);
+            let test = if op.op == LogicalAnd { temp.clone() } else { format!("!{}", temp) };
+            self.line(format!("if ({}) {{", test));
+            for line in prelude.lines() {
+                self.out.push_str("    ");
+                self.out.push_str(line);
+                self.out.push('\n');
+            }
+            self.line(format!("    {} = {};", temp, rhs));
+            self.line("}");
+            return Some(temp);
+        }
+
+        let lhs_type = self.type_of(&op.lhs);
+        let rhs_type = self.type_of(&op.rhs);
+        let lhs = self.value(&op.lhs)?;
+        let rhs = self.value(&op.rhs)?;
+        let comparison = match op.op {
+            Equal => Some("=="),
+            NotEqual => Some("!="),
+            LessThan => Some("<"),
+            GreaterThan => Some(">"),
+            LessThanOrEqual => Some("<="),
+            GreaterThanOrEqual => Some(">="),
+            _ => None,
+        };
+        if let Some(operator) = comparison {
+            let negate = if op.op == Equal { "!" } else { "" };
+            let null_rhs = matches!(op.rhs.as_ref(), Expression::NullLiteral) || rhs_type == Type::Null;
+            let null_lhs = matches!(op.lhs.as_ref(), Expression::NullLiteral) || lhs_type == Type::Null;
+            return Some(match (&lhs_type, &rhs_type) {
+                (Type::Optional(_), _) if null_rhs => format!("({}{}.some)", negate, lhs),
+                (_, Type::Optional(_)) if null_lhs => format!("({}{}.some)", negate, rhs),
+                (Type::Optional(_), Type::Optional(_)) => format!("({} {} {})", lhs, operator, rhs),
+                (Type::Optional(_), _) | (_, Type::Optional(_)) => {
+                    let (optional, optional_type, other) =
+                        if matches!(lhs_type, Type::Optional(_)) { (lhs, &lhs_type, rhs) } else { (rhs, &rhs_type, lhs) };
+                    let optional = self.store(optional_type, &optional);
+                    let equal = format!("({o}.some && {o}.value == {})", other, o = optional);
+                    if op.op == Equal { equal } else { format!("(!{})", equal) }
+                }
+                _ => format!("({} {} {})", lhs, operator, rhs),
+            });
+        }
+
+        let name = mangle::suffix(ty);
+        let checked = |function: &str| format!("fig_{}_{}({}, {})", function, name, lhs, rhs);
+        Some(match op.op {
+            Add | Subtract if matches!(lhs_type, Type::Pointer { .. }) && is_integer(&rhs_type) => {
+                let operator = if op.op == Add { "+" } else { "-" };
+                format!("({} {} {})", lhs, operator, rhs)
+            }
+            Subtract if matches!(lhs_type, Type::Pointer { .. }) => format!("((ptrdiff_t)({} - {}))", lhs, rhs),
+            Add if is_integer(ty) => checked("add"),
+            Subtract if is_integer(ty) => checked("sub"),
+            Multiply if is_integer(ty) => checked("mul"),
+            Divide if is_integer(ty) => checked("div"),
+            Modulo => checked("rem"),
+            ShiftLeft => format!("fig_shl_{}({}, (int64_t)({}))", name, lhs, rhs),
+            ShiftRight => format!("fig_shr_{}({}, (int64_t)({}))", name, lhs, rhs),
+            Add => format!("({} + {})", lhs, rhs),
+            Subtract => format!("({} - {})", lhs, rhs),
+            Multiply => format!("({} * {})", lhs, rhs),
+            Divide => format!("({} / {})", lhs, rhs),
+            BitwiseAnd | BitwiseOr | BitwiseXor => {
+                let operator = match op.op {
+                    BitwiseAnd => "&",
+                    BitwiseOr => "|",
+                    _ => "^",
+                };
+                format!("(({})({} {} {}))", self.emitter.c_type(ty), lhs, operator, rhs)
+            }
+            _ => return None,
+        })
+    }
+
+    fn unary(&mut self, op: &UnaryOpExpr, ty: &Type) -> Lowered {
+        match op.op {
+            UnaryOperator::Negate if matches!(op.operand.as_ref(), Expression::IntegerLiteral(_)) => {
+                let Expression::IntegerLiteral(lit) = op.operand.as_ref() else { unreachable!() };
+                Some(int_constant(-literal_value(lit), ty))
+            }
+            UnaryOperator::Negate if is_integer(ty) => {
+                let operand = self.value(&op.operand)?;
+                Some(format!("fig_neg_{}({})", mangle::suffix(ty), operand))
+            }
+            UnaryOperator::Negate => Some(format!("(-{})", self.value(&op.operand)?)),
+            UnaryOperator::Plus => self.value(&op.operand),
+            UnaryOperator::LogicalNot => Some(format!("(!{})", self.value(&op.operand)?)),
+            UnaryOperator::BitwiseNot => {
+                let operand = self.value(&op.operand)?;
+                Some(format!("(({})~{})", self.emitter.c_type(ty), operand))
+            }
+            UnaryOperator::AddressOf => {
+                if let Some(place) = self.lvalue(&op.operand) {
+                    return Some(format!("(&{})", place));
+                }
+                // The address of a temporary that lives until the end of the block
+                let operand_type = self.type_of(&op.operand);
+                let value = self.value(&op.operand)?;
+                let temp = self.store(&operand_type, &value);
+                Some(format!("(&{})", temp))
+            }
+            UnaryOperator::Dereference => {
+                let pointer = self.value(&op.operand)?;
+                let c_type = self.emitter.c_type(ty);
+                Some(format!("(*({} *)fig_nonnull({}))", c_type, pointer))
+            }
+        }
+    }
+
+    fn cast(&mut self, operand: &Expression, to: &Type) -> Lowered {
+        let from = self.type_of(operand);
+        let value = self.value(operand)?;
+        if from == *to {
+            return Some(value);
+        }
+        let c_type = self.emitter.c_type(to);
+        let is_enum = |this: &Self, ty: &Type| {
+            matches!(ty, Type::Path(path) if matches!(this.emitter.items.lookup_type(path), Some(TypeDef::Enum(_))))
+        };
+        Some(if is_float(&from) && is_integer(to) {
+            format!("fig_ftoi_{}((double)({}))", mangle::suffix(to), value)
+        } else if is_enum(self, to) {
+            format!("fig_enum__{}((int64_t)({}))", c_type, value)
+        } else if matches!(to, Type::Bool) {
+            format!("(({}) != 0)", value)
+        } else if matches!(from, Type::Pointer { .. }) != matches!(to, Type::Pointer { .. }) {
+            format!("(({})(uintptr_t)({}))", c_type, value)
+        } else {
+            format!("(({})({}))", c_type, value)
+        })
+    }
+
+    fn field(&mut self, access: &FieldAccessExpr) -> Lowered {
+        let object_type = self.type_of(&access.object);
+        let mut object = self.expression(&access.object)?;
+        let mut object_type = object_type;
+        if access.is_propagating {
+            let conversion = self.body.unwrap_field(access)?.clone();
+            object = self.propagate(object, &object_type, &conversion)?;
+            let Type::ErrorUnion { ok_type, .. } = object_type else { return None };
+            object_type = *ok_type;
+        }
+        let (base, through_pointer) = match &object_type {
+            Type::Pointer { element_type, .. } => ((**element_type).clone(), true),
+            other => (other.clone(), false),
+        };
+        if let Type::Array { size, .. } = &base
+            && access.field == "len"
+        {
+            return Some(match size {
+                Some(size) => format!("((size_t){})", format_expression(size)),
+                None if through_pointer => format!("{}->len", object),
+                None => format!("{}.len", object),
+            });
+        }
+        let member = mangle::ident(&access.field);
+        let object = if through_pointer {
+            if matches!(access.object.as_ref(), Expression::SelfValue) {
+                format!("{}->", object)
+            } else {
+                let c_type = self.emitter.c_type(&base);
+                format!("(({} *)fig_nonnull({}))->", c_type, object)
+            }
+        } else {
+            format!("{}.", object)
+        };
+        let Type::Path(path) = &base else { return None };
+        let items = self.emitter.items;
+        let Some(TypeDef::Union(u)) = items.lookup_type(path) else {
+            return Some(format!("{}{}", object, member));
+        };
+        // Reading a union variant checks that it is the active one
+        let tag = u.variants.iter().position(|v| v.name == access.field)?;
+        let c_type = self.emitter.c_type(&base);
+        let temp = self.temp();
+        let object = object.strip_suffix("->").map(str::to_string).unwrap_or_else(|| {
+            format!("&{}", object.strip_suffix('.').unwrap_or(&object))
+        });
+        let object = if self.is_place(&access.object) || through_pointer {
+            object
+        } else {
+            // Not an lvalue: copy it into a temporary first
+            let value = object.trim_start_matches('&').to_string();
+            format!("&{}", self.store(&base, &value))
+        };
+        self.line(format!("{} *{} = {};", c_type, temp, object));
+        self.line(format!(
+            "if ({t}->tag != {}) fig_inactive(\"{}\", \"{}\", fig_variants__{}[{t}->tag]);",
+            tag,
+            access.field,
+            u.name,
+            c_type,
+            t = temp
+        ));
+        Some(format!("{}->as.{}", temp, member))
+    }
+
+    /// Unwrap the `T ! E` in `value`, returning from the function with the
+    /// converted error if it holds one. Returns the C expression of the `T`.
+    fn propagate(&mut self, value: String, ty: &Type, conversion: &ErrorConversion) -> Lowered {
+        let Type::ErrorUnion { err_type: into, .. } = self.return_type.clone() else { return None };
+        let temp = self.store(ty, &value);
+        let err = self.convert_error(format!("{}.err", temp), conversion, &into)?;
+        let return_type = self.emitter.c_type(&self.return_type.clone());
+        self.line(format!("if ({}.is_err) return (({}){{.is_err = true, .err = {}}});", temp, return_type, err));
+        Some(format!("{}.ok", temp))
+    }
+
+    fn assign(&mut self, assign: &AssignExpr) -> Option<()> {
+        let lhs_type = self.type_of(&assign.lhs);
+        // Writing a union variant makes it the active one
+        if let Expression::FieldAccess(access) = assign.lhs.as_ref()
+            && assign.op == AssignOperator::Assign
+            && let Some((base, through_pointer)) = match self.type_of(&access.object) {
+                Type::Pointer { element_type, .. } => Some((*element_type, true)),
+                other => Some((other, false)),
+            }
+            && let Type::Path(path) = &base
+            && let Some(TypeDef::Union(_)) = self.emitter.items.lookup_type(path)
+        {
+            let tag = self.variant_tag(&base, &access.field)?;
+            let object = self.expression(&access.object)?;
+            let rhs = self.convert(&assign.rhs, &lhs_type)?;
+            let c_type = self.emitter.c_type(&base);
+            let pointer = if through_pointer { format!("({} *)fig_nonnull({})", c_type, object) } else { format!("&{}", object) };
+            let temp = self.store(&Type::Pointer { nullable: false, mutable: true, element_type: Box::new(base) }, &pointer);
+            if lhs_type != Type::Ok {
+                self.line(format!("{}->as.{} = {};", temp, mangle::ident(&access.field), rhs));
+            }
+            self.line(format!("{}->tag = {};", temp, tag));
+            return Some(());
+        }
+
+        let place = self.lvalue(&assign.lhs)?;
+        if assign.op == AssignOperator::Assign {
+            let rhs = self.convert(&assign.rhs, &lhs_type)?;
+            self.line(format!("{} = {};", place, rhs));
+            return Some(());
+        }
+        let rhs = self.value(&assign.rhs)?;
+        let name = mangle::suffix(&lhs_type);
+        let (function, operator) = match assign.op {
+            AssignOperator::AddAssign => ("add", "+="),
+            AssignOperator::SubAssign => ("sub", "-="),
+            AssignOperator::MulAssign => ("mul", "*="),
+            AssignOperator::DivAssign => ("div", "/="),
+            AssignOperator::ModAssign => ("rem", "%="),
+            AssignOperator::BitAndAssign => ("", "&="),
+            AssignOperator::BitOrAssign => ("", "|="),
+            AssignOperator::BitXorAssign => ("", "^="),
+            AssignOperator::ShlAssign => ("shl", ""),
+            AssignOperator::ShrAssign => ("shr", ""),
+            AssignOperator::Assign => unreachable!("handled above"),
+        };
+        if function.is_empty() || !is_integer(&lhs_type) && !matches!(function, "shl" | "shr") {
+            // Bitwise operators, and arithmetic on floats and pointers, cannot overflow
+            self.line(format!("{} {} {};", place, operator, rhs));
+        } else if matches!(function, "shl" | "shr") {
+            self.line(format!("{p} = fig_{}_{}({p}, (int64_t)({}));", function, name, rhs, p = place));
+        } else {
+            self.line(format!("{p} = fig_{}_{}({p}, {});", function, name, rhs, p = place));
+        }
+        Some(())
+    }
+
+    // ========================================================================
+    // Calls
+    // ========================================================================
+
+    fn call(&mut self, call: &CallExpr, expr: &Expression) -> Lowered {
+        let target = self.body.call(call).cloned()?;
+        let lowered = match target {
+            CallTarget::Function { instance, self_arg } => self.function_call(call, &instance, self_arg)?,
+            CallTarget::Construct(ty) => {
+                let fields = self.emitter.fields_of(&ty);
+                let mut values = Vec::with_capacity(fields.len());
+                for ((_, field_type), arg) in fields.iter().zip(&call.args) {
+                    values.push(self.convert(arg, field_type)?);
+                }
+                let c_type = self.emitter.c_type(&ty);
+                if values.is_empty() {
+                    format!("(({}){{0}})", c_type)
+                } else {
+                    format!("(({}){{{}}})", c_type, values.join(", "))
+                }
+            }
+            CallTarget::Variant { union, variant } => self.variant(&union, &variant, call.args.first())?,
+            CallTarget::Builtin(builtin) => return self.builtin(builtin, &call.args, expr),
+            CallTarget::Value(_) => return self.unsupported("calls through function values", expr),
+        };
+        if !call.is_propagating {
+            return Some(lowered);
+        }
+        let conversion = self.body.unwrap_call(call)?.clone();
+        let result_type = match self.body.call(call) {
+            Some(CallTarget::Function { instance, .. }) => self.emitter.tc.signature(instance).ok()?.return_type,
+            _ => return None,
+        };
+        self.propagate(lowered, &result_type, &conversion)
+    }
+
+    fn function_call(&mut self, call: &CallExpr, instance: &Instance<'a>, self_arg: Option<SelfArg>) -> Lowered {
+        let signature = match self.emitter.tc.signature(instance) {
+            Ok(signature) => signature,
+            Err(diagnostic) => {
+                self.emitter.diagnostics.push(diagnostic);
+                return None;
+            }
+        };
+        let mut args = Vec::with_capacity(call.args.len() + 1);
+        let mut explicit = call.args.iter();
+        if let Some(self_type) = &signature.self_type {
+            match (self_arg, call.callee.as_ref()) {
+                (Some(self_arg), Expression::FieldAccess(access)) => {
+                    let mut object_type = self.type_of(&access.object);
+                    let mut object = self.expression(&access.object)?;
+                    if access.is_propagating {
+                        let conversion = self.body.unwrap_field(access)?.clone();
+                        object = self.propagate(object, &object_type, &conversion)?;
+                        let Type::ErrorUnion { ok_type, .. } = object_type else { return None };
+                        object_type = *ok_type;
+                    }
+                    let receiver = match self_arg {
+                        SelfArg::Value => object,
+                        SelfArg::Pointer if matches!(access.object.as_ref(), Expression::SelfValue) => object,
+                        SelfArg::Pointer => format!("fig_nonnull({})", object),
+                        SelfArg::AddressOf if !access.is_propagating && self.is_place(&access.object) => {
+                            format!("&{}", object)
+                        }
+                        SelfArg::AddressOf => format!("&{}", self.store(&object_type, &object)),
+                        SelfArg::Deref => {
+                            let Type::Pointer { element_type, .. } = &object_type else { return None };
+                            let c_type = self.emitter.c_type(element_type);
+                            format!("*({} *)fig_nonnull({})", c_type, object)
+                        }
+                    };
+                    args.push(receiver);
+                }
+                _ => {
+                    let receiver = explicit.next()?;
+                    args.push(self.convert(receiver, self_type)?);
+                }
+            }
+        }
+        let libc = instance.function.signature.is_extern
+            && LIBC_FUNCTIONS.contains(&instance.function.signature.name.as_str());
+        for ((_, param_type), arg) in signature.params.iter().zip(explicit) {
+            let value = self.convert(arg, param_type)?;
+            // Let C convert pointers to the library's own parameter types
+            args.push(if libc && matches!(param_type, Type::Pointer { .. }) { format!("(void *){}", value) } else { value });
+        }
+        let name = self.emitter.function_name(instance);
+        Some(format!("{}({})", name, args.join(", ")))
+    }
+
+    fn builtin(&mut self, builtin: Builtin, args: &[Expression], expr: &Expression) -> Lowered {
+        let byte_pointer = |call: String| format!("((uint8_t *){})", call);
+        Some(match builtin {
+            Builtin::Print | Builtin::Println => {
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        self.line("putchar(' ');");
+                    }
+                    if let Expression::StringLiteral(text) = arg {
+                        self.line(format!("fwrite({}, 1, {}, stdout);", c_string(text), text.len()));
+                        continue;
+                    }
+                    let ty = self.type_of(arg);
+                    let value = self.value(arg)?;
+                    match self.emitter.print_value(&ty, &value, 0) {
+                        Ok(lines) => {
+                            for line in lines {
+                                self.line(line);
+                            }
+                        }
+                        Err(message) => {
+                            self.emitter.diagnostics.push(
+                                Diagnostic::error(message).in_function(&self.name).with_snippet(format_expression(expr)),
+                            );
+                            return None;
+                        }
+                    }
+                }
+                if builtin == Builtin::Println {
+                    self.line("putchar('\\n');");
+                }
+                String::new()
+            }
+            Builtin::Assert => {
+                let condition = self.value(&args[0])?;
+                self.line(format!("if (!{}) fig_trap(\"assertion failed\");", condition));
+                String::new()
+            }
+            Builtin::Malloc => byte_pointer(format!("malloc({})", self.value(&args[0])?)),
+            Builtin::Calloc => {
+                let count = self.value(&args[0])?;
+                let size = self.value(&args[1])?;
+                byte_pointer(format!("calloc({}, {})", count, size))
+            }
+            Builtin::Realloc => {
+                let pointer = self.value(&args[0])?;
+                let size = self.value(&args[1])?;
+                byte_pointer(format!("realloc({}, {})", pointer, size))
+            }
+            Builtin::Free => format!("free({})", self.value(&args[0])?),
+        })
+    }
+}
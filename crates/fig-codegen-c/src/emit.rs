@@ -0,0 +1,604 @@
+//! The translation unit: types, prototypes and function bodies
+//!
+//! Emission covers the function instances the [`Monomorphizer`] reaches from
+//! the non-generic functions with bodies, so every generic function is
+//! emitted once per instance actually used. Types are emitted on demand in
+//! dependency order: a type used by value is defined before its user, one
+//! used through a pointer only needs the forward `typedef`.
+//!
+//! Representations:
+//!
+//! | Fig                 | C                                               |
+//! |---------------------|-------------------------------------------------|
+//! | `*T`, `?*T`         | `T *`                                           |
+//! | `?T`                | `struct { bool some; T value; }`                |
+//! | `T ! E`             | `struct { bool is_err; union { T ok; E err; }; }` |
+//! | `[T; N]`            | `struct { T data[N]; }`                         |
+//! | `[T]`               | `struct { T *ptr; size_t len; }`                |
+//! | `struct`            | `struct`, with `FIG_PACKED` when `packed`      |
+//! | `enum`              | its representation type, plus a `#define` per variant |
+//! | `union`             | `struct { tag; union { ... } as; }`             |
+
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use fig_parser::ast::*;
+use fig_parser::format::format_type;
+use fig_sema::diagnostics::Diagnostic;
+use fig_sema::items::{ItemTable, TypeDef};
+use fig_sema::layout::{Shape, Target};
+use fig_sema::mono::{MonoFunction, Monomorphizer};
+use fig_sema::typeck::{Instance, TypeChecker, roots};
+
+use crate::body::FunctionLowering;
+use crate::mangle::{self, LIBC_FUNCTIONS};
+use crate::runtime::RUNTIME;
+
+/// What the generated C `main` does with Fig's `main`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryPoint {
+    /// Exit with `main`'s result if it is an integer, 0 otherwise; a
+    /// returned error exits with 1
+    ExitCode,
+    /// Print `main`'s result on its own line, as the interpreter shows it
+    PrintResult,
+    /// No C `main`, for linking the output into a C program
+    None,
+}
+
+/// Lowers the functions of an [`ItemTable`] to one C11 translation unit
+pub struct CEmitter<'a> {
+    pub(crate) items: &'a ItemTable<'a>,
+    pub(crate) tc: TypeChecker<'a>,
+    entry: EntryPoint,
+    /// Leave out the per-type helpers, which need the runtime
+    pub(crate) header: bool,
+    /// `typedef`s, enum constants and forward declarations
+    pub(crate) forward: String,
+    /// Struct bodies and per-type helpers
+    pub(crate) definitions: String,
+    pub(crate) prototypes: String,
+    bodies: String,
+    declared: HashSet<String>,
+    defined: HashSet<String>,
+    externs: HashSet<String>,
+    pub(crate) diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> CEmitter<'a> {
+    pub fn new(items: &'a ItemTable<'a>) -> Self {
+        CEmitter {
+            items,
+            tc: TypeChecker::new(items, Target::host()),
+            entry: EntryPoint::ExitCode,
+            header: false,
+            forward: String::new(),
+            definitions: String::new(),
+            prototypes: String::new(),
+            bodies: String::new(),
+            declared: HashSet::new(),
+            defined: HashSet::new(),
+            externs: HashSet::new(),
+            diagnostics: Vec::new(),
+        }
+    }
+
+    /// The target whose pointer width bounds `usize` literals; the host by default
+    pub fn with_target(mut self, target: Target) -> Self {
+        self.tc = TypeChecker::new(self.items, target);
+        self
+    }
+
+    pub fn with_entry(mut self, entry: EntryPoint) -> Self {
+        self.entry = entry;
+        self
+    }
+
+    /// Emit every function reachable from the program's non-generic functions
+    pub fn emit(mut self) -> Result<String, Vec<Diagnostic>> {
+        let mono = Monomorphizer::new(&mut self.tc).run(roots(self.items))?;
+        for function in &mono.functions {
+            if function.instance.function.body.is_some() && !function.instance.function.signature.is_extern {
+                self.emit_function(function);
+            } else {
+                self.declare_extern(&function.instance);
+            }
+        }
+        let main = self.emit_entry();
+        if !self.diagnostics.is_empty() {
+            return Err(self.diagnostics);
+        }
+
+        let mut out = String::from("/* Generated by the Fig compiler */\n\n");
+        out.push_str(RUNTIME);
+        for section in [&self.forward, &self.definitions, &self.prototypes, &self.bodies, &main] {
+            if !section.is_empty() {
+                out.push('\n');
+                out.push_str(section);
+            }
+        }
+        Ok(out)
+    }
+
+    fn emit_function(&mut self, function: &MonoFunction<'a>) {
+        let MonoFunction { instance, signature, body } = function;
+        let name = self.function_name(instance);
+        let visibility = &instance.function.signature.visibility;
+        let linkage = if matches!(visibility, Visibility::Public | Visibility::Export) || name == "fig_main" {
+            ""
+        } else {
+            "static "
+        };
+        let mut lowering = FunctionLowering::new(self, instance, body, signature.return_type.clone());
+        let mut params = Vec::new();
+        if let Some(self_type) = &signature.self_type {
+            params.push(lowering.declare_param("self", self_type));
+        }
+        for (param, ty) in &signature.params {
+            params.push(lowering.declare_param(param, ty));
+        }
+        let text = lowering.lower(instance.function.body.expect("only functions with bodies are emitted"));
+        let params = if params.is_empty() { "void".to_string() } else { params.join(", ") };
+        let ret = self.return_type(&signature.return_type);
+        let _ = writeln!(self.prototypes, "{}{} {}({});", linkage, ret, name, params);
+        let _ = write!(self.bodies, "{}{} {}({}) {{\n{}}}\n\n", linkage, ret, name, params, text);
+    }
+
+    /// The C `main` for [`EntryPoint`]
+    fn emit_entry(&mut self) -> String {
+        if self.entry == EntryPoint::None {
+            return String::new();
+        }
+        let Some(main) = self.items.functions().iter().find(|f| f.qualified_name() == "main" && f.body.is_some())
+        else {
+            return String::new();
+        };
+        let instance = Instance::new(main);
+        let ret = match self.tc.signature(&instance) {
+            Ok(signature) => signature.return_type,
+            Err(_) => return String::new(),
+        };
+        let mut out = String::from("int main(void) {\n");
+        match (self.entry, &ret) {
+            (EntryPoint::PrintResult, ty) => {
+                let value = if *ty == Type::Ok { "fig_main(), (fig_ok)0" } else { "fig_main()" };
+                let _ = writeln!(out, "    {} fig_result = ({});", self.c_type(ty), value);
+                match self.print_value(ty, "fig_result", 0) {
+                    Ok(lines) => {
+                        for line in lines {
+                            let _ = writeln!(out, "    {}", line);
+                        }
+                    }
+                    Err(message) => self.diagnostics.push(Diagnostic::error(message).in_function("main")),
+                }
+                out.push_str("    putchar('\\n');\n    return 0;\n");
+            }
+            (_, ty) if fig_sema::typeck::is_integer(ty) => out.push_str("    return (int)fig_main();\n"),
+            (_, Type::ErrorUnion { .. }) => out.push_str(
+                "    if (fig_main().is_err) {\n        fputs(\"error: main returned an error\\n\", stderr);\n        return 1;\n    }\n    return 0;\n",
+            ),
+            _ => out.push_str("    fig_main();\n    return 0;\n"),
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    // ========================================================================
+    // Functions
+    // ========================================================================
+
+    /// The C name of a function instance. `extern` functions keep their own
+    /// name, and the rest are named as [`mangle::function`] describes.
+    pub(crate) fn function_name(&mut self, instance: &Instance<'a>) -> String {
+        let signature = instance.function.signature;
+        if signature.is_extern || instance.function.body.is_none() {
+            self.declare_extern(instance);
+            return signature.name.clone();
+        }
+        let qualified = instance.function.qualified_name();
+        let mut name = match qualified.as_str() {
+            "main" => "fig_main".to_string(),
+            _ => mangle::function(&qualified, signature.visibility == Visibility::Export),
+        };
+        for (_, ty) in &instance.bindings {
+            name.push_str("__");
+            name.push_str(&mangle::suffix(ty));
+        }
+        name
+    }
+
+    pub(crate) fn declare_extern(&mut self, instance: &Instance<'a>) {
+        let signature = instance.function.signature;
+        if LIBC_FUNCTIONS.contains(&signature.name.as_str()) || !self.externs.insert(signature.name.clone()) {
+            return;
+        }
+        if !signature.is_extern {
+            self.diagnostics.push(
+                Diagnostic::error(format!("`{}` is declared but never defined", instance.function.qualified_name()))
+                    .with_note("only `extern` functions may be declared without a body"),
+            );
+            return;
+        }
+        let signature = match self.tc.signature(instance) {
+            Ok(signature) => signature,
+            Err(diagnostic) => {
+                self.diagnostics.push(diagnostic);
+                return;
+            }
+        };
+        let params: Vec<String> =
+            signature.params.iter().map(|(name, ty)| format!("{} {}", self.c_type(ty), mangle::ident(name))).collect();
+        let params = if params.is_empty() { "void".to_string() } else { params.join(", ") };
+        let ret = self.return_type(&signature.return_type);
+        let _ = writeln!(self.prototypes, "extern {} {}({});", ret, instance.function.signature.name, params);
+    }
+
+    pub(crate) fn return_type(&mut self, ty: &Type) -> String {
+        if *ty == Type::Ok { "void".to_string() } else { self.c_type(ty) }
+    }
+
+    // ========================================================================
+    // Types
+    // ========================================================================
+
+    /// The C spelling of a normalised type, defining it first if needed
+    pub(crate) fn c_type(&mut self, ty: &Type) -> String {
+        self.define(ty);
+        self.type_name(ty)
+    }
+
+    fn type_name(&mut self, ty: &Type) -> String {
+        match ty {
+            Type::U8 => "uint8_t".into(),
+            Type::U16 => "uint16_t".into(),
+            Type::U32 => "uint32_t".into(),
+            Type::U64 => "uint64_t".into(),
+            Type::USize => "size_t".into(),
+            Type::I8 => "int8_t".into(),
+            Type::I16 => "int16_t".into(),
+            Type::I32 => "int32_t".into(),
+            Type::I64 => "int64_t".into(),
+            Type::ISize => "ptrdiff_t".into(),
+            Type::F32 => "float".into(),
+            Type::F64 => "double".into(),
+            Type::Bool => "bool".into(),
+            Type::Ok => "fig_ok".into(),
+            Type::Null => "void *".into(),
+            Type::Pointer { element_type, .. } => {
+                self.declare(element_type);
+                let element = self.type_name(element_type);
+                if element.ends_with('*') { format!("{}*", element) } else { format!("{} *", element) }
+            }
+            Type::Path(path) => mangle::path(path),
+            other => format!("fig_{}", mangle::suffix(other)),
+        }
+    }
+
+    /// Make `ty` nameable: a forward `typedef` for aggregates
+    fn declare(&mut self, ty: &Type) {
+        match ty {
+            Type::Pointer { element_type, .. } => self.declare(element_type),
+            Type::Path(path) if matches!(self.items.lookup_type(path), Some(TypeDef::Enum(_))) => self.define(ty),
+            Type::Path(_) | Type::Optional(_) | Type::Array { .. } | Type::ErrorUnion { .. } => {
+                let name = self.type_name(ty);
+                if self.declared.insert(name.clone()) {
+                    let _ = writeln!(self.forward, "typedef struct {} {};", name, name);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Make `ty` complete, defining the types it contains by value first
+    fn define(&mut self, ty: &Type) {
+        let (Type::Path(_) | Type::Optional(_) | Type::Array { .. } | Type::ErrorUnion { .. }) = ty else {
+            if let Type::Pointer { element_type, .. } = ty {
+                self.declare(element_type);
+            }
+            return;
+        };
+        let name = self.type_name(ty);
+        if !self.defined.insert(name.clone()) {
+            return;
+        }
+        self.declare(ty);
+        match ty {
+            Type::Optional(inner) => {
+                let inner = self.c_type(inner);
+                let _ = writeln!(self.definitions, "struct {} {{\n    bool some;\n    {} value;\n}};\n", name, inner);
+            }
+            Type::ErrorUnion { ok_type, err_type } => {
+                let ok = self.c_type(ok_type);
+                let err = self.c_type(&Type::Path(err_type.clone()));
+                let _ = writeln!(
+                    self.definitions,
+                    "struct {} {{\n    bool is_err;\n    union {{\n        {} ok;\n        {} err;\n    }};\n}};\n",
+                    name, ok, err
+                );
+            }
+            Type::Array { element_type, size: Some(size) } => {
+                let element = self.c_type(element_type);
+                let count = match size.as_ref() {
+                    Expression::IntegerLiteral(lit) => lit.as_u64().unwrap_or(0),
+                    _ => 0,
+                };
+                // C has no zero-length arrays
+                let _ = writeln!(self.definitions, "struct {} {{\n    {} data[{}];\n}};\n", name, element, count.max(1));
+            }
+            Type::Array { element_type, size: None } => {
+                let element = self.c_type(element_type);
+                let _ = writeln!(self.definitions, "struct {} {{\n    {} *ptr;\n    size_t len;\n}};\n", name, element);
+            }
+            Type::Path(path) => self.define_named(ty, path, &name),
+            _ => unreachable!("only aggregates are defined"),
+        }
+    }
+
+    fn define_named(&mut self, ty: &Type, path: &Path, name: &str) {
+        let Some(def) = self.items.lookup_type(path) else { return };
+        match def {
+            TypeDef::Struct(s) => {
+                let fields = self.fields_of(ty);
+                let mut members = Vec::new();
+                for (field, field_type) in &fields {
+                    members.push(format!("{} {}", self.c_type(field_type), mangle::ident(field)));
+                }
+                if members.is_empty() {
+                    members.push("uint8_t fig_empty".to_string());
+                }
+                if s.annotations.iter().any(|a| a.name == "align")
+                    && let Ok(align) = self.tc.layout().align_of(ty)
+                {
+                    members[0] = format!("_Alignas({}) {}", align, members[0]);
+                }
+                let packed = if s.is_packed { "FIG_PACKED " } else { "" };
+                let mut text = format!("struct {}{} {{\n", packed, name);
+                for member in members {
+                    let _ = writeln!(text, "    {};", member);
+                }
+                text.push_str("};\n\n");
+                self.definitions.push_str(&text);
+            }
+            TypeDef::Union(u) => {
+                let variants = self.fields_of(ty);
+                let tag = match self.tc.layout().layout_of(ty).map(|l| l.shape) {
+                    Ok(Shape::Tagged { tag_size: 2, .. }) => "uint16_t",
+                    Ok(Shape::Tagged { tag_size: 4, .. }) => "uint32_t",
+                    _ => "uint8_t",
+                };
+                let mut text = format!("struct {} {{\n    {} tag;\n", name, tag);
+                let payloads: Vec<&(String, Type)> = variants.iter().filter(|(_, ty)| *ty != Type::Ok).collect();
+                if !payloads.is_empty() {
+                    text.push_str("    union {\n");
+                    for (variant, variant_type) in payloads {
+                        let _ = writeln!(text, "        {} {};", self.c_type(variant_type), mangle::ident(variant));
+                    }
+                    text.push_str("    } as;\n");
+                }
+                text.push_str("};\n\n");
+                if self.header {
+                    self.definitions.push_str(&text);
+                    return;
+                }
+                let names: Vec<String> = u.variants.iter().map(|v| format!("\"{}\"", v.name)).collect();
+                let _ = write!(
+                    text,
+                    "static const char *const fig_variants__{}[] = {{{}}};\n\n",
+                    name,
+                    names.join(", ")
+                );
+                self.definitions.push_str(&text);
+            }
+            TypeDef::Enum(e) => {
+                let repr = match self.tc.layout().layout_of(ty) {
+                    Ok(layout) => {
+                        let signed = matches!(layout.shape, Shape::Enum { signed: true, .. });
+                        format!("{}int{}_t", if signed { "" } else { "u" }, layout.size * 8)
+                    }
+                    Err(error) => {
+                        self.diagnostics.push(Diagnostic::error(error.to_string()));
+                        return;
+                    }
+                };
+                let discriminants = self.tc.layout().enum_discriminants(e).unwrap_or_default();
+                let _ = writeln!(self.forward, "typedef {} {};", repr, name);
+                for (variant, value) in &discriminants {
+                    let _ = writeln!(self.forward, "#define {}__{} (({}){})", name, variant, name, value);
+                }
+                if self.header {
+                    return;
+                }
+                // Checked conversion from an integer, and the name of a value
+                let mut text = format!("static inline {} fig_enum__{}(int64_t value) {{\n    switch (value) {{\n", name, name);
+                for (_, value) in &discriminants {
+                    let _ = writeln!(text, "    case {}:", value);
+                }
+                if !discriminants.is_empty() {
+                    let _ = writeln!(text, "        return ({})value;", name);
+                }
+                let _ = write!(
+                    text,
+                    "    }}\n    fig_out_of_range(value, \"{}\");\n    return 0;\n}}\n\nstatic inline const char *fig_name__{}({} value) {{\n    switch (value) {{\n",
+                    e.name, name, name
+                );
+                for (variant, value) in &discriminants {
+                    let _ = writeln!(text, "    case {}:\n        return \"{}::{}\";", value, e.name, variant);
+                }
+                text.push_str("    }\n    return \"?\";\n}\n\n");
+                self.definitions.push_str(&text);
+            }
+            TypeDef::Alias(_) | TypeDef::Interface(_) => {
+                self.diagnostics.push(Diagnostic::error(format!(
+                    "values of interface type `{}` are not supported by the C backend",
+                    format_type(ty)
+                )));
+            }
+        }
+    }
+
+    /// The fields of a struct, or the variants of a union, with the type's
+    /// generic arguments substituted
+    pub(crate) fn fields_of(&mut self, ty: &Type) -> Vec<(String, Type)> {
+        self.tc.fields(ty).unwrap_or_else(|message| {
+            self.diagnostics.push(Diagnostic::error(message));
+            Vec::new()
+        })
+    }
+
+    // ========================================================================
+    // Printing
+    // ========================================================================
+
+    /// Statements printing `value` of type `ty` the way the interpreter's
+    /// `print` shows it. `depth` keeps the temporaries of nested values apart.
+    pub(crate) fn print_value(&mut self, ty: &Type, value: &str, depth: usize) -> Result<Vec<String>, String> {
+        let temp = format!("fig_p{}", depth);
+        let index = format!("fig_i{}", depth);
+        let block = |this: &mut Self, inner: Vec<String>| -> Vec<String> {
+            let mut lines = vec![format!("{{ {} {} = {};", this.c_type(ty), temp, value)];
+            lines.extend(inner.into_iter().map(|line| format!("    {}", line)));
+            lines.push("}".to_string());
+            lines
+        };
+        Ok(match ty {
+            Type::U8 | Type::U16 | Type::U32 | Type::U64 | Type::USize => {
+                vec![format!("printf(\"%\" PRIu64, (uint64_t)({}));", value)]
+            }
+            Type::I8 | Type::I16 | Type::I32 | Type::I64 | Type::ISize => {
+                vec![format!("printf(\"%\" PRId64, (int64_t)({}));", value)]
+            }
+            Type::F32 => vec![format!("fig_print_float({}, true);", value)],
+            Type::F64 => vec![format!("fig_print_float({}, false);", value)],
+            Type::Bool => vec![format!("fputs(({}) ? \"true\" : \"false\", stdout);", value)],
+            Type::Ok => vec!["fputs(\"ok\", stdout);".to_string()],
+            Type::Null => vec!["fputs(\"null\", stdout);".to_string()],
+            Type::Array { element_type, size: None } if **element_type == Type::U8 => {
+                block(self, vec![format!("fwrite({}.ptr, 1, {}.len, stdout);", temp, temp)])
+            }
+            Type::Array { element_type, size: Some(size) } => {
+                let element = self.print_value(element_type, &format!("{}.data[{}]", temp, index), depth + 1)?;
+                let mut inner = vec![
+                    "putchar('[');".to_string(),
+                    format!("for (size_t {i} = 0; {i} < {}; {i}++) {{", fig_parser::format::format_expression(size), i = index),
+                    format!("    if ({}) fputs(\", \", stdout);", index),
+                ];
+                inner.extend(element.into_iter().map(|line| format!("    {}", line)));
+                inner.push("}".to_string());
+                inner.push("putchar(']');".to_string());
+                block(self, inner)
+            }
+            Type::Optional(inner) => {
+                let some = self.print_value(inner, &format!("{}.value", temp), depth + 1)?;
+                let mut lines = vec![format!("if ({}.some) {{", temp)];
+                lines.extend(some.into_iter().map(|line| format!("    {}", line)));
+                lines.push("} else {".to_string());
+                lines.push("    fputs(\"null\", stdout);".to_string());
+                lines.push("}".to_string());
+                block(self, lines)
+            }
+            Type::ErrorUnion { ok_type, err_type } => {
+                let ok = self.print_value(ok_type, &format!("{}.ok", temp), depth + 1)?;
+                let err = self.print_value(&Type::Path(err_type.clone()), &format!("{}.err", temp), depth + 1)?;
+                let mut lines = vec![format!("if ({}.is_err) {{", temp), "    fputs(\"error(\", stdout);".to_string()];
+                lines.extend(err.into_iter().map(|line| format!("    {}", line)));
+                lines.push("    putchar(')');".to_string());
+                lines.push("} else {".to_string());
+                lines.extend(ok.into_iter().map(|line| format!("    {}", line)));
+                lines.push("}".to_string());
+                block(self, lines)
+            }
+            Type::Path(path) => match self.items.lookup_type(path) {
+                Some(TypeDef::Enum(_)) => {
+                    let name = self.c_type(ty);
+                    vec![format!("fputs(fig_name__{}({}), stdout);", name, value)]
+                }
+                Some(TypeDef::Struct(s)) => {
+                    let mut lines = vec![format!("fputs(\"{}(\", stdout);", s.name)];
+                    for (i, (field, field_type)) in self.fields_of(ty).into_iter().enumerate() {
+                        let separator = if i > 0 { ", " } else { "" };
+                        lines.push(format!("fputs(\"{}{}: \", stdout);", separator, field));
+                        let access = format!("{}.{}", temp, mangle::ident(&field));
+                        lines.extend(self.print_value(&field_type, &access, depth + 1)?);
+                    }
+                    lines.push("putchar(')');".to_string());
+                    block(self, lines)
+                }
+                Some(TypeDef::Union(u)) => {
+                    let mut lines = vec![format!("switch ({}.tag) {{", temp)];
+                    for (i, (variant, variant_type)) in self.fields_of(ty).into_iter().enumerate() {
+                        lines.push(format!("case {}:", i));
+                        lines.push(format!("    fputs(\"{}::{}(\", stdout);", u.name, variant));
+                        let access = format!("{}.as.{}", temp, mangle::ident(&variant));
+                        let payload = if variant_type == Type::Ok {
+                            vec!["fputs(\"ok\", stdout);".to_string()]
+                        } else {
+                            self.print_value(&variant_type, &access, depth + 1)?
+                        };
+                        lines.extend(payload.into_iter().map(|line| format!("    {}", line)));
+                        lines.push("    putchar(')');".to_string());
+                        lines.push("    break;".to_string());
+                    }
+                    lines.push("}".to_string());
+                    block(self, lines)
+                }
+                _ => return Err(format!("cannot print a value of type `{}`", format_type(ty))),
+            },
+            _ => return Err(format!("cannot print a value of type `{}`", format_type(ty))),
+        })
+    }
+}
+
+/// Lower `items` to C with the default options
+pub fn emit_c(items: &ItemTable) -> Result<String, Vec<Diagnostic>> {
+    CEmitter::new(items).emit()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    fn emit(src: &str) -> String {
+        let sf = parse(src);
+        let items = ItemTable::from_source_file(&sf);
+        CEmitter::new(&items).with_entry(EntryPoint::None).emit().unwrap_or_else(|diags| panic!("{:?}", diags))
+    }
+
+    #[test]
+    fn test_lowers_declarations() {
+        let c = emit(
+            "\
+packed struct Header
+    tag: u8
+    len: u32
+
+enum[u16] Kind
+    A
+    B = 7
+
+extern func abs(x: i32) -> i32
+extern func fig_hook(h: *Header) -> ok
+
+func[T] largest(a: T, b: T) -> T
+    if a > b
+        return a
+    return b
+
+namespace geo::shapes
+
+public func area(h: Header, k: Kind) -> u32
+    fig_hook(&h)
+    return largest(h.len, largest(3u8, 4u8) as u32) + abs(-1) as u32
+",
+        );
+        assert!(c.contains("struct FIG_PACKED Header {"), "{}", c);
+        assert!(c.contains("typedef uint16_t Kind;"), "{}", c);
+        assert!(c.contains("#define Kind__B ((Kind)7)"), "{}", c);
+        // `abs` comes from <stdlib.h>; other externs get a prototype
+        assert!(!c.contains("extern int32_t abs("), "{}", c);
+        assert!(c.contains("extern void fig_hook(Header * h);"), "{}", c);
+        assert!(c.contains("static uint32_t fig__largest__u32(uint32_t a, uint32_t b)"), "{}", c);
+        assert!(c.contains("static uint8_t fig__largest__u8(uint8_t a, uint8_t b)"), "{}", c);
+        assert!(c.contains("uint32_t fig__geo__shapes__area(Header h, Kind k) {"), "{}", c);
+    }
+}
@@ -0,0 +1,298 @@
+//! C headers describing the ABI of a Fig library
+//!
+//! A header declares what a C or Rust program needs to link against the
+//! output of [`CEmitter`]: every `export` struct, union and enum, every
+//! `export` const as a `#define`, a prototype for every `export` function and
+//! the `extern` functions the library itself expects the linker to provide.
+//! Names are mangled exactly as in the translation unit (see [`mangle`]), so
+//! `export func geo::area` is `geo__area` on both sides. Generic items have no
+//! single C symbol and are left out.
+//!
+//! [`mangle`]: crate::mangle
+
+use std::fmt::Write;
+
+use fig_parser::ast::*;
+use fig_parser::format::format_type;
+use fig_sema::diagnostics::Diagnostic;
+use fig_sema::items::{ItemTable, TypeDef};
+use fig_sema::layout::Target;
+use fig_sema::typeck::{Instance, is_integer, roots};
+
+use crate::body::c_string;
+use crate::emit::CEmitter;
+use crate::mangle;
+
+/// The part of the runtime the declarations refer to, guarded so that the
+/// header can be included next to a generated translation unit
+const PRELUDE: &str = r#"#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifndef FIG_PACKED
+#define FIG_PACKED __attribute__((packed))
+#endif
+
+#ifndef FIG_OK_DEFINED
+#define FIG_OK_DEFINED
+typedef uint8_t fig_ok;
+#endif
+"#;
+
+/// Writes the C header for the exported items of an [`ItemTable`]
+pub struct HeaderEmitter<'a> {
+    emitter: CEmitter<'a>,
+    guard: String,
+}
+
+impl<'a> HeaderEmitter<'a> {
+    pub fn new(items: &'a ItemTable<'a>) -> Self {
+        let mut emitter = CEmitter::new(items);
+        emitter.header = true;
+        HeaderEmitter { emitter, guard: "FIG_GENERATED_H".to_string() }
+    }
+
+    /// The target whose layout the declarations describe; the host by default
+    pub fn with_target(mut self, target: Target) -> Self {
+        self.emitter = self.emitter.with_target(target);
+        self.emitter.header = true;
+        self
+    }
+
+    /// The include guard macro, `FIG_GENERATED_H` by default
+    pub fn with_guard(mut self, guard: impl Into<String>) -> Self {
+        self.guard = guard.into();
+        self
+    }
+
+    pub fn emit(mut self) -> Result<String, Vec<Diagnostic>> {
+        let items = self.emitter.items;
+        for (name, def) in items.types() {
+            let (TypeDef::Struct(Struct { visibility, .. })
+            | TypeDef::Union(Union { visibility, .. })
+            | TypeDef::Enum(Enum { visibility, .. })) = def
+            else {
+                continue;
+            };
+            if *visibility == Visibility::Export && def.generic_params().is_empty() {
+                let path = Path { segments: name.split("::").map(str::to_string).collect(), generic_args: vec![] };
+                self.emitter.c_type(&Type::Path(path));
+            }
+        }
+
+        let mut consts = String::new();
+        for (name, c) in items.consts() {
+            if c.visibility == Visibility::Export && c.generic_params.is_empty() {
+                match self.const_value(name, c) {
+                    Ok(value) => {
+                        let _ = writeln!(consts, "#define {} {}", mangle::qualified(name), value);
+                    }
+                    Err(diagnostic) => self.emitter.diagnostics.push(diagnostic),
+                }
+            }
+        }
+
+        for function in items.functions() {
+            let signature = function.signature;
+            if function.body.is_none() && signature.is_extern && signature.generic_params.is_empty() {
+                self.emitter.declare_extern(&Instance::new(function));
+            }
+        }
+        let mut exports = String::new();
+        for instance in roots(items) {
+            let function = instance.function;
+            if function.signature.visibility != Visibility::Export {
+                continue;
+            }
+            let signature = match self.emitter.tc.signature(&instance) {
+                Ok(signature) => signature,
+                Err(diagnostic) => {
+                    self.emitter.diagnostics.push(diagnostic);
+                    continue;
+                }
+            };
+            let mut params = Vec::new();
+            if let Some(self_type) = &signature.self_type {
+                params.push(format!("{} self", self.emitter.c_type(self_type)));
+            }
+            for (param, ty) in &signature.params {
+                params.push(format!("{} {}", self.emitter.c_type(ty), mangle::ident(param)));
+            }
+            let params = if params.is_empty() { "void".to_string() } else { params.join(", ") };
+            let qualified = function.qualified_name();
+            let name = if qualified == "main" { "fig_main".to_string() } else { mangle::function(&qualified, true) };
+            let ret = self.emitter.return_type(&signature.return_type);
+            let _ = writeln!(exports, "{} {}({});", ret, name, params);
+        }
+        if !self.emitter.diagnostics.is_empty() {
+            return Err(self.emitter.diagnostics);
+        }
+
+        let mut out = String::from("/* Generated by the Fig compiler */\n\n");
+        let _ = writeln!(out, "#ifndef {}\n#define {}\n", self.guard, self.guard);
+        out.push_str(PRELUDE);
+        out.push_str("\n#ifdef __cplusplus\nextern \"C\" {\n#endif\n");
+        for section in [&self.emitter.forward, &self.emitter.definitions, &consts, &exports, &self.emitter.prototypes] {
+            if !section.is_empty() {
+                out.push('\n');
+                out.push_str(section);
+            }
+        }
+        let _ = write!(out, "\n#ifdef __cplusplus\n}}\n#endif\n\n#endif /* {} */\n", self.guard);
+        Ok(out)
+    }
+
+    /// The C expression a `#define` expands to for `c`
+    fn const_value(&mut self, name: &str, c: &ConstStatement) -> Result<String, Diagnostic> {
+        let ty = match &c.ty {
+            Some(ty) => Some(self.emitter.tc.normalize(ty, &Vec::new()).map_err(Diagnostic::error)?),
+            None => None,
+        };
+        let unsupported = || {
+            let ty = ty.as_ref().map_or_else(|| "this value".to_string(), |ty| format!("type `{}`", format_type(ty)));
+            Diagnostic::error(format!("exported const `{}` of {} cannot be declared in a C header", name, ty))
+                .with_note("only integer, enum, bool, float and string constants have a C spelling")
+        };
+        if let Expression::StringLiteral(text) = c.value.as_ref() {
+            return Ok(c_string(text));
+        }
+        if let Some(value) = float_value(&c.value) {
+            return match &ty {
+                Some(Type::F32) => Ok(format!("((float){:?})", value)),
+                Some(Type::F64) | None => Ok(format!("{:?}", value)),
+                Some(_) => Err(unsupported()),
+            };
+        }
+        let value = self.emitter.tc.layout().eval_const(&c.value).map_err(|_| unsupported())?;
+        match &ty {
+            Some(Type::Bool) => Ok((if value != 0 { "true" } else { "false" }).to_string()),
+            None if matches!(c.value.as_ref(), Expression::BooleanLiteral(_)) => {
+                Ok((if value != 0 { "true" } else { "false" }).to_string())
+            }
+            None => Ok(value.to_string()),
+            Some(ty @ Type::Path(path)) if matches!(self.emitter.items.lookup_type(path), Some(TypeDef::Enum(_))) => {
+                Ok(format!("(({}){})", self.emitter.c_type(ty), value))
+            }
+            Some(ty) if is_integer(ty) => Ok(format!("(({}){})", self.emitter.c_type(ty), value)),
+            Some(_) => Err(unsupported()),
+        }
+    }
+}
+
+/// The value of a float literal, possibly negated or parenthesised
+fn float_value(expr: &Expression) -> Option<f64> {
+    match expr {
+        Expression::FloatLiteral(lit) => lit.as_f64().ok(),
+        Expression::Parenthesized(inner) => float_value(inner),
+        Expression::UnaryOp(op) if op.op == UnaryOperator::Negate => float_value(&op.operand).map(|v| -v),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    fn header(src: &str) -> String {
+        let sf = parse(src);
+        let items = ItemTable::from_source_file(&sf);
+        HeaderEmitter::new(&items).with_guard("GEO_H").emit().unwrap_or_else(|diags| panic!("{:?}", diags))
+    }
+
+    #[test]
+    fn test_declares_exported_items() {
+        let h = header(
+            "\
+namespace geo
+
+export packed struct Header
+    tag: u8
+    len: u32
+
+export enum[u8] Kind
+    A
+    B = 7
+
+export union Shape
+    circle: f64
+    empty: ok
+
+struct Hidden
+    x: i32
+
+export const LIMIT: u16 = 4 * 16
+export const NAME = \"geo\"
+export const SCALE: f32 = 0.5
+const PRIVATE: i32 = 1
+
+extern func fig_hook(h: *Header) -> ok
+extern func abs(x: i32) -> i32
+
+export func Header::size(*self) -> u32
+    return self.len
+
+export func area(h: Header, k: Kind) -> u32
+    fig_hook(&h)
+    return h.len
+
+public func helper() -> i32
+    return 1
+
+export func[T] largest(a: T, b: T) -> T
+    return a
+",
+        );
+        assert!(h.contains("#ifndef GEO_H\n#define GEO_H\n"), "{}", h);
+        assert!(h.contains("struct FIG_PACKED geo__Header {"), "{}", h);
+        assert!(h.contains("typedef uint8_t geo__Kind;"), "{}", h);
+        assert!(h.contains("#define geo__Kind__B ((geo__Kind)7)"), "{}", h);
+        assert!(h.contains("struct geo__Shape {\n    uint8_t tag;"), "{}", h);
+        assert!(h.contains("#define geo__LIMIT ((uint16_t)64)"), "{}", h);
+        assert!(h.contains("#define geo__NAME \"geo\""), "{}", h);
+        assert!(h.contains("#define geo__SCALE ((float)0.5)"), "{}", h);
+        assert!(h.contains("uint32_t geo__Header__size(geo__Header * self);"), "{}", h);
+        assert!(h.contains("uint32_t geo__area(geo__Header h, geo__Kind k);"), "{}", h);
+        assert!(h.contains("extern void fig_hook(geo__Header * h);"), "{}", h);
+        // Runtime helpers, private items, libc and generics stay out
+        for absent in ["fig_variants__", "fig_enum__", "Hidden", "PRIVATE", "helper", "largest", " abs("] {
+            assert!(!h.contains(absent), "`{}` in {}", absent, h);
+        }
+    }
+
+    #[test]
+    fn test_rejects_consts_without_a_c_spelling() {
+        let sf = parse("struct P\n    x: i32\n\nexport const ORIGIN: [i32; 2] = [0, 0]\n");
+        let items = ItemTable::from_source_file(&sf);
+        let diagnostics = HeaderEmitter::new(&items).emit().unwrap_err();
+        assert!(diagnostics[0].message.contains("`ORIGIN`"), "{:?}", diagnostics);
+    }
+
+    #[test]
+    fn test_header_compiles() {
+        let h = header("export struct Point\n    x: i32\n    y: i32\n\nexport func norm(p: *Point) -> i64\n    return 0\n");
+        let dir = std::env::temp_dir().join(format!("fig-header-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("point.h");
+        std::fs::write(&path, &h).unwrap();
+        let Ok(status) = std::process::Command::new("cc").args(["-fsyntax-only", "-x", "c"]).arg(&path).status() else {
+            return;
+        };
+        assert!(status.success(), "{}", h);
+    }
+
+    #[test]
+    fn test_header_keeps_fig_ok_under_a_custom_packed_macro() {
+        let h = header("export struct Flag\n    set: ok\n");
+        assert!(h.contains("#ifndef FIG_OK_DEFINED\n#define FIG_OK_DEFINED\ntypedef uint8_t fig_ok;\n#endif"), "{}", h);
+        let dir = std::env::temp_dir().join(format!("fig-header-packed-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("flag.h"), &h).unwrap();
+        let consumer = dir.join("consumer.c");
+        std::fs::write(&consumer, "#define FIG_PACKED\n#include \"flag.h\"\nstruct Flag f;\n").unwrap();
+        let Ok(status) = std::process::Command::new("cc").args(["-fsyntax-only"]).arg(&consumer).status() else {
+            return;
+        };
+        assert!(status.success(), "{}", h);
+    }
+}
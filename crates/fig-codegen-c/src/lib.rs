@@ -0,0 +1,29 @@
+//! C source backend for Fig
+//!
+//! [`CEmitter`] type-checks every function reachable from the program's
+//! roots (see [`fig_sema::typeck::roots`]), monomorphising generic functions
+//! as it finds their instances, and writes one self-contained C11 translation
+//! unit. Arithmetic, indexing, dereferences and union reads are checked at
+//! run time and trap with the same messages as `fig-interp`.
+//!
+//! ```ignore
+//! let sf = SourceFileParser::new().parse(Lexer::new(src))?;
+//! let items = ItemTable::from_source_file(&sf);
+//! let c = CEmitter::new(&items).with_entry(EntryPoint::ExitCode).emit()?;
+//! ```
+
+mod body;
+mod emit;
+mod header;
+pub mod mangle;
+pub mod runtime;
+
+pub use emit::{CEmitter, EntryPoint, emit_c};
+pub use header::HeaderEmitter;
+
+#[cfg(test)]
+pub(crate) fn parse(src: &str) -> fig_parser::ast::SourceFile {
+    fig_parser::SourceFileParser::new()
+        .parse(fig_parser::Lexer::new(src))
+        .unwrap()
+}
@@ -0,0 +1,152 @@
+//! C identifiers for Fig names
+//!
+//! Namespace and receiver segments are joined with `__`, so `net::Socket::open`
+//! becomes `net__Socket__open`. An instance of a generic function or type
+//! appends `__` and the mangled form of each argument: `Vec[*u8]` becomes
+//! `Vec__ptr_u8`. A pointer's mutability and nullability are part of its
+//! form, so `*mut u8` is `mutptr_u8` and `?*mut u8` is `optmutptr_u8`.
+//!
+//! A function the program defines is a symbol in the same namespace as
+//! everything the C library declares, so unless it is `export` it is named
+//! with [`FUNCTION_PREFIX`] rather than checked against a list: `func div`
+//! becomes `fig__div`. Other names that would collide with C keywords, the
+//! headers the output includes or the runtime's `fig_` prefix get a
+//! trailing `_`.
+
+use fig_parser::ast::{Expression, Path, Type};
+
+const RESERVED: &[&str] = &[
+    // C11 keywords
+    "auto", "break", "case", "char", "const", "continue", "default", "do", "double", "else", "enum", "extern",
+    "float", "for", "goto", "if", "inline", "int", "long", "register", "restrict", "return", "short", "signed",
+    "sizeof", "static", "struct", "switch", "typedef", "union", "unsigned", "void", "volatile", "while", "_Bool",
+    "_Complex", "_Imaginary", "_Alignas", "_Alignof", "_Atomic", "_Generic", "_Noreturn", "_Static_assert",
+    "_Thread_local",
+    // Macros and typedefs from the included headers
+    "bool", "true", "false", "NULL", "EOF", "errno", "stdin", "stdout", "stderr", "offsetof", "size_t",
+    "ptrdiff_t", "int8_t", "int16_t", "int32_t", "int64_t", "uint8_t", "uint16_t", "uint32_t", "uint64_t",
+    "intptr_t", "uintptr_t", "FILE", "main",
+];
+
+/// Functions declared by the headers the output includes. An `extern`
+/// declaration of one of these gets no prototype of its own.
+pub const LIBC_FUNCTIONS: &[&str] = &[
+    "abort", "abs", "atoi", "calloc", "exit", "fclose", "fflush", "fgets", "fopen", "fprintf", "fputs", "fread",
+    "free", "fwrite", "getchar", "getenv", "labs", "malloc", "memchr", "memcmp", "memcpy", "memmove", "memset",
+    "perror", "printf", "putchar", "puts", "qsort", "rand", "realloc", "snprintf", "sprintf", "srand", "strcat",
+    "strchr", "strcmp", "strcpy", "strdup", "strlen", "strncmp", "strncpy", "strstr", "strtod", "strtol", "system",
+];
+
+/// The prefix of every function that is neither `extern` nor `export`. The
+/// runtime's own names never have a `_` after `fig_`.
+pub const FUNCTION_PREFIX: &str = "fig__";
+
+/// A Fig identifier as a C identifier
+pub fn ident(name: &str) -> String {
+    if RESERVED.contains(&name) || LIBC_FUNCTIONS.contains(&name) || name.starts_with("fig_") || name.starts_with('_')
+    {
+        format!("{}_", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// A qualified name such as `core::Vec::push`
+pub fn qualified(name: &str) -> String {
+    let segments: Vec<&str> = name.split("::").collect();
+    match segments.as_slice() {
+        [single] => ident(single),
+        _ => segments.join("__"),
+    }
+}
+
+/// The C symbol of a function defined in Fig. An `export` function keeps its
+/// qualified name so that C code can call it.
+pub fn function(name: &str, is_export: bool) -> String {
+    if is_export { qualified(name) } else { format!("{}{}", FUNCTION_PREFIX, name.replace("::", "__")) }
+}
+
+/// The C name of a named type instance, e.g. `Pair__i32` for `Pair[i32]`
+pub fn path(path: &Path) -> String {
+    let mut name = qualified(&path.segments.join("::"));
+    for arg in &path.generic_args {
+        name.push_str("__");
+        name.push_str(&suffix(arg));
+    }
+    name
+}
+
+/// A type as a fragment of an identifier
+pub fn suffix(ty: &Type) -> String {
+    match ty {
+        Type::U8 => "u8".into(),
+        Type::U16 => "u16".into(),
+        Type::U32 => "u32".into(),
+        Type::U64 => "u64".into(),
+        Type::USize => "usize".into(),
+        Type::I8 => "i8".into(),
+        Type::I16 => "i16".into(),
+        Type::I32 => "i32".into(),
+        Type::I64 => "i64".into(),
+        Type::ISize => "isize".into(),
+        Type::F32 => "f32".into(),
+        Type::F64 => "f64".into(),
+        Type::Bool => "bool".into(),
+        Type::Ok => "ok".into(),
+        Type::Null => "null".into(),
+        Type::SelfType => "Self".into(),
+        Type::Pointer { nullable, mutable, element_type } => {
+            let (opt, mutable) = (if *nullable { "opt" } else { "" }, if *mutable { "mut" } else { "" });
+            format!("{}{}ptr_{}", opt, mutable, suffix(element_type))
+        }
+        Type::Optional(inner) => format!("opt_{}", suffix(inner)),
+        Type::Array { element_type, size: Some(size) } => format!("arr{}_{}", constant(size), suffix(element_type)),
+        Type::Array { element_type, size: None } => format!("slice_{}", suffix(element_type)),
+        Type::ErrorUnion { ok_type, err_type } => format!("res_{}__{}", suffix(ok_type), self::path(err_type)),
+        Type::Path(p) => self::path(p),
+        Type::Const(value) => constant(value),
+        Type::Function(function) => {
+            let mut name = format!("{}{}", if function.is_effect { "efn" } else { "fn" }, function.params.len());
+            for param in &function.params {
+                name.push('_');
+                name.push_str(&suffix(param));
+            }
+            let ok = Type::Ok;
+            format!("{}__{}", name, suffix(function.return_type.as_deref().unwrap_or(&ok)))
+        }
+    }
+}
+
+/// An evaluated array size or const argument
+fn constant(value: &Expression) -> String {
+    match value {
+        Expression::IntegerLiteral(lit) => lit.digits().to_string(),
+        Expression::UnaryOp(op) => format!("m{}", constant(&op.operand)),
+        _ => "x".into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mangles_names_and_types() {
+        assert_eq!(qualified("net::Socket::open"), "net__Socket__open");
+        assert_eq!(qualified("free"), "free_");
+        assert_eq!(qualified("fig_value"), "fig_value_");
+        assert_eq!(function("div", false), "fig__div");
+        assert_eq!(function("net::Socket::open", false), "fig__net__Socket__open");
+        assert_eq!(function("geo::area", true), "geo__area");
+        let vec = Path::with_generics(
+            vec!["Vec".into()],
+            vec![Type::Pointer { nullable: false, mutable: false, element_type: Box::new(Type::U8) }],
+        );
+        assert_eq!(path(&vec), "Vec__ptr_u8");
+        assert_eq!(suffix(&Type::Optional(Box::new(Type::Path(vec)))), "opt_Vec__ptr_u8");
+        let pointer = |nullable, mutable| Type::Pointer { nullable, mutable, element_type: Box::new(Type::I32) };
+        assert_eq!(suffix(&pointer(false, true)), "mutptr_i32");
+        assert_eq!(suffix(&pointer(true, false)), "optptr_i32");
+        assert_eq!(suffix(&pointer(true, true)), "optmutptr_i32");
+    }
+}
@@ -0,0 +1,184 @@
+//! The C runtime emitted at the top of every translation unit
+//!
+//! Checked arithmetic goes through small inline functions so that a trap
+//! reports the same message as the interpreter, e.g. "`u8` addition
+//! overflowed". Overflow detection uses the `__builtin_*_overflow` family,
+//! which GCC and Clang both provide.
+
+/// Headers, helpers and the checked-arithmetic functions for every integer type
+pub const RUNTIME: &str = r#"#include <inttypes.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#if !defined(__GNUC__)
+#error "the Fig C runtime needs the GCC or Clang overflow builtins"
+#endif
+
+#define FIG_PACKED __attribute__((packed))
+
+#define FIG_OK_DEFINED
+typedef uint8_t fig_ok;
+
+__attribute__((noreturn)) static inline void fig_trap(const char *message) {
+    fflush(stdout);
+    fprintf(stderr, "error: %s\n", message);
+    exit(101);
+}
+
+static inline void *fig_nonnull(void *pointer) {
+    if (!pointer) fig_trap("null pointer dereference");
+    return pointer;
+}
+
+static inline size_t fig_index_i(int64_t index, size_t len) {
+    if (index < 0 || (uint64_t)index >= len) {
+        char message[96];
+        snprintf(message, sizeof message, "index %" PRId64 " is out of bounds for length %zu", index, len);
+        fig_trap(message);
+    }
+    return (size_t)index;
+}
+
+static inline size_t fig_index_u(uint64_t index, size_t len) {
+    if (index >= len) {
+        char message[96];
+        snprintf(message, sizeof message, "index %" PRIu64 " is out of bounds for length %zu", index, len);
+        fig_trap(message);
+    }
+    return (size_t)index;
+}
+
+static inline void fig_check_slice(uint64_t start, uint64_t end, size_t len) {
+    if (start > end || end > len) {
+        char message[128];
+        snprintf(message, sizeof message, "slice %" PRIu64 "..%" PRIu64 " is out of range for length %zu",
+                 start, end, len);
+        fig_trap(message);
+    }
+}
+
+static inline void fig_check_shift(int64_t amount, int64_t bits, const char *type) {
+    if (amount < 0 || amount >= bits) {
+        char message[96];
+        snprintf(message, sizeof message, "shift by %" PRId64 " is out of range for `%s`", amount, type);
+        fig_trap(message);
+    }
+}
+
+static inline void fig_out_of_range(int64_t value, const char *type) {
+    char message[96];
+    snprintf(message, sizeof message, "%" PRId64 " does not fit in `%s`", value, type);
+    fig_trap(message);
+}
+
+static inline void fig_inactive(const char *variant, const char *type, const char *active) {
+    char message[256];
+    snprintf(message, sizeof message, "read of variant `%s` of `%s`, but `%s` is active", variant, type, active);
+    fig_trap(message);
+}
+
+/* Prints the shortest decimal that reads back as `value`, without an exponent */
+static inline void fig_print_float(double value, bool single) {
+    if (value != value) {
+        fputs("NaN", stdout);
+        return;
+    }
+    if (value - value != 0) {
+        fputs(value < 0 ? "-inf" : "inf", stdout);
+        return;
+    }
+    char text[64];
+    int precision = 1;
+    for (; precision < 17; precision++) {
+        snprintf(text, sizeof text, "%.*e", precision - 1, value);
+        double back = strtod(text, NULL);
+        if (single ? (float)back == (float)value : back == value) break;
+    }
+    snprintf(text, sizeof text, "%.*e", precision - 1, value);
+    const char *c = text;
+    if (*c == '-') {
+        putchar('-');
+        c++;
+    }
+    char digits[32];
+    int count = 0;
+    for (; *c && *c != 'e'; c++) {
+        if (*c != '.') digits[count++] = *c;
+    }
+    int point = atoi(c + 1) + 1;
+    while (count > 1 && digits[count - 1] == '0') count--;
+    if (point <= 0) {
+        fputs("0.", stdout);
+        for (int i = 0; i < -point; i++) putchar('0');
+        fwrite(digits, 1, count, stdout);
+    } else if (point >= count) {
+        fwrite(digits, 1, count, stdout);
+        for (int i = count; i < point; i++) putchar('0');
+    } else {
+        fwrite(digits, 1, point, stdout);
+        putchar('.');
+        fwrite(digits + point, 1, count - point, stdout);
+    }
+}
+
+#define FIG_INTEGER(N, T, MIN, MAX)                                                                  \
+    static inline T fig_add_##N(T a, T b) {                                                          \
+        T r;                                                                                         \
+        if (__builtin_add_overflow(a, b, &r)) fig_trap("`" #N "` addition overflowed");             \
+        return r;                                                                                    \
+    }                                                                                                \
+    static inline T fig_sub_##N(T a, T b) {                                                          \
+        T r;                                                                                         \
+        if (__builtin_sub_overflow(a, b, &r)) fig_trap("`" #N "` subtraction overflowed");          \
+        return r;                                                                                    \
+    }                                                                                                \
+    static inline T fig_mul_##N(T a, T b) {                                                          \
+        T r;                                                                                         \
+        if (__builtin_mul_overflow(a, b, &r)) fig_trap("`" #N "` multiplication overflowed");       \
+        return r;                                                                                    \
+    }                                                                                                \
+    static inline T fig_div_##N(T a, T b) {                                                          \
+        if (b == 0) fig_trap("division by zero");                                                    \
+        if (MIN != 0 && a == MIN && b == (T)-1) fig_trap("`" #N "` division overflowed");           \
+        return a / b;                                                                                \
+    }                                                                                                \
+    static inline T fig_rem_##N(T a, T b) {                                                          \
+        if (b == 0) fig_trap("division by zero");                                                    \
+        if (MIN != 0 && b == (T)-1) return 0;                                                        \
+        return a % b;                                                                                \
+    }                                                                                                \
+    static inline T fig_neg_##N(T a) {                                                               \
+        if (MIN != 0 && a == MIN) fig_trap("`" #N "` negation overflowed");                         \
+        return (T)-a;                                                                                \
+    }                                                                                                \
+    static inline T fig_shl_##N(T a, int64_t b) {                                                    \
+        fig_check_shift(b, sizeof(T) * 8, #N);                                                       \
+        return (T)((uint64_t)a << b);                                                                \
+    }                                                                                                \
+    static inline T fig_shr_##N(T a, int64_t b) {                                                    \
+        fig_check_shift(b, sizeof(T) * 8, #N);                                                       \
+        return (T)(a >> b);                                                                          \
+    }                                                                                                \
+    /* Float to integer casts saturate, and NaN becomes zero */                                      \
+    static inline T fig_ftoi_##N(double v) {                                                         \
+        if (v != v) return 0;                                                                        \
+        if (v <= (double)MIN) return MIN;                                                            \
+        if (v >= (double)MAX) return MAX;                                                            \
+        return (T)v;                                                                                 \
+    }
+
+FIG_INTEGER(u8, uint8_t, 0, UINT8_MAX)
+FIG_INTEGER(u16, uint16_t, 0, UINT16_MAX)
+FIG_INTEGER(u32, uint32_t, 0, UINT32_MAX)
+FIG_INTEGER(u64, uint64_t, 0, UINT64_MAX)
+FIG_INTEGER(usize, size_t, 0, SIZE_MAX)
+FIG_INTEGER(i8, int8_t, INT8_MIN, INT8_MAX)
+FIG_INTEGER(i16, int16_t, INT16_MIN, INT16_MAX)
+FIG_INTEGER(i32, int32_t, INT32_MIN, INT32_MAX)
+FIG_INTEGER(i64, int64_t, INT64_MIN, INT64_MAX)
+FIG_INTEGER(isize, ptrdiff_t, PTRDIFF_MIN, PTRDIFF_MAX)
+"#;
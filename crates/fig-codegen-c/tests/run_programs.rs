@@ -0,0 +1,73 @@
+// Compiles every program in tests/run/ to C, builds it with the system C
+// compiler and checks the executable against the same header comments the
+// interpreter's test uses:
+//
+//   // expect: <value>     the value `main` returns, printed after the output
+//   // output: <line>      one line of `print`/`println` output, in order
+//   // trap: <message>     the runtime error the program stops with
+//
+// The test is skipped when no `cc` is on the PATH.
+
+use std::path::Path;
+use std::process::Command;
+
+use fig_codegen_c::{CEmitter, EntryPoint};
+use fig_parser::{Lexer, SourceFileParser};
+use fig_sema::construct::resolve_construction;
+use fig_sema::items::ItemTable;
+use fig_sema::layout::Target;
+use fig_test_support::{check_programs, header};
+
+fn run(path: &Path, out_dir: &Path) -> Result<(), String> {
+    let src = std::fs::read_to_string(path).unwrap();
+    let sf = SourceFileParser::new()
+        .parse(Lexer::new(&src))
+        .map_err(|e| format!("parse error: {:?}", e))?;
+    let sf = resolve_construction(sf);
+    let items = ItemTable::from_source_file(&sf);
+    let c = CEmitter::new(&items)
+        .with_target(Target::X86_64)
+        .with_entry(EntryPoint::PrintResult)
+        .emit()
+        .map_err(|diagnostics| diagnostics.iter().map(|d| d.to_string()).collect::<Vec<_>>().join("\n"))?;
+
+    let stem = path.file_stem().unwrap().to_string_lossy();
+    let c_path = out_dir.join(format!("{}.c", stem));
+    let exe_path = out_dir.join(stem.as_ref());
+    std::fs::write(&c_path, c).unwrap();
+    let cc = Command::new("cc")
+        .args(["-std=c11", "-w", "-o"])
+        .arg(&exe_path)
+        .arg(&c_path)
+        .output()
+        .unwrap();
+    if !cc.status.success() {
+        return Err(format!("cc failed:\n{}", String::from_utf8_lossy(&cc.stderr)));
+    }
+
+    let run = Command::new(&exe_path).output().unwrap();
+    let stdout = String::from_utf8_lossy(&run.stdout);
+    let stderr = String::from_utf8_lossy(&run.stderr);
+    let mut expected_output = header(&src, "output");
+    match (header(&src, "expect").first(), header(&src, "trap").first()) {
+        (Some(expected), None) if run.status.success() => expected_output.push(expected),
+        (None, Some(expected)) if stderr.trim_end() == format!("error: {}", expected) => {}
+        _ => return Err(format!("exited with {}, stderr {:?}", run.status, stderr)),
+    }
+    let output: Vec<&str> = stdout.lines().collect();
+    if output != expected_output {
+        return Err(format!("printed {:?}, expected {:?}", output, expected_output));
+    }
+    Ok(())
+}
+
+#[test]
+fn run_programs() {
+    if Command::new("cc").arg("--version").output().is_err() {
+        eprintln!("skipping: no C compiler");
+        return;
+    }
+    let out_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("run_programs");
+    std::fs::create_dir_all(&out_dir).unwrap();
+    check_programs(|path| run(path, &out_dir));
+}
@@ -0,0 +1,214 @@
+//! The System V x86-64 calling convention
+//!
+//! Every function, Fig's own as well as `extern` ones, is called the way a C
+//! compiler calls a function with the same C signature, so exported functions
+//! can be called from C and `extern` functions can be C functions. A scalar
+//! is passed as itself, with `bool` and narrow integers extended as C
+//! requires. An aggregate of at most 16 bytes whose fields are all aligned is
+//! split into eightbytes, each passed in a general-purpose register if any
+//! of its bytes hold integer data and in an SSE register otherwise. Other
+//! aggregates are copied to the stack as arguments and written through a
+//! hidden pointer as results.
+
+use cranelift_codegen::ir::{self, AbiParam, ArgumentExtension, ArgumentPurpose, types};
+use cranelift_codegen::isa::CallConv;
+
+/// Registers System V passes arguments in
+const INTEGER_REGISTERS: usize = 6;
+const SSE_REGISTERS: usize = 8;
+
+/// The class of one eightbyte of an aggregate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Class {
+    Integer,
+    Sse,
+}
+
+impl Class {
+    /// The type an eightbyte of this class is moved as
+    pub fn ty(self) -> ir::Type {
+        match self {
+            Class::Integer => types::I64,
+            Class::Sse => types::F64,
+        }
+    }
+}
+
+/// A scalar part of an aggregate, at some offset in it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Leaf {
+    pub offset: u64,
+    pub size: u64,
+    pub float: bool,
+}
+
+/// How a parameter or result of a Fig type is passed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Arg {
+    /// A scalar of this type, and whether narrow integers are sign-extended
+    Scalar(ir::Type, bool),
+    /// An aggregate of `size` bytes made of these leaves
+    Aggregate { size: u64, leaves: Vec<Leaf> },
+}
+
+/// Where a value is passed, once registers have been assigned
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PassMode {
+    Scalar(ir::Type),
+    /// An aggregate split into eightbytes, one register each; empty for a
+    /// zero-sized aggregate, which is not passed at all
+    Direct(Vec<Class>),
+    /// An argument copied to the stack, of this size rounded up to eight
+    /// bytes, or a result written through the hidden pointer
+    Memory(u32),
+}
+
+/// A function's signature, with how each Fig parameter is passed
+#[derive(Debug, Clone)]
+pub struct Signature {
+    pub params: Vec<PassMode>,
+    pub result: PassMode,
+    pub clif: ir::Signature,
+}
+
+impl Signature {
+    /// Whether the first Cranelift parameter is the hidden result pointer
+    pub fn sret(&self) -> bool {
+        matches!(self.result, PassMode::Memory(_))
+    }
+}
+
+/// The eightbyte classes of an aggregate, or `None` if it is passed in memory
+pub fn classify(size: u64, leaves: &[Leaf]) -> Option<Vec<Class>> {
+    if size > 16 || leaves.iter().any(|leaf| leaf.size > 0 && leaf.offset % leaf.size != 0) {
+        return None;
+    }
+    let mut classes = vec![None; size.div_ceil(8) as usize];
+    for leaf in leaves.iter().filter(|leaf| leaf.size > 0) {
+        let class = if leaf.float { Class::Sse } else { Class::Integer };
+        let slot = &mut classes[(leaf.offset / 8) as usize];
+        *slot = match (*slot, class) {
+            (Some(Class::Integer), _) | (_, Class::Integer) => Some(Class::Integer),
+            _ => Some(Class::Sse),
+        };
+    }
+    // Padding-only eightbytes carry no data, but still take a register
+    Some(classes.into_iter().map(|class| class.unwrap_or(Class::Sse)).collect())
+}
+
+fn round_up(size: u64) -> u32 {
+    (size.div_ceil(8) * 8) as u32
+}
+
+/// Assign registers to the parameters and result of a function
+pub fn signature(params: &[Arg], result: &Arg) -> Signature {
+    let mut clif = ir::Signature::new(CallConv::SystemV);
+    let result = match result {
+        Arg::Scalar(ty, signed) => {
+            clif.returns.push(scalar_param(*ty, *signed));
+            PassMode::Scalar(*ty)
+        }
+        Arg::Aggregate { size, leaves } => match classify(*size, leaves) {
+            Some(classes) => {
+                clif.returns.extend(classes.iter().map(|class| AbiParam::new(class.ty())));
+                PassMode::Direct(classes)
+            }
+            None => {
+                clif.params.push(AbiParam::special(types::I64, ArgumentPurpose::StructReturn));
+                PassMode::Memory(round_up(*size))
+            }
+        },
+    };
+    let mut integer = INTEGER_REGISTERS - clif.params.len();
+    let mut sse = SSE_REGISTERS;
+    let mut modes = Vec::with_capacity(params.len());
+    for param in params {
+        let mode = match param {
+            Arg::Scalar(ty, signed) => {
+                let left = if ty.is_float() { &mut sse } else { &mut integer };
+                *left = left.saturating_sub(1);
+                clif.params.push(scalar_param(*ty, *signed));
+                PassMode::Scalar(*ty)
+            }
+            Arg::Aggregate { size, leaves } => {
+                let classes = classify(*size, leaves).filter(|classes| {
+                    let wanted = classes.iter().filter(|&&class| class == Class::Integer).count();
+                    wanted <= integer && classes.len() - wanted <= sse
+                });
+                match classes {
+                    Some(classes) => {
+                        for class in &classes {
+                            match class {
+                                Class::Integer => integer -= 1,
+                                Class::Sse => sse -= 1,
+                            }
+                            clif.params.push(AbiParam::new(class.ty()));
+                        }
+                        PassMode::Direct(classes)
+                    }
+                    None => {
+                        let size = round_up(*size);
+                        clif.params.push(AbiParam::special(types::I64, ArgumentPurpose::StructArgument(size)));
+                        PassMode::Memory(size)
+                    }
+                }
+            }
+        };
+        modes.push(mode);
+    }
+    Signature { params: modes, result, clif }
+}
+
+fn scalar_param(ty: ir::Type, signed: bool) -> AbiParam {
+    let param = AbiParam::new(ty);
+    match ty {
+        types::I8 | types::I16 if signed => param.sext(),
+        types::I8 | types::I16 => AbiParam { extension: ArgumentExtension::Uext, ..param },
+        _ => param,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaf(offset: u64, size: u64, float: bool) -> Leaf {
+        Leaf { offset, size, float }
+    }
+
+    #[test]
+    fn test_small_aggregates_are_split_into_eightbytes() {
+        // struct { i32 a; float b; double c; }
+        let leaves = [leaf(0, 4, false), leaf(4, 4, true), leaf(8, 8, true)];
+        assert_eq!(classify(16, &leaves), Some(vec![Class::Integer, Class::Sse]));
+        // struct { float x, y; }
+        assert_eq!(classify(8, &[leaf(0, 4, true), leaf(4, 4, true)]), Some(vec![Class::Sse]));
+        // Larger than two eightbytes
+        assert_eq!(classify(24, &[leaf(0, 8, false), leaf(8, 8, false), leaf(16, 8, false)]), None);
+        // A packed struct with a misaligned `u32`
+        assert_eq!(classify(5, &[leaf(0, 1, false), leaf(1, 4, false)]), None);
+    }
+
+    #[test]
+    fn test_aggregates_that_do_not_fit_in_registers_go_on_the_stack() {
+        let pair = Arg::Aggregate { size: 16, leaves: vec![leaf(0, 8, false), leaf(8, 8, false)] };
+        let int = Arg::Scalar(types::I64, false);
+        let params = [int.clone(), int.clone(), int.clone(), int.clone(), int.clone(), pair, int];
+        let sig = signature(&params, &Arg::Scalar(types::I32, true));
+        // One register is left for a pair that needs two, so the pair goes on
+        // the stack and the last integer still gets the register
+        assert_eq!(sig.params[5], PassMode::Memory(16));
+        assert_eq!(sig.clif.params[5].purpose, ArgumentPurpose::StructArgument(16));
+        assert_eq!(sig.params[6], PassMode::Scalar(types::I64));
+    }
+
+    #[test]
+    fn test_large_results_use_a_hidden_pointer() {
+        let big = Arg::Aggregate { size: 24, leaves: vec![leaf(0, 8, false), leaf(8, 8, false), leaf(16, 8, true)] };
+        let sig = signature(&[Arg::Scalar(types::I8, false)], &big);
+        assert!(sig.sret());
+        assert_eq!(sig.clif.params[0].purpose, ArgumentPurpose::StructReturn);
+        assert_eq!(sig.clif.params[1].extension, ArgumentExtension::Uext);
+        assert!(sig.clif.returns.is_empty());
+    }
+}
@@ -0,0 +1,188 @@
+//! DWARF line information
+//!
+//! Instructions carry the byte offset of the statement they were generated
+//! for as their source location. Once code generation is done, each
+//! function's offsets become rows of a `.debug_line` sequence, and a
+//! compilation unit with one subprogram per function lets debuggers and
+//! `addr2line` name the function and source line of any address. Function
+//! addresses are relocations against their symbols, so the object can be
+//! linked anywhere.
+
+use cranelift_module::FuncId;
+use cranelift_object::ObjectProduct;
+use gimli::write::{
+    Address, AttributeValue, DwarfUnit, EndianVec, LineProgram, LineString, Range, RangeList, Relocation,
+    RelocationTarget, RelocateWriter, Sections,
+};
+use gimli::{Encoding, Format, LineEncoding, RunTimeEndian, SectionId};
+use object::write::{self as object_write, SymbolId};
+use object::{RelocationEncoding, RelocationFlags, RelocationKind, SectionKind};
+
+/// Where the code of one function came from
+pub(crate) struct FunctionLines {
+    pub id: FuncId,
+    pub name: String,
+    /// Size of the function's code in bytes
+    pub size: u32,
+    /// Code offsets and the source byte offsets generated from there on
+    pub rows: Vec<(u32, usize)>,
+}
+
+/// A section's bytes and the relocations they need
+#[derive(Clone)]
+struct Writer {
+    data: EndianVec<RunTimeEndian>,
+    relocations: Vec<Relocation>,
+}
+
+impl RelocateWriter for Writer {
+    type Writer = EndianVec<RunTimeEndian>;
+
+    fn writer(&self) -> &Self::Writer {
+        &self.data
+    }
+
+    fn writer_mut(&mut self) -> &mut Self::Writer {
+        &mut self.data
+    }
+
+    fn relocate(&mut self, relocation: Relocation) {
+        self.relocations.push(relocation);
+    }
+}
+
+/// Line and column, both from 1, of each byte offset in a source file
+struct LineIndex {
+    starts: Vec<usize>,
+}
+
+impl LineIndex {
+    fn new(src: &str) -> Self {
+        let starts = std::iter::once(0).chain(src.match_indices('\n').map(|(i, _)| i + 1)).collect();
+        LineIndex { starts }
+    }
+
+    fn position(&self, offset: usize) -> (u64, u64) {
+        let line = self.starts.partition_point(|&start| start <= offset) - 1;
+        (line as u64 + 1, (offset - self.starts[line]) as u64 + 1)
+    }
+}
+
+/// Add `.debug_*` sections describing `functions`, compiled from `src` at
+/// `path`, to an object
+pub(crate) fn write(product: &mut ObjectProduct, functions: &[FunctionLines], path: &str, src: &str) -> Result<(), String> {
+    let encoding = Encoding { format: Format::Dwarf32, version: 4, address_size: 8 };
+    let file = std::path::Path::new(path);
+    let file_name = file.file_name().map_or_else(|| path.to_string(), |name| name.to_string_lossy().into_owned());
+    let comp_dir = match file.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_string_lossy().into_owned(),
+        _ => ".".to_string(),
+    };
+
+    let mut dwarf = DwarfUnit::new(encoding);
+    let mut program = LineProgram::new(
+        encoding,
+        LineEncoding::default(),
+        LineString::String(comp_dir.clone().into_bytes()),
+        LineString::String(file_name.clone().into_bytes()),
+        None,
+    );
+    let directory = program.default_directory();
+    let file_id = program.add_file(LineString::String(file_name.clone().into_bytes()), directory, None);
+    let lines = LineIndex::new(src);
+    for (symbol, function) in functions.iter().enumerate() {
+        program.begin_sequence(Some(Address::Symbol { symbol, addend: 0 }));
+        for &(code_offset, byte_offset) in &function.rows {
+            let (line, column) = lines.position(byte_offset.min(src.len()));
+            let row = program.row();
+            row.address_offset = code_offset as u64;
+            row.file = file_id;
+            row.line = line;
+            row.column = column;
+            program.generate_row();
+        }
+        program.end_sequence(function.size as u64);
+    }
+    dwarf.unit.line_program = program;
+
+    let ranges = functions
+        .iter()
+        .enumerate()
+        .map(|(symbol, function)| Range::StartLength {
+            begin: Address::Symbol { symbol, addend: 0 },
+            length: function.size as u64,
+        })
+        .collect();
+    let ranges = dwarf.unit.ranges.add(RangeList(ranges));
+    let root = dwarf.unit.root();
+    let unit = dwarf.unit.get_mut(root);
+    unit.set(gimli::DW_AT_producer, AttributeValue::String(b"fig".to_vec()));
+    unit.set(gimli::DW_AT_name, AttributeValue::String(file_name.into_bytes()));
+    unit.set(gimli::DW_AT_comp_dir, AttributeValue::String(comp_dir.into_bytes()));
+    unit.set(gimli::DW_AT_low_pc, AttributeValue::Address(Address::Constant(0)));
+    unit.set(gimli::DW_AT_ranges, AttributeValue::RangeListRef(ranges));
+    for (symbol, function) in functions.iter().enumerate() {
+        let id = dwarf.unit.add(root, gimli::DW_TAG_subprogram);
+        let subprogram = dwarf.unit.get_mut(id);
+        subprogram.set(gimli::DW_AT_name, AttributeValue::String(function.name.clone().into_bytes()));
+        subprogram.set(gimli::DW_AT_low_pc, AttributeValue::Address(Address::Symbol { symbol, addend: 0 }));
+        subprogram.set(gimli::DW_AT_high_pc, AttributeValue::Udata(function.size as u64));
+    }
+
+    let mut sections =
+        Sections::new(Writer { data: EndianVec::new(RunTimeEndian::Little), relocations: Vec::new() });
+    dwarf.write(&mut sections).map_err(|e| format!("cannot write debug info: {}", e))?;
+
+    // Sections first, since relocations may refer to any of them
+    let mut ids: Vec<(SectionId, object_write::SectionId)> = Vec::new();
+    sections
+        .for_each(|id, section| -> Result<(), String> {
+            if !section.data.slice().is_empty() {
+                let segment = product.object.segment_name(object::write::StandardSegment::Debug).to_vec();
+                let section_id = product.object.add_section(segment, id.name().as_bytes().to_vec(), SectionKind::Debug);
+                product.object.set_section_data(section_id, section.data.slice().to_vec(), 1);
+                ids.push((id, section_id));
+            }
+            Ok(())
+        })?;
+    sections.for_each(|id, section| -> Result<(), String> {
+        let Some(&(_, section_id)) = ids.iter().find(|(name, _)| *name == id) else { return Ok(()) };
+        for relocation in &section.relocations {
+            let symbol: SymbolId = match relocation.target {
+                RelocationTarget::Symbol(index) => product.function_symbol(functions[index].id),
+                RelocationTarget::Section(target) => {
+                    let Some(&(_, target)) = ids.iter().find(|(name, _)| *name == target) else {
+                        return Err(format!("relocation against missing section {}", target.name()));
+                    };
+                    product.object.section_symbol(target)
+                }
+            };
+            let flags = RelocationFlags::Generic {
+                kind: RelocationKind::Absolute,
+                encoding: RelocationEncoding::Generic,
+                size: relocation.size * 8,
+            };
+            let relocation = object_write::Relocation {
+                offset: relocation.offset as u64,
+                symbol,
+                addend: relocation.addend,
+                flags,
+            };
+            product.object.add_relocation(section_id, relocation).map_err(|e| format!("cannot relocate debug info: {}", e))?;
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_index_counts_from_one() {
+        let lines = LineIndex::new("func main() {\n    return 1;\n}\n");
+        assert_eq!(lines.position(0), (1, 1));
+        assert_eq!(lines.position(18), (2, 5));
+        assert_eq!(lines.position(28), (3, 1));
+    }
+}
@@ -0,0 +1,632 @@
+//! Module-level code generation: symbols, signatures, static data and the
+//! generated `main`
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use cranelift_codegen::ir::{self, AbiParam, types};
+use cranelift_codegen::isa::{self, CallConv};
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_codegen::Context;
+use cranelift_frontend::FunctionBuilderContext;
+use cranelift_module::{DataDescription, DataId, FuncId, Linkage, Module};
+use cranelift_object::{ObjectBuilder, ObjectModule};
+use fig_codegen_c::EntryPoint;
+use fig_codegen_c::mangle;
+use fig_mir::ir::{self as mir, BasicBlock, Body, Callee, Constant, Inline, LocalDecl, Operand, Rvalue, Statement, Terminator};
+use fig_parser::ast::Type;
+use fig_parser::format::format_type;
+use fig_sema::diagnostics::Diagnostic;
+use fig_sema::items::{ItemTable, TypeDef};
+use fig_sema::layout::{Layout, Shape, Target};
+use fig_sema::typeck::{Builtin, TypeChecker, is_float};
+
+use crate::abi::{self, Arg, Leaf, Signature};
+use crate::debug::{self, FunctionLines};
+use crate::function::FunctionTranslator;
+
+/// The target whose layouts and calling convention the backend uses
+pub const TARGET: Target = Target::X86_64;
+
+/// The triple objects are generated for
+const TRIPLE: &str = "x86_64-unknown-linux-gnu";
+
+/// Lowers the MIR of a program to an x86-64 ELF object
+pub struct ObjectEmitter<'a> {
+    items: &'a ItemTable<'a>,
+    entry: EntryPoint,
+    source: Option<(String, &'a str)>,
+}
+
+impl<'a> ObjectEmitter<'a> {
+    pub fn new(items: &'a ItemTable<'a>) -> Self {
+        ObjectEmitter { items, entry: EntryPoint::ExitCode, source: None }
+    }
+
+    pub fn with_entry(mut self, entry: EntryPoint) -> Self {
+        self.entry = entry;
+        self
+    }
+
+    /// Emit DWARF line information mapping code back to `src`, the contents
+    /// of the file at `path`
+    pub fn with_debug_info(mut self, path: impl Into<String>, src: &'a str) -> Self {
+        self.source = Some((path.into(), src));
+        self
+    }
+
+    /// Lower, verify and compile every function reachable from the program's
+    /// roots, returning the bytes of a relocatable object
+    pub fn emit(&self) -> Result<Vec<u8>, Vec<Diagnostic>> {
+        let program = fig_mir::lower_program(self.items, TARGET)?;
+        let errors = fig_mir::verify(&program);
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        self.emit_program(&program)
+    }
+
+    /// Compile a verified MIR program
+    pub fn emit_program(&self, program: &mir::Program) -> Result<Vec<u8>, Vec<Diagnostic>> {
+        let module = new_module().map_err(|message| vec![Diagnostic::error(message)])?;
+        let mut codegen = Codegen {
+            program,
+            items: self.items,
+            tc: TypeChecker::new(self.items, TARGET),
+            module,
+            functions: HashMap::new(),
+            externs: HashMap::new(),
+            strings: HashMap::new(),
+            zeros: HashMap::new(),
+            variant_names: HashMap::new(),
+            prints: HashMap::new(),
+            print_queue: Vec::new(),
+            lines: Vec::new(),
+            diagnostics: Vec::new(),
+        };
+        codegen.declare();
+        let entry = self.entry_body(&mut codegen);
+        let mut ctx = codegen.module.make_context();
+        let mut builder_ctx = FunctionBuilderContext::new();
+        for body in &program.functions {
+            codegen.define(body, &mut ctx, &mut builder_ctx);
+        }
+        if let Some(entry) = &entry {
+            codegen.define(entry, &mut ctx, &mut builder_ctx);
+        }
+        while let Some((id, ty)) = codegen.print_queue.pop() {
+            codegen.define_print(id, &ty, &mut ctx, &mut builder_ctx);
+        }
+        if !codegen.diagnostics.is_empty() {
+            return Err(codegen.diagnostics);
+        }
+        let mut product = codegen.module.finish();
+        if let Some((path, src)) = &self.source {
+            debug::write(&mut product, &codegen.lines, path, src).map_err(|message| vec![Diagnostic::error(message)])?;
+        }
+        product.emit().map_err(|e| vec![Diagnostic::error(format!("cannot write the object: {}", e))])
+    }
+
+    /// The C `main` for [`EntryPoint`], as MIR that calls Fig's `main`
+    fn entry_body(&self, codegen: &mut Codegen) -> Option<Body> {
+        if self.entry == EntryPoint::None {
+            return None;
+        }
+        let main = codegen.program.function("main")?;
+        if main.arg_count != 0 {
+            codegen.diagnostics.push(Diagnostic::error("`main` must not take parameters").in_function("main"));
+            return None;
+        }
+        let ty = main.return_type.clone();
+        let local = |ty: Type| LocalDecl { ty, name: None };
+        let exit = |code: i128| Terminator::Return(Operand::Const(Constant::Int(code, Type::I32)));
+        let call = Statement::Assign(mir::Local(0).into(), Rvalue::Call(Callee::Function("main".into()), Vec::new()));
+        let mut locals = vec![local(ty.clone())];
+        let blocks = match (self.entry, &ty) {
+            (EntryPoint::PrintResult, _) => {
+                let print = Rvalue::Call(Callee::Builtin(Builtin::Println), vec![mir::Local(0).into()]);
+                vec![BasicBlock::new(vec![call, Statement::Eval(print)], exit(0))]
+            }
+            (_, ty) if fig_sema::typeck::is_integer(ty) => {
+                locals.push(local(Type::I32));
+                let cast = Statement::Assign(mir::Local(1).into(), Rvalue::Cast(mir::Local(0).into(), Type::I32));
+                vec![BasicBlock::new(vec![call, cast], Terminator::Return(mir::Local(1).into()))]
+            }
+            (_, Type::ErrorUnion { .. }) => {
+                locals.push(local(Type::Bool));
+                let is_err = Statement::Assign(mir::Local(1).into(), Rvalue::IsErr(mir::Local(0).into()));
+                let branch =
+                    Terminator::Branch { cond: mir::Local(1).into(), then_block: mir::BlockId(1), else_block: mir::BlockId(2) };
+                let failed = Rvalue::Call(Callee::Extern(MAIN_FAILED.into()), Vec::new());
+                vec![
+                    BasicBlock::new(vec![call, is_err], branch),
+                    BasicBlock::new(vec![Statement::Eval(failed)], exit(1)),
+                    BasicBlock::new(Vec::new(), exit(0)),
+                ]
+            }
+            _ => vec![BasicBlock::new(vec![call], exit(0))],
+        };
+        let body = Body { name: ENTRY.into(), exported: true, arg_count: 0, locals, return_type: Type::I32, blocks, inline: Inline::Default };
+        codegen.declare_body(&body);
+        Some(body)
+    }
+}
+
+fn new_module() -> Result<ObjectModule, String> {
+    let mut flags = settings::builder();
+    for (name, value) in [("opt_level", "speed"), ("is_pic", "true"), ("preserve_frame_pointers", "true")] {
+        flags.set(name, value).map_err(|e| format!("cannot set `{}`: {}", name, e))?;
+    }
+    let isa = isa::lookup_by_name(TRIPLE)
+        .map_err(|e| format!("cannot target {}: {}", TRIPLE, e))?
+        .finish(settings::Flags::new(flags))
+        .map_err(|e| format!("cannot target {}: {}", TRIPLE, e))?;
+    let builder = ObjectBuilder::new(isa, "fig", cranelift_module::default_libcall_names())
+        .map_err(|e| format!("cannot create the object: {}", e))?;
+    Ok(ObjectModule::new(builder))
+}
+
+/// The name of the generated C `main`, as a MIR body
+const ENTRY: &str = "<entry>";
+
+/// The runtime function the generated `main` calls when Fig's `main` fails
+const MAIN_FAILED: &str = "fig_rt_main_failed";
+
+/// Runtime and libc functions generated code calls, with their C signatures
+const RUNTIME: &[(&str, &[ir::Type], &[ir::Type])] = &[
+    ("fig_rt_trap", &[types::I64], &[]),
+    ("fig_rt_index", &[types::I64, types::I64, types::I8], &[]),
+    ("fig_rt_slice", &[types::I64, types::I64, types::I64], &[]),
+    ("fig_rt_shift", &[types::I64, types::I64, types::I64], &[]),
+    ("fig_rt_out_of_range", &[types::I64, types::I64], &[]),
+    ("fig_rt_inactive", &[types::I64, types::I64, types::I64, types::I64], &[]),
+    (MAIN_FAILED, &[], &[]),
+    ("fig_rt_print_i64", &[types::I64], &[]),
+    ("fig_rt_print_u64", &[types::I64], &[]),
+    ("fig_rt_print_float", &[types::F64, types::I8], &[]),
+    ("fig_rt_print_pointer", &[types::I64], &[]),
+    ("fig_rt_print_bytes", &[types::I64, types::I64], &[]),
+    ("fig_rt_print_str", &[types::I64], &[]),
+    ("fig_rt_print_byte", &[types::I8], &[]),
+    ("memmove", &[types::I64, types::I64, types::I64], &[types::I64]),
+    ("malloc", &[types::I64], &[types::I64]),
+    ("calloc", &[types::I64, types::I64], &[types::I64]),
+    ("realloc", &[types::I64, types::I64], &[types::I64]),
+    ("free", &[types::I64], &[]),
+];
+
+/// How a value of some type is represented
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Repr {
+    /// In an SSA value of this type
+    Scalar(ir::Type),
+    /// In memory, with this size in bytes and alignment
+    Memory(u64, u64),
+}
+
+/// State shared by every function of one object
+pub(crate) struct Codegen<'p, 'a> {
+    pub program: &'p mir::Program,
+    pub items: &'a ItemTable<'a>,
+    pub tc: TypeChecker<'a>,
+    pub module: ObjectModule,
+    /// Function instances and the generated `main`, by MIR name
+    pub functions: HashMap<String, (FuncId, Rc<Signature>)>,
+    /// `extern` functions, the runtime and the libc functions it uses
+    pub externs: HashMap<String, (FuncId, Rc<Signature>)>,
+    /// NUL-terminated string bytes, and `[u8]` slices over them
+    strings: HashMap<(String, bool), DataId>,
+    /// All-zero blocks by size, the value of a tagged `null`
+    zeros: HashMap<u64, DataId>,
+    /// Tables of a union's variant names, for inactive variant traps
+    variant_names: HashMap<String, DataId>,
+    /// The function printing each type, by its formatted name
+    prints: HashMap<String, FuncId>,
+    pub print_queue: Vec<(FuncId, Type)>,
+    /// Where each function's code came from, for debug info
+    pub lines: Vec<FunctionLines>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Codegen<'_, 'a> {
+    pub fn error(&mut self, function: &str, message: impl Into<String>) {
+        self.diagnostics.push(Diagnostic::error(message).in_function(function));
+    }
+
+    /// Declare every function instance, `extern` function and runtime function
+    fn declare(&mut self) {
+        let program = self.program;
+        for body in &program.functions {
+            self.declare_body(body);
+        }
+        for decl in &program.externs {
+            let params: Vec<Arg> = decl.params.iter().map(|ty| self.arg(ty)).collect();
+            // A C function returning `void` is an `extern` returning `ok`
+            let result = if decl.return_type == Type::Ok {
+                Arg::Aggregate { size: 0, leaves: Vec::new() }
+            } else {
+                self.arg(&decl.return_type)
+            };
+            let signature = abi::signature(&params, &result);
+            self.declare_extern(&decl.name, signature);
+        }
+        for (name, params, returns) in RUNTIME {
+            if self.externs.contains_key(*name) {
+                continue;
+            }
+            let mut clif = ir::Signature::new(CallConv::SystemV);
+            clif.params.extend(params.iter().map(|&ty| AbiParam::new(ty)));
+            clif.returns.extend(returns.iter().map(|&ty| AbiParam::new(ty)));
+            let signature = Signature { params: Vec::new(), result: abi::PassMode::Direct(Vec::new()), clif };
+            self.declare_extern(name, signature);
+        }
+    }
+
+    fn declare_extern(&mut self, name: &str, signature: Signature) {
+        match self.module.declare_function(name, Linkage::Import, &signature.clif) {
+            Ok(id) => {
+                self.externs.insert(name.to_string(), (id, Rc::new(signature)));
+            }
+            Err(e) => self.diagnostics.push(Diagnostic::error(format!("cannot declare `{}`: {}", name, e))),
+        }
+    }
+
+    fn declare_body(&mut self, body: &Body) {
+        let params: Vec<Arg> = body.params().map(|local| self.arg(&body.local(local).ty)).collect();
+        let result = self.arg(&body.return_type);
+        let signature = abi::signature(&params, &result);
+        let (name, linkage) = symbol(body);
+        match self.module.declare_function(&name, linkage, &signature.clif) {
+            Ok(id) => {
+                self.functions.insert(body.name.clone(), (id, Rc::new(signature)));
+            }
+            Err(e) => self.error(&body.name, format!("cannot declare `{}`: {}", name, e)),
+        }
+    }
+
+    /// Compile one body and record its line table
+    fn define(&mut self, body: &Body, ctx: &mut Context, builder_ctx: &mut FunctionBuilderContext) {
+        let Some((id, signature)) = self.functions.get(&body.name).cloned() else { return };
+        ctx.func.signature = signature.clif.clone();
+        ctx.func.name = ir::UserFuncName::user(0, id.as_u32());
+        FunctionTranslator::translate(self, body, &signature, &mut ctx.func, builder_ctx);
+        self.finish(id, &body.name, ctx);
+    }
+
+    fn define_print(&mut self, id: FuncId, ty: &Type, ctx: &mut Context, builder_ctx: &mut FunctionBuilderContext) {
+        ctx.func.signature = print_signature();
+        ctx.func.name = ir::UserFuncName::user(0, id.as_u32());
+        FunctionTranslator::translate_print(self, ty, &mut ctx.func, builder_ctx);
+        self.finish(id, &format!("print[{}]", format_type(ty)), ctx);
+    }
+
+    fn finish(&mut self, id: FuncId, name: &str, ctx: &mut Context) {
+        if !self.diagnostics.is_empty() {
+            ctx.clear();
+            return;
+        }
+        if let Err(e) = self.module.define_function(id, ctx) {
+            let message = match e {
+                cranelift_module::ModuleError::Compilation(e) => {
+                    cranelift_codegen::print_errors::pretty_error(&ctx.func, e)
+                }
+                e => e.to_string(),
+            };
+            self.error(name, format!("code generation failed: {}", message));
+        } else if let Some(code) = ctx.compiled_code() {
+            let rows = code
+                .buffer
+                .get_srclocs_sorted()
+                .iter()
+                .filter(|srcloc| !srcloc.loc.is_default())
+                .map(|srcloc| (srcloc.start, srcloc.loc.bits() as usize))
+                .collect();
+            let size = code.buffer.total_size();
+            self.lines.push(FunctionLines { id, name: name.to_string(), size, rows });
+        }
+        ctx.clear();
+    }
+
+    // ========================================================================
+    // Types
+    // ========================================================================
+
+    pub fn layout(&mut self, ty: &Type) -> Layout {
+        match self.tc.layout().layout_of(ty) {
+            Ok(layout) => layout,
+            Err(e) => {
+                self.diagnostics.push(Diagnostic::error(e.to_string()));
+                Layout { size: 0, align: 1, shape: Shape::Scalar { non_null: false } }
+            }
+        }
+    }
+
+    pub fn repr(&mut self, ty: &Type) -> Repr {
+        let scalar = match ty {
+            Type::Bool | Type::U8 | Type::I8 | Type::Ok | Type::Null => types::I8,
+            Type::U16 | Type::I16 => types::I16,
+            Type::U32 | Type::I32 => types::I32,
+            Type::U64 | Type::I64 | Type::USize | Type::ISize | Type::Pointer { .. } => types::I64,
+            Type::F32 => types::F32,
+            Type::F64 => types::F64,
+            _ => {
+                let layout = self.layout(ty);
+                return match layout.shape {
+                    Shape::Enum { .. } => Repr::Scalar(int_type(layout.size)),
+                    Shape::Niche { .. } => Repr::Scalar(types::I64),
+                    _ => Repr::Memory(layout.size, layout.align),
+                };
+            }
+        };
+        Repr::Scalar(scalar)
+    }
+
+    /// Whether a scalar of `ty` is a signed integer or enum discriminant
+    pub fn is_signed(&mut self, ty: &Type) -> bool {
+        match TARGET.integer_info(ty) {
+            Some((_, signed)) => signed,
+            None => matches!(self.type_def(ty), Some(TypeDef::Enum(_)))
+                && matches!(self.layout(ty).shape, Shape::Enum { signed: true, .. }),
+        }
+    }
+
+    pub fn type_def(&self, ty: &Type) -> Option<TypeDef<'a>> {
+        match ty {
+            Type::Path(path) => self.items.lookup_type(path),
+            _ => None,
+        }
+    }
+
+    /// The declared name of a named type, without generic arguments
+    pub fn type_name(&self, ty: &Type) -> String {
+        self.type_def(ty).map_or_else(|| format_type(ty), |def| def.name().to_string())
+    }
+
+    /// Field or variant names and types of a struct or union
+    pub fn fields(&mut self, ty: &Type) -> Vec<(String, Type)> {
+        match self.tc.fields(ty) {
+            Ok(fields) => fields,
+            Err(message) => {
+                self.diagnostics.push(Diagnostic::error(message));
+                Vec::new()
+            }
+        }
+    }
+
+    /// Offset of a named field or variant payload in a layout
+    pub fn offset(&mut self, layout: &Layout, name: &str) -> u64 {
+        let offset = layout.field(name).map(|field| field.offset);
+        offset.unwrap_or_else(|| {
+            self.diagnostics.push(Diagnostic::error(format!("no field `{}` in layout", name)));
+            0
+        })
+    }
+
+    /// The type of the tag of a tagged layout
+    pub fn tag(layout: &Layout) -> ir::Type {
+        match layout.shape {
+            Shape::Tagged { tag_size, .. } => int_type(tag_size),
+            _ => types::I8,
+        }
+    }
+
+    /// How a value of `ty` is passed
+    fn arg(&mut self, ty: &Type) -> Arg {
+        match self.repr(ty) {
+            Repr::Scalar(scalar) => Arg::Scalar(scalar, self.is_signed(ty)),
+            Repr::Memory(size, _) => {
+                let mut leaves = Vec::new();
+                // Larger aggregates are passed in memory whatever their fields
+                if size <= 16 {
+                    self.leaves(ty, 0, &mut leaves);
+                }
+                Arg::Aggregate { size, leaves }
+            }
+        }
+    }
+
+    /// The scalars making up a value of `ty`, at `base` in an aggregate
+    fn leaves(&mut self, ty: &Type, base: u64, leaves: &mut Vec<Leaf>) {
+        if let Repr::Scalar(scalar) = self.repr(ty) {
+            leaves.push(Leaf { offset: base, size: scalar.bytes() as u64, float: is_float(ty) });
+            return;
+        }
+        let layout = self.layout(ty);
+        let tag = Leaf { offset: base, size: Self::tag(&layout).bytes() as u64, float: false };
+        match ty {
+            Type::Array { element_type, size: Some(_) } => {
+                if let Shape::Array { element, count } = &layout.shape {
+                    for i in 0..*count {
+                        self.leaves(element_type, base + i * element.size, leaves);
+                    }
+                }
+            }
+            Type::Array { size: None, .. } => {
+                leaves.push(Leaf { offset: base, size: 8, float: false });
+                leaves.push(Leaf { offset: base + 8, size: 8, float: false });
+            }
+            Type::Optional(inner) => {
+                leaves.push(tag);
+                let offset = self.offset(&layout, "some");
+                self.leaves(inner, base + offset, leaves);
+            }
+            Type::ErrorUnion { ok_type, err_type } => {
+                leaves.push(tag);
+                let ok = self.offset(&layout, "ok");
+                self.leaves(ok_type, base + ok, leaves);
+                let err = self.offset(&layout, "err");
+                self.leaves(&Type::Path(err_type.clone()), base + err, leaves);
+            }
+            Type::Path(_) => {
+                if matches!(layout.shape, Shape::Tagged { .. }) {
+                    leaves.push(tag);
+                }
+                for (name, field_type) in self.fields(ty) {
+                    if field_type != Type::Ok {
+                        let offset = self.offset(&layout, &name);
+                        self.leaves(&field_type, base + offset, leaves);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Parameter and return types of a callee, including the runtime's
+    pub fn signature_of(&self, callee: &Callee) -> Option<(Vec<Type>, Type)> {
+        match callee {
+            Callee::Extern(name) if name == MAIN_FAILED => Some((Vec::new(), Type::Ok)),
+            _ => self.program.signature(callee),
+        }
+    }
+
+    // ========================================================================
+    // Static data
+    // ========================================================================
+
+    fn data(&mut self, bytes: Vec<u8>, align: u64, writable: bool) -> DataId {
+        let id = self.module.declare_anonymous_data(writable, false).expect("anonymous data has no name to clash");
+        let mut description = DataDescription::new();
+        description.define(bytes.into_boxed_slice());
+        description.set_align(align);
+        self.module.define_data(id, &description).expect("anonymous data is defined once");
+        id
+    }
+
+    /// A string's bytes, NUL-terminated for C, or a `[u8]` slice over them
+    pub fn string(&mut self, text: &str, slice: bool) -> DataId {
+        if let Some(&id) = self.strings.get(&(text.to_string(), slice)) {
+            return id;
+        }
+        let id = if slice {
+            let bytes = self.string(text, false);
+            let id = self.module.declare_anonymous_data(true, false).expect("anonymous data has no name to clash");
+            let mut description = DataDescription::new();
+            let mut contents = vec![0; 16];
+            contents[8..].copy_from_slice(&(text.len() as u64).to_le_bytes());
+            description.define(contents.into_boxed_slice());
+            description.set_align(8);
+            let target = self.module.declare_data_in_data(bytes, &mut description);
+            description.write_data_addr(0, target, 0);
+            self.module.define_data(id, &description).expect("anonymous data is defined once");
+            id
+        } else {
+            let mut bytes = text.as_bytes().to_vec();
+            bytes.push(0);
+            self.data(bytes, 1, false)
+        };
+        self.strings.insert((text.to_string(), slice), id);
+        id
+    }
+
+    pub fn zeros(&mut self, size: u64) -> DataId {
+        if let Some(&id) = self.zeros.get(&size) {
+            return id;
+        }
+        let id = self.data(vec![0; size.max(1) as usize], 16, false);
+        self.zeros.insert(size, id);
+        id
+    }
+
+    /// An array of pointers to the names of a union's variants, in tag order
+    pub fn variant_names(&mut self, ty: &Type) -> DataId {
+        let key = format_type(ty);
+        if let Some(&id) = self.variant_names.get(&key) {
+            return id;
+        }
+        let names: Vec<DataId> = self.fields(ty).into_iter().map(|(name, _)| self.string(&name, false)).collect();
+        let id = self.module.declare_anonymous_data(true, false).expect("anonymous data has no name to clash");
+        let mut description = DataDescription::new();
+        description.define(vec![0; names.len().max(1) * 8].into_boxed_slice());
+        description.set_align(8);
+        for (i, name) in names.into_iter().enumerate() {
+            let target = self.module.declare_data_in_data(name, &mut description);
+            description.write_data_addr(i as u32 * 8, target, 0);
+        }
+        self.module.define_data(id, &description).expect("anonymous data is defined once");
+        self.variant_names.insert(key, id);
+        id
+    }
+
+    // ========================================================================
+    // Printing
+    // ========================================================================
+
+    /// The function printing a value of `ty` given its address, declared the
+    /// first time it is asked for and defined once the bodies are done
+    pub fn print_function(&mut self, ty: &Type) -> Result<FuncId, String> {
+        let key = format_type(ty);
+        if let Some(&id) = self.prints.get(&key) {
+            return Ok(id);
+        }
+        self.check_printable(ty)?;
+        let name = format!("print[{}]", key);
+        let id = self
+            .module
+            .declare_function(&name, Linkage::Local, &print_signature())
+            .map_err(|e| format!("cannot declare `{}`: {}", name, e))?;
+        self.prints.insert(key, id);
+        self.print_queue.push((id, ty.clone()));
+        Ok(id)
+    }
+
+    fn check_printable(&mut self, ty: &Type) -> Result<(), String> {
+        let unprintable = || Err(format!("cannot print a value of type `{}`", format_type(ty)));
+        match ty {
+            Type::Optional(inner) => match self.layout(ty).shape {
+                Shape::Niche { .. } => Ok(()),
+                _ => self.check_printable(inner),
+            },
+            Type::ErrorUnion { ok_type, err_type } => {
+                self.check_printable(ok_type)?;
+                self.check_printable(&Type::Path(err_type.clone()))
+            }
+            Type::Array { element_type, size: None } if **element_type == Type::U8 => Ok(()),
+            Type::Array { size: None, .. } => unprintable(),
+            Type::Array { element_type, .. } => self.check_printable(element_type),
+            Type::Path(_) => match self.type_def(ty) {
+                Some(TypeDef::Enum(_)) => Ok(()),
+                Some(TypeDef::Struct(_) | TypeDef::Union(_)) => {
+                    for (_, field_type) in self.fields(ty) {
+                        if field_type != Type::Ok {
+                            self.check_printable(&field_type)?;
+                        }
+                    }
+                    Ok(())
+                }
+                _ => unprintable(),
+            },
+            _ if matches!(self.repr(ty), Repr::Scalar(_)) => Ok(()),
+            _ => unprintable(),
+        }
+    }
+}
+
+/// Print functions take the address of the value
+fn print_signature() -> ir::Signature {
+    let mut signature = ir::Signature::new(CallConv::SystemV);
+    signature.params.push(AbiParam::new(types::I64));
+    signature
+}
+
+pub(crate) fn int_type(size: u64) -> ir::Type {
+    match size {
+        1 => types::I8,
+        2 => types::I16,
+        4 => types::I32,
+        _ => types::I64,
+    }
+}
+
+/// The symbol and linkage of a function. Exported functions get the C
+/// backend's names so C code can call them; the generated C `main` is
+/// `main`, and Fig's `main` is `fig_main`.
+fn symbol(body: &Body) -> (String, Linkage) {
+    if body.name == ENTRY {
+        return ("main".to_string(), Linkage::Export);
+    }
+    let (head, generic_args) = body.name.split_at(body.name.find('[').unwrap_or(body.name.len()));
+    let name = if head == "main" { "fig_main".to_string() } else { mangle::qualified(head) };
+    let linkage = if body.exported || head == "main" { Linkage::Export } else { Linkage::Local };
+    (format!("{}{}", name, generic_args), linkage)
+}
@@ -0,0 +1,1509 @@
+//! Translation of one MIR body to Cranelift IR
+//!
+//! Scalar locals whose address is never taken become Cranelift variables and
+//! live in registers; aggregates and address-taken scalars get a stack slot
+//! each. A place translates to a variable or an address, with the checks of
+//! its projections emitted on the way, and a failed check calls the runtime
+//! to report it before trapping in a cold block. Each statement's source
+//! position becomes the source location of its instructions, from which the
+//! line table is built.
+
+use std::collections::HashMap;
+
+use cranelift_codegen::entity::EntityRef;
+use cranelift_codegen::ir::condcodes::{FloatCC, IntCC};
+use cranelift_codegen::ir::{
+    self, Block, FuncRef, GlobalValue, InstBuilder, MemFlags, SourceLoc, StackSlotData, StackSlotKind, TrapCode,
+    Value, types,
+};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use cranelift_module::{DataId, FuncId, Module};
+use fig_mir::ir::{
+    AggregateKind, BinOp, BlockId, Body, Callee, Constant, Inline, Operand, Place, Projection, Rvalue, Statement,
+    Terminator, UnOp,
+};
+use fig_parser::ast::Type;
+use fig_parser::format::format_type;
+use fig_sema::items::TypeDef;
+use fig_sema::layout::{Shape, integer_bounds};
+use fig_sema::propagation::ErrorConversion;
+use fig_sema::typeck::{Builtin, is_float};
+
+use crate::abi::{PassMode, Signature};
+use crate::emit::{Codegen, Repr, int_type};
+
+/// Where a local lives
+#[derive(Debug, Clone, Copy)]
+enum Storage {
+    Var(Variable),
+    Slot(ir::StackSlot),
+}
+
+/// A translated place
+#[derive(Debug, Clone, Copy)]
+enum Loc {
+    Var(Variable),
+    Addr(Value),
+}
+
+/// A translated operand: a scalar, or the address of an aggregate
+#[derive(Debug, Clone, Copy)]
+enum Val {
+    Scalar(Value),
+    Memory(Value),
+}
+
+/// What a place is computed for, which decides how union variants are checked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Access {
+    /// Trap unless each variant on the path is active
+    Read,
+    /// Make the variants on the path active
+    Write,
+    /// Neither, for `&place`
+    Address,
+}
+
+/// The trap code of a failed check; the runtime has already reported it
+fn failed_check() -> TrapCode {
+    TrapCode::user(1).expect("1 is a valid user trap code")
+}
+
+fn flags() -> MemFlags {
+    // Fields of packed structs may be unaligned
+    MemFlags::new()
+}
+
+pub(crate) struct FunctionTranslator<'t, 'p, 'a> {
+    c: &'t mut Codegen<'p, 'a>,
+    body: &'t Body,
+    signature: &'t Signature,
+    b: FunctionBuilder<'t>,
+    locals: Vec<Storage>,
+    blocks: Vec<Block>,
+    /// The hidden result pointer
+    sret: Option<Value>,
+    funcs: HashMap<FuncId, FuncRef>,
+    data: HashMap<DataId, GlobalValue>,
+}
+
+impl<'t, 'p, 'a> FunctionTranslator<'t, 'p, 'a> {
+    fn new(
+        c: &'t mut Codegen<'p, 'a>,
+        body: &'t Body,
+        signature: &'t Signature,
+        func: &'t mut ir::Function,
+        builder_ctx: &'t mut FunctionBuilderContext,
+    ) -> Self {
+        FunctionTranslator {
+            c,
+            body,
+            signature,
+            b: FunctionBuilder::new(func, builder_ctx),
+            locals: Vec::new(),
+            blocks: Vec::new(),
+            sret: None,
+            funcs: HashMap::new(),
+            data: HashMap::new(),
+        }
+    }
+
+    /// Translate a body into `func`, whose signature is already set
+    pub fn translate(
+        c: &'t mut Codegen<'p, 'a>,
+        body: &'t Body,
+        signature: &'t Signature,
+        func: &'t mut ir::Function,
+        builder_ctx: &'t mut FunctionBuilderContext,
+    ) {
+        let mut t = FunctionTranslator::new(c, body, signature, func, builder_ctx);
+        t.body();
+        t.b.seal_all_blocks();
+        t.b.finalize();
+    }
+
+    /// Translate the function printing a value of `ty` at the address it is passed
+    pub fn translate_print(
+        c: &'t mut Codegen<'p, 'a>,
+        ty: &Type,
+        func: &'t mut ir::Function,
+        builder_ctx: &'t mut FunctionBuilderContext,
+    ) {
+        let body = Body {
+            name: format!("print[{}]", format_type(ty)),
+            exported: false,
+            arg_count: 0,
+            locals: Vec::new(),
+            return_type: Type::Ok,
+            blocks: Vec::new(),
+            inline: Inline::Default,
+        };
+        let signature = Signature { params: Vec::new(), result: PassMode::Direct(Vec::new()), clif: func.signature.clone() };
+        let mut t = FunctionTranslator::new(c, &body, &signature, func, builder_ctx);
+        let entry = t.b.create_block();
+        t.b.append_block_params_for_function_params(entry);
+        t.b.switch_to_block(entry);
+        let address = t.b.block_params(entry)[0];
+        t.print_value(ty, address);
+        t.b.ins().return_(&[]);
+        t.b.seal_all_blocks();
+        t.b.finalize();
+    }
+
+    fn error(&mut self, message: impl Into<String>) {
+        let name = self.body.name.clone();
+        self.c.error(&name, message);
+    }
+
+    fn place_type(&self, place: &Place) -> Type {
+        self.body.place_type(place).unwrap_or(Type::Ok)
+    }
+
+    fn operand_type(&self, operand: &Operand) -> Type {
+        self.body.operand_type(operand).unwrap_or(Type::Ok)
+    }
+
+    // ========================================================================
+    // Frames, blocks and statements
+    // ========================================================================
+
+    fn body(&mut self) {
+        let body = self.body;
+        let entry = self.b.create_block();
+        self.b.append_block_params_for_function_params(entry);
+        self.b.switch_to_block(entry);
+
+        let address_taken = address_taken(body);
+        for (i, decl) in body.locals.iter().enumerate() {
+            let storage = match self.c.repr(&decl.ty) {
+                Repr::Scalar(ty) if !address_taken[i] => {
+                    let var = Variable::new(i);
+                    self.b.declare_var(var, ty);
+                    Storage::Var(var)
+                }
+                Repr::Scalar(ty) => Storage::Slot(self.slot(ty.bytes() as u64, ty.bytes() as u64)),
+                // Rounded up so parameters can be stored an eightbyte at a time
+                Repr::Memory(size, align) => Storage::Slot(self.slot(size.div_ceil(8) * 8, align)),
+            };
+            self.locals.push(storage);
+        }
+
+        let mut params = self.b.block_params(entry).to_vec().into_iter();
+        if self.signature.sret() {
+            self.sret = params.next();
+        }
+        for (local, mode) in body.params().zip(&self.signature.params) {
+            let loc = self.local(local.index());
+            match mode {
+                PassMode::Scalar(_) => {
+                    let value = params.next().expect("a scalar parameter has a value");
+                    self.store(loc, Val::Scalar(value), &body.local(local).ty);
+                }
+                PassMode::Direct(classes) => {
+                    let address = self.addr(loc);
+                    for i in 0..classes.len() {
+                        let value = params.next().expect("each eightbyte has a value");
+                        self.b.ins().store(flags(), value, address, i as i32 * 8);
+                    }
+                }
+                PassMode::Memory(_) => {
+                    let value = params.next().expect("a stack parameter has an address");
+                    self.store(loc, Val::Memory(value), &body.local(local).ty);
+                }
+            }
+        }
+
+        self.blocks = body.blocks.iter().map(|_| self.b.create_block()).collect();
+        // The entry block cannot be a loop header, so it jumps to bb0
+        self.b.ins().jump(self.blocks[0], &[]);
+        for (index, block) in body.blocks.iter().enumerate() {
+            self.b.switch_to_block(self.blocks[index]);
+            for (i, statement) in block.statements.iter().enumerate() {
+                if let Some(span) = block.span(i) {
+                    self.b.set_srcloc(SourceLoc::new(span.start as u32));
+                }
+                self.statement(statement);
+            }
+            if let Some(span) = block.terminator_span {
+                self.b.set_srcloc(SourceLoc::new(span.start as u32));
+            }
+            self.terminator(&block.terminator);
+        }
+    }
+
+    fn slot(&mut self, size: u64, align: u64) -> ir::StackSlot {
+        let align_shift = align.max(1).trailing_zeros() as u8;
+        self.b.create_sized_stack_slot(StackSlotData::new(StackSlotKind::ExplicitSlot, size as u32, align_shift))
+    }
+
+    /// The address of fresh stack space
+    fn temp(&mut self, size: u64, align: u64) -> Value {
+        let slot = self.slot(size, align.max(8));
+        self.b.ins().stack_addr(types::I64, slot, 0)
+    }
+
+    fn local(&mut self, index: usize) -> Loc {
+        match self.locals[index] {
+            Storage::Var(var) => Loc::Var(var),
+            Storage::Slot(slot) => Loc::Addr(self.b.ins().stack_addr(types::I64, slot, 0)),
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Assign(place, rvalue) => {
+                let ty = self.place_type(place);
+                let value = self.rvalue(rvalue);
+                let loc = self.place(place, Access::Write);
+                self.store(loc, value, &ty);
+            }
+            Statement::Eval(rvalue) => {
+                self.rvalue(rvalue);
+            }
+        }
+    }
+
+    fn terminator(&mut self, terminator: &Terminator) {
+        match terminator {
+            Terminator::Goto(target) => {
+                self.b.ins().jump(self.blocks[target.index()], &[]);
+            }
+            Terminator::Branch { cond, then_block, else_block } => {
+                let cond = self.value(cond);
+                let (then_block, else_block) = (self.blocks[then_block.index()], self.blocks[else_block.index()]);
+                self.b.ins().brif(cond, then_block, &[], else_block, &[]);
+            }
+            Terminator::Return(value) => {
+                let value = self.operand(value);
+                self.ret(value);
+            }
+            Terminator::Propagate { value, dest, conversion, next } => self.propagate(value, dest, conversion, *next),
+            Terminator::Unreachable => {
+                let message = format!("`{}` ended without returning a value", self.body.name);
+                self.trap_message(&message);
+                self.b.ins().trap(failed_check());
+            }
+        }
+    }
+
+    fn ret(&mut self, value: Val) {
+        let return_type = self.body.return_type.clone();
+        match &self.signature.result {
+            PassMode::Scalar(_) => {
+                let value = self.scalar(value);
+                self.b.ins().return_(&[value]);
+            }
+            PassMode::Direct(classes) => {
+                let classes = classes.clone();
+                let source = self.memory(value);
+                let size = self.c.layout(&return_type).size;
+                let temp = self.temp(classes.len() as u64 * 8, 8);
+                self.copy(temp, source, size);
+                let values: Vec<Value> = classes
+                    .iter()
+                    .enumerate()
+                    .map(|(i, class)| self.b.ins().load(class.ty(), flags(), temp, i as i32 * 8))
+                    .collect();
+                self.b.ins().return_(&values);
+            }
+            PassMode::Memory(_) => {
+                let source = self.memory(value);
+                let dest = self.sret.expect("a function returning in memory has a result pointer");
+                let size = self.c.layout(&return_type).size;
+                self.copy(dest, source, size);
+                self.b.ins().return_(&[]);
+            }
+        }
+    }
+
+    /// Return the converted error when `value` holds one, else store its
+    /// success value in `dest` and continue at `next`
+    fn propagate(&mut self, value: &Operand, dest: &Place, conversion: &ErrorConversion, next: BlockId) {
+        let union_type = self.operand_type(value);
+        let return_type = self.body.return_type.clone();
+        let (Type::ErrorUnion { ok_type, err_type }, Type::ErrorUnion { err_type: return_err, .. }) =
+            (&union_type, &return_type)
+        else {
+            self.error(format!("cannot propagate from `{}`", format_type(&union_type)));
+            return;
+        };
+        let (err_type, return_err) = (Type::Path(err_type.clone()), Type::Path(return_err.clone()));
+        let layout = self.c.layout(&union_type);
+        let ok_offset = self.c.offset(&layout, "ok");
+        let err_offset = self.c.offset(&layout, "err");
+        let source = self.operand(value);
+        let source = self.memory(source);
+        let tag = self.b.ins().load(Codegen::tag(&layout), flags(), source, 0);
+        let (err_block, ok_block) = (self.b.create_block(), self.b.create_block());
+        self.b.ins().brif(tag, err_block, &[], ok_block, &[]);
+
+        // The error path returns `err(converted)`
+        self.b.switch_to_block(err_block);
+        let return_layout = self.c.layout(&return_type);
+        let result = self.temp(return_layout.size, return_layout.align);
+        let one = self.iconst(Codegen::tag(&return_layout), 1);
+        self.b.ins().store(flags(), one, result, 0);
+        let return_err_offset = self.c.offset(&return_layout, "err");
+        let err_dest = self.offset(result, return_err_offset);
+        let err_source = self.offset(source, err_offset);
+        match conversion {
+            ErrorConversion::Identity => self.copy_value(err_dest, err_source, &err_type),
+            ErrorConversion::Variant { variant } => {
+                let payload = self.set_variant(err_dest, &return_err, variant);
+                self.copy_value(payload, err_source, &err_type);
+            }
+            ErrorConversion::Function { function } => {
+                let Some((id, signature)) = self.c.functions.get(function).cloned() else {
+                    self.error(format!("missing error conversion `{}`", function));
+                    return;
+                };
+                let error = self.load_value(err_source, &err_type);
+                let converted = self.call_with(id, &signature, &[(error, err_type)], &return_err);
+                self.store(Loc::Addr(err_dest), converted, &return_err);
+            }
+        }
+        self.ret(Val::Memory(result));
+
+        self.b.switch_to_block(ok_block);
+        let ok_source = self.offset(source, ok_offset);
+        let ok = self.load_value(ok_source, ok_type);
+        let loc = self.place(dest, Access::Write);
+        self.store(loc, ok, ok_type);
+        self.b.ins().jump(self.blocks[next.index()], &[]);
+    }
+
+    // ========================================================================
+    // Places and operands
+    // ========================================================================
+
+    fn place(&mut self, place: &Place, access: Access) -> Loc {
+        let mut loc = self.local(place.local.index());
+        let mut ty = self.body.local(place.local).ty.clone();
+        // Variants before the last pointer are read, whatever the access
+        let mut types = Vec::with_capacity(place.projection.len());
+        let mut last_indirect = None;
+        for (i, projection) in place.projection.iter().enumerate() {
+            let through_pointer = matches!(projection, Projection::Deref)
+                || (matches!(projection, Projection::Index(_)) && matches!(ty, Type::Pointer { .. }));
+            if through_pointer {
+                last_indirect = Some(i);
+            }
+            types.push(ty.clone());
+            ty = projection.apply(&ty).unwrap_or(Type::Ok);
+        }
+        for (i, (projection, base)) in place.projection.iter().zip(types).enumerate() {
+            let access = if last_indirect.is_some_and(|last| i < last) { Access::Read } else { access };
+            loc = match projection {
+                Projection::Field(name, _) => {
+                    let layout = self.c.layout(&base);
+                    let offset = self.c.offset(&layout, name);
+                    let address = self.addr(loc);
+                    Loc::Addr(self.offset(address, offset))
+                }
+                Projection::Variant(name, _) => {
+                    let address = self.addr(loc);
+                    Loc::Addr(match access {
+                        Access::Read => self.check_variant(address, &base, name),
+                        Access::Write => self.set_variant(address, &base, name),
+                        Access::Address => {
+                            let layout = self.c.layout(&base);
+                            let offset = self.c.offset(&layout, name);
+                            self.offset(address, offset)
+                        }
+                    })
+                }
+                Projection::Index(index) => Loc::Addr(self.index(loc, &base, *index)),
+                Projection::Deref => {
+                    let pointer = self.read(loc, types::I64);
+                    self.check_non_null(pointer);
+                    Loc::Addr(pointer)
+                }
+                Projection::Payload => {
+                    let layout = self.c.layout(&base);
+                    if matches!(layout.shape, Shape::Niche { .. }) {
+                        loc
+                    } else {
+                        let offset = self.c.offset(&layout, "some");
+                        let address = self.addr(loc);
+                        Loc::Addr(self.offset(address, offset))
+                    }
+                }
+                Projection::OkValue | Projection::ErrValue => {
+                    let layout = self.c.layout(&base);
+                    let name = if matches!(projection, Projection::OkValue) { "ok" } else { "err" };
+                    let offset = self.c.offset(&layout, name);
+                    let address = self.addr(loc);
+                    Loc::Addr(self.offset(address, offset))
+                }
+            };
+        }
+        loc
+    }
+
+    /// The address of an element of an array, slice or pointer
+    fn index(&mut self, base_loc: Loc, base: &Type, index: fig_mir::ir::Local) -> Value {
+        let index_type = self.body.local(index).ty.clone();
+        let signed = self.c.is_signed(&index_type);
+        let index_loc = self.local(index.index());
+        let index_scalar = match self.c.repr(&index_type) {
+            Repr::Scalar(ty) => ty,
+            Repr::Memory(..) => types::I64,
+        };
+        let value = self.read(index_loc, index_scalar);
+        let value = self.extend(value, signed, types::I64);
+        let (Type::Array { element_type, .. } | Type::Pointer { element_type, .. }) = base else {
+            self.error(format!("cannot index a `{}`", format_type(base)));
+            return value;
+        };
+        let stride = self.c.layout(element_type).size;
+        let start = match base {
+            Type::Array { size: Some(_), .. } => {
+                let count = match self.c.layout(base).shape {
+                    Shape::Array { count, .. } => count,
+                    _ => 0,
+                };
+                let len = self.b.ins().iconst(types::I64, count as i64);
+                self.check_index(value, len, signed);
+                self.addr(base_loc)
+            }
+            Type::Array { size: None, .. } => {
+                let slice = self.addr(base_loc);
+                let pointer = self.b.ins().load(types::I64, flags(), slice, 0);
+                let len = self.b.ins().load(types::I64, flags(), slice, 8);
+                self.check_index(value, len, signed);
+                pointer
+            }
+            _ => {
+                let pointer = self.read(base_loc, types::I64);
+                self.check_non_null(pointer);
+                pointer
+            }
+        };
+        let offset = self.b.ins().imul_imm(value, stride as i64);
+        self.b.ins().iadd(start, offset)
+    }
+
+    /// Trap unless the variant `name` of the union at `address` is active,
+    /// returning the address of its payload
+    fn check_variant(&mut self, address: Value, union_type: &Type, name: &str) -> Value {
+        let layout = self.c.layout(union_type);
+        let variant = self.variant_index(union_type, name);
+        let tag = self.b.ins().load(Codegen::tag(&layout), flags(), address, 0);
+        let inactive = self.b.ins().icmp_imm(IntCC::NotEqual, tag, variant as i64);
+        self.trap_if(inactive, |t| {
+            let union_name = t.c.type_name(union_type);
+            let union_name = t.c_string(&union_name);
+            let names = t.c.variant_names(union_type);
+            let names = t.data_address(names);
+            let variant = t.b.ins().iconst(types::I64, variant as i64);
+            let active = t.extend(tag, false, types::I64);
+            t.call_runtime("fig_rt_inactive", &[union_name, names, variant, active]);
+        });
+        let offset = self.c.offset(&layout, name);
+        self.offset(address, offset)
+    }
+
+    /// Make the variant `name` of the union at `address` active, returning
+    /// the address of its payload
+    fn set_variant(&mut self, address: Value, union_type: &Type, name: &str) -> Value {
+        let layout = self.c.layout(union_type);
+        let variant = self.variant_index(union_type, name);
+        let tag = self.iconst(Codegen::tag(&layout), variant as i128);
+        self.b.ins().store(flags(), tag, address, 0);
+        let offset = self.c.offset(&layout, name);
+        self.offset(address, offset)
+    }
+
+    fn variant_index(&mut self, union_type: &Type, name: &str) -> usize {
+        self.c.fields(union_type).iter().position(|(variant, _)| variant == name).unwrap_or(0)
+    }
+
+    fn check_index(&mut self, index: Value, len: Value, signed: bool) {
+        // A negative index is a large unsigned one
+        let out_of_bounds = self.b.ins().icmp(IntCC::UnsignedGreaterThanOrEqual, index, len);
+        self.trap_if(out_of_bounds, |t| {
+            let signed = t.b.ins().iconst(types::I8, signed as i64);
+            t.call_runtime("fig_rt_index", &[index, len, signed]);
+        });
+    }
+
+    fn check_non_null(&mut self, pointer: Value) {
+        let null = self.b.ins().icmp_imm(IntCC::Equal, pointer, 0);
+        self.trap_if(null, |t| t.trap_message("null pointer dereference"));
+    }
+
+    fn addr(&mut self, loc: Loc) -> Value {
+        match loc {
+            Loc::Addr(address) => address,
+            Loc::Var(_) => {
+                self.error("a register local has no address");
+                self.b.ins().iconst(types::I64, 0)
+            }
+        }
+    }
+
+    fn read(&mut self, loc: Loc, ty: ir::Type) -> Value {
+        match loc {
+            Loc::Var(var) => self.b.use_var(var),
+            Loc::Addr(address) => self.b.ins().load(ty, flags(), address, 0),
+        }
+    }
+
+    fn offset(&mut self, address: Value, offset: u64) -> Value {
+        if offset == 0 { address } else { self.b.ins().iadd_imm(address, offset as i64) }
+    }
+
+    fn operand(&mut self, operand: &Operand) -> Val {
+        match operand {
+            Operand::Copy(place) => {
+                let ty = self.place_type(place);
+                let loc = self.place(place, Access::Read);
+                match self.c.repr(&ty) {
+                    Repr::Scalar(scalar) => Val::Scalar(self.read(loc, scalar)),
+                    Repr::Memory(..) => Val::Memory(self.addr(loc)),
+                }
+            }
+            Operand::Const(constant) => self.constant(constant),
+        }
+    }
+
+    fn constant(&mut self, constant: &Constant) -> Val {
+        match constant {
+            Constant::Int(value, ty) => {
+                let scalar = match self.c.repr(ty) {
+                    Repr::Scalar(scalar) => scalar,
+                    Repr::Memory(..) => types::I64,
+                };
+                Val::Scalar(self.iconst(scalar, *value))
+            }
+            Constant::Float(value, ty) if *ty == Type::F32 => Val::Scalar(self.b.ins().f32const(*value as f32)),
+            Constant::Float(value, _) => Val::Scalar(self.b.ins().f64const(*value)),
+            Constant::Bool(value) => Val::Scalar(self.b.ins().iconst(types::I8, *value as i64)),
+            Constant::Str(text, ty) => {
+                let slice = matches!(ty, Type::Array { .. });
+                let id = self.c.string(text, slice);
+                let address = self.data_address(id);
+                if slice { Val::Memory(address) } else { Val::Scalar(address) }
+            }
+            Constant::Ok => Val::Scalar(self.b.ins().iconst(types::I8, 0)),
+            Constant::Null(ty) => match self.c.repr(ty) {
+                Repr::Scalar(scalar) => Val::Scalar(self.iconst(scalar, 0)),
+                Repr::Memory(size, _) => {
+                    let id = self.c.zeros(size);
+                    Val::Memory(self.data_address(id))
+                }
+            },
+        }
+    }
+
+    /// An integer constant, truncated to the width of `ty`
+    fn iconst(&mut self, ty: ir::Type, value: i128) -> Value {
+        let bits = ty.bits();
+        let value = if bits >= 64 { value as i64 } else { (value as i64) & ((1i64 << bits) - 1) };
+        self.b.ins().iconst(ty, value)
+    }
+
+    fn value(&mut self, operand: &Operand) -> Value {
+        let value = self.operand(operand);
+        self.scalar(value)
+    }
+
+    fn scalar(&mut self, value: Val) -> Value {
+        match value {
+            Val::Scalar(value) => value,
+            Val::Memory(address) => {
+                self.error("an aggregate used as a scalar");
+                address
+            }
+        }
+    }
+
+    fn memory(&mut self, value: Val) -> Value {
+        match value {
+            Val::Memory(address) => address,
+            Val::Scalar(value) => {
+                self.error("a scalar used as an aggregate");
+                value
+            }
+        }
+    }
+
+    /// The address of a value, storing a scalar to the stack first
+    fn spill(&mut self, value: Val) -> Value {
+        match value {
+            Val::Memory(address) => address,
+            Val::Scalar(value) => {
+                let size = self.b.func.dfg.value_type(value).bytes() as u64;
+                let address = self.temp(size, size);
+                self.b.ins().store(flags(), value, address, 0);
+                address
+            }
+        }
+    }
+
+    fn load_value(&mut self, address: Value, ty: &Type) -> Val {
+        match self.c.repr(ty) {
+            Repr::Scalar(scalar) => Val::Scalar(self.b.ins().load(scalar, flags(), address, 0)),
+            Repr::Memory(..) => Val::Memory(address),
+        }
+    }
+
+    fn store(&mut self, loc: Loc, value: Val, ty: &Type) {
+        match (loc, value) {
+            (Loc::Var(var), Val::Scalar(value)) => self.b.def_var(var, value),
+            (Loc::Addr(address), Val::Scalar(value)) => {
+                self.b.ins().store(flags(), value, address, 0);
+            }
+            (Loc::Addr(address), Val::Memory(source)) => self.copy_value(address, source, ty),
+            (Loc::Var(_), Val::Memory(_)) => self.error(format!("an aggregate stored to a `{}` register", format_type(ty))),
+        }
+    }
+
+    fn copy_value(&mut self, dest: Value, source: Value, ty: &Type) {
+        let size = self.c.layout(ty).size;
+        self.copy(dest, source, size);
+    }
+
+    /// Copy `size` bytes, which may overlap
+    fn copy(&mut self, dest: Value, source: Value, size: u64) {
+        if size > 64 {
+            let size = self.b.ins().iconst(types::I64, size as i64);
+            self.call_runtime("memmove", &[dest, source, size]);
+            return;
+        }
+        let mut chunks = Vec::new();
+        let mut offset = 0;
+        for chunk in [8, 4, 2, 1] {
+            while size - offset >= chunk {
+                let value = self.b.ins().load(int_type(chunk), flags(), source, offset as i32);
+                chunks.push((value, offset));
+                offset += chunk;
+            }
+        }
+        for (value, offset) in chunks {
+            self.b.ins().store(flags(), value, dest, offset as i32);
+        }
+    }
+
+    // ========================================================================
+    // Rvalues
+    // ========================================================================
+
+    fn rvalue(&mut self, rvalue: &Rvalue) -> Val {
+        match rvalue {
+            Rvalue::Use(operand) => self.operand(operand),
+            Rvalue::Binary(op, lhs, rhs) => Val::Scalar(self.binary(*op, lhs, rhs)),
+            Rvalue::Unary(op, operand) => Val::Scalar(self.unary(*op, operand)),
+            Rvalue::Cast(operand, to) => {
+                let from = self.operand_type(operand);
+                let value = self.value(operand);
+                Val::Scalar(self.cast(value, &from, to))
+            }
+            Rvalue::AddressOf(place) => {
+                let loc = self.place(place, Access::Address);
+                Val::Scalar(self.addr(loc))
+            }
+            Rvalue::Aggregate(kind, operands) => self.aggregate(kind, operands),
+            Rvalue::Unsize(place, _) => {
+                let array_type = self.place_type(place);
+                let count = match self.c.layout(&array_type).shape {
+                    Shape::Array { count, .. } => count,
+                    _ => 0,
+                };
+                let loc = self.place(place, Access::Address);
+                let array = self.addr(loc);
+                let slice = self.temp(16, 8);
+                let len = self.b.ins().iconst(types::I64, count as i64);
+                self.b.ins().store(flags(), array, slice, 0);
+                self.b.ins().store(flags(), len, slice, 8);
+                Val::Memory(slice)
+            }
+            Rvalue::Len(place) => {
+                let loc = self.place(place, Access::Read);
+                let slice = self.addr(loc);
+                Val::Scalar(self.b.ins().load(types::I64, flags(), slice, 8))
+            }
+            Rvalue::Subslice(place, start, end) => {
+                let array_type = self.place_type(place);
+                let loc = self.place(place, Access::Read);
+                let base = self.addr(loc);
+                let (pointer, len) = match (&array_type, self.c.layout(&array_type).shape) {
+                    (Type::Array { size: Some(_), .. }, Shape::Array { count, .. }) => {
+                        (base, self.b.ins().iconst(types::I64, count as i64))
+                    }
+                    _ => {
+                        let pointer = self.b.ins().load(types::I64, flags(), base, 0);
+                        (pointer, self.b.ins().load(types::I64, flags(), base, 8))
+                    }
+                };
+                let start = self.value(start);
+                let end = self.value(end);
+                let backwards = self.b.ins().icmp(IntCC::UnsignedGreaterThan, start, end);
+                let past_len = self.b.ins().icmp(IntCC::UnsignedGreaterThan, end, len);
+                let out_of_bounds = self.b.ins().bor(backwards, past_len);
+                self.trap_if(out_of_bounds, |t| {
+                    t.call_runtime("fig_rt_slice", &[start, end, len]);
+                });
+                let stride = match &array_type {
+                    Type::Array { element_type, .. } => self.c.layout(element_type).size,
+                    _ => 0,
+                };
+                let offset = self.b.ins().imul_imm(start, stride as i64);
+                let data = self.b.ins().iadd(pointer, offset);
+                let count = self.b.ins().isub(end, start);
+                let slice = self.temp(16, 8);
+                self.b.ins().store(flags(), data, slice, 0);
+                self.b.ins().store(flags(), count, slice, 8);
+                Val::Memory(slice)
+            }
+            Rvalue::IsNull(operand) => {
+                let ty = self.operand_type(operand);
+                let value = self.operand(operand);
+                let flag = match value {
+                    Val::Scalar(value) => value,
+                    Val::Memory(address) => {
+                        let layout = self.c.layout(&ty);
+                        self.b.ins().load(Codegen::tag(&layout), flags(), address, 0)
+                    }
+                };
+                Val::Scalar(self.b.ins().icmp_imm(IntCC::Equal, flag, 0))
+            }
+            Rvalue::IsErr(operand) => {
+                let ty = self.operand_type(operand);
+                let value = self.operand(operand);
+                let address = self.memory(value);
+                let layout = self.c.layout(&ty);
+                let tag = self.b.ins().load(Codegen::tag(&layout), flags(), address, 0);
+                Val::Scalar(self.b.ins().icmp_imm(IntCC::NotEqual, tag, 0))
+            }
+            Rvalue::Call(callee, args) => self.call(callee, args),
+        }
+    }
+
+    /// Build an aggregate on the stack
+    fn aggregate(&mut self, kind: &AggregateKind, operands: &[Operand]) -> Val {
+        let ty = kind.ty().clone();
+        if let (AggregateKind::Some(_), Repr::Scalar(_), [operand]) = (kind, self.c.repr(&ty), operands) {
+            // A `?*T` is the pointer itself
+            return self.operand(operand);
+        }
+        let layout = self.c.layout(&ty);
+        let dest = self.temp(layout.size, layout.align);
+        match kind {
+            AggregateKind::Struct(_) => {
+                for ((name, field_type), operand) in self.c.fields(&ty).iter().zip(operands) {
+                    let offset = self.c.offset(&layout, name);
+                    let value = self.operand(operand);
+                    let address = self.offset(dest, offset);
+                    self.store(Loc::Addr(address), value, field_type);
+                }
+            }
+            AggregateKind::Array(_) => {
+                let (Type::Array { element_type, .. }, Shape::Array { element, .. }) = (&ty, &layout.shape) else {
+                    return Val::Memory(dest);
+                };
+                for (i, operand) in operands.iter().enumerate() {
+                    let value = self.operand(operand);
+                    let address = self.offset(dest, i as u64 * element.size);
+                    self.store(Loc::Addr(address), value, element_type);
+                }
+            }
+            AggregateKind::Variant(_, variant) => {
+                let payload = self.set_variant(dest, &ty, variant);
+                if let Some(operand) = operands.first() {
+                    let payload_type = self.operand_type(operand);
+                    let value = self.operand(operand);
+                    self.store(Loc::Addr(payload), value, &payload_type);
+                }
+            }
+            AggregateKind::Some(_) | AggregateKind::Ok(_) | AggregateKind::Err(_) => {
+                let (tag, name) = match kind {
+                    AggregateKind::Some(_) => (1, "some"),
+                    AggregateKind::Ok(_) => (0, "ok"),
+                    _ => (1, "err"),
+                };
+                let tag = self.iconst(Codegen::tag(&layout), tag);
+                self.b.ins().store(flags(), tag, dest, 0);
+                if let Some(operand) = operands.first() {
+                    let offset = self.c.offset(&layout, name);
+                    let payload_type = self.operand_type(operand);
+                    let value = self.operand(operand);
+                    let address = self.offset(dest, offset);
+                    self.store(Loc::Addr(address), value, &payload_type);
+                }
+            }
+        }
+        Val::Memory(dest)
+    }
+
+    fn binary(&mut self, op: BinOp, lhs: &Operand, rhs: &Operand) -> Value {
+        let lhs_type = self.operand_type(lhs);
+        let rhs_type = self.operand_type(rhs);
+        let a = self.value(lhs);
+        let b = self.value(rhs);
+        if op.is_comparison() {
+            if is_float(&lhs_type) {
+                let cc = match op {
+                    BinOp::Eq => FloatCC::Equal,
+                    BinOp::Ne => FloatCC::NotEqual,
+                    BinOp::Lt => FloatCC::LessThan,
+                    BinOp::Le => FloatCC::LessThanOrEqual,
+                    BinOp::Gt => FloatCC::GreaterThan,
+                    _ => FloatCC::GreaterThanOrEqual,
+                };
+                return self.b.ins().fcmp(cc, a, b);
+            }
+            let signed = self.c.is_signed(&lhs_type);
+            let cc = match (op, signed) {
+                (BinOp::Eq, _) => IntCC::Equal,
+                (BinOp::Ne, _) => IntCC::NotEqual,
+                (BinOp::Lt, true) => IntCC::SignedLessThan,
+                (BinOp::Lt, false) => IntCC::UnsignedLessThan,
+                (BinOp::Le, true) => IntCC::SignedLessThanOrEqual,
+                (BinOp::Le, false) => IntCC::UnsignedLessThanOrEqual,
+                (BinOp::Gt, true) => IntCC::SignedGreaterThan,
+                (BinOp::Gt, false) => IntCC::UnsignedGreaterThan,
+                (_, true) => IntCC::SignedGreaterThanOrEqual,
+                (_, false) => IntCC::UnsignedGreaterThanOrEqual,
+            };
+            return self.b.ins().icmp(cc, a, b);
+        }
+        if let Type::Pointer { element_type, .. } = &lhs_type {
+            let stride = self.c.layout(element_type).size.max(1) as i64;
+            if let (BinOp::Sub, Type::Pointer { .. }) = (op, &rhs_type) {
+                let distance = self.b.ins().isub(a, b);
+                return self.b.ins().sdiv_imm(distance, stride);
+            }
+            let signed = self.c.is_signed(&rhs_type);
+            let index = self.extend(b, signed, types::I64);
+            let delta = self.b.ins().imul_imm(index, stride);
+            return match op {
+                BinOp::Add => self.b.ins().iadd(a, delta),
+                BinOp::Sub => self.b.ins().isub(a, delta),
+                _ => {
+                    self.error(format!("`{}` on a pointer", op.name()));
+                    a
+                }
+            };
+        }
+        if is_float(&lhs_type) {
+            return match op {
+                BinOp::Add => self.b.ins().fadd(a, b),
+                BinOp::Sub => self.b.ins().fsub(a, b),
+                BinOp::Mul => self.b.ins().fmul(a, b),
+                BinOp::Div => self.b.ins().fdiv(a, b),
+                _ => {
+                    self.error(format!("`{}` on a float", op.name()));
+                    a
+                }
+            };
+        }
+        let name = format_type(&lhs_type);
+        let signed = self.c.is_signed(&lhs_type);
+        let ty = self.b.func.dfg.value_type(a);
+        match op {
+            BinOp::BitAnd => self.b.ins().band(a, b),
+            BinOp::BitOr => self.b.ins().bor(a, b),
+            BinOp::BitXor => self.b.ins().bxor(a, b),
+            BinOp::Add | BinOp::Sub | BinOp::Mul => {
+                let ins = self.b.ins();
+                let (result, overflowed) = match (op, signed) {
+                    (BinOp::Add, true) => ins.sadd_overflow(a, b),
+                    (BinOp::Add, false) => ins.uadd_overflow(a, b),
+                    (BinOp::Sub, true) => ins.ssub_overflow(a, b),
+                    (BinOp::Sub, false) => ins.usub_overflow(a, b),
+                    (_, true) => ins.smul_overflow(a, b),
+                    (_, false) => ins.umul_overflow(a, b),
+                };
+                let what = match op {
+                    BinOp::Add => "addition",
+                    BinOp::Sub => "subtraction",
+                    _ => "multiplication",
+                };
+                let message = format!("`{}` {} overflowed", name, what);
+                self.trap_if(overflowed, |t| t.trap_message(&message));
+                result
+            }
+            BinOp::Div | BinOp::Rem => {
+                let zero = self.b.ins().icmp_imm(IntCC::Equal, b, 0);
+                self.trap_if(zero, |t| t.trap_message("division by zero"));
+                if !signed {
+                    return if op == BinOp::Div { self.b.ins().udiv(a, b) } else { self.b.ins().urem(a, b) };
+                }
+                let minus_one = self.iconst(ty, -1);
+                let by_minus_one = self.b.ins().icmp(IntCC::Equal, b, minus_one);
+                if op == BinOp::Rem {
+                    // `MIN % -1` is 0, which `x % 1` also gives
+                    let one = self.b.ins().iconst(ty, 1);
+                    let divisor = self.b.ins().select(by_minus_one, one, b);
+                    return self.b.ins().srem(a, divisor);
+                }
+                let (min, _) = integer_bounds(ty.bits(), true);
+                let min = self.iconst(ty, min);
+                let is_min = self.b.ins().icmp(IntCC::Equal, a, min);
+                let overflowed = self.b.ins().band(is_min, by_minus_one);
+                let message = format!("`{}` division overflowed", name);
+                self.trap_if(overflowed, |t| t.trap_message(&message));
+                self.b.ins().sdiv(a, b)
+            }
+            BinOp::Shl | BinOp::Shr => {
+                let amount_signed = self.c.is_signed(&rhs_type);
+                let amount = self.extend(b, amount_signed, types::I64);
+                let bits = ty.bits() as i64;
+                // A negative amount is a large unsigned one
+                let out_of_range = self.b.ins().icmp_imm(IntCC::UnsignedGreaterThanOrEqual, amount, bits);
+                self.trap_if(out_of_range, |t| {
+                    let bits = t.b.ins().iconst(types::I64, bits);
+                    let type_name = t.c_string(&name);
+                    t.call_runtime("fig_rt_shift", &[amount, bits, type_name]);
+                });
+                match (op, signed) {
+                    (BinOp::Shl, _) => self.b.ins().ishl(a, amount),
+                    (_, true) => self.b.ins().sshr(a, amount),
+                    (_, false) => self.b.ins().ushr(a, amount),
+                }
+            }
+            _ => unreachable!("comparisons are handled above"),
+        }
+    }
+
+    fn unary(&mut self, op: UnOp, operand: &Operand) -> Value {
+        let ty = self.operand_type(operand);
+        let a = self.value(operand);
+        match op {
+            UnOp::Neg if is_float(&ty) => self.b.ins().fneg(a),
+            UnOp::Neg => {
+                let scalar = self.b.func.dfg.value_type(a);
+                // Only `MIN` has no negation, and for unsigned types only 0 has one
+                let overflowed = if self.c.is_signed(&ty) {
+                    let (min, _) = integer_bounds(scalar.bits(), true);
+                    let min = self.iconst(scalar, min);
+                    self.b.ins().icmp(IntCC::Equal, a, min)
+                } else {
+                    self.b.ins().icmp_imm(IntCC::NotEqual, a, 0)
+                };
+                let message = format!("`{}` negation overflowed", format_type(&ty));
+                self.trap_if(overflowed, |t| t.trap_message(&message));
+                self.b.ins().ineg(a)
+            }
+            UnOp::Not => self.b.ins().icmp_imm(IntCC::Equal, a, 0),
+            UnOp::BitNot => self.b.ins().bnot(a),
+        }
+    }
+
+    /// The scalar type and signedness of an integer-like type: integers,
+    /// enums, `bool`, pointers and `?*T`
+    fn int_info(&mut self, ty: &Type) -> Option<(ir::Type, bool)> {
+        match self.c.repr(ty) {
+            Repr::Scalar(scalar) if scalar.is_int() => Some((scalar, self.c.is_signed(ty))),
+            _ => None,
+        }
+    }
+
+    /// Widen or truncate an integer
+    fn extend(&mut self, value: Value, signed: bool, to: ir::Type) -> Value {
+        let from = self.b.func.dfg.value_type(value);
+        match from.bits().cmp(&to.bits()) {
+            std::cmp::Ordering::Less if signed => self.b.ins().sextend(to, value),
+            std::cmp::Ordering::Less => self.b.ins().uextend(to, value),
+            std::cmp::Ordering::Greater => self.b.ins().ireduce(to, value),
+            std::cmp::Ordering::Equal => value,
+        }
+    }
+
+    fn cast(&mut self, value: Value, from: &Type, to: &Type) -> Value {
+        if from == to {
+            return value;
+        }
+        let source = self.int_info(from);
+        match to {
+            Type::Bool => match source {
+                Some(_) => self.b.ins().icmp_imm(IntCC::NotEqual, value, 0),
+                None => {
+                    let zero = self.b.ins().f64const(0.0);
+                    let value = if *from == Type::F32 { self.b.ins().fpromote(types::F64, value) } else { value };
+                    self.b.ins().fcmp(FloatCC::NotEqual, value, zero)
+                }
+            },
+            Type::F32 | Type::F64 => {
+                let float = if *to == Type::F32 { types::F32 } else { types::F64 };
+                match (source, from) {
+                    (_, Type::F32) => self.b.ins().fpromote(float, value),
+                    (_, Type::F64) => self.b.ins().fdemote(float, value),
+                    (Some((_, signed)), _) => {
+                        let wide = self.extend(value, signed, types::I64);
+                        if signed {
+                            self.b.ins().fcvt_from_sint(float, wide)
+                        } else {
+                            self.b.ins().fcvt_from_uint(float, wide)
+                        }
+                    }
+                    (None, _) => {
+                        self.error(format!("cannot cast `{}` to a float", format_type(from)));
+                        value
+                    }
+                }
+            }
+            _ => {
+                let Some((target, target_signed)) = self.int_info(to) else {
+                    self.error(format!("cannot cast to `{}`", format_type(to)));
+                    return value;
+                };
+                let result = match source {
+                    Some((_, signed)) => self.extend(value, signed, target),
+                    None if is_float(from) => self.float_to_int(value, target, target_signed),
+                    None => {
+                        self.error(format!("cannot cast `{}` to `{}`", format_type(from), format_type(to)));
+                        return value;
+                    }
+                };
+                if let Some(TypeDef::Enum(_)) = self.c.type_def(to) {
+                    self.check_discriminant(result, to);
+                }
+                result
+            }
+        }
+    }
+
+    /// Float to integer casts saturate, and NaN becomes zero
+    fn float_to_int(&mut self, value: Value, target: ir::Type, signed: bool) -> Value {
+        if target == types::I64 {
+            return if signed {
+                self.b.ins().fcvt_to_sint_sat(types::I64, value)
+            } else {
+                self.b.ins().fcvt_to_uint_sat(types::I64, value)
+            };
+        }
+        let wide = self.b.ins().fcvt_to_sint_sat(types::I64, value);
+        let (min, max) = integer_bounds(target.bits(), signed);
+        let min = self.b.ins().iconst(types::I64, min as i64);
+        let max = self.b.ins().iconst(types::I64, max as i64);
+        let clamped = self.b.ins().smax(wide, min);
+        let clamped = self.b.ins().smin(clamped, max);
+        self.b.ins().ireduce(target, clamped)
+    }
+
+    /// Trap unless `value` is one of the discriminants of the enum `ty`
+    fn check_discriminant(&mut self, value: Value, ty: &Type) {
+        let Shape::Enum { discriminants, signed } = self.c.layout(ty).shape else { return };
+        let scalar = self.b.func.dfg.value_type(value);
+        let mut valid = self.b.ins().iconst(types::I8, 0);
+        for (_, discriminant) in discriminants {
+            let discriminant = self.iconst(scalar, discriminant);
+            let equal = self.b.ins().icmp(IntCC::Equal, value, discriminant);
+            valid = self.b.ins().bor(valid, equal);
+        }
+        let invalid = self.b.ins().icmp_imm(IntCC::Equal, valid, 0);
+        let name = self.c.type_name(ty);
+        self.trap_if(invalid, |t| {
+            let value = t.extend(value, signed, types::I64);
+            let name = t.c_string(&name);
+            t.call_runtime("fig_rt_out_of_range", &[value, name]);
+        });
+    }
+
+    // ========================================================================
+    // Calls
+    // ========================================================================
+
+    fn call(&mut self, callee: &Callee, args: &[Operand]) -> Val {
+        let (name, entry) = match callee {
+            Callee::Builtin(builtin) => return self.builtin(*builtin, args),
+            Callee::Function(name) => (name, self.c.functions.get(name).cloned()),
+            Callee::Extern(name) => (name, self.c.externs.get(name).cloned()),
+        };
+        let Some((id, signature)) = entry else {
+            self.error(format!("call of unknown function `{}`", name));
+            return Val::Scalar(self.b.ins().iconst(types::I8, 0));
+        };
+        let result_type = self.c.signature_of(callee).map_or(Type::Ok, |(_, result)| result);
+        let args: Vec<(Val, Type)> =
+            args.iter().map(|arg| (self.operand(arg), self.operand_type(arg))).collect();
+        self.call_with(id, &signature, &args, &result_type)
+    }
+
+    /// Call a function as `signature` says, leaving an aggregate result on the stack
+    fn call_with(&mut self, id: FuncId, signature: &Signature, args: &[(Val, Type)], result: &Type) -> Val {
+        let mut values = Vec::with_capacity(signature.clif.params.len());
+        let result_address = match signature.result {
+            PassMode::Memory(size) => {
+                let address = self.temp(size as u64, 16);
+                values.push(address);
+                Some(address)
+            }
+            _ => None,
+        };
+        for ((value, ty), mode) in args.iter().zip(&signature.params) {
+            match mode {
+                PassMode::Scalar(_) => values.push(self.scalar(*value)),
+                PassMode::Direct(classes) => {
+                    let source = self.memory(*value);
+                    let temp = self.temp(classes.len() as u64 * 8, 8);
+                    self.copy_value(temp, source, ty);
+                    for (i, class) in classes.iter().enumerate() {
+                        values.push(self.b.ins().load(class.ty(), flags(), temp, i as i32 * 8));
+                    }
+                }
+                PassMode::Memory(size) => {
+                    // The callee's copy is made from a whole number of
+                    // eightbytes, so the value is padded out first
+                    let source = self.memory(*value);
+                    let temp = self.temp(*size as u64, 8);
+                    self.copy_value(temp, source, ty);
+                    values.push(temp);
+                }
+            }
+        }
+        let func_ref = self.func_ref(id);
+        let call = self.b.ins().call(func_ref, &values);
+        let results = self.b.inst_results(call).to_vec();
+        match (&signature.result, self.c.repr(result)) {
+            (PassMode::Memory(_), _) => Val::Memory(result_address.expect("the result pointer was passed")),
+            (PassMode::Direct(classes), Repr::Memory(..)) => {
+                let temp = self.temp(classes.len() as u64 * 8, 8);
+                for (i, value) in results.into_iter().enumerate() {
+                    self.b.ins().store(flags(), value, temp, i as i32 * 8);
+                }
+                Val::Memory(temp)
+            }
+            // A C function returning `void`
+            (_, Repr::Scalar(scalar)) => match results.first() {
+                Some(&value) => Val::Scalar(value),
+                None => Val::Scalar(self.iconst(scalar, 0)),
+            },
+            (PassMode::Scalar(_), Repr::Memory(..)) => {
+                self.error(format!("`{}` is returned as a scalar", format_type(result)));
+                Val::Scalar(results[0])
+            }
+        }
+    }
+
+    fn builtin(&mut self, builtin: Builtin, args: &[Operand]) -> Val {
+        let ok = Val::Scalar(self.b.ins().iconst(types::I8, 0));
+        match builtin {
+            Builtin::Print | Builtin::Println => {
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        self.print_byte(b' ');
+                    }
+                    let ty = self.operand_type(arg);
+                    match self.c.print_function(&ty) {
+                        Ok(id) => {
+                            let value = self.operand(arg);
+                            let address = self.spill(value);
+                            let func_ref = self.func_ref(id);
+                            self.b.ins().call(func_ref, &[address]);
+                        }
+                        Err(message) => self.error(message),
+                    }
+                }
+                if builtin == Builtin::Println {
+                    self.print_byte(b'\n');
+                }
+                ok
+            }
+            Builtin::Assert => {
+                let cond = self.value(&args[0]);
+                let failed = self.b.ins().icmp_imm(IntCC::Equal, cond, 0);
+                self.trap_if(failed, |t| t.trap_message("assertion failed"));
+                ok
+            }
+            Builtin::Malloc | Builtin::Calloc | Builtin::Realloc | Builtin::Free => {
+                let values: Vec<Value> = args.iter().map(|arg| self.value(arg)).collect();
+                let name = match builtin {
+                    Builtin::Malloc => "malloc",
+                    Builtin::Calloc => "calloc",
+                    Builtin::Realloc => "realloc",
+                    _ => "free",
+                };
+                match self.call_runtime(name, &values) {
+                    Some(pointer) => Val::Scalar(pointer),
+                    None => ok,
+                }
+            }
+        }
+    }
+
+    fn func_ref(&mut self, id: FuncId) -> FuncRef {
+        if let Some(&func_ref) = self.funcs.get(&id) {
+            return func_ref;
+        }
+        let func_ref = self.c.module.declare_func_in_func(id, self.b.func);
+        self.funcs.insert(id, func_ref);
+        func_ref
+    }
+
+    fn data_address(&mut self, id: DataId) -> Value {
+        let global = match self.data.get(&id) {
+            Some(&global) => global,
+            None => {
+                let global = self.c.module.declare_data_in_func(id, self.b.func);
+                self.data.insert(id, global);
+                global
+            }
+        };
+        self.b.ins().global_value(types::I64, global)
+    }
+
+    /// The address of a NUL-terminated copy of `text`
+    fn c_string(&mut self, text: &str) -> Value {
+        let id = self.c.string(text, false);
+        self.data_address(id)
+    }
+
+    /// Call a runtime or libc function, returning its result if it has one
+    fn call_runtime(&mut self, name: &str, args: &[Value]) -> Option<Value> {
+        let (id, _) = self.c.externs[name].clone();
+        let func_ref = self.func_ref(id);
+        let call = self.b.ins().call(func_ref, args);
+        self.b.inst_results(call).first().copied()
+    }
+
+    /// Emit `report` and a trap in a cold block taken when `cond` is true
+    fn trap_if(&mut self, cond: Value, report: impl FnOnce(&mut Self)) {
+        let trap = self.b.create_block();
+        let next = self.b.create_block();
+        self.b.set_cold_block(trap);
+        self.b.ins().brif(cond, trap, &[], next, &[]);
+        self.b.switch_to_block(trap);
+        report(self);
+        self.b.ins().trap(failed_check());
+        self.b.switch_to_block(next);
+    }
+
+    fn trap_message(&mut self, message: &str) {
+        let message = self.c_string(message);
+        self.call_runtime("fig_rt_trap", &[message]);
+    }
+
+    // ========================================================================
+    // Printing
+    // ========================================================================
+
+    fn print_str(&mut self, text: &str) {
+        let text = self.c_string(text);
+        self.call_runtime("fig_rt_print_str", &[text]);
+    }
+
+    fn print_byte(&mut self, byte: u8) {
+        let byte = self.b.ins().iconst(types::I8, byte as i64);
+        self.call_runtime("fig_rt_print_byte", &[byte]);
+    }
+
+    /// Print a value that is part of another, inline if it is a primitive
+    fn print_nested(&mut self, ty: &Type, address: Value) {
+        if matches!(self.c.repr(ty), Repr::Scalar(_)) && self.c.type_def(ty).is_none() {
+            self.print_value(ty, address);
+            return;
+        }
+        match self.c.print_function(ty) {
+            Ok(id) => {
+                let func_ref = self.func_ref(id);
+                self.b.ins().call(func_ref, &[address]);
+            }
+            Err(message) => self.error(message),
+        }
+    }
+
+    /// Print the value of type `ty` at `address` as `print` shows it
+    fn print_value(&mut self, ty: &Type, address: Value) {
+        let layout = self.c.layout(ty);
+        match ty {
+            Type::F32 | Type::F64 => {
+                let scalar = if *ty == Type::F32 { types::F32 } else { types::F64 };
+                let value = self.b.ins().load(scalar, flags(), address, 0);
+                let value = if *ty == Type::F32 { self.b.ins().fpromote(types::F64, value) } else { value };
+                let single = self.b.ins().iconst(types::I8, (*ty == Type::F32) as i64);
+                self.call_runtime("fig_rt_print_float", &[value, single]);
+            }
+            Type::Bool => {
+                let value = self.b.ins().load(types::I8, flags(), address, 0);
+                let yes = self.c_string("true");
+                let no = self.c_string("false");
+                let text = self.b.ins().select(value, yes, no);
+                self.call_runtime("fig_rt_print_str", &[text]);
+            }
+            Type::Ok => self.print_str("ok"),
+            Type::Null => self.print_str("null"),
+            Type::Pointer { .. } => {
+                let value = self.b.ins().load(types::I64, flags(), address, 0);
+                self.call_runtime("fig_rt_print_pointer", &[value]);
+            }
+            Type::Optional(inner) => {
+                let (some, null, done) = (self.b.create_block(), self.b.create_block(), self.b.create_block());
+                match layout.shape {
+                    Shape::Niche { .. } => {
+                        let pointer = self.b.ins().load(types::I64, flags(), address, 0);
+                        self.b.ins().brif(pointer, some, &[], null, &[]);
+                        self.b.switch_to_block(some);
+                        self.call_runtime("fig_rt_print_pointer", &[pointer]);
+                    }
+                    _ => {
+                        let tag = self.b.ins().load(Codegen::tag(&layout), flags(), address, 0);
+                        self.b.ins().brif(tag, some, &[], null, &[]);
+                        self.b.switch_to_block(some);
+                        let offset = self.c.offset(&layout, "some");
+                        let payload = self.offset(address, offset);
+                        self.print_nested(inner, payload);
+                    }
+                }
+                self.b.ins().jump(done, &[]);
+                self.b.switch_to_block(null);
+                self.print_str("null");
+                self.b.ins().jump(done, &[]);
+                self.b.switch_to_block(done);
+            }
+            Type::ErrorUnion { ok_type, err_type } => {
+                let (err, ok, done) = (self.b.create_block(), self.b.create_block(), self.b.create_block());
+                let tag = self.b.ins().load(Codegen::tag(&layout), flags(), address, 0);
+                self.b.ins().brif(tag, err, &[], ok, &[]);
+                self.b.switch_to_block(err);
+                self.print_str("error(");
+                let offset = self.c.offset(&layout, "err");
+                let payload = self.offset(address, offset);
+                self.print_nested(&Type::Path(err_type.clone()), payload);
+                self.print_byte(b')');
+                self.b.ins().jump(done, &[]);
+                self.b.switch_to_block(ok);
+                let offset = self.c.offset(&layout, "ok");
+                let payload = self.offset(address, offset);
+                self.print_nested(ok_type, payload);
+                self.b.ins().jump(done, &[]);
+                self.b.switch_to_block(done);
+            }
+            Type::Array { size: None, .. } => {
+                let pointer = self.b.ins().load(types::I64, flags(), address, 0);
+                let len = self.b.ins().load(types::I64, flags(), address, 8);
+                self.call_runtime("fig_rt_print_bytes", &[pointer, len]);
+            }
+            Type::Array { element_type, .. } => {
+                let Shape::Array { element, count } = &layout.shape else { return };
+                let (header, body, separator, element_block, done) = (
+                    self.b.create_block(),
+                    self.b.create_block(),
+                    self.b.create_block(),
+                    self.b.create_block(),
+                    self.b.create_block(),
+                );
+                self.print_byte(b'[');
+                let zero = self.b.ins().iconst(types::I64, 0);
+                self.b.ins().jump(header, &[zero]);
+
+                self.b.switch_to_block(header);
+                let i = self.b.append_block_param(header, types::I64);
+                let more = self.b.ins().icmp_imm(IntCC::UnsignedLessThan, i, *count as i64);
+                self.b.ins().brif(more, body, &[], done, &[]);
+
+                self.b.switch_to_block(body);
+                self.b.ins().brif(i, separator, &[], element_block, &[]);
+                self.b.switch_to_block(separator);
+                self.print_str(", ");
+                self.b.ins().jump(element_block, &[]);
+
+                self.b.switch_to_block(element_block);
+                let offset = self.b.ins().imul_imm(i, element.size as i64);
+                let element_address = self.b.ins().iadd(address, offset);
+                self.print_nested(element_type, element_address);
+                let next = self.b.ins().iadd_imm(i, 1);
+                self.b.ins().jump(header, &[next]);
+
+                self.b.switch_to_block(done);
+                self.print_byte(b']');
+            }
+            Type::Path(_) => match (self.c.type_def(ty), &layout.shape) {
+                (_, Shape::Enum { discriminants, signed }) => {
+                    let name = self.c.type_name(ty);
+                    let value = self.b.ins().load(int_type(layout.size), flags(), address, 0);
+                    let done = self.b.create_block();
+                    for (variant, discriminant) in discriminants {
+                        let (matched, next) = (self.b.create_block(), self.b.create_block());
+                        let discriminant = self.iconst(int_type(layout.size), *discriminant);
+                        let equal = self.b.ins().icmp(IntCC::Equal, value, discriminant);
+                        self.b.ins().brif(equal, matched, &[], next, &[]);
+                        self.b.switch_to_block(matched);
+                        self.print_str(&format!("{}::{}", name, variant));
+                        self.b.ins().jump(done, &[]);
+                        self.b.switch_to_block(next);
+                    }
+                    self.print_str(&format!("{}(", name));
+                    let wide = self.extend(value, *signed, types::I64);
+                    let print = if *signed { "fig_rt_print_i64" } else { "fig_rt_print_u64" };
+                    self.call_runtime(print, &[wide]);
+                    self.print_byte(b')');
+                    self.b.ins().jump(done, &[]);
+                    self.b.switch_to_block(done);
+                }
+                (Some(TypeDef::Struct(_)), _) => {
+                    let name = self.c.type_name(ty);
+                    let mut text = format!("{}(", name);
+                    for (i, (field, field_type)) in self.c.fields(ty).into_iter().enumerate() {
+                        if i > 0 {
+                            text.push_str(", ");
+                        }
+                        text.push_str(&field);
+                        text.push_str(": ");
+                        self.print_str(&std::mem::take(&mut text));
+                        let offset = self.c.offset(&layout, &field);
+                        let field_address = self.offset(address, offset);
+                        self.print_nested(&field_type, field_address);
+                    }
+                    text.push(')');
+                    self.print_str(&text);
+                }
+                (Some(TypeDef::Union(_)), _) => {
+                    let name = self.c.type_name(ty);
+                    let tag = self.b.ins().load(Codegen::tag(&layout), flags(), address, 0);
+                    let done = self.b.create_block();
+                    for (i, (variant, payload_type)) in self.c.fields(ty).into_iter().enumerate() {
+                        let (matched, next) = (self.b.create_block(), self.b.create_block());
+                        let equal = self.b.ins().icmp_imm(IntCC::Equal, tag, i as i64);
+                        self.b.ins().brif(equal, matched, &[], next, &[]);
+                        self.b.switch_to_block(matched);
+                        self.print_str(&format!("{}::{}(", name, variant));
+                        if payload_type == Type::Ok {
+                            self.print_str("ok");
+                        } else {
+                            let offset = self.c.offset(&layout, &variant);
+                            let payload = self.offset(address, offset);
+                            self.print_nested(&payload_type, payload);
+                        }
+                        self.print_byte(b')');
+                        self.b.ins().jump(done, &[]);
+                        self.b.switch_to_block(next);
+                    }
+                    self.print_str(&format!("{}(?)", name));
+                    self.b.ins().jump(done, &[]);
+                    self.b.switch_to_block(done);
+                }
+                _ => self.error(format!("cannot print a value of type `{}`", format_type(ty))),
+            },
+            _ => {
+                let Some((scalar, signed)) = self.int_info(ty) else {
+                    self.error(format!("cannot print a value of type `{}`", format_type(ty)));
+                    return;
+                };
+                let value = self.b.ins().load(scalar, flags(), address, 0);
+                let wide = self.extend(value, signed, types::I64);
+                let print = if signed { "fig_rt_print_i64" } else { "fig_rt_print_u64" };
+                self.call_runtime(print, &[wide]);
+            }
+        }
+    }
+}
+
+/// Which locals have their address taken, and so need a stack slot
+fn address_taken(body: &Body) -> Vec<bool> {
+    let mut taken = vec![false; body.locals.len()];
+    for block in &body.blocks {
+        for statement in &block.statements {
+            let (Statement::Assign(_, rvalue) | Statement::Eval(rvalue)) = statement;
+            if let Rvalue::AddressOf(place) | Rvalue::Unsize(place, _) | Rvalue::Subslice(place, ..) = rvalue {
+                // Through a pointer, the local itself is only read
+                if !place.projection.contains(&Projection::Deref) {
+                    taken[place.local.index()] = true;
+                }
+            }
+        }
+    }
+    taken
+}
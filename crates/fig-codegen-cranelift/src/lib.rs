@@ -0,0 +1,27 @@
+//! Native x86-64 backend for Fig
+//!
+//! [`ObjectEmitter`] lowers a program to MIR, compiles each body with
+//! Cranelift into a relocatable ELF object, and [`link`] links the object
+//! with a small C runtime into an executable using the system C compiler.
+//! Types are laid out by [`fig_sema::layout`] for [`TARGET`], so structs,
+//! unions and packed types match the C backend byte for byte, and every
+//! function follows the System V calling convention (see [`abi`]), so
+//! `extern func` declarations can call libc and other C code directly.
+//! Checks trap with the same messages as the other backends, and line
+//! information for debuggers is derived from statement spans.
+//!
+//! ```ignore
+//! let sf = SourceFileParser::new().parse(Lexer::new(src))?;
+//! let items = ItemTable::from_source_file(&sf);
+//! let object = ObjectEmitter::new(&items).with_debug_info("main.fig", src).emit()?;
+//! link(&object, Path::new("main"))?;
+//! ```
+
+pub mod abi;
+mod debug;
+mod emit;
+mod function;
+pub mod link;
+
+pub use emit::{ObjectEmitter, TARGET};
+pub use link::{link, runtime_source};
@@ -0,0 +1,109 @@
+//! Linking objects into executables
+//!
+//! Generated code calls a handful of runtime functions to report traps and
+//! print values. They are the C backend's runtime plus the non-inline entry
+//! points below, compiled by the system C compiler as part of the link, so
+//! messages and number formatting match the other backends exactly.
+
+use std::path::Path;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use fig_codegen_c::runtime::RUNTIME;
+
+/// The functions generated code calls, on top of the C backend's runtime.
+/// Checks only call the trap functions once they have failed.
+const SUPPORT: &str = r#"
+void fig_rt_trap(const char *message) {
+    fig_trap(message);
+}
+
+void fig_rt_index(int64_t index, uint64_t len, bool is_signed) {
+    if (is_signed) fig_index_i(index, len);
+    fig_index_u((uint64_t)index, len);
+}
+
+void fig_rt_slice(uint64_t start, uint64_t end, uint64_t len) {
+    fig_check_slice(start, end, len);
+}
+
+void fig_rt_shift(int64_t amount, int64_t bits, const char *type) {
+    fig_check_shift(amount, bits, type);
+}
+
+void fig_rt_out_of_range(int64_t value, const char *type) {
+    fig_out_of_range(value, type);
+}
+
+void fig_rt_inactive(const char *type, const char *const *variants, uint64_t variant, uint64_t active) {
+    fig_inactive(variants[variant], type, variants[active]);
+}
+
+void fig_rt_main_failed(void) {
+    fflush(stdout);
+    fputs("error: main returned an error\n", stderr);
+}
+
+void fig_rt_print_i64(int64_t value) {
+    printf("%" PRId64, value);
+}
+
+void fig_rt_print_u64(uint64_t value) {
+    printf("%" PRIu64, value);
+}
+
+void fig_rt_print_float(double value, bool single) {
+    fig_print_float(value, single);
+}
+
+void fig_rt_print_pointer(uint64_t value) {
+    printf("0x%" PRIx64, value);
+}
+
+void fig_rt_print_bytes(const uint8_t *bytes, size_t len) {
+    fwrite(bytes, 1, len, stdout);
+}
+
+void fig_rt_print_str(const char *text) {
+    fputs(text, stdout);
+}
+
+void fig_rt_print_byte(uint8_t byte) {
+    putchar(byte);
+}
+"#;
+
+/// The C source of the runtime an object is linked with
+pub fn runtime_source() -> String {
+    format!("{}{}", RUNTIME, SUPPORT)
+}
+
+/// Link an object with the runtime into an executable at `output`, using the
+/// C compiler named by `$CC`, or `cc`
+pub fn link(object: &[u8], output: &Path) -> Result<(), String> {
+    static LINKS: AtomicUsize = AtomicUsize::new(0);
+    let link = LINKS.fetch_add(1, Ordering::Relaxed);
+    let dir = std::env::temp_dir().join(format!("fig-link-{}-{}", std::process::id(), link));
+    std::fs::create_dir_all(&dir).map_err(|e| format!("cannot create {}: {}", dir.display(), e))?;
+    let object_path = dir.join("program.o");
+    let runtime_path = dir.join("runtime.c");
+    let result = (|| {
+        std::fs::write(&object_path, object).map_err(|e| format!("cannot write {}: {}", object_path.display(), e))?;
+        std::fs::write(&runtime_path, runtime_source())
+            .map_err(|e| format!("cannot write {}: {}", runtime_path.display(), e))?;
+        let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+        let status = Command::new(&cc)
+            .args(["-std=c11", "-w", "-o"])
+            .arg(output)
+            .arg(&object_path)
+            .arg(&runtime_path)
+            .output()
+            .map_err(|e| format!("cannot run `{}`: {}", cc, e))?;
+        if !status.status.success() {
+            return Err(format!("`{}` failed:\n{}", cc, String::from_utf8_lossy(&status.stderr).trim_end()));
+        }
+        Ok(())
+    })();
+    let _ = std::fs::remove_dir_all(&dir);
+    result
+}
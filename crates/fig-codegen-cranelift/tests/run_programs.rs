@@ -0,0 +1,61 @@
+// Compiles every program in tests/run/ to an x86-64 object, links it with
+// the runtime and checks the executable against the same header comments
+// the other backends' tests use:
+//
+//   // expect: <value>     the value `main` returns, printed after the output
+//   // output: <line>      one line of `print`/`println` output, in order
+//   // trap: <message>     the runtime error the program stops with
+//
+// The test is skipped when no `cc` is on the PATH or the host is not
+// x86-64.
+
+use std::path::Path;
+use std::process::Command;
+
+use fig_codegen_c::EntryPoint;
+use fig_codegen_cranelift::{ObjectEmitter, link};
+use fig_parser::{Lexer, SourceFileParser};
+use fig_sema::items::ItemTable;
+use fig_test_support::{check_programs, header};
+
+fn run(path: &Path, out_dir: &Path) -> Result<(), String> {
+    let src = std::fs::read_to_string(path).unwrap();
+    let sf = SourceFileParser::new()
+        .parse(Lexer::new(&src))
+        .map_err(|e| format!("parse error: {:?}", e))?;
+    let items = ItemTable::from_source_file(&sf);
+    let object = ObjectEmitter::new(&items)
+        .with_entry(EntryPoint::PrintResult)
+        .with_debug_info(path.to_string_lossy(), &src)
+        .emit()
+        .map_err(|diagnostics| diagnostics.iter().map(|d| d.to_string()).collect::<Vec<_>>().join("\n"))?;
+
+    let exe_path = out_dir.join(path.file_stem().unwrap());
+    link(&object, &exe_path)?;
+
+    let run = Command::new(&exe_path).output().unwrap();
+    let stdout = String::from_utf8_lossy(&run.stdout);
+    let stderr = String::from_utf8_lossy(&run.stderr);
+    let mut expected_output = header(&src, "output");
+    match (header(&src, "expect").first(), header(&src, "trap").first()) {
+        (Some(expected), None) if run.status.success() => expected_output.push(expected),
+        (None, Some(expected)) if stderr.trim_end() == format!("error: {}", expected) => {}
+        _ => return Err(format!("exited with {}, stderr {:?}", run.status, stderr)),
+    }
+    let output: Vec<&str> = stdout.lines().collect();
+    if output != expected_output {
+        return Err(format!("printed {:?}, expected {:?}", output, expected_output));
+    }
+    Ok(())
+}
+
+#[test]
+fn run_programs() {
+    if !cfg!(all(target_arch = "x86_64", target_os = "linux")) || Command::new("cc").arg("--version").output().is_err() {
+        eprintln!("skipping: no x86-64 Linux C toolchain");
+        return;
+    }
+    let out_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("run_programs");
+    std::fs::create_dir_all(&out_dir).unwrap();
+    check_programs(|path| run(path, &out_dir));
+}
@@ -0,0 +1,173 @@
+//! The allocator behind `malloc`, `calloc`, `realloc` and `free`
+//!
+//! Allocations are bumped from the heap pointer, after static data, growing
+//! memory as needed. Each block is 8-byte aligned and preceded by its size,
+//! which `realloc` uses to copy the old contents. `free` only gives memory
+//! back when the block is the most recent one, which covers the common
+//! pattern of freeing in reverse order; anything else is reclaimed with the
+//! instance.
+
+use wasm_encoder::{BlockType, Instruction, MemArg, ValType};
+
+use crate::emit::{Codegen, HEAP_POINTER, Op};
+
+use Instruction::*;
+
+fn memarg() -> MemArg {
+    MemArg { offset: 0, align: 2, memory_index: 0 }
+}
+
+fn ins<const N: usize>(instructions: [Instruction<'static>; N]) -> impl Iterator<Item = Op> {
+    instructions.into_iter().map(Op::Ins)
+}
+
+/// Return a null pointer if the value on the stack is true
+fn return_null_if() -> impl Iterator<Item = Op> {
+    ins([If(BlockType::Empty), I32Const(0), Return, End])
+}
+
+/// The size of memory in bytes, as an `i64`
+fn memory_bytes() -> impl Iterator<Item = Op> {
+    ins([MemorySize(0), I64ExtendI32U, I64Const(16), I64Shl])
+}
+
+/// Parameter and result types of an allocator function
+pub(crate) fn signature(name: &str) -> (&'static [ValType], &'static [ValType]) {
+    match name {
+        "malloc" => (&[ValType::I32], &[ValType::I32]),
+        "calloc" | "realloc" => (&[ValType::I32, ValType::I32], &[ValType::I32]),
+        _ => (&[ValType::I32], &[]),
+    }
+}
+
+/// The locals after the parameters, and the instructions, of an allocator
+/// function
+pub(crate) fn code(c: &mut Codegen, name: &str) -> (Vec<ValType>, Vec<Op>) {
+    let mut code = Vec::new();
+    let locals = match name {
+        // (size) -> address, with locals address and end
+        "malloc" => {
+            code.extend(ins([
+                GlobalGet(HEAP_POINTER),
+                I32Const(7),
+                I32Add,
+                I32Const(-8),
+                I32And,
+                I32Const(8),
+                I32Add,
+                LocalSet(1),
+                // In 64 bits, so that running out of address space is seen
+                LocalGet(1),
+                I64ExtendI32U,
+                LocalGet(0),
+                I64ExtendI32U,
+                I64Add,
+                LocalTee(2),
+            ]));
+            code.extend(memory_bytes());
+            code.extend(ins([I64GtU, If(BlockType::Empty), LocalGet(2)]));
+            code.extend(memory_bytes());
+            code.extend(ins([
+                I64Sub,
+                I64Const(0xffff),
+                I64Add,
+                I64Const(16),
+                I64ShrU,
+                I32WrapI64,
+                MemoryGrow(0),
+                I32Const(-1),
+                I32Eq,
+            ]));
+            code.extend(return_null_if());
+            code.extend(ins([
+                End,
+                LocalGet(1),
+                I32Const(8),
+                I32Sub,
+                LocalGet(0),
+                I32Store(memarg()),
+                LocalGet(2),
+                I32WrapI64,
+                GlobalSet(HEAP_POINTER),
+                LocalGet(1),
+            ]));
+            vec![ValType::I32, ValType::I64]
+        }
+        // (count, size) -> address, with locals bytes and address
+        "calloc" => {
+            let malloc = c.allocator("malloc");
+            code.extend(ins([
+                LocalGet(0),
+                I64ExtendI32U,
+                LocalGet(1),
+                I64ExtendI32U,
+                I64Mul,
+                LocalTee(2),
+                I64Const(0xffff_ffff),
+                I64GtU,
+            ]));
+            code.extend(return_null_if());
+            code.extend(ins([LocalGet(2), I32WrapI64]));
+            code.push(Op::Call(malloc));
+            code.extend(ins([LocalTee(3), I32Eqz]));
+            code.extend(return_null_if());
+            // A freed block may be handed out again, so it is cleared
+            code.extend(ins([LocalGet(3), I32Const(0), LocalGet(2), I32WrapI64, MemoryFill(0), LocalGet(3)]));
+            vec![ValType::I64, ValType::I32]
+        }
+        // (address, size) -> address, with locals new address and old size
+        "realloc" => {
+            let malloc = c.allocator("malloc");
+            code.extend(ins([LocalGet(0), I32Eqz, If(BlockType::Empty), LocalGet(1)]));
+            code.push(Op::Call(malloc));
+            code.extend(ins([Return, End, LocalGet(1)]));
+            code.push(Op::Call(malloc));
+            code.extend(ins([LocalTee(2), I32Eqz]));
+            code.extend(return_null_if());
+            // Copy the smaller of the old and new sizes
+            code.extend(ins([
+                LocalGet(2),
+                LocalGet(0),
+                LocalGet(0),
+                I32Const(8),
+                I32Sub,
+                I32Load(memarg()),
+                LocalTee(3),
+                LocalGet(1),
+                LocalGet(3),
+                LocalGet(1),
+                I32LtU,
+                Select,
+                MemoryCopy { src_mem: 0, dst_mem: 0 },
+                LocalGet(2),
+            ]));
+            vec![ValType::I32, ValType::I32]
+        }
+        // (address), rolling the heap back if the block is the last one
+        _ => {
+            code.extend(ins([
+                LocalGet(0),
+                I32Eqz,
+                If(BlockType::Empty),
+                Return,
+                End,
+                LocalGet(0),
+                LocalGet(0),
+                I32Const(8),
+                I32Sub,
+                I32Load(memarg()),
+                I32Add,
+                GlobalGet(HEAP_POINTER),
+                I32Eq,
+                If(BlockType::Empty),
+                LocalGet(0),
+                I32Const(8),
+                I32Sub,
+                GlobalSet(HEAP_POINTER),
+                End,
+            ]));
+            Vec::new()
+        }
+    };
+    (locals, code)
+}
@@ -0,0 +1,809 @@
+//! Module-level code generation: imports, signatures, static data, the
+//! generated `main` and the assembly of the binary
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use fig_codegen_c::EntryPoint;
+use fig_codegen_c::mangle;
+use fig_mir::ir::{self as mir, BasicBlock, Body, Callee, Constant, Inline, LocalDecl, Operand, Rvalue, Statement, Terminator};
+use fig_parser::ast::{Type, Visibility};
+use fig_parser::format::format_type;
+use fig_sema::diagnostics::Diagnostic;
+use fig_sema::items::{ItemTable, TypeDef};
+use fig_sema::layout::{Layout, Shape, Target};
+use fig_sema::typeck::{Builtin, TypeChecker};
+use wasm_encoder::{
+    CodeSection, ConstExpr, DataSection, EntityType, ExportKind, ExportSection, Function, FunctionSection,
+    GlobalSection, GlobalType, ImportSection, Instruction, MemorySection, MemoryType, Module, NameMap, NameSection,
+    TypeSection, ValType,
+};
+
+use crate::alloc;
+use crate::function::FunctionTranslator;
+
+/// The target whose layouts the backend uses
+pub const TARGET: Target = Target::WASM32;
+
+/// Where static data would start without a stack; nothing lives below it,
+/// so no object has the address 0
+const NULL_GUARD: u32 = 16;
+/// The shadow stack grows down from here towards `NULL_GUARD`, and static
+/// data follows it
+pub(crate) const STACK_TOP: u32 = NULL_GUARD + (1 << 20);
+/// The lowest address a frame may start at
+pub(crate) const STACK_LIMIT: u32 = NULL_GUARD;
+const PAGE_SIZE: u64 = 1 << 16;
+
+/// The mutable `i32` globals of every module
+pub(crate) const STACK_POINTER: u32 = 0;
+pub(crate) const HEAP_POINTER: u32 = 1;
+
+/// The module the runtime is imported from
+const RUNTIME_MODULE: &str = "fig";
+/// The module `extern func`s are imported from
+const EXTERN_MODULE: &str = "env";
+
+/// The functions a host provides in the `fig` module, with their parameter
+/// types; none returns a value. Only those a module calls are imported.
+///
+/// | Import | Parameters | Does |
+/// |---|---|---|
+/// | `trap` | message | stops with `message` |
+/// | `index` | index, length, whether the index is signed | stops with "index {index} is out of bounds for length {length}" |
+/// | `slice` | start, end, length | stops with "slice {start}..{end} is out of range for length {length}" |
+/// | `shift` | amount, bit width, type name | stops with "shift by {amount} is out of range for \`{type}\`" |
+/// | `out_of_range` | value, enum name | stops with "{value} does not fit in \`{enum}\`" |
+/// | `inactive` | union name, variant read, active variant | stops with "read of variant \`{variant}\` of \`{union}\`, but \`{active}\` is active" |
+/// | `main_failed` | | reports that `main` returned an error, then returns |
+/// | `print_i64`, `print_u64` | value | prints an integer |
+/// | `print_float` | value, whether it is an `f32` | prints a float as `print` shows it |
+/// | `print_pointer` | address | prints an address as hex, e.g. `0x1f4` |
+/// | `print_bytes` | address, length | prints bytes |
+/// | `print_str` | address | prints NUL-terminated bytes |
+/// | `print_byte` | byte | prints one byte |
+pub const RUNTIME: &[(&str, &[ValType])] = &[
+    ("trap", &[ValType::I32]),
+    ("index", &[ValType::I64, ValType::I64, ValType::I32]),
+    ("slice", &[ValType::I32, ValType::I32, ValType::I32]),
+    ("shift", &[ValType::I64, ValType::I64, ValType::I32]),
+    ("out_of_range", &[ValType::I64, ValType::I32]),
+    ("inactive", &[ValType::I32, ValType::I32, ValType::I32]),
+    ("main_failed", &[]),
+    ("print_i64", &[ValType::I64]),
+    ("print_u64", &[ValType::I64]),
+    ("print_float", &[ValType::F64, ValType::I32]),
+    ("print_pointer", &[ValType::I32]),
+    ("print_bytes", &[ValType::I32, ValType::I32]),
+    ("print_str", &[ValType::I32]),
+    ("print_byte", &[ValType::I32]),
+];
+
+/// The name of the generated `main`, as a MIR body
+const ENTRY: &str = "<entry>";
+
+/// The `extern` name the generated `main` calls when Fig's `main` fails,
+/// which becomes the runtime's `main_failed`
+pub(crate) const MAIN_FAILED: &str = "fig_rt_main_failed";
+
+/// Lowers the MIR of a program to a WebAssembly module
+pub struct WasmEmitter<'a> {
+    items: &'a ItemTable<'a>,
+    entry: EntryPoint,
+}
+
+impl<'a> WasmEmitter<'a> {
+    pub fn new(items: &'a ItemTable<'a>) -> Self {
+        WasmEmitter { items, entry: EntryPoint::ExitCode }
+    }
+
+    /// What the exported `main` does with Fig's `main`; with
+    /// [`EntryPoint::None`] the module has no `main` export
+    pub fn with_entry(mut self, entry: EntryPoint) -> Self {
+        self.entry = entry;
+        self
+    }
+
+    /// Lower, verify and compile every function reachable from the program's
+    /// roots, returning the bytes of a module
+    pub fn emit(&self) -> Result<Vec<u8>, Vec<Diagnostic>> {
+        let program = fig_mir::lower_program(self.items, TARGET)?;
+        let errors = fig_mir::verify(&program);
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        self.emit_program(&program)
+    }
+
+    /// Compile a verified MIR program
+    pub fn emit_program(&self, program: &mir::Program) -> Result<Vec<u8>, Vec<Diagnostic>> {
+        let mut codegen = Codegen {
+            program,
+            items: self.items,
+            tc: TypeChecker::new(self.items, TARGET),
+            defined: Vec::new(),
+            functions: HashMap::new(),
+            externs: HashMap::new(),
+            extern_order: Vec::new(),
+            runtime_used: vec![false; RUNTIME.len()],
+            allocator: HashMap::new(),
+            data: Vec::new(),
+            strings: HashMap::new(),
+            zeros: HashMap::new(),
+            variant_names: HashMap::new(),
+            prints: HashMap::new(),
+            print_queue: Vec::new(),
+            diagnostics: Vec::new(),
+        };
+        codegen.declare();
+        let entry = self.entry_body(&mut codegen);
+        for body in &program.functions {
+            codegen.define(body);
+        }
+        if let Some(entry) = &entry {
+            codegen.define(entry);
+        }
+        while let Some((id, ty)) = codegen.print_queue.pop() {
+            codegen.define_print(id, &ty);
+        }
+        if !codegen.diagnostics.is_empty() {
+            return Err(codegen.diagnostics);
+        }
+        Ok(codegen.finish())
+    }
+
+    /// The exported `main` for [`EntryPoint`], as MIR that calls Fig's `main`
+    fn entry_body(&self, codegen: &mut Codegen) -> Option<Body> {
+        if self.entry == EntryPoint::None {
+            return None;
+        }
+        let main = codegen.program.function("main")?;
+        if main.arg_count != 0 {
+            codegen.diagnostics.push(Diagnostic::error("`main` must not take parameters").in_function("main"));
+            return None;
+        }
+        let ty = main.return_type.clone();
+        let local = |ty: Type| LocalDecl { ty, name: None };
+        let exit = |code: i128| Terminator::Return(Operand::Const(Constant::Int(code, Type::I32)));
+        let call = Statement::Assign(mir::Local(0).into(), Rvalue::Call(Callee::Function("main".into()), Vec::new()));
+        let mut locals = vec![local(ty.clone())];
+        let blocks = match (self.entry, &ty) {
+            (EntryPoint::PrintResult, _) => {
+                let print = Rvalue::Call(Callee::Builtin(Builtin::Println), vec![mir::Local(0).into()]);
+                vec![BasicBlock::new(vec![call, Statement::Eval(print)], exit(0))]
+            }
+            (_, ty) if fig_sema::typeck::is_integer(ty) => {
+                locals.push(local(Type::I32));
+                let cast = Statement::Assign(mir::Local(1).into(), Rvalue::Cast(mir::Local(0).into(), Type::I32));
+                vec![BasicBlock::new(vec![call, cast], Terminator::Return(mir::Local(1).into()))]
+            }
+            (_, Type::ErrorUnion { .. }) => {
+                locals.push(local(Type::Bool));
+                let is_err = Statement::Assign(mir::Local(1).into(), Rvalue::IsErr(mir::Local(0).into()));
+                let branch =
+                    Terminator::Branch { cond: mir::Local(1).into(), then_block: mir::BlockId(1), else_block: mir::BlockId(2) };
+                let failed = Rvalue::Call(Callee::Extern(MAIN_FAILED.into()), Vec::new());
+                vec![
+                    BasicBlock::new(vec![call, is_err], branch),
+                    BasicBlock::new(vec![Statement::Eval(failed)], exit(1)),
+                    BasicBlock::new(Vec::new(), exit(0)),
+                ]
+            }
+            _ => vec![BasicBlock::new(vec![call], exit(0))],
+        };
+        let body = Body { name: ENTRY.into(), exported: true, arg_count: 0, locals, return_type: Type::I32, blocks, inline: Inline::Default };
+        codegen.declare_body(&body);
+        Some(body)
+    }
+}
+
+/// The text format of a binary module
+pub fn to_wat(module: &[u8]) -> Result<String, String> {
+    wasmprinter::print_bytes(module).map_err(|e| format!("cannot print the module: {}", e))
+}
+
+/// A function of the module, in the order of the import section for
+/// imports and of the code section for the rest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum FuncId {
+    /// An entry of [`RUNTIME`]
+    Runtime(usize),
+    /// An `extern func`, by declaration order
+    Extern(usize),
+    /// A function with a body
+    Defined(usize),
+}
+
+/// An instruction, with calls by [`FuncId`] since function indices are only
+/// known once every import is
+#[derive(Debug, Clone)]
+pub(crate) enum Op {
+    Ins(Instruction<'static>),
+    Call(FuncId),
+}
+
+/// A function's wasm signature
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Signature {
+    pub params: Vec<ValType>,
+    pub results: Vec<ValType>,
+    /// Whether the first parameter is the address the result is written to
+    pub sret: bool,
+}
+
+/// The width of a scalar in memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Scalar {
+    I8,
+    I16,
+    I32,
+    I64,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    /// The type of the wasm value holding a scalar. Narrow integers are kept
+    /// sign- or zero-extended to 32 bits.
+    pub fn val_type(self) -> ValType {
+        match self {
+            Scalar::I8 | Scalar::I16 | Scalar::I32 => ValType::I32,
+            Scalar::I64 => ValType::I64,
+            Scalar::F32 => ValType::F32,
+            Scalar::F64 => ValType::F64,
+        }
+    }
+
+    pub fn size(self) -> u64 {
+        match self {
+            Scalar::I8 => 1,
+            Scalar::I16 => 2,
+            Scalar::I32 | Scalar::F32 => 4,
+            Scalar::I64 | Scalar::F64 => 8,
+        }
+    }
+
+    pub fn bits(self) -> u32 {
+        self.size() as u32 * 8
+    }
+
+    pub fn is_int(self) -> bool {
+        !matches!(self, Scalar::F32 | Scalar::F64)
+    }
+}
+
+pub(crate) fn int_scalar(size: u64) -> Scalar {
+    match size {
+        1 => Scalar::I8,
+        2 => Scalar::I16,
+        4 => Scalar::I32,
+        _ => Scalar::I64,
+    }
+}
+
+/// How a value of some type is represented
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Repr {
+    /// In a wasm value
+    Scalar(Scalar),
+    /// In memory, with this size in bytes and alignment
+    Memory(u64, u64),
+}
+
+/// A function with a body
+struct Defined {
+    name: String,
+    export: Option<String>,
+    signature: Rc<Signature>,
+    /// The declared locals after the parameters, and the instructions
+    code: Option<(Vec<ValType>, Vec<Op>)>,
+}
+
+/// State shared by every function of one module
+pub(crate) struct Codegen<'p, 'a> {
+    pub program: &'p mir::Program,
+    pub items: &'a ItemTable<'a>,
+    pub tc: TypeChecker<'a>,
+    defined: Vec<Defined>,
+    /// Function instances and the generated `main`, by MIR name
+    pub functions: HashMap<String, (FuncId, Rc<Signature>)>,
+    /// `extern` functions, by name
+    pub externs: HashMap<String, (FuncId, Rc<Signature>)>,
+    extern_order: Vec<(String, Rc<Signature>)>,
+    runtime_used: Vec<bool>,
+    /// The allocator functions declared so far
+    allocator: HashMap<&'static str, FuncId>,
+    /// Static data, placed at `STACK_TOP`
+    data: Vec<u8>,
+    /// NUL-terminated string bytes, and `[u8]` slices over them
+    strings: HashMap<(String, bool), u32>,
+    /// All-zero blocks by size, the value of a tagged `null`
+    zeros: HashMap<u64, u32>,
+    /// Tables of a union's variant names, for inactive variant traps
+    variant_names: HashMap<String, u32>,
+    /// The function printing each type, by its formatted name
+    prints: HashMap<String, FuncId>,
+    pub print_queue: Vec<(FuncId, Type)>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Codegen<'_, 'a> {
+    pub fn error(&mut self, function: &str, message: impl Into<String>) {
+        self.diagnostics.push(Diagnostic::error(message).in_function(function));
+    }
+
+    /// Declare every function instance and `extern` function
+    fn declare(&mut self) {
+        let program = self.program;
+        for body in &program.functions {
+            self.declare_body(body);
+        }
+        for decl in &program.externs {
+            let params: Vec<Type> = decl.params.clone();
+            // A C function returning `void` is an `extern` returning `ok`
+            let result = (decl.return_type != Type::Ok).then_some(&decl.return_type);
+            let signature = Rc::new(self.signature(&params, result));
+            let id = FuncId::Extern(self.extern_order.len());
+            self.extern_order.push((decl.name.clone(), signature.clone()));
+            self.externs.insert(decl.name.clone(), (id, signature));
+        }
+    }
+
+    fn declare_body(&mut self, body: &Body) {
+        let params: Vec<Type> = body.params().map(|local| body.local(local).ty.clone()).collect();
+        let signature = Rc::new(self.signature(&params, Some(&body.return_type)));
+        let export = self.export_name(body);
+        let id = self.declare_function(&body.name, export, signature.clone());
+        self.functions.insert(body.name.clone(), (id, signature));
+    }
+
+    fn declare_function(&mut self, name: &str, export: Option<String>, signature: Rc<Signature>) -> FuncId {
+        self.defined.push(Defined { name: name.to_string(), export, signature, code: None });
+        FuncId::Defined(self.defined.len() - 1)
+    }
+
+    /// The export name of a body: C names for `export` functions, so C code
+    /// built for wasm can call them, `fig_main` for Fig's `main`, and `main`
+    /// for the generated one
+    fn export_name(&self, body: &Body) -> Option<String> {
+        if body.name == ENTRY {
+            return Some("main".to_string());
+        }
+        // Generic instances have no C name to be called by
+        if body.name.contains('[') {
+            return None;
+        }
+        let def = self.items.functions().iter().find(|f| f.body.is_some() && f.qualified_name() == body.name)?;
+        if def.signature.visibility != Visibility::Export {
+            return None;
+        }
+        Some(if body.name == "main" { "fig_main".to_string() } else { mangle::qualified(&body.name) })
+    }
+
+    /// Scalars are passed as themselves and aggregates as the address of a
+    /// copy; an aggregate result is written through a leading hidden pointer
+    fn signature(&mut self, params: &[Type], result: Option<&Type>) -> Signature {
+        let mut signature = Signature { params: Vec::new(), results: Vec::new(), sret: false };
+        match result.map(|ty| self.repr(ty)) {
+            Some(Repr::Scalar(scalar)) => signature.results.push(scalar.val_type()),
+            Some(Repr::Memory(..)) => {
+                signature.params.push(ValType::I32);
+                signature.sret = true;
+            }
+            None => {}
+        }
+        for ty in params {
+            signature.params.push(match self.repr(ty) {
+                Repr::Scalar(scalar) => scalar.val_type(),
+                Repr::Memory(..) => ValType::I32,
+            });
+        }
+        signature
+    }
+
+    fn define(&mut self, body: &Body) {
+        let Some((FuncId::Defined(index), signature)) = self.functions.get(&body.name).cloned() else { return };
+        let code = FunctionTranslator::translate(self, body, &signature);
+        self.defined[index].code = Some(code);
+    }
+
+    fn define_print(&mut self, id: FuncId, ty: &Type) {
+        let FuncId::Defined(index) = id else { return };
+        let code = FunctionTranslator::translate_print(self, ty);
+        self.defined[index].code = Some(code);
+    }
+
+    /// A runtime import, marking it as used
+    pub fn runtime(&mut self, name: &str) -> FuncId {
+        let index = RUNTIME.iter().position(|(import, _)| *import == name).expect("a runtime function");
+        self.runtime_used[index] = true;
+        FuncId::Runtime(index)
+    }
+
+    /// `malloc`, `calloc`, `realloc` or `free`, defined the first time it is asked for
+    pub fn allocator(&mut self, name: &'static str) -> FuncId {
+        if let Some(&id) = self.allocator.get(name) {
+            return id;
+        }
+        let (params, results) = alloc::signature(name);
+        let signature = Rc::new(Signature { params: params.to_vec(), results: results.to_vec(), sret: false });
+        let id = self.declare_function(name, None, signature);
+        self.allocator.insert(name, id);
+        let code = alloc::code(self, name);
+        if let FuncId::Defined(index) = id {
+            self.defined[index].code = Some(code);
+        }
+        id
+    }
+
+    /// Assemble the module, now that every function is defined
+    fn finish(self) -> Vec<u8> {
+        let mut runtime_index = vec![0; RUNTIME.len()];
+        let mut imported = 0;
+        for (index, _) in RUNTIME.iter().enumerate().filter(|(index, _)| self.runtime_used[*index]) {
+            runtime_index[index] = imported;
+            imported += 1;
+        }
+        let extern_base = imported;
+        let defined_base = extern_base + self.extern_order.len() as u32;
+        let function_index = |id: FuncId| match id {
+            FuncId::Runtime(index) => runtime_index[index],
+            FuncId::Extern(index) => extern_base + index as u32,
+            FuncId::Defined(index) => defined_base + index as u32,
+        };
+
+        let mut type_indices: HashMap<(Vec<ValType>, Vec<ValType>), u32> = HashMap::new();
+        let mut types = TypeSection::new();
+        let mut type_index = |params: &[ValType], results: &[ValType]| {
+            let next = type_indices.len() as u32;
+            *type_indices.entry((params.to_vec(), results.to_vec())).or_insert_with(|| {
+                types.ty().function(params.iter().copied(), results.iter().copied());
+                next
+            })
+        };
+
+        let mut names = NameMap::new();
+        let mut imports = ImportSection::new();
+        for (index, (name, params)) in RUNTIME.iter().enumerate().filter(|(index, _)| self.runtime_used[*index]) {
+            imports.import(RUNTIME_MODULE, name, EntityType::Function(type_index(params, &[])));
+            names.append(runtime_index[index], name);
+        }
+        for (i, (name, signature)) in self.extern_order.iter().enumerate() {
+            let ty = type_index(&signature.params, &signature.results);
+            imports.import(EXTERN_MODULE, name, EntityType::Function(ty));
+            names.append(extern_base + i as u32, name);
+        }
+
+        let mut functions = FunctionSection::new();
+        let mut exports = ExportSection::new();
+        let mut code = CodeSection::new();
+        exports.export("memory", ExportKind::Memory, 0);
+        for (i, defined) in self.defined.iter().enumerate() {
+            let index = defined_base + i as u32;
+            functions.function(type_index(&defined.signature.params, &defined.signature.results));
+            if let Some(export) = &defined.export {
+                exports.export(export, ExportKind::Func, index);
+            }
+            names.append(index, &defined.name);
+            let (locals, ops) = defined.code.as_ref().expect("every declared function is defined");
+            let mut function = Function::new(locals.iter().map(|&ty| (1, ty)));
+            for op in ops {
+                match op {
+                    Op::Ins(instruction) => function.instruction(instruction),
+                    Op::Call(id) => function.instruction(&Instruction::Call(function_index(*id))),
+                };
+            }
+            function.instruction(&Instruction::End);
+            code.function(&function);
+        }
+
+        let heap_start = (STACK_TOP as u64 + self.data.len() as u64).div_ceil(16) * 16;
+        let mut memories = MemorySection::new();
+        memories.memory(MemoryType {
+            minimum: heap_start.div_ceil(PAGE_SIZE).max(1),
+            maximum: None,
+            memory64: false,
+            shared: false,
+            page_size_log2: None,
+        });
+        let mut globals = GlobalSection::new();
+        let global = GlobalType { val_type: ValType::I32, mutable: true, shared: false };
+        globals.global(global, &ConstExpr::i32_const(STACK_TOP as i32));
+        globals.global(global, &ConstExpr::i32_const(heap_start as i32));
+        let mut data = DataSection::new();
+        if !self.data.is_empty() {
+            data.active(0, &ConstExpr::i32_const(STACK_TOP as i32), self.data.iter().copied());
+        }
+        let mut name_section = NameSection::new();
+        name_section.functions(&names);
+
+        let mut module = Module::new();
+        module.section(&types);
+        module.section(&imports);
+        module.section(&functions);
+        module.section(&memories);
+        module.section(&globals);
+        module.section(&exports);
+        module.section(&code);
+        module.section(&data);
+        module.section(&name_section);
+        module.finish()
+    }
+
+    // ========================================================================
+    // Types
+    // ========================================================================
+
+    pub fn layout(&mut self, ty: &Type) -> Layout {
+        match self.tc.layout().layout_of(ty) {
+            Ok(layout) => layout,
+            Err(e) => {
+                self.diagnostics.push(Diagnostic::error(e.to_string()));
+                Layout { size: 0, align: 1, shape: Shape::Scalar { non_null: false } }
+            }
+        }
+    }
+
+    pub fn repr(&mut self, ty: &Type) -> Repr {
+        let scalar = match ty {
+            Type::Bool | Type::U8 | Type::I8 | Type::Ok | Type::Null => Scalar::I8,
+            Type::U16 | Type::I16 => Scalar::I16,
+            Type::U32 | Type::I32 | Type::USize | Type::ISize | Type::Pointer { .. } => Scalar::I32,
+            Type::U64 | Type::I64 => Scalar::I64,
+            Type::F32 => Scalar::F32,
+            Type::F64 => Scalar::F64,
+            _ => {
+                let layout = self.layout(ty);
+                return match layout.shape {
+                    Shape::Enum { .. } => Repr::Scalar(int_scalar(layout.size)),
+                    Shape::Niche { .. } => Repr::Scalar(Scalar::I32),
+                    _ => Repr::Memory(layout.size, layout.align),
+                };
+            }
+        };
+        Repr::Scalar(scalar)
+    }
+
+    /// Whether a scalar of `ty` is a signed integer or enum discriminant
+    pub fn is_signed(&mut self, ty: &Type) -> bool {
+        match TARGET.integer_info(ty) {
+            Some((_, signed)) => signed,
+            None => matches!(self.type_def(ty), Some(TypeDef::Enum(_)))
+                && matches!(self.layout(ty).shape, Shape::Enum { signed: true, .. }),
+        }
+    }
+
+    pub fn type_def(&self, ty: &Type) -> Option<TypeDef<'a>> {
+        match ty {
+            Type::Path(path) => self.items.lookup_type(path),
+            _ => None,
+        }
+    }
+
+    /// The declared name of a named type, without generic arguments
+    pub fn type_name(&self, ty: &Type) -> String {
+        self.type_def(ty).map_or_else(|| format_type(ty), |def| def.name().to_string())
+    }
+
+    /// Field or variant names and types of a struct or union
+    pub fn fields(&mut self, ty: &Type) -> Vec<(String, Type)> {
+        match self.tc.fields(ty) {
+            Ok(fields) => fields,
+            Err(message) => {
+                self.diagnostics.push(Diagnostic::error(message));
+                Vec::new()
+            }
+        }
+    }
+
+    /// Offset of a named field or variant payload in a layout
+    pub fn offset(&mut self, layout: &Layout, name: &str) -> u64 {
+        let offset = layout.field(name).map(|field| field.offset);
+        offset.unwrap_or_else(|| {
+            self.diagnostics.push(Diagnostic::error(format!("no field `{}` in layout", name)));
+            0
+        })
+    }
+
+    /// The width of the tag of a tagged layout
+    pub fn tag(layout: &Layout) -> Scalar {
+        match layout.shape {
+            Shape::Tagged { tag_size, .. } => int_scalar(tag_size),
+            _ => Scalar::I8,
+        }
+    }
+
+    /// Parameter and return types of a callee, including the runtime's
+    pub fn signature_of(&self, callee: &Callee) -> Option<(Vec<Type>, Type)> {
+        match callee {
+            Callee::Extern(name) if name == MAIN_FAILED => Some((Vec::new(), Type::Ok)),
+            _ => self.program.signature(callee),
+        }
+    }
+
+    // ========================================================================
+    // Static data
+    // ========================================================================
+
+    /// Place bytes in the data segment, returning their address
+    fn data(&mut self, bytes: &[u8], align: u64) -> u32 {
+        let offset = (self.data.len() as u64).div_ceil(align) * align;
+        self.data.resize(offset as usize, 0);
+        self.data.extend_from_slice(bytes);
+        STACK_TOP + offset as u32
+    }
+
+    /// The address of a string's bytes, NUL-terminated for C, or of a `[u8]`
+    /// slice over them
+    pub fn string(&mut self, text: &str, slice: bool) -> u32 {
+        if let Some(&address) = self.strings.get(&(text.to_string(), slice)) {
+            return address;
+        }
+        let address = if slice {
+            let bytes = self.string(text, false);
+            let mut contents = bytes.to_le_bytes().to_vec();
+            contents.extend_from_slice(&(text.len() as u32).to_le_bytes());
+            self.data(&contents, 4)
+        } else {
+            let mut bytes = text.as_bytes().to_vec();
+            bytes.push(0);
+            self.data(&bytes, 1)
+        };
+        self.strings.insert((text.to_string(), slice), address);
+        address
+    }
+
+    pub fn zeros(&mut self, size: u64) -> u32 {
+        if let Some(&address) = self.zeros.get(&size) {
+            return address;
+        }
+        let address = self.data(&vec![0; size.max(1) as usize], 16);
+        self.zeros.insert(size, address);
+        address
+    }
+
+    /// The address of an array of pointers to the names of a union's
+    /// variants, in tag order
+    pub fn variant_names(&mut self, ty: &Type) -> u32 {
+        let key = format_type(ty);
+        if let Some(&address) = self.variant_names.get(&key) {
+            return address;
+        }
+        let names: Vec<u32> = self.fields(ty).into_iter().map(|(name, _)| self.string(&name, false)).collect();
+        let table: Vec<u8> = names.iter().flat_map(|address| address.to_le_bytes()).collect();
+        let address = self.data(&table, 4);
+        self.variant_names.insert(key, address);
+        address
+    }
+
+    // ========================================================================
+    // Printing
+    // ========================================================================
+
+    /// The function printing a value of `ty` given its address, declared the
+    /// first time it is asked for and defined once the bodies are done
+    pub fn print_function(&mut self, ty: &Type) -> Result<FuncId, String> {
+        let key = format_type(ty);
+        if let Some(&id) = self.prints.get(&key) {
+            return Ok(id);
+        }
+        self.check_printable(ty)?;
+        let signature = Rc::new(Signature { params: vec![ValType::I32], results: Vec::new(), sret: false });
+        let id = self.declare_function(&format!("print[{}]", key), None, signature);
+        self.prints.insert(key, id);
+        self.print_queue.push((id, ty.clone()));
+        Ok(id)
+    }
+
+    fn check_printable(&mut self, ty: &Type) -> Result<(), String> {
+        let unprintable = || Err(format!("cannot print a value of type `{}`", format_type(ty)));
+        match ty {
+            Type::Optional(inner) => match self.layout(ty).shape {
+                Shape::Niche { .. } => Ok(()),
+                _ => self.check_printable(inner),
+            },
+            Type::ErrorUnion { ok_type, err_type } => {
+                self.check_printable(ok_type)?;
+                self.check_printable(&Type::Path(err_type.clone()))
+            }
+            Type::Array { element_type, size: None } if **element_type == Type::U8 => Ok(()),
+            Type::Array { size: None, .. } => unprintable(),
+            Type::Array { element_type, .. } => self.check_printable(element_type),
+            Type::Path(_) => match self.type_def(ty) {
+                Some(TypeDef::Enum(_)) => Ok(()),
+                Some(TypeDef::Struct(_) | TypeDef::Union(_)) => {
+                    for (_, field_type) in self.fields(ty) {
+                        if field_type != Type::Ok {
+                            self.check_printable(&field_type)?;
+                        }
+                    }
+                    Ok(())
+                }
+                _ => unprintable(),
+            },
+            _ if matches!(self.repr(ty), Repr::Scalar(_)) => Ok(()),
+            _ => unprintable(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fig_parser::{Lexer, SourceFileParser};
+    use wasmparser::{ExternalKind, Parser, Payload, TypeRef};
+
+    use super::*;
+
+    fn emit(src: &str, entry: EntryPoint) -> Vec<u8> {
+        let sf = SourceFileParser::new().parse(Lexer::new(src)).unwrap();
+        let items = ItemTable::from_source_file(&sf);
+        WasmEmitter::new(&items).with_entry(entry).emit().unwrap_or_else(|diags| panic!("{:?}", diags))
+    }
+
+    /// The (module, name) of each function import and the name of each
+    /// export, all functions but the memory
+    fn interface(module: &[u8]) -> (Vec<(String, String)>, Vec<String>) {
+        let (mut imports, mut exports) = (Vec::new(), Vec::new());
+        for payload in Parser::new(0).parse_all(module) {
+            match payload.unwrap() {
+                Payload::ImportSection(section) => {
+                    for import in section {
+                        let import = import.unwrap();
+                        assert!(matches!(import.ty, TypeRef::Func(_)));
+                        imports.push((import.module.to_string(), import.name.to_string()));
+                    }
+                }
+                Payload::ExportSection(section) => {
+                    for export in section {
+                        let export = export.unwrap();
+                        let kind = if export.name == "memory" { ExternalKind::Memory } else { ExternalKind::Func };
+                        assert_eq!(export.kind, kind);
+                        exports.push(export.name.to_string());
+                    }
+                }
+                _ => {}
+            }
+        }
+        (imports, exports)
+    }
+
+    const SRC: &str = "\
+extern func! record(value: i64) -> ok
+
+export func scale(x: i32, factor: i32) -> i32
+    return x * factor
+
+func helper(x: i32) -> i32
+    return x + 1
+
+func! main() -> i32
+    record(40)
+    return helper(scale(7, 6))
+";
+
+    #[test]
+    fn test_maps_visibility_to_exports_and_externs_to_imports() {
+        let module = emit(SRC, EntryPoint::ExitCode);
+        wasmparser::Validator::new().validate_all(&module).unwrap();
+        let (imports, exports) = interface(&module);
+        // Only the runtime functions the code calls are imported
+        assert_eq!(imports, vec![("fig".to_string(), "trap".to_string()), ("env".to_string(), "record".to_string())]);
+        assert_eq!(exports, vec!["memory", "scale", "main"]);
+    }
+
+    #[test]
+    fn test_without_an_entry_point_main_is_not_exported() {
+        let module = emit(SRC, EntryPoint::None);
+        let (_, exports) = interface(&module);
+        assert_eq!(exports, vec!["memory", "scale"]);
+    }
+
+    #[test]
+    fn test_prints_the_text_format() {
+        let wat = to_wat(&emit(SRC, EntryPoint::ExitCode)).unwrap();
+        assert!(wat.contains("(import \"env\" \"record\" (func $record"), "{}", wat);
+        assert!(wat.contains("(export \"scale\" (func $scale))"), "{}", wat);
+        assert!(wat.contains("(func $helper"), "{}", wat);
+        assert!(to_wat(b"not a module").is_err());
+    }
+}
@@ -0,0 +1,1844 @@
+//! Translation of one MIR body to a wasm function
+//!
+//! Scalar locals whose address is never taken become wasm locals; aggregates
+//! and address-taken scalars get a slot in the function's frame on the
+//! shadow stack. Every intermediate value is a wasm local too, so the operand
+//! stack is empty between instructions the translator emits; the locals of a
+//! statement are reused by the next.
+//!
+//! Wasm only has structured control flow, so the blocks of a body become a
+//! dispatch loop. A `br_table` on the number of the next block breaks out of
+//! as many nested `block`s as it takes to reach that block's code, and a
+//! jump sets the number and branches back to the loop, or falls into the
+//! next block directly:
+//!
+//! ```text
+//! loop
+//!   block ... block        ;; one per MIR block
+//!     br_table pc
+//!   end
+//!   bb0 code
+//!   end
+//!   bb1 code
+//!   ...
+//! end
+//! ```
+
+use std::collections::HashMap;
+
+use fig_mir::ir::{
+    AggregateKind, BinOp, BlockId, Body, Callee, Constant, Inline, Operand, Place, Projection, Rvalue, Statement,
+    Terminator, UnOp,
+};
+use fig_parser::ast::Type;
+use fig_parser::format::format_type;
+use fig_sema::items::TypeDef;
+use fig_sema::layout::{Shape, integer_bounds, wrap_integer};
+use fig_sema::propagation::ErrorConversion;
+use fig_sema::typeck::{Builtin, is_float};
+use wasm_encoder::{BlockType, Instruction, MemArg, ValType};
+
+use crate::emit::{Codegen, FuncId, MAIN_FAILED, Op, Repr, STACK_LIMIT, STACK_POINTER, Scalar, Signature};
+
+use Instruction::*;
+
+/// A wasm local holding a value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Value(u32);
+
+/// Where a local lives
+#[derive(Debug, Clone, Copy)]
+enum Storage {
+    Var(Value),
+    /// At this offset in the frame
+    Frame(u64),
+    /// At the address in a parameter, for aggregates passed by pointer
+    Indirect(Value),
+}
+
+/// A translated place
+#[derive(Debug, Clone, Copy)]
+enum Loc {
+    Var(Value),
+    Addr(Value),
+}
+
+/// A translated operand: a scalar, or the address of an aggregate
+#[derive(Debug, Clone, Copy)]
+enum Val {
+    Scalar(Value),
+    Memory(Value),
+}
+
+/// What a place is computed for, which decides how union variants are checked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Access {
+    /// Trap unless each variant on the path is active
+    Read,
+    /// Make the variants on the path active
+    Write,
+    /// Neither, for `&place`
+    Address,
+}
+
+fn memarg(offset: u64) -> MemArg {
+    // Fields of packed structs may be unaligned
+    MemArg { offset, align: 0, memory_index: 0 }
+}
+
+/// The `i32` or `i64` form of an integer instruction
+fn pick(scalar: Scalar, i32: Instruction<'static>, i64: Instruction<'static>) -> Instruction<'static> {
+    if scalar.val_type() == ValType::I64 { i64 } else { i32 }
+}
+
+pub(crate) struct FunctionTranslator<'t, 'p, 'a> {
+    c: &'t mut Codegen<'p, 'a>,
+    body: &'t Body,
+    code: Vec<Op>,
+    param_count: u32,
+    /// The types of the wasm locals after the parameters
+    locals: Vec<ValType>,
+    /// Locals made for the current statement, and those free for reuse
+    scratch: Vec<(ValType, Value)>,
+    free: HashMap<ValType, Vec<Value>>,
+    storage: Vec<Storage>,
+    frame_size: u64,
+    /// The frame's address, and the stack pointer to restore on return
+    fp: Value,
+    saved: Value,
+    /// The number of the block to run next
+    pc: Value,
+    /// The hidden result pointer
+    sret: Option<Value>,
+    /// The MIR block being translated, and how deep in structured
+    /// instructions of its own the code being emitted is
+    current: usize,
+    depth: u32,
+}
+
+impl<'t, 'p, 'a> FunctionTranslator<'t, 'p, 'a> {
+    fn new(c: &'t mut Codegen<'p, 'a>, body: &'t Body, param_count: u32) -> Self {
+        let mut t = FunctionTranslator {
+            c,
+            body,
+            code: Vec::new(),
+            param_count,
+            locals: Vec::new(),
+            scratch: Vec::new(),
+            free: HashMap::new(),
+            storage: Vec::new(),
+            frame_size: 0,
+            fp: Value(0),
+            saved: Value(0),
+            pc: Value(0),
+            sret: None,
+            current: 0,
+            depth: 0,
+        };
+        t.fp = t.var(ValType::I32);
+        t.saved = t.var(ValType::I32);
+        t
+    }
+
+    /// Translate a body with this signature, returning its declared locals
+    /// and instructions
+    pub fn translate(c: &'t mut Codegen<'p, 'a>, body: &'t Body, signature: &Signature) -> (Vec<ValType>, Vec<Op>) {
+        let mut t = FunctionTranslator::new(c, body, signature.params.len() as u32);
+        if signature.sret {
+            t.sret = Some(Value(0));
+        }
+        t.body();
+        t.finish()
+    }
+
+    /// Translate the function printing a value of `ty` at the address it is passed
+    pub fn translate_print(c: &'t mut Codegen<'p, 'a>, ty: &Type) -> (Vec<ValType>, Vec<Op>) {
+        let body = Body {
+            name: format!("print[{}]", format_type(ty)),
+            exported: false,
+            arg_count: 0,
+            locals: Vec::new(),
+            return_type: Type::Ok,
+            blocks: Vec::new(),
+            inline: Inline::Default,
+        };
+        let mut t = FunctionTranslator::new(c, &body, 1);
+        t.print_value(ty, Value(0));
+        t.restore_stack();
+        t.finish()
+    }
+
+    /// Prepend the prologue, which claims the frame, now that its size is known
+    fn finish(mut self) -> (Vec<ValType>, Vec<Op>) {
+        let body = std::mem::take(&mut self.code);
+        let frame_size = self.frame_size.div_ceil(16) * 16;
+        self.emit(GlobalGet(STACK_POINTER));
+        self.emit(LocalTee(self.saved.0));
+        if frame_size == 0 {
+            self.emit(LocalSet(self.fp.0));
+        } else {
+            self.emit(I32Const(frame_size as i32));
+            self.emit(I32Sub);
+            self.emit(LocalTee(self.fp.0));
+            self.emit(I32Const(STACK_LIMIT as i32));
+            self.emit(I32LtS);
+            self.emit(If(BlockType::Empty));
+            self.trap_message("call stack overflow");
+            self.emit(Unreachable);
+            self.emit(End);
+            self.get(self.fp);
+            self.emit(GlobalSet(STACK_POINTER));
+        }
+        self.code.extend(body);
+        (self.locals, self.code)
+    }
+
+    fn error(&mut self, message: impl Into<String>) {
+        let name = self.body.name.clone();
+        self.c.error(&name, message);
+    }
+
+    fn place_type(&self, place: &Place) -> Type {
+        self.body.place_type(place).unwrap_or(Type::Ok)
+    }
+
+    fn operand_type(&self, operand: &Operand) -> Type {
+        self.body.operand_type(operand).unwrap_or(Type::Ok)
+    }
+
+    // ========================================================================
+    // Locals and instructions
+    // ========================================================================
+
+    fn emit(&mut self, instruction: Instruction<'static>) {
+        self.code.push(Op::Ins(instruction));
+    }
+
+    fn call_id(&mut self, id: FuncId) {
+        self.code.push(Op::Call(id));
+    }
+
+    /// A local for the whole function
+    fn var(&mut self, ty: ValType) -> Value {
+        self.locals.push(ty);
+        Value(self.param_count + self.locals.len() as u32 - 1)
+    }
+
+    /// A local for the current statement
+    fn scratch(&mut self, ty: ValType) -> Value {
+        let value = match self.free.get_mut(&ty).and_then(Vec::pop) {
+            Some(value) => value,
+            None => self.var(ty),
+        };
+        self.scratch.push((ty, value));
+        value
+    }
+
+    /// Make the locals of the statement just translated free for reuse
+    fn release(&mut self) {
+        for (ty, value) in self.scratch.drain(..) {
+            self.free.entry(ty).or_default().push(value);
+        }
+    }
+
+    fn get(&mut self, value: Value) {
+        self.emit(LocalGet(value.0));
+    }
+
+    /// Pop the value on the stack into a new local
+    fn set(&mut self, ty: ValType) -> Value {
+        let value = self.scratch(ty);
+        self.emit(LocalSet(value.0));
+        value
+    }
+
+    fn op1(&mut self, instruction: Instruction<'static>, a: Value, result: ValType) -> Value {
+        self.get(a);
+        self.emit(instruction);
+        self.set(result)
+    }
+
+    fn op2(&mut self, instruction: Instruction<'static>, a: Value, b: Value, result: ValType) -> Value {
+        self.get(a);
+        self.get(b);
+        self.emit(instruction);
+        self.set(result)
+    }
+
+    /// An integer constant, wrapped to the width of `scalar`
+    fn int(&mut self, scalar: Scalar, signed: bool, value: i128) -> Value {
+        let value = wrap_integer(value, scalar.bits(), signed);
+        match scalar.val_type() {
+            ValType::I64 => self.emit(I64Const(value as i64)),
+            _ => self.emit(I32Const(value as i32)),
+        }
+        self.set(scalar.val_type())
+    }
+
+    fn i32(&mut self, value: i32) -> Value {
+        self.emit(I32Const(value));
+        self.set(ValType::I32)
+    }
+
+    fn i64(&mut self, value: i64) -> Value {
+        self.emit(I64Const(value));
+        self.set(ValType::I64)
+    }
+
+    /// Whether an integer is not zero, as an `i32` condition
+    fn nonzero(&mut self, value: Value, scalar: Scalar) -> Value {
+        match scalar.val_type() {
+            ValType::I64 => {
+                let zero = self.i64(0);
+                self.op2(I64Ne, value, zero, ValType::I32)
+            }
+            _ => value,
+        }
+    }
+
+    fn eqz(&mut self, value: Value, scalar: Scalar) -> Value {
+        self.op1(pick(scalar, I32Eqz, I64Eqz), value, ValType::I32)
+    }
+
+    fn eq_const(&mut self, value: Value, scalar: Scalar, signed: bool, constant: i128) -> Value {
+        let constant = self.int(scalar, signed, constant);
+        self.op2(pick(scalar, I32Eq, I64Eq), value, constant, ValType::I32)
+    }
+
+    /// Sign- or zero-extend the low bits of a narrow integer
+    fn normalize(&mut self, value: Value, scalar: Scalar, signed: bool) -> Value {
+        match (scalar, signed) {
+            (Scalar::I8, true) => self.op1(I32Extend8S, value, ValType::I32),
+            (Scalar::I16, true) => self.op1(I32Extend16S, value, ValType::I32),
+            (Scalar::I8 | Scalar::I16, false) => {
+                let mask = self.i32(if scalar == Scalar::I8 { 0xff } else { 0xffff });
+                self.op2(I32And, value, mask, ValType::I32)
+            }
+            _ => value,
+        }
+    }
+
+    /// Convert an integer from one width and signedness to another, wrapping
+    fn convert(&mut self, value: Value, from: Scalar, from_signed: bool, to: Scalar, to_signed: bool) -> Value {
+        match (from.val_type(), to.val_type()) {
+            (ValType::I64, ValType::I64) => value,
+            (ValType::I64, _) => {
+                let low = self.op1(I32WrapI64, value, ValType::I32);
+                self.normalize(low, to, to_signed)
+            }
+            (_, ValType::I64) if from_signed => self.op1(I64ExtendI32S, value, ValType::I64),
+            (_, ValType::I64) => self.op1(I64ExtendI32U, value, ValType::I64),
+            _ => self.normalize(value, to, to_signed),
+        }
+    }
+
+    fn load(&mut self, scalar: Scalar, signed: bool, address: Value, offset: u64) -> Value {
+        let memarg = memarg(offset);
+        self.get(address);
+        self.emit(match (scalar, signed) {
+            (Scalar::I8, true) => I32Load8S(memarg),
+            (Scalar::I8, false) => I32Load8U(memarg),
+            (Scalar::I16, true) => I32Load16S(memarg),
+            (Scalar::I16, false) => I32Load16U(memarg),
+            (Scalar::I32, _) => I32Load(memarg),
+            (Scalar::I64, _) => I64Load(memarg),
+            (Scalar::F32, _) => F32Load(memarg),
+            (Scalar::F64, _) => F64Load(memarg),
+        });
+        self.set(scalar.val_type())
+    }
+
+    fn store_scalar(&mut self, scalar: Scalar, address: Value, offset: u64, value: Value) {
+        let memarg = memarg(offset);
+        self.get(address);
+        self.get(value);
+        self.emit(match scalar {
+            Scalar::I8 => I32Store8(memarg),
+            Scalar::I16 => I32Store16(memarg),
+            Scalar::I32 => I32Store(memarg),
+            Scalar::I64 => I64Store(memarg),
+            Scalar::F32 => F32Store(memarg),
+            Scalar::F64 => F64Store(memarg),
+        });
+    }
+
+    /// Start a structured instruction of the current block's code
+    fn open(&mut self, instruction: Instruction<'static>) {
+        self.emit(instruction);
+        self.depth += 1;
+    }
+
+    fn close(&mut self) {
+        self.emit(End);
+        self.depth -= 1;
+    }
+
+    // ========================================================================
+    // Frames, blocks and statements
+    // ========================================================================
+
+    fn body(&mut self) {
+        let body = self.body;
+        let address_taken = address_taken(body);
+        let first_param = self.sret.is_some() as u32;
+        for (i, decl) in body.locals.iter().enumerate() {
+            let param = (i < body.arg_count).then(|| Value(first_param + i as u32));
+            let storage = match (self.c.repr(&decl.ty), param) {
+                (Repr::Scalar(_), Some(param)) if !address_taken[i] => Storage::Var(param),
+                (Repr::Scalar(scalar), _) if !address_taken[i] => Storage::Var(self.var(scalar.val_type())),
+                (Repr::Scalar(scalar), _) => Storage::Frame(self.slot(scalar.size(), scalar.size())),
+                // The caller passes a copy, which is the callee's to change
+                (Repr::Memory(..), Some(param)) => Storage::Indirect(param),
+                (Repr::Memory(size, align), None) => Storage::Frame(self.slot(size, align)),
+            };
+            self.storage.push(storage);
+        }
+        for (i, decl) in body.locals.iter().enumerate().take(body.arg_count) {
+            if let Storage::Frame(_) = self.storage[i] {
+                let loc = self.local(i);
+                self.store(loc, Val::Scalar(Value(first_param + i as u32)), &decl.ty);
+            }
+        }
+        self.release();
+
+        let n = body.blocks.len() as u32;
+        self.pc = self.var(ValType::I32);
+        self.emit(Loop(BlockType::Empty));
+        for _ in 0..n {
+            self.emit(Block(BlockType::Empty));
+        }
+        self.get(self.pc);
+        self.emit(BrTable((0..n).collect::<Vec<_>>().into(), n.saturating_sub(1)));
+        self.emit(End);
+        for (index, block) in body.blocks.iter().enumerate() {
+            self.current = index;
+            self.depth = 0;
+            for statement in &block.statements {
+                self.statement(statement);
+                self.release();
+            }
+            self.terminator(&block.terminator);
+            self.release();
+            // Closes the `block` the next block's code follows, or the loop
+            self.emit(End);
+        }
+        self.emit(Unreachable);
+    }
+
+    /// Frame space for a local or temporary, as an offset from `fp`
+    fn slot(&mut self, size: u64, align: u64) -> u64 {
+        let offset = self.frame_size.div_ceil(align.max(1)) * align.max(1);
+        self.frame_size = offset + size;
+        offset
+    }
+
+    fn frame_address(&mut self, offset: u64) -> Value {
+        self.offset(self.fp, offset)
+    }
+
+    /// The address of fresh frame space
+    fn temp(&mut self, size: u64, align: u64) -> Value {
+        let offset = self.slot(size, align);
+        self.frame_address(offset)
+    }
+
+    fn local(&mut self, index: usize) -> Loc {
+        match self.storage[index] {
+            Storage::Var(value) => Loc::Var(value),
+            Storage::Frame(offset) => Loc::Addr(self.frame_address(offset)),
+            Storage::Indirect(address) => Loc::Addr(address),
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Assign(place, rvalue) => {
+                let ty = self.place_type(place);
+                let value = self.rvalue(rvalue);
+                let loc = self.place(place, Access::Write);
+                self.store(loc, value, &ty);
+            }
+            Statement::Eval(rvalue) => {
+                self.rvalue(rvalue);
+            }
+        }
+    }
+
+    fn terminator(&mut self, terminator: &Terminator) {
+        match terminator {
+            Terminator::Goto(target) => self.jump(*target),
+            Terminator::Branch { cond, then_block, else_block } => {
+                let cond = self.value(cond);
+                self.get(cond);
+                self.open(If(BlockType::Empty));
+                self.jump(*then_block);
+                self.emit(Else);
+                self.jump(*else_block);
+                self.close();
+            }
+            Terminator::Return(value) => {
+                let value = self.operand(value);
+                self.ret(value);
+            }
+            Terminator::Propagate { value, dest, conversion, next } => self.propagate(value, dest, conversion, *next),
+            Terminator::Unreachable => {
+                let message = format!("`{}` ended without returning a value", self.body.name);
+                self.trap_message(&message);
+                self.emit(Unreachable);
+            }
+        }
+    }
+
+    /// Continue at `target`: fall into it if it comes next, else go round
+    /// the dispatch loop
+    fn jump(&mut self, target: BlockId) {
+        let blocks = self.body.blocks.len() as u32;
+        let (current, target) = (self.current as u32, target.index() as u32);
+        if target == current + 1 {
+            if self.depth > 0 {
+                self.emit(Br(self.depth));
+            }
+            return;
+        }
+        self.emit(I32Const(target as i32));
+        self.emit(LocalSet(self.pc.0));
+        self.emit(Br(self.depth + blocks - current - 1));
+    }
+
+    fn restore_stack(&mut self) {
+        self.get(self.saved);
+        self.emit(GlobalSet(STACK_POINTER));
+    }
+
+    fn ret(&mut self, value: Val) {
+        match (self.sret, value) {
+            (Some(dest), value) => {
+                let return_type = self.body.return_type.clone();
+                let source = self.memory(value);
+                self.copy_value(dest, source, &return_type);
+                self.restore_stack();
+            }
+            (None, value) => {
+                let value = self.scalar(value);
+                self.restore_stack();
+                self.get(value);
+            }
+        }
+        self.emit(Return);
+    }
+
+    /// Return the converted error when `value` holds one, else store its
+    /// success value in `dest` and continue at `next`
+    fn propagate(&mut self, value: &Operand, dest: &Place, conversion: &ErrorConversion, next: BlockId) {
+        let union_type = self.operand_type(value);
+        let return_type = self.body.return_type.clone();
+        let (Type::ErrorUnion { ok_type, err_type }, Type::ErrorUnion { err_type: return_err, .. }) =
+            (&union_type, &return_type)
+        else {
+            self.error(format!("cannot propagate from `{}`", format_type(&union_type)));
+            return;
+        };
+        let (err_type, return_err) = (Type::Path(err_type.clone()), Type::Path(return_err.clone()));
+        let layout = self.c.layout(&union_type);
+        let ok_offset = self.c.offset(&layout, "ok");
+        let err_offset = self.c.offset(&layout, "err");
+        let source = self.operand(value);
+        let source = self.memory(source);
+        let tag_scalar = Codegen::tag(&layout);
+        let tag = self.load(tag_scalar, false, source, 0);
+        let failed = self.nonzero(tag, tag_scalar);
+        self.get(failed);
+        self.open(If(BlockType::Empty));
+
+        // The error path returns `err(converted)`
+        let return_layout = self.c.layout(&return_type);
+        let result = self.temp(return_layout.size, return_layout.align);
+        let return_tag = Codegen::tag(&return_layout);
+        let one = self.int(return_tag, false, 1);
+        self.store_scalar(return_tag, result, 0, one);
+        let return_err_offset = self.c.offset(&return_layout, "err");
+        let err_dest = self.offset(result, return_err_offset);
+        let err_source = self.offset(source, err_offset);
+        match conversion {
+            ErrorConversion::Identity => self.copy_value(err_dest, err_source, &err_type),
+            ErrorConversion::Variant { variant } => {
+                let payload = self.set_variant(err_dest, &return_err, variant);
+                self.copy_value(payload, err_source, &err_type);
+            }
+            ErrorConversion::Function { function } => {
+                let Some((id, signature)) = self.c.functions.get(function).cloned() else {
+                    self.error(format!("missing error conversion `{}`", function));
+                    return;
+                };
+                let error = self.load_value(err_source, &err_type);
+                let converted = self.call_with(id, &signature, &[(error, err_type)], &return_err);
+                self.store(Loc::Addr(err_dest), converted, &return_err);
+            }
+        }
+        self.ret(Val::Memory(result));
+        self.close();
+
+        let ok_source = self.offset(source, ok_offset);
+        let ok = self.load_value(ok_source, ok_type);
+        let loc = self.place(dest, Access::Write);
+        self.store(loc, ok, ok_type);
+        self.jump(next);
+    }
+
+    // ========================================================================
+    // Places and operands
+    // ========================================================================
+
+    fn place(&mut self, place: &Place, access: Access) -> Loc {
+        let mut loc = self.local(place.local.index());
+        let mut ty = self.body.local(place.local).ty.clone();
+        // Variants before the last pointer are read, whatever the access
+        let mut types = Vec::with_capacity(place.projection.len());
+        let mut last_indirect = None;
+        for (i, projection) in place.projection.iter().enumerate() {
+            let through_pointer = matches!(projection, Projection::Deref)
+                || (matches!(projection, Projection::Index(_)) && matches!(ty, Type::Pointer { .. }));
+            if through_pointer {
+                last_indirect = Some(i);
+            }
+            types.push(ty.clone());
+            ty = projection.apply(&ty).unwrap_or(Type::Ok);
+        }
+        for (i, (projection, base)) in place.projection.iter().zip(types).enumerate() {
+            let access = if last_indirect.is_some_and(|last| i < last) { Access::Read } else { access };
+            loc = match projection {
+                Projection::Field(name, _) => {
+                    let layout = self.c.layout(&base);
+                    let offset = self.c.offset(&layout, name);
+                    let address = self.addr(loc);
+                    Loc::Addr(self.offset(address, offset))
+                }
+                Projection::Variant(name, _) => {
+                    let address = self.addr(loc);
+                    Loc::Addr(match access {
+                        Access::Read => self.check_variant(address, &base, name),
+                        Access::Write => self.set_variant(address, &base, name),
+                        Access::Address => {
+                            let layout = self.c.layout(&base);
+                            let offset = self.c.offset(&layout, name);
+                            self.offset(address, offset)
+                        }
+                    })
+                }
+                Projection::Index(index) => Loc::Addr(self.index(loc, &base, *index)),
+                Projection::Deref => {
+                    let pointer = self.read(loc, Scalar::I32, false);
+                    self.check_non_null(pointer);
+                    Loc::Addr(pointer)
+                }
+                Projection::Payload => {
+                    let layout = self.c.layout(&base);
+                    if matches!(layout.shape, Shape::Niche { .. }) {
+                        loc
+                    } else {
+                        let offset = self.c.offset(&layout, "some");
+                        let address = self.addr(loc);
+                        Loc::Addr(self.offset(address, offset))
+                    }
+                }
+                Projection::OkValue | Projection::ErrValue => {
+                    let layout = self.c.layout(&base);
+                    let name = if matches!(projection, Projection::OkValue) { "ok" } else { "err" };
+                    let offset = self.c.offset(&layout, name);
+                    let address = self.addr(loc);
+                    Loc::Addr(self.offset(address, offset))
+                }
+            };
+        }
+        loc
+    }
+
+    /// The address of an element of an array, slice or pointer
+    fn index(&mut self, base_loc: Loc, base: &Type, index: fig_mir::ir::Local) -> Value {
+        let index_type = self.body.local(index).ty.clone();
+        let signed = self.c.is_signed(&index_type);
+        let index_loc = self.local(index.index());
+        let index_scalar = match self.c.repr(&index_type) {
+            Repr::Scalar(scalar) => scalar,
+            Repr::Memory(..) => Scalar::I64,
+        };
+        let value = self.read(index_loc, index_scalar, signed);
+        let value = self.convert(value, index_scalar, signed, Scalar::I64, signed);
+        let (Type::Array { element_type, .. } | Type::Pointer { element_type, .. }) = base else {
+            self.error(format!("cannot index a `{}`", format_type(base)));
+            return value;
+        };
+        let stride = self.c.layout(element_type).size;
+        let start = match base {
+            Type::Array { size: Some(_), .. } => {
+                let count = match self.c.layout(base).shape {
+                    Shape::Array { count, .. } => count,
+                    _ => 0,
+                };
+                let len = self.i64(count as i64);
+                self.check_index(value, len, signed);
+                self.addr(base_loc)
+            }
+            Type::Array { size: None, .. } => {
+                let slice = self.addr(base_loc);
+                let pointer = self.load(Scalar::I32, false, slice, 0);
+                let len = self.load(Scalar::I32, false, slice, 4);
+                let len = self.op1(I64ExtendI32U, len, ValType::I64);
+                self.check_index(value, len, signed);
+                pointer
+            }
+            _ => {
+                let pointer = self.read(base_loc, Scalar::I32, false);
+                self.check_non_null(pointer);
+                pointer
+            }
+        };
+        let value = self.op1(I32WrapI64, value, ValType::I32);
+        let stride = self.i32(stride as i32);
+        let offset = self.op2(I32Mul, value, stride, ValType::I32);
+        self.op2(I32Add, start, offset, ValType::I32)
+    }
+
+    /// Trap unless the variant `name` of the union at `address` is active,
+    /// returning the address of its payload
+    fn check_variant(&mut self, address: Value, union_type: &Type, name: &str) -> Value {
+        let layout = self.c.layout(union_type);
+        let variant = self.variant_index(union_type, name);
+        let tag_scalar = Codegen::tag(&layout);
+        let tag = self.load(tag_scalar, false, address, 0);
+        let active = self.eq_const(tag, tag_scalar, false, variant as i128);
+        let inactive = self.op1(I32Eqz, active, ValType::I32);
+        self.trap_if(inactive, |t| {
+            let union_name = t.c.type_name(union_type);
+            let union_name = t.c_string(&union_name);
+            let variant_name = t.c_string(name);
+            let names = t.c.variant_names(union_type);
+            let tag = t.convert(tag, tag_scalar, false, Scalar::I32, false);
+            let four = t.i32(4);
+            let entry = t.op2(I32Mul, tag, four, ValType::I32);
+            let active = t.load(Scalar::I32, false, entry, names as u64);
+            t.call_runtime("inactive", &[union_name, variant_name, active]);
+        });
+        let offset = self.c.offset(&layout, name);
+        self.offset(address, offset)
+    }
+
+    /// Make the variant `name` of the union at `address` active, returning
+    /// the address of its payload
+    fn set_variant(&mut self, address: Value, union_type: &Type, name: &str) -> Value {
+        let layout = self.c.layout(union_type);
+        let variant = self.variant_index(union_type, name);
+        let tag_scalar = Codegen::tag(&layout);
+        let tag = self.int(tag_scalar, false, variant as i128);
+        self.store_scalar(tag_scalar, address, 0, tag);
+        let offset = self.c.offset(&layout, name);
+        self.offset(address, offset)
+    }
+
+    fn variant_index(&mut self, union_type: &Type, name: &str) -> usize {
+        self.c.fields(union_type).iter().position(|(variant, _)| variant == name).unwrap_or(0)
+    }
+
+    fn check_index(&mut self, index: Value, len: Value, signed: bool) {
+        // A negative index is a large unsigned one
+        let out_of_bounds = self.op2(I64GeU, index, len, ValType::I32);
+        self.trap_if(out_of_bounds, |t| {
+            let signed = t.i32(signed as i32);
+            t.call_runtime("index", &[index, len, signed]);
+        });
+    }
+
+    fn check_non_null(&mut self, pointer: Value) {
+        let null = self.op1(I32Eqz, pointer, ValType::I32);
+        self.trap_if(null, |t| t.trap_message("null pointer dereference"));
+    }
+
+    fn addr(&mut self, loc: Loc) -> Value {
+        match loc {
+            Loc::Addr(address) => address,
+            Loc::Var(_) => {
+                self.error("a register local has no address");
+                self.i32(0)
+            }
+        }
+    }
+
+    fn read(&mut self, loc: Loc, scalar: Scalar, signed: bool) -> Value {
+        match loc {
+            Loc::Var(value) => value,
+            Loc::Addr(address) => self.load(scalar, signed, address, 0),
+        }
+    }
+
+    fn offset(&mut self, address: Value, offset: u64) -> Value {
+        if offset == 0 {
+            return address;
+        }
+        let offset = self.i32(offset as i32);
+        self.op2(I32Add, address, offset, ValType::I32)
+    }
+
+    fn operand(&mut self, operand: &Operand) -> Val {
+        match operand {
+            Operand::Copy(place) => {
+                let ty = self.place_type(place);
+                let loc = self.place(place, Access::Read);
+                match self.c.repr(&ty) {
+                    Repr::Scalar(scalar) => {
+                        let signed = self.c.is_signed(&ty);
+                        Val::Scalar(self.read(loc, scalar, signed))
+                    }
+                    Repr::Memory(..) => Val::Memory(self.addr(loc)),
+                }
+            }
+            Operand::Const(constant) => self.constant(constant),
+        }
+    }
+
+    fn constant(&mut self, constant: &Constant) -> Val {
+        match constant {
+            Constant::Int(value, ty) => {
+                let scalar = match self.c.repr(ty) {
+                    Repr::Scalar(scalar) => scalar,
+                    Repr::Memory(..) => Scalar::I64,
+                };
+                let signed = self.c.is_signed(ty);
+                Val::Scalar(self.int(scalar, signed, *value))
+            }
+            Constant::Float(value, ty) if *ty == Type::F32 => {
+                self.emit(F32Const((*value as f32).into()));
+                Val::Scalar(self.set(ValType::F32))
+            }
+            Constant::Float(value, _) => {
+                self.emit(F64Const((*value).into()));
+                Val::Scalar(self.set(ValType::F64))
+            }
+            Constant::Bool(value) => Val::Scalar(self.i32(*value as i32)),
+            Constant::Str(text, ty) => {
+                let slice = matches!(ty, Type::Array { .. });
+                let address = self.c.string(text, slice);
+                let address = self.i32(address as i32);
+                if slice { Val::Memory(address) } else { Val::Scalar(address) }
+            }
+            Constant::Ok => Val::Scalar(self.i32(0)),
+            Constant::Null(ty) => match self.c.repr(ty) {
+                Repr::Scalar(scalar) => Val::Scalar(self.int(scalar, false, 0)),
+                Repr::Memory(size, _) => {
+                    let address = self.c.zeros(size);
+                    Val::Memory(self.i32(address as i32))
+                }
+            },
+        }
+    }
+
+    fn value(&mut self, operand: &Operand) -> Value {
+        let value = self.operand(operand);
+        self.scalar(value)
+    }
+
+    fn scalar(&mut self, value: Val) -> Value {
+        match value {
+            Val::Scalar(value) => value,
+            Val::Memory(address) => {
+                self.error("an aggregate used as a scalar");
+                address
+            }
+        }
+    }
+
+    fn memory(&mut self, value: Val) -> Value {
+        match value {
+            Val::Memory(address) => address,
+            Val::Scalar(value) => {
+                self.error("a scalar used as an aggregate");
+                value
+            }
+        }
+    }
+
+    /// The address of a value of type `ty`, storing a scalar to the frame first
+    fn spill(&mut self, value: Val, ty: &Type) -> Value {
+        match (value, self.c.repr(ty)) {
+            (Val::Scalar(value), Repr::Scalar(scalar)) => {
+                let address = self.temp(scalar.size(), scalar.size());
+                self.store_scalar(scalar, address, 0, value);
+                address
+            }
+            (value, _) => self.memory(value),
+        }
+    }
+
+    fn load_value(&mut self, address: Value, ty: &Type) -> Val {
+        match self.c.repr(ty) {
+            Repr::Scalar(scalar) => {
+                let signed = self.c.is_signed(ty);
+                Val::Scalar(self.load(scalar, signed, address, 0))
+            }
+            Repr::Memory(..) => Val::Memory(address),
+        }
+    }
+
+    fn store(&mut self, loc: Loc, value: Val, ty: &Type) {
+        match (loc, value) {
+            (Loc::Var(var), Val::Scalar(value)) => {
+                self.get(value);
+                self.emit(LocalSet(var.0));
+            }
+            (Loc::Addr(address), Val::Scalar(value)) => match self.c.repr(ty) {
+                Repr::Scalar(scalar) => self.store_scalar(scalar, address, 0, value),
+                Repr::Memory(..) => self.error(format!("a scalar stored to a `{}`", format_type(ty))),
+            },
+            (Loc::Addr(address), Val::Memory(source)) => self.copy_value(address, source, ty),
+            (Loc::Var(_), Val::Memory(_)) => self.error(format!("an aggregate stored to a `{}` register", format_type(ty))),
+        }
+    }
+
+    fn copy_value(&mut self, dest: Value, source: Value, ty: &Type) {
+        let size = self.c.layout(ty).size;
+        self.copy(dest, source, size);
+    }
+
+    /// Copy `size` bytes, which may overlap
+    fn copy(&mut self, dest: Value, source: Value, size: u64) {
+        if size > 32 {
+            let size = self.i32(size as i32);
+            self.get(dest);
+            self.get(source);
+            self.get(size);
+            self.emit(MemoryCopy { src_mem: 0, dst_mem: 0 });
+            return;
+        }
+        let mut chunks = Vec::new();
+        let mut offset = 0;
+        for scalar in [Scalar::I64, Scalar::I32, Scalar::I16, Scalar::I8] {
+            while size - offset >= scalar.size() {
+                let value = self.load(scalar, false, source, offset);
+                chunks.push((scalar, value, offset));
+                offset += scalar.size();
+            }
+        }
+        for (scalar, value, offset) in chunks {
+            self.store_scalar(scalar, dest, offset, value);
+        }
+    }
+
+    // ========================================================================
+    // Rvalues
+    // ========================================================================
+
+    fn rvalue(&mut self, rvalue: &Rvalue) -> Val {
+        match rvalue {
+            Rvalue::Use(operand) => self.operand(operand),
+            Rvalue::Binary(op, lhs, rhs) => Val::Scalar(self.binary(*op, lhs, rhs)),
+            Rvalue::Unary(op, operand) => Val::Scalar(self.unary(*op, operand)),
+            Rvalue::Cast(operand, to) => {
+                let from = self.operand_type(operand);
+                let value = self.value(operand);
+                Val::Scalar(self.cast(value, &from, to))
+            }
+            Rvalue::AddressOf(place) => {
+                let loc = self.place(place, Access::Address);
+                Val::Scalar(self.addr(loc))
+            }
+            Rvalue::Aggregate(kind, operands) => self.aggregate(kind, operands),
+            Rvalue::Unsize(place, _) => {
+                let array_type = self.place_type(place);
+                let count = match self.c.layout(&array_type).shape {
+                    Shape::Array { count, .. } => count,
+                    _ => 0,
+                };
+                let loc = self.place(place, Access::Address);
+                let array = self.addr(loc);
+                let slice = self.temp(8, 4);
+                let len = self.i32(count as i32);
+                self.store_scalar(Scalar::I32, slice, 0, array);
+                self.store_scalar(Scalar::I32, slice, 4, len);
+                Val::Memory(slice)
+            }
+            Rvalue::Len(place) => {
+                let loc = self.place(place, Access::Read);
+                let slice = self.addr(loc);
+                Val::Scalar(self.load(Scalar::I32, false, slice, 4))
+            }
+            Rvalue::Subslice(place, start, end) => {
+                let array_type = self.place_type(place);
+                let loc = self.place(place, Access::Read);
+                let base = self.addr(loc);
+                let (pointer, len) = match (&array_type, self.c.layout(&array_type).shape) {
+                    (Type::Array { size: Some(_), .. }, Shape::Array { count, .. }) => (base, self.i32(count as i32)),
+                    _ => (self.load(Scalar::I32, false, base, 0), self.load(Scalar::I32, false, base, 4)),
+                };
+                let start = self.value(start);
+                let end = self.value(end);
+                let backwards = self.op2(I32GtU, start, end, ValType::I32);
+                let past_len = self.op2(I32GtU, end, len, ValType::I32);
+                let out_of_bounds = self.op2(I32Or, backwards, past_len, ValType::I32);
+                self.trap_if(out_of_bounds, |t| t.call_runtime("slice", &[start, end, len]));
+                let stride = match &array_type {
+                    Type::Array { element_type, .. } => self.c.layout(element_type).size,
+                    _ => 0,
+                };
+                let stride = self.i32(stride as i32);
+                let offset = self.op2(I32Mul, start, stride, ValType::I32);
+                let data = self.op2(I32Add, pointer, offset, ValType::I32);
+                let count = self.op2(I32Sub, end, start, ValType::I32);
+                let slice = self.temp(8, 4);
+                self.store_scalar(Scalar::I32, slice, 0, data);
+                self.store_scalar(Scalar::I32, slice, 4, count);
+                Val::Memory(slice)
+            }
+            Rvalue::IsNull(operand) => {
+                let ty = self.operand_type(operand);
+                let value = self.operand(operand);
+                Val::Scalar(match value {
+                    Val::Scalar(pointer) => self.eqz(pointer, Scalar::I32),
+                    Val::Memory(address) => {
+                        let layout = self.c.layout(&ty);
+                        let tag_scalar = Codegen::tag(&layout);
+                        let tag = self.load(tag_scalar, false, address, 0);
+                        self.eqz(tag, tag_scalar)
+                    }
+                })
+            }
+            Rvalue::IsErr(operand) => {
+                let ty = self.operand_type(operand);
+                let value = self.operand(operand);
+                let address = self.memory(value);
+                let layout = self.c.layout(&ty);
+                let tag_scalar = Codegen::tag(&layout);
+                let tag = self.load(tag_scalar, false, address, 0);
+                let ok = self.eqz(tag, tag_scalar);
+                Val::Scalar(self.op1(I32Eqz, ok, ValType::I32))
+            }
+            Rvalue::Call(callee, args) => self.call(callee, args),
+        }
+    }
+
+    /// Build an aggregate in the frame
+    fn aggregate(&mut self, kind: &AggregateKind, operands: &[Operand]) -> Val {
+        let ty = kind.ty().clone();
+        if let (AggregateKind::Some(_), Repr::Scalar(_), [operand]) = (kind, self.c.repr(&ty), operands) {
+            // A `?*T` is the pointer itself
+            return self.operand(operand);
+        }
+        let layout = self.c.layout(&ty);
+        let dest = self.temp(layout.size, layout.align);
+        match kind {
+            AggregateKind::Struct(_) => {
+                for ((name, field_type), operand) in self.c.fields(&ty).iter().zip(operands) {
+                    let offset = self.c.offset(&layout, name);
+                    let value = self.operand(operand);
+                    let address = self.offset(dest, offset);
+                    self.store(Loc::Addr(address), value, field_type);
+                }
+            }
+            AggregateKind::Array(_) => {
+                let (Type::Array { element_type, .. }, Shape::Array { element, .. }) = (&ty, &layout.shape) else {
+                    return Val::Memory(dest);
+                };
+                for (i, operand) in operands.iter().enumerate() {
+                    let value = self.operand(operand);
+                    let address = self.offset(dest, i as u64 * element.size);
+                    self.store(Loc::Addr(address), value, element_type);
+                }
+            }
+            AggregateKind::Variant(_, variant) => {
+                let payload = self.set_variant(dest, &ty, variant);
+                if let Some(operand) = operands.first() {
+                    let payload_type = self.operand_type(operand);
+                    let value = self.operand(operand);
+                    self.store(Loc::Addr(payload), value, &payload_type);
+                }
+            }
+            AggregateKind::Some(_) | AggregateKind::Ok(_) | AggregateKind::Err(_) => {
+                let (tag, name) = match kind {
+                    AggregateKind::Some(_) => (1, "some"),
+                    AggregateKind::Ok(_) => (0, "ok"),
+                    _ => (1, "err"),
+                };
+                let tag_scalar = Codegen::tag(&layout);
+                let tag = self.int(tag_scalar, false, tag);
+                self.store_scalar(tag_scalar, dest, 0, tag);
+                if let Some(operand) = operands.first() {
+                    let offset = self.c.offset(&layout, name);
+                    let payload_type = self.operand_type(operand);
+                    let value = self.operand(operand);
+                    let address = self.offset(dest, offset);
+                    self.store(Loc::Addr(address), value, &payload_type);
+                }
+            }
+        }
+        Val::Memory(dest)
+    }
+
+    /// The scalar of an integer-like type, falling back to `i64`
+    fn int_scalar(&mut self, ty: &Type) -> Scalar {
+        match self.c.repr(ty) {
+            Repr::Scalar(scalar) => scalar,
+            Repr::Memory(..) => Scalar::I64,
+        }
+    }
+
+    fn binary(&mut self, op: BinOp, lhs: &Operand, rhs: &Operand) -> Value {
+        let lhs_type = self.operand_type(lhs);
+        let rhs_type = self.operand_type(rhs);
+        let a = self.value(lhs);
+        let b = self.value(rhs);
+        if op.is_comparison() {
+            if is_float(&lhs_type) {
+                let double = lhs_type == Type::F64;
+                let instruction = match (op, double) {
+                    (BinOp::Eq, false) => F32Eq,
+                    (BinOp::Eq, true) => F64Eq,
+                    (BinOp::Ne, false) => F32Ne,
+                    (BinOp::Ne, true) => F64Ne,
+                    (BinOp::Lt, false) => F32Lt,
+                    (BinOp::Lt, true) => F64Lt,
+                    (BinOp::Le, false) => F32Le,
+                    (BinOp::Le, true) => F64Le,
+                    (BinOp::Gt, false) => F32Gt,
+                    (BinOp::Gt, true) => F64Gt,
+                    (_, false) => F32Ge,
+                    (_, true) => F64Ge,
+                };
+                return self.op2(instruction, a, b, ValType::I32);
+            }
+            let scalar = self.int_scalar(&lhs_type);
+            let signed = self.c.is_signed(&lhs_type);
+            let instruction = match (op, signed) {
+                (BinOp::Eq, _) => pick(scalar, I32Eq, I64Eq),
+                (BinOp::Ne, _) => pick(scalar, I32Ne, I64Ne),
+                (BinOp::Lt, true) => pick(scalar, I32LtS, I64LtS),
+                (BinOp::Lt, false) => pick(scalar, I32LtU, I64LtU),
+                (BinOp::Le, true) => pick(scalar, I32LeS, I64LeS),
+                (BinOp::Le, false) => pick(scalar, I32LeU, I64LeU),
+                (BinOp::Gt, true) => pick(scalar, I32GtS, I64GtS),
+                (BinOp::Gt, false) => pick(scalar, I32GtU, I64GtU),
+                (_, true) => pick(scalar, I32GeS, I64GeS),
+                (_, false) => pick(scalar, I32GeU, I64GeU),
+            };
+            return self.op2(instruction, a, b, ValType::I32);
+        }
+        if let Type::Pointer { element_type, .. } = &lhs_type {
+            let stride = self.c.layout(element_type).size.max(1) as i32;
+            let stride = self.i32(stride);
+            if let (BinOp::Sub, Type::Pointer { .. }) = (op, &rhs_type) {
+                let distance = self.op2(I32Sub, a, b, ValType::I32);
+                return self.op2(I32DivS, distance, stride, ValType::I32);
+            }
+            let index_scalar = self.int_scalar(&rhs_type);
+            let signed = self.c.is_signed(&rhs_type);
+            let index = self.convert(b, index_scalar, signed, Scalar::I32, signed);
+            let delta = self.op2(I32Mul, index, stride, ValType::I32);
+            return match op {
+                BinOp::Add => self.op2(I32Add, a, delta, ValType::I32),
+                BinOp::Sub => self.op2(I32Sub, a, delta, ValType::I32),
+                _ => {
+                    self.error(format!("`{}` on a pointer", op.name()));
+                    a
+                }
+            };
+        }
+        if is_float(&lhs_type) {
+            let double = lhs_type == Type::F64;
+            let (instruction, ty) = match (op, double) {
+                (BinOp::Add, false) => (F32Add, ValType::F32),
+                (BinOp::Add, true) => (F64Add, ValType::F64),
+                (BinOp::Sub, false) => (F32Sub, ValType::F32),
+                (BinOp::Sub, true) => (F64Sub, ValType::F64),
+                (BinOp::Mul, false) => (F32Mul, ValType::F32),
+                (BinOp::Mul, true) => (F64Mul, ValType::F64),
+                (BinOp::Div, false) => (F32Div, ValType::F32),
+                (BinOp::Div, true) => (F64Div, ValType::F64),
+                _ => {
+                    self.error(format!("`{}` on a float", op.name()));
+                    return a;
+                }
+            };
+            return self.op2(instruction, a, b, ty);
+        }
+        let name = format_type(&lhs_type);
+        let signed = self.c.is_signed(&lhs_type);
+        let scalar = self.int_scalar(&lhs_type);
+        let ty = scalar.val_type();
+        match op {
+            BinOp::BitAnd => self.op2(pick(scalar, I32And, I64And), a, b, ty),
+            BinOp::BitOr => self.op2(pick(scalar, I32Or, I64Or), a, b, ty),
+            BinOp::BitXor => self.op2(pick(scalar, I32Xor, I64Xor), a, b, ty),
+            BinOp::Add | BinOp::Sub | BinOp::Mul => {
+                let what = match op {
+                    BinOp::Add => "addition",
+                    BinOp::Sub => "subtraction",
+                    _ => "multiplication",
+                };
+                let message = format!("`{}` {} overflowed", name, what);
+                self.checked(op, a, b, scalar, signed, &message)
+            }
+            BinOp::Div | BinOp::Rem => {
+                let zero = self.eqz(b, scalar);
+                self.trap_if(zero, |t| t.trap_message("division by zero"));
+                if !signed {
+                    let instruction = match op {
+                        BinOp::Div => pick(scalar, I32DivU, I64DivU),
+                        _ => pick(scalar, I32RemU, I64RemU),
+                    };
+                    return self.op2(instruction, a, b, ty);
+                }
+                let by_minus_one = self.eq_const(b, scalar, true, -1);
+                if op == BinOp::Rem {
+                    // `MIN % -1` is 0, which `x % 1` also gives, but wasm traps on it
+                    let one = self.int(scalar, true, 1);
+                    self.get(one);
+                    self.get(b);
+                    self.get(by_minus_one);
+                    self.emit(Select);
+                    let divisor = self.set(ty);
+                    return self.op2(pick(scalar, I32RemS, I64RemS), a, divisor, ty);
+                }
+                let (min, _) = integer_bounds(scalar.bits(), true);
+                let is_min = self.eq_const(a, scalar, true, min);
+                let overflowed = self.op2(I32And, is_min, by_minus_one, ValType::I32);
+                let message = format!("`{}` division overflowed", name);
+                self.trap_if(overflowed, |t| t.trap_message(&message));
+                self.op2(pick(scalar, I32DivS, I64DivS), a, b, ty)
+            }
+            BinOp::Shl | BinOp::Shr => {
+                let amount_scalar = self.int_scalar(&rhs_type);
+                let amount_signed = self.c.is_signed(&rhs_type);
+                let amount = self.convert(b, amount_scalar, amount_signed, Scalar::I64, amount_signed);
+                let bits = self.i64(scalar.bits() as i64);
+                // A negative amount is a large unsigned one
+                let out_of_range = self.op2(I64GeU, amount, bits, ValType::I32);
+                self.trap_if(out_of_range, |t| {
+                    let type_name = t.c_string(&name);
+                    t.call_runtime("shift", &[amount, bits, type_name]);
+                });
+                let amount = self.convert(amount, Scalar::I64, false, Scalar::I32, false);
+                let amount = if ty == ValType::I64 { self.op1(I64ExtendI32U, amount, ValType::I64) } else { amount };
+                match (op, signed) {
+                    (BinOp::Shl, _) => {
+                        let shifted = self.op2(pick(scalar, I32Shl, I64Shl), a, amount, ty);
+                        self.normalize(shifted, scalar, signed)
+                    }
+                    (_, true) => self.op2(pick(scalar, I32ShrS, I64ShrS), a, amount, ty),
+                    (_, false) => self.op2(pick(scalar, I32ShrU, I64ShrU), a, amount, ty),
+                }
+            }
+            _ => unreachable!("comparisons are handled above"),
+        }
+    }
+
+    /// Add, subtract or multiply, trapping with `message` on overflow
+    fn checked(&mut self, op: BinOp, a: Value, b: Value, scalar: Scalar, signed: bool, message: &str) -> Value {
+        match scalar {
+            // Narrow values cannot overflow 32 bits, so the result is
+            // checked against the narrow range
+            Scalar::I8 | Scalar::I16 => {
+                let instruction = match op {
+                    BinOp::Add => I32Add,
+                    BinOp::Sub => I32Sub,
+                    _ => I32Mul,
+                };
+                let result = self.op2(instruction, a, b, ValType::I32);
+                let overflowed = if signed {
+                    let normal = self.normalize(result, scalar, true);
+                    self.op2(I32Ne, result, normal, ValType::I32)
+                } else {
+                    let (_, max) = integer_bounds(scalar.bits(), false);
+                    let max = self.i32(max as i32);
+                    self.op2(I32GtU, result, max, ValType::I32)
+                };
+                self.trap_if(overflowed, |t| t.trap_message(message));
+                return result;
+            }
+            // 32-bit values are computed in 64 bits
+            Scalar::I32 => {
+                let extend = if signed { I64ExtendI32S } else { I64ExtendI32U };
+                let wide_a = self.op1(extend.clone(), a, ValType::I64);
+                let wide_b = self.op1(extend, b, ValType::I64);
+                let instruction = match op {
+                    BinOp::Add => I64Add,
+                    BinOp::Sub => I64Sub,
+                    _ => I64Mul,
+                };
+                let result = self.op2(instruction, wide_a, wide_b, ValType::I64);
+                let overflowed = if signed {
+                    let normal = self.op1(I64Extend32S, result, ValType::I64);
+                    self.op2(I64Ne, result, normal, ValType::I32)
+                } else {
+                    let max = self.i64(u32::MAX as i64);
+                    self.op2(I64GtU, result, max, ValType::I32)
+                };
+                self.trap_if(overflowed, |t| t.trap_message(message));
+                return self.op1(I32WrapI64, result, ValType::I32);
+            }
+            Scalar::I64 | Scalar::F32 | Scalar::F64 => {}
+        }
+        // 64-bit values are checked with bit tricks
+        let instruction = match op {
+            BinOp::Add => I64Add,
+            BinOp::Sub => I64Sub,
+            _ => I64Mul,
+        };
+        let result = self.op2(instruction, a, b, ValType::I64);
+        let overflowed = match (op, signed) {
+            // Overflow flips the sign against both operands
+            (BinOp::Add, true) => {
+                let x = self.op2(I64Xor, a, result, ValType::I64);
+                let y = self.op2(I64Xor, b, result, ValType::I64);
+                let both = self.op2(I64And, x, y, ValType::I64);
+                let zero = self.i64(0);
+                self.op2(I64LtS, both, zero, ValType::I32)
+            }
+            (BinOp::Sub, true) => {
+                let x = self.op2(I64Xor, a, b, ValType::I64);
+                let y = self.op2(I64Xor, a, result, ValType::I64);
+                let both = self.op2(I64And, x, y, ValType::I64);
+                let zero = self.i64(0);
+                self.op2(I64LtS, both, zero, ValType::I32)
+            }
+            (BinOp::Add, false) => self.op2(I64LtU, result, a, ValType::I32),
+            (BinOp::Sub, false) => self.op2(I64LtU, a, b, ValType::I32),
+            // The product overflowed unless dividing it by `a` gives `b`
+            (_, false) => {
+                let is_zero = self.eqz(a, Scalar::I64);
+                let one = self.i64(1);
+                let divisor = self.select(one, a, is_zero, ValType::I64);
+                let quotient = self.op2(I64DivU, result, divisor, ValType::I64);
+                let differs = self.op2(I64Ne, quotient, b, ValType::I32);
+                let zero = self.i32(0);
+                self.select(zero, differs, is_zero, ValType::I32)
+            }
+            (_, true) => {
+                // Dividing by -1 could trap, and `-1 * b` overflows only for `MIN`
+                let is_zero = self.eqz(a, Scalar::I64);
+                let is_minus_one = self.eq_const(a, Scalar::I64, true, -1);
+                let one = self.i64(1);
+                let either = self.op2(I32Or, is_zero, is_minus_one, ValType::I32);
+                let divisor = self.select(one, a, either, ValType::I64);
+                let quotient = self.op2(I64DivS, result, divisor, ValType::I64);
+                let differs = self.op2(I64Ne, quotient, b, ValType::I32);
+                let b_is_min = self.eq_const(b, Scalar::I64, true, i64::MIN as i128);
+                let overflowed = self.select(b_is_min, differs, is_minus_one, ValType::I32);
+                let zero = self.i32(0);
+                self.select(zero, overflowed, is_zero, ValType::I32)
+            }
+        };
+        self.trap_if(overflowed, |t| t.trap_message(message));
+        result
+    }
+
+    /// `then` if `cond` is true, else `otherwise`
+    fn select(&mut self, then: Value, otherwise: Value, cond: Value, ty: ValType) -> Value {
+        self.get(then);
+        self.get(otherwise);
+        self.get(cond);
+        self.emit(Select);
+        self.set(ty)
+    }
+
+    fn unary(&mut self, op: UnOp, operand: &Operand) -> Value {
+        let ty = self.operand_type(operand);
+        let a = self.value(operand);
+        match op {
+            UnOp::Neg if ty == Type::F32 => self.op1(F32Neg, a, ValType::F32),
+            UnOp::Neg if ty == Type::F64 => self.op1(F64Neg, a, ValType::F64),
+            UnOp::Neg => {
+                let scalar = self.int_scalar(&ty);
+                let signed = self.c.is_signed(&ty);
+                // Only `MIN` has no negation, and for unsigned types only 0 has one
+                let overflowed = if signed {
+                    let (min, _) = integer_bounds(scalar.bits(), true);
+                    self.eq_const(a, scalar, true, min)
+                } else {
+                    self.nonzero(a, scalar)
+                };
+                let message = format!("`{}` negation overflowed", format_type(&ty));
+                self.trap_if(overflowed, |t| t.trap_message(&message));
+                let zero = self.int(scalar, signed, 0);
+                self.op2(pick(scalar, I32Sub, I64Sub), zero, a, scalar.val_type())
+            }
+            UnOp::Not => self.op1(I32Eqz, a, ValType::I32),
+            UnOp::BitNot => {
+                let scalar = self.int_scalar(&ty);
+                let signed = self.c.is_signed(&ty);
+                let ones = self.int(scalar, true, -1);
+                let inverted = self.op2(pick(scalar, I32Xor, I64Xor), a, ones, scalar.val_type());
+                self.normalize(inverted, scalar, signed)
+            }
+        }
+    }
+
+    /// The scalar and signedness of an integer-like type: integers, enums,
+    /// `bool`, pointers and `?*T`
+    fn int_info(&mut self, ty: &Type) -> Option<(Scalar, bool)> {
+        match self.c.repr(ty) {
+            Repr::Scalar(scalar) if scalar.is_int() => Some((scalar, self.c.is_signed(ty))),
+            _ => None,
+        }
+    }
+
+    fn cast(&mut self, value: Value, from: &Type, to: &Type) -> Value {
+        if from == to {
+            return value;
+        }
+        let source = self.int_info(from);
+        match to {
+            Type::Bool => match source {
+                Some((scalar, _)) => {
+                    let zero = self.eqz(value, scalar);
+                    self.op1(I32Eqz, zero, ValType::I32)
+                }
+                None if *from == Type::F32 => {
+                    self.emit(F32Const(0.0f32.into()));
+                    let zero = self.set(ValType::F32);
+                    self.op2(F32Ne, value, zero, ValType::I32)
+                }
+                None => {
+                    self.emit(F64Const(0.0f64.into()));
+                    let zero = self.set(ValType::F64);
+                    self.op2(F64Ne, value, zero, ValType::I32)
+                }
+            },
+            Type::F32 | Type::F64 => {
+                let single = *to == Type::F32;
+                let ty = if single { ValType::F32 } else { ValType::F64 };
+                match (source, from) {
+                    (_, Type::F32) => self.op1(F64PromoteF32, value, ty),
+                    (_, Type::F64) => self.op1(F32DemoteF64, value, ty),
+                    (Some((scalar, signed)), _) => {
+                        let wide = self.convert(value, scalar, signed, Scalar::I64, signed);
+                        let instruction = match (single, signed) {
+                            (true, true) => F32ConvertI64S,
+                            (true, false) => F32ConvertI64U,
+                            (false, true) => F64ConvertI64S,
+                            (false, false) => F64ConvertI64U,
+                        };
+                        self.op1(instruction, wide, ty)
+                    }
+                    (None, _) => {
+                        self.error(format!("cannot cast `{}` to a float", format_type(from)));
+                        value
+                    }
+                }
+            }
+            _ => {
+                let Some((target, target_signed)) = self.int_info(to) else {
+                    self.error(format!("cannot cast to `{}`", format_type(to)));
+                    return value;
+                };
+                let result = match source {
+                    Some((scalar, signed)) => self.convert(value, scalar, signed, target, target_signed),
+                    None if is_float(from) => self.float_to_int(value, *from == Type::F32, target, target_signed),
+                    None => {
+                        self.error(format!("cannot cast `{}` to `{}`", format_type(from), format_type(to)));
+                        return value;
+                    }
+                };
+                if let Some(TypeDef::Enum(_)) = self.c.type_def(to) {
+                    self.check_discriminant(result, to);
+                }
+                result
+            }
+        }
+    }
+
+    /// Float to integer casts saturate, and NaN becomes zero
+    fn float_to_int(&mut self, value: Value, single: bool, target: Scalar, signed: bool) -> Value {
+        if target == Scalar::I64 {
+            let instruction = match (single, signed) {
+                (true, true) => I64TruncSatF32S,
+                (true, false) => I64TruncSatF32U,
+                (false, true) => I64TruncSatF64S,
+                (false, false) => I64TruncSatF64U,
+            };
+            return self.op1(instruction, value, ValType::I64);
+        }
+        let wide = self.op1(if single { I64TruncSatF32S } else { I64TruncSatF64S }, value, ValType::I64);
+        let (min, max) = integer_bounds(target.bits(), signed);
+        let min = self.i64(min as i64);
+        let max = self.i64(max as i64);
+        let below = self.op2(I64LtS, wide, min, ValType::I32);
+        let clamped = self.select(min, wide, below, ValType::I64);
+        let above = self.op2(I64GtS, clamped, max, ValType::I32);
+        let clamped = self.select(max, clamped, above, ValType::I64);
+        self.op1(I32WrapI64, clamped, ValType::I32)
+    }
+
+    /// Trap unless `value` is one of the discriminants of the enum `ty`
+    fn check_discriminant(&mut self, value: Value, ty: &Type) {
+        let Shape::Enum { discriminants, signed } = self.c.layout(ty).shape else { return };
+        let scalar = self.int_scalar(ty);
+        let mut valid = self.i32(0);
+        for (_, discriminant) in discriminants {
+            let equal = self.eq_const(value, scalar, signed, discriminant);
+            valid = self.op2(I32Or, valid, equal, ValType::I32);
+        }
+        let invalid = self.op1(I32Eqz, valid, ValType::I32);
+        let name = self.c.type_name(ty);
+        self.trap_if(invalid, |t| {
+            let value = t.convert(value, scalar, signed, Scalar::I64, signed);
+            let name = t.c_string(&name);
+            t.call_runtime("out_of_range", &[value, name]);
+        });
+    }
+
+    // ========================================================================
+    // Calls
+    // ========================================================================
+
+    fn call(&mut self, callee: &Callee, args: &[Operand]) -> Val {
+        let (name, entry) = match callee {
+            Callee::Builtin(builtin) => return self.builtin(*builtin, args),
+            Callee::Extern(name) if name == MAIN_FAILED => {
+                self.call_runtime("main_failed", &[]);
+                return Val::Scalar(self.i32(0));
+            }
+            Callee::Function(name) => (name, self.c.functions.get(name).cloned()),
+            Callee::Extern(name) => (name, self.c.externs.get(name).cloned()),
+        };
+        let Some((id, signature)) = entry else {
+            self.error(format!("call of unknown function `{}`", name));
+            return Val::Scalar(self.i32(0));
+        };
+        let result_type = self.c.signature_of(callee).map_or(Type::Ok, |(_, result)| result);
+        let args: Vec<(Val, Type)> =
+            args.iter().map(|arg| (self.operand(arg), self.operand_type(arg))).collect();
+        self.call_with(id, &signature, &args, &result_type)
+    }
+
+    /// Call a function as `signature` says, leaving an aggregate result in the frame
+    fn call_with(&mut self, id: FuncId, signature: &Signature, args: &[(Val, Type)], result: &Type) -> Val {
+        let mut values = Vec::with_capacity(signature.params.len());
+        let result_address = if signature.sret {
+            let layout = self.c.layout(result);
+            let address = self.temp(layout.size, layout.align);
+            values.push(address);
+            Some(address)
+        } else {
+            None
+        };
+        for (value, ty) in args {
+            values.push(match *value {
+                Val::Scalar(value) => value,
+                // Each call gets its own copy, which the callee may change
+                Val::Memory(source) => {
+                    let layout = self.c.layout(ty);
+                    let copy = self.temp(layout.size, layout.align);
+                    self.copy(copy, source, layout.size);
+                    copy
+                }
+            });
+        }
+        for value in values {
+            self.get(value);
+        }
+        self.call_id(id);
+        if let Some(&ty) = signature.results.first() {
+            return Val::Scalar(self.set(ty));
+        }
+        match (result_address, self.c.repr(result)) {
+            (Some(address), _) => Val::Memory(address),
+            // A C function returning `void`
+            (None, Repr::Scalar(scalar)) => Val::Scalar(self.int(scalar, false, 0)),
+            (None, Repr::Memory(..)) => {
+                self.error(format!("`{}` has no result", format_type(result)));
+                Val::Memory(self.i32(0))
+            }
+        }
+    }
+
+    fn builtin(&mut self, builtin: Builtin, args: &[Operand]) -> Val {
+        match builtin {
+            Builtin::Print | Builtin::Println => {
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        self.print_byte(b' ');
+                    }
+                    let ty = self.operand_type(arg);
+                    match self.c.print_function(&ty) {
+                        Ok(id) => {
+                            let value = self.operand(arg);
+                            let address = self.spill(value, &ty);
+                            self.get(address);
+                            self.call_id(id);
+                        }
+                        Err(message) => self.error(message),
+                    }
+                }
+                if builtin == Builtin::Println {
+                    self.print_byte(b'\n');
+                }
+            }
+            Builtin::Assert => {
+                let cond = self.value(&args[0]);
+                let failed = self.op1(I32Eqz, cond, ValType::I32);
+                self.trap_if(failed, |t| t.trap_message("assertion failed"));
+            }
+            Builtin::Malloc | Builtin::Calloc | Builtin::Realloc | Builtin::Free => {
+                let values: Vec<Value> = args.iter().map(|arg| self.value(arg)).collect();
+                let name = match builtin {
+                    Builtin::Malloc => "malloc",
+                    Builtin::Calloc => "calloc",
+                    Builtin::Realloc => "realloc",
+                    _ => "free",
+                };
+                let id = self.c.allocator(name);
+                for value in values {
+                    self.get(value);
+                }
+                self.call_id(id);
+                if builtin != Builtin::Free {
+                    return Val::Scalar(self.set(ValType::I32));
+                }
+            }
+        }
+        Val::Scalar(self.i32(0))
+    }
+
+    /// The address of a NUL-terminated copy of `text`
+    fn c_string(&mut self, text: &str) -> Value {
+        let address = self.c.string(text, false);
+        self.i32(address as i32)
+    }
+
+    fn call_runtime(&mut self, name: &str, args: &[Value]) {
+        let id = self.c.runtime(name);
+        for &arg in args {
+            self.get(arg);
+        }
+        self.call_id(id);
+    }
+
+    /// Emit `report` and a trap, run when `cond` is true
+    fn trap_if(&mut self, cond: Value, report: impl FnOnce(&mut Self)) {
+        self.get(cond);
+        self.open(If(BlockType::Empty));
+        report(self);
+        self.emit(Unreachable);
+        self.close();
+    }
+
+    fn trap_message(&mut self, message: &str) {
+        let message = self.c_string(message);
+        self.call_runtime("trap", &[message]);
+    }
+
+    // ========================================================================
+    // Printing
+    // ========================================================================
+
+    fn print_str(&mut self, text: &str) {
+        let text = self.c_string(text);
+        self.call_runtime("print_str", &[text]);
+    }
+
+    fn print_byte(&mut self, byte: u8) {
+        let byte = self.i32(byte as i32);
+        self.call_runtime("print_byte", &[byte]);
+    }
+
+    /// Print a value that is part of another, inline if it is a primitive
+    fn print_nested(&mut self, ty: &Type, address: Value) {
+        if matches!(self.c.repr(ty), Repr::Scalar(_)) && self.c.type_def(ty).is_none() {
+            self.print_value(ty, address);
+            return;
+        }
+        match self.c.print_function(ty) {
+            Ok(id) => {
+                self.get(address);
+                self.call_id(id);
+            }
+            Err(message) => self.error(message),
+        }
+    }
+
+    /// Print the value of type `ty` at `address` as `print` shows it
+    fn print_value(&mut self, ty: &Type, address: Value) {
+        let layout = self.c.layout(ty);
+        match ty {
+            Type::F32 | Type::F64 => {
+                let single = *ty == Type::F32;
+                let value = self.load(if single { Scalar::F32 } else { Scalar::F64 }, false, address, 0);
+                let value = if single { self.op1(F64PromoteF32, value, ValType::F64) } else { value };
+                let single = self.i32(single as i32);
+                self.call_runtime("print_float", &[value, single]);
+            }
+            Type::Bool => {
+                let value = self.load(Scalar::I8, false, address, 0);
+                let yes = self.c_string("true");
+                let no = self.c_string("false");
+                let text = self.select(yes, no, value, ValType::I32);
+                self.call_runtime("print_str", &[text]);
+            }
+            Type::Ok => self.print_str("ok"),
+            Type::Null => self.print_str("null"),
+            Type::Pointer { .. } => {
+                let value = self.load(Scalar::I32, false, address, 0);
+                self.call_runtime("print_pointer", &[value]);
+            }
+            Type::Optional(inner) => {
+                match layout.shape {
+                    Shape::Niche { .. } => {
+                        let pointer = self.load(Scalar::I32, false, address, 0);
+                        self.get(pointer);
+                        self.open(If(BlockType::Empty));
+                        self.call_runtime("print_pointer", &[pointer]);
+                    }
+                    _ => {
+                        let tag_scalar = Codegen::tag(&layout);
+                        let tag = self.load(tag_scalar, false, address, 0);
+                        let some = self.nonzero(tag, tag_scalar);
+                        self.get(some);
+                        self.open(If(BlockType::Empty));
+                        let offset = self.c.offset(&layout, "some");
+                        let payload = self.offset(address, offset);
+                        self.print_nested(inner, payload);
+                    }
+                }
+                self.emit(Else);
+                self.print_str("null");
+                self.close();
+            }
+            Type::ErrorUnion { ok_type, err_type } => {
+                let tag_scalar = Codegen::tag(&layout);
+                let tag = self.load(tag_scalar, false, address, 0);
+                let failed = self.nonzero(tag, tag_scalar);
+                self.get(failed);
+                self.open(If(BlockType::Empty));
+                self.print_str("error(");
+                let offset = self.c.offset(&layout, "err");
+                let payload = self.offset(address, offset);
+                self.print_nested(&Type::Path(err_type.clone()), payload);
+                self.print_byte(b')');
+                self.emit(Else);
+                let offset = self.c.offset(&layout, "ok");
+                let payload = self.offset(address, offset);
+                self.print_nested(ok_type, payload);
+                self.close();
+            }
+            Type::Array { size: None, .. } => {
+                let pointer = self.load(Scalar::I32, false, address, 0);
+                let len = self.load(Scalar::I32, false, address, 4);
+                self.call_runtime("print_bytes", &[pointer, len]);
+            }
+            Type::Array { element_type, .. } => {
+                let Shape::Array { element, count } = &layout.shape else { return };
+                self.print_byte(b'[');
+                let i = self.i32(0);
+                let count = self.i32(*count as i32);
+                self.open(Block(BlockType::Empty));
+                self.open(Loop(BlockType::Empty));
+                let done = self.op2(I32GeU, i, count, ValType::I32);
+                self.get(done);
+                self.emit(BrIf(1));
+                self.get(i);
+                self.open(If(BlockType::Empty));
+                self.print_str(", ");
+                self.close();
+                let stride = self.i32(element.size as i32);
+                let offset = self.op2(I32Mul, i, stride, ValType::I32);
+                let element_address = self.op2(I32Add, address, offset, ValType::I32);
+                self.print_nested(element_type, element_address);
+                let one = self.i32(1);
+                self.get(i);
+                self.get(one);
+                self.emit(I32Add);
+                self.emit(LocalSet(i.0));
+                self.emit(Br(0));
+                self.close();
+                self.close();
+                self.print_byte(b']');
+            }
+            Type::Path(_) => match (self.c.type_def(ty), &layout.shape) {
+                (_, Shape::Enum { discriminants, signed }) => {
+                    let name = self.c.type_name(ty);
+                    let scalar = self.int_scalar(ty);
+                    let value = self.load(scalar, *signed, address, 0);
+                    self.open(Block(BlockType::Empty));
+                    for (variant, discriminant) in discriminants {
+                        let equal = self.eq_const(value, scalar, *signed, *discriminant);
+                        self.get(equal);
+                        self.open(If(BlockType::Empty));
+                        self.print_str(&format!("{}::{}", name, variant));
+                        self.emit(Br(1));
+                        self.close();
+                    }
+                    self.print_str(&format!("{}(", name));
+                    let wide = self.convert(value, scalar, *signed, Scalar::I64, *signed);
+                    self.call_runtime(if *signed { "print_i64" } else { "print_u64" }, &[wide]);
+                    self.print_byte(b')');
+                    self.close();
+                }
+                (Some(TypeDef::Struct(_)), _) => {
+                    let name = self.c.type_name(ty);
+                    let mut text = format!("{}(", name);
+                    for (i, (field, field_type)) in self.c.fields(ty).into_iter().enumerate() {
+                        if i > 0 {
+                            text.push_str(", ");
+                        }
+                        text.push_str(&field);
+                        text.push_str(": ");
+                        self.print_str(&std::mem::take(&mut text));
+                        let offset = self.c.offset(&layout, &field);
+                        let field_address = self.offset(address, offset);
+                        self.print_nested(&field_type, field_address);
+                    }
+                    text.push(')');
+                    self.print_str(&text);
+                }
+                (Some(TypeDef::Union(_)), _) => {
+                    let name = self.c.type_name(ty);
+                    let tag_scalar = Codegen::tag(&layout);
+                    let tag = self.load(tag_scalar, false, address, 0);
+                    self.open(Block(BlockType::Empty));
+                    for (i, (variant, payload_type)) in self.c.fields(ty).into_iter().enumerate() {
+                        let equal = self.eq_const(tag, tag_scalar, false, i as i128);
+                        self.get(equal);
+                        self.open(If(BlockType::Empty));
+                        self.print_str(&format!("{}::{}(", name, variant));
+                        if payload_type == Type::Ok {
+                            self.print_str("ok");
+                        } else {
+                            let offset = self.c.offset(&layout, &variant);
+                            let payload = self.offset(address, offset);
+                            self.print_nested(&payload_type, payload);
+                        }
+                        self.print_byte(b')');
+                        self.emit(Br(1));
+                        self.close();
+                    }
+                    self.print_str(&format!("{}(?)", name));
+                    self.close();
+                }
+                _ => self.error(format!("cannot print a value of type `{}`", format_type(ty))),
+            },
+            _ => {
+                let Some((scalar, signed)) = self.int_info(ty) else {
+                    self.error(format!("cannot print a value of type `{}`", format_type(ty)));
+                    return;
+                };
+                let value = self.load(scalar, signed, address, 0);
+                let wide = self.convert(value, scalar, signed, Scalar::I64, signed);
+                self.call_runtime(if signed { "print_i64" } else { "print_u64" }, &[wide]);
+            }
+        }
+    }
+}
+
+/// Which locals have their address taken, and so need frame space
+fn address_taken(body: &Body) -> Vec<bool> {
+    let mut taken = vec![false; body.locals.len()];
+    for block in &body.blocks {
+        for statement in &block.statements {
+            let (Statement::Assign(_, rvalue) | Statement::Eval(rvalue)) = statement;
+            if let Rvalue::AddressOf(place) | Rvalue::Unsize(place, _) | Rvalue::Subslice(place, ..) = rvalue {
+                // Through a pointer, the local itself is only read
+                if !place.projection.contains(&Projection::Deref) {
+                    taken[place.local.index()] = true;
+                }
+            }
+        }
+    }
+    taken
+}
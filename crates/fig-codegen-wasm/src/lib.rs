@@ -0,0 +1,36 @@
+//! WebAssembly backend for Fig
+//!
+//! [`WasmEmitter`] lowers a program to MIR and compiles each body to a
+//! function of one wasm32 module. Types are laid out by [`fig_sema::layout`]
+//! for [`TARGET`], and pointers are offsets into the module's linear memory,
+//! which it exports as `memory`:
+//!
+//! ```text
+//! 0        16                  STACK_TOP              heap
+//! | null   | shadow stack <-   | strings, tables      | malloc ->
+//! ```
+//!
+//! Aggregates and locals whose address is taken live in a frame on the
+//! shadow stack. `export` functions become wasm exports under their C names
+//! and each `extern func` is imported from the `env` module, both following
+//! the wasm32 C ABI: scalars are passed as themselves, aggregates as a
+//! pointer to a copy, and aggregate results through a hidden pointer. The
+//! allocator is a bump allocator inside the module.
+//!
+//! Checks and printing call functions the host provides in the `fig`
+//! module, listed in [`RUNTIME`]. Each `trap`-like import reports its
+//! failure and must not return; a host usually raises a wasm trap. Strings
+//! are passed as the address of NUL-terminated bytes.
+//!
+//! ```ignore
+//! let sf = SourceFileParser::new().parse(Lexer::new(src))?;
+//! let items = ItemTable::from_source_file(&sf);
+//! let module = WasmEmitter::new(&items).emit()?;
+//! std::fs::write("main.wasm", &module)?;
+//! ```
+
+mod alloc;
+mod emit;
+mod function;
+
+pub use emit::{RUNTIME, TARGET, WasmEmitter, to_wat};
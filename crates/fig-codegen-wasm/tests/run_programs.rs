@@ -0,0 +1,246 @@
+// Compiles every program in tests/run/ to a wasm module, validates it and
+// runs it in an embedded engine, checking it against the same header
+// comments the other backends' tests use:
+//
+//   // expect: <value>     the value `main` returns, printed after the output
+//   // output: <line>      one line of `print`/`println` output, in order
+//   // trap: <message>     the runtime error the program stops with
+//
+// The host below implements the `fig` runtime imports with the messages and
+// number formatting of the C runtime.
+
+use std::path::Path;
+
+use fig_codegen_c::EntryPoint;
+use fig_codegen_wasm::WasmEmitter;
+use fig_parser::{Lexer, SourceFileParser};
+use fig_sema::construct::resolve_construction;
+use fig_sema::items::ItemTable;
+use fig_test_support::{check_programs, header};
+use wasmi::{Caller, Engine, Error, Extern, Linker, Module, Store};
+
+#[derive(Default)]
+struct Host {
+    stdout: Vec<u8>,
+    trap: Option<String>,
+}
+
+fn memory<'c>(caller: &'c Caller<'_, Host>) -> &'c [u8] {
+    let memory = caller.get_export("memory").and_then(Extern::into_memory).expect("an exported memory");
+    memory.data(caller)
+}
+
+fn bytes(caller: &Caller<'_, Host>, address: i32, len: i32) -> Vec<u8> {
+    memory(caller)[address as u32 as usize..][..len as u32 as usize].to_vec()
+}
+
+fn c_str(caller: &Caller<'_, Host>, address: i32) -> String {
+    let data = &memory(caller)[address as u32 as usize..];
+    let len = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..len]).into_owned()
+}
+
+fn trap(mut caller: Caller<'_, Host>, message: String) -> Result<(), Error> {
+    caller.data_mut().trap = Some(message.clone());
+    Err(Error::new(message))
+}
+
+fn print(caller: &mut Caller<'_, Host>, text: impl AsRef<[u8]>) {
+    caller.data_mut().stdout.extend_from_slice(text.as_ref());
+}
+
+fn linker(engine: &Engine) -> Linker<Host> {
+    let mut linker = Linker::new(engine);
+    linker
+        .func_wrap("fig", "trap", |caller: Caller<'_, Host>, message: i32| {
+            let message = c_str(&caller, message);
+            trap(caller, message)
+        })
+        .unwrap()
+        .func_wrap("fig", "index", |caller: Caller<'_, Host>, index: i64, len: i64, signed: i32| {
+            let index = if signed != 0 { index.to_string() } else { (index as u64).to_string() };
+            trap(caller, format!("index {} is out of bounds for length {}", index, len as u64))
+        })
+        .unwrap()
+        .func_wrap("fig", "slice", |caller: Caller<'_, Host>, start: i32, end: i32, len: i32| {
+            let message = format!("slice {}..{} is out of range for length {}", start as u32, end as u32, len as u32);
+            trap(caller, message)
+        })
+        .unwrap()
+        .func_wrap("fig", "shift", |caller: Caller<'_, Host>, amount: i64, _bits: i64, ty: i32| {
+            let message = format!("shift by {} is out of range for `{}`", amount, c_str(&caller, ty));
+            trap(caller, message)
+        })
+        .unwrap()
+        .func_wrap("fig", "out_of_range", |caller: Caller<'_, Host>, value: i64, ty: i32| {
+            let message = format!("{} does not fit in `{}`", value, c_str(&caller, ty));
+            trap(caller, message)
+        })
+        .unwrap()
+        .func_wrap("fig", "inactive", |caller: Caller<'_, Host>, ty: i32, variant: i32, active: i32| {
+            let (ty, variant, active) = (c_str(&caller, ty), c_str(&caller, variant), c_str(&caller, active));
+            trap(caller, format!("read of variant `{}` of `{}`, but `{}` is active", variant, ty, active))
+        })
+        .unwrap()
+        .func_wrap("fig", "main_failed", |mut caller: Caller<'_, Host>| {
+            caller.data_mut().trap = Some("main returned an error".to_string());
+        })
+        .unwrap()
+        .func_wrap("fig", "print_i64", |mut caller: Caller<'_, Host>, value: i64| print(&mut caller, value.to_string()))
+        .unwrap()
+        .func_wrap("fig", "print_u64", |mut caller: Caller<'_, Host>, value: i64| {
+            print(&mut caller, (value as u64).to_string())
+        })
+        .unwrap()
+        .func_wrap("fig", "print_float", |mut caller: Caller<'_, Host>, value: f64, single: i32| {
+            let text = if single != 0 { (value as f32).to_string() } else { value.to_string() };
+            print(&mut caller, text)
+        })
+        .unwrap()
+        .func_wrap("fig", "print_pointer", |mut caller: Caller<'_, Host>, value: i32| {
+            print(&mut caller, format!("{:#x}", value as u32))
+        })
+        .unwrap()
+        .func_wrap("fig", "print_bytes", |mut caller: Caller<'_, Host>, address: i32, len: i32| {
+            let text = bytes(&caller, address, len);
+            print(&mut caller, text)
+        })
+        .unwrap()
+        .func_wrap("fig", "print_str", |mut caller: Caller<'_, Host>, address: i32| {
+            let text = c_str(&caller, address);
+            print(&mut caller, text)
+        })
+        .unwrap()
+        .func_wrap("fig", "print_byte", |mut caller: Caller<'_, Host>, byte: i32| print(&mut caller, [byte as u8]))
+        .unwrap();
+    linker
+}
+
+/// Validate and run a module's `main`, returning its output and any trap
+fn execute(module: &[u8]) -> Result<(String, Option<String>), String> {
+    wasmparser::Validator::new().validate_all(module).map_err(|e| format!("invalid module: {}", e))?;
+    let engine = Engine::default();
+    let module = Module::new(&engine, module).map_err(|e| e.to_string())?;
+    let mut store = Store::new(&engine, Host::default());
+    let instance = linker(&engine)
+        .instantiate(&mut store, &module)
+        .and_then(|pre| pre.start(&mut store))
+        .map_err(|e| e.to_string())?;
+    let main = instance.get_typed_func::<(), i32>(&store, "main").map_err(|e| e.to_string())?;
+    let result = main.call(&mut store, ());
+    let host = store.into_data();
+    let stdout = String::from_utf8_lossy(&host.stdout).into_owned();
+    match (result, host.trap) {
+        (Ok(_), trap) => Ok((stdout, trap)),
+        (Err(_), Some(trap)) => Ok((stdout, Some(trap))),
+        (Err(e), None) => Err(format!("trapped without a report: {}", e)),
+    }
+}
+
+fn run(path: &Path) -> Result<(), String> {
+    let src = std::fs::read_to_string(path).unwrap();
+    let sf = SourceFileParser::new()
+        .parse(Lexer::new(&src))
+        .map_err(|e| format!("parse error: {:?}", e))?;
+    let sf = resolve_construction(sf);
+    let items = ItemTable::from_source_file(&sf);
+    let module = WasmEmitter::new(&items)
+        .with_entry(EntryPoint::PrintResult)
+        .emit()
+        .map_err(|diagnostics| diagnostics.iter().map(|d| d.to_string()).collect::<Vec<_>>().join("\n"))?;
+
+    let (stdout, trap) = execute(&module)?;
+    let mut expected_output = header(&src, "output");
+    match (header(&src, "expect").first(), header(&src, "trap").first(), &trap) {
+        (Some(expected), None, None) => expected_output.push(expected),
+        (None, Some(expected), Some(trap)) if trap == expected => {}
+        _ => return Err(format!("stopped with {:?}", trap)),
+    }
+    let output: Vec<&str> = stdout.lines().collect();
+    if output != expected_output {
+        return Err(format!("printed {:?}, expected {:?}", output, expected_output));
+    }
+    Ok(())
+}
+
+#[test]
+fn run_programs() {
+    check_programs(run);
+}
+
+fn compile(src: &str, entry: EntryPoint) -> Vec<u8> {
+    let sf = SourceFileParser::new().parse(Lexer::new(src)).unwrap();
+    let items = ItemTable::from_source_file(&sf);
+    WasmEmitter::new(&items).with_entry(entry).emit().unwrap_or_else(|diags| panic!("{:?}", diags))
+}
+
+/// What `main` prints, or the trap it stops with
+fn outcome(src: &str) -> String {
+    match execute(&compile(src, EntryPoint::PrintResult)).unwrap() {
+        (stdout, None) => stdout.trim_end().to_string(),
+        (_, Some(trap)) => format!("error: {}", trap),
+    }
+}
+
+#[test]
+fn test_exports_and_extern_imports_in_a_sandbox() {
+    let module = compile(
+        "\
+extern func! record(value: i64) -> ok
+
+export func scale(x: i32, factor: i32) -> i32
+    return x * factor
+
+func! main() -> i32
+    record(40)
+    record(2)
+    return scale(7, 6)
+",
+        EntryPoint::ExitCode,
+    );
+    let engine = Engine::default();
+    let module = Module::new(&engine, &module[..]).unwrap();
+    let mut store = Store::new(&engine, Vec::<i64>::new());
+    let mut linker = Linker::new(&engine);
+    linker.func_wrap("env", "record", |mut caller: Caller<'_, Vec<i64>>, value: i64| caller.data_mut().push(value)).unwrap();
+    linker.func_wrap("fig", "trap", |_: Caller<'_, Vec<i64>>, _: i32| Err::<(), _>(Error::new("trap"))).unwrap();
+    let instance = linker.instantiate(&mut store, &module).unwrap().start(&mut store).unwrap();
+
+    let main = instance.get_typed_func::<(), i32>(&store, "main").unwrap();
+    assert_eq!(main.call(&mut store, ()).unwrap(), 42);
+    assert_eq!(store.data(), &[40, 2]);
+    // An `export` function can be called on its own, and its checks still trap
+    let scale = instance.get_typed_func::<(i32, i32), i32>(&store, "scale").unwrap();
+    assert_eq!(scale.call(&mut store, (-3, 5)).unwrap(), -15);
+    assert!(scale.call(&mut store, (1 << 20, 1 << 20)).is_err());
+}
+
+#[test]
+fn test_checked_arithmetic_matches_the_other_backends() {
+    let cases = [
+        ("func main() -> i64\n    let a: i64 = 3037000499\n    return a * a\n", "9223372030926249001"),
+        ("func main() -> i64\n    let a: i64 = 3037000500\n    return a * a\n", "error: `i64` multiplication overflowed"),
+        ("func main() -> i64\n    let a: i64 = -9223372036854775807\n    return a - 1\n", "-9223372036854775808"),
+        ("func main() -> i64\n    let a: i64 = -9223372036854775807\n    return a - 2\n", "error: `i64` subtraction overflowed"),
+        ("func main() -> u64\n    let a: u64 = 18446744073709551615\n    return a + 1\n", "error: `u64` addition overflowed"),
+        ("func main() -> u32\n    let a: u32 = 4294967295\n    return a / 3\n", "1431655765"),
+        ("func main() -> i32\n    let a: i32 = 2147483647\n    return a + 1\n", "error: `i32` addition overflowed"),
+        ("func main() -> i8\n    let a: i8 = -128\n    return a % -1\n", "0"),
+        ("func main() -> i8\n    let a: i8 = -128\n    return a / -1\n", "error: `i8` division overflowed"),
+        ("func main() -> i16\n    let a: i16 = -300\n    return a * 100\n", "-30000"),
+        ("func main() -> u8\n    let a: u8 = 1\n    return a << 7\n", "128"),
+        ("func main() -> u8\n    let a: u8 = 1\n    return a << 8\n", "error: shift by 8 is out of range for `u8`"),
+        ("func main() -> u8\n    let a: u8 = 0\n    return ~a\n", "255"),
+        ("func main() -> i8\n    let a: i32 = 200\n    return a as i8\n", "-56"),
+        ("func main() -> u8\n    let a: f64 = 300.5\n    return a as u8\n", "255"),
+        ("func main() -> f32\n    let a: i32 = 3\n    return a as f32 / 2.0\n", "1.5"),
+    ];
+    let failures: Vec<String> = cases
+        .iter()
+        .filter_map(|(src, expected)| {
+            let actual = outcome(src);
+            (actual != *expected).then(|| format!("{:?}: got {:?}, expected {:?}", src, actual, expected))
+        })
+        .collect();
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
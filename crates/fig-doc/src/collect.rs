@@ -0,0 +1,402 @@
+//! Gathering the documented items of a source file into pages
+
+use std::collections::{HashMap, VecDeque};
+
+use fig_parser::ast::*;
+use fig_parser::format::{
+    format_expression, format_generic_params, format_head, format_item, format_signature, format_type,
+};
+use fig_sema::index::{DefKind, index, tokenize};
+
+use crate::{Documentation, Entry, Member, Options, Page};
+
+/// Gather the items of `file`, parsed from `text`, into pages by namespace
+pub fn document(file: &SourceFile, text: &str, options: &Options) -> Documentation {
+    let (tokens, _) = tokenize(text);
+    let mut docs: HashMap<(DefKind, String), VecDeque<String>> = HashMap::new();
+    for def in index(file, text, &tokens) {
+        if def.kind != DefKind::Local {
+            docs.entry((def.kind, def.qualified)).or_default().push_back(def.docs);
+        }
+    }
+    let mut collector = Collector { options, docs, pages: Vec::new(), on_types: Vec::new() };
+    let mut namespace = Vec::new();
+    for item in &file.items {
+        let item = match item {
+            NamespaceItem::NamespaceDeclaration(decl) => {
+                namespace = decl.name.segments.clone();
+                let docs = collector.docs(DefKind::Namespace, &namespace);
+                collector.namespace_docs(&namespace, docs);
+                continue;
+            }
+            NamespaceItem::Namespace(ns) => Item::Namespace(ns),
+            NamespaceItem::Function(f) => Item::Function(&f.signature),
+            NamespaceItem::FunctionDeclaration(d) => Item::Function(&d.signature),
+            NamespaceItem::TypeAlias(a) => Item::Alias(a),
+            NamespaceItem::Struct(s) => Item::Struct(s),
+            NamespaceItem::Enum(e) => Item::Enum(e),
+            NamespaceItem::Union(u) => Item::Union(u),
+            NamespaceItem::Interface(i) => Item::Interface(i),
+            NamespaceItem::Const(c) => Item::Const(c),
+            NamespaceItem::Using(_) => continue,
+        };
+        collector.item(item, &namespace);
+    }
+    collector.finish()
+}
+
+/// An item, whether it appears at the top level or in a namespace block
+enum Item<'a> {
+    Namespace(&'a Namespace),
+    Function(&'a FunctionSignature),
+    Alias(&'a TypeAlias),
+    Struct(&'a Struct),
+    Enum(&'a Enum),
+    Union(&'a Union),
+    Interface(&'a Interface),
+    Const(&'a ConstStatement),
+}
+
+/// A function or constant declared on a type, e.g. `Point::origin`, kept
+/// until every type is known
+struct OnType {
+    namespace: Vec<String>,
+    receiver: Vec<String>,
+    visibility: Visibility,
+    member: Member,
+}
+
+struct Collector<'o> {
+    options: &'o Options,
+    /// The doc comments of each declaration, in source order
+    docs: HashMap<(DefKind, String), VecDeque<String>>,
+    pages: Vec<Page>,
+    on_types: Vec<OnType>,
+}
+
+impl Collector<'_> {
+    /// The doc comment of the next declaration of `path`. Every declaration
+    /// takes its own, documented or not, so the rest stay in order.
+    fn docs(&mut self, kind: DefKind, path: &[String]) -> String {
+        self.docs.get_mut(&(kind, path.join("::"))).and_then(VecDeque::pop_front).unwrap_or_default()
+    }
+
+    fn documented(&self, visibility: &Visibility) -> bool {
+        self.options.private || *visibility == Visibility::Export
+    }
+
+    fn page(&mut self, namespace: &[String]) -> &mut Page {
+        let found = self.pages.iter().position(|page| page.namespace == namespace);
+        let index = found.unwrap_or_else(|| {
+            self.pages.push(Page { namespace: namespace.to_vec(), ..Page::default() });
+            self.pages.len() - 1
+        });
+        &mut self.pages[index]
+    }
+
+    fn namespace_docs(&mut self, namespace: &[String], docs: String) {
+        let page = self.page(namespace);
+        if !docs.is_empty() {
+            if !page.docs.is_empty() {
+                page.docs.push_str("\n\n");
+            }
+            page.docs.push_str(&docs);
+        }
+    }
+
+    fn item(&mut self, item: Item, namespace: &[String]) {
+        match item {
+            Item::Namespace(ns) => {
+                let mut inner = namespace.to_vec();
+                inner.extend(ns.name.segments.iter().cloned());
+                let docs = self.docs(DefKind::Namespace, &inner);
+                self.namespace_docs(&inner, docs);
+                for statement in &ns.items {
+                    let item = match statement {
+                        Statement::Namespace(n) => Item::Namespace(n),
+                        Statement::Function(f) => Item::Function(&f.signature),
+                        Statement::FunctionDeclaration(d) => Item::Function(&d.signature),
+                        Statement::TypeAlias(a) => Item::Alias(a),
+                        Statement::Struct(s) => Item::Struct(s),
+                        Statement::Enum(e) => Item::Enum(e),
+                        Statement::Union(u) => Item::Union(u),
+                        Statement::Interface(i) => Item::Interface(i),
+                        Statement::Const(c) => Item::Const(c),
+                        _ => continue,
+                    };
+                    self.item(item, &inner);
+                }
+            }
+            Item::Function(signature) => self.function(signature, namespace),
+            Item::Alias(a) => {
+                let declaration = rendering(NamespaceItem::TypeAlias(a.clone()));
+                let mut entry = self.entry(DefKind::Alias, &a.name, namespace, &a.visibility, declaration);
+                entry.generic_params = generic_params(&a.generic_params);
+                self.add(entry, namespace, &a.visibility);
+            }
+            Item::Struct(s) => {
+                let declaration = rendering(NamespaceItem::Struct(s.clone()));
+                let mut entry = self.entry(DefKind::Struct, &s.name, namespace, &s.visibility, declaration);
+                entry.generic_params = generic_params(&s.generic_params);
+                entry.requires = s.requires.clone();
+                for field in &s.fields {
+                    let declaration = format!("{}: {}", field.name, format_type(&field.ty));
+                    let member = self.member(DefKind::Field, &field.name, namespace, &s.name, declaration);
+                    entry.members.push(member);
+                }
+                self.add(entry, namespace, &s.visibility);
+            }
+            Item::Union(u) => {
+                let declaration = rendering(NamespaceItem::Union(u.clone()));
+                let mut entry = self.entry(DefKind::Union, &u.name, namespace, &u.visibility, declaration);
+                entry.generic_params = generic_params(&u.generic_params);
+                entry.requires = u.requires.clone();
+                for variant in &u.variants {
+                    let declaration = format!("{}: {}", variant.name, format_type(&variant.ty));
+                    let member = self.member(DefKind::Variant, &variant.name, namespace, &u.name, declaration);
+                    entry.members.push(member);
+                }
+                self.add(entry, namespace, &u.visibility);
+            }
+            Item::Enum(e) => {
+                let declaration = rendering(NamespaceItem::Enum(e.clone()));
+                let mut entry = self.entry(DefKind::Enum, &e.name, namespace, &e.visibility, declaration);
+                entry.generic_params = generic_params(&e.generic_params);
+                entry.requires = e.requires.clone();
+                for variant in &e.variants {
+                    let declaration = match &variant.value {
+                        Some(value) => format!("{} = {}", variant.name, format_expression(value)),
+                        None => variant.name.clone(),
+                    };
+                    let member = self.member(DefKind::Variant, &variant.name, namespace, &e.name, declaration);
+                    entry.members.push(member);
+                }
+                self.add(entry, namespace, &e.visibility);
+            }
+            Item::Interface(i) => {
+                let mut declaration = format!(
+                    "{}interface {}{}",
+                    format_head(&i.visibility, &i.annotations),
+                    i.name,
+                    format_generic_params(&i.generic_params)
+                );
+                for (keyword, types) in [("extends", &i.extends), ("requires", &i.requires)] {
+                    if !types.is_empty() {
+                        declaration.push_str(&format!("\n    {}", keyword));
+                        for ty in types {
+                            declaration.push_str(&format!("\n        {}", format_type(ty)));
+                        }
+                    }
+                }
+                let mut entry = self.entry(DefKind::Interface, &i.name, namespace, &i.visibility, declaration);
+                entry.generic_params = generic_params(&i.generic_params);
+                entry.extends = i.extends.clone();
+                entry.requires = i.requires.clone();
+                for method in &i.methods {
+                    let declaration = format_signature(method);
+                    let member = self.member(DefKind::Method, &method.name, namespace, &i.name, declaration);
+                    entry.members.push(member);
+                }
+                self.add(entry, namespace, &i.visibility);
+            }
+            Item::Const(c) => {
+                let declaration = rendering(NamespaceItem::Const(c.clone()));
+                let receiver: Vec<String> = c.receiver.iter().map(|segment| segment.name.clone()).collect();
+                let mut path = namespace.to_vec();
+                path.extend(receiver.iter().cloned());
+                path.push(c.name.clone());
+                let docs = self.docs(DefKind::Const, &path);
+                if !self.documented(&c.visibility) {
+                    return;
+                }
+                if !receiver.is_empty() {
+                    let member = Member { kind: DefKind::Const, name: c.name.clone(), declaration, docs };
+                    let visibility = c.visibility.clone();
+                    self.on_types.push(OnType { namespace: namespace.to_vec(), receiver, visibility, member });
+                    return;
+                }
+                let entry = Entry {
+                    generic_params: generic_params(&c.generic_params),
+                    ..new_entry(DefKind::Const, &c.name, &c.visibility, declaration, docs)
+                };
+                self.page(namespace).entries.push(entry);
+            }
+        }
+    }
+
+    fn function(&mut self, signature: &FunctionSignature, namespace: &[String]) {
+        let receiver: Vec<String> = signature.receiver.iter().flat_map(|r| r.segments.iter().cloned()).collect();
+        let mut path = namespace.to_vec();
+        path.extend(receiver.iter().cloned());
+        path.push(signature.name.clone());
+        let docs = self.docs(DefKind::Function, &path);
+        if !self.documented(&signature.visibility) {
+            return;
+        }
+        let declaration = format_signature(signature);
+        if !receiver.is_empty() {
+            let member = Member { kind: DefKind::Function, name: signature.name.clone(), declaration, docs };
+            let visibility = signature.visibility.clone();
+            self.on_types.push(OnType { namespace: namespace.to_vec(), receiver, visibility, member });
+            return;
+        }
+        let entry = Entry {
+            generic_params: generic_params(&signature.generic_params),
+            ..new_entry(DefKind::Function, &signature.name, &signature.visibility, declaration, docs)
+        };
+        self.page(namespace).entries.push(entry);
+    }
+
+    /// An entry for a type or interface, taking its doc comment
+    fn entry(
+        &mut self,
+        kind: DefKind,
+        name: &str,
+        namespace: &[String],
+        visibility: &Visibility,
+        declaration: String,
+    ) -> Entry {
+        let mut path = namespace.to_vec();
+        path.push(name.to_string());
+        let docs = self.docs(kind, &path);
+        new_entry(kind, name, visibility, declaration, docs)
+    }
+
+    fn member(&mut self, kind: DefKind, name: &str, namespace: &[String], parent: &str, declaration: String) -> Member {
+        let mut path = namespace.to_vec();
+        path.push(parent.to_string());
+        path.push(name.to_string());
+        let docs = self.docs(kind, &path);
+        Member { kind, name: name.to_string(), declaration, docs }
+    }
+
+    /// Add `entry` to its namespace's page if it is documented. The page is
+    /// made either way, so namespaces keep the order they are declared in.
+    fn add(&mut self, entry: Entry, namespace: &[String], visibility: &Visibility) {
+        let documented = self.documented(visibility);
+        let page = self.page(namespace);
+        if documented {
+            page.entries.push(entry);
+        }
+    }
+
+    /// Put each function and constant declared on a type under the type,
+    /// or on its own when the type is not documented, and drop the pages
+    /// left empty
+    fn finish(mut self) -> Documentation {
+        for on_type in std::mem::take(&mut self.on_types) {
+            let Some((name, prefix)) = on_type.receiver.split_last() else { continue };
+            let mut namespace = on_type.namespace.clone();
+            namespace.extend(prefix.iter().cloned());
+            let owner = self.pages.iter_mut().filter(|page| page.namespace == namespace).find_map(|page| {
+                page.entries.iter_mut().find(|entry| {
+                    entry.name == *name
+                        && matches!(entry.kind, DefKind::Struct | DefKind::Union | DefKind::Enum | DefKind::Interface)
+                })
+            });
+            match owner {
+                Some(entry) => entry.members.push(on_type.member),
+                None => {
+                    let member = on_type.member;
+                    let name = format!("{}::{}", on_type.receiver.join("::"), member.name);
+                    let entry = new_entry(member.kind, &name, &on_type.visibility, member.declaration, member.docs);
+                    self.page(&on_type.namespace).entries.push(entry);
+                }
+            }
+        }
+        self.pages.retain(|page| !page.entries.is_empty());
+        Documentation { pages: self.pages }
+    }
+}
+
+fn new_entry(kind: DefKind, name: &str, visibility: &Visibility, declaration: String, docs: String) -> Entry {
+    Entry {
+        kind,
+        name: name.to_string(),
+        visibility: visibility.clone(),
+        declaration,
+        generic_params: Vec::new(),
+        extends: Vec::new(),
+        requires: Vec::new(),
+        docs,
+        members: Vec::new(),
+    }
+}
+
+fn rendering(item: NamespaceItem) -> String {
+    format_item(&item).unwrap_or_default().trim_end().to_string()
+}
+
+fn generic_params(params: &[GenericParameter]) -> Vec<String> {
+    params
+        .iter()
+        .map(|param| {
+            let list = format_generic_params(std::slice::from_ref(param));
+            list[1..list.len() - 1].to_string()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fig_parser::{Lexer, SourceFileParser};
+
+    const SRC: &str = "\
+/// Plane geometry
+namespace geo
+
+/// A point
+export #deprecated
+struct Point
+    /// Across
+    x: i32
+    y: i32
+
+struct Hidden
+    z: i32
+
+/// Its distance from the origin
+export func Point::norm(self) -> i32
+    return self.x + self.y
+
+func Hidden::get(self) -> i32
+    return self.z
+
+export interface Shape[T: Point]
+    extends
+        Sized
+    func area(*self) -> T
+";
+
+    fn collect(options: &Options) -> Documentation {
+        let file = SourceFileParser::new().parse(Lexer::new(SRC)).unwrap();
+        document(&file, SRC, options)
+    }
+
+    #[test]
+    fn test_exported_items_with_docs() {
+        let documentation = collect(&Options::default());
+        let [page] = &documentation.pages[..] else { panic!("{:?}", documentation.pages) };
+        assert_eq!(page.title(), "geo");
+        assert_eq!(page.docs, "Plane geometry");
+        let names: Vec<&str> = page.entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["Point", "Shape"]);
+        let point = &page.entries[0];
+        assert_eq!(point.docs, "A point");
+        let members: Vec<(&str, &str)> = point.members.iter().map(|m| (m.name.as_str(), m.docs.as_str())).collect();
+        assert_eq!(members, [("x", "Across"), ("y", ""), ("norm", "Its distance from the origin")]);
+        let shape = &page.entries[1];
+        assert_eq!(shape.generic_params, ["T: Point"]);
+        assert_eq!(shape.extends.len(), 1);
+        assert!(shape.declaration.starts_with("export interface Shape[T: Point]\n    extends\n        Sized"));
+    }
+
+    #[test]
+    fn test_private_items_on_request() {
+        let documentation = collect(&Options { private: true });
+        let names: Vec<&str> = documentation.pages[0].entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["Point", "Hidden", "Shape"]);
+        assert_eq!(documentation.pages[0].entries[1].members[1].name, "get");
+    }
+}
@@ -0,0 +1,287 @@
+//! Rendering documentation as static HTML
+
+use fig_highlight::{CSS, Class, highlight};
+use fig_parser::ast::{Type, Visibility};
+use fig_parser::format::format_type;
+use fig_sema::index::DefKind;
+
+use crate::search::search_index;
+use crate::{Documentation, Entry, Links, Member, Page, kind_name, summary};
+
+const STYLE: &str = "\
+body { font-family: sans-serif; margin: 0; display: flex; }
+nav { min-width: 14em; padding: 1em; background: #f6f8fa; min-height: 100vh; }
+nav ul { list-style: none; padding-left: 0; }
+main { padding: 1em 2em; max-width: 60em; }
+pre.fig { background: #f6f8fa; padding: 0.75em; overflow-x: auto; }
+pre.fig a { color: inherit; text-decoration: underline dotted; }
+.kind { color: #8e908c; font-weight: normal; }
+.visibility { font-size: 0.8em; border: 1px solid #ccc; border-radius: 3px; padding: 0 0.3em; }
+.member { margin-left: 1.5em; }
+dl.clauses dt { font-weight: bold; }
+#results li { margin: 0.3em 0; }
+";
+
+/// A script that lists the entries of `FIG_SEARCH_INDEX` whose path
+/// contains what is typed in `#search`
+const SEARCH: &str = "\
+const input = document.getElementById('search');
+const results = document.getElementById('results');
+input.addEventListener('input', () => {
+    const query = input.value.toLowerCase();
+    results.innerHTML = '';
+    if (!query) return;
+    for (const entry of FIG_SEARCH_INDEX) {
+        if (!entry.path.toLowerCase().includes(query)) continue;
+        const item = document.createElement('li');
+        const link = document.createElement('a');
+        link.href = entry.url;
+        link.textContent = entry.path;
+        item.append(link, ' ' + entry.kind + (entry.summary ? ' \\u2014 ' + entry.summary : ''));
+        results.append(item);
+    }
+});
+";
+
+/// The documentation as HTML files, by file name: `index.html` with a
+/// search box, a page per namespace, `style.css` and the search index as
+/// `search-index.json` and as the script `search-index.js`
+pub fn to_html(documentation: &Documentation) -> Vec<(String, String)> {
+    let links = Links::new(documentation, "html");
+    let index = search_index(documentation, "html");
+    let mut files = vec![
+        ("index.html".to_string(), index_page(documentation)),
+        ("style.css".to_string(), format!("{}{}", STYLE, CSS)),
+        ("search-index.js".to_string(), format!("const FIG_SEARCH_INDEX = {};\n{}", index, SEARCH)),
+        ("search-index.json".to_string(), format!("{}\n", index)),
+    ];
+    for (number, page) in documentation.pages.iter().enumerate() {
+        files.push((format!("{}.html", page.stem()), namespace_page(documentation, page, number, &links)));
+    }
+    files
+}
+
+fn index_page(documentation: &Documentation) -> String {
+    let mut body = String::from("<h1>API documentation</h1>\n");
+    body.push_str("<input id=\"search\" type=\"search\" placeholder=\"Search\" autocomplete=\"off\">\n");
+    body.push_str("<ul id=\"results\"></ul>\n<h2>Namespaces</h2>\n<ul>\n");
+    for page in &documentation.pages {
+        body.push_str(&format!("<li><a href=\"{}.html\"><code>{}</code></a>", page.stem(), escape(&page.title())));
+        let summary = summary(&page.docs);
+        if !summary.is_empty() {
+            body.push_str(&format!(" \u{2014} {}", inline(&summary)));
+        }
+        body.push_str("</li>\n");
+    }
+    body.push_str("</ul>\n<script src=\"search-index.js\"></script>\n");
+    document("API documentation", &navigation(documentation), &body)
+}
+
+fn namespace_page(documentation: &Documentation, page: &Page, number: usize, links: &Links) -> String {
+    let title = page.title();
+    let mut body = format!("<h1>Namespace <code>{}</code></h1>\n", escape(&title));
+    body.push_str(&prose(&page.docs));
+    for entry in &page.entries {
+        body.push_str(&entry_section(entry, number, links));
+    }
+    document(&title, &navigation(documentation), &body)
+}
+
+fn entry_section(entry: &Entry, page: usize, links: &Links) -> String {
+    let anchor = entry.anchor();
+    let mut html = format!(
+        "<section id=\"{}\">\n<h2><span class=\"kind\">{}</span> <code>{}</code>",
+        escape(&anchor),
+        kind_name(entry.kind),
+        escape(&entry.name)
+    );
+    if let Some(keyword) = visibility(&entry.visibility) {
+        html.push_str(&format!(" <span class=\"visibility\">{}</span>", keyword));
+    }
+    html.push_str("</h2>\n");
+    html.push_str(&code(&entry.declaration, page, links));
+    let mut clauses = String::new();
+    if !entry.generic_params.is_empty() {
+        let params: Vec<String> = entry.generic_params.iter().map(|p| format!("<code>{}</code>", escape(p))).collect();
+        clauses.push_str(&format!("<dt>Generic parameters</dt><dd>{}</dd>\n", params.join(", ")));
+    }
+    for (name, types) in [("Extends", &entry.extends), ("Requires", &entry.requires)] {
+        if !types.is_empty() {
+            let types: Vec<String> = types.iter().map(|ty| clause(ty, page, links)).collect();
+            clauses.push_str(&format!("<dt>{}</dt><dd>{}</dd>\n", name, types.join(", ")));
+        }
+    }
+    if !clauses.is_empty() {
+        html.push_str(&format!("<dl class=\"clauses\">\n{}</dl>\n", clauses));
+    }
+    html.push_str(&prose(&entry.docs));
+    for (heading, kinds) in [
+        ("Fields", &[DefKind::Field][..]),
+        ("Variants", &[DefKind::Variant]),
+        ("Methods", &[DefKind::Method, DefKind::Function]),
+        ("Constants", &[DefKind::Const]),
+    ] {
+        let members: Vec<&Member> = entry.members.iter().filter(|m| kinds.contains(&m.kind)).collect();
+        if members.is_empty() {
+            continue;
+        }
+        html.push_str(&format!("<h3>{}</h3>\n", heading));
+        for member in members {
+            html.push_str(&format!("<div class=\"member\" id=\"{}.{}\">\n", escape(&anchor), escape(&member.name)));
+            html.push_str(&code(&member.declaration, page, links));
+            html.push_str(&prose(&member.docs));
+            html.push_str("</div>\n");
+        }
+    }
+    html.push_str("</section>\n");
+    html
+}
+
+/// The list of namespaces every page starts with
+fn navigation(documentation: &Documentation) -> String {
+    let mut html = String::from("<p><a href=\"index.html\">Index</a></p>\n<ul>\n");
+    for page in &documentation.pages {
+        html.push_str(&format!("<li><a href=\"{}.html\">{}</a></li>\n", page.stem(), escape(&page.title())));
+    }
+    html.push_str("</ul>\n");
+    html
+}
+
+fn document(title: &str, navigation: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+         <link rel=\"stylesheet\" href=\"style.css\">\n</head>\n<body>\n<nav>\n{}</nav>\n<main>\n{}</main>\n\
+         </body>\n</html>\n",
+        escape(title),
+        navigation,
+        body
+    )
+}
+
+/// A declaration, highlighted, with each documented type it names linked
+fn code(source: &str, page: usize, links: &Links) -> String {
+    let mut html = String::from("<pre class=\"fig\"><code>");
+    let mut at = 0;
+    for found in highlight(source) {
+        if found.span.start < at {
+            continue;
+        }
+        names(&source[at..found.span.start], page, links, &mut html);
+        let text = &source[found.span.clone()];
+        match (found.class, links.resolve(text, page)) {
+            (Class::Type, Some(url)) => {
+                html.push_str(&format!("<a class=\"fig-type\" href=\"{}\">{}</a>", url, escape(text)))
+            }
+            (class, _) => html.push_str(&format!("<span class=\"fig-{}\">{}</span>", class.name(), escape(text))),
+        }
+        at = found.span.end;
+    }
+    names(&source[at..], page, links, &mut html);
+    html.push_str("</code></pre>\n");
+    html
+}
+
+/// Unclassified source, with the names of documented types linked. The
+/// highlighter only knows the types a declaration itself declares.
+fn names(text: &str, page: usize, links: &Links, html: &mut String) {
+    let mut rest = text;
+    while let Some(start) = rest.find(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        let length = rest[start..].find(|c: char| !c.is_ascii_alphanumeric() && c != '_');
+        let end = length.map_or(rest.len(), |n| start + n);
+        html.push_str(&escape(&rest[..start]));
+        let name = &rest[start..end];
+        match links.resolve(name, page) {
+            Some(url) => html.push_str(&format!("<a class=\"fig-type\" href=\"{}\">{}</a>", url, name)),
+            None => html.push_str(name),
+        }
+        rest = &rest[end..];
+    }
+    html.push_str(&escape(rest));
+}
+
+/// A type from an `extends` or `requires` clause, linked when documented
+fn clause(ty: &Type, page: usize, links: &Links) -> String {
+    let text = format!("<code>{}</code>", escape(&format_type(ty)));
+    match Links::clause_target(ty).and_then(|name| links.resolve(name, page)) {
+        Some(url) => format!("<a href=\"{}\">{}</a>", url, text),
+        None => text,
+    }
+}
+
+/// A doc comment as paragraphs, with `code` spans
+fn prose(docs: &str) -> String {
+    let mut html = String::new();
+    let mut paragraph: Vec<&str> = Vec::new();
+    for line in docs.lines().chain([""]) {
+        if line.trim().is_empty() {
+            if !paragraph.is_empty() {
+                html.push_str(&format!("<p>{}</p>\n", inline(&paragraph.join("\n"))));
+                paragraph.clear();
+            }
+        } else {
+            paragraph.push(line.trim());
+        }
+    }
+    html
+}
+
+/// Escape `text`, making the stretches between backticks `<code>`
+fn inline(text: &str) -> String {
+    text.split('`')
+        .enumerate()
+        .map(|(i, piece)| if i % 2 == 1 { format!("<code>{}</code>", escape(piece)) } else { escape(piece) })
+        .collect()
+}
+
+fn visibility(visibility: &Visibility) -> Option<&'static str> {
+    match visibility {
+        Visibility::Default => None,
+        Visibility::Public => Some("public"),
+        Visibility::Export => Some("export"),
+        Visibility::Private => Some("private"),
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut html = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '&' => html.push_str("&amp;"),
+            '"' => html.push_str("&quot;"),
+            c => html.push(c),
+        }
+    }
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Options, document as collect};
+    use fig_parser::{Lexer, SourceFileParser};
+
+    #[test]
+    fn test_links_and_prose() {
+        let src = "\
+namespace geo
+
+/// A point with `i32` coordinates
+export struct Point
+    x: i32
+    y: i32
+
+/// Points one step apart
+export struct Line
+    a: Point
+    b: Point
+";
+        let file = SourceFileParser::new().parse(Lexer::new(src)).unwrap();
+        let files = to_html(&collect(&file, src, &Options::default()));
+        let (_, page) = files.iter().find(|(name, _)| name == "geo.html").unwrap();
+        assert!(page.contains("<p>A point with <code>i32</code> coordinates</p>"), "{}", page);
+        assert!(page.contains("<span class=\"fig-field\">a</span>: <a class=\"fig-type\" href=\"geo.html#Point\">"));
+        assert!(page.contains("<div class=\"member\" id=\"Line.b\">"), "{}", page);
+    }
+}
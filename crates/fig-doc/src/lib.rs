@@ -0,0 +1,179 @@
+//! API documentation for Fig sources
+//!
+//! [`document`] gathers the items of a source file into one [`Page`] per
+//! namespace: each item's declaration rendered without bodies, its generic
+//! parameters, `requires` and `extends` clauses, visibility and doc
+//! comment, with fields, variants, interface methods and the functions and
+//! constants declared on a type listed under the type. Only `export` items
+//! are documented unless [`Options::private`] is set.
+//!
+//! Doc comments are the `///` lines directly above a declaration. The lexer
+//! skips comments, so they are found through the declarations the
+//! [`fig_sema::index`] locates in the source.
+//!
+//! ```ignore
+//! let documentation = document(&file, &src, &Options::default());
+//! for (name, contents) in to_html(&documentation) {
+//!     std::fs::write(dir.join(name), contents)?;
+//! }
+//! ```
+//!
+//! [`to_html`] and [`to_markdown`] render a set of linked pages, an index
+//! and a search index. Types and interfaces named in declarations and
+//! clauses link to their own documentation.
+
+mod collect;
+mod html;
+mod markdown;
+mod search;
+
+use std::collections::HashMap;
+
+use fig_parser::ast::{Type, Visibility};
+use fig_sema::index::DefKind;
+
+pub use collect::document;
+pub use html::to_html;
+pub use markdown::to_markdown;
+
+/// What to document
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// Also document items that are not `export`
+    pub private: bool,
+}
+
+/// The documentation of a source file
+#[derive(Debug, Clone, Default)]
+pub struct Documentation {
+    /// One page per namespace that has documented items, in source order
+    pub pages: Vec<Page>,
+}
+
+/// The documented items of a namespace
+#[derive(Debug, Clone, Default)]
+pub struct Page {
+    /// The namespace's path, empty for items outside any namespace
+    pub namespace: Vec<String>,
+    /// The doc comments of the namespace's declarations
+    pub docs: String,
+    pub entries: Vec<Entry>,
+}
+
+/// A documented item
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub kind: DefKind,
+    /// The item's name, e.g. `Point`, or `Point::origin` for a function on
+    /// a type this page does not document
+    pub name: String,
+    pub visibility: Visibility,
+    /// The declaration rendered as source, without bodies
+    pub declaration: String,
+    /// Each generic parameter with its bounds, e.g. `T: Hash`
+    pub generic_params: Vec<String>,
+    pub extends: Vec<Type>,
+    pub requires: Vec<Type>,
+    pub docs: String,
+    pub members: Vec<Member>,
+}
+
+/// A field, variant, interface method, or a function or constant declared
+/// on the type of the entry it belongs to
+#[derive(Debug, Clone)]
+pub struct Member {
+    pub kind: DefKind,
+    pub name: String,
+    pub declaration: String,
+    pub docs: String,
+}
+
+impl Page {
+    /// The namespace as written in source, e.g. `core::iter`
+    pub fn title(&self) -> String {
+        if self.namespace.is_empty() { "top level".to_string() } else { self.namespace.join("::") }
+    }
+
+    /// The page's file name without its extension, e.g. `core.iter`
+    pub fn stem(&self) -> String {
+        if self.namespace.is_empty() { "top-level".to_string() } else { self.namespace.join(".") }
+    }
+}
+
+impl Entry {
+    /// The fragment the entry is found at on its page
+    pub fn anchor(&self) -> String {
+        self.name.replace("::", ".")
+    }
+}
+
+/// A lower-case name for the kind of an item, e.g. `interface`
+pub fn kind_name(kind: DefKind) -> &'static str {
+    match kind {
+        DefKind::Namespace => "namespace",
+        DefKind::Function => "function",
+        DefKind::Struct => "struct",
+        DefKind::Union => "union",
+        DefKind::Enum => "enum",
+        DefKind::Interface => "interface",
+        DefKind::Alias => "type",
+        DefKind::Const => "const",
+        DefKind::Field => "field",
+        DefKind::Variant => "variant",
+        DefKind::Method => "method",
+        DefKind::Local => "local",
+    }
+}
+
+/// The documented types and interfaces, by name, for linking to them
+struct Links {
+    targets: HashMap<String, Vec<(usize, String)>>,
+    stems: Vec<String>,
+    extension: &'static str,
+}
+
+impl Links {
+    fn new(documentation: &Documentation, extension: &'static str) -> Links {
+        let mut targets: HashMap<String, Vec<(usize, String)>> = HashMap::new();
+        for (page, found) in documentation.pages.iter().enumerate() {
+            for entry in &found.entries {
+                if let DefKind::Struct | DefKind::Union | DefKind::Enum | DefKind::Interface | DefKind::Alias =
+                    entry.kind
+                {
+                    targets.entry(entry.name.clone()).or_default().push((page, entry.anchor()));
+                }
+            }
+        }
+        let stems = documentation.pages.iter().map(Page::stem).collect();
+        Links { targets, stems, extension }
+    }
+
+    /// Where `name`, seen on page `from`, is documented: on the same page
+    /// if it is there, otherwise on the only page that has it
+    fn resolve(&self, name: &str, from: usize) -> Option<String> {
+        let targets = self.targets.get(name)?;
+        let (page, anchor) = match targets.iter().find(|(page, _)| *page == from) {
+            Some(target) => target,
+            None if targets.len() == 1 => &targets[0],
+            None => return None,
+        };
+        Some(self.url(*page, anchor))
+    }
+
+    fn url(&self, page: usize, anchor: &str) -> String {
+        format!("{}.{}#{}", self.stems[page], self.extension, anchor)
+    }
+
+    /// The name a clause type links through: the last segment of its path
+    fn clause_target(ty: &Type) -> Option<&str> {
+        match ty {
+            Type::Path(path) => path.segments.last().map(String::as_str),
+            _ => None,
+        }
+    }
+}
+
+/// The first paragraph of a doc comment, on one line
+fn summary(docs: &str) -> String {
+    docs.lines().map(str::trim).take_while(|line| !line.is_empty()).collect::<Vec<_>>().join(" ")
+}
@@ -0,0 +1,115 @@
+//! Rendering documentation as Markdown, e.g. for the documentation site
+//!
+//! Each entry and member is preceded by an `<a id>` anchor so links work
+//! the same whatever the renderer makes of the headings. Declarations are
+//! `fig` code blocks, which cannot hold links, so the types of clauses are
+//! linked in a list after them.
+
+use fig_parser::ast::{Type, Visibility};
+use fig_parser::format::format_type;
+use fig_sema::index::DefKind;
+
+use crate::search::search_index;
+use crate::{Documentation, Entry, Links, Member, Page, kind_name, summary};
+
+/// The documentation as Markdown files, by file name: `index.md`, a page
+/// per namespace, and the search index as `search-index.json`
+pub fn to_markdown(documentation: &Documentation) -> Vec<(String, String)> {
+    let links = Links::new(documentation, "md");
+    let mut files = vec![
+        ("index.md".to_string(), index_page(documentation)),
+        ("search-index.json".to_string(), format!("{}\n", search_index(documentation, "md"))),
+    ];
+    for (number, page) in documentation.pages.iter().enumerate() {
+        files.push((format!("{}.md", page.stem()), namespace_page(page, number, &links)));
+    }
+    files
+}
+
+fn index_page(documentation: &Documentation) -> String {
+    let mut markdown = String::from("# API documentation\n\n");
+    for page in &documentation.pages {
+        markdown.push_str(&format!("- [`{}`]({}.md)", page.title(), page.stem()));
+        let summary = summary(&page.docs);
+        if !summary.is_empty() {
+            markdown.push_str(&format!(" \u{2014} {}", summary));
+        }
+        markdown.push('\n');
+    }
+    markdown
+}
+
+fn namespace_page(page: &Page, number: usize, links: &Links) -> String {
+    let mut markdown = format!("# Namespace `{}`\n\n", page.title());
+    if !page.docs.is_empty() {
+        markdown.push_str(&format!("{}\n\n", page.docs));
+    }
+    for entry in &page.entries {
+        markdown.push_str(&entry_section(entry, number, links));
+    }
+    markdown
+}
+
+fn entry_section(entry: &Entry, page: usize, links: &Links) -> String {
+    let anchor = entry.anchor();
+    let mut markdown = format!("<a id=\"{}\"></a>\n\n## {} `{}`\n\n", anchor, kind_name(entry.kind), entry.name);
+    markdown.push_str(&format!("```fig\n{}\n```\n\n", entry.declaration));
+    let mut clauses = String::new();
+    if let Some(keyword) = visibility(&entry.visibility) {
+        clauses.push_str(&format!("- Visibility: `{}`\n", keyword));
+    }
+    if !entry.generic_params.is_empty() {
+        let params: Vec<String> = entry.generic_params.iter().map(|p| format!("`{}`", p)).collect();
+        clauses.push_str(&format!("- Generic parameters: {}\n", params.join(", ")));
+    }
+    for (name, types) in [("Extends", &entry.extends), ("Requires", &entry.requires)] {
+        if !types.is_empty() {
+            let types: Vec<String> = types.iter().map(|ty| clause(ty, page, links)).collect();
+            clauses.push_str(&format!("- {}: {}\n", name, types.join(", ")));
+        }
+    }
+    if !clauses.is_empty() {
+        markdown.push_str(&format!("{}\n", clauses));
+    }
+    if !entry.docs.is_empty() {
+        markdown.push_str(&format!("{}\n\n", entry.docs));
+    }
+    for (heading, kinds) in [
+        ("Fields", &[DefKind::Field][..]),
+        ("Variants", &[DefKind::Variant]),
+        ("Methods", &[DefKind::Method, DefKind::Function]),
+        ("Constants", &[DefKind::Const]),
+    ] {
+        let members: Vec<&Member> = entry.members.iter().filter(|m| kinds.contains(&m.kind)).collect();
+        if members.is_empty() {
+            continue;
+        }
+        markdown.push_str(&format!("### {}\n\n", heading));
+        for member in members {
+            markdown.push_str(&format!("<a id=\"{}.{}\"></a>\n\n", anchor, member.name));
+            markdown.push_str(&format!("```fig\n{}\n```\n\n", member.declaration));
+            if !member.docs.is_empty() {
+                markdown.push_str(&format!("{}\n\n", member.docs));
+            }
+        }
+    }
+    markdown
+}
+
+/// A type from an `extends` or `requires` clause, linked when documented
+fn clause(ty: &Type, page: usize, links: &Links) -> String {
+    let text = format!("`{}`", format_type(ty));
+    match Links::clause_target(ty).and_then(|name| links.resolve(name, page)) {
+        Some(url) => format!("[{}]({})", text, url),
+        None => text,
+    }
+}
+
+fn visibility(visibility: &Visibility) -> Option<&'static str> {
+    match visibility {
+        Visibility::Default => None,
+        Visibility::Public => Some("public"),
+        Visibility::Export => Some("export"),
+        Visibility::Private => Some("private"),
+    }
+}
@@ -0,0 +1,43 @@
+//! The search index: every documented item and member with where it is
+
+use serde::Serialize;
+
+use crate::{Documentation, kind_name, summary};
+
+#[derive(Serialize)]
+struct SearchEntry {
+    /// The qualified name, e.g. `geo::Point::x`
+    path: String,
+    kind: &'static str,
+    /// The page and fragment it is documented at
+    url: String,
+    /// The first paragraph of its doc comment
+    summary: String,
+}
+
+/// The index as a JSON array, with URLs to pages with `extension`
+pub(crate) fn search_index(documentation: &Documentation, extension: &str) -> String {
+    let mut entries = Vec::new();
+    for page in &documentation.pages {
+        let file = format!("{}.{}", page.stem(), extension);
+        let prefix: String = page.namespace.iter().map(|segment| format!("{}::", segment)).collect();
+        for entry in &page.entries {
+            let anchor = entry.anchor();
+            entries.push(SearchEntry {
+                path: format!("{}{}", prefix, entry.name),
+                kind: kind_name(entry.kind),
+                url: format!("{}#{}", file, anchor),
+                summary: summary(&entry.docs),
+            });
+            for member in &entry.members {
+                entries.push(SearchEntry {
+                    path: format!("{}{}::{}", prefix, entry.name, member.name),
+                    kind: kind_name(member.kind),
+                    url: format!("{}#{}.{}", file, anchor, member.name),
+                    summary: summary(&member.docs),
+                });
+            }
+        }
+    }
+    serde_json::to_string(&entries).unwrap_or_default()
+}
@@ -0,0 +1,583 @@
+//! Classifying tokens
+//!
+//! Two passes over the tokens. The first follows the indentation to know
+//! which block each line is in, classifies names where they are declared,
+//! and collects the declared type and variant names and each function's
+//! parameters. The second classifies every other token from its
+//! neighbours and those collected names. Comments never reach the token
+//! stream, so they are found in the gaps between tokens.
+
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+use fig_lexer::{IndentLexer, Token};
+
+use crate::{Class, Highlight};
+
+/// Classify the ranges of `src`, in order. Unclassified text, such as
+/// local variable names and punctuation, has no range.
+pub fn highlight(src: &str) -> Vec<Highlight> {
+    let lexemes = lex(src);
+    let mut scan = Scan::default();
+    scan.run(src, &lexemes);
+    let mut highlights: Vec<Highlight> = (0..lexemes.len())
+        .filter_map(|i| scan.classify(src, &lexemes, i).map(|class| Highlight { span: lexemes[i].span.clone(), class }))
+        .collect();
+    highlights.extend(comments(src, &lexemes));
+    highlights.sort_by_key(|h| h.span.start);
+    highlights
+}
+
+/// A token other than an indentation change
+struct Lexeme {
+    token: Token,
+    span: Range<usize>,
+    /// Blocks opened (positive) or closed (negative) just before this token
+    indent: isize,
+}
+
+fn lex(src: &str) -> Vec<Lexeme> {
+    let mut lexer = IndentLexer::new(src);
+    let mut lexemes = Vec::new();
+    let mut indent = 0;
+    while let Some(result) = lexer.next() {
+        match result {
+            Ok(Token::Indent) => indent += 1,
+            Ok(Token::Dedent) => indent -= 1,
+            Ok(token) => lexemes.push(Lexeme { token, span: lexer.span(), indent: std::mem::take(&mut indent) }),
+            Err(_) => {}
+        }
+    }
+    lexemes
+}
+
+/// The comments in the gaps between tokens
+fn comments(src: &str, lexemes: &[Lexeme]) -> Vec<Highlight> {
+    let mut found = Vec::new();
+    let mut last = 0;
+    for span in lexemes.iter().map(|l| l.span.clone()).chain(std::iter::once(src.len()..src.len())) {
+        if span.start > last {
+            let gap = &src[last..span.start];
+            let mut at = 0;
+            while let Some(slash) = gap[at..].find('/') {
+                let start = at + slash;
+                let rest = &gap[start..];
+                let len = if rest.starts_with("//") {
+                    rest.find('\n').unwrap_or(rest.len())
+                } else if rest.starts_with("/*") {
+                    rest.find("*/").map_or(rest.len(), |end| end + 2)
+                } else {
+                    at = start + 1;
+                    continue;
+                };
+                found.push(Highlight { span: last + start..last + start + len, class: Class::Comment });
+                at = start + len;
+            }
+        }
+        last = last.max(span.end);
+    }
+    found
+}
+
+/// The kind of block a line is in
+#[derive(Debug, Clone, Copy)]
+enum Block {
+    Struct,
+    /// An enum's variants or a union's members
+    Variants,
+    Interface,
+    /// The body of the function with this index into [`Scan::parameters`]
+    Function(usize),
+    Other,
+}
+
+#[derive(Default)]
+struct Scan {
+    /// Classes of names where they are declared, by token index
+    declared: HashMap<usize, Class>,
+    types: HashSet<String>,
+    variants: HashSet<String>,
+    /// The parameter names of each function
+    parameters: Vec<Vec<String>>,
+    /// The function whose signature or body each token is in
+    function_of: Vec<Option<usize>>,
+}
+
+impl Scan {
+    fn run(&mut self, src: &str, lexemes: &[Lexeme]) {
+        let mut blocks: Vec<Block> = Vec::new();
+        // The block the current line opens, if it is followed by an indented one
+        let mut opens = None;
+        let mut line_function = None;
+        let mut line_start = true;
+        for (i, lexeme) in lexemes.iter().enumerate() {
+            if lexeme.indent > 0 {
+                blocks.push(opens.take().unwrap_or(Block::Other));
+                blocks.extend((1..lexeme.indent).map(|_| Block::Other));
+            }
+            for _ in lexeme.indent..0 {
+                blocks.pop();
+            }
+            if lexeme.token == Token::Newline {
+                line_start = true;
+            } else if line_start {
+                line_start = false;
+                let block = blocks.last().copied().unwrap_or(Block::Other);
+                match (&lexeme.token, block) {
+                    (Token::Ident(_), Block::Struct) if next_is(lexemes, i, Token::Colon) => {
+                        self.declared.insert(i, Class::Field);
+                    }
+                    (Token::Ident(name), Block::Variants) => {
+                        self.declared.insert(i, Class::EnumVariant);
+                        self.variants.insert(name.clone());
+                    }
+                    _ => {}
+                }
+                let mut start = i;
+                while matches!(
+                    lexemes.get(start).map(|l| &l.token),
+                    Some(Token::Public | Token::Export | Token::Private | Token::Packed | Token::Extern)
+                ) {
+                    start += 1;
+                }
+                let in_interface = matches!(block, Block::Interface);
+                (opens, line_function) = self.declaration(src, lexemes, start, in_interface);
+            }
+            let innermost = blocks.iter().rev().find_map(|b| match b {
+                Block::Function(f) => Some(*f),
+                _ => None,
+            });
+            self.function_of.push(line_function.or(innermost));
+        }
+    }
+
+    /// Classify the names declared by the line starting at `start`, giving
+    /// the block the line opens and the function it declares
+    fn declaration(
+        &mut self,
+        src: &str,
+        lexemes: &[Lexeme],
+        start: usize,
+        in_interface: bool,
+    ) -> (Option<Block>, Option<usize>) {
+        let Some(keyword) = lexemes.get(start).map(|l| &l.token) else { return (None, None) };
+        let line_end = (start..lexemes.len()).find(|&j| lexemes[j].token == Token::Newline).unwrap_or(lexemes.len());
+        let block = match keyword {
+            Token::Struct => Block::Struct,
+            Token::Union | Token::Enum => Block::Variants,
+            Token::Interface => Block::Interface,
+            Token::Type => Block::Other,
+            Token::Namespace => {
+                for (j, lexeme) in lexemes.iter().enumerate().take(line_end).skip(start + 1) {
+                    if is_name(&lexeme.token) {
+                        self.declared.insert(j, Class::Namespace);
+                    }
+                }
+                return (None, None);
+            }
+            Token::Func | Token::Fn => {
+                let function = self.function(src, lexemes, start + 1, line_end, in_interface);
+                return (Some(Block::Function(function)), Some(function));
+            }
+            _ => return (None, None),
+        };
+        // The type's name, skipping an enum's `[repr]`, then its generic parameters
+        let mut j = start + 1;
+        while j < line_end {
+            match &lexemes[j].token {
+                Token::LBracket => j = self.generics(src, lexemes, j) + 1,
+                Token::Ident(name) => {
+                    self.declared.insert(j, Class::Type);
+                    self.types.insert(name.clone());
+                    if lexemes.get(j + 1).is_some_and(|l| l.token == Token::LBracket) {
+                        self.generics(src, lexemes, j + 1);
+                    }
+                    break;
+                }
+                _ => break,
+            }
+        }
+        (Some(block), None)
+    }
+
+    /// Classify a function signature from after `func`, returning the function's index
+    fn function(&mut self, src: &str, lexemes: &[Lexeme], mut j: usize, line_end: usize, in_interface: bool) -> usize {
+        if lexemes.get(j).is_some_and(|l| l.token == Token::Bang) {
+            j += 1;
+        }
+        if lexemes.get(j).is_some_and(|l| l.token == Token::LBracket) {
+            j = self.generics(src, lexemes, j) + 1;
+        }
+        // Receiver segments, then the name
+        let mut segments = Vec::new();
+        while j < line_end && is_name(&lexemes[j].token) {
+            segments.push(j);
+            let mut next = j + 1;
+            if lexemes.get(next).is_some_and(|l| l.token == Token::LBracket) {
+                next = self.generics(src, lexemes, next) + 1;
+            }
+            if lexemes.get(next).is_some_and(|l| l.token == Token::ColonColon) {
+                j = next + 1;
+            } else {
+                j = next;
+                break;
+            }
+        }
+        if let Some((&name, receiver)) = segments.split_last() {
+            let is_method = !receiver.is_empty() || in_interface;
+            self.declared.insert(name, if is_method { Class::Method } else { Class::Function });
+            if let Some((&ty, namespaces)) = receiver.split_last() {
+                self.declared.insert(ty, Class::Type);
+                self.types.insert(src[lexemes[ty].span.clone()].to_string());
+                for &segment in namespaces {
+                    self.declared.insert(segment, Class::Namespace);
+                }
+            }
+        }
+        // Parameters are `name: Type` directly inside the parentheses
+        let mut parameters = Vec::new();
+        let mut depth = 0;
+        for k in j..line_end {
+            match &lexemes[k].token {
+                Token::LParen | Token::LBracket => depth += 1,
+                Token::RParen | Token::RBracket => depth -= 1,
+                Token::Ident(name) if depth == 1 && next_is(lexemes, k, Token::Colon) => {
+                    self.declared.insert(k, Class::Parameter);
+                    parameters.push(name.clone());
+                }
+                _ => {}
+            }
+            if depth == 0 && k > j {
+                break;
+            }
+        }
+        self.parameters.push(parameters);
+        self.parameters.len() - 1
+    }
+
+    /// Declare the names in the generic parameter list opening at `open` as
+    /// types, returning the index of its closing bracket
+    fn generics(&mut self, src: &str, lexemes: &[Lexeme], open: usize) -> usize {
+        let mut depth = 0;
+        for j in open..lexemes.len() {
+            match &lexemes[j].token {
+                Token::LBracket | Token::LParen => depth += 1,
+                Token::RBracket | Token::RParen => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return j;
+                    }
+                }
+                Token::Newline => return j,
+                token if is_name(token) => {
+                    self.declared.insert(j, Class::Type);
+                    self.types.insert(src[lexemes[j].span.clone()].to_string());
+                }
+                _ => {}
+            }
+        }
+        lexemes.len()
+    }
+
+    fn classify(&self, src: &str, lexemes: &[Lexeme], i: usize) -> Option<Class> {
+        let token = &lexemes[i].token;
+        let prev = i.checked_sub(1).map(|p| &lexemes[p].token);
+        Some(match token {
+            Token::Ident(_) | Token::Std | Token::Core | Token::Alloc => return self.name(src, lexemes, i),
+            Token::Bang if matches!(prev, Some(Token::Func | Token::Fn)) => Class::Keyword,
+            Token::Hash => Class::Annotation,
+            Token::True
+            | Token::False
+            | Token::OkLiteral
+            | Token::Null
+            | Token::IntegerLiteral(_)
+            | Token::FloatLiteral(_)
+            | Token::StringLiteral(_)
+            | Token::InterpolatedStringLiteral(_)
+            | Token::CharLiteral(_) => Class::Literal,
+            Token::U8
+            | Token::U16
+            | Token::U32
+            | Token::U64
+            | Token::USize
+            | Token::ISize
+            | Token::I8
+            | Token::I16
+            | Token::I32
+            | Token::I64
+            | Token::F32
+            | Token::F64
+            | Token::Bool => Class::PrimitiveType,
+            Token::Plus
+            | Token::Minus
+            | Token::Star
+            | Token::Slash
+            | Token::Percent
+            | Token::EqEq
+            | Token::Ne
+            | Token::Lt
+            | Token::Gt
+            | Token::Le
+            | Token::Ge
+            | Token::AndAnd
+            | Token::OrOr
+            | Token::Bang
+            | Token::And
+            | Token::Or
+            | Token::Caret
+            | Token::Tilde
+            | Token::Shl
+            | Token::Shr
+            | Token::Eq
+            | Token::PlusEq
+            | Token::MinusEq
+            | Token::StarEq
+            | Token::SlashEq
+            | Token::PercentEq
+            | Token::AndEq
+            | Token::OrEq
+            | Token::CaretEq
+            | Token::ShlEq
+            | Token::ShrEq
+            | Token::Arrow
+            | Token::FatArrow
+            | Token::DotDot
+            | Token::DotDotEq
+            | Token::Question => Class::Operator,
+            Token::Indent
+            | Token::Dedent
+            | Token::Newline
+            | Token::LParen
+            | Token::RParen
+            | Token::LBrace
+            | Token::RBrace
+            | Token::LBracket
+            | Token::RBracket
+            | Token::ColonColon
+            | Token::Colon
+            | Token::Semicolon
+            | Token::Comma
+            | Token::Dot
+            | Token::Underscore
+            | Token::Comment => return None,
+            _ => Class::Keyword,
+        })
+    }
+
+    /// Classify an identifier, or `std`, `core` or `alloc`, from its context
+    fn name(&self, src: &str, lexemes: &[Lexeme], i: usize) -> Option<Class> {
+        if let Some(class) = self.declared.get(&i) {
+            return Some(*class);
+        }
+        let name = &src[lexemes[i].span.clone()];
+        let token = |j: Option<usize>| j.and_then(|j| lexemes.get(j)).map(|l| &l.token);
+        let prev = token(i.checked_sub(1));
+        let next = token(Some(i + 1));
+        if prev == Some(&Token::Hash) {
+            return Some(Class::Annotation);
+        }
+        let is_call = next == Some(&Token::LParen)
+            || (next == Some(&Token::Bang) && token(Some(i + 2)) == Some(&Token::LParen));
+        // `.field`, or `.!field` through a nullable pointer
+        let after_dot = prev == Some(&Token::Dot)
+            || (prev == Some(&Token::Bang) && token(i.checked_sub(2)) == Some(&Token::Dot));
+        if after_dot {
+            return Some(if is_call { Class::Method } else { Class::Field });
+        }
+        let owner = (prev == Some(&Token::ColonColon))
+            .then(|| owner(lexemes, i))
+            .flatten()
+            .map(|j| &src[lexemes[j].span.clone()]);
+        let owner_is_type = owner.is_some_and(|o| self.types.contains(o));
+        if is_call {
+            return Some(if owner_is_type {
+                Class::Method
+            } else if self.types.contains(name) {
+                Class::Type
+            } else {
+                Class::Function
+            });
+        }
+        if continues_path(lexemes, i) {
+            return Some(if self.types.contains(name) { Class::Type } else { Class::Namespace });
+        }
+        if owner_is_type && self.variants.contains(name) {
+            return Some(Class::EnumVariant);
+        }
+        if self.types.contains(name) {
+            return Some(Class::Type);
+        }
+        let function = self.function_of.get(i).copied().flatten()?;
+        self.parameters[function].iter().any(|p| p == name).then_some(Class::Parameter)
+    }
+}
+
+/// Whether the token after `i` is `token`
+fn next_is(lexemes: &[Lexeme], i: usize, token: Token) -> bool {
+    lexemes.get(i + 1).is_some_and(|l| l.token == token)
+}
+
+/// Whether a token can be a path segment
+fn is_name(token: &Token) -> bool {
+    matches!(token, Token::Ident(_) | Token::Std | Token::Core | Token::Alloc)
+}
+
+/// Whether the name at `i` is followed by `::`, possibly after generic arguments
+fn continues_path(lexemes: &[Lexeme], i: usize) -> bool {
+    let mut next = i + 1;
+    if lexemes.get(next).is_some_and(|l| l.token == Token::LBracket) {
+        let mut depth = 0;
+        for (j, lexeme) in lexemes.iter().enumerate().skip(next) {
+            match lexeme.token {
+                Token::LBracket => depth += 1,
+                Token::RBracket => {
+                    depth -= 1;
+                    if depth == 0 {
+                        next = j + 1;
+                        break;
+                    }
+                }
+                Token::Newline => return false,
+                _ => {}
+            }
+        }
+    }
+    lexemes.get(next).is_some_and(|l| l.token == Token::ColonColon)
+}
+
+/// The path segment before the `::` that precedes the name at `i`, skipping
+/// its generic arguments
+fn owner(lexemes: &[Lexeme], i: usize) -> Option<usize> {
+    let mut j = i.checked_sub(2)?;
+    if lexemes[j].token == Token::RBracket {
+        let mut depth = 0;
+        loop {
+            match lexemes[j].token {
+                Token::RBracket => depth += 1,
+                Token::LBracket => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+            j = j.checked_sub(1)?;
+        }
+        j = j.checked_sub(1)?;
+    }
+    is_name(&lexemes[j].token).then_some(j)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Each classified range as `class:text`
+    fn classes(src: &str) -> Vec<String> {
+        highlight(src).into_iter().map(|h| format!("{}:{}", h.class.name(), &src[h.span])).collect()
+    }
+
+    #[test]
+    fn test_declarations() {
+        let src = "\
+namespace geo
+
+// A point
+packed struct Point
+    x: i32
+    y: i32
+
+enum[u8] Dir
+    N
+    S = 4
+";
+        assert_eq!(
+            classes(src),
+            [
+                "keyword:namespace",
+                "namespace:geo",
+                "comment:// A point",
+                "keyword:packed",
+                "keyword:struct",
+                "type:Point",
+                "field:x",
+                "primitive-type:i32",
+                "field:y",
+                "primitive-type:i32",
+                "keyword:enum",
+                "primitive-type:u8",
+                "type:Dir",
+                "enum-variant:N",
+                "enum-variant:S",
+                "operator:=",
+                "literal:4",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_functions_and_uses() {
+        let src = "\
+#inline
+func[T] Point::scale(*self, by: T) -> Point
+    let p = std::math::max(by, 2)
+    return Point(self.x * by, Dir::S).!norm()
+";
+        assert_eq!(
+            classes(src),
+            [
+                "annotation:#",
+                "annotation:inline",
+                "keyword:func",
+                "type:T",
+                "type:Point",
+                "method:scale",
+                "operator:*",
+                "keyword:self",
+                "parameter:by",
+                "type:T",
+                "operator:->",
+                "type:Point",
+                "keyword:let",
+                "operator:=",
+                "namespace:std",
+                "namespace:math",
+                "function:max",
+                "parameter:by",
+                "literal:2",
+                "keyword:return",
+                "type:Point",
+                "keyword:self",
+                "field:x",
+                "operator:*",
+                "parameter:by",
+                "namespace:Dir",
+                "operator:!",
+                "method:norm",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_block_comments_and_broken_source() {
+        let src = "func! f(a: i32) -> ok\n    /* not\n       done */ return g(a +\n";
+        assert_eq!(
+            classes(src),
+            [
+                "keyword:func",
+                "keyword:!",
+                "function:f",
+                "parameter:a",
+                "primitive-type:i32",
+                "operator:->",
+                "literal:ok",
+                "comment:/* not\n       done */",
+                "keyword:return",
+                "function:g",
+                "parameter:a",
+                "operator:+",
+            ]
+        );
+    }
+}
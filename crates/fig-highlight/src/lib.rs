@@ -0,0 +1,95 @@
+//! Syntax highlighting for Fig
+//!
+//! [`highlight`] classifies the ranges of a source file — keywords, types,
+//! functions, parameters, fields, comments and so on — for editors and the
+//! documentation site. It works from the token stream rather than the AST,
+//! so it still colors a file that does not parse, and it reads indentation
+//! to tell a struct's fields from an enum's variants or a function body.
+//!
+//! Names are classified by how they are used and where they are declared:
+//! `Point` is a type wherever it appears once the file declares
+//! `struct Point`, and `std`, `core` and `alloc` — keywords to the lexer —
+//! are namespaces when they are path segments.
+//!
+//! ```ignore
+//! let highlights = highlight("func main() -> i32\n    return 0\n");
+//! print!("{}", to_ansi(src, &highlights));
+//! ```
+//!
+//! [`to_html`] and [`to_ansi`] render the result.
+
+mod classify;
+mod render;
+
+use std::ops::Range;
+
+pub use classify::highlight;
+pub use render::{CSS, to_ansi, to_html};
+
+/// What a highlighted range is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Class {
+    Keyword,
+    /// A declared type, generic parameter or receiver
+    Type,
+    /// `i32`, `bool` and the other built-in types
+    PrimitiveType,
+    Function,
+    /// A function called with `.`, declared with a receiver or in an interface
+    Method,
+    Namespace,
+    Parameter,
+    Field,
+    /// An enum variant or union member
+    EnumVariant,
+    /// `#name`
+    Annotation,
+    /// Numbers, strings, characters, `true`, `false`, `ok` and `null`
+    Literal,
+    Comment,
+    Operator,
+}
+
+impl Class {
+    pub const ALL: [Class; 13] = [
+        Class::Keyword,
+        Class::Type,
+        Class::PrimitiveType,
+        Class::Function,
+        Class::Method,
+        Class::Namespace,
+        Class::Parameter,
+        Class::Field,
+        Class::EnumVariant,
+        Class::Annotation,
+        Class::Literal,
+        Class::Comment,
+        Class::Operator,
+    ];
+
+    /// The name used for the class in HTML, e.g. `primitive-type`
+    pub fn name(self) -> &'static str {
+        match self {
+            Class::Keyword => "keyword",
+            Class::Type => "type",
+            Class::PrimitiveType => "primitive-type",
+            Class::Function => "function",
+            Class::Method => "method",
+            Class::Namespace => "namespace",
+            Class::Parameter => "parameter",
+            Class::Field => "field",
+            Class::EnumVariant => "enum-variant",
+            Class::Annotation => "annotation",
+            Class::Literal => "literal",
+            Class::Comment => "comment",
+            Class::Operator => "operator",
+        }
+    }
+}
+
+/// A classified byte range of the source
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Highlight {
+    pub span: Range<usize>,
+    pub class: Class,
+}
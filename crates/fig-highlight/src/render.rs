@@ -0,0 +1,124 @@
+//! Rendering highlighted source as HTML or for a terminal
+
+use crate::{Class, Highlight};
+
+/// Colors for the classes [`to_html`] emits, for pages that have none of their own
+pub const CSS: &str = "\
+.fig-keyword { color: #8959a8; font-weight: bold; }
+.fig-type { color: #3e999f; }
+.fig-primitive-type { color: #3e999f; font-style: italic; }
+.fig-function { color: #4271ae; }
+.fig-method { color: #4271ae; }
+.fig-namespace { color: #718c00; }
+.fig-parameter { color: #c82829; }
+.fig-field { color: #b35f00; }
+.fig-enum-variant { color: #b35f00; font-style: italic; }
+.fig-annotation { color: #eab700; }
+.fig-literal { color: #718c00; }
+.fig-comment { color: #8e908c; font-style: italic; }
+.fig-operator { color: #3e999f; }
+";
+
+/// `src` as HTML, each highlight a `<span class=\"fig-CLASS\">` with the
+/// class's [`name`](Class::name). The caller wraps it, usually in
+/// `<pre><code>`.
+pub fn to_html(src: &str, highlights: &[Highlight]) -> String {
+    let mut html = String::with_capacity(src.len() * 2);
+    render(src, highlights, |text, class| match class {
+        Some(class) => {
+            html.push_str(&format!("<span class=\"fig-{}\">", class.name()));
+            escape(text, &mut html);
+            html.push_str("</span>");
+        }
+        None => escape(text, &mut html),
+    });
+    html
+}
+
+/// `src` with ANSI color escapes for a terminal
+pub fn to_ansi(src: &str, highlights: &[Highlight]) -> String {
+    let mut ansi = String::with_capacity(src.len() * 2);
+    render(src, highlights, |text, class| match class {
+        Some(class) => ansi.push_str(&format!("\x1b[{}m{}\x1b[0m", sgr(class), text)),
+        None => ansi.push_str(text),
+    });
+    ansi
+}
+
+/// Call `emit` with each stretch of `src` and its class, in order
+fn render(src: &str, highlights: &[Highlight], mut emit: impl FnMut(&str, Option<Class>)) {
+    let mut at = 0;
+    for highlight in highlights {
+        // Overlapping or out-of-order ranges are skipped rather than repeated
+        if highlight.span.start < at || highlight.span.end > src.len() {
+            continue;
+        }
+        if highlight.span.start > at {
+            emit(&src[at..highlight.span.start], None);
+        }
+        emit(&src[highlight.span.clone()], Some(highlight.class));
+        at = highlight.span.end;
+    }
+    if at < src.len() {
+        emit(&src[at..], None);
+    }
+}
+
+fn escape(text: &str, html: &mut String) {
+    for c in text.chars() {
+        match c {
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '&' => html.push_str("&amp;"),
+            '"' => html.push_str("&quot;"),
+            c => html.push(c),
+        }
+    }
+}
+
+/// The SGR parameters for a class
+fn sgr(class: Class) -> &'static str {
+    match class {
+        Class::Keyword => "1;35",
+        Class::Type => "36",
+        Class::PrimitiveType => "3;36",
+        Class::Function | Class::Method => "34",
+        Class::Namespace => "32",
+        Class::Parameter => "31",
+        Class::Field => "33",
+        Class::EnumVariant => "3;33",
+        Class::Annotation => "93",
+        Class::Literal => "92",
+        Class::Comment => "3;90",
+        Class::Operator => "96",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::highlight;
+
+    #[test]
+    fn test_html() {
+        let src = "func lt(a: i32) -> bool\n    return a < 1 // \"one\"\n";
+        assert_eq!(
+            to_html(src, &highlight(src)),
+            "<span class=\"fig-keyword\">func</span> <span class=\"fig-function\">lt</span>(\
+             <span class=\"fig-parameter\">a</span>: <span class=\"fig-primitive-type\">i32</span>) \
+             <span class=\"fig-operator\">-&gt;</span> <span class=\"fig-primitive-type\">bool</span>\n    \
+             <span class=\"fig-keyword\">return</span> <span class=\"fig-parameter\">a</span> \
+             <span class=\"fig-operator\">&lt;</span> <span class=\"fig-literal\">1</span> \
+             <span class=\"fig-comment\">// &quot;one&quot;</span>\n"
+        );
+    }
+
+    #[test]
+    fn test_ansi() {
+        let src = "let x = 1\n";
+        assert_eq!(
+            to_ansi(src, &highlight(src)),
+            "\x1b[1;35mlet\x1b[0m x \x1b[96m=\x1b[0m \x1b[92m1\x1b[0m\n"
+        );
+    }
+}
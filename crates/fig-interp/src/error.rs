@@ -0,0 +1,117 @@
+//! Runtime errors
+//!
+//! Everything that stops a running program is a [`RuntimeError`]: traps the
+//! program itself caused (overflow, out-of-bounds access, a dangling
+//! pointer), and constructs the interpreter cannot execute. A [`Trap`] pairs
+//! the error with the call stack at the point it happened.
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeError {
+    /// Checked arithmetic left the range of its type
+    Overflow { op: &'static str, ty: String },
+    DivisionByZero,
+    /// A shift by a negative amount or by at least the operand's width
+    ShiftOutOfRange { amount: i128, ty: String },
+    /// A literal or converted value that does not fit the declared type
+    OutOfRange { value: String, ty: String },
+    IndexOutOfBounds { index: i128, len: usize },
+    /// A slice `start..end` with `start > end` or `end` past the length
+    SliceOutOfBounds { start: u64, end: u64, len: usize },
+    NullDereference,
+    /// Access through a pointer to freed heap memory
+    UseAfterFree,
+    /// Access through a pointer to a local whose scope has ended
+    DanglingPointer,
+    /// A pointer past the end of its allocation
+    OutOfBounds,
+    DoubleFree,
+    /// `free` of a pointer that is not the start of a heap allocation
+    InvalidFree,
+    /// A read of memory that was never written
+    UninitializedRead,
+    /// A read of a union variant other than the active one
+    InactiveVariant { union: String, variant: String, active: String },
+    UndefinedName(String),
+    UndefinedFunction(String),
+    NoSuchField { ty: String, field: String },
+    NoSuchMethod { ty: String, method: String },
+    ArityMismatch { function: String, expected: usize, found: usize },
+    TypeMismatch(String),
+    /// `assert` with a false condition
+    AssertionFailed,
+    /// A construct the interpreter cannot execute
+    Unsupported(String),
+    /// Calls nested deeper than the configured limit
+    StackOverflow,
+    /// The configured statement budget ran out
+    OutOfFuel,
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimeError::Overflow { op, ty } => write!(f, "`{}` {} overflowed", ty, op),
+            RuntimeError::DivisionByZero => write!(f, "division by zero"),
+            RuntimeError::ShiftOutOfRange { amount, ty } => {
+                write!(f, "shift by {} is out of range for `{}`", amount, ty)
+            }
+            RuntimeError::OutOfRange { value, ty } => write!(f, "{} does not fit in `{}`", value, ty),
+            RuntimeError::IndexOutOfBounds { index, len } => {
+                write!(f, "index {} is out of bounds for length {}", index, len)
+            }
+            RuntimeError::SliceOutOfBounds { start, end, len } => {
+                write!(f, "slice {}..{} is out of range for length {}", start, end, len)
+            }
+            RuntimeError::NullDereference => write!(f, "null pointer dereference"),
+            RuntimeError::UseAfterFree => write!(f, "use of freed memory"),
+            RuntimeError::DanglingPointer => write!(f, "use of a pointer to a local that is out of scope"),
+            RuntimeError::OutOfBounds => write!(f, "pointer is outside its allocation"),
+            RuntimeError::DoubleFree => write!(f, "memory freed twice"),
+            RuntimeError::InvalidFree => write!(f, "free of a pointer that was not returned by an allocator"),
+            RuntimeError::UninitializedRead => write!(f, "read of uninitialized memory"),
+            RuntimeError::InactiveVariant { union, variant, active } => write!(
+                f,
+                "read of variant `{}` of `{}`, but `{}` is active",
+                variant, union, active
+            ),
+            RuntimeError::UndefinedName(name) => write!(f, "undefined name `{}`", name),
+            RuntimeError::UndefinedFunction(name) => write!(f, "undefined function `{}`", name),
+            RuntimeError::NoSuchField { ty, field } => write!(f, "`{}` has no field `{}`", ty, field),
+            RuntimeError::NoSuchMethod { ty, method } => write!(f, "`{}` has no method `{}`", ty, method),
+            RuntimeError::ArityMismatch { function, expected, found } => write!(
+                f,
+                "`{}` takes {} argument(s) but {} were supplied",
+                function, expected, found
+            ),
+            RuntimeError::TypeMismatch(message) => write!(f, "type mismatch: {}", message),
+            RuntimeError::AssertionFailed => write!(f, "assertion failed"),
+            RuntimeError::Unsupported(what) => write!(f, "unsupported: {}", what),
+            RuntimeError::StackOverflow => write!(f, "call stack overflow"),
+            RuntimeError::OutOfFuel => write!(f, "statement budget exhausted"),
+        }
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+/// A runtime error with the call stack at the point it was raised,
+/// innermost function first
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trap {
+    pub error: RuntimeError,
+    pub backtrace: Vec<String>,
+}
+
+impl fmt::Display for Trap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "error: {}", self.error)?;
+        for function in &self.backtrace {
+            write!(f, "\n  in `{}`", function)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Trap {}
@@ -0,0 +1,1591 @@
+//! The evaluator
+//!
+//! Statements and expressions are executed directly from the AST. Every local
+//! variable lives in its own stack allocation, so `&x` is an ordinary
+//! [`Pointer`] and a pointer that outlives `x`'s scope is caught on its next
+//! use. Names are resolved through the [`ItemTable`] on each call; nothing is
+//! compiled ahead of time. Values do not carry generic arguments, so each
+//! frame keeps the bindings of the instance the type checker resolved for its
+//! call, which is what `sizeof(T)` and its siblings are evaluated against.
+//!
+//! Calls resolve their callee in this order: a method through a `.` receiver,
+//! a function by qualified name, positional construction of a struct
+//! (`Point(1, 2)`), construction of a union variant (`Shape::Circle(r)`), and
+//! finally the host builtins. The builtins are `print`, `println`, `assert`,
+//! and `malloc`, `calloc`, `realloc` and `free`, which also serve as the
+//! bodies of matching `extern` declarations.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use fig_lexer::{FloatSuffix, IntegerLiteral};
+use fig_parser::ast::*;
+use fig_sema::conformance::ConformanceChecker;
+use fig_sema::items::{FunctionDef, ItemTable, TypeDef};
+use fig_sema::layout::{LayoutEngine, LayoutError, Target};
+use fig_sema::typeck::{Bindings, CallTarget, Instance, TypeChecker, TypedBody};
+
+use crate::error::{RuntimeError, Trap};
+use crate::memory::{Memory, Region};
+use crate::ops;
+use crate::value::{Int, IntType, Pointer, Step, Value};
+
+/// Calls nested deeper than this trap with [`RuntimeError::StackOverflow`]
+const DEFAULT_MAX_CALL_DEPTH: usize = 1000;
+
+/// Stack size of the thread programs run on. Each Fig call nests a dozen
+/// evaluator frames, which in a debug build outgrows the default 2 MiB stack
+/// within a few dozen calls.
+const EVAL_STACK_SIZE: usize = 256 << 20;
+
+/// Largest allocation `malloc` will satisfy, in cells; larger requests return `null`
+const MAX_ALLOCATION: i128 = 1 << 24;
+
+/// How a statement finished
+enum Flow {
+    Normal,
+    Return(Value),
+    Break(Option<String>),
+    Continue,
+}
+
+/// Why evaluation of an expression stopped early
+enum Unwind {
+    Trap(RuntimeError),
+    /// An error value leaving a `callee!(args)` or `.!` site, on its way to the
+    /// enclosing function's return
+    Propagate(Value),
+}
+
+impl From<RuntimeError> for Unwind {
+    fn from(error: RuntimeError) -> Self {
+        Unwind::Trap(error)
+    }
+}
+
+type Eval<T> = Result<T, Unwind>;
+
+#[derive(Default)]
+struct Scope {
+    locals: Vec<(String, Pointer)>,
+    /// Locals and temporaries that expire with the scope
+    allocations: Vec<usize>,
+}
+
+struct Frame<'a> {
+    function: String,
+    scopes: Vec<Scope>,
+    return_type: Option<Type>,
+    /// The generic arguments of the running instance
+    bindings: Bindings,
+    /// The checked body of the running instance, when it type checks
+    typed: Option<Arc<TypedBody<'a>>>,
+}
+
+fn layout_error(error: LayoutError) -> RuntimeError {
+    RuntimeError::Unsupported(error.to_string())
+}
+
+/// `instance` when the type checker resolved the call to `def`, otherwise
+/// `def` without generic arguments
+fn instance_of<'a>(def: &'a FunctionDef<'a>, instance: Option<Instance<'a>>) -> Instance<'a> {
+    instance.filter(|instance| std::ptr::eq(instance.function, def)).unwrap_or_else(|| Instance::new(def))
+}
+
+/// The name a value of type `ty` reports from [`Value::type_name`]
+fn type_name(ty: &Type) -> Option<String> {
+    match ty {
+        Type::Path(path) => path.segments.last().cloned(),
+        Type::F32 => Some("f32".to_string()),
+        Type::F64 => Some("f64".to_string()),
+        Type::Bool => Some("bool".to_string()),
+        other => IntType::from_type(other).map(|ty| ty.name().to_string()),
+    }
+}
+
+pub struct Interpreter<'a> {
+    items: &'a ItemTable<'a>,
+    layout: LayoutEngine<'a>,
+    checker: TypeChecker<'a>,
+    /// Checked bodies by instance name; `None` for one that does not check
+    bodies: HashMap<String, Option<Arc<TypedBody<'a>>>>,
+    memory: Memory,
+    frames: Vec<Frame<'a>>,
+    /// Values of `const` items, keyed by declaration address
+    consts: HashMap<usize, Value>,
+    /// String literals, interned into static memory
+    strings: HashMap<String, Value>,
+    output: String,
+    fuel: Option<u64>,
+    max_call_depth: usize,
+    /// The call stack where the trap being unwound was raised
+    backtrace: Option<Vec<String>>,
+}
+
+impl<'a> Interpreter<'a> {
+    pub fn new(items: &'a ItemTable<'a>) -> Self {
+        Interpreter {
+            items,
+            layout: LayoutEngine::new(items, Target::host()),
+            checker: TypeChecker::new(items, Target::host()),
+            bodies: HashMap::new(),
+            memory: Memory::new(),
+            frames: Vec::new(),
+            consts: HashMap::new(),
+            strings: HashMap::new(),
+            output: String::new(),
+            fuel: None,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            backtrace: None,
+        }
+    }
+
+    /// Use `target`'s integer widths for `usize`, `isize` and `sizeof`
+    pub fn with_target(mut self, target: Target) -> Self {
+        self.layout = LayoutEngine::new(self.items, target);
+        self.checker = TypeChecker::new(self.items, target);
+        self.bodies.clear();
+        self
+    }
+
+    /// Trap with [`RuntimeError::OutOfFuel`] after executing `fuel` statements
+    pub fn with_fuel(mut self, fuel: u64) -> Self {
+        self.fuel = Some(fuel);
+        self
+    }
+
+    pub fn with_max_call_depth(mut self, depth: usize) -> Self {
+        self.max_call_depth = depth;
+        self
+    }
+
+    /// Call the function with the given qualified name, e.g. `main` or `Vec::new`
+    pub fn call(&mut self, name: &str, args: Vec<Value>) -> Result<Value, Trap> {
+        std::thread::scope(|scope| {
+            let evaluator = std::thread::Builder::new()
+                .stack_size(EVAL_STACK_SIZE)
+                .spawn_scoped(scope, || self.call_here(name, args))
+                .expect("failed to spawn the evaluator thread");
+            evaluator.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+        })
+    }
+
+    fn call_here(&mut self, name: &str, args: Vec<Value>) -> Result<Value, Trap> {
+        let items = self.items;
+        let path = Path { segments: name.split("::").map(String::from).collect(), generic_args: vec![] };
+        self.backtrace = None;
+        let result = match items.lookup_function(&path) {
+            Some(def) => self.call_function(Instance::new(def), args),
+            None => Err(RuntimeError::UndefinedFunction(name.to_string()).into()),
+        };
+        match result {
+            Ok(value) => Ok(value),
+            Err(Unwind::Trap(error)) => Err(Trap { error, backtrace: self.backtrace.take().unwrap_or_default() }),
+            Err(Unwind::Propagate(error)) => Ok(Value::Error(Box::new(error))),
+        }
+    }
+
+    /// Everything written by `print` and `println` so far
+    pub fn output(&self) -> &str {
+        &self.output
+    }
+
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    fn target(&self) -> Target {
+        self.layout.target()
+    }
+
+    // ========================================================================
+    // Frames and scopes
+    // ========================================================================
+
+    fn frame(&mut self) -> &mut Frame<'a> {
+        self.frames.last_mut().expect("evaluation runs inside a frame")
+    }
+
+    fn push_frame(&mut self, function: String, return_type: Option<Type>, instance: Option<&Instance<'a>>) -> Eval<()> {
+        if self.frames.len() >= self.max_call_depth {
+            return Err(RuntimeError::StackOverflow.into());
+        }
+        let bindings = instance.map(|instance| instance.bindings.clone()).unwrap_or_default();
+        let typed = instance.and_then(|instance| self.typed_body(instance));
+        self.frames.push(Frame { function, scopes: vec![Scope::default()], return_type, bindings, typed });
+        Ok(())
+    }
+
+    /// The checked body of `instance`, checked on its first call
+    fn typed_body(&mut self, instance: &Instance<'a>) -> Option<Arc<TypedBody<'a>>> {
+        let name = instance.name();
+        if let Some(typed) = self.bodies.get(&name) {
+            return typed.clone();
+        }
+        let typed = self.checker.check(instance).ok().map(Arc::new);
+        self.bodies.insert(name, typed.clone());
+        typed
+    }
+
+    /// The instance the type checker resolved for `call` in the running body
+    fn callee_instance(&self, call: &CallExpr) -> Option<Instance<'a>> {
+        match self.frames.last()?.typed.as_ref()?.call(call)? {
+            CallTarget::Function { instance, .. } => Some(instance.clone()),
+            _ => None,
+        }
+    }
+
+    /// `ty` with the running instance's generic arguments substituted
+    fn instantiate(&mut self, ty: &Type) -> Type {
+        let Some(frame) = self.frames.last().filter(|frame| !frame.bindings.is_empty()) else { return ty.clone() };
+        let bindings = frame.bindings.clone();
+        self.checker.normalize(ty, &bindings).unwrap_or_else(|_| ty.clone())
+    }
+
+    /// Pop the current frame, first recording the call stack if a trap is
+    /// passing through it for the first time
+    fn pop_frame<T>(&mut self, result: &Eval<T>) {
+        if let Err(Unwind::Trap(_)) = result
+            && self.backtrace.is_none()
+        {
+            self.backtrace = Some(self.frames.iter().rev().map(|f| f.function.clone()).collect());
+        }
+        if let Some(frame) = self.frames.pop() {
+            for scope in frame.scopes {
+                self.expire(scope);
+            }
+        }
+    }
+
+    fn push_scope(&mut self) {
+        self.frame().scopes.push(Scope::default());
+    }
+
+    fn pop_scope(&mut self) {
+        if let Some(scope) = self.frame().scopes.pop() {
+            self.expire(scope);
+        }
+    }
+
+    fn expire(&mut self, scope: Scope) {
+        for alloc in scope.allocations {
+            self.memory.expire(alloc);
+        }
+    }
+
+    /// Allocate stack cells that live until the current scope ends
+    fn stack(&mut self, cells: Vec<Value>) -> Pointer {
+        let ptr = self.memory.allocate(Region::Stack, cells);
+        let scope = self.frame().scopes.last_mut().expect("frames have a scope");
+        scope.allocations.push(ptr.alloc);
+        ptr
+    }
+
+    fn temp(&mut self, value: Value) -> Pointer {
+        self.stack(vec![value])
+    }
+
+    fn declare(&mut self, name: &str, value: Value) {
+        let ptr = self.temp(value);
+        let scope = self.frame().scopes.last_mut().expect("frames have a scope");
+        scope.locals.push((name.to_string(), ptr));
+    }
+
+    fn local(&self, name: &str) -> Option<Pointer> {
+        self.frames
+            .last()?
+            .scopes
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.locals.iter().rev())
+            .find(|(n, _)| n == name)
+            .map(|(_, ptr)| ptr.clone())
+    }
+
+    fn consume_fuel(&mut self) -> Result<(), RuntimeError> {
+        if let Some(fuel) = &mut self.fuel {
+            if *fuel == 0 {
+                return Err(RuntimeError::OutOfFuel);
+            }
+            *fuel -= 1;
+        }
+        Ok(())
+    }
+
+    // ========================================================================
+    // Calls
+    // ========================================================================
+
+    /// Call `instance` with `args`, the receiver first for methods
+    fn call_function(&mut self, instance: Instance<'a>, args: Vec<Value>) -> Eval<Value> {
+        let def = instance.function;
+        let sig = def.signature;
+        let Some(body) = def.body else {
+            return self
+                .builtin(&sig.name, args)
+                .unwrap_or_else(|| Err(RuntimeError::UndefinedFunction(def.qualified_name()).into()));
+        };
+        let expected = sig.params.len() + usize::from(sig.self_param.is_some());
+        if args.len() != expected {
+            return Err(RuntimeError::ArityMismatch { function: def.qualified_name(), expected, found: args.len() }.into());
+        }
+        let return_type = match sig.return_types.as_slice() {
+            [ty] => Some(ty.clone()),
+            _ => None,
+        };
+        self.push_frame(def.qualified_name(), return_type.clone(), Some(&instance))?;
+        let result = match self.run_function(sig, body, args) {
+            Err(Unwind::Propagate(error)) => {
+                self.convert_error(error, return_type.as_ref()).map(|e| Value::Error(Box::new(e)))
+            }
+            other => other,
+        };
+        self.pop_frame(&result);
+        result
+    }
+
+    fn run_function(&mut self, sig: &FunctionSignature, body: &Block, args: Vec<Value>) -> Eval<Value> {
+        let mut args = args.into_iter();
+        if sig.self_param.is_some() {
+            let receiver = args.next().expect("arity was checked");
+            self.declare("self", receiver);
+        }
+        for (param, arg) in sig.params.iter().zip(args) {
+            let value = self.coerce(arg, &param.ty)?;
+            self.declare(&param.name, value);
+        }
+        match self.exec_block(body)? {
+            Flow::Return(value) => Ok(value),
+            Flow::Normal => Ok(Value::Ok),
+            Flow::Break(_) | Flow::Continue => {
+                Err(RuntimeError::Unsupported("`break` or `continue` outside of a loop".to_string()).into())
+            }
+        }
+    }
+
+    /// Convert an error leaving a function to the error type it declares: as
+    /// is when the types match, wrapped in the matching variant when the
+    /// declared error is a union, or through `E::from` when one exists
+    fn convert_error(&mut self, error: Value, return_type: Option<&Type>) -> Eval<Value> {
+        let Some(Type::ErrorUnion { err_type, .. }) = return_type.map(|ty| self.resolve_alias(ty)) else {
+            return Ok(error);
+        };
+        let items = self.items;
+        if err_type.segments.last() == Some(&error.type_name()) {
+            return Ok(error);
+        }
+        if let Some(TypeDef::Union(u)) = items.lookup_type(&err_type)
+            && let Some(variant) = u.variants.iter().find(|v| type_name(&v.ty) == Some(error.type_name()))
+        {
+            return Ok(Value::Union { name: u.name.clone(), variant: variant.name.clone(), payload: Box::new(error) });
+        }
+        let mut from = err_type.segments.clone();
+        from.push("from".to_string());
+        match items.lookup_function(&Path { segments: from, generic_args: vec![] }) {
+            Some(def) => self.call_function(Instance::new(def), vec![error]),
+            None => Ok(error),
+        }
+    }
+
+    /// The method `name` of the type named `ty`: declared on the type itself,
+    /// or a default body on an interface the type satisfies
+    fn find_method(&self, ty: &str, name: &str) -> Option<&'a FunctionDef<'a>> {
+        let items = self.items;
+        let method_of = |receiver: &str| {
+            items.functions().iter().find(|f| {
+                f.receiver_name() == Some(receiver) && f.signature.name == name && f.signature.self_param.is_some()
+            })
+        };
+        if let Some(method) = method_of(ty) {
+            return Some(method);
+        }
+        let checker = ConformanceChecker::new(items);
+        let self_type = Type::Path(Path::simple(ty.to_string()));
+        items.types().find_map(|(_, def)| {
+            let TypeDef::Interface(iface) = def else { return None };
+            let method = method_of(&iface.name).filter(|f| f.body.is_some())?;
+            checker.satisfies(&self_type, &Path::simple(iface.name.clone())).is_ok().then_some(method)
+        })
+    }
+
+    /// Call the method `name` on the value stored at `place`, as `instance`
+    /// when the type checker resolved the call to that method
+    fn call_method(
+        &mut self,
+        place: Pointer,
+        name: &str,
+        mut args: Vec<Value>,
+        instance: Option<Instance<'a>>,
+    ) -> Eval<Value> {
+        let ty = self.memory.get(&place)?.type_name();
+        let def = self
+            .find_method(&ty, name)
+            .ok_or_else(|| RuntimeError::NoSuchMethod { ty, method: name.to_string() })?;
+        let receiver = match &def.signature.self_param {
+            Some(SelfParameter { is_pointer: true, .. }) => Value::Pointer(place),
+            _ => self.memory.read(&place)?,
+        };
+        args.insert(0, receiver);
+        self.call_function(instance_of(def, instance), args)
+    }
+
+    /// The qualified name a callee or `Type::member` expression spells, with
+    /// generic arguments dropped; `None` for a local or a computed value
+    fn callee_path(&self, expr: &Expression) -> Option<Vec<String>> {
+        match expr {
+            Expression::Path(path) => match path.segments.as_slice() {
+                [name] if self.local(name).is_some() => None,
+                segments => Some(segments.to_vec()),
+            },
+            Expression::TypeAccess(access) => {
+                let mut segments = self.callee_path(&access.object)?;
+                segments.push(access.member.clone());
+                Some(segments)
+            }
+            Expression::Index(index) => self.callee_path(&index.object),
+            _ => None,
+        }
+    }
+
+    fn eval_args(&mut self, args: &[Expression]) -> Eval<Vec<Value>> {
+        args.iter().map(|arg| self.eval(arg)).collect()
+    }
+
+    /// Evaluate the arguments of a call to a function taking `params`. An
+    /// array passed for a slice parameter is shared rather than copied, so the
+    /// callee's writes through the slice reach the caller's array.
+    fn eval_params(&mut self, args: &[Expression], params: &[FunctionParameter]) -> Eval<Vec<Value>> {
+        let mut values = Vec::with_capacity(args.len());
+        for (i, arg) in args.iter().enumerate() {
+            let is_slice = params
+                .get(i)
+                .is_some_and(|param| matches!(self.resolve_alias(&param.ty), Type::Array { size: None, .. }));
+            if !is_slice {
+                values.push(self.eval(arg)?);
+                continue;
+            }
+            let place = self.place(arg)?;
+            values.push(match self.memory.get(&place)? {
+                Value::Array(elements) => Value::Slice { ptr: place.step(Step::Index(0)), len: elements.len() },
+                value => value.clone(),
+            });
+        }
+        Ok(values)
+    }
+
+    fn eval_call(&mut self, call: &CallExpr) -> Eval<Value> {
+        let instance = self.callee_instance(call);
+        let result = self.call_target(&call.callee, &call.args, instance)?;
+        match result {
+            Value::Error(error) if call.is_propagating => Err(Unwind::Propagate(*error)),
+            value => Ok(value),
+        }
+    }
+
+    fn call_target(&mut self, callee: &Expression, args: &[Expression], instance: Option<Instance<'a>>) -> Eval<Value> {
+        if let Expression::FieldAccess(access) = callee {
+            let place = if access.is_propagating {
+                let object = self.eval(&access.object)?;
+                let object = self.unwrap_error(object)?;
+                self.temp(object)
+            } else {
+                self.object_place(&access.object)?
+            };
+            let args = match &instance {
+                Some(instance) => self.eval_params(args, &instance.function.signature.params)?,
+                None => self.eval_args(args)?,
+            };
+            return self.call_method(place, &access.field, args, instance);
+        }
+
+        let items = self.items;
+        let Some(segments) = self.callee_path(callee) else {
+            return Err(RuntimeError::Unsupported("call of a computed value".to_string()).into());
+        };
+        let path = Path { segments: segments.clone(), generic_args: vec![] };
+        // A bare name never means a method, even when the item table's suffix match finds one
+        if let Some(def) = items.lookup_function(&path)
+            && (segments.len() > 1 || def.signature.receiver.is_none())
+        {
+            let args = self.eval_params(args, &def.signature.params)?;
+            return self.call_function(instance_of(def, instance), args);
+        }
+        if let Some(TypeDef::Struct(s)) = items.lookup_type(&path) {
+            return self.construct_struct(s, args);
+        }
+        if let [ty @ .., variant] = segments.as_slice()
+            && !ty.is_empty()
+            && let Some(TypeDef::Union(u)) = items.lookup_type(&Path { segments: ty.to_vec(), generic_args: vec![] })
+            && let Some(variant) = u.variants.iter().find(|v| v.name == *variant)
+        {
+            let [arg] = args else {
+                return Err(RuntimeError::ArityMismatch {
+                    function: segments.join("::"),
+                    expected: 1,
+                    found: args.len(),
+                }
+                .into());
+            };
+            return self.union_value(u, variant, arg);
+        }
+        let args = self.eval_args(args)?;
+        let name = segments.last().expect("paths have a segment");
+        self.builtin(name, args)
+            .unwrap_or_else(|| Err(RuntimeError::UndefinedFunction(segments.join("::")).into()))
+    }
+
+    fn union_value(&mut self, u: &Union, variant: &UnionVariant, payload: &Expression) -> Eval<Value> {
+        let payload = self.eval(payload)?;
+        let payload = self.coerce(payload, &variant.ty)?;
+        Ok(Value::Union { name: u.name.clone(), variant: variant.name.clone(), payload: Box::new(payload) })
+    }
+
+    fn construct_struct(&mut self, s: &Struct, args: &[Expression]) -> Eval<Value> {
+        if args.len() != s.fields.len() {
+            return Err(RuntimeError::ArityMismatch {
+                function: s.name.clone(),
+                expected: s.fields.len(),
+                found: args.len(),
+            }
+            .into());
+        }
+        let mut fields = Vec::with_capacity(args.len());
+        for (field, arg) in s.fields.iter().zip(args) {
+            let value = self.eval(arg)?;
+            fields.push((field.name.clone(), self.coerce(value, &field.ty)?));
+        }
+        Ok(Value::Struct { name: s.name.clone(), fields })
+    }
+
+    /// A struct literal: the values written in source order, then the base,
+    /// whose fields fill in the ones not written
+    fn struct_literal(&mut self, lit: &StructLiteralExpr) -> Eval<Value> {
+        let path = Path { segments: lit.ty.segments.clone(), generic_args: vec![] };
+        let Some(TypeDef::Struct(s)) = self.items.lookup_type(&path) else {
+            return Err(RuntimeError::UndefinedName(lit.ty.segments.join("::")).into());
+        };
+        let mut values: Vec<Option<Value>> = vec![None; s.fields.len()];
+        for (i, init) in lit.fields.iter().enumerate() {
+            let index = match &init.name {
+                Some(name) => s.fields.iter().position(|f| f.name == *name).ok_or_else(|| {
+                    RuntimeError::NoSuchField { ty: s.name.clone(), field: name.clone() }
+                })?,
+                None => i,
+            };
+            let Some(field) = s.fields.get(index) else {
+                return Err(RuntimeError::ArityMismatch {
+                    function: s.name.clone(),
+                    expected: s.fields.len(),
+                    found: lit.fields.len(),
+                }
+                .into());
+            };
+            let value = self.eval(&init.value)?;
+            values[index] = Some(self.coerce(value, &field.ty)?);
+        }
+        let mut base = match &lit.base {
+            Some(base) => match self.eval(base)? {
+                Value::Struct { fields, .. } => fields,
+                other => {
+                    let message = format!("cannot copy fields from `{}`", other.type_name());
+                    return Err(RuntimeError::TypeMismatch(message).into());
+                }
+            },
+            None => Vec::new(),
+        };
+        let mut fields = Vec::with_capacity(s.fields.len());
+        for (field, value) in s.fields.iter().zip(values) {
+            let value = match value {
+                Some(value) => value,
+                None => match base.iter().position(|(name, _)| *name == field.name) {
+                    Some(index) => base.swap_remove(index).1,
+                    None => {
+                        return Err(RuntimeError::ArityMismatch {
+                            function: s.name.clone(),
+                            expected: s.fields.len(),
+                            found: lit.fields.len(),
+                        }
+                        .into());
+                    }
+                },
+            };
+            fields.push((field.name.clone(), value));
+        }
+        Ok(Value::Struct { name: s.name.clone(), fields })
+    }
+
+    /// Host functions; `None` if `name` is not one
+    fn builtin(&mut self, name: &str, args: Vec<Value>) -> Option<Eval<Value>> {
+        let arity = |expected: usize| {
+            Err(RuntimeError::ArityMismatch { function: name.to_string(), expected, found: args.len() }.into())
+        };
+        Some(match (name, args.as_slice()) {
+            ("print" | "println", _) => {
+                let text: Vec<String> = args.iter().map(|arg| self.text_of(arg)).collect();
+                self.output.push_str(&text.join(" "));
+                if name == "println" {
+                    self.output.push('\n');
+                }
+                Ok(Value::Ok)
+            }
+            ("assert", [Value::Bool(true)]) => Ok(Value::Ok),
+            ("assert", [Value::Bool(false)]) => Err(RuntimeError::AssertionFailed.into()),
+            ("assert", [other]) => Err(RuntimeError::TypeMismatch(format!("cannot assert `{}`", other.type_name())).into()),
+            ("assert", _) => arity(1),
+            ("malloc", [Value::Int(size)]) => Ok(self.allocate(size.value, Value::Uninit)),
+            ("malloc", _) => arity(1),
+            ("calloc", [Value::Int(count), Value::Int(size)]) => {
+                Ok(self.allocate(count.value.saturating_mul(size.value), Value::Int(Int { value: 0, ty: None })))
+            }
+            ("calloc", _) => arity(2),
+            ("realloc", [Value::Null, Value::Int(size)]) => Ok(self.allocate(size.value, Value::Uninit)),
+            ("realloc", [Value::Pointer(ptr), Value::Int(size)]) => {
+                if size.value > MAX_ALLOCATION {
+                    Ok(Value::Null)
+                } else {
+                    self.memory.resize(ptr, size.value.max(0) as usize).map(|_| Value::Pointer(ptr.clone())).map_err(Unwind::from)
+                }
+            }
+            ("realloc", _) => arity(2),
+            ("free", [Value::Null]) => Ok(Value::Ok),
+            ("free", [Value::Pointer(ptr)]) => self.memory.free(ptr).map(|_| Value::Ok).map_err(Unwind::from),
+            ("free", _) => arity(1),
+            _ => return None,
+        })
+    }
+
+    fn allocate(&mut self, cells: i128, fill: Value) -> Value {
+        if !(0..=MAX_ALLOCATION).contains(&cells) {
+            return Value::Null;
+        }
+        Value::Pointer(self.memory.allocate(Region::Heap, vec![fill; cells as usize]))
+    }
+
+    /// How `print` shows a value: `[u8]` slices as text, everything else with
+    /// [`Value`]'s `Display`
+    fn text_of(&self, value: &Value) -> String {
+        if let Value::Slice { ptr, len } = value {
+            let bytes: Option<Vec<u8>> = (0..*len as i128)
+                .map(|i| match self.memory.read(&ptr.add(i)?) {
+                    Ok(Value::Int(Int { value, ty: Some(IntType::U8) })) => Some(value as u8),
+                    _ => None,
+                })
+                .collect();
+            if let Some(bytes) = bytes {
+                return String::from_utf8_lossy(&bytes).into_owned();
+            }
+        }
+        value.to_string()
+    }
+
+    /// A `[u8]` slice of `text` in static memory
+    fn string(&mut self, text: &str) -> Value {
+        let cells = text.bytes().map(|b| Value::int(b as i128, IntType::U8)).collect();
+        let ptr = self.memory.allocate(Region::Static, cells);
+        Value::Slice { ptr, len: text.len() }
+    }
+
+    // ========================================================================
+    // Types
+    // ========================================================================
+
+    /// `ty` with type aliases replaced by what they name
+    fn resolve_alias(&self, ty: &Type) -> Type {
+        let mut ty = ty.clone();
+        // Bounded, in case of a cyclic alias
+        for _ in 0..32 {
+            match &ty {
+                Type::Path(path) => match self.items.lookup_type(path) {
+                    Some(TypeDef::Alias(alias)) => ty = alias.aliased_type.clone(),
+                    _ => break,
+                },
+                _ => break,
+            }
+        }
+        ty
+    }
+
+    /// Convert `value` to the declared type `ty`: integers take the type when
+    /// their value fits, arrays become slices, and a value of the error type
+    /// of `T ! E` becomes an error. Types the interpreter cannot see through,
+    /// such as generic parameters, leave the value unchanged.
+    fn coerce(&mut self, value: Value, ty: &Type) -> Eval<Value> {
+        let ty = self.resolve_alias(ty);
+        let target = self.target();
+        if let Some(int_ty) = IntType::from_type(&ty)
+            && let Value::Int(i) = &value
+        {
+            return Ok(ops::fit(i.value, int_ty, &target)?);
+        }
+        Ok(match (&ty, value) {
+            (_, Value::Error(error)) => Value::Error(error),
+            (Type::F32 | Type::F64, Value::Int(Int { value, ty: None })) => ops::cast(Value::Int(Int { value, ty: None }), &ty, &target)?,
+            (Type::F32 | Type::F64, value @ Value::Float { .. }) => ops::cast(value, &ty, &target)?,
+            (Type::Optional(_), Value::Null) => Value::Null,
+            (Type::Optional(inner), value) => self.coerce(value, inner)?,
+            (Type::Array { element_type, size }, Value::Array(elements)) => {
+                let elements =
+                    elements.into_iter().map(|e| self.coerce(e, element_type)).collect::<Eval<Vec<_>>>()?;
+                if size.is_some() {
+                    Value::Array(elements)
+                } else {
+                    let len = elements.len();
+                    Value::Slice { ptr: self.stack(elements), len }
+                }
+            }
+            (Type::ErrorUnion { ok_type, err_type }, value) => {
+                let name = value.type_name();
+                if err_type.segments.last() == Some(&name) && type_name(ok_type) != Some(name) {
+                    Value::Error(Box::new(value))
+                } else {
+                    self.coerce(value, ok_type)?
+                }
+            }
+            (_, value) => value,
+        })
+    }
+
+    fn cast(&mut self, value: Value, ty: &Type) -> Eval<Value> {
+        let ty = self.resolve_alias(ty);
+        let Type::Path(path) = &ty else {
+            return Ok(ops::cast(value, &ty, &self.target())?);
+        };
+        match (self.items.lookup_type(path), value) {
+            (Some(TypeDef::Enum(e)), Value::Int(i)) => {
+                let discriminants = self.layout.enum_discriminants(e).map_err(layout_error)?;
+                let (variant, discriminant) = discriminants
+                    .into_iter()
+                    .find(|(_, d)| *d == i.value)
+                    .ok_or_else(|| RuntimeError::OutOfRange { value: i.value.to_string(), ty: e.name.clone() })?;
+                Ok(Value::Enum { name: e.name.clone(), variant, discriminant })
+            }
+            (Some(def), value) if value.type_name() != def.name() => Err(RuntimeError::TypeMismatch(format!(
+                "cannot cast `{}` to `{}`",
+                value.type_name(),
+                def.name()
+            ))
+            .into()),
+            // The same type, or a generic parameter
+            (_, value) => Ok(value),
+        }
+    }
+
+    // ========================================================================
+    // Statements
+    // ========================================================================
+
+    fn exec_block(&mut self, block: &Block) -> Eval<Flow> {
+        self.push_scope();
+        let result = self.exec_statements(&block.statements);
+        self.pop_scope();
+        result
+    }
+
+    fn exec_statements(&mut self, statements: &[Statement]) -> Eval<Flow> {
+        for statement in statements {
+            match self.exec(statement)? {
+                Flow::Normal => {}
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn exec(&mut self, statement: &Statement) -> Eval<Flow> {
+        self.consume_fuel()?;
+        match statement {
+            Statement::Expression(expr) => {
+                self.eval(expr)?;
+            }
+            Statement::Let(s) => self.bind(&s.name, s.ty.as_ref(), &s.value)?,
+            Statement::Mut(s) => self.bind(&s.name, s.ty.as_ref(), &s.value)?,
+            Statement::Const(s) => self.bind(&s.name, s.ty.as_ref(), &s.value)?,
+            Statement::Return(expr) => {
+                let value = self.eval(expr)?;
+                let value = match self.frame().return_type.clone() {
+                    Some(ty) => self.coerce(value, &ty)?,
+                    None => value,
+                };
+                return Ok(Flow::Return(value));
+            }
+            Statement::Break(label) => return Ok(Flow::Break(label.clone())),
+            Statement::Continue => return Ok(Flow::Continue),
+            Statement::Block(block) => {
+                return Ok(match (self.exec_block(&block.body)?, &block.name) {
+                    (Flow::Break(Some(label)), Some(name)) if label == *name => Flow::Normal,
+                    (flow, _) => flow,
+                });
+            }
+            Statement::If(s) => {
+                if self.condition(&s.condition)? {
+                    return self.exec_block(&s.then_body);
+                }
+                for clause in &s.elif_clauses {
+                    if self.condition(&clause.condition)? {
+                        return self.exec_block(&clause.body);
+                    }
+                }
+                if let Some(body) = &s.else_body {
+                    return self.exec_block(body);
+                }
+            }
+            Statement::While(s) => {
+                while self.condition(&s.condition)? {
+                    if let Some(flow) = self.iteration(&s.body, None)? {
+                        return Ok(flow);
+                    }
+                }
+            }
+            Statement::For(s) => return self.exec_for(s),
+            // Declarations are found through the item table
+            Statement::Pass
+            | Statement::Using(_)
+            | Statement::Function(_)
+            | Statement::FunctionDeclaration(_)
+            | Statement::TypeAlias(_)
+            | Statement::Struct(_)
+            | Statement::Enum(_)
+            | Statement::Union(_)
+            | Statement::Interface(_)
+            | Statement::Namespace(_) => {}
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn bind(&mut self, name: &str, ty: Option<&Type>, value: &Expression) -> Eval<()> {
+        let value = self.eval(value)?;
+        let value = match ty {
+            Some(ty) => self.coerce(value, ty)?,
+            None => value,
+        };
+        self.declare(name, value);
+        Ok(())
+    }
+
+    fn condition(&mut self, expr: &Expression) -> Eval<bool> {
+        match self.eval(expr)? {
+            Value::Bool(b) => Ok(b),
+            other => Err(RuntimeError::TypeMismatch(format!("condition is `{}`, not `bool`", other.type_name())).into()),
+        }
+    }
+
+    /// Run one loop iteration, with `binding` declared if given. Returns the
+    /// flow that ends the loop, or `None` to carry on.
+    fn iteration(&mut self, body: &Block, binding: Option<(&str, Value)>) -> Eval<Option<Flow>> {
+        self.push_scope();
+        if let Some((name, value)) = binding {
+            self.declare(name, value);
+        }
+        let flow = self.exec_block(body);
+        self.pop_scope();
+        Ok(match flow? {
+            Flow::Normal | Flow::Continue => None,
+            Flow::Break(None) => Some(Flow::Normal),
+            flow => Some(flow),
+        })
+    }
+
+    /// `for` over a range of integers, an array, a slice, or an iterator:
+    /// anything with a `next(*mut self) -> ?T` method, which is called until
+    /// it returns `null`
+    fn exec_for(&mut self, s: &ForStatement) -> Eval<Flow> {
+        let pattern = s.pattern.as_str();
+        if let Expression::Range(range) = s.iterable.as_ref() {
+            return self.exec_range_for(s, range);
+        }
+        match self.eval(&s.iterable)? {
+            Value::Array(elements) => {
+                for element in elements {
+                    if let Some(flow) = self.iteration(&s.body, Some((pattern, element)))? {
+                        return Ok(flow);
+                    }
+                }
+            }
+            Value::Slice { ptr, len } => {
+                for i in 0..len as i128 {
+                    let element = self.memory.read(&ptr.add(i).ok_or(RuntimeError::OutOfBounds)?)?;
+                    if let Some(flow) = self.iteration(&s.body, Some((pattern, element)))? {
+                        return Ok(flow);
+                    }
+                }
+            }
+            iterator @ (Value::Struct { .. } | Value::Union { .. } | Value::Pointer(_)) => {
+                self.push_scope();
+                let place = match iterator {
+                    Value::Pointer(ptr) => ptr,
+                    other => self.temp(other),
+                };
+                let result = loop {
+                    let next = match self.call_method(place.clone(), "next", vec![], None) {
+                        Ok(Value::Null) => break Ok(Flow::Normal),
+                        Ok(next) => next,
+                        Err(unwind) => break Err(unwind),
+                    };
+                    match self.iteration(&s.body, Some((pattern, next))) {
+                        Ok(None) => {}
+                        Ok(Some(flow)) => break Ok(flow),
+                        Err(unwind) => break Err(unwind),
+                    }
+                };
+                self.pop_scope();
+                return result;
+            }
+            other => {
+                return Err(RuntimeError::TypeMismatch(format!("cannot iterate over `{}`", other.type_name())).into());
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    /// Count through `start..end` or `start..=end`. The bounds are evaluated
+    /// once, and an unsuffixed start takes the type of the end, as in `0..n`.
+    fn exec_range_for(&mut self, s: &ForStatement, range: &RangeExpr) -> Eval<Flow> {
+        let Some(start) = &range.start else {
+            return Err(RuntimeError::Unsupported("a range without a start in `for`".to_string()).into());
+        };
+        let start = self.range_bound(start)?;
+        let end = match &range.end {
+            Some(end) => Some(self.range_bound(end)?),
+            None => None,
+        };
+        let target = self.target();
+        let mut current = match (start.ty, end.and_then(|end| end.ty)) {
+            (None, Some(ty)) => ops::fit(start.value, ty, &target)?,
+            _ => Value::Int(start),
+        };
+        loop {
+            let Value::Int(i) = current else { unreachable!("ranges count in integers") };
+            let is_last = match end {
+                Some(end) if i.value > end.value || (i.value == end.value && !range.is_inclusive) => break,
+                Some(end) => i.value == end.value,
+                None => false,
+            };
+            if let Some(flow) = self.iteration(&s.body, Some((s.pattern.as_str(), current.clone())))? {
+                return Ok(flow);
+            }
+            if is_last {
+                break;
+            }
+            current = ops::binary(BinaryOperator::Add, current, Value::Int(Int { value: 1, ty: None }), &target)?;
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn range_bound(&mut self, expr: &Expression) -> Eval<Int> {
+        match self.eval(expr)? {
+            Value::Int(i) => Ok(i),
+            other => {
+                let message = format!("range bound is `{}`, not an integer", other.type_name());
+                Err(RuntimeError::TypeMismatch(message).into())
+            }
+        }
+    }
+
+    /// `object[start..end]`: a slice into the array or slice `object` holds,
+    /// sharing its elements
+    fn subslice(&mut self, index: &IndexExpr) -> Eval<Value> {
+        let Expression::Range(range) = index.index.as_ref() else { unreachable!("checked by the caller") };
+        let place = self.place(&index.object)?;
+        let start = match &range.start {
+            Some(start) => self.range_bound(start)?.value,
+            None => 0,
+        };
+        let end = match &range.end {
+            Some(end) => Some(self.range_bound(end)?.value + i128::from(range.is_inclusive)),
+            None => None,
+        };
+        let (first, len) = match self.memory.get(&place)? {
+            Value::Array(elements) => (place.step(Step::Index(0)), elements.len()),
+            Value::Slice { ptr, len } => (ptr.clone(), *len),
+            Value::Null => return Err(RuntimeError::NullDereference.into()),
+            other => return Err(RuntimeError::TypeMismatch(format!("cannot slice `{}`", other.type_name())).into()),
+        };
+        let end = end.unwrap_or(len as i128);
+        if start < 0 || start > end || end > len as i128 {
+            return Err(RuntimeError::SliceOutOfBounds { start: start as u64, end: end as u64, len }.into());
+        }
+        Ok(Value::Slice { ptr: first.add(start).expect("start is in bounds"), len: (end - start) as usize })
+    }
+
+    // ========================================================================
+    // Places
+    // ========================================================================
+
+    /// The address an expression designates. Expressions that are not places
+    /// are evaluated into a temporary.
+    fn place(&mut self, expr: &Expression) -> Eval<Pointer> {
+        match expr {
+            Expression::Path(path) if path.segments.len() == 1 && self.local(&path.segments[0]).is_some() => {
+                Ok(self.local(&path.segments[0]).expect("checked above"))
+            }
+            Expression::SelfValue => {
+                self.local("self").ok_or_else(|| RuntimeError::UndefinedName("self".to_string()).into())
+            }
+            Expression::Parenthesized(inner) => self.place(inner),
+            Expression::FieldAccess(access) if !access.is_propagating => {
+                Ok(self.object_place(&access.object)?.step(Step::Field(access.field.clone())))
+            }
+            Expression::Index(index) if !matches!(*index.index, Expression::Range(_)) => self.index_place(index),
+            Expression::UnaryOp(UnaryOpExpr { op: UnaryOperator::Dereference, operand }) => {
+                let ptr = self.eval(operand)?;
+                self.pointee(ptr)
+            }
+            _ => {
+                let value = self.eval(expr)?;
+                Ok(self.temp(value))
+            }
+        }
+    }
+
+    /// The place of the object in `object.field`, looking through one pointer
+    fn object_place(&mut self, expr: &Expression) -> Eval<Pointer> {
+        let place = self.place(expr)?;
+        match self.memory.get(&place)? {
+            Value::Pointer(ptr) => Ok(ptr.clone()),
+            Value::Null => Err(RuntimeError::NullDereference.into()),
+            _ => Ok(place),
+        }
+    }
+
+    fn pointee(&self, value: Value) -> Eval<Pointer> {
+        match value {
+            Value::Pointer(ptr) => Ok(ptr),
+            Value::Null => Err(RuntimeError::NullDereference.into()),
+            other => Err(RuntimeError::TypeMismatch(format!("cannot dereference `{}`", other.type_name())).into()),
+        }
+    }
+
+    fn index_place(&mut self, index: &IndexExpr) -> Eval<Pointer> {
+        let place = self.place(&index.object)?;
+        let i = match self.eval(&index.index)? {
+            Value::Int(i) => i.value,
+            other => {
+                return Err(RuntimeError::TypeMismatch(format!("cannot index with `{}`", other.type_name())).into());
+            }
+        };
+        let out_of_bounds = |len| RuntimeError::IndexOutOfBounds { index: i, len };
+        match self.memory.get(&place)? {
+            Value::Array(elements) => {
+                let len = elements.len();
+                if i < 0 || i >= len as i128 {
+                    return Err(out_of_bounds(len).into());
+                }
+                Ok(place.step(Step::Index(i as usize)))
+            }
+            Value::Slice { ptr, len } => {
+                if i < 0 || i >= *len as i128 {
+                    return Err(out_of_bounds(*len).into());
+                }
+                Ok(ptr.add(i).expect("index is in bounds"))
+            }
+            Value::Pointer(ptr) => Ok(ptr.add(i).ok_or(RuntimeError::OutOfBounds)?),
+            Value::Null => Err(RuntimeError::NullDereference.into()),
+            other => Err(RuntimeError::TypeMismatch(format!("cannot index `{}`", other.type_name())).into()),
+        }
+    }
+
+    /// Write `value` to `place`. An unsuffixed integer takes the type of the
+    /// integer it replaces.
+    fn store(&mut self, place: &Pointer, value: Value) -> Eval<()> {
+        let current = match self.memory.get(place)? {
+            Value::Int(Int { ty: Some(ty), .. }) => Some(*ty),
+            _ => None,
+        };
+        let value = match (current, value) {
+            (Some(ty), Value::Int(Int { value, ty: None })) => ops::fit(value, ty, &self.target())?,
+            (_, value) => value,
+        };
+        self.memory.write(place, value)?;
+        Ok(())
+    }
+
+    fn assign(&mut self, assign: &AssignExpr) -> Eval<()> {
+        let place = self.place(&assign.lhs)?;
+        let rhs = self.eval(&assign.rhs)?;
+        let op = match assign.op {
+            AssignOperator::Assign => return self.store(&place, rhs),
+            AssignOperator::AddAssign => BinaryOperator::Add,
+            AssignOperator::SubAssign => BinaryOperator::Subtract,
+            AssignOperator::MulAssign => BinaryOperator::Multiply,
+            AssignOperator::DivAssign => BinaryOperator::Divide,
+            AssignOperator::ModAssign => BinaryOperator::Modulo,
+            AssignOperator::BitAndAssign => BinaryOperator::BitwiseAnd,
+            AssignOperator::BitOrAssign => BinaryOperator::BitwiseOr,
+            AssignOperator::BitXorAssign => BinaryOperator::BitwiseXor,
+            AssignOperator::ShlAssign => BinaryOperator::ShiftLeft,
+            AssignOperator::ShrAssign => BinaryOperator::ShiftRight,
+        };
+        let current = self.memory.read(&place)?;
+        let value = ops::binary(op, current, rhs, &self.target())?;
+        self.store(&place, value)
+    }
+
+    // ========================================================================
+    // Expressions
+    // ========================================================================
+
+    fn eval(&mut self, expr: &Expression) -> Eval<Value> {
+        let target = self.target();
+        Ok(match expr {
+            Expression::IntegerLiteral(lit) => self.integer(lit)?,
+            Expression::FloatLiteral(lit) => {
+                let value = lit.as_f64().map_err(|_| RuntimeError::OutOfRange { value: lit.to_string(), ty: "f64".to_string() })?;
+                let ty = if lit.suffix() == Some(&FloatSuffix::F32) { Type::F32 } else { Type::F64 };
+                ops::cast(Value::Float { value, single: false }, &ty, &target)?
+            }
+            Expression::BooleanLiteral(b) => Value::Bool(*b),
+            Expression::CharLiteral(c) => {
+                let c = c.chars().next().map_or(0, u32::from);
+                let ty = if c <= 0xFF { IntType::U8 } else { IntType::U32 };
+                Value::int(c as i128, ty)
+            }
+            Expression::StringLiteral(s) => match self.strings.get(s) {
+                Some(value) => value.clone(),
+                None => {
+                    let value = self.string(s);
+                    self.strings.insert(s.clone(), value.clone());
+                    value
+                }
+            },
+            Expression::OkLiteral => Value::Ok,
+            Expression::NullLiteral => Value::Null,
+            Expression::Index(index) if matches!(*index.index, Expression::Range(_)) => self.subslice(index)?,
+            Expression::SelfValue | Expression::Index(_) => {
+                let place = self.place(expr)?;
+                self.memory.read(&place)?
+            }
+            Expression::Path(path) => match path.segments.as_slice() {
+                [name] if self.local(name).is_some() => {
+                    let place = self.local(name).expect("checked above");
+                    self.memory.read(&place)?
+                }
+                segments => self.global(segments)?,
+            },
+            Expression::TypeAccess(_) => match self.callee_path(expr) {
+                Some(segments) => self.global(&segments)?,
+                None => return Err(RuntimeError::Unsupported("`::` on a value".to_string()).into()),
+            },
+            Expression::StructLiteral(lit) => self.struct_literal(lit)?,
+            Expression::VariantLiteral(lit) => {
+                let mut segments = lit.ty.segments.clone();
+                segments.push(lit.variant.clone());
+                match &lit.payload {
+                    Some(payload) => {
+                        let path = Path { segments: lit.ty.segments.clone(), generic_args: vec![] };
+                        let Some(TypeDef::Union(u)) = self.items.lookup_type(&path) else {
+                            return Err(RuntimeError::UndefinedName(segments.join("::")).into());
+                        };
+                        let Some(variant) = u.variants.iter().find(|v| v.name == lit.variant) else {
+                            return Err(RuntimeError::UndefinedName(segments.join("::")).into());
+                        };
+                        self.union_value(u, variant, payload)?
+                    }
+                    None => self.global(&segments)?,
+                }
+            }
+            Expression::EnumVariant(value) => {
+                let mut segments = value.ty.segments.clone();
+                segments.push(value.variant.clone());
+                self.global(&segments)?
+            }
+            Expression::ArrayLiteral(array) => Value::Array(self.eval_args(&array.elements)?),
+            Expression::Lambda(_) => return Err(RuntimeError::Unsupported("lambdas".to_string()).into()),
+            Expression::Range(_) => {
+                return Err(RuntimeError::Unsupported("a range outside `for` or a slice".to_string()).into());
+            }
+            Expression::InterpolatedString(parts) => {
+                let mut text = String::new();
+                for part in parts {
+                    let value = match part {
+                        InterpolatedPart::Text(t) => match t.strip_prefix('{').and_then(|t| t.strip_suffix('}')) {
+                            Some(source) => {
+                                let expr = fig_parser::ExpressionParser::new()
+                                    .parse(fig_parser::Lexer::new(source))
+                                    .map_err(|_| RuntimeError::Unsupported(format!("interpolation `{}`", source)))?;
+                                self.eval(&expr)?
+                            }
+                            None => {
+                                text.push_str(t);
+                                continue;
+                            }
+                        },
+                        InterpolatedPart::Expression(expr) => self.eval(expr)?,
+                    };
+                    text.push_str(&self.text_of(&value));
+                }
+                self.string(&text)
+            }
+            Expression::BinaryOp(binary) => {
+                let lhs = self.eval(&binary.lhs)?;
+                match (binary.op, &lhs) {
+                    (BinaryOperator::LogicalAnd, Value::Bool(false)) => Value::Bool(false),
+                    (BinaryOperator::LogicalOr, Value::Bool(true)) => Value::Bool(true),
+                    _ => {
+                        let rhs = self.eval(&binary.rhs)?;
+                        ops::binary(binary.op, lhs, rhs, &target)?
+                    }
+                }
+            }
+            Expression::UnaryOp(unary) => match unary.op {
+                UnaryOperator::AddressOf => Value::Pointer(self.place(&unary.operand)?),
+                UnaryOperator::Dereference => {
+                    let ptr = self.eval(&unary.operand)?;
+                    let ptr = self.pointee(ptr)?;
+                    self.memory.read(&ptr)?
+                }
+                op => {
+                    let operand = self.eval(&unary.operand)?;
+                    ops::unary(op, operand, &target)?
+                }
+            },
+            Expression::FieldAccess(access) => {
+                let place = if access.is_propagating {
+                    let object = self.eval(&access.object)?;
+                    let object = self.unwrap_error(object)?;
+                    self.temp(object)
+                } else {
+                    self.object_place(&access.object)?
+                };
+                match (self.memory.get(&place)?, access.field.as_str()) {
+                    (Value::Slice { len, .. }, "len") => Value::usize(*len),
+                    (Value::Array(elements), "len") => Value::usize(elements.len()),
+                    _ => self.memory.read(&place.step(Step::Field(access.field.clone())))?,
+                }
+            }
+            Expression::Call(call) => self.eval_call(call)?,
+            Expression::Cast(cast) => {
+                let value = self.eval(&cast.expr)?;
+                self.cast(value, &cast.target_type)?
+            }
+            Expression::Sizeof(ty) => {
+                let ty = self.instantiate(ty);
+                Value::usize(self.layout.size_of(&ty).map_err(layout_error)? as usize)
+            }
+            Expression::Alignof(ty) => {
+                let ty = self.instantiate(ty);
+                Value::usize(self.layout.align_of(&ty).map_err(layout_error)? as usize)
+            }
+            Expression::Offsetof(offsetof) => {
+                let ty = self.instantiate(&offsetof.ty);
+                Value::usize(self.layout.offset_of(&ty, &offsetof.field).map_err(layout_error)? as usize)
+            }
+            Expression::Parenthesized(inner) => self.eval(inner)?,
+            Expression::Assign(assign) => {
+                self.assign(assign)?;
+                Value::Ok
+            }
+        })
+    }
+
+    fn integer(&self, lit: &IntegerLiteral) -> Result<Value, RuntimeError> {
+        let out_of_range = || RuntimeError::OutOfRange { value: lit.to_string(), ty: "u64".to_string() };
+        let value = lit.as_u64().map_err(|_| out_of_range())? as i128;
+        match lit.suffix() {
+            Some(suffix) => ops::fit(value, IntType::from_suffix(suffix), &self.target()),
+            None => Ok(Value::Int(Int { value, ty: None })),
+        }
+    }
+
+    /// The ok side of a `T ! E`, or the error on its way out of the function
+    fn unwrap_error(&self, value: Value) -> Eval<Value> {
+        match value {
+            Value::Error(error) => Err(Unwind::Propagate(*error)),
+            value => Ok(value),
+        }
+    }
+
+    /// A name that is not a local: a `const` item, an enum variant, or a
+    /// union variant that carries no payload
+    fn global(&mut self, segments: &[String]) -> Eval<Value> {
+        let items = self.items;
+        let path = Path { segments: segments.to_vec(), generic_args: vec![] };
+        if let Some(c) = items.lookup_const(&path) {
+            return self.const_value(c);
+        }
+        if let [ty @ .., variant] = segments
+            && !ty.is_empty()
+        {
+            match items.lookup_type(&Path { segments: ty.to_vec(), generic_args: vec![] }) {
+                Some(TypeDef::Enum(e)) => {
+                    let discriminants = self.layout.enum_discriminants(e).map_err(layout_error)?;
+                    if let Some((_, discriminant)) = discriminants.into_iter().find(|(name, _)| name == variant) {
+                        return Ok(Value::Enum { name: e.name.clone(), variant: variant.clone(), discriminant });
+                    }
+                }
+                Some(TypeDef::Union(u)) if u.variants.iter().any(|v| v.name == *variant && v.ty == Type::Ok) => {
+                    return Ok(Value::Union { name: u.name.clone(), variant: variant.clone(), payload: Box::new(Value::Ok) });
+                }
+                _ => {}
+            }
+        }
+        Err(RuntimeError::UndefinedName(segments.join("::")).into())
+    }
+
+    /// The value of a `const` item, evaluated on first use
+    fn const_value(&mut self, c: &'a ConstStatement) -> Eval<Value> {
+        let key = c as *const ConstStatement as usize;
+        if let Some(value) = self.consts.get(&key) {
+            return Ok(value.clone());
+        }
+        self.push_frame(c.name.clone(), None, None)?;
+        let result = self.eval(&c.value).and_then(|value| match &c.ty {
+            Some(ty) => self.coerce(value, ty),
+            None => Ok(value),
+        });
+        self.pop_frame(&result);
+        let value = result?;
+        self.consts.insert(key, value.clone());
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    fn run(src: &str) -> (Result<Value, Trap>, String) {
+        let sf = parse(src);
+        let items = ItemTable::from_source_file(&sf);
+        let mut interp = Interpreter::new(&items).with_target(Target::X86_64).with_fuel(100_000);
+        let result = interp.call("main", vec![]);
+        (result, interp.output().to_string())
+    }
+
+    fn value(src: &str) -> Value {
+        run(src).0.unwrap()
+    }
+
+    fn trap(src: &str) -> RuntimeError {
+        run(src).0.unwrap_err().error
+    }
+
+    #[test]
+    fn test_arithmetic_follows_literal_suffixes() {
+        assert_eq!(value("func main() -> u8\n    return 250u8 + 5\n"), Value::int(255, IntType::U8));
+        assert_eq!(
+            trap("func main() -> u8\n    return 250u8 + 6\n"),
+            RuntimeError::Overflow { op: "addition", ty: "u8".into() }
+        );
+        assert_eq!(value("func main() -> u8\n    return (250u16 + 6) as u8\n"), Value::int(0, IntType::U8));
+        assert_eq!(value("func main() -> i32\n    return 2 + 3 * 4 - 10 / 3\n"), Value::int(11, IntType::I32));
+        assert!(matches!(trap("func main() -> u8\n    let x: u8 = 256\n    return x\n"), RuntimeError::OutOfRange { .. }));
+    }
+
+    #[test]
+    fn test_control_flow() {
+        let src = "\
+func main() -> i32
+    mut total = 0
+    mut i = 0
+    while true
+        i += 1
+        if i > 10
+            break
+        elif i % 2 == 0
+            continue
+        total += i
+    block outer
+        for x in [1, 2, 3]
+            if x == 2
+                break outer
+            total += 100
+    return total
+";
+        assert_eq!(value(src), Value::int(125, IntType::I32));
+    }
+
+    #[test]
+    fn test_structs_methods_and_pointers() {
+        let src = "\
+struct Counter
+    count: u32
+
+func Counter::bump(*mut self, by: u32) -> ok
+    self.count += by
+    pass
+
+func Counter::get(self) -> u32
+    return self.count
+
+func main() -> u32
+    mut c = Counter(1)
+    c.bump(2)
+    let p = &c
+    p.bump(3)
+    (*p).count = (*p).count * 2
+    return c.get()
+";
+        assert_eq!(value(src), Value::int(12, IntType::U32));
+    }
+
+    #[test]
+    fn test_enums_unions_and_optionals() {
+        let src = "\
+enum Color
+    Red
+    Green = 5
+    Blue
+
+union Shape
+    circle: u32
+    square: u32
+
+func area(s: Shape) -> u32
+    return s.square * s.square
+
+func find(xs: [i32], x: i32) -> ?usize
+    mut i = 0usize
+    for y in xs
+        if y == x
+            return i
+        i += 1
+    return null
+
+func main() -> u32
+    let c = Color::Blue
+    assert(c as u8 == 6)
+    assert(find([1, 2, 3], 3) == 2)
+    assert(find([1, 2, 3], 4) == null)
+    return area(Shape::square(7))
+";
+        assert_eq!(value(src), Value::int(49, IntType::U32));
+        let inactive = "\
+union Shape
+    circle: u32
+    square: u32
+
+func main() -> u32
+    let s = Shape::circle(1)
+    return s.square
+";
+        assert!(matches!(trap(inactive), RuntimeError::InactiveVariant { .. }));
+    }
+
+    #[test]
+    fn test_error_unions_propagate() {
+        let src = "\
+struct ParseError
+    at: usize
+
+func digit(c: u8) -> u8 ! ParseError
+    if c < '0' || c > '9'
+        return ParseError(0)
+    return c - '0'
+
+func! number(s: [u8]) -> u32 ! ParseError
+    mut n = 0u32
+    for c in s
+        n = n * 10 + digit!(c) as u32
+    return n
+
+func main() -> u32
+    let bad = number(\"1x\")
+    assert(bad != 12)
+    return number(\"042\")
+";
+        assert_eq!(value(src), Value::int(42, IntType::U32));
+        let error = "\
+struct E
+    code: i32
+
+func fail() -> i32 ! E
+    return E(7)
+
+func! main() -> i32 ! E
+    return fail!() + 1
+";
+        let result = value(error);
+        assert_eq!(
+            result,
+            Value::Error(Box::new(Value::Struct {
+                name: "E".into(),
+                fields: vec![("code".into(), Value::int(7, IntType::I32))]
+            }))
+        );
+    }
+
+    #[test]
+    fn test_memory_errors_trap() {
+        let dangling = "\
+func escape() -> *i32
+    let x = 1
+    return &x
+
+func main() -> i32
+    return *escape()
+";
+        assert_eq!(trap(dangling), RuntimeError::DanglingPointer);
+        let freed = "\
+func main() -> i32
+    let p = malloc(16) as *mut i32
+    p[0] = 1
+    free(p)
+    return p[0]
+";
+        assert_eq!(trap(freed), RuntimeError::UseAfterFree);
+        let bounds = "\
+func main() -> i32
+    let xs: [i32; 2] = [1, 2]
+    return xs[2]
+";
+        assert_eq!(trap(bounds), RuntimeError::IndexOutOfBounds { index: 2, len: 2 });
+    }
+
+    #[test]
+    fn test_traps_carry_a_backtrace() {
+        let src = "\
+func inner(x: u8) -> u8
+    return x * 2
+
+func main() -> u8
+    return inner(200)
+";
+        let error = run(src).0.unwrap_err();
+        assert_eq!(error.backtrace, vec!["inner".to_string(), "main".to_string()]);
+        assert_eq!(error.to_string(), "error: `u8` multiplication overflowed\n  in `inner`\n  in `main`");
+        let recursion = "func f(n: u64) -> u64\n    return f(n + 1)\n\nfunc main() -> u64\n    return f(0)\n";
+        assert_eq!(trap(recursion), RuntimeError::StackOverflow);
+        assert_eq!(trap("func main() -> ok\n    while true\n        pass\n"), RuntimeError::OutOfFuel);
+    }
+
+    #[test]
+    fn test_print_and_interpolation() {
+        let src = "\
+const LIMIT: u16 = 3
+
+func main() -> ok
+    for i in [1, 2, 3]
+        if i <= LIMIT
+            println($\"i = {i}\", \"ok\")
+    print(\"done\")
+    pass
+";
+        assert_eq!(run(src).1, "i = 1 ok\ni = 2 ok\ni = 3 ok\ndone");
+    }
+
+    #[test]
+    fn test_generic_sizes_and_shared_slices() {
+        let src = "\
+struct Pair[T]
+    a: T
+    b: T
+
+func[T] width(value: T) -> usize
+    return sizeof(Pair[T])
+
+func! fill(out: [i32]) -> ok
+    out[0] = 7
+    pass
+
+func! main() -> usize
+    mut items = [0, 0]
+    fill(items)
+    println(items[0])
+    return width(1u16) + width(1u64)
+";
+        let (result, output) = run(src);
+        assert_eq!(result.unwrap(), Value::usize(20));
+        assert_eq!(output, "7\n");
+    }
+}
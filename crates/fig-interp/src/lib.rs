@@ -0,0 +1,28 @@
+//! Tree-walking interpreter for Fig
+//!
+//! The [`Interpreter`] executes a parsed source file directly, looking up
+//! declarations through a `fig-sema` [`ItemTable`](fig_sema::items::ItemTable).
+//! Memory is simulated (see [`memory`]), so out-of-bounds accesses, dangling
+//! pointers and integer overflow stop the program with a [`error::Trap`]
+//! instead of corrupting it.
+//!
+//! ```ignore
+//! let sf = SourceFileParser::new().parse(Lexer::new(src))?;
+//! let items = ItemTable::from_source_file(&sf);
+//! let result = Interpreter::new(&items).call("main", vec![])?;
+//! ```
+
+pub mod error;
+mod interp;
+pub mod memory;
+mod ops;
+pub mod value;
+
+pub use interp::Interpreter;
+
+#[cfg(test)]
+pub(crate) fn parse(src: &str) -> fig_parser::ast::SourceFile {
+    fig_parser::SourceFileParser::new()
+        .parse(fig_parser::Lexer::new(src))
+        .unwrap()
+}
@@ -0,0 +1,242 @@
+//! Simulated memory
+//!
+//! Memory is a list of allocations, each a sequence of cells holding one
+//! [`Value`] apiece. Cells are not bytes: a pointer to element `i` of a
+//! `*mut T` buffer addresses cell `i` whatever the size of `T`, so a buffer
+//! obtained as `malloc(sizeof(T) * n) as *mut T` simply has spare cells.
+//!
+//! Allocations are never reused. A freed heap block or an out-of-scope
+//! local keeps its slot with a state recording why it is gone, so stale
+//! pointers are reported precisely instead of aliasing newer data.
+
+use crate::error::RuntimeError;
+use crate::value::{Pointer, Step, Value};
+
+/// Where an allocation lives, which decides how it may be released
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Region {
+    /// A local variable or temporary, released when its scope ends
+    Stack,
+    /// `malloc` and friends, released by `free`
+    Heap,
+    /// String literals, never released
+    Static,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Live,
+    Freed,
+    Expired,
+}
+
+#[derive(Debug, Clone)]
+struct Allocation {
+    region: Region,
+    state: State,
+    cells: Vec<Value>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Memory {
+    allocations: Vec<Allocation>,
+}
+
+impl Memory {
+    pub fn new() -> Self {
+        Memory::default()
+    }
+
+    /// Create an allocation holding `cells` and return a pointer to its first cell
+    pub fn allocate(&mut self, region: Region, cells: Vec<Value>) -> Pointer {
+        self.allocations.push(Allocation { region, state: State::Live, cells });
+        Pointer::new(self.allocations.len() - 1)
+    }
+
+    /// Release a heap allocation through a pointer to its start
+    pub fn free(&mut self, ptr: &Pointer) -> Result<(), RuntimeError> {
+        let alloc = self.allocations.get_mut(ptr.alloc).ok_or(RuntimeError::InvalidFree)?;
+        if alloc.region != Region::Heap || ptr.offset != 0 || !ptr.path.is_empty() {
+            return Err(RuntimeError::InvalidFree);
+        }
+        match alloc.state {
+            State::Live => {
+                alloc.state = State::Freed;
+                alloc.cells = Vec::new();
+                Ok(())
+            }
+            State::Freed => Err(RuntimeError::DoubleFree),
+            State::Expired => Err(RuntimeError::InvalidFree),
+        }
+    }
+
+    /// End the lifetime of a stack allocation
+    pub fn expire(&mut self, alloc: usize) {
+        if let Some(a) = self.allocations.get_mut(alloc) {
+            a.state = State::Expired;
+            a.cells = Vec::new();
+        }
+    }
+
+    /// Grow or shrink a live heap allocation in place; new cells are uninitialized
+    pub fn resize(&mut self, ptr: &Pointer, len: usize) -> Result<(), RuntimeError> {
+        let alloc = self.live(ptr.alloc)?;
+        if alloc.region != Region::Heap || ptr.offset != 0 || !ptr.path.is_empty() {
+            return Err(RuntimeError::InvalidFree);
+        }
+        alloc.cells.resize(len, Value::Uninit);
+        Ok(())
+    }
+
+    /// Number of live allocations in `region`
+    pub fn live_count(&self, region: Region) -> usize {
+        self.allocations.iter().filter(|a| a.region == region && a.state == State::Live).count()
+    }
+
+    /// The value at `ptr` without copying it; unlike [`Memory::read`], this
+    /// returns [`Value::Uninit`] rather than trapping on unwritten memory
+    pub fn get(&self, ptr: &Pointer) -> Result<&Value, RuntimeError> {
+        let alloc = match self.allocations.get(ptr.alloc) {
+            Some(a) if a.state == State::Live => a,
+            Some(Allocation { state: State::Freed, .. }) => return Err(RuntimeError::UseAfterFree),
+            Some(_) => return Err(RuntimeError::DanglingPointer),
+            None => return Err(RuntimeError::OutOfBounds),
+        };
+        let mut value = alloc.cells.get(ptr.offset).ok_or(RuntimeError::OutOfBounds)?;
+        for step in &ptr.path {
+            value = descend(value, step)?;
+        }
+        Ok(value)
+    }
+
+    pub fn read(&self, ptr: &Pointer) -> Result<Value, RuntimeError> {
+        match self.get(ptr)? {
+            Value::Uninit => Err(RuntimeError::UninitializedRead),
+            v => Ok(v.clone()),
+        }
+    }
+
+    pub fn write(&mut self, ptr: &Pointer, new: Value) -> Result<(), RuntimeError> {
+        let alloc = self.live(ptr.alloc)?;
+        let mut value = alloc.cells.get_mut(ptr.offset).ok_or(RuntimeError::OutOfBounds)?;
+        for step in &ptr.path {
+            value = descend_mut(value, step)?;
+        }
+        *value = new;
+        Ok(())
+    }
+
+    fn live(&mut self, alloc: usize) -> Result<&mut Allocation, RuntimeError> {
+        match self.allocations.get_mut(alloc) {
+            Some(a) if a.state == State::Live => Ok(a),
+            Some(Allocation { state: State::Freed, .. }) => Err(RuntimeError::UseAfterFree),
+            Some(_) => Err(RuntimeError::DanglingPointer),
+            None => Err(RuntimeError::OutOfBounds),
+        }
+    }
+}
+
+fn descend<'v>(value: &'v Value, step: &Step) -> Result<&'v Value, RuntimeError> {
+    match (value, step) {
+        (Value::Struct { name, fields }, Step::Field(field)) => fields
+            .iter()
+            .find(|(n, _)| n == field)
+            .map(|(_, v)| v)
+            .ok_or_else(|| RuntimeError::NoSuchField { ty: name.clone(), field: field.clone() }),
+        (Value::Union { name, variant, payload }, Step::Field(field)) => {
+            if variant == field {
+                Ok(payload)
+            } else {
+                Err(RuntimeError::InactiveVariant {
+                    union: name.clone(),
+                    variant: field.clone(),
+                    active: variant.clone(),
+                })
+            }
+        }
+        (Value::Array(elements), Step::Index(i)) => elements
+            .get(*i)
+            .ok_or(RuntimeError::IndexOutOfBounds { index: *i as i128, len: elements.len() }),
+        (Value::Uninit, _) => Err(RuntimeError::UninitializedRead),
+        (other, Step::Field(field)) => {
+            Err(RuntimeError::NoSuchField { ty: other.type_name(), field: field.clone() })
+        }
+        (other, Step::Index(_)) => Err(RuntimeError::TypeMismatch(format!("cannot index `{}`", other.type_name()))),
+    }
+}
+
+/// Like [`descend`], but writing a union field switches the active variant
+fn descend_mut<'v>(value: &'v mut Value, step: &Step) -> Result<&'v mut Value, RuntimeError> {
+    match (value, step) {
+        (Value::Struct { name, fields }, Step::Field(field)) => {
+            let name = name.clone();
+            fields
+                .iter_mut()
+                .find(|(n, _)| n == field)
+                .map(|(_, v)| v)
+                .ok_or_else(|| RuntimeError::NoSuchField { ty: name, field: field.clone() })
+        }
+        (Value::Union { variant, payload, .. }, Step::Field(field)) => {
+            if variant != field {
+                *variant = field.clone();
+                **payload = Value::Uninit;
+            }
+            Ok(payload)
+        }
+        (Value::Array(elements), Step::Index(i)) => {
+            let len = elements.len();
+            elements.get_mut(*i).ok_or(RuntimeError::IndexOutOfBounds { index: *i as i128, len })
+        }
+        (other, Step::Field(field)) => {
+            Err(RuntimeError::NoSuchField { ty: other.type_name(), field: field.clone() })
+        }
+        (other, Step::Index(_)) => Err(RuntimeError::TypeMismatch(format!("cannot index `{}`", other.type_name()))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value::IntType;
+
+    #[test]
+    fn test_stale_pointers_are_reported() {
+        let mut memory = Memory::new();
+        let heap = memory.allocate(Region::Heap, vec![Value::Uninit; 4]);
+        assert_eq!(memory.read(&heap), Err(RuntimeError::UninitializedRead));
+        memory.write(&heap.add(3).unwrap(), Value::int(7, IntType::U8)).unwrap();
+        assert_eq!(memory.read(&heap.add(3).unwrap()), Ok(Value::int(7, IntType::U8)));
+        assert_eq!(memory.read(&heap.add(4).unwrap()), Err(RuntimeError::OutOfBounds));
+        assert_eq!(memory.free(&heap.add(1).unwrap()), Err(RuntimeError::InvalidFree));
+        memory.free(&heap).unwrap();
+        assert_eq!(memory.read(&heap), Err(RuntimeError::UseAfterFree));
+        assert_eq!(memory.free(&heap), Err(RuntimeError::DoubleFree));
+
+        let local = memory.allocate(Region::Stack, vec![Value::Bool(true)]);
+        memory.expire(local.alloc);
+        assert_eq!(memory.read(&local), Err(RuntimeError::DanglingPointer));
+    }
+
+    #[test]
+    fn test_paths_into_aggregates() {
+        let mut memory = Memory::new();
+        let point = Value::Struct {
+            name: "Point".into(),
+            fields: vec![("x".into(), Value::Array(vec![Value::Bool(false); 2]))],
+        };
+        let ptr = memory.allocate(Region::Stack, vec![point]);
+        let elem = ptr.step(Step::Field("x".into())).step(Step::Index(1));
+        memory.write(&elem, Value::Bool(true)).unwrap();
+        assert_eq!(memory.read(&elem), Ok(Value::Bool(true)));
+        assert_eq!(elem.add(-1).unwrap().distance(&elem), Some(-1));
+
+        let shape = Value::Union { name: "Shape".into(), variant: "a".into(), payload: Box::new(Value::Ok) };
+        let ptr = memory.allocate(Region::Stack, vec![shape]);
+        assert!(matches!(
+            memory.read(&ptr.step(Step::Field("b".into()))),
+            Err(RuntimeError::InactiveVariant { .. })
+        ));
+        memory.write(&ptr.step(Step::Field("b".into())), Value::Null).unwrap();
+        assert_eq!(memory.read(&ptr.step(Step::Field("b".into()))), Ok(Value::Null));
+    }
+}
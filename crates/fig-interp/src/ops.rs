@@ -0,0 +1,312 @@
+//! Operators and casts on runtime values
+//!
+//! Integer `+`, `-`, `*`, `/`, `%` and negation are checked: a result outside
+//! the operand type traps with [`RuntimeError::Overflow`]. Bitwise operators
+//! and `as` casts between integers wrap. An unsuffixed literal takes the type
+//! of the other operand, and two unsuffixed operands are checked as `i64`.
+
+use fig_parser::ast::{BinaryOperator, Type, UnaryOperator};
+use fig_sema::layout::{Target, integer_bounds, wrap_integer};
+
+use crate::error::RuntimeError;
+use crate::value::{Int, IntType, Value};
+
+/// Bit width, signedness and display name of an integer's effective type
+fn int_info(ty: Option<IntType>, target: &Target) -> (u32, bool, &'static str) {
+    let ty = ty.unwrap_or(IntType::I64);
+    let (bits, signed) = ty.info(target);
+    (bits, signed, ty.name())
+}
+
+/// The common type of two integer operands
+fn unify(a: Int, b: Int) -> Result<Option<IntType>, RuntimeError> {
+    match (a.ty, b.ty) {
+        (Some(x), Some(y)) if x != y => Err(RuntimeError::TypeMismatch(format!(
+            "operands have types `{}` and `{}`",
+            x.name(),
+            y.name()
+        ))),
+        (x, y) => Ok(x.or(y)),
+    }
+}
+
+/// `value` as an integer of type `ty`, trapping if it does not fit
+fn checked(value: i128, ty: Option<IntType>, op: &'static str, target: &Target) -> Result<Value, RuntimeError> {
+    let (bits, signed, name) = int_info(ty, target);
+    let (min, max) = integer_bounds(bits, signed);
+    if value < min || value > max {
+        return Err(RuntimeError::Overflow { op, ty: name.to_string() });
+    }
+    Ok(Value::Int(Int { value, ty }))
+}
+
+fn wrapped(value: i128, ty: Option<IntType>, target: &Target) -> Value {
+    let (bits, signed, _) = int_info(ty, target);
+    Value::Int(Int { value: wrap_integer(value, bits, signed), ty })
+}
+
+/// Round to single precision when the value is an `f32`
+fn float(value: f64, single: bool) -> Value {
+    let value = if single { value as f32 as f64 } else { value };
+    Value::Float { value, single }
+}
+
+/// Check that an integer fits `ty`, as when it is stored in a declared variable
+pub(crate) fn fit(value: i128, ty: IntType, target: &Target) -> Result<Value, RuntimeError> {
+    let (bits, signed) = ty.info(target);
+    let (min, max) = integer_bounds(bits, signed);
+    if value < min || value > max {
+        return Err(RuntimeError::OutOfRange { value: value.to_string(), ty: ty.name().to_string() });
+    }
+    Ok(Value::int(value, ty))
+}
+
+/// Structural equality; integers compare by value whatever their type
+pub(crate) fn equal(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Int(x), Value::Int(y)) => x.value == y.value,
+        (Value::Float { value: x, .. }, Value::Float { value: y, .. }) => x == y,
+        (Value::Array(xs), Value::Array(ys)) => xs.len() == ys.len() && xs.iter().zip(ys).all(|(x, y)| equal(x, y)),
+        (Value::Struct { name: n1, fields: f1 }, Value::Struct { name: n2, fields: f2 }) => {
+            n1 == n2 && f1.len() == f2.len() && f1.iter().zip(f2).all(|((_, x), (_, y))| equal(x, y))
+        }
+        (Value::Enum { name: n1, variant: v1, .. }, Value::Enum { name: n2, variant: v2, .. }) => n1 == n2 && v1 == v2,
+        (
+            Value::Union { name: n1, variant: v1, payload: p1 },
+            Value::Union { name: n2, variant: v2, payload: p2 },
+        ) => n1 == n2 && v1 == v2 && equal(p1, p2),
+        (Value::Error(x), Value::Error(y)) => equal(x, y),
+        _ => a == b,
+    }
+}
+
+fn mismatch(op: BinaryOperator, lhs: &Value, rhs: &Value) -> RuntimeError {
+    RuntimeError::TypeMismatch(format!(
+        "`{:?}` is not defined for `{}` and `{}`",
+        op,
+        lhs.type_name(),
+        rhs.type_name()
+    ))
+}
+
+pub(crate) fn binary(op: BinaryOperator, lhs: Value, rhs: Value, target: &Target) -> Result<Value, RuntimeError> {
+    use BinaryOperator::*;
+    match op {
+        Equal => return Ok(Value::Bool(equal(&lhs, &rhs))),
+        NotEqual => return Ok(Value::Bool(!equal(&lhs, &rhs))),
+        _ => {}
+    }
+    match (&lhs, &rhs) {
+        (Value::Int(a), Value::Int(b)) => int_binary(op, *a, *b, target),
+        (Value::Float { value: a, single: sa }, Value::Float { value: b, single: sb }) => {
+            let single = *sa || *sb;
+            Ok(match op {
+                Add => float(a + b, single),
+                Subtract => float(a - b, single),
+                Multiply => float(a * b, single),
+                Divide => float(a / b, single),
+                Modulo => float(a % b, single),
+                LessThan => Value::Bool(a < b),
+                GreaterThan => Value::Bool(a > b),
+                LessThanOrEqual => Value::Bool(a <= b),
+                GreaterThanOrEqual => Value::Bool(a >= b),
+                _ => return Err(mismatch(op, &lhs, &rhs)),
+            })
+        }
+        (Value::Bool(a), Value::Bool(b)) => Ok(Value::Bool(match op {
+            LogicalAnd => *a && *b,
+            LogicalOr => *a || *b,
+            BitwiseAnd => *a & *b,
+            BitwiseOr => *a | *b,
+            BitwiseXor => *a ^ *b,
+            _ => return Err(mismatch(op, &lhs, &rhs)),
+        })),
+        (Value::Pointer(p), Value::Int(n)) | (Value::Int(n), Value::Pointer(p)) if op == Add => {
+            p.add(n.value).map(Value::Pointer).ok_or(RuntimeError::OutOfBounds)
+        }
+        (Value::Pointer(p), Value::Int(n)) if op == Subtract => {
+            p.add(-n.value).map(Value::Pointer).ok_or(RuntimeError::OutOfBounds)
+        }
+        (Value::Pointer(p), Value::Pointer(q)) => {
+            let distance = p
+                .distance(q)
+                .ok_or_else(|| RuntimeError::TypeMismatch("pointers into different objects".to_string()))?;
+            Ok(match op {
+                Subtract => Value::int(distance, IntType::ISize),
+                LessThan => Value::Bool(distance < 0),
+                GreaterThan => Value::Bool(distance > 0),
+                LessThanOrEqual => Value::Bool(distance <= 0),
+                GreaterThanOrEqual => Value::Bool(distance >= 0),
+                _ => return Err(mismatch(op, &lhs, &rhs)),
+            })
+        }
+        _ => Err(mismatch(op, &lhs, &rhs)),
+    }
+}
+
+fn int_binary(op: BinaryOperator, a: Int, b: Int, target: &Target) -> Result<Value, RuntimeError> {
+    use BinaryOperator::*;
+    // The shift amount never decides the result type
+    if let ShiftLeft | ShiftRight = op {
+        let (bits, _, name) = int_info(a.ty, target);
+        if b.value < 0 || b.value >= bits as i128 {
+            return Err(RuntimeError::ShiftOutOfRange { amount: b.value, ty: name.to_string() });
+        }
+        let shifted = if op == ShiftLeft { a.value.wrapping_shl(b.value as u32) } else { a.value >> b.value };
+        return Ok(wrapped(shifted, a.ty, target));
+    }
+    let ty = unify(a, b)?;
+    let overflow = |op| {
+        let (_, _, name) = int_info(ty, target);
+        RuntimeError::Overflow { op, ty: name.to_string() }
+    };
+    match op {
+        Add => checked(a.value.checked_add(b.value).ok_or_else(|| overflow("addition"))?, ty, "addition", target),
+        Subtract => checked(
+            a.value.checked_sub(b.value).ok_or_else(|| overflow("subtraction"))?,
+            ty,
+            "subtraction",
+            target,
+        ),
+        Multiply => checked(
+            a.value.checked_mul(b.value).ok_or_else(|| overflow("multiplication"))?,
+            ty,
+            "multiplication",
+            target,
+        ),
+        Divide | Modulo => {
+            if b.value == 0 {
+                return Err(RuntimeError::DivisionByZero);
+            }
+            // Truncating division, as in C; `MIN / -1` is caught by the range check
+            let value = if op == Divide { a.value / b.value } else { a.value % b.value };
+            checked(value, ty, "division", target)
+        }
+        BitwiseAnd => Ok(wrapped(a.value & b.value, ty, target)),
+        BitwiseOr => Ok(wrapped(a.value | b.value, ty, target)),
+        BitwiseXor => Ok(wrapped(a.value ^ b.value, ty, target)),
+        LessThan => Ok(Value::Bool(a.value < b.value)),
+        GreaterThan => Ok(Value::Bool(a.value > b.value)),
+        LessThanOrEqual => Ok(Value::Bool(a.value <= b.value)),
+        GreaterThanOrEqual => Ok(Value::Bool(a.value >= b.value)),
+        _ => Err(mismatch(op, &Value::Int(a), &Value::Int(b))),
+    }
+}
+
+/// Prefix operators other than `&` and `*`, which need memory
+pub(crate) fn unary(op: UnaryOperator, operand: Value, target: &Target) -> Result<Value, RuntimeError> {
+    match (op, &operand) {
+        (UnaryOperator::Negate, Value::Int(i)) => checked(-i.value, i.ty, "negation", target),
+        (UnaryOperator::Negate, Value::Float { value, single }) => Ok(float(-value, *single)),
+        (UnaryOperator::Plus, Value::Int(_) | Value::Float { .. }) => Ok(operand),
+        (UnaryOperator::LogicalNot, Value::Bool(b)) => Ok(Value::Bool(!b)),
+        (UnaryOperator::BitwiseNot, Value::Int(i)) => Ok(wrapped(!i.value, i.ty, target)),
+        _ => Err(RuntimeError::TypeMismatch(format!(
+            "`{:?}` is not defined for `{}`",
+            op,
+            operand.type_name()
+        ))),
+    }
+}
+
+/// `value as ty` for primitive and pointer targets. `ty` must already have
+/// its aliases resolved; casts to named types are handled by the caller.
+pub(crate) fn cast(value: Value, ty: &Type, target: &Target) -> Result<Value, RuntimeError> {
+    if let Some(int_ty) = IntType::from_type(ty) {
+        let (bits, signed) = int_ty.info(target);
+        let raw = match &value {
+            Value::Int(i) => i.value,
+            Value::Bool(b) => *b as i128,
+            Value::Enum { discriminant, .. } => *discriminant,
+            // Float to integer saturates, and NaN becomes zero
+            Value::Float { value, .. } => {
+                let (min, max) = integer_bounds(bits, signed);
+                if value.is_nan() {
+                    0
+                } else {
+                    (value.trunc() as i128).clamp(min, max)
+                }
+            }
+            _ => return Err(cast_error(&value, ty)),
+        };
+        return Ok(Value::int(wrap_integer(raw, bits, signed), int_ty));
+    }
+    match (ty, &value) {
+        (Type::F32 | Type::F64, Value::Int(i)) => Ok(float(i.value as f64, *ty == Type::F32)),
+        (Type::F32 | Type::F64, Value::Float { value: f, .. }) => Ok(float(*f, *ty == Type::F32)),
+        (Type::Bool, Value::Bool(_)) => Ok(value),
+        (Type::Pointer { .. }, Value::Pointer(_)) => Ok(value),
+        (Type::Pointer { nullable: true, .. }, Value::Null) => Ok(value),
+        _ => Err(cast_error(&value, ty)),
+    }
+}
+
+fn cast_error(value: &Value, ty: &Type) -> RuntimeError {
+    RuntimeError::TypeMismatch(format!(
+        "cannot cast `{}` to `{}`",
+        value.type_name(),
+        fig_parser::format::format_type(ty)
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const T: Target = Target::X86_64;
+
+    fn typed(value: i128, ty: IntType) -> Value {
+        Value::int(value, ty)
+    }
+
+    fn untyped(value: i128) -> Value {
+        Value::Int(Int { value, ty: None })
+    }
+
+    #[test]
+    fn test_checked_arithmetic_traps_at_type_bounds() {
+        use BinaryOperator::*;
+        assert_eq!(binary(Add, typed(200, IntType::U8), untyped(55), &T), Ok(typed(255, IntType::U8)));
+        assert_eq!(
+            binary(Add, typed(200, IntType::U8), untyped(56), &T),
+            Err(RuntimeError::Overflow { op: "addition", ty: "u8".into() })
+        );
+        assert!(matches!(binary(Subtract, typed(0, IntType::USize), untyped(1), &T), Err(RuntimeError::Overflow { .. })));
+        assert!(matches!(
+            binary(Divide, typed(i32::MIN as i128, IntType::I32), untyped(-1), &T),
+            Err(RuntimeError::Overflow { .. })
+        ));
+        assert_eq!(binary(Modulo, untyped(-7), untyped(2), &T), Ok(untyped(-1)));
+        assert_eq!(binary(Divide, untyped(1), untyped(0), &T), Err(RuntimeError::DivisionByZero));
+        assert!(matches!(
+            binary(Add, typed(1, IntType::U8), typed(1, IntType::U16), &T),
+            Err(RuntimeError::TypeMismatch(_))
+        ));
+    }
+
+    #[test]
+    fn test_bitwise_operators_and_casts_wrap() {
+        use BinaryOperator::*;
+        assert_eq!(binary(ShiftLeft, typed(0x81, IntType::U8), untyped(1), &T), Ok(typed(2, IntType::U8)));
+        assert_eq!(binary(ShiftRight, typed(-8, IntType::I8), untyped(1), &T), Ok(typed(-4, IntType::I8)));
+        assert!(matches!(
+            binary(ShiftLeft, typed(1, IntType::U32), untyped(32), &T),
+            Err(RuntimeError::ShiftOutOfRange { .. })
+        ));
+        assert_eq!(unary(UnaryOperator::BitwiseNot, typed(0, IntType::U16), &T), Ok(typed(0xFFFF, IntType::U16)));
+        assert_eq!(cast(untyped(300), &Type::U8, &T), Ok(typed(44, IntType::U8)));
+        assert_eq!(cast(typed(-1, IntType::I32), &Type::U32, &T), Ok(typed(0xFFFF_FFFF, IntType::U32)));
+        assert_eq!(cast(Value::Float { value: 1e10, single: false }, &Type::I16, &T), Ok(typed(32767, IntType::I16)));
+        assert_eq!(
+            cast(untyped(16_777_217), &Type::F32, &T),
+            Ok(Value::Float { value: 16_777_216.0, single: true })
+        );
+    }
+
+    #[test]
+    fn test_fit_reports_out_of_range_values() {
+        assert_eq!(fit(255, IntType::U8, &T), Ok(typed(255, IntType::U8)));
+        assert!(matches!(fit(-1, IntType::USize, &T), Err(RuntimeError::OutOfRange { .. })));
+        assert!(matches!(fit(1 << 40, IntType::USize, &Target::WASM32), Err(RuntimeError::OutOfRange { .. })));
+    }
+}
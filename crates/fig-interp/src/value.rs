@@ -0,0 +1,255 @@
+//! Runtime values
+//!
+//! Values are untyped except where the type changes behaviour: integers carry
+//! their width so arithmetic can trap or wrap exactly as the suffix or
+//! declaration says, and aggregates carry their type name so methods can be
+//! found on them.
+//!
+//! `?T` has no wrapper of its own: an absent value is [`Value::Null`] and a
+//! present one is just the `T`. The error side of `T ! E` is marked with
+//! [`Value::Error`]; the success side is the plain `T`.
+
+use std::fmt;
+
+use fig_lexer::IntegerSuffix;
+use fig_parser::ast::Type;
+use fig_sema::layout::Target;
+
+/// The integer primitives
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntType {
+    U8,
+    U16,
+    U32,
+    U64,
+    USize,
+    I8,
+    I16,
+    I32,
+    I64,
+    ISize,
+}
+
+impl IntType {
+    pub fn from_type(ty: &Type) -> Option<Self> {
+        Some(match ty {
+            Type::U8 => IntType::U8,
+            Type::U16 => IntType::U16,
+            Type::U32 => IntType::U32,
+            Type::U64 => IntType::U64,
+            Type::USize => IntType::USize,
+            Type::I8 => IntType::I8,
+            Type::I16 => IntType::I16,
+            Type::I32 => IntType::I32,
+            Type::I64 => IntType::I64,
+            Type::ISize => IntType::ISize,
+            _ => return None,
+        })
+    }
+
+    pub fn from_suffix(suffix: &IntegerSuffix) -> Self {
+        match suffix {
+            IntegerSuffix::U8 => IntType::U8,
+            IntegerSuffix::U16 => IntType::U16,
+            IntegerSuffix::U32 => IntType::U32,
+            IntegerSuffix::U64 => IntType::U64,
+            IntegerSuffix::USize => IntType::USize,
+            IntegerSuffix::I8 => IntType::I8,
+            IntegerSuffix::I16 => IntType::I16,
+            IntegerSuffix::I32 => IntType::I32,
+            IntegerSuffix::I64 => IntType::I64,
+            IntegerSuffix::ISize => IntType::ISize,
+        }
+    }
+
+    pub fn to_type(self) -> Type {
+        match self {
+            IntType::U8 => Type::U8,
+            IntType::U16 => Type::U16,
+            IntType::U32 => Type::U32,
+            IntType::U64 => Type::U64,
+            IntType::USize => Type::USize,
+            IntType::I8 => Type::I8,
+            IntType::I16 => Type::I16,
+            IntType::I32 => Type::I32,
+            IntType::I64 => Type::I64,
+            IntType::ISize => Type::ISize,
+        }
+    }
+
+    /// Bit width and signedness on `target`
+    pub fn info(self, target: &Target) -> (u32, bool) {
+        target.integer_info(&self.to_type()).expect("integer primitive")
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            IntType::U8 => "u8",
+            IntType::U16 => "u16",
+            IntType::U32 => "u32",
+            IntType::U64 => "u64",
+            IntType::USize => "usize",
+            IntType::I8 => "i8",
+            IntType::I16 => "i16",
+            IntType::I32 => "i32",
+            IntType::I64 => "i64",
+            IntType::ISize => "isize",
+        }
+    }
+}
+
+/// An integer value. `ty` is `None` for an unsuffixed literal that has not
+/// yet met a typed operand or declaration; such values are checked as `i64`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Int {
+    pub value: i128,
+    pub ty: Option<IntType>,
+}
+
+/// One step from a memory cell into the value stored there
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    /// A struct field, or the payload of a union variant
+    Field(String),
+    /// An element of a fixed-size array
+    Index(usize),
+}
+
+/// An address in the interpreter's [`Memory`](crate::memory::Memory): a
+/// cell of an allocation, then a path into the value held in that cell.
+/// Pointer arithmetic moves between cells, or between array elements when
+/// the path ends in an index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pointer {
+    pub alloc: usize,
+    pub offset: usize,
+    pub path: Vec<Step>,
+}
+
+impl Pointer {
+    pub fn new(alloc: usize) -> Self {
+        Pointer { alloc, offset: 0, path: Vec::new() }
+    }
+
+    /// This pointer extended by one step into the pointee
+    pub fn step(&self, step: Step) -> Self {
+        let mut path = self.path.clone();
+        path.push(step);
+        Pointer { alloc: self.alloc, offset: self.offset, path }
+    }
+
+    /// The pointer `n` elements further on, or `None` if that would precede
+    /// the start of the allocation or array
+    pub fn add(&self, n: i128) -> Option<Self> {
+        let mut moved = self.clone();
+        match moved.path.last_mut() {
+            Some(Step::Index(i)) => *i = usize::try_from(*i as i128 + n).ok()?,
+            _ => moved.offset = usize::try_from(moved.offset as i128 + n).ok()?,
+        }
+        Some(moved)
+    }
+
+    /// The element distance from `other` to `self`, if both point into the same sequence
+    pub fn distance(&self, other: &Pointer) -> Option<i128> {
+        if self.alloc != other.alloc {
+            return None;
+        }
+        match (self.path.split_last(), other.path.split_last()) {
+            (None, None) => Some(self.offset as i128 - other.offset as i128),
+            (Some((Step::Index(a), pa)), Some((Step::Index(b), pb))) if pa == pb && self.offset == other.offset => {
+                Some(*a as i128 - *b as i128)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Int(Int),
+    Float { value: f64, single: bool },
+    Bool(bool),
+    /// The unit value `ok`
+    Ok,
+    /// `null`: an absent `?T` or a null pointer
+    Null,
+    /// The contents of memory that has not been written yet
+    Uninit,
+    Pointer(Pointer),
+    /// `[T]`: a pointer to the first element and a length
+    Slice { ptr: Pointer, len: usize },
+    /// `[T; N]`
+    Array(Vec<Value>),
+    Struct { name: String, fields: Vec<(String, Value)> },
+    Enum { name: String, variant: String, discriminant: i128 },
+    Union { name: String, variant: String, payload: Box<Value> },
+    /// The error side of a `T ! E`
+    Error(Box<Value>),
+}
+
+impl Value {
+    pub fn int(value: i128, ty: IntType) -> Self {
+        Value::Int(Int { value, ty: Some(ty) })
+    }
+
+    pub fn usize(value: usize) -> Self {
+        Value::int(value as i128, IntType::USize)
+    }
+
+    /// Name of the value's type, for methods and messages
+    pub fn type_name(&self) -> String {
+        match self {
+            Value::Int(Int { ty: Some(ty), .. }) => ty.name().to_string(),
+            Value::Int(Int { ty: None, .. }) => "integer".to_string(),
+            Value::Float { single: true, .. } => "f32".to_string(),
+            Value::Float { single: false, .. } => "f64".to_string(),
+            Value::Bool(_) => "bool".to_string(),
+            Value::Ok => "ok".to_string(),
+            Value::Null => "null".to_string(),
+            Value::Uninit => "uninitialized memory".to_string(),
+            Value::Pointer(_) => "pointer".to_string(),
+            Value::Slice { .. } => "slice".to_string(),
+            Value::Array(_) => "array".to_string(),
+            Value::Struct { name, .. } | Value::Enum { name, .. } | Value::Union { name, .. } => name.clone(),
+            Value::Error(inner) => format!("error {}", inner.type_name()),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(i) => write!(f, "{}", i.value),
+            Value::Float { value, .. } => write!(f, "{}", value),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Ok => write!(f, "ok"),
+            Value::Null => write!(f, "null"),
+            Value::Uninit => write!(f, "<uninit>"),
+            Value::Pointer(p) => write!(f, "<ptr {}+{}>", p.alloc, p.offset),
+            Value::Slice { ptr, len } => write!(f, "<slice {}+{}; {}>", ptr.alloc, ptr.offset, len),
+            Value::Array(elements) => {
+                write!(f, "[")?;
+                for (i, e) in elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", e)?;
+                }
+                write!(f, "]")
+            }
+            Value::Struct { name, fields } => {
+                write!(f, "{}(", name)?;
+                for (i, (field, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", field, value)?;
+                }
+                write!(f, ")")
+            }
+            Value::Enum { name, variant, .. } => write!(f, "{}::{}", name, variant),
+            Value::Union { name, variant, payload } => write!(f, "{}::{}({})", name, variant, payload),
+            Value::Error(inner) => write!(f, "error({})", inner),
+        }
+    }
+}
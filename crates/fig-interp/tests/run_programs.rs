@@ -0,0 +1,44 @@
+// Runs every program in tests/run/ and checks it against the expectations
+// written in its header comments:
+//
+//   // expect: <value>     the value `main` returns, as printed by `Display`
+//   // output: <line>      one line of `print`/`println` output, in order
+//   // trap: <message>     the runtime error the program stops with
+
+use std::path::Path;
+
+use fig_interp::Interpreter;
+use fig_parser::{Lexer, SourceFileParser};
+use fig_sema::construct::resolve_construction;
+use fig_sema::items::ItemTable;
+use fig_sema::layout::Target;
+use fig_test_support::{check_programs, header};
+
+fn run(path: &Path) -> Result<(), String> {
+    let src = std::fs::read_to_string(path).unwrap();
+    let sf = SourceFileParser::new()
+        .parse(Lexer::new(&src))
+        .map_err(|e| format!("parse error: {:?}", e))?;
+    let sf = resolve_construction(sf);
+    let items = ItemTable::from_source_file(&sf);
+    let mut interp = Interpreter::new(&items).with_target(Target::X86_64).with_fuel(10_000_000);
+    let result = interp.call("main", vec![]);
+
+    match (result, header(&src, "expect").first(), header(&src, "trap").first()) {
+        (Ok(value), Some(expected), None) if value.to_string() == *expected => {}
+        (Err(trap), None, Some(expected)) if trap.error.to_string() == *expected => {}
+        (Ok(value), _, _) => return Err(format!("returned {}", value)),
+        (Err(trap), _, _) => return Err(trap.to_string()),
+    }
+    let expected_output = header(&src, "output");
+    let output: Vec<&str> = interp.output().lines().collect();
+    if output != expected_output {
+        return Err(format!("printed {:?}, expected {:?}", output, expected_output));
+    }
+    Ok(())
+}
+
+#[test]
+fn run_programs() {
+    check_programs(run);
+}
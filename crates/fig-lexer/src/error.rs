@@ -3,7 +3,7 @@ use std::num::ParseIntError;
 use std::ops::Range;
 
 /// Detailed lexical error type with position information
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Default)]
 pub enum LexicalError {
     /// Invalid integer literal (e.g., overflow, invalid digits)
     InvalidInteger {
@@ -48,6 +48,7 @@ pub enum LexicalError {
     },
     
     /// Default error variant (for invalid tokens that don't match any pattern)
+    #[default]
     InvalidToken,
 }
 
@@ -118,12 +119,6 @@ impl LexicalError {
     }
 }
 
-impl Default for LexicalError {
-    fn default() -> Self {
-        LexicalError::InvalidToken
-    }
-}
-
 impl fmt::Display for LexicalError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
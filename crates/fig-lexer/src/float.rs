@@ -1,9 +1,10 @@
 use derive_builder::Builder;
-use serde::Serialize;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 
 use crate::{Token, LexicalError};
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub enum FloatSuffix {
     F32,
     F64,
@@ -29,14 +30,14 @@ impl std::fmt::Display for FloatSuffix {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub enum FloatExponent {
     Positive(u32),
     Negative(u32),
     Unsigned(u32),
 }
 
-#[derive(Debug, Clone, PartialEq, Builder, Serialize)]
+#[derive(Debug, Clone, PartialEq, Builder, Serialize, Deserialize, JsonSchema)]
 pub struct FloatLiteral {
     digits: String,
 
@@ -51,6 +52,30 @@ impl FloatLiteral {
     pub fn builder() -> FloatLiteralBuilder {
         FloatLiteralBuilder::default()
     }
+
+    /// Get the digits before the exponent
+    pub fn digits(&self) -> &str {
+        &self.digits
+    }
+
+    /// Get the exponent if present
+    pub fn exponent(&self) -> Option<&FloatExponent> {
+        self.exponent.as_ref()
+    }
+
+    /// Get the suffix if present
+    pub fn suffix(&self) -> Option<&FloatSuffix> {
+        self.suffix.as_ref()
+    }
+
+    /// Parse the float literal as an f64
+    pub fn as_f64(&self) -> Result<f64, std::num::ParseFloatError> {
+        let mut text = self.digits.replace('_', "");
+        if let Some(exp) = &self.exponent {
+            text.push_str(&exp.to_string());
+        }
+        text.parse()
+    }
 }
 
 impl std::fmt::Display for FloatExponent {
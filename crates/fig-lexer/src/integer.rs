@@ -1,10 +1,11 @@
 use derive_builder::Builder;
 use std::fmt::Display;
-use serde::Serialize;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 
 use crate::{Token, LexicalError};
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize)]
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub enum Base {
     Binary,
     Octal,
@@ -13,7 +14,7 @@ pub enum Base {
     Hex,
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize)]
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub enum IntegerSuffix {
     U8,
     U16,
@@ -64,7 +65,7 @@ impl Display for IntegerSuffix {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Builder, Serialize)]
+#[derive(Debug, Clone, PartialEq, Builder, Serialize, Deserialize, JsonSchema)]
 pub struct IntegerLiteral {
     #[builder(default)]
     base: Base,
@@ -243,6 +243,10 @@ pub enum Token {
     Comma,
     #[token(".")]
     Dot,
+    #[token("..")]
+    DotDot,
+    #[token("..=")]
+    DotDotEq,
 
     // Special wildcard identifier
     #[token("_")]
@@ -520,9 +524,7 @@ impl<'source> Iterator for IndentLexer<'source> {
                         
                         if !indent_tokens.is_empty() {
                             // Add indent/dedent tokens to pending (all except first)
-                            for i in 1..indent_tokens.len() {
-                                self.pending_tokens.push(indent_tokens[i].clone());
-                            }
+                            self.pending_tokens.extend(indent_tokens[1..].iter().cloned());
                             // Store the real token at the end
                             self.pending_tokens.push(token);
                             // Return first indent/dedent token
@@ -1057,7 +1059,7 @@ mod tests {
     fn test_unrecognized_character() {
         let mut lex = Token::lexer("let $invalid;"); // $ is not tokenized
         assert_eq!(lex.next().unwrap().unwrap(), Token::Let);
-        assert_eq!(lex.next().unwrap().is_err(), true); // Should be an error for $
+        assert!(lex.next().unwrap().is_err()); // Should be an error for $
         // The error token might be followed by valid tokens, depending on Logos' recovery
         // However, we expect the next valid token to be 'invalid' if Logos recovers.
         // For this test, we just check if it produces an error.
@@ -1100,6 +1102,20 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_ranges_between_integers() {
+        // `0..10` is not the float `0.` followed by `.10`
+        let int = |digits: &str| {
+            Token::IntegerLiteral(
+                IntegerLiteral::builder().base(Base::Decimal).digits(digits.to_string()).suffix(None).build().unwrap(),
+            )
+        };
+        lexer_test_helper(
+            "0..10 1..=n",
+            vec![int("0"), Token::DotDot, int("10"), int("1"), Token::DotDotEq, Token::Ident("n".to_string())],
+        );
+    }
+
     #[test]
     fn test_float_vs_integer_no_suffix() {
         lexer_test_helper(
@@ -7,11 +7,11 @@ use fig_lexer::{IndentLexer, LexicalError};
 
 fn lexer_test(path: &Utf8Path, contents: String) -> datatest_stable::Result<()> {
     // Tokenize the entire file
-    let mut lexer = IndentLexer::new(&contents);
+    let lexer = IndentLexer::new(&contents);
     let mut tokens = Vec::new();
     let mut errors = Vec::new();
 
-    while let Some(result) = lexer.next() {
+    for result in lexer {
         match result {
             Ok(token) => tokens.push(token),
             Err(e) => errors.push(e),
@@ -42,8 +42,7 @@ fn lexer_test(path: &Utf8Path, contents: String) -> datatest_stable::Result<()>
         .as_str()
         .trim_start_matches("../../tests/valid/")
         .trim_end_matches(".fig")
-        .replace('/', "__")
-        .replace('\\', "__");
+        .replace(['/', '\\'], "__");
 
     // Assert snapshot using insta with YAML format
     insta::assert_yaml_snapshot!(snapshot_name, tokens);
@@ -0,0 +1,646 @@
+//! Answers to editor queries about one document
+//!
+//! An [`Analysis`] is built from the text of a document each time it
+//! changes: the text is lexed, parsed, indexed and checked once, and the
+//! queries then work from the tokens and the [`Definition`]s. Positions are
+//! byte offsets; the server converts them to and from LSP positions.
+
+use fig_lexer::Token;
+use fig_parser::ast::{Path, SourceFile, Span, Type};
+use fig_parser::format::format_signature;
+use fig_parser::{ExpressionParser, Lexer, SourceFileParser};
+use fig_sema::diagnostics::{Diagnostic, Severity};
+use fig_sema::index::{DefKind, Definition, Tok, index, matching_open, tokenize};
+use fig_sema::items::{ItemTable, TypeDef};
+use fig_sema::layout::{LayoutEngine, Shape, Target};
+use fig_sema::resolve::{BodyScope, Callee, strip_pointers};
+use lsp_types::Position;
+
+use crate::position::LineIndex;
+
+/// Stands in for the name being typed when completing after a `.` or `::`
+/// that leaves the document unparsable
+const PLACEHOLDER: &str = "__complete";
+
+/// A diagnostic located in the document
+#[derive(Debug, Clone)]
+pub struct Problem {
+    pub span: Span,
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// A declaration and the declarations inside it, for the document outline
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: DefKind,
+    pub detail: String,
+    pub span: Span,
+    pub extent: Span,
+    pub children: Vec<Symbol>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Completion {
+    pub label: String,
+    pub kind: DefKind,
+    pub detail: String,
+}
+
+pub struct Analysis {
+    text: String,
+    lines: LineIndex,
+    tokens: Vec<Tok>,
+    file: Option<SourceFile>,
+    definitions: Vec<Definition>,
+    problems: Vec<Problem>,
+}
+
+impl Analysis {
+    pub fn new(text: impl Into<String>) -> Self {
+        let text = text.into();
+        let lines = LineIndex::new(&text);
+        let (tokens, invalid) = tokenize(&text);
+        let mut analysis =
+            Analysis { text, lines, tokens, file: None, definitions: Vec::new(), problems: Vec::new() };
+        match SourceFileParser::new().parse(Lexer::new(&analysis.text)) {
+            Ok(file) => {
+                analysis.definitions = index(&file, &analysis.text, &analysis.tokens);
+                analysis.problems = analysis.check(&file);
+                analysis.file = Some(file);
+            }
+            Err(error) => analysis.problems.push(analysis.syntax_error(error, invalid)),
+        }
+        analysis
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn position(&self, offset: usize) -> Position {
+        self.lines.position(&self.text, offset)
+    }
+
+    pub fn offset(&self, position: Position) -> usize {
+        self.lines.offset(&self.text, position)
+    }
+
+    pub fn problems(&self) -> &[Problem] {
+        &self.problems
+    }
+
+    fn syntax_error(
+        &self,
+        error: lalrpop_util::ParseError<usize, Token, fig_parser::LexicalError>,
+        invalid: Option<Span>,
+    ) -> Problem {
+        use lalrpop_util::ParseError::*;
+        let (span, message) = match error {
+            InvalidToken { location } => (Span { start: location, end: location }, "invalid token".to_string()),
+            UnrecognizedEof { location, .. } => {
+                (Span { start: location, end: location }, "unexpected end of file".to_string())
+            }
+            UnrecognizedToken { token: (start, _, end), .. } | ExtraToken { token: (start, _, end) } => {
+                let found = self.text.get(start..end).unwrap_or_default().trim();
+                let message = match found {
+                    "" => "unexpected line break".to_string(),
+                    found => format!("unexpected `{}`", found),
+                };
+                (Span { start, end }, message)
+            }
+            User { .. } => (invalid.unwrap_or_default(), "invalid token".to_string()),
+        };
+        Problem { span, severity: Severity::Error, message }
+    }
+
+    /// The semantic passes' diagnostics, then the type checker's when there
+    /// are no errors for it to trip over
+    fn check(&self, file: &SourceFile) -> Vec<Problem> {
+        let items = ItemTable::from_source_file(file);
+        let mut diagnostics = fig_sema::check(&items);
+        if !diagnostics.iter().any(Diagnostic::is_error)
+            && let Err(errors) = fig_sema::mono::monomorphize(&items, Target::host())
+        {
+            diagnostics.extend(errors);
+        }
+        diagnostics
+            .into_iter()
+            .map(|diagnostic| {
+                let mut message = diagnostic.message.clone();
+                for note in &diagnostic.notes {
+                    message.push('\n');
+                    message.push_str(note);
+                }
+                Problem { span: self.locate(&diagnostic), severity: diagnostic.severity, message }
+            })
+            .collect()
+    }
+
+    /// Where a diagnostic points: at its snippet in the statement it spans,
+    /// or that statement, or its snippet in the function it names, or that
+    /// function's name, or the first name in backticks that the document
+    /// declares, or else the start of the document
+    fn locate(&self, diagnostic: &Diagnostic) -> Span {
+        if let Some(&span) = diagnostic.span.as_deref()
+            && let Some(statement) = self.text.get(span.start..span.end)
+        {
+            if let Some(snippet) = &diagnostic.snippet
+                && let Some(at) = statement.find(snippet.as_str())
+            {
+                return Span { start: span.start + at, end: span.start + at + snippet.len() };
+            }
+            // Statement spans run on to the line break
+            return Span { start: span.start, end: span.start + statement.trim_end().len() };
+        }
+        if let Some(function) = &diagnostic.function {
+            // Instances of generic functions are named with their type arguments
+            let name = function.split('[').next().unwrap_or_default();
+            let found = self.definitions.iter().find(|d| d.kind == DefKind::Function && d.qualified == name);
+            if let Some(def) = found {
+                let extent = &self.text[def.extent.start..def.extent.end];
+                if let Some(snippet) = &diagnostic.snippet
+                    && let Some(at) = extent.find(snippet.as_str())
+                {
+                    return Span { start: def.extent.start + at, end: def.extent.start + at + snippet.len() };
+                }
+                return def.span;
+            }
+        }
+        for name in diagnostic.message.split('`').skip(1).step_by(2) {
+            let name = name.split('[').next().unwrap_or_default();
+            let found = self
+                .definitions
+                .iter()
+                .rev()
+                .find(|d| d.kind != DefKind::Local && (d.qualified == name || d.name == name));
+            if let Some(def) = found {
+                return def.span;
+            }
+        }
+        Span::default()
+    }
+
+    /// Where the name at `offset` is declared
+    pub fn definition(&self, offset: usize) -> Option<Span> {
+        let items = ItemTable::from_source_file(self.file.as_ref()?);
+        let def = self.resolve(&items, self.ident_at(offset)?)?;
+        Some(self.definitions[def].span)
+    }
+
+    /// Every use of the name at `offset`, and its declaration if asked for
+    pub fn references(&self, offset: usize, include_declaration: bool) -> Vec<Span> {
+        let Some(file) = &self.file else { return Vec::new() };
+        let items = ItemTable::from_source_file(file);
+        let Some(def) = self.ident_at(offset).and_then(|index| self.resolve(&items, index)) else { return Vec::new() };
+        let target = &self.definitions[def];
+        (0..self.tokens.len())
+            .filter(|&i| matches!(&self.tokens[i].token, Token::Ident(name) if *name == target.name))
+            .filter(|&i| include_declaration || self.tokens[i].span != target.span)
+            .filter(|&i| self.resolve(&items, i) == Some(def))
+            .map(|i| self.tokens[i].span)
+            .collect()
+    }
+
+    /// The name at `offset` and a Markdown description of what it names
+    pub fn hover(&self, offset: usize) -> Option<(Span, String)> {
+        let items = ItemTable::from_source_file(self.file.as_ref()?);
+        let index = self.ident_at(offset)?;
+        let def = &self.definitions[self.resolve(&items, index)?];
+        let mut layout = LayoutEngine::new(&items, Target::host());
+        let mut text = format!("```fig\n{}\n```", def.detail);
+        match def.kind {
+            DefKind::Struct | DefKind::Union => {
+                let ty = Type::Path(path_of(&def.qualified));
+                if let Ok(found) = layout.layout_of(&ty) {
+                    if let Shape::Struct { fields, .. } = &found.shape {
+                        let mut lines: Vec<String> = def.detail.lines().map(str::to_string).collect();
+                        for field in fields {
+                            let prefix = format!("    {}:", field.name);
+                            if let Some(line) = lines.iter_mut().find(|l| l.starts_with(&prefix)) {
+                                line.push_str(&format!("  // offset {}", field.offset));
+                            }
+                        }
+                        text = format!("```fig\n{}\n```", lines.join("\n"));
+                    }
+                    text.push_str(&format!("\n\nsize {}, align {}", found.size, found.align));
+                }
+            }
+            DefKind::Field => {
+                let parent = &self.definitions[def.parent?];
+                let ty = Type::Path(path_of(&parent.qualified));
+                if let Ok(offset) = layout.offset_of(&ty, &def.name) {
+                    text.push_str(&format!("\n\nfield of `{}` at offset {}", parent.name, offset));
+                }
+            }
+            DefKind::Variant => {
+                let parent = &self.definitions[def.parent?];
+                if let Some(TypeDef::Enum(e)) = items.lookup_type(&path_of(&parent.qualified))
+                    && let Ok(discriminants) = layout.enum_discriminants(e)
+                    && let Some((_, value)) = discriminants.iter().find(|(name, _)| *name == def.name)
+                {
+                    text = format!("```fig\n{}::{} = {}\n```", parent.name, def.name, value);
+                }
+            }
+            _ => {}
+        }
+        if !def.docs.is_empty() {
+            text.push_str(&format!("\n\n{}", def.docs));
+        }
+        Some((self.tokens[index].span, text))
+    }
+
+    /// The declarations in the document, nested as they are in the source
+    pub fn symbols(&self) -> Vec<Symbol> {
+        self.children(None)
+    }
+
+    fn children(&self, parent: Option<usize>) -> Vec<Symbol> {
+        self.definitions
+            .iter()
+            .enumerate()
+            .filter(|(_, d)| d.parent == parent && d.kind != DefKind::Local)
+            .map(|(i, d)| Symbol {
+                name: d.name.clone(),
+                kind: d.kind,
+                detail: d.detail.lines().next().unwrap_or_default().to_string(),
+                span: d.span,
+                extent: d.extent,
+                children: self.children(Some(i)),
+            })
+            .collect()
+    }
+
+    /// What can follow the `.` or `::` before `offset`, with the name being typed, if any
+    pub fn completions(&self, offset: usize) -> Vec<Completion> {
+        let offset = offset.min(self.text.len());
+        let bytes = self.text.as_bytes();
+        let mut start = offset;
+        while start > 0 && (bytes[start - 1].is_ascii_alphanumeric() || bytes[start - 1] == b'_') {
+            start -= 1;
+        }
+        let before = &self.text[..start];
+        if !before.ends_with('.') && !before.ends_with("::") {
+            return Vec::new();
+        }
+        let Some(file) = &self.file else {
+            // `p.` alone does not parse, but `p.name` does
+            if start != offset {
+                return Vec::new();
+            }
+            let mut text = self.text.clone();
+            text.insert_str(offset, PLACEHOLDER);
+            let completed = Analysis::new(text);
+            return if completed.file.is_some() { completed.completions(offset) } else { Vec::new() };
+        };
+        let items = ItemTable::from_source_file(file);
+        let Some(separator) = self.tokens.iter().position(|t| t.span.end == start) else { return Vec::new() };
+        let mut completions = match self.tokens[separator].token {
+            Token::Dot => self.member_completions(&items, separator),
+            Token::ColonColon => {
+                let segments = self.path_ending(separator);
+                self.path_completions(&items, &segments, start)
+            }
+            _ => Vec::new(),
+        };
+        completions.retain(|c| c.label != PLACEHOLDER);
+        completions.dedup_by(|a, b| a.label == b.label);
+        completions
+    }
+
+    fn member_completions(&self, items: &ItemTable, dot: usize) -> Vec<Completion> {
+        let Some(ty) = self.receiver_type(items, dot) else { return Vec::new() };
+        let Type::Path(path) = strip_pointers(&ty) else { return Vec::new() };
+        let Some((key, def)) = items.lookup_type_entry(path) else { return Vec::new() };
+        let mut completions = self.members(key, &[DefKind::Field, DefKind::Variant, DefKind::Method]);
+        for method in items.methods_of(def.name()) {
+            if method.signature.self_param.is_some() {
+                let detail = format_signature(method.signature);
+                completions.push(Completion { label: method.signature.name.clone(), kind: DefKind::Function, detail });
+            }
+        }
+        completions
+    }
+
+    fn path_completions(&self, items: &ItemTable, segments: &[String], offset: usize) -> Vec<Completion> {
+        let Some(target) = self.resolve_path(segments, offset) else { return Vec::new() };
+        let target = &self.definitions[target];
+        match target.kind {
+            DefKind::Namespace => self.members(
+                &target.qualified,
+                &[
+                    DefKind::Namespace,
+                    DefKind::Function,
+                    DefKind::Struct,
+                    DefKind::Union,
+                    DefKind::Enum,
+                    DefKind::Interface,
+                    DefKind::Alias,
+                    DefKind::Const,
+                ],
+            ),
+            DefKind::Struct | DefKind::Union | DefKind::Enum | DefKind::Alias | DefKind::Interface => {
+                let kinds: &[DefKind] = if target.kind == DefKind::Enum { &[DefKind::Variant] } else { &[] };
+                let mut completions = self.members(&target.qualified, kinds);
+                // Methods taking `self` are called with `.`
+                let associated = |c: &Completion| {
+                    c.kind != DefKind::Function
+                        || items
+                            .methods_of(&target.name)
+                            .any(|m| m.signature.name == c.label && m.signature.self_param.is_none())
+                };
+                let others = self.members(&target.qualified, &[DefKind::Function, DefKind::Const]);
+                completions.extend(others.into_iter().filter(associated));
+                completions
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// The declarations of the given kinds directly inside the one named `qualified`
+    fn members(&self, qualified: &str, kinds: &[DefKind]) -> Vec<Completion> {
+        let prefix = format!("{}::", qualified);
+        self.definitions
+            .iter()
+            .filter(|d| kinds.contains(&d.kind))
+            .filter(|d| d.qualified.strip_prefix(&prefix).is_some_and(|rest| !rest.contains("::")))
+            .map(|d| Completion { label: d.name.clone(), kind: d.kind, detail: d.detail.clone() })
+            .collect()
+    }
+
+    /// The identifier token at or just before `offset`
+    fn ident_at(&self, offset: usize) -> Option<usize> {
+        let first = self.tokens.partition_point(|t| t.span.end < offset);
+        (first..self.tokens.len().min(first + 2)).find(|&i| {
+            let tok = &self.tokens[i];
+            tok.span.start <= offset && offset <= tok.span.end && matches!(tok.token, Token::Ident(_))
+        })
+    }
+
+    /// The definition that the identifier token at `index` declares or names
+    fn resolve(&self, items: &ItemTable, index: usize) -> Option<usize> {
+        let tok = &self.tokens[index];
+        if let Some(def) = self.definitions.iter().position(|d| d.span == tok.span) {
+            return Some(def);
+        }
+        let Token::Ident(name) = &tok.token else { return None };
+        if index > 0 && self.tokens[index - 1].token == Token::Dot {
+            return self.resolve_member(items, index - 1, name);
+        }
+        let segments = self.path_ending(index + 1);
+        if segments.len() == 1
+            && let Some(local) = self.local(name, tok.span.start)
+        {
+            return Some(local);
+        }
+        self.resolve_path(&segments, tok.span.start)
+    }
+
+    /// The field, variant or method that `.name` after the token `dot` names
+    fn resolve_member(&self, items: &ItemTable, dot: usize, name: &str) -> Option<usize> {
+        let ty = self.receiver_type(items, dot)?;
+        let Type::Path(path) = strip_pointers(&ty) else { return None };
+        let (key, _) = items.lookup_type_entry(path)?;
+        if let Some(def) = self.item_named(&format!("{}::{}", key, name)) {
+            return Some(def);
+        }
+        let scope = self.scope_at(items, self.tokens[dot].span.start)?;
+        match scope.resolve_method(Some(ty), name).first()? {
+            Callee::Function(f) => self.item_named(&f.qualified_name()),
+            Callee::InterfaceMethod { interface, signature } => self.definitions.iter().position(|d| {
+                d.kind == DefKind::Method
+                    && d.name == signature.name
+                    && d.parent.is_some_and(|p| self.definitions[p].name == interface.name)
+            }),
+        }
+    }
+
+    /// The type of the expression ending just before the token `dot`
+    fn receiver_type(&self, items: &ItemTable, dot: usize) -> Option<Type> {
+        let start = self.expression_start(dot)?;
+        let source = &self.text[self.tokens[start].span.start..self.tokens[dot].span.start];
+        let expression = ExpressionParser::new().parse(Lexer::new(source)).ok()?;
+        self.scope_at(items, self.tokens[dot].span.start)?.type_of(&expression)
+    }
+
+    /// The first token of a chain of names, `.` and `::` accesses, calls and
+    /// indexing ending just before the token `end`
+    fn expression_start(&self, end: usize) -> Option<usize> {
+        let mut start = end;
+        while start > 0 {
+            match self.tokens[start - 1].token {
+                Token::Ident(_) | Token::SelfLower => {
+                    start -= 1;
+                    if start > 0 && matches!(self.tokens[start - 1].token, Token::Dot | Token::ColonColon) {
+                        start -= 1;
+                    } else {
+                        break;
+                    }
+                }
+                Token::RParen | Token::RBracket => start = matching_open(&self.tokens, start - 1)?,
+                _ => break,
+            }
+        }
+        (start < end).then_some(start)
+    }
+
+    /// The path segments before the token `end`, e.g. `["geo", "Point"]` for
+    /// `geo::Point[T]`; generic arguments are skipped
+    fn path_ending(&self, end: usize) -> Vec<String> {
+        let mut segments = Vec::new();
+        let mut last = end;
+        while last > 0 {
+            let mut at = last - 1;
+            if self.tokens[at].token == Token::RBracket {
+                match matching_open(&self.tokens, at) {
+                    Some(open) if open > 0 => at = open - 1,
+                    _ => break,
+                }
+            }
+            let Token::Ident(name) = &self.tokens[at].token else { break };
+            segments.insert(0, name.clone());
+            if at < 2 || self.tokens[at - 1].token != Token::ColonColon {
+                break;
+            }
+            last = at - 1;
+        }
+        segments
+    }
+
+    /// The local `name` visible at `offset`
+    fn local(&self, name: &str, offset: usize) -> Option<usize> {
+        self.definitions
+            .iter()
+            .enumerate()
+            .filter(|(_, d)| d.kind == DefKind::Local && d.name == name)
+            .filter(|(_, d)| d.scope.is_some_and(|s| s.start <= offset && offset <= s.end))
+            .max_by_key(|(_, d)| d.span.start)
+            .map(|(i, _)| i)
+    }
+
+    /// The item a path names at `offset`: relative to the enclosing
+    /// namespaces, innermost first, or else the one item whose qualified
+    /// name ends with it
+    fn resolve_path(&self, segments: &[String], offset: usize) -> Option<usize> {
+        if segments.is_empty() {
+            return None;
+        }
+        let path = segments.join("::");
+        let mut namespace: Vec<&str> = self
+            .definitions
+            .iter()
+            .filter(|d| d.kind == DefKind::Namespace && d.scope.is_some_and(|s| s.start <= offset && offset <= s.end))
+            .max_by_key(|d| d.scope.map(|s| s.start))
+            .map(|d| d.qualified.split("::").collect())
+            .unwrap_or_default();
+        loop {
+            let mut qualified = namespace.join("::");
+            if !qualified.is_empty() {
+                qualified.push_str("::");
+            }
+            qualified.push_str(&path);
+            if let Some(def) = self.item_named(&qualified) {
+                return Some(def);
+            }
+            if namespace.pop().is_none() {
+                break;
+            }
+        }
+        let suffix = format!("::{}", path);
+        let mut found = self.definitions.iter().enumerate().filter(|(_, d)| d.qualified.ends_with(&suffix));
+        match (found.next(), found.next()) {
+            (Some((def, _)), None) => Some(def),
+            _ => None,
+        }
+    }
+
+    fn item_named(&self, qualified: &str) -> Option<usize> {
+        self.definitions.iter().position(|d| d.kind != DefKind::Local && d.qualified == qualified)
+    }
+
+    /// The scope of the function body around `offset`, with the locals
+    /// declared before it bound
+    fn scope_at<'t, 'a>(&self, items: &'t ItemTable<'a>, offset: usize) -> Option<BodyScope<'t, 'a>> {
+        let (index, def) = self
+            .definitions
+            .iter()
+            .enumerate()
+            .filter(|(_, d)| d.kind == DefKind::Function && d.extent.start <= offset && offset <= d.extent.end)
+            .max_by_key(|(_, d)| d.extent.start)?;
+        let mut scope = BodyScope::new(items, items.functions().get(def.function?)?);
+        for local in &self.definitions {
+            if local.parent == Some(index)
+                && local.span.start < offset
+                && local.scope.is_some_and(|s| s.start <= offset && offset <= s.end)
+                && let Some(binding) = &local.binding
+            {
+                scope.bind(&local.name, binding.clone());
+            }
+        }
+        Some(scope)
+    }
+}
+
+fn path_of(qualified: &str) -> Path {
+    Path { segments: qualified.split("::").map(str::to_string).collect(), generic_args: Vec::new() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SRC: &str = "\
+namespace geo
+
+struct Point
+    x: i32
+    y: i32
+
+/// Compass directions
+#flags
+enum Dir
+    N
+    /// South
+    S
+
+func Point::flip(*self) -> Dir
+    return Dir::S
+
+func len(p: Point) -> i32
+    let d = p.x + p.y
+    return d
+";
+
+    fn at(text: &str, needle: &str, nth: usize) -> usize {
+        text.match_indices(needle).nth(nth).unwrap().0
+    }
+
+    #[test]
+    fn test_definition_and_references() {
+        let analysis = Analysis::new(SRC);
+        assert!(analysis.problems().is_empty(), "{:?}", analysis.problems());
+        // `p.x` goes to the field
+        let x = at(SRC, "p.x", 0) + 2;
+        assert_eq!(analysis.definition(x), Some(Span { start: at(SRC, "x:", 0), end: at(SRC, "x:", 0) + 1 }));
+        // `d` in `return d` goes to the `let`
+        let d = at(SRC, "return d", 0) + 7;
+        assert_eq!(analysis.definition(d).map(|s| s.start), Some(at(SRC, "d =", 0)));
+        // `Dir` is declared once and used twice
+        assert_eq!(analysis.references(at(SRC, "Dir", 0), true).len(), 3);
+        assert_eq!(analysis.references(at(SRC, "Dir", 0), false).len(), 2);
+    }
+
+    #[test]
+    fn test_symbols() {
+        let analysis = Analysis::new(SRC);
+        let symbols = analysis.symbols();
+        let names: Vec<&str> = symbols.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["geo", "Point", "Dir", "flip", "len"]);
+        assert_eq!(symbols[1].children.len(), 2);
+        assert_eq!(symbols[2].children[1].name, "S");
+    }
+
+    #[test]
+    fn test_completion_after_dot_and_colons() {
+        let text = SRC.replace("let d = p.x + p.y", "let d = p.");
+        let analysis = Analysis::new(text.as_str());
+        assert!(!analysis.problems().is_empty());
+        let labels: Vec<String> =
+            analysis.completions(at(&text, "p.", 0) + 2).into_iter().map(|c| c.label).collect();
+        assert_eq!(labels, ["x", "y", "flip"]);
+
+        let labels: Vec<String> =
+            Analysis::new(SRC).completions(at(SRC, "Dir::", 0) + 5).into_iter().map(|c| c.label).collect();
+        assert_eq!(labels, ["N", "S"]);
+    }
+
+    #[test]
+    fn test_hover_shows_doc_comments() {
+        let analysis = Analysis::new(SRC);
+        let (_, text) = analysis.hover(at(SRC, "Dir", 0)).unwrap();
+        assert!(text.ends_with("\n\nCompass directions"), "{}", text);
+        let (_, text) = analysis.hover(at(SRC, "Dir::S", 0) + 5).unwrap();
+        assert!(text.ends_with("\n\nSouth"), "{}", text);
+        let (_, text) = analysis.hover(at(SRC, "Point", 0)).unwrap();
+        assert!(!text.contains("Compass"), "{}", text);
+    }
+
+    #[test]
+    fn test_diagnostics_are_located() {
+        let text = "func set(p: *mut i32) -> ok\n    *p = 1\n";
+        let analysis = Analysis::new(text);
+        let problem = &analysis.problems()[0];
+        assert!(problem.message.contains("writes through a pointer"), "{}", problem.message);
+        assert_eq!(&text[problem.span.start..problem.span.end], "*p = 1");
+
+        // Written differently from the snippet, so only its statement span finds it
+        let text = "func set(p: *mut i32) -> ok\n    *p=1\n";
+        let analysis = Analysis::new(text);
+        let problem = &analysis.problems()[0];
+        assert_eq!(&text[problem.span.start..problem.span.end], "*p=1");
+
+        let analysis = Analysis::new("func main() -> i32\n    return (1\n");
+        assert_eq!(analysis.problems()[0].message, "unexpected line break");
+    }
+}
@@ -0,0 +1,23 @@
+//! A language server for Fig
+//!
+//! The server keeps an [`analysis::Analysis`] of each open document, built
+//! from the lexer, parser and the `fig-sema` item table and resolver. It
+//! offers:
+//!
+//! - diagnostics from the parser and the semantic checks, on every change
+//! - a document outline of the declared items, their fields and variants
+//! - go-to-definition and find-references for names and paths, including
+//!   locals and fields reached with `.`
+//! - hover with function signatures and type layouts
+//! - completion after `.` and `::`
+//! - semantic tokens from [`fig_highlight`]
+//!
+//! The AST records only where statements are, so the names of items are
+//! found by matching the items against the tokens; see [`fig_sema::index`].
+
+pub mod analysis;
+mod position;
+mod semantic;
+mod server;
+
+pub use server::{capabilities, serve};
@@ -0,0 +1,18 @@
+//! `fig-lsp`: the Fig language server, speaking LSP over stdin and stdout
+
+use std::process::ExitCode;
+
+use lsp_server::Connection;
+
+fn main() -> ExitCode {
+    let (connection, io_threads) = Connection::stdio();
+    let served = fig_lsp::serve(&connection);
+    drop(connection);
+    match served.map_err(|e| e.to_string()).and_then(|()| io_threads.join().map_err(|e| e.to_string())) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("error: {}", message);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,57 @@
+//! Conversion between byte offsets and LSP positions
+//!
+//! LSP positions count lines from zero and columns in UTF-16 code units,
+//! while the lexer and parser work in byte offsets.
+
+use lsp_types::Position;
+
+pub struct LineIndex {
+    /// Byte offset of the start of each line
+    line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    pub fn new(text: &str) -> Self {
+        let mut line_starts = vec![0];
+        line_starts.extend(text.match_indices('\n').map(|(i, _)| i + 1));
+        LineIndex { line_starts }
+    }
+
+    pub fn position(&self, text: &str, offset: usize) -> Position {
+        let offset = offset.min(text.len());
+        let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        let start = self.line_starts[line];
+        let column = text.get(start..offset).map_or(offset - start, |s| s.encode_utf16().count());
+        Position::new(line as u32, column as u32)
+    }
+
+    /// The byte offset of a position, clamped to the end of its line
+    pub fn offset(&self, text: &str, position: Position) -> usize {
+        let Some(&start) = self.line_starts.get(position.line as usize) else { return text.len() };
+        let end = self.line_starts.get(position.line as usize + 1).map_or(text.len(), |&next| next - 1);
+        let mut units = 0;
+        for (i, c) in text[start..end].char_indices() {
+            if units >= position.character as usize {
+                return start + i;
+            }
+            units += c.len_utf16();
+        }
+        end
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip_with_wide_characters() {
+        let text = "let a = 1\nlet s = \"é😀\" + b\n";
+        let index = LineIndex::new(text);
+        let b = text.find('b').unwrap();
+        assert_eq!(index.position(text, b), Position::new(1, 16));
+        assert_eq!(index.offset(text, Position::new(1, 16)), b);
+        assert_eq!(index.offset(text, Position::new(0, 99)), 9);
+        assert_eq!(index.position(text, text.len()), Position::new(2, 0));
+    }
+}
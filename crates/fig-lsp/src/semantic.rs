@@ -0,0 +1,98 @@
+//! Semantic tokens from [`fig_highlight`]
+//!
+//! Each highlight class maps to one of the standard LSP token types, so
+//! editor themes color them without configuration. Literals are split into
+//! numbers, strings and keywords by their first character.
+
+use fig_highlight::{Class, highlight};
+use lsp_types::{SemanticToken, SemanticTokenModifier, SemanticTokenType, SemanticTokensLegend};
+
+use crate::analysis::Analysis;
+
+const TYPES: [SemanticTokenType; 13] = [
+    SemanticTokenType::KEYWORD,
+    SemanticTokenType::TYPE,
+    SemanticTokenType::FUNCTION,
+    SemanticTokenType::METHOD,
+    SemanticTokenType::NAMESPACE,
+    SemanticTokenType::PARAMETER,
+    SemanticTokenType::PROPERTY,
+    SemanticTokenType::ENUM_MEMBER,
+    SemanticTokenType::DECORATOR,
+    SemanticTokenType::NUMBER,
+    SemanticTokenType::STRING,
+    SemanticTokenType::COMMENT,
+    SemanticTokenType::OPERATOR,
+];
+
+/// Set on primitive types
+const DEFAULT_LIBRARY: u32 = 1;
+
+pub fn legend() -> SemanticTokensLegend {
+    SemanticTokensLegend {
+        token_types: TYPES.to_vec(),
+        token_modifiers: vec![SemanticTokenModifier::DEFAULT_LIBRARY],
+    }
+}
+
+/// The document's highlights, one token per line of each, relative to the one before
+pub fn semantic_tokens(analysis: &Analysis) -> Vec<SemanticToken> {
+    let text = analysis.text();
+    let mut tokens = Vec::new();
+    let (mut line, mut start) = (0, 0);
+    for highlight in highlight(text) {
+        let (token_type, modifiers) = match highlight.class {
+            Class::Keyword => (SemanticTokenType::KEYWORD, 0),
+            Class::Type => (SemanticTokenType::TYPE, 0),
+            Class::PrimitiveType => (SemanticTokenType::TYPE, DEFAULT_LIBRARY),
+            Class::Function => (SemanticTokenType::FUNCTION, 0),
+            Class::Method => (SemanticTokenType::METHOD, 0),
+            Class::Namespace => (SemanticTokenType::NAMESPACE, 0),
+            Class::Parameter => (SemanticTokenType::PARAMETER, 0),
+            Class::Field => (SemanticTokenType::PROPERTY, 0),
+            Class::EnumVariant => (SemanticTokenType::ENUM_MEMBER, 0),
+            Class::Annotation => (SemanticTokenType::DECORATOR, 0),
+            Class::Comment => (SemanticTokenType::COMMENT, 0),
+            Class::Operator => (SemanticTokenType::OPERATOR, 0),
+            Class::Literal => match text[highlight.span.clone()].chars().next() {
+                Some('0'..='9') => (SemanticTokenType::NUMBER, 0),
+                Some('"' | '\'' | '$') => (SemanticTokenType::STRING, 0),
+                _ => (SemanticTokenType::KEYWORD, 0),
+            },
+        };
+        let token_type = TYPES.iter().position(|t| *t == token_type).unwrap_or_default() as u32;
+        // Clients need not support tokens spanning lines, so a block comment is split
+        let mut offset = highlight.span.start;
+        for piece in text[highlight.span.clone()].split('\n') {
+            if !piece.is_empty() {
+                let position = analysis.position(offset);
+                let delta_start = if position.line == line { position.character - start } else { position.character };
+                tokens.push(SemanticToken {
+                    delta_line: position.line - line,
+                    delta_start,
+                    length: piece.encode_utf16().count() as u32,
+                    token_type,
+                    token_modifiers_bitset: modifiers,
+                });
+                (line, start) = (position.line, position.character);
+            }
+            offset += piece.len() + 1;
+        }
+    }
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokens_are_relative_and_split_by_line() {
+        let analysis = Analysis::new("/* a\nb */ let s = \"é\"\n");
+        let tokens: Vec<[u32; 5]> = semantic_tokens(&analysis)
+            .iter()
+            .map(|t| [t.delta_line, t.delta_start, t.length, t.token_type, t.token_modifiers_bitset])
+            .collect();
+        assert_eq!(tokens, [[0, 0, 4, 11, 0], [1, 0, 4, 11, 0], [0, 5, 3, 0, 0], [0, 6, 1, 12, 0], [0, 2, 3, 10, 0]]);
+    }
+}
@@ -0,0 +1,285 @@
+//! The LSP message loop
+//!
+//! Documents are synced in full: every change replaces the text and
+//! rebuilds its [`Analysis`], then republishes its diagnostics. Requests are
+//! answered from the latest analysis of the document they name. A request
+//! whose params do not parse gets an error reply; a notification has no
+//! reply, so one with bad params is logged to stderr and skipped.
+
+use std::collections::HashMap;
+use std::error::Error;
+
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _, PublishDiagnostics,
+};
+use lsp_types::request::{
+    Completion, DocumentSymbolRequest, GotoDefinition, HoverRequest, References, Request as _,
+    SemanticTokensFullRequest,
+};
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams, CompletionResponse, DiagnosticSeverity,
+    DidChangeTextDocumentParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams, DocumentSymbol,
+    DocumentSymbolParams, DocumentSymbolResponse, GotoDefinitionParams, GotoDefinitionResponse, Hover, HoverContents,
+    HoverParams, HoverProviderCapability, Location, MarkupContent, MarkupKind, OneOf, PublishDiagnosticsParams, Range,
+    ReferenceParams, SemanticTokens, SemanticTokensFullOptions, SemanticTokensOptions, SemanticTokensParams,
+    SemanticTokensResult, SemanticTokensServerCapabilities, ServerCapabilities, SymbolKind,
+    TextDocumentPositionParams, TextDocumentSyncCapability, TextDocumentSyncKind, Uri,
+};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::analysis::{Analysis, Symbol};
+use crate::semantic::{legend, semantic_tokens};
+use fig_parser::ast::Span;
+use fig_sema::diagnostics::Severity;
+use fig_sema::index::DefKind;
+
+type Result<T> = std::result::Result<T, Box<dyn Error + Sync + Send>>;
+
+pub fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        references_provider: Some(OneOf::Left(true)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec![".".to_string(), ":".to_string()]),
+            ..CompletionOptions::default()
+        }),
+        semantic_tokens_provider: Some(SemanticTokensServerCapabilities::SemanticTokensOptions(SemanticTokensOptions {
+            legend: legend(),
+            full: Some(SemanticTokensFullOptions::Bool(true)),
+            ..SemanticTokensOptions::default()
+        })),
+        ..ServerCapabilities::default()
+    }
+}
+
+/// Initialize `connection`, then serve requests until the client shuts it down
+pub fn serve(connection: &Connection) -> Result<()> {
+    connection.initialize(serde_json::to_value(capabilities())?)?;
+    let mut server = Server { connection, documents: HashMap::new() };
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection.handle_shutdown(&request)? {
+                    return Ok(());
+                }
+                let response = server.request(request);
+                connection.sender.send(Message::Response(response))?;
+            }
+            Message::Notification(notification) => server.notification(notification)?,
+            Message::Response(_) => {}
+        }
+    }
+    Ok(())
+}
+
+/// The params of a notification, or none once the reason they do not parse
+/// has been logged
+fn params<P: DeserializeOwned>(notification: Notification) -> Option<P> {
+    match serde_json::from_value(notification.params) {
+        Ok(params) => Some(params),
+        Err(error) => {
+            eprintln!("fig-lsp: ignoring `{}` notification: {}", notification.method, error);
+            None
+        }
+    }
+}
+
+struct Server<'c> {
+    connection: &'c Connection,
+    documents: HashMap<String, Analysis>,
+}
+
+impl Server<'_> {
+    fn notification(&mut self, notification: Notification) -> Result<()> {
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let Some(params) = params::<DidOpenTextDocumentParams>(notification) else { return Ok(()) };
+                self.update(params.text_document.uri, params.text_document.text)
+            }
+            DidChangeTextDocument::METHOD => {
+                let Some(params) = params::<DidChangeTextDocumentParams>(notification) else { return Ok(()) };
+                match params.content_changes.into_iter().last() {
+                    Some(change) => self.update(params.text_document.uri, change.text),
+                    None => Ok(()),
+                }
+            }
+            DidCloseTextDocument::METHOD => {
+                let Some(params) = params::<DidCloseTextDocumentParams>(notification) else { return Ok(()) };
+                self.documents.remove(params.text_document.uri.as_str());
+                self.publish(params.text_document.uri, Vec::new())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn update(&mut self, uri: Uri, text: String) -> Result<()> {
+        let analysis = Analysis::new(text);
+        let diagnostics = analysis
+            .problems()
+            .iter()
+            .map(|problem| lsp_types::Diagnostic {
+                range: range(&analysis, problem.span),
+                severity: Some(match problem.severity {
+                    Severity::Error => DiagnosticSeverity::ERROR,
+                    Severity::Warning => DiagnosticSeverity::WARNING,
+                }),
+                source: Some("fig".to_string()),
+                message: problem.message.clone(),
+                ..lsp_types::Diagnostic::default()
+            })
+            .collect();
+        self.documents.insert(uri.as_str().to_string(), analysis);
+        self.publish(uri, diagnostics)
+    }
+
+    fn publish(&self, uri: Uri, diagnostics: Vec<lsp_types::Diagnostic>) -> Result<()> {
+        let params = PublishDiagnosticsParams { uri, diagnostics, version: None };
+        let notification = Notification::new(PublishDiagnostics::METHOD.to_string(), params);
+        self.connection.sender.send(Message::Notification(notification))?;
+        Ok(())
+    }
+
+    fn request(&self, request: Request) -> Response {
+        let id = request.id.clone();
+        let result = match request.method.as_str() {
+            DocumentSymbolRequest::METHOD => self.handle(request, Self::document_symbols),
+            GotoDefinition::METHOD => self.handle(request, Self::definition),
+            References::METHOD => self.handle(request, Self::references),
+            HoverRequest::METHOD => self.handle(request, Self::hover),
+            Completion::METHOD => self.handle(request, Self::completion),
+            SemanticTokensFullRequest::METHOD => self.handle(request, Self::semantic_tokens),
+            method => {
+                let message = format!("unknown method `{}`", method);
+                return Response::new_err(id, ErrorCode::MethodNotFound as i32, message);
+            }
+        };
+        match result {
+            Ok(value) => Response::new_ok(id, value),
+            Err(message) => Response::new_err(id, ErrorCode::InvalidParams as i32, message),
+        }
+    }
+
+    fn handle<P, R>(&self, request: Request, handler: fn(&Self, P) -> Option<R>) -> std::result::Result<Value, String>
+    where
+        P: DeserializeOwned,
+        R: serde::Serialize,
+    {
+        let params = serde_json::from_value(request.params).map_err(|e| e.to_string())?;
+        serde_json::to_value(handler(self, params)).map_err(|e| e.to_string())
+    }
+
+    fn document(&self, uri: &Uri) -> Option<&Analysis> {
+        self.documents.get(uri.as_str())
+    }
+
+    /// The document and offset a position request is about
+    fn at(&self, params: &TextDocumentPositionParams) -> Option<(&Analysis, usize)> {
+        let analysis = self.document(&params.text_document.uri)?;
+        Some((analysis, analysis.offset(params.position)))
+    }
+
+    fn document_symbols(&self, params: DocumentSymbolParams) -> Option<DocumentSymbolResponse> {
+        let analysis = self.document(&params.text_document.uri)?;
+        let symbols = analysis.symbols().into_iter().map(|symbol| document_symbol(analysis, symbol)).collect();
+        Some(DocumentSymbolResponse::Nested(symbols))
+    }
+
+    fn definition(&self, params: GotoDefinitionParams) -> Option<GotoDefinitionResponse> {
+        let position = params.text_document_position_params;
+        let (analysis, offset) = self.at(&position)?;
+        let span = analysis.definition(offset)?;
+        Some(GotoDefinitionResponse::Scalar(Location::new(position.text_document.uri, range(analysis, span))))
+    }
+
+    fn references(&self, params: ReferenceParams) -> Option<Vec<Location>> {
+        let position = params.text_document_position;
+        let (analysis, offset) = self.at(&position)?;
+        let spans = analysis.references(offset, params.context.include_declaration);
+        let uri = position.text_document.uri;
+        Some(spans.into_iter().map(|span| Location::new(uri.clone(), range(analysis, span))).collect())
+    }
+
+    fn hover(&self, params: HoverParams) -> Option<Hover> {
+        let (analysis, offset) = self.at(&params.text_document_position_params)?;
+        let (span, value) = analysis.hover(offset)?;
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent { kind: MarkupKind::Markdown, value }),
+            range: Some(range(analysis, span)),
+        })
+    }
+
+    fn completion(&self, params: CompletionParams) -> Option<CompletionResponse> {
+        let (analysis, offset) = self.at(&params.text_document_position)?;
+        let items = analysis
+            .completions(offset)
+            .into_iter()
+            .map(|completion| CompletionItem {
+                label: completion.label,
+                kind: Some(completion_kind(completion.kind)),
+                detail: Some(completion.detail),
+                ..CompletionItem::default()
+            })
+            .collect();
+        Some(CompletionResponse::Array(items))
+    }
+
+    fn semantic_tokens(&self, params: SemanticTokensParams) -> Option<SemanticTokensResult> {
+        let analysis = self.document(&params.text_document.uri)?;
+        let data = semantic_tokens(analysis);
+        Some(SemanticTokensResult::Tokens(SemanticTokens { result_id: None, data }))
+    }
+}
+
+fn range(analysis: &Analysis, span: Span) -> Range {
+    Range::new(analysis.position(span.start), analysis.position(span.end))
+}
+
+#[allow(deprecated)]
+fn document_symbol(analysis: &Analysis, symbol: Symbol) -> DocumentSymbol {
+    DocumentSymbol {
+        name: symbol.name,
+        detail: Some(symbol.detail),
+        kind: symbol_kind(symbol.kind),
+        tags: None,
+        deprecated: None,
+        range: range(analysis, symbol.extent),
+        selection_range: range(analysis, symbol.span),
+        children: Some(symbol.children.into_iter().map(|child| document_symbol(analysis, child)).collect()),
+    }
+}
+
+fn symbol_kind(kind: DefKind) -> SymbolKind {
+    match kind {
+        DefKind::Namespace => SymbolKind::NAMESPACE,
+        DefKind::Function => SymbolKind::FUNCTION,
+        DefKind::Struct | DefKind::Union => SymbolKind::STRUCT,
+        DefKind::Enum => SymbolKind::ENUM,
+        DefKind::Interface => SymbolKind::INTERFACE,
+        DefKind::Alias => SymbolKind::TYPE_PARAMETER,
+        DefKind::Const => SymbolKind::CONSTANT,
+        DefKind::Field => SymbolKind::FIELD,
+        DefKind::Variant => SymbolKind::ENUM_MEMBER,
+        DefKind::Method => SymbolKind::METHOD,
+        DefKind::Local => SymbolKind::VARIABLE,
+    }
+}
+
+fn completion_kind(kind: DefKind) -> CompletionItemKind {
+    match kind {
+        DefKind::Namespace => CompletionItemKind::MODULE,
+        DefKind::Function => CompletionItemKind::FUNCTION,
+        DefKind::Struct | DefKind::Union | DefKind::Alias => CompletionItemKind::STRUCT,
+        DefKind::Enum => CompletionItemKind::ENUM,
+        DefKind::Interface => CompletionItemKind::INTERFACE,
+        DefKind::Const => CompletionItemKind::CONSTANT,
+        DefKind::Field => CompletionItemKind::FIELD,
+        DefKind::Variant => CompletionItemKind::ENUM_MEMBER,
+        DefKind::Method => CompletionItemKind::METHOD,
+        DefKind::Local => CompletionItemKind::VARIABLE,
+    }
+}
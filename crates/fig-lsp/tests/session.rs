@@ -0,0 +1,236 @@
+// Drives the `fig-lsp` binary through scripted JSON-RPC sessions over stdio
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+use serde_json::{Value, json};
+
+const URI: &str = "file:///work/geo.fig";
+
+const SRC: &str = "\
+namespace geo
+
+struct Point
+    x: i32
+    y: i32
+
+enum Dir
+    N
+    S
+
+func Point::flip(*self) -> Dir
+    return Dir::S
+
+func len(p: Point) -> i32
+    let d = p.x + p.y
+    return d
+";
+
+struct Session {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    next_id: u64,
+}
+
+impl Session {
+    fn start() -> Self {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_fig-lsp"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        let mut session = Session { child, stdin, stdout, next_id: 0 };
+        let result = session.request("initialize", json!({ "capabilities": {} }));
+        assert_eq!(result["capabilities"]["hoverProvider"], true, "{}", result);
+        session.notify("initialized", json!({}));
+        session
+    }
+
+    fn send(&mut self, message: Value) {
+        let body = message.to_string();
+        write!(self.stdin, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+        self.stdin.flush().unwrap();
+    }
+
+    fn receive(&mut self) -> Value {
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            self.stdout.read_line(&mut line).unwrap();
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some(value) = line.strip_prefix("Content-Length: ") {
+                length = value.parse().unwrap();
+            }
+        }
+        let mut body = vec![0; length];
+        self.stdout.read_exact(&mut body).unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    fn notify(&mut self, method: &str, params: Value) {
+        self.send(json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+    }
+
+    /// Send a request and wait for its result, skipping notifications
+    fn request(&mut self, method: &str, params: Value) -> Value {
+        self.next_id += 1;
+        let id = self.next_id;
+        self.send(json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }));
+        loop {
+            let message = self.receive();
+            if message["id"] == id {
+                assert!(message.get("error").is_none(), "{}", message);
+                return message["result"].clone();
+            }
+        }
+    }
+
+    /// The diagnostics published next
+    fn diagnostics(&mut self) -> Vec<Value> {
+        loop {
+            let message = self.receive();
+            if message["method"] == "textDocument/publishDiagnostics" {
+                assert_eq!(message["params"]["uri"], URI);
+                return message["params"]["diagnostics"].as_array().unwrap().clone();
+            }
+        }
+    }
+
+    fn change(&mut self, version: i32, text: &str) -> Vec<Value> {
+        let document = json!({ "uri": URI, "version": version });
+        let changes = json!([{ "text": text }]);
+        self.notify("textDocument/didChange", json!({ "textDocument": document, "contentChanges": changes }));
+        self.diagnostics()
+    }
+
+    fn at(&mut self, method: &str, line: u32, character: u32, context: Value) -> Value {
+        let params = json!({
+            "textDocument": { "uri": URI },
+            "position": { "line": line, "character": character },
+            "context": context,
+        });
+        self.request(method, params)
+    }
+
+    fn finish(mut self) {
+        assert_eq!(self.request("shutdown", Value::Null), Value::Null);
+        self.notify("exit", Value::Null);
+        assert!(self.child.wait().unwrap().success());
+    }
+}
+
+fn open(session: &mut Session, text: &str) -> Vec<Value> {
+    let document = json!({ "uri": URI, "languageId": "fig", "version": 1, "text": text });
+    session.notify("textDocument/didOpen", json!({ "textDocument": document }));
+    session.diagnostics()
+}
+
+/// The context of a completion request triggered by typing `character`
+fn triggered(character: &str) -> Value {
+    json!({ "triggerKind": 2, "triggerCharacter": character })
+}
+
+fn range(start: (u32, u32), end: (u32, u32)) -> Value {
+    json!({
+        "start": { "line": start.0, "character": start.1 },
+        "end": { "line": end.0, "character": end.1 },
+    })
+}
+
+#[test]
+fn test_diagnostics_follow_changes() {
+    let mut session = Session::start();
+    assert_eq!(open(&mut session, SRC), Vec::<Value>::new());
+
+    let broken = SRC.replace("return d", "return (d");
+    let diagnostics = session.change(2, &broken);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0]["severity"], 1);
+    assert_eq!(diagnostics[0]["range"]["start"]["line"], 15, "{:?}", diagnostics);
+
+    let impure = "func set(p: *mut i32) -> ok\n    *p = 1\n";
+    let diagnostics = session.change(3, impure);
+    assert_eq!(diagnostics[0]["range"], range((1, 4), (1, 10)));
+    assert!(diagnostics[0]["message"].as_str().unwrap().contains("writes through a pointer"));
+
+    assert_eq!(session.change(4, SRC), Vec::<Value>::new());
+    session.finish();
+}
+
+#[test]
+fn test_malformed_notifications_are_skipped() {
+    let mut session = Session::start();
+    session.notify("textDocument/didOpen", json!({ "textDocument": { "uri": URI } }));
+    session.notify("textDocument/didChange", json!({ "contentChanges": 7 }));
+    assert_eq!(open(&mut session, SRC), Vec::<Value>::new());
+    let symbols = session.request("textDocument/documentSymbol", json!({ "textDocument": { "uri": URI } }));
+    assert!(!symbols.as_array().unwrap().is_empty(), "{}", symbols);
+    session.finish();
+}
+
+#[test]
+fn test_navigation() {
+    let mut session = Session::start();
+    open(&mut session, SRC);
+
+    let symbols = session.request("textDocument/documentSymbol", json!({ "textDocument": { "uri": URI } }));
+    let names: Vec<&str> = symbols.as_array().unwrap().iter().map(|s| s["name"].as_str().unwrap()).collect();
+    assert_eq!(names, ["geo", "Point", "Dir", "flip", "len"]);
+    assert_eq!(symbols[1]["kind"], 23);
+    assert_eq!(symbols[1]["range"], range((2, 0), (4, 10)));
+    assert_eq!(symbols[1]["children"][0]["name"], "x");
+
+    // `p.x` on line 14 goes to the field
+    let location = session.at("textDocument/definition", 14, 14, Value::Null);
+    assert_eq!(location["uri"], URI);
+    assert_eq!(location["range"], range((3, 4), (3, 5)));
+
+    // `Dir` is declared once and used twice
+    let references = session.at("textDocument/references", 6, 5, json!({ "includeDeclaration": true }));
+    let lines: Vec<&Value> = references.as_array().unwrap().iter().map(|r| &r["range"]["start"]["line"]).collect();
+    assert_eq!(lines, [6, 10, 11]);
+
+    // The local `d` in `return d`
+    let references = session.at("textDocument/references", 15, 11, json!({ "includeDeclaration": true }));
+    assert_eq!(references.as_array().unwrap().len(), 2);
+
+    // `namespace` is a keyword and `geo` a namespace; `struct Point` follows two lines down
+    let tokens = session.request("textDocument/semanticTokens/full", json!({ "textDocument": { "uri": URI } }));
+    let data: Vec<u64> = tokens["data"].as_array().unwrap().iter().map(|n| n.as_u64().unwrap()).collect();
+    assert_eq!(data[..15], [0, 0, 9, 0, 0, 0, 10, 3, 4, 0, 2, 0, 6, 0, 0]);
+    session.finish();
+}
+
+#[test]
+fn test_hover_and_completion() {
+    let mut session = Session::start();
+    open(&mut session, SRC);
+
+    let hover = session.at("textDocument/hover", 10, 12, Value::Null);
+    assert_eq!(hover["contents"]["kind"], "markdown");
+    assert_eq!(hover["contents"]["value"], "```fig\nfunc Point::flip(*self) -> Dir\n```");
+
+    let hover = session.at("textDocument/hover", 13, 13, Value::Null);
+    let value = hover["contents"]["value"].as_str().unwrap();
+    assert!(value.contains("    y: i32  // offset 4"), "{}", value);
+    assert!(value.ends_with("size 8, align 4"), "{}", value);
+
+    let completions = session.at("textDocument/completion", 11, 16, triggered(":"));
+    let labels: Vec<&str> = completions.as_array().unwrap().iter().map(|c| c["label"].as_str().unwrap()).collect();
+    assert_eq!(labels, ["N", "S"]);
+    assert_eq!(completions[0]["kind"], 20);
+
+    // `p.` alone does not parse
+    let text = SRC.replace("let d = p.x + p.y", "let d = p.");
+    session.change(2, &text);
+    let completions = session.at("textDocument/completion", 14, 14, triggered("."));
+    let labels: Vec<&str> = completions.as_array().unwrap().iter().map(|c| c["label"].as_str().unwrap()).collect();
+    assert_eq!(labels, ["x", "y", "flip"]);
+    session.finish();
+}
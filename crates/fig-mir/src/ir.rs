@@ -0,0 +1,659 @@
+//! The mid-level IR and its textual dump
+//!
+//! A [`Body`] is one monomorphic function instance: a list of typed locals
+//! and a control-flow graph of [`BasicBlock`]s. Parameters are the first
+//! `arg_count` locals. Statements only move values between places; all
+//! control flow, including loops (a `goto` back to the loop header), `break`
+//! out of a named block and the early return of `callee!(args)`, is a
+//! [`Terminator`].
+//!
+//! Operations keep Fig's checked semantics, so a backend decides how to
+//! implement them: integer arithmetic traps on overflow, and [`Projection::Index`],
+//! [`Projection::Deref`] and [`Projection::Variant`] trap on an out-of-bounds
+//! index, a null pointer and an inactive union variant. Most temporaries are
+//! assigned once; those joining the values of two branches, such as the
+//! result of `&&`, and loop counters are assigned on each path.
+//!
+//! The [`Display`](fmt::Display) impls print the dump format:
+//!
+//! ```text
+//! fn largest[u8](_0: u8, _1: u8) -> u8
+//!     let _0: u8  // a
+//!     let _1: u8  // b
+//!     let _2: bool
+//!
+//!     bb0:
+//!         _2 = gt _0, _1
+//!         branch _2, bb1, bb2
+//!
+//!     bb1:
+//!         return _0
+//!
+//!     bb2:
+//!         return _1
+//! ```
+
+use std::fmt;
+
+use fig_parser::ast::{Span, Type};
+use fig_parser::format::format_type;
+use fig_sema::propagation::ErrorConversion;
+use fig_sema::typeck::Builtin;
+
+/// A local variable, parameter or temporary of a [`Body`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Local(pub u32);
+
+impl Local {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockId(pub u32);
+
+impl BlockId {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// The lowered functions of a program
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Program {
+    /// Function instances in the order they were reached
+    pub functions: Vec<Body>,
+    /// The `extern` functions they call
+    pub externs: Vec<Extern>,
+}
+
+impl Program {
+    pub fn function(&self, name: &str) -> Option<&Body> {
+        self.functions.iter().find(|f| f.name == name)
+    }
+
+    pub fn extern_function(&self, name: &str) -> Option<&Extern> {
+        self.externs.iter().find(|f| f.name == name)
+    }
+
+    /// Parameter and return types of the function a [`Callee`] names, or
+    /// `None` for builtins and unknown names
+    pub fn signature(&self, callee: &Callee) -> Option<(Vec<Type>, Type)> {
+        match callee {
+            Callee::Function(name) => {
+                let f = self.function(name)?;
+                Some((f.params().map(|local| f.locals[local.index()].ty.clone()).collect(), f.return_type.clone()))
+            }
+            Callee::Extern(name) => {
+                let f = self.extern_function(name)?;
+                Some((f.params.clone(), f.return_type.clone()))
+            }
+            Callee::Builtin(_) => None,
+        }
+    }
+}
+
+/// An `extern func`, called by its own name
+#[derive(Debug, Clone, PartialEq)]
+pub struct Extern {
+    pub name: String,
+    pub params: Vec<Type>,
+    pub return_type: Type,
+}
+
+/// One function instance
+#[derive(Debug, Clone, PartialEq)]
+pub struct Body {
+    /// The instance name, e.g. `Vec::push[i32]`
+    pub name: String,
+    /// Whether the function is `public` or `export`
+    pub exported: bool,
+    pub arg_count: usize,
+    pub locals: Vec<LocalDecl>,
+    pub return_type: Type,
+    /// `blocks[0]` is the entry block
+    pub blocks: Vec<BasicBlock>,
+    /// What the function's `#inline` or `#noinline` annotation asks of the
+    /// inliner
+    pub inline: Inline,
+}
+
+/// An inlining hint, from the `#inline` and `#noinline` annotations
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Inline {
+    /// Inline when the body is small
+    #[default]
+    Default,
+    Always,
+    Never,
+}
+
+impl Body {
+    pub fn params(&self) -> impl Iterator<Item = Local> + use<> {
+        (0..self.arg_count as u32).map(Local)
+    }
+
+    pub fn local(&self, local: Local) -> &LocalDecl {
+        &self.locals[local.index()]
+    }
+
+    pub fn block(&self, id: BlockId) -> &BasicBlock {
+        &self.blocks[id.index()]
+    }
+
+    /// The type of a place, following its projections. `None` when a
+    /// projection does not apply to the type it is applied to.
+    pub fn place_type(&self, place: &Place) -> Option<Type> {
+        let mut ty = self.locals.get(place.local.index())?.ty.clone();
+        for projection in &place.projection {
+            ty = projection.apply(&ty)?;
+        }
+        Some(ty)
+    }
+
+    pub fn operand_type(&self, operand: &Operand) -> Option<Type> {
+        match operand {
+            Operand::Copy(place) => self.place_type(place),
+            Operand::Const(constant) => Some(constant.ty()),
+        }
+    }
+
+    /// Drop the blocks the entry block cannot reach, renumbering the rest
+    /// in their original order
+    pub fn remove_unreachable_blocks(&mut self) {
+        let mut reachable = vec![false; self.blocks.len()];
+        let mut stack = vec![BlockId(0)];
+        while let Some(block) = stack.pop() {
+            if std::mem::replace(&mut reachable[block.index()], true) {
+                continue;
+            }
+            stack.extend(self.blocks[block.index()].terminator.successors());
+        }
+        let mut renumbered = Vec::with_capacity(self.blocks.len());
+        let mut next = 0;
+        for &is_reachable in &reachable {
+            renumbered.push(BlockId(next));
+            next += u32::from(is_reachable);
+        }
+        let blocks = std::mem::take(&mut self.blocks);
+        for (block, is_reachable) in blocks.into_iter().zip(reachable) {
+            if is_reachable {
+                self.blocks.push(block);
+            }
+        }
+        for block in &mut self.blocks {
+            for target in block.terminator.successors_mut() {
+                *target = renumbered[target.index()];
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocalDecl {
+    pub ty: Type,
+    /// The source name, `None` for temporaries
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BasicBlock {
+    pub statements: Vec<Statement>,
+    pub terminator: Terminator,
+    /// The source statement each statement was lowered from, in step with
+    /// `statements`; empty when unknown
+    pub spans: Vec<Span>,
+    /// The source statement the terminator was lowered from
+    pub terminator_span: Option<Span>,
+}
+
+impl BasicBlock {
+    /// A block with no source positions
+    pub fn new(statements: Vec<Statement>, terminator: Terminator) -> Self {
+        BasicBlock { statements, terminator, spans: Vec::new(), terminator_span: None }
+    }
+
+    /// Where the statement at `index` came from, if known
+    pub fn span(&self, index: usize) -> Option<Span> {
+        self.spans.get(index).copied()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement {
+    /// Store the value of an rvalue into a place. Assigning to a place
+    /// ending in [`Projection::Variant`] makes that variant the active one.
+    Assign(Place, Rvalue),
+    /// Evaluate an rvalue for its effects only, e.g. a call returning `ok`
+    Eval(Rvalue),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Terminator {
+    Goto(BlockId),
+    /// Continue with `then_block` if `cond` is true, `else_block` otherwise
+    Branch { cond: Operand, then_block: BlockId, else_block: BlockId },
+    Return(Operand),
+    /// The early return of `callee!(args)` and `object.!field`: if `value`
+    /// holds an error, return it from the function after `conversion`;
+    /// otherwise store the success value in `dest` and continue at `next`
+    Propagate { value: Operand, dest: Place, conversion: ErrorConversion, next: BlockId },
+    /// The end of a function that returns a value on every path
+    Unreachable,
+}
+
+impl Terminator {
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Goto(target) => vec![*target],
+            Terminator::Branch { then_block, else_block, .. } => vec![*then_block, *else_block],
+            Terminator::Propagate { next, .. } => vec![*next],
+            Terminator::Return(_) | Terminator::Unreachable => Vec::new(),
+        }
+    }
+
+    pub fn successors_mut(&mut self) -> Vec<&mut BlockId> {
+        match self {
+            Terminator::Goto(target) => vec![target],
+            Terminator::Branch { then_block, else_block, .. } => vec![then_block, else_block],
+            Terminator::Propagate { next, .. } => vec![next],
+            Terminator::Return(_) | Terminator::Unreachable => Vec::new(),
+        }
+    }
+}
+
+/// A memory location: a local and a path into it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Place {
+    pub local: Local,
+    pub projection: Vec<Projection>,
+}
+
+impl Place {
+    pub fn project(mut self, projection: Projection) -> Place {
+        self.projection.push(projection);
+        self
+    }
+}
+
+impl From<Local> for Place {
+    fn from(local: Local) -> Self {
+        Place { local, projection: Vec::new() }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Projection {
+    /// A struct field, with its type
+    Field(String, Type),
+    /// The payload of a union variant, with its type
+    Variant(String, Type),
+    /// An element of an array, slice or pointer
+    Index(Local),
+    /// The value a pointer points to
+    Deref,
+    /// The value of a `?T` known to hold one
+    Payload,
+    /// The success value of a `T ! E` known to hold one
+    OkValue,
+    /// The error of a `T ! E` known to hold one
+    ErrValue,
+}
+
+impl Projection {
+    /// The type of the projected place, given the type of its base
+    pub fn apply(&self, base: &Type) -> Option<Type> {
+        match (self, base) {
+            (Projection::Field(_, ty) | Projection::Variant(_, ty), Type::Path(_)) => Some(ty.clone()),
+            (Projection::Index(_), Type::Array { element_type, .. } | Type::Pointer { element_type, .. }) => {
+                Some((**element_type).clone())
+            }
+            (Projection::Deref, Type::Pointer { element_type, .. }) => Some((**element_type).clone()),
+            (Projection::Payload, Type::Optional(inner)) => Some((**inner).clone()),
+            (Projection::OkValue, Type::ErrorUnion { ok_type, .. }) => Some((**ok_type).clone()),
+            (Projection::ErrValue, Type::ErrorUnion { err_type, .. }) => Some(Type::Path(err_type.clone())),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Copy(Place),
+    Const(Constant),
+}
+
+impl From<Local> for Operand {
+    fn from(local: Local) -> Self {
+        Operand::Copy(local.into())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constant {
+    /// An integer, or the discriminant of an enum variant, of type `ty`
+    Int(i128, Type),
+    Float(f64, Type),
+    Bool(bool),
+    /// String bytes, as a `[u8]` slice or a `*u8`
+    Str(String, Type),
+    Ok,
+    /// The empty value of a `?T` or `?*T`
+    Null(Type),
+}
+
+impl Constant {
+    pub fn ty(&self) -> Type {
+        match self {
+            Constant::Int(_, ty) | Constant::Float(_, ty) | Constant::Str(_, ty) | Constant::Null(ty) => ty.clone(),
+            Constant::Bool(_) => Type::Bool,
+            Constant::Ok => Type::Ok,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    /// On a pointer and an integer, moves the pointer by that many elements
+    Add,
+    /// On a pointer and an integer, moves the pointer back; on two pointers,
+    /// the distance between them in elements, as an `isize`
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    BitAnd,
+    BitOr,
+    BitXor,
+    /// Traps when the shift amount is negative or not below the bit width
+    Shl,
+    Shr,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl BinOp {
+    pub fn is_comparison(self) -> bool {
+        matches!(self, BinOp::Eq | BinOp::Ne | BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            BinOp::Add => "add",
+            BinOp::Sub => "sub",
+            BinOp::Mul => "mul",
+            BinOp::Div => "div",
+            BinOp::Rem => "rem",
+            BinOp::BitAnd => "and",
+            BinOp::BitOr => "or",
+            BinOp::BitXor => "xor",
+            BinOp::Shl => "shl",
+            BinOp::Shr => "shr",
+            BinOp::Eq => "eq",
+            BinOp::Ne => "ne",
+            BinOp::Lt => "lt",
+            BinOp::Le => "le",
+            BinOp::Gt => "gt",
+            BinOp::Ge => "ge",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnOp {
+    /// Integer negation traps on overflow
+    Neg,
+    /// Logical not of a `bool`
+    Not,
+    BitNot,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Callee {
+    /// A function instance of the program, by instance name
+    Function(String),
+    Extern(String),
+    Builtin(Builtin),
+}
+
+/// What an [`Rvalue::Aggregate`] builds
+#[derive(Debug, Clone, PartialEq)]
+pub enum AggregateKind {
+    /// A struct, one operand per field
+    Struct(Type),
+    /// An array, one operand per element
+    Array(Type),
+    /// A union variant with its payload, or none for an `ok` variant
+    Variant(Type, String),
+    /// A `?T` holding its one operand
+    Some(Type),
+    /// A `T ! E` holding the success value
+    Ok(Type),
+    /// A `T ! E` holding the error
+    Err(Type),
+}
+
+impl AggregateKind {
+    pub fn ty(&self) -> &Type {
+        match self {
+            AggregateKind::Struct(ty)
+            | AggregateKind::Array(ty)
+            | AggregateKind::Variant(ty, _)
+            | AggregateKind::Some(ty)
+            | AggregateKind::Ok(ty)
+            | AggregateKind::Err(ty) => ty,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rvalue {
+    Use(Operand),
+    Binary(BinOp, Operand, Operand),
+    Unary(UnOp, Operand),
+    /// An `as` cast, or an implicit integer widening
+    Cast(Operand, Type),
+    AddressOf(Place),
+    Aggregate(AggregateKind, Vec<Operand>),
+    /// The slice of type `Type` over the whole array at a place
+    Unsize(Place, Type),
+    /// The length of the slice at a place, as a `usize`
+    Len(Place),
+    /// The slice over the elements `start..end` of the array or slice at a
+    /// place, both `usize`s; traps unless `start <= end <= len`
+    Subslice(Place, Operand, Operand),
+    /// Whether a `?T` or `?*T` is empty
+    IsNull(Operand),
+    /// Whether a `T ! E` holds an error
+    IsErr(Operand),
+    Call(Callee, Vec<Operand>),
+}
+
+// ============================================================================
+// Dump format
+// ============================================================================
+
+impl fmt::Display for Local {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "_{}", self.0)
+    }
+}
+
+impl fmt::Display for BlockId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bb{}", self.0)
+    }
+}
+
+impl fmt::Display for Place {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut text = self.local.to_string();
+        for projection in &self.projection {
+            text = match projection {
+                Projection::Field(name, _) => format!("{}.{}", text, name),
+                Projection::Variant(name, _) => format!("{} as {}", text, name),
+                Projection::Index(index) => format!("{}[{}]", text, index),
+                Projection::Deref => format!("(*{})", text),
+                Projection::Payload => format!("{}.some", text),
+                Projection::OkValue => format!("{}.ok", text),
+                Projection::ErrValue => format!("{}.err", text),
+            };
+        }
+        f.write_str(&text)
+    }
+}
+
+impl fmt::Display for Constant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Constant::Int(value, ty) => write!(f, "{}_{}", value, format_type(ty)),
+            Constant::Float(value, ty) => write!(f, "{:?}_{}", value, format_type(ty)),
+            Constant::Bool(value) => write!(f, "{}", value),
+            Constant::Str(value, _) => write!(f, "{:?}", value),
+            Constant::Ok => f.write_str("ok"),
+            Constant::Null(ty) => write!(f, "null_{}", format_type(ty)),
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Copy(place) => write!(f, "{}", place),
+            Operand::Const(constant) => write!(f, "{}", constant),
+        }
+    }
+}
+
+impl fmt::Display for Callee {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Callee::Function(name) => f.write_str(name),
+            Callee::Extern(name) => write!(f, "extern {}", name),
+            Callee::Builtin(builtin) => write!(f, "builtin {:?}", builtin),
+        }
+    }
+}
+
+fn list(operands: &[Operand]) -> String {
+    operands.iter().map(Operand::to_string).collect::<Vec<_>>().join(", ")
+}
+
+impl fmt::Display for Rvalue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rvalue::Use(operand) => write!(f, "{}", operand),
+            Rvalue::Binary(op, lhs, rhs) => write!(f, "{} {}, {}", op.name(), lhs, rhs),
+            Rvalue::Unary(op, operand) => {
+                let name = match op {
+                    UnOp::Neg => "neg",
+                    UnOp::Not => "not",
+                    UnOp::BitNot => "bitnot",
+                };
+                write!(f, "{} {}", name, operand)
+            }
+            Rvalue::Cast(operand, ty) => write!(f, "cast {} as {}", operand, format_type(ty)),
+            Rvalue::AddressOf(place) => write!(f, "&{}", place),
+            Rvalue::Aggregate(kind, operands) => {
+                let (name, ty) = match kind {
+                    AggregateKind::Struct(ty) => ("struct", ty),
+                    AggregateKind::Array(ty) => ("array", ty),
+                    AggregateKind::Variant(ty, variant) => {
+                        return write!(f, "variant {}::{}({})", format_type(ty), variant, list(operands));
+                    }
+                    AggregateKind::Some(ty) => ("some", ty),
+                    AggregateKind::Ok(ty) => ("ok", ty),
+                    AggregateKind::Err(ty) => ("err", ty),
+                };
+                write!(f, "{} {}({})", name, format_type(ty), list(operands))
+            }
+            Rvalue::Unsize(place, ty) => write!(f, "unsize {} as {}", place, format_type(ty)),
+            Rvalue::Len(place) => write!(f, "len {}", place),
+            Rvalue::Subslice(place, start, end) => write!(f, "subslice {}[{}..{}]", place, start, end),
+            Rvalue::IsNull(operand) => write!(f, "is_null {}", operand),
+            Rvalue::IsErr(operand) => write!(f, "is_err {}", operand),
+            Rvalue::Call(callee, args) => write!(f, "call {}({})", callee, list(args)),
+        }
+    }
+}
+
+impl fmt::Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Statement::Assign(place, rvalue) => write!(f, "{} = {}", place, rvalue),
+            Statement::Eval(rvalue) => write!(f, "{}", rvalue),
+        }
+    }
+}
+
+impl fmt::Display for Terminator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Terminator::Goto(target) => write!(f, "goto {}", target),
+            Terminator::Branch { cond, then_block, else_block } => {
+                write!(f, "branch {}, {}, {}", cond, then_block, else_block)
+            }
+            Terminator::Return(value) => write!(f, "return {}", value),
+            Terminator::Propagate { value, dest, conversion, next } => {
+                let conversion = match conversion {
+                    ErrorConversion::Identity => String::new(),
+                    ErrorConversion::Variant { variant } => format!(" as {}", variant),
+                    ErrorConversion::Function { function } => format!(" via {}", function),
+                };
+                write!(f, "{} = propagate {}{}, {}", dest, value, conversion, next)
+            }
+            Terminator::Unreachable => f.write_str("unreachable"),
+        }
+    }
+}
+
+impl fmt::Display for Body {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let params: Vec<String> =
+            self.params().map(|local| format!("{}: {}", local, format_type(&self.local(local).ty))).collect();
+        match self.inline {
+            Inline::Default => {}
+            Inline::Always => writeln!(f, "#inline")?,
+            Inline::Never => writeln!(f, "#noinline")?,
+        }
+        let export = if self.exported { "export " } else { "" };
+        writeln!(f, "{}fn {}({}) -> {}", export, self.name, params.join(", "), format_type(&self.return_type))?;
+        for (i, decl) in self.locals.iter().enumerate() {
+            let decl_text = format!("let _{}: {}", i, format_type(&decl.ty));
+            match &decl.name {
+                Some(name) => writeln!(f, "    {}  // {}", decl_text, name)?,
+                None => writeln!(f, "    {}", decl_text)?,
+            }
+        }
+        for (i, block) in self.blocks.iter().enumerate() {
+            writeln!(f)?;
+            writeln!(f, "    bb{}:", i)?;
+            for statement in &block.statements {
+                writeln!(f, "        {}", statement)?;
+            }
+            writeln!(f, "        {}", block.terminator)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for e in &self.externs {
+            let params: Vec<String> = e.params.iter().map(format_type).collect();
+            writeln!(f, "extern fn {}({}) -> {}", e.name, params.join(", "), format_type(&e.return_type))?;
+        }
+        for (i, body) in self.functions.iter().enumerate() {
+            if i > 0 || !self.externs.is_empty() {
+                writeln!(f)?;
+            }
+            write!(f, "{}", body)?;
+        }
+        Ok(())
+    }
+}
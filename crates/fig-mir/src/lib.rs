@@ -0,0 +1,33 @@
+//! Mid-level IR for Fig
+//!
+//! MIR sits between the checked AST and the code generators. Each function
+//! instance becomes a [`Body`](ir::Body): explicit, typed locals and a
+//! control-flow graph of basic blocks, with every implicit conversion, short
+//! circuit, loop and error propagation spelled out. [`lower_program`]
+//! lowers the instances the monomorphiser finds, [`verify`] checks the
+//! result, and the passes in [`opt`] optimise it. See [`ir`] for the textual
+//! dump format.
+//!
+//! ```ignore
+//! let sf = SourceFileParser::new().parse(Lexer::new(src))?;
+//! let items = ItemTable::from_source_file(&sf);
+//! let mut program = lower_program(&items, Target::host())?;
+//! opt::optimize(&mut program, Target::host());
+//! assert!(verify(&program).is_empty());
+//! println!("{}", program);
+//! ```
+
+pub mod ir;
+mod lower;
+pub mod opt;
+mod verify;
+
+pub use lower::{lower_function, lower_program};
+pub use verify::verify;
+
+#[cfg(test)]
+pub(crate) fn parse(src: &str) -> fig_parser::ast::SourceFile {
+    fig_parser::SourceFileParser::new()
+        .parse(fig_parser::Lexer::new(src))
+        .unwrap()
+}
@@ -0,0 +1,1280 @@
+//! Lowering of checked function bodies to MIR
+//!
+//! Each function instance is type-checked with `fig-sema`'s
+//! [`TypeChecker`] first, so lowering can rely on the types, call targets
+//! and error conversions recorded in its [`TypedBody`]. The implicit
+//! conversions the checker allows become explicit here: integer widening is
+//! a [`Rvalue::Cast`], wrapping into `?T` or `T ! E` an
+//! [`Rvalue::Aggregate`], and an array passed as a slice an
+//! [`Rvalue::Unsize`].
+//!
+//! Statements after a `return`, `break` or `continue` in the same block are
+//! not lowered, and blocks that end up unreachable are removed.
+
+use std::collections::HashMap;
+
+use fig_lexer::IntegerLiteral;
+use fig_parser::ast::{self, Block, Expression, Path, Span, Type, Visibility};
+use fig_parser::format::format_expression;
+use fig_sema::diagnostics::Diagnostic;
+use fig_sema::items::{ItemTable, TypeDef};
+use fig_sema::layout::Target;
+use fig_sema::mono::Monomorphizer;
+use fig_sema::propagation::ErrorConversion;
+use fig_sema::typeck::{
+    Bindings, Builtin, CallTarget, Instance, Iteration, PathTarget, SelfArg, Signature, TypeChecker, TypedBody, is_float,
+    is_integer, roots,
+};
+
+use crate::ir::*;
+
+/// Lower every function instance the [`Monomorphizer`] reaches from the
+/// program's non-generic functions (see [`roots`]), for `target`
+pub fn lower_program<'a>(items: &'a ItemTable<'a>, target: Target) -> Result<Program, Vec<Diagnostic>> {
+    let mut tc = TypeChecker::new(items, target);
+    let mono = Monomorphizer::new(&mut tc).run(roots(items))?;
+    let mut program = Program::default();
+    let mut diagnostics = Vec::new();
+    for function in &mono.functions {
+        let instance = &function.instance;
+        if is_extern(instance) {
+            match extern_decl(instance, &function.signature) {
+                Ok(decl) => program.externs.push(decl),
+                Err(diagnostic) => diagnostics.push(diagnostic),
+            }
+            continue;
+        }
+        match lower(&mut tc, instance, &function.signature, &function.body) {
+            Ok(body) => program.functions.push(body),
+            Err(errors) => diagnostics.extend(errors),
+        }
+    }
+    if diagnostics.is_empty() { Ok(program) } else { Err(diagnostics) }
+}
+
+/// Lower one function instance
+pub fn lower_function<'a>(tc: &mut TypeChecker<'a>, instance: &Instance<'a>) -> Result<Body, Vec<Diagnostic>> {
+    let signature = tc.signature(instance).map_err(|d| vec![d])?;
+    let typed = tc.check(instance)?;
+    lower(tc, instance, &signature, &typed)
+}
+
+fn is_extern(instance: &Instance) -> bool {
+    instance.function.signature.is_extern || instance.function.body.is_none()
+}
+
+fn extern_decl(instance: &Instance, signature: &Signature) -> Result<Extern, Diagnostic> {
+    if !instance.function.signature.is_extern {
+        return Err(Diagnostic::error(format!("`{}` is declared but never defined", instance.function.qualified_name()))
+            .with_note("only `extern` functions may be declared without a body"));
+    }
+    Ok(Extern {
+        name: instance.function.signature.name.clone(),
+        params: signature.params.iter().map(|(_, ty)| ty.clone()).collect(),
+        return_type: signature.return_type.clone(),
+    })
+}
+
+fn lower<'a>(
+    tc: &mut TypeChecker<'a>,
+    instance: &Instance<'a>,
+    signature: &Signature,
+    typed: &TypedBody<'a>,
+) -> Result<Body, Vec<Diagnostic>> {
+    let bindings = tc.bindings_of(instance).map_err(|message| vec![Diagnostic::error(message)])?;
+    let block = instance.function.body.expect("only functions with bodies are lowered");
+    let mut builder = Builder {
+        tc,
+        typed,
+        bindings,
+        name: instance.name(),
+        return_type: signature.return_type.clone(),
+        locals: Vec::new(),
+        blocks: Vec::new(),
+        current: None,
+        span: None,
+        scopes: vec![HashMap::new()],
+        loops: Vec::new(),
+        labels: Vec::new(),
+        diagnostics: Vec::new(),
+    };
+    let entry = builder.new_block();
+    builder.current = Some(entry);
+    if let Some(self_type) = &signature.self_type {
+        builder.declare("self", self_type.clone());
+    }
+    for (name, ty) in &signature.params {
+        builder.declare(name, ty.clone());
+    }
+    let arg_count = builder.locals.len();
+    builder.block(block);
+    if builder.current.is_some() {
+        let end = if builder.return_type == Type::Ok {
+            Terminator::Return(Operand::Const(Constant::Ok))
+        } else {
+            Terminator::Unreachable
+        };
+        builder.terminate(end);
+    }
+    if !builder.diagnostics.is_empty() {
+        return Err(builder.diagnostics);
+    }
+    let annotations = &instance.function.signature.annotations;
+    let inline = if annotations.iter().any(|a| a.name == "noinline") {
+        Inline::Never
+    } else if annotations.iter().any(|a| a.name == "inline") {
+        Inline::Always
+    } else {
+        Inline::Default
+    };
+    let mut body = Body {
+        name: builder.name,
+        exported: matches!(instance.function.signature.visibility, Visibility::Public | Visibility::Export),
+        arg_count,
+        locals: builder.locals,
+        return_type: signature.return_type.clone(),
+        blocks: builder
+            .blocks
+            .into_iter()
+            .map(|block| {
+                let (terminator, terminator_span) = match block.terminator {
+                    Some((terminator, span)) => (terminator, span),
+                    None => (Terminator::Unreachable, None),
+                };
+                BasicBlock { statements: block.statements, terminator, spans: block.spans, terminator_span }
+            })
+            .collect(),
+        inline,
+    };
+    body.remove_unreachable_blocks();
+    Ok(body)
+}
+
+fn literal_value(lit: &IntegerLiteral) -> i128 {
+    lit.as_u64().map(i128::from).unwrap_or(0)
+}
+
+/// A block being built
+struct PendingBlock {
+    statements: Vec<Statement>,
+    spans: Vec<Span>,
+    terminator: Option<(Terminator, Option<Span>)>,
+}
+
+/// State while lowering one function instance
+struct Builder<'t, 'b, 'a> {
+    tc: &'t mut TypeChecker<'a>,
+    typed: &'b TypedBody<'a>,
+    /// Generic arguments of the instance, for `sizeof` and friends
+    bindings: Bindings,
+    name: String,
+    return_type: Type,
+    locals: Vec<LocalDecl>,
+    blocks: Vec<PendingBlock>,
+    /// The block being filled; `None` after a terminator, until the next
+    /// block starts
+    current: Option<BlockId>,
+    /// The source statement being lowered, if the body has positions
+    span: Option<Span>,
+    /// Source names to locals, innermost scope last
+    scopes: Vec<HashMap<String, Local>>,
+    /// Where `break` and `continue` go, innermost loop last
+    loops: Vec<(BlockId, BlockId)>,
+    /// Exits of named blocks, innermost last
+    labels: Vec<(String, BlockId)>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Builder<'_, '_, 'a> {
+    fn error(&mut self, message: impl Into<String>, expr: &Expression) {
+        self.diagnostics
+            .push(Diagnostic::error(message).in_function(&self.name).with_snippet(format_expression(expr)));
+    }
+
+    // ========================================================================
+    // Blocks and locals
+    // ========================================================================
+
+    fn new_block(&mut self) -> BlockId {
+        self.blocks.push(PendingBlock { statements: Vec::new(), spans: Vec::new(), terminator: None });
+        BlockId(self.blocks.len() as u32 - 1)
+    }
+
+    fn push(&mut self, statement: Statement) {
+        if let Some(current) = self.current {
+            let block = &mut self.blocks[current.index()];
+            block.statements.push(statement);
+            block.spans.extend(self.span);
+        }
+    }
+
+    fn terminate(&mut self, terminator: Terminator) {
+        if let Some(current) = self.current.take() {
+            self.blocks[current.index()].terminator = Some((terminator, self.span));
+        }
+    }
+
+    fn goto(&mut self, target: BlockId) {
+        self.terminate(Terminator::Goto(target));
+    }
+
+    /// End the current block with a jump to `block` and continue there
+    fn enter(&mut self, block: BlockId) {
+        self.goto(block);
+        self.current = Some(block);
+    }
+
+    fn temp(&mut self, ty: Type) -> Local {
+        self.locals.push(LocalDecl { ty, name: None });
+        Local(self.locals.len() as u32 - 1)
+    }
+
+    /// Introduce a source variable in the innermost scope
+    fn declare(&mut self, name: &str, ty: Type) -> Local {
+        self.locals.push(LocalDecl { ty, name: Some(name.to_string()) });
+        let local = Local(self.locals.len() as u32 - 1);
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), local);
+        }
+        local
+    }
+
+    fn lookup(&self, name: &str) -> Option<Local> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name)).copied()
+    }
+
+    /// Store `rvalue` in a fresh temporary of type `ty`
+    fn assign_temp(&mut self, ty: Type, rvalue: Rvalue) -> Operand {
+        let temp = self.temp(ty);
+        self.push(Statement::Assign(temp.into(), rvalue));
+        temp.into()
+    }
+
+    /// A local holding the value of `operand`
+    fn local_of(&mut self, operand: Operand, ty: &Type) -> Local {
+        match operand {
+            Operand::Copy(Place { local, projection }) if projection.is_empty() => local,
+            operand => {
+                let temp = self.temp(ty.clone());
+                self.push(Statement::Assign(temp.into(), Rvalue::Use(operand)));
+                temp
+            }
+        }
+    }
+
+    fn type_of(&self, expr: &Expression) -> Type {
+        self.typed.type_of(expr).cloned().unwrap_or(Type::Ok)
+    }
+
+    fn callee(&self, instance: &Instance<'a>) -> Callee {
+        if is_extern(instance) {
+            Callee::Extern(instance.function.signature.name.clone())
+        } else {
+            Callee::Function(instance.name())
+        }
+    }
+
+    // ========================================================================
+    // Statements
+    // ========================================================================
+
+    fn block(&mut self, block: &Block) {
+        self.scopes.push(HashMap::new());
+        let outer = self.span;
+        for (i, stmt) in block.statements.iter().enumerate() {
+            if self.current.is_none() {
+                break;
+            }
+            self.span = block.span(i).or(outer);
+            self.statement(stmt);
+        }
+        self.span = outer;
+        self.scopes.pop();
+    }
+
+    fn statement(&mut self, stmt: &ast::Statement) {
+        use ast::Statement as S;
+        match stmt {
+            S::Expression(expr) => {
+                // A bare place is still read, so that a bad index or pointer traps
+                if let Some(Operand::Copy(place)) = self.operand(expr)
+                    && !place.projection.is_empty()
+                {
+                    self.push(Statement::Eval(Rvalue::Use(Operand::Copy(place))));
+                }
+            }
+            S::Let(ast::LetStatement { name, value, .. })
+            | S::Mut(ast::MutStatement { name, value, .. })
+            | S::Const(ast::ConstStatement { name, value, .. }) => {
+                let Some(ty) = self.typed.local(stmt).cloned() else { return };
+                let Some(value) = self.convert(value, &ty) else { return };
+                let local = self.declare(name, ty);
+                self.push(Statement::Assign(local.into(), Rvalue::Use(value)));
+            }
+            S::Return(value) => {
+                let ret = self.return_type.clone();
+                let value = if ret == Type::Ok {
+                    self.operand(value).map(|_| Operand::Const(Constant::Ok))
+                } else {
+                    self.convert(value, &ret)
+                };
+                if let Some(value) = value {
+                    self.terminate(Terminator::Return(value));
+                }
+            }
+            S::Break(None) => {
+                if let Some(&(exit, _)) = self.loops.last() {
+                    self.goto(exit);
+                }
+            }
+            S::Break(Some(label)) => {
+                match self.labels.iter().rev().find(|(name, _)| name == label).map(|(_, exit)| *exit) {
+                    Some(exit) => self.goto(exit),
+                    None => self.diagnostics.push(
+                        Diagnostic::error(format!("no enclosing block is named `{}`", label)).in_function(&self.name),
+                    ),
+                }
+            }
+            S::Continue => {
+                if let Some(&(_, next)) = self.loops.last() {
+                    self.goto(next);
+                }
+            }
+            S::Block(block) => {
+                let Some(name) = &block.name else {
+                    self.block(&block.body);
+                    return;
+                };
+                let exit = self.new_block();
+                self.labels.push((name.clone(), exit));
+                self.block(&block.body);
+                self.labels.pop();
+                self.enter(exit);
+            }
+            S::If(stmt) => {
+                let mut exit = None;
+                self.if_chain(&stmt.condition, &stmt.then_body, &stmt.elif_clauses, &stmt.else_body, &mut exit);
+                self.current = exit;
+            }
+            S::While(stmt) => {
+                let header = self.new_block();
+                let body = self.new_block();
+                let exit = self.new_block();
+                self.enter(header);
+                let Some(cond) = self.operand(&stmt.condition) else { return };
+                self.terminate(Terminator::Branch { cond, then_block: body, else_block: exit });
+                self.current = Some(body);
+                self.loops.push((exit, header));
+                self.block(&stmt.body);
+                self.loops.pop();
+                self.goto(header);
+                self.current = Some(exit);
+            }
+            S::For(for_stmt) => self.for_loop(stmt, for_stmt),
+            // Nested declarations are collected into the item table
+            _ => {}
+        }
+    }
+
+    /// Lower an `if` with its `elif` and `else` clauses. The join block is
+    /// only created once some branch falls through to it.
+    fn if_chain(
+        &mut self,
+        condition: &Expression,
+        then_body: &Block,
+        elifs: &[ast::ElifClause],
+        else_body: &Option<Block>,
+        exit: &mut Option<BlockId>,
+    ) {
+        let Some(cond) = self.operand(condition) else { return };
+        let then_block = self.new_block();
+        let else_block = if elifs.is_empty() && else_body.is_none() {
+            *exit.get_or_insert_with(|| self.new_block())
+        } else {
+            self.new_block()
+        };
+        self.terminate(Terminator::Branch { cond, then_block, else_block });
+        self.current = Some(then_block);
+        self.block(then_body);
+        self.join(exit);
+        if Some(else_block) == *exit {
+            return;
+        }
+        self.current = Some(else_block);
+        match (elifs.split_first(), else_body) {
+            (Some((first, rest)), _) => self.if_chain(&first.condition, &first.body, rest, else_body, exit),
+            (None, Some(body)) => {
+                self.block(body);
+                self.join(exit);
+            }
+            (None, None) => unreachable!("handled above"),
+        }
+    }
+
+    /// Jump to the join block of an `if`, if the current block falls through
+    fn join(&mut self, exit: &mut Option<BlockId>) {
+        if self.current.is_some() {
+            let target = *exit.get_or_insert_with(|| self.new_block());
+            self.goto(target);
+        }
+    }
+
+    fn for_loop(&mut self, stmt: &ast::Statement, for_stmt: &ast::ForStatement) {
+        let (Some(item), Some(iteration)) = (self.typed.local(stmt).cloned(), self.typed.iteration(for_stmt).cloned())
+        else {
+            return;
+        };
+        if let (Iteration::Range, Expression::Range(range)) = (&iteration, for_stmt.iterable.as_ref()) {
+            self.range_loop(for_stmt, range, item);
+            return;
+        }
+        let iterable_type = self.type_of(&for_stmt.iterable);
+        // Evaluate the iterable once, into a temporary the loop owns
+        let Some(value) = self.operand(&for_stmt.iterable) else { return };
+        let iterable = match value {
+            Operand::Copy(place) if place.projection.is_empty() && self.locals[place.local.index()].name.is_none() => {
+                place.local
+            }
+            value => {
+                let temp = self.temp(iterable_type.clone());
+                self.push(Statement::Assign(temp.into(), Rvalue::Use(value)));
+                temp
+            }
+        };
+        self.scopes.push(HashMap::new());
+        let header = self.new_block();
+        let body = self.new_block();
+        let step = self.new_block();
+        let exit = self.new_block();
+        match iteration {
+            Iteration::Elements => {
+                let index = self.temp(Type::USize);
+                self.push(Statement::Assign(index.into(), Rvalue::Use(Operand::Const(Constant::Int(0, Type::USize)))));
+                self.enter(header);
+                let len = match &iterable_type {
+                    Type::Array { size: Some(size), .. } => Operand::Const(Constant::Int(self.array_len(size), Type::USize)),
+                    _ => self.assign_temp(Type::USize, Rvalue::Len(iterable.into())),
+                };
+                let cond = self.assign_temp(Type::Bool, Rvalue::Binary(BinOp::Lt, index.into(), len));
+                self.terminate(Terminator::Branch { cond, then_block: body, else_block: exit });
+                self.current = Some(body);
+                let pattern = self.declare(&for_stmt.pattern, item);
+                let element = Place::from(iterable).project(Projection::Index(index));
+                self.push(Statement::Assign(pattern.into(), Rvalue::Use(Operand::Copy(element))));
+                self.loops.push((exit, step));
+                self.block(&for_stmt.body);
+                self.loops.pop();
+                self.enter(step);
+                let one = Operand::Const(Constant::Int(1, Type::USize));
+                self.push(Statement::Assign(index.into(), Rvalue::Binary(BinOp::Add, index.into(), one)));
+                self.goto(header);
+            }
+            Iteration::Range => unreachable!("handled by `range_loop`"),
+            Iteration::Iterator { next, self_arg } => {
+                self.enter(header);
+                let signature = match self.tc.signature(&next) {
+                    Ok(signature) => signature,
+                    Err(diagnostic) => {
+                        self.diagnostics.push(diagnostic);
+                        return;
+                    }
+                };
+                let self_type = signature.self_type.unwrap_or_else(|| iterable_type.clone());
+                let receiver = self.self_arg(iterable.into(), &iterable_type, self_arg, &self_type);
+                let Some(receiver) = receiver else { return };
+                let next_type = signature.return_type;
+                let callee = self.callee(&next);
+                let result = self.assign_temp(next_type.clone(), Rvalue::Call(callee, vec![receiver]));
+                let done = self.assign_temp(Type::Bool, Rvalue::IsNull(result.clone()));
+                self.terminate(Terminator::Branch { cond: done, then_block: exit, else_block: body });
+                self.current = Some(body);
+                let pattern = self.declare(&for_stmt.pattern, item.clone());
+                let Operand::Copy(result) = result else { unreachable!("temporaries are places") };
+                let value = match next_type {
+                    Type::Pointer { .. } => Rvalue::Cast(Operand::Copy(result), item),
+                    _ => Rvalue::Use(Operand::Copy(result.project(Projection::Payload))),
+                };
+                self.push(Statement::Assign(pattern.into(), value));
+                self.loops.push((exit, step));
+                self.block(&for_stmt.body);
+                self.loops.pop();
+                self.enter(step);
+                self.goto(header);
+            }
+        }
+        self.scopes.pop();
+        self.current = Some(exit);
+    }
+
+    /// `for i in start..end`: a hidden counter steps through the range, so
+    /// assigning to the pattern variable does not change the iteration. An
+    /// inclusive range stops after the pass with the counter at its end
+    /// rather than incrementing past it, which could overflow.
+    fn range_loop(&mut self, for_stmt: &ast::ForStatement, range: &ast::RangeExpr, item: Type) {
+        let Some(start) = range.start.as_deref().and_then(|start| self.operand(start)) else { return };
+        let counter = self.temp(item.clone());
+        self.push(Statement::Assign(counter.into(), Rvalue::Use(start)));
+        let end = match range.end.as_deref() {
+            Some(end) => {
+                let Some(end) = self.operand(end) else { return };
+                let end = match end {
+                    Operand::Const(_) => end,
+                    end => self.assign_temp(item.clone(), Rvalue::Use(end)),
+                };
+                Some(end)
+            }
+            None => None,
+        };
+        self.scopes.push(HashMap::new());
+        let header = self.new_block();
+        let body = self.new_block();
+        let step = self.new_block();
+        let exit = self.new_block();
+        self.enter(header);
+        match &end {
+            Some(end) => {
+                let op = if range.is_inclusive { BinOp::Le } else { BinOp::Lt };
+                let cond = self.assign_temp(Type::Bool, Rvalue::Binary(op, counter.into(), end.clone()));
+                self.terminate(Terminator::Branch { cond, then_block: body, else_block: exit });
+                self.current = Some(body);
+            }
+            None => self.enter(body),
+        }
+        let pattern = self.declare(&for_stmt.pattern, item.clone());
+        self.push(Statement::Assign(pattern.into(), Rvalue::Use(counter.into())));
+        self.loops.push((exit, step));
+        self.block(&for_stmt.body);
+        self.loops.pop();
+        self.enter(step);
+        if let (Some(end), true) = (end, range.is_inclusive) {
+            let last = self.assign_temp(Type::Bool, Rvalue::Binary(BinOp::Eq, counter.into(), end));
+            let increment = self.new_block();
+            self.terminate(Terminator::Branch { cond: last, then_block: exit, else_block: increment });
+            self.current = Some(increment);
+        }
+        let one = Operand::Const(Constant::Int(1, item));
+        self.push(Statement::Assign(counter.into(), Rvalue::Binary(BinOp::Add, counter.into(), one)));
+        self.goto(header);
+        self.scopes.pop();
+        self.current = Some(exit);
+    }
+
+    /// The length of an array type, evaluated by the checker to a literal
+    fn array_len(&mut self, size: &Expression) -> i128 {
+        match size {
+            Expression::IntegerLiteral(lit) => literal_value(lit),
+            other => self.tc.layout().eval_const(other).unwrap_or(0),
+        }
+    }
+
+    // ========================================================================
+    // Expressions
+    // ========================================================================
+
+    /// Lower `expr` and convert it to `to`
+    fn convert(&mut self, expr: &Expression, to: &Type) -> Option<Operand> {
+        let from = self.type_of(expr);
+        let operand = self.operand(expr)?;
+        self.coerce(operand, &from, to)
+    }
+
+    /// Convert `operand` of type `from` to `to`, following the implicit
+    /// conversions the type checker allows
+    fn coerce(&mut self, operand: Operand, from: &Type, to: &Type) -> Option<Operand> {
+        if from == to {
+            return Some(operand);
+        }
+        let rvalue = match (from, to) {
+            (Type::Null, _) => return Some(Operand::Const(Constant::Null(to.clone()))),
+            (Type::Pointer { .. }, Type::Pointer { .. }) => Rvalue::Cast(operand, to.clone()),
+            (from, Type::Optional(inner)) => {
+                let inner = self.coerce(operand, from, inner)?;
+                Rvalue::Aggregate(AggregateKind::Some(to.clone()), vec![inner])
+            }
+            (from, Type::ErrorUnion { ok_type, err_type }) => {
+                if self.tc.assignable(from, ok_type) {
+                    let ok = self.coerce(operand, from, ok_type)?;
+                    Rvalue::Aggregate(AggregateKind::Ok(to.clone()), vec![ok])
+                } else {
+                    let conversion = self.tc.error_conversion(from, err_type)?;
+                    let err = self.convert_error(operand, &conversion, err_type)?;
+                    Rvalue::Aggregate(AggregateKind::Err(to.clone()), vec![err])
+                }
+            }
+            (Type::Array { size: Some(_), .. }, Type::Array { size: None, .. }) => {
+                let array = self.local_of(operand, from);
+                Rvalue::Unsize(array.into(), to.clone())
+            }
+            (from, to) if is_integer(from) && is_integer(to) => Rvalue::Cast(operand, to.clone()),
+            _ => {
+                self.diagnostics.push(
+                    Diagnostic::error(format!(
+                        "cannot convert `{}` to `{}`",
+                        fig_parser::format::format_type(from),
+                        fig_parser::format::format_type(to)
+                    ))
+                    .in_function(&self.name),
+                );
+                return None;
+            }
+        };
+        Some(self.assign_temp(to.clone(), rvalue))
+    }
+
+    /// Wrap an error value into the variant a conversion names
+    fn convert_error(&mut self, operand: Operand, conversion: &ErrorConversion, into: &Path) -> Option<Operand> {
+        let into = Type::Path(into.clone());
+        match conversion {
+            ErrorConversion::Identity => Some(operand),
+            ErrorConversion::Variant { variant } => Some(self.assign_temp(
+                into.clone(),
+                Rvalue::Aggregate(AggregateKind::Variant(into, variant.clone()), vec![operand]),
+            )),
+            ErrorConversion::Function { function } => {
+                let path = Path::with_generics(function.split("::").map(String::from).collect(), Vec::new());
+                let def = self.tc.items().lookup_function(&path)?;
+                let callee = self.callee(&Instance::new(def));
+                Some(self.assign_temp(into, Rvalue::Call(callee, vec![operand])))
+            }
+        }
+    }
+
+    /// End the block with a propagation edge for `value`, a `T ! E`, and
+    /// return the local holding the `T`
+    fn propagate(&mut self, value: Operand, ty: &Type, conversion: ErrorConversion) -> Option<Local> {
+        let Type::ErrorUnion { ok_type, .. } = ty else { return None };
+        let dest = self.temp((**ok_type).clone());
+        let next = self.new_block();
+        self.terminate(Terminator::Propagate { value, dest: dest.into(), conversion, next });
+        self.current = Some(next);
+        Some(dest)
+    }
+
+    /// The place an expression denotes. Expressions that are not places are
+    /// evaluated into a temporary, whose place is returned.
+    fn place(&mut self, expr: &Expression) -> Option<Place> {
+        match expr {
+            Expression::SelfValue => self.lookup("self").map(Place::from),
+            Expression::Path(path) if matches!(self.typed.path(expr), Some(PathTarget::Local)) => {
+                self.lookup(&path.segments[0]).map(Place::from)
+            }
+            Expression::Parenthesized(inner) => self.place(inner),
+            Expression::FieldAccess(access) if !self.is_len(access) => {
+                let mut object_type = self.type_of(&access.object);
+                let mut base = if access.is_propagating {
+                    let value = self.operand(&access.object)?;
+                    let conversion = self.typed.unwrap_field(access)?.clone();
+                    let ok = self.propagate(value, &object_type, conversion)?;
+                    let Type::ErrorUnion { ok_type, .. } = object_type else { return None };
+                    object_type = *ok_type;
+                    Place::from(ok)
+                } else {
+                    self.place(&access.object)?
+                };
+                if let Type::Pointer { element_type, .. } = object_type {
+                    base = base.project(Projection::Deref);
+                    object_type = *element_type;
+                }
+                let ty = self.type_of(expr);
+                let is_union = match &object_type {
+                    Type::Path(path) => matches!(self.tc.items().lookup_type(path), Some(TypeDef::Union(_))),
+                    _ => false,
+                };
+                let projection = if is_union {
+                    Projection::Variant(access.field.clone(), ty)
+                } else {
+                    Projection::Field(access.field.clone(), ty)
+                };
+                Some(base.project(projection))
+            }
+            Expression::Index(index) if !matches!(*index.index, Expression::Range(_)) => {
+                let base = self.place(&index.object)?;
+                let index_type = self.type_of(&index.index);
+                let position = self.operand(&index.index)?;
+                let position = self.local_of(position, &index_type);
+                Some(base.project(Projection::Index(position)))
+            }
+            Expression::UnaryOp(op) if op.op == ast::UnaryOperator::Dereference => {
+                Some(self.place(&op.operand)?.project(Projection::Deref))
+            }
+            _ => {
+                let ty = self.type_of(expr);
+                let operand = self.operand(expr)?;
+                Some(self.local_of(operand, &ty).into())
+            }
+        }
+    }
+
+    /// `object[start..end]`, with the bounds as `usize`s. An open start is
+    /// 0, an open end the length, and an inclusive end one past the bound.
+    fn subslice(&mut self, index: &ast::IndexExpr, ty: Type) -> Option<Operand> {
+        let Expression::Range(range) = index.index.as_ref() else { return None };
+        let object_type = self.type_of(&index.object);
+        let base = self.place(&index.object)?;
+        let start = match range.start.as_deref() {
+            Some(start) => self.convert(start, &Type::USize)?,
+            None => Operand::Const(Constant::Int(0, Type::USize)),
+        };
+        let end = match (range.end.as_deref(), &object_type) {
+            (Some(end), _) => {
+                let end = self.convert(end, &Type::USize)?;
+                if range.is_inclusive {
+                    let one = Operand::Const(Constant::Int(1, Type::USize));
+                    self.assign_temp(Type::USize, Rvalue::Binary(BinOp::Add, end, one))
+                } else {
+                    end
+                }
+            }
+            (None, Type::Array { size: Some(size), .. }) => {
+                Operand::Const(Constant::Int(self.array_len(size), Type::USize))
+            }
+            (None, _) => self.assign_temp(Type::USize, Rvalue::Len(base.clone())),
+        };
+        Some(self.assign_temp(ty, Rvalue::Subslice(base, start, end)))
+    }
+
+    /// Whether a field access reads the length of an array or slice
+    fn is_len(&self, access: &ast::FieldAccessExpr) -> bool {
+        let base = match self.typed.type_of(&access.object) {
+            Some(Type::Pointer { element_type, .. }) => element_type,
+            Some(other) => other,
+            None => return false,
+        };
+        access.field == "len" && !access.is_propagating && matches!(base, Type::Array { .. })
+    }
+
+    /// The value of an expression. Side effects are pushed to the current
+    /// block; the operand is valid right after them.
+    fn operand(&mut self, expr: &Expression) -> Option<Operand> {
+        let ty = self.type_of(expr);
+        let constant = |c| Some(Operand::Const(c));
+        match expr {
+            Expression::IntegerLiteral(lit) if is_float(&ty) => constant(Constant::Float(literal_value(lit) as f64, ty)),
+            Expression::IntegerLiteral(lit) => constant(Constant::Int(literal_value(lit), ty)),
+            Expression::FloatLiteral(lit) => constant(Constant::Float(lit.as_f64().unwrap_or(0.0), ty)),
+            Expression::BooleanLiteral(b) => constant(Constant::Bool(*b)),
+            Expression::CharLiteral(c) => constant(Constant::Int(c.chars().next().map_or(0, u32::from) as i128, ty)),
+            Expression::StringLiteral(text) => constant(Constant::Str(text.clone(), ty)),
+            Expression::OkLiteral => constant(Constant::Ok),
+            Expression::NullLiteral => constant(Constant::Null(ty)),
+            Expression::Path(_) | Expression::TypeAccess(_) | Expression::EnumVariant(_) => {
+                match self.typed.path(expr)?.clone() {
+                    PathTarget::Local => Some(Operand::Copy(self.place(expr)?)),
+                    PathTarget::Const(c) => {
+                        let from = self.type_of(&c.value);
+                        let value = self.operand(&c.value)?;
+                        self.coerce(value, &from, &ty)
+                    }
+                    PathTarget::EnumVariant { discriminant } => constant(Constant::Int(discriminant, ty)),
+                    PathTarget::UnionVariant { variant } => {
+                        Some(self.assign_temp(ty.clone(), Rvalue::Aggregate(AggregateKind::Variant(ty, variant), vec![])))
+                    }
+                }
+            }
+            Expression::StructLiteral(lit) => self.struct_literal(lit, ty, expr),
+            Expression::VariantLiteral(lit) => {
+                let payload = self.tc.fields(&ty).ok()?.into_iter().find(|(name, _)| *name == lit.variant)?.1;
+                let values = match &lit.payload {
+                    Some(value) if payload != Type::Ok => vec![self.convert(value, &payload)?],
+                    _ => Vec::new(),
+                };
+                let rvalue = Rvalue::Aggregate(AggregateKind::Variant(ty.clone(), lit.variant.clone()), values);
+                Some(self.assign_temp(ty, rvalue))
+            }
+            Expression::Index(index) if matches!(*index.index, Expression::Range(_)) => self.subslice(index, ty),
+            Expression::SelfValue | Expression::Index(_) => Some(Operand::Copy(self.place(expr)?)),
+            Expression::FieldAccess(access) if self.is_len(access) => {
+                let object_type = self.type_of(&access.object);
+                let (base, array_type) = match object_type {
+                    Type::Pointer { element_type, .. } => {
+                        (self.place(&access.object)?.project(Projection::Deref), *element_type)
+                    }
+                    other => (self.place(&access.object)?, other),
+                };
+                match array_type {
+                    Type::Array { size: Some(size), .. } => constant(Constant::Int(self.array_len(&size), Type::USize)),
+                    _ => Some(self.assign_temp(Type::USize, Rvalue::Len(base))),
+                }
+            }
+            Expression::FieldAccess(_) => Some(Operand::Copy(self.place(expr)?)),
+            Expression::ArrayLiteral(array) => {
+                let Type::Array { element_type, .. } = &ty else { return None };
+                let mut elements = Vec::with_capacity(array.elements.len());
+                for element in &array.elements {
+                    elements.push(self.convert(element, element_type)?);
+                }
+                Some(self.assign_temp(ty.clone(), Rvalue::Aggregate(AggregateKind::Array(ty), elements)))
+            }
+            Expression::InterpolatedString(_) => {
+                self.error("interpolated strings cannot be lowered yet", expr);
+                None
+            }
+            Expression::Lambda(_) => {
+                self.error("lambdas cannot be lowered yet", expr);
+                None
+            }
+            Expression::Range(_) => {
+                self.error("a range can only be lowered as a `for` loop or a slice", expr);
+                None
+            }
+            Expression::BinaryOp(op) => self.binary(op, ty),
+            Expression::UnaryOp(op) => self.unary(op, ty),
+            Expression::Call(call) => self.call(call, ty, expr),
+            Expression::Cast(cast) => {
+                let from = self.type_of(&cast.expr);
+                let value = self.operand(&cast.expr)?;
+                if from == ty {
+                    return Some(value);
+                }
+                Some(self.assign_temp(ty.clone(), Rvalue::Cast(value, ty)))
+            }
+            Expression::Sizeof(target) | Expression::Alignof(target) => {
+                let target = self.normalize(target, expr)?;
+                let layout = self.tc.layout();
+                let value = if matches!(expr, Expression::Sizeof(_)) {
+                    layout.size_of(&target)
+                } else {
+                    layout.align_of(&target)
+                };
+                match value {
+                    Ok(value) => constant(Constant::Int(value as i128, Type::USize)),
+                    Err(e) => {
+                        self.error(e.to_string(), expr);
+                        None
+                    }
+                }
+            }
+            Expression::Offsetof(offsetof) => {
+                let target = self.normalize(&offsetof.ty, expr)?;
+                match self.tc.layout().offset_of(&target, &offsetof.field) {
+                    Ok(value) => constant(Constant::Int(value as i128, Type::USize)),
+                    Err(e) => {
+                        self.error(e.to_string(), expr);
+                        None
+                    }
+                }
+            }
+            Expression::Parenthesized(inner) => self.operand(inner),
+            Expression::Assign(assign) => {
+                self.assign(assign)?;
+                constant(Constant::Ok)
+            }
+        }
+    }
+
+    fn normalize(&mut self, ty: &Type, expr: &Expression) -> Option<Type> {
+        let bindings = self.bindings.clone();
+        match self.tc.normalize(ty, &bindings) {
+            Ok(ty) => Some(ty),
+            Err(message) => {
+                self.error(message, expr);
+                None
+            }
+        }
+    }
+
+    fn binary(&mut self, op: &ast::BinaryOpExpr, ty: Type) -> Option<Operand> {
+        use ast::BinaryOperator as B;
+        if matches!(op.op, B::LogicalAnd | B::LogicalOr) {
+            // `a && b` is `if a { b } else { false }`, and `a || b` is `if a { true } else { b }`
+            let result = self.temp(Type::Bool);
+            let lhs = self.operand(&op.lhs)?;
+            let rhs_block = self.new_block();
+            let short_block = self.new_block();
+            let exit = self.new_block();
+            let (then_block, else_block) =
+                if op.op == B::LogicalAnd { (rhs_block, short_block) } else { (short_block, rhs_block) };
+            self.terminate(Terminator::Branch { cond: lhs, then_block, else_block });
+            self.current = Some(short_block);
+            let short = Operand::Const(Constant::Bool(op.op == B::LogicalOr));
+            self.push(Statement::Assign(result.into(), Rvalue::Use(short)));
+            self.goto(exit);
+            self.current = Some(rhs_block);
+            let rhs = self.operand(&op.rhs)?;
+            self.push(Statement::Assign(result.into(), Rvalue::Use(rhs)));
+            self.enter(exit);
+            return Some(result.into());
+        }
+
+        let lhs_type = self.type_of(&op.lhs);
+        let rhs_type = self.type_of(&op.rhs);
+        let lhs = self.operand(&op.lhs)?;
+        let rhs = self.operand(&op.rhs)?;
+        let bin_op = match op.op {
+            B::Add => BinOp::Add,
+            B::Subtract => BinOp::Sub,
+            B::Multiply => BinOp::Mul,
+            B::Divide => BinOp::Div,
+            B::Modulo => BinOp::Rem,
+            B::BitwiseAnd => BinOp::BitAnd,
+            B::BitwiseOr => BinOp::BitOr,
+            B::BitwiseXor => BinOp::BitXor,
+            B::ShiftLeft => BinOp::Shl,
+            B::ShiftRight => BinOp::Shr,
+            B::Equal => BinOp::Eq,
+            B::NotEqual => BinOp::Ne,
+            B::LessThan => BinOp::Lt,
+            B::GreaterThan => BinOp::Gt,
+            B::LessThanOrEqual => BinOp::Le,
+            B::GreaterThanOrEqual => BinOp::Ge,
+            B::LogicalAnd | B::LogicalOr => unreachable!("handled above"),
+        };
+        if matches!(bin_op, BinOp::Eq | BinOp::Ne) {
+            let is_null = |expr: &Expression, ty: &Type| matches!(expr, Expression::NullLiteral) || *ty == Type::Null;
+            let nullable = |ty: &Type| matches!(ty, Type::Optional(_) | Type::Pointer { .. });
+            let tested = if is_null(&op.rhs, &rhs_type) && nullable(&lhs_type) {
+                Some(lhs.clone())
+            } else if is_null(&op.lhs, &lhs_type) && nullable(&rhs_type) {
+                Some(rhs.clone())
+            } else {
+                None
+            };
+            if let Some(tested) = tested {
+                let empty = self.assign_temp(Type::Bool, Rvalue::IsNull(tested));
+                return Some(if bin_op == BinOp::Eq { empty } else { self.assign_temp(Type::Bool, Rvalue::Unary(UnOp::Not, empty)) });
+            }
+            if let Some(result) = self.compare_optional(bin_op, (lhs.clone(), &lhs_type), (rhs.clone(), &rhs_type)) {
+                return Some(result);
+            }
+        }
+        Some(self.assign_temp(ty, Rvalue::Binary(bin_op, lhs, rhs)))
+    }
+
+    /// `?T == T`: equal when the optional holds a value equal to the other side
+    fn compare_optional(&mut self, op: BinOp, lhs: (Operand, &Type), rhs: (Operand, &Type)) -> Option<Operand> {
+        let ((optional, optional_type), (other, _)) = match (lhs.1, rhs.1) {
+            (Type::Optional(_), Type::Optional(_)) => return None,
+            (Type::Optional(_), _) => (lhs, rhs),
+            (_, Type::Optional(_)) => (rhs, lhs),
+            _ => return None,
+        };
+        let optional = self.local_of(optional, optional_type);
+        let result = self.temp(Type::Bool);
+        let empty = self.assign_temp(Type::Bool, Rvalue::IsNull(optional.into()));
+        let some_block = self.new_block();
+        let none_block = self.new_block();
+        let exit = self.new_block();
+        self.terminate(Terminator::Branch { cond: empty, then_block: none_block, else_block: some_block });
+        self.current = Some(none_block);
+        self.push(Statement::Assign(result.into(), Rvalue::Use(Operand::Const(Constant::Bool(op == BinOp::Ne)))));
+        self.goto(exit);
+        self.current = Some(some_block);
+        let value = Operand::Copy(Place::from(optional).project(Projection::Payload));
+        self.push(Statement::Assign(result.into(), Rvalue::Binary(op, value, other)));
+        self.enter(exit);
+        Some(result.into())
+    }
+
+    fn unary(&mut self, op: &ast::UnaryOpExpr, ty: Type) -> Option<Operand> {
+        use ast::UnaryOperator as U;
+        let rvalue = match op.op {
+            U::Negate if matches!(op.operand.as_ref(), Expression::IntegerLiteral(_)) => {
+                let Expression::IntegerLiteral(lit) = op.operand.as_ref() else { unreachable!() };
+                let value = -literal_value(lit);
+                return Some(Operand::Const(if is_float(&ty) {
+                    Constant::Float(value as f64, ty)
+                } else {
+                    Constant::Int(value, ty)
+                }));
+            }
+            U::Negate => Rvalue::Unary(UnOp::Neg, self.operand(&op.operand)?),
+            U::Plus => return self.operand(&op.operand),
+            U::LogicalNot => Rvalue::Unary(UnOp::Not, self.operand(&op.operand)?),
+            U::BitwiseNot => Rvalue::Unary(UnOp::BitNot, self.operand(&op.operand)?),
+            U::AddressOf => Rvalue::AddressOf(self.place(&op.operand)?),
+            U::Dereference => return Some(Operand::Copy(self.place(&op.operand)?.project(Projection::Deref))),
+        };
+        Some(self.assign_temp(ty, rvalue))
+    }
+
+    fn assign(&mut self, assign: &ast::AssignExpr) -> Option<()> {
+        use ast::AssignOperator as A;
+        let lhs_type = self.type_of(&assign.lhs);
+        let place = self.place(&assign.lhs)?;
+        let op = match assign.op {
+            A::Assign => {
+                let value = self.convert(&assign.rhs, &lhs_type)?;
+                self.push(Statement::Assign(place, Rvalue::Use(value)));
+                return Some(());
+            }
+            A::AddAssign => BinOp::Add,
+            A::SubAssign => BinOp::Sub,
+            A::MulAssign => BinOp::Mul,
+            A::DivAssign => BinOp::Div,
+            A::ModAssign => BinOp::Rem,
+            A::BitAndAssign => BinOp::BitAnd,
+            A::BitOrAssign => BinOp::BitOr,
+            A::BitXorAssign => BinOp::BitXor,
+            A::ShlAssign => BinOp::Shl,
+            A::ShrAssign => BinOp::Shr,
+        };
+        let rhs = self.operand(&assign.rhs)?;
+        self.push(Statement::Assign(place.clone(), Rvalue::Binary(op, Operand::Copy(place), rhs)));
+        Some(())
+    }
+
+    // ========================================================================
+    // Calls
+    // ========================================================================
+
+    /// A struct literal. The values written are evaluated in source order,
+    /// then the base; fields not written are copied out of the base.
+    fn struct_literal(&mut self, lit: &ast::StructLiteralExpr, ty: Type, expr: &Expression) -> Option<Operand> {
+        let fields = match self.tc.fields(&ty) {
+            Ok(fields) => fields,
+            Err(message) => {
+                self.error(message, expr);
+                return None;
+            }
+        };
+        let mut values: Vec<Option<Operand>> = vec![None; fields.len()];
+        for (i, init) in lit.fields.iter().enumerate() {
+            let index = match &init.name {
+                Some(name) => fields.iter().position(|(field, _)| field == name)?,
+                None => i,
+            };
+            values[index] = Some(self.convert(&init.value, &fields[index].1)?);
+        }
+        let base = match &lit.base {
+            Some(base) => Some(self.place(base)?),
+            None => None,
+        };
+        let mut operands = Vec::with_capacity(fields.len());
+        for ((name, field_type), value) in fields.into_iter().zip(values) {
+            operands.push(match (value, &base) {
+                (Some(value), _) => value,
+                (None, Some(base)) => Operand::Copy(base.clone().project(Projection::Field(name, field_type))),
+                (None, None) => return None,
+            });
+        }
+        Some(self.assign_temp(ty.clone(), Rvalue::Aggregate(AggregateKind::Struct(ty), operands)))
+    }
+
+    fn call(&mut self, call: &ast::CallExpr, ty: Type, expr: &Expression) -> Option<Operand> {
+        let target = self.typed.call(call)?.clone();
+        let (rvalue, result_type) = match target {
+            CallTarget::Function { instance, self_arg } => {
+                let signature = match self.tc.signature(&instance) {
+                    Ok(signature) => signature,
+                    Err(diagnostic) => {
+                        self.diagnostics.push(diagnostic);
+                        return None;
+                    }
+                };
+                let mut args = Vec::with_capacity(call.args.len() + 1);
+                let mut explicit = call.args.iter();
+                if let Some(self_type) = &signature.self_type {
+                    match (self_arg, call.callee.as_ref()) {
+                        (Some(self_arg), Expression::FieldAccess(access)) => {
+                            args.push(self.receiver(access, self_arg, self_type)?);
+                        }
+                        _ => {
+                            let receiver = explicit.next()?;
+                            args.push(self.convert(receiver, self_type)?);
+                        }
+                    }
+                }
+                for ((_, param_type), arg) in signature.params.iter().zip(explicit) {
+                    args.push(self.convert(arg, param_type)?);
+                }
+                (Rvalue::Call(self.callee(&instance), args), signature.return_type)
+            }
+            CallTarget::Construct(struct_type) => {
+                let fields = match self.tc.fields(&struct_type) {
+                    Ok(fields) => fields,
+                    Err(message) => {
+                        self.error(message, expr);
+                        return None;
+                    }
+                };
+                let mut values = Vec::with_capacity(fields.len());
+                for ((_, field_type), arg) in fields.iter().zip(&call.args) {
+                    values.push(self.convert(arg, field_type)?);
+                }
+                (Rvalue::Aggregate(AggregateKind::Struct(struct_type.clone()), values), struct_type)
+            }
+            CallTarget::Variant { union, variant } => {
+                let payload = self.tc.fields(&union).ok()?.into_iter().find(|(name, _)| *name == variant)?.1;
+                let values = match call.args.first() {
+                    Some(arg) if payload != Type::Ok => vec![self.convert(arg, &payload)?],
+                    _ => Vec::new(),
+                };
+                (Rvalue::Aggregate(AggregateKind::Variant(union.clone(), variant), values), union)
+            }
+            CallTarget::Builtin(builtin) => {
+                let mut args = Vec::with_capacity(call.args.len());
+                for arg in &call.args {
+                    args.push(match (builtin, arg) {
+                        (Builtin::Assert, arg) => self.convert(arg, &Type::Bool)?,
+                        (_, arg) => self.operand(arg)?,
+                    });
+                }
+                (Rvalue::Call(Callee::Builtin(builtin), args), ty.clone())
+            }
+            CallTarget::Value(_) => {
+                self.error("calls through function values cannot be lowered yet", expr);
+                return None;
+            }
+        };
+        if result_type == Type::Ok {
+            self.push(Statement::Eval(rvalue));
+            return Some(Operand::Const(Constant::Ok));
+        }
+        let value = self.assign_temp(result_type.clone(), rvalue);
+        if !call.is_propagating {
+            return Some(value);
+        }
+        let conversion = self.typed.unwrap_call(call)?.clone();
+        self.propagate(value, &result_type, conversion).map(Operand::from)
+    }
+
+    /// The `self` argument of a method call through `.`, as a `self_type`
+    fn receiver(&mut self, access: &ast::FieldAccessExpr, self_arg: SelfArg, self_type: &Type) -> Option<Operand> {
+        let mut object_type = self.type_of(&access.object);
+        let object = if access.is_propagating {
+            let value = self.operand(&access.object)?;
+            let conversion = self.typed.unwrap_field(access)?.clone();
+            let ok = self.propagate(value, &object_type, conversion)?;
+            let Type::ErrorUnion { ok_type, .. } = object_type else { return None };
+            object_type = *ok_type;
+            Place::from(ok)
+        } else {
+            self.place(&access.object)?
+        };
+        self.self_arg(object, &object_type, self_arg, self_type)
+    }
+
+    /// Pass the value at `object` as the `self` of a method
+    fn self_arg(&mut self, object: Place, object_type: &Type, self_arg: SelfArg, self_type: &Type) -> Option<Operand> {
+        match self_arg {
+            SelfArg::Value | SelfArg::Pointer => self.coerce(Operand::Copy(object), object_type, self_type),
+            SelfArg::AddressOf => Some(self.assign_temp(self_type.clone(), Rvalue::AddressOf(object))),
+            SelfArg::Deref => Some(Operand::Copy(object.project(Projection::Deref))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    fn lower_src(src: &str) -> Program {
+        let sf = parse(src);
+        let items = ItemTable::from_source_file(&sf);
+        let program = lower_program(&items, Target::X86_64).unwrap_or_else(|diags| panic!("{:?}", diags));
+        let errors = crate::verify(&program);
+        assert!(errors.is_empty(), "{:?}\n{}", errors, program);
+        program
+    }
+
+    #[test]
+    fn test_statements_keep_their_source_spans() {
+        let src = "func f(a: i32) -> i32\n    let b = a * 2\n    return b + 1\n";
+        let program = lower_src(src);
+        let block = &program.function("f").unwrap().blocks[0];
+        let text = |span: Span| src[span.start..span.end].trim_end();
+        assert_eq!(text(block.span(0).unwrap()), "let b = a * 2");
+        assert_eq!(text(block.terminator_span.unwrap()), "return b + 1");
+    }
+
+    #[test]
+    fn test_dump_of_generic_instance() {
+        let program = lower_src(
+            "\
+func[T] largest(a: T, b: T) -> T
+    if a > b
+        return a
+    return b
+
+func main() -> u8
+    return largest(3u8, 4u8)
+",
+        );
+        let body = program.function("largest[u8]").unwrap();
+        assert_eq!(
+            body.to_string(),
+            "\
+fn largest[u8](_0: u8, _1: u8) -> u8
+    let _0: u8  // a
+    let _1: u8  // b
+    let _2: bool
+
+    bb0:
+        _2 = gt _0, _1
+        branch _2, bb1, bb2
+
+    bb1:
+        return _0
+
+    bb2:
+        return _1
+"
+        );
+    }
+
+    #[test]
+    fn test_short_circuit_and_loops_become_blocks() {
+        let program = lower_src(
+            "\
+func count(xs: [i32; 4], limit: i32) -> i32
+    mut n: i32 = 0
+    for x in xs
+        if x < 0 || x > limit
+            continue
+        n += 1
+    return n
+",
+        );
+        let body = program.function("count").unwrap();
+        let dump = body.to_string();
+        // `||` branches around its right operand; `continue` targets the step block
+        assert!(dump.contains("lt _"), "{}", dump);
+        assert!(body.blocks.len() >= 7, "{}", dump);
+        assert!(dump.contains("= add _"), "{}", dump);
+        assert!(!dump.contains("unreachable"), "{}", dump);
+    }
+
+    #[test]
+    fn test_propagation_is_a_terminator() {
+        let program = lower_src(
+            "\
+union Error
+    Parse: u8
+
+func digit(c: u8) -> u8 ! Error
+    if c < 48
+        return Error::Parse(c)
+    return c - 48
+
+func! twice(c: u8) -> u8 ! Error
+    return digit!(c) * 2
+",
+        );
+        let body = program.function("twice").unwrap();
+        assert!(
+            body.blocks.iter().any(|b| matches!(b.terminator, Terminator::Propagate { .. })),
+            "{}",
+            body
+        );
+        let dump = program.function("digit").unwrap().to_string();
+        assert!(dump.contains("err(") || dump.contains("err "), "{}", dump);
+        assert!(dump.contains("ok"), "{}", dump);
+    }
+
+    #[test]
+    fn test_statements_after_return_are_dropped() {
+        let program = lower_src(
+            "\
+func f(x: i32) -> i32
+    if x > 0
+        return 1
+    else
+        return 2
+    return 3
+",
+        );
+        let dump = program.function("f").unwrap().to_string();
+        assert!(!dump.contains("3_i32"), "{}", dump);
+    }
+}
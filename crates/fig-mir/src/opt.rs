@@ -0,0 +1,879 @@
+//! Optimisation passes over MIR
+//!
+//! Each pass rewrites a body in place and reports whether it changed
+//! anything, so it can be run and tested on its own. [`optimize`] runs the
+//! inliner once, then the body passes until none of them changes anything.
+//!
+//! The passes keep Fig's checked semantics. Constant folding leaves any
+//! operation that would trap for run time, so the program still stops with
+//! the same error, and dead-code removal only drops statements that can
+//! neither trap nor have an effect.
+
+use std::collections::HashMap;
+
+use fig_parser::ast::{Span, Type};
+use fig_sema::layout::{Target, integer_bounds, wrap_integer};
+
+use crate::ir::*;
+
+/// Bodies of one block with at most this many statements are inlined
+/// without an `#inline` annotation
+pub const INLINE_THRESHOLD: usize = 8;
+
+/// Run every pass over a program
+pub fn optimize(program: &mut Program, target: Target) {
+    inline_calls(program);
+    for body in &mut program.functions {
+        loop {
+            let mut changed = fold_constants(body, target);
+            changed |= fold_branches(body);
+            changed |= remove_unreachable_blocks(body);
+            changed |= merge_blocks(body);
+            changed |= remove_dead_code(body);
+            if !changed {
+                break;
+            }
+        }
+    }
+}
+
+// ============================================================================
+// Constant folding
+// ============================================================================
+
+/// Evaluate operations on constants, and replace reads of locals assigned a
+/// constant exactly once with that constant
+pub fn fold_constants(body: &mut Body, target: Target) -> bool {
+    let mut changed = false;
+    loop {
+        let mut folded = propagate_constants(body);
+        for block in &mut body.blocks {
+            for statement in &mut block.statements {
+                if let Statement::Assign(_, rvalue) = statement
+                    && let Some(constant) = fold(rvalue, target)
+                {
+                    *rvalue = Rvalue::Use(Operand::Const(constant));
+                    folded = true;
+                }
+            }
+        }
+        if !folded {
+            return changed;
+        }
+        changed = true;
+    }
+}
+
+/// Locals whose only assignment stores a constant. Parameters, and locals
+/// that are partly assigned or have their address taken, can change in ways
+/// a single assignment does not show.
+fn constant_locals(body: &Body) -> Vec<Option<Constant>> {
+    let mut assignments = vec![0; body.locals.len()];
+    let mut values = vec![None; body.locals.len()];
+    let mut pinned = vec![false; body.locals.len()];
+    for param in body.params() {
+        pinned[param.index()] = true;
+    }
+    for block in &body.blocks {
+        for statement in &block.statements {
+            let rvalue = match statement {
+                Statement::Assign(place, rvalue) if place.projection.is_empty() => {
+                    assignments[place.local.index()] += 1;
+                    if let Rvalue::Use(Operand::Const(constant)) = rvalue {
+                        values[place.local.index()] = Some(constant.clone());
+                    }
+                    rvalue
+                }
+                Statement::Assign(place, rvalue) => {
+                    pinned[place.local.index()] = true;
+                    rvalue
+                }
+                Statement::Eval(rvalue) => rvalue,
+            };
+            if let Rvalue::AddressOf(place) | Rvalue::Unsize(place, _) | Rvalue::Subslice(place, ..) = rvalue {
+                pinned[place.local.index()] = true;
+            }
+        }
+        if let Terminator::Propagate { dest, .. } = &block.terminator {
+            pinned[dest.local.index()] = true;
+        }
+    }
+    values
+        .into_iter()
+        .enumerate()
+        .map(|(i, value)| value.filter(|_| assignments[i] == 1 && !pinned[i]))
+        .collect()
+}
+
+fn propagate_constants(body: &mut Body) -> bool {
+    let constants = constant_locals(body);
+    let mut changed = false;
+    for block in &mut body.blocks {
+        for operand in operands_mut(block) {
+            if let Operand::Copy(place) = operand
+                && place.projection.is_empty()
+                && let Some(constant) = &constants[place.local.index()]
+            {
+                *operand = Operand::Const(constant.clone());
+                changed = true;
+            }
+        }
+    }
+    changed
+}
+
+/// The constant an rvalue evaluates to, or `None` when it is not constant
+/// or would trap
+fn fold(rvalue: &Rvalue, target: Target) -> Option<Constant> {
+    match rvalue {
+        Rvalue::Binary(op, Operand::Const(lhs), Operand::Const(rhs)) => fold_binary(*op, lhs, rhs, target),
+        Rvalue::Unary(op, Operand::Const(operand)) => fold_unary(*op, operand, target),
+        Rvalue::Cast(Operand::Const(operand), ty) => fold_cast(operand, ty, target),
+        _ => None,
+    }
+}
+
+fn fold_binary(op: BinOp, lhs: &Constant, rhs: &Constant, target: Target) -> Option<Constant> {
+    match (lhs, rhs) {
+        (Constant::Bool(a), Constant::Bool(b)) => match op {
+            BinOp::Eq => Some(Constant::Bool(a == b)),
+            BinOp::Ne | BinOp::BitXor => Some(Constant::Bool(a != b)),
+            BinOp::BitAnd => Some(Constant::Bool(a & b)),
+            BinOp::BitOr => Some(Constant::Bool(a | b)),
+            _ => None,
+        },
+        (Constant::Int(a, ty), Constant::Int(b, rhs_type)) => {
+            let (a, b) = (*a, *b);
+            if op.is_comparison() {
+                if ty != rhs_type {
+                    return None;
+                }
+                return Some(Constant::Bool(match op {
+                    BinOp::Eq => a == b,
+                    BinOp::Ne => a != b,
+                    BinOp::Lt => a < b,
+                    BinOp::Le => a <= b,
+                    BinOp::Gt => a > b,
+                    _ => a >= b,
+                }));
+            }
+            let (bits, signed) = target.integer_info(ty)?;
+            let value = match op {
+                BinOp::Shl | BinOp::Shr => {
+                    target.integer_info(rhs_type)?;
+                    if b < 0 || b >= i128::from(bits) {
+                        return None;
+                    }
+                    match op {
+                        BinOp::Shl => wrap_integer(a << b, bits, signed),
+                        _ => a >> b,
+                    }
+                }
+                _ if ty != rhs_type => return None,
+                BinOp::Add => a.checked_add(b)?,
+                BinOp::Sub => a.checked_sub(b)?,
+                BinOp::Mul => a.checked_mul(b)?,
+                BinOp::Div => a.checked_div(b)?,
+                BinOp::Rem => a.checked_rem(b)?,
+                BinOp::BitAnd => a & b,
+                BinOp::BitOr => a | b,
+                BinOp::BitXor => a ^ b,
+                _ => return None,
+            };
+            let (min, max) = integer_bounds(bits, signed);
+            (min..=max).contains(&value).then(|| Constant::Int(value, ty.clone()))
+        }
+        _ => None,
+    }
+}
+
+fn fold_unary(op: UnOp, operand: &Constant, target: Target) -> Option<Constant> {
+    match (op, operand) {
+        (UnOp::Not, Constant::Bool(a)) => Some(Constant::Bool(!a)),
+        (UnOp::Neg, Constant::Int(a, ty)) => {
+            let (min, max) = target.integer_info(ty).map(|(bits, signed)| integer_bounds(bits, signed))?;
+            (min..=max).contains(&-a).then(|| Constant::Int(-a, ty.clone()))
+        }
+        (UnOp::BitNot, Constant::Int(a, ty)) => {
+            let (bits, signed) = target.integer_info(ty)?;
+            Some(Constant::Int(wrap_integer(!a, bits, signed), ty.clone()))
+        }
+        _ => None,
+    }
+}
+
+/// Casts between integers and `bool`. Casts to an enum check the value and
+/// are left alone, as are float casts, whose rounding is the backend's.
+fn fold_cast(operand: &Constant, to: &Type, target: Target) -> Option<Constant> {
+    let value = match operand {
+        Constant::Int(value, from) => {
+            target.integer_info(from)?;
+            *value
+        }
+        Constant::Bool(value) => i128::from(*value),
+        _ => return None,
+    };
+    if *to == Type::Bool {
+        return Some(Constant::Bool(value != 0));
+    }
+    let (bits, signed) = target.integer_info(to)?;
+    Some(Constant::Int(wrap_integer(value, bits, signed), to.clone()))
+}
+
+// ============================================================================
+// Control flow
+// ============================================================================
+
+/// Replace branches on a constant condition with a jump to the block taken
+pub fn fold_branches(body: &mut Body) -> bool {
+    let mut changed = false;
+    for block in &mut body.blocks {
+        if let Terminator::Branch { cond: Operand::Const(Constant::Bool(cond)), then_block, else_block } =
+            block.terminator
+        {
+            block.terminator = Terminator::Goto(if cond { then_block } else { else_block });
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// [`Body::remove_unreachable_blocks`], as a pass
+pub fn remove_unreachable_blocks(body: &mut Body) -> bool {
+    let count = body.blocks.len();
+    body.remove_unreachable_blocks();
+    body.blocks.len() != count
+}
+
+/// Append each block to its only predecessor when that predecessor jumps
+/// straight to it
+pub fn merge_blocks(body: &mut Body) -> bool {
+    let mut predecessors = vec![0; body.blocks.len()];
+    for block in &body.blocks {
+        for target in block.terminator.successors() {
+            predecessors[target.index()] += 1;
+        }
+    }
+    let mut changed = false;
+    for i in 0..body.blocks.len() {
+        while let Terminator::Goto(target) = body.blocks[i].terminator {
+            if target.index() == i || target.index() == 0 || predecessors[target.index()] != 1 {
+                break;
+            }
+            let unreachable = BasicBlock::new(Vec::new(), Terminator::Unreachable);
+            let next = std::mem::replace(&mut body.blocks[target.index()], unreachable);
+            predecessors[target.index()] = 0;
+            append(&mut body.blocks[i], next);
+            changed = true;
+        }
+    }
+    if changed {
+        body.remove_unreachable_blocks();
+    }
+    changed
+}
+
+/// Move `next`'s statements and terminator to the end of `block`
+fn append(block: &mut BasicBlock, next: BasicBlock) {
+    let known = |block: &BasicBlock| block.spans.len() == block.statements.len();
+    if known(block) && known(&next) {
+        block.spans.extend(next.spans);
+    } else {
+        block.spans.clear();
+    }
+    block.statements.extend(next.statements);
+    block.terminator = next.terminator;
+    block.terminator_span = next.terminator_span;
+}
+
+// ============================================================================
+// Dead code
+// ============================================================================
+
+/// Drop assignments to locals that are never read, when computing the value
+/// can neither trap nor have an effect, then drop the locals no statement
+/// mentions any more
+pub fn remove_dead_code(body: &mut Body) -> bool {
+    let mut changed = false;
+    loop {
+        let read = read_locals(body);
+        let mut removed = false;
+        for block in &mut body.blocks {
+            let known = block.spans.len() == block.statements.len();
+            let statements = std::mem::take(&mut block.statements);
+            let spans = std::mem::take(&mut block.spans);
+            for (i, statement) in statements.into_iter().enumerate() {
+                let dead = match &statement {
+                    Statement::Assign(place, rvalue) => {
+                        place.projection.is_empty() && !read[place.local.index()] && is_pure(rvalue)
+                    }
+                    Statement::Eval(rvalue) => is_pure(rvalue),
+                };
+                if dead {
+                    removed = true;
+                    continue;
+                }
+                block.statements.push(statement);
+                if known {
+                    block.spans.push(spans[i]);
+                }
+            }
+        }
+        if !removed {
+            break;
+        }
+        changed = true;
+    }
+    changed | remove_unused_locals(body)
+}
+
+/// Which locals some statement or terminator reads. Assigning a whole local
+/// does not read it; assigning part of one counts as a read, so the rest of
+/// the value is kept.
+fn read_locals(body: &mut Body) -> Vec<bool> {
+    let mut read = vec![false; body.locals.len()];
+    for block in &mut body.blocks {
+        let assigned: Vec<Local> = block
+            .statements
+            .iter()
+            .filter_map(|statement| match statement {
+                Statement::Assign(place, _) if place.projection.is_empty() => Some(place.local),
+                _ => None,
+            })
+            .collect();
+        let mut uses = vec![0; body.locals.len()];
+        for place in places_mut(block) {
+            for local in place_locals(place) {
+                uses[local.index()] += 1;
+            }
+        }
+        // Each whole-local assignment mentions its local once without reading it
+        for local in assigned {
+            uses[local.index()] -= 1;
+        }
+        for (local, count) in uses.into_iter().enumerate() {
+            read[local] |= count > 0;
+        }
+    }
+    read
+}
+
+/// Renumber the locals past the parameters, dropping those never mentioned
+fn remove_unused_locals(body: &mut Body) -> bool {
+    let mut used = vec![false; body.locals.len()];
+    for param in body.params() {
+        used[param.index()] = true;
+    }
+    for block in &mut body.blocks {
+        for place in places_mut(block) {
+            for local in place_locals(place) {
+                used[local.index()] = true;
+            }
+        }
+    }
+    if used.iter().all(|&used| used) {
+        return false;
+    }
+    let mut renumbered = Vec::with_capacity(used.len());
+    let mut next = 0;
+    for &is_used in &used {
+        renumbered.push(Local(next));
+        next += u32::from(is_used);
+    }
+    let locals = std::mem::take(&mut body.locals);
+    body.locals = locals.into_iter().zip(&used).filter(|(_, used)| **used).map(|(decl, _)| decl).collect();
+    for block in &mut body.blocks {
+        rename_locals(block, |local| renumbered[local.index()]);
+    }
+    true
+}
+
+/// A place whose projections cannot trap
+fn is_safe_place(place: &Place) -> bool {
+    place.projection.iter().all(|projection| {
+        matches!(projection, Projection::Field(..) | Projection::Payload | Projection::OkValue | Projection::ErrValue)
+    })
+}
+
+fn is_safe_operand(operand: &Operand) -> bool {
+    match operand {
+        Operand::Copy(place) => is_safe_place(place),
+        Operand::Const(_) => true,
+    }
+}
+
+/// Whether an rvalue can be dropped when its value is not needed: it calls
+/// nothing and cannot trap
+fn is_pure(rvalue: &Rvalue) -> bool {
+    match rvalue {
+        Rvalue::Use(operand) | Rvalue::IsNull(operand) | Rvalue::IsErr(operand) => is_safe_operand(operand),
+        Rvalue::Binary(op, lhs, rhs) => {
+            (op.is_comparison() || matches!(op, BinOp::BitAnd | BinOp::BitOr | BinOp::BitXor))
+                && is_safe_operand(lhs)
+                && is_safe_operand(rhs)
+        }
+        Rvalue::Unary(op, operand) => *op != UnOp::Neg && is_safe_operand(operand),
+        Rvalue::Cast(operand, ty) => !matches!(ty, Type::Path(_)) && is_safe_operand(operand),
+        Rvalue::AddressOf(place) | Rvalue::Unsize(place, _) | Rvalue::Len(place) => is_safe_place(place),
+        Rvalue::Aggregate(_, operands) => operands.iter().all(is_safe_operand),
+        Rvalue::Subslice(..) | Rvalue::Call(..) => false,
+    }
+}
+
+// ============================================================================
+// Inlining
+// ============================================================================
+
+/// Replace calls of small or `#inline` functions with a copy of their body,
+/// returning how many calls were inlined. Calls are inlined one level deep:
+/// the copied bodies are those from before the pass, and calls inside them
+/// stay calls. Functions marked `#noinline`, and those that propagate an
+/// error out of themselves, are never inlined.
+pub fn inline_calls(program: &mut Program) -> usize {
+    let inlinable: HashMap<String, Body> = program
+        .functions
+        .iter()
+        .filter(|body| is_inlinable(body))
+        .map(|body| (body.name.clone(), body.clone()))
+        .collect();
+    program.functions.iter_mut().map(|body| inline_into(body, &inlinable)).sum()
+}
+
+fn is_inlinable(body: &Body) -> bool {
+    if body.blocks.iter().any(|block| matches!(block.terminator, Terminator::Propagate { .. })) {
+        return false;
+    }
+    match body.inline {
+        Inline::Always => true,
+        Inline::Never => false,
+        Inline::Default => body.blocks.len() == 1 && body.blocks[0].statements.len() <= INLINE_THRESHOLD,
+    }
+}
+
+fn inline_into(body: &mut Body, inlinable: &HashMap<String, Body>) -> usize {
+    // The blocks left to scan for calls. Inlining a call splits its block,
+    // and the rest of the block is scanned in its place.
+    let mut pending: Vec<BlockId> = (0..body.blocks.len() as u32).map(BlockId).collect();
+    let mut count = 0;
+    while let Some(block) = pending.pop() {
+        let call = body.blocks[block.index()].statements.iter().enumerate().find_map(|(i, statement)| {
+            let (Statement::Assign(_, Rvalue::Call(Callee::Function(name), _))
+            | Statement::Eval(Rvalue::Call(Callee::Function(name), _))) = statement
+            else {
+                return None;
+            };
+            let callee = inlinable.get(name).filter(|callee| callee.name != body.name)?;
+            Some((i, callee))
+        });
+        if let Some((index, callee)) = call {
+            pending.push(inline_call(body, block, index, callee));
+            count += 1;
+        }
+    }
+    count
+}
+
+/// Inline the call at `index` of `block`, returning the block holding the
+/// statements after it
+fn inline_call(body: &mut Body, block: BlockId, index: usize, callee: &Body) -> BlockId {
+    let local_offset = body.locals.len() as u32;
+    let block_offset = body.blocks.len() as u32;
+    let continuation = BlockId(block_offset + callee.blocks.len() as u32);
+    body.locals.extend(callee.locals.iter().cloned());
+
+    let caller = &mut body.blocks[block.index()];
+    let span = caller.span(index);
+    let mut rest = caller.statements.split_off(index);
+    let rest_spans = if caller.spans.len() > index { caller.spans.split_off(index).split_off(1) } else { Vec::new() };
+    let (dest, args) = match rest.remove(0) {
+        Statement::Assign(dest, Rvalue::Call(_, args)) => (Some(dest), args),
+        Statement::Eval(Rvalue::Call(_, args)) => (None, args),
+        _ => unreachable!("inlined statement is not a call"),
+    };
+    for (param, arg) in callee.params().zip(args) {
+        let param = Local(param.0 + local_offset);
+        push(caller, Statement::Assign(param.into(), Rvalue::Use(arg)), span);
+    }
+    let after = BasicBlock {
+        statements: rest,
+        terminator: std::mem::replace(&mut caller.terminator, Terminator::Goto(BlockId(block_offset))),
+        spans: rest_spans,
+        terminator_span: std::mem::replace(&mut caller.terminator_span, span),
+    };
+
+    for callee_block in &callee.blocks {
+        let mut copy = callee_block.clone();
+        rename_locals(&mut copy, |local| Local(local.0 + local_offset));
+        for target in copy.terminator.successors_mut() {
+            target.0 += block_offset;
+        }
+        if let Terminator::Return(value) = &copy.terminator {
+            let value = value.clone();
+            if let Some(dest) = &dest {
+                let span = copy.terminator_span;
+                push(&mut copy, Statement::Assign(dest.clone(), Rvalue::Use(value)), span);
+            }
+            copy.terminator = Terminator::Goto(continuation);
+        }
+        body.blocks.push(copy);
+    }
+    body.blocks.push(after);
+    continuation
+}
+
+/// Add a statement to the end of a block, keeping its spans in step
+fn push(block: &mut BasicBlock, statement: Statement, span: Option<Span>) {
+    let known = block.spans.len() == block.statements.len();
+    match span {
+        Some(span) if known => block.spans.push(span),
+        _ => block.spans.clear(),
+    }
+    block.statements.push(statement);
+}
+
+// ============================================================================
+// Visiting places
+// ============================================================================
+
+fn rvalue_operands_mut(rvalue: &mut Rvalue) -> Vec<&mut Operand> {
+    match rvalue {
+        Rvalue::Use(operand)
+        | Rvalue::Unary(_, operand)
+        | Rvalue::Cast(operand, _)
+        | Rvalue::IsNull(operand)
+        | Rvalue::IsErr(operand) => vec![operand],
+        Rvalue::Binary(_, lhs, rhs) | Rvalue::Subslice(_, lhs, rhs) => vec![lhs, rhs],
+        Rvalue::Aggregate(_, operands) | Rvalue::Call(_, operands) => operands.iter_mut().collect(),
+        Rvalue::AddressOf(_) | Rvalue::Unsize(..) | Rvalue::Len(_) => Vec::new(),
+    }
+}
+
+/// Every operand of a block's statements and terminator
+fn operands_mut(block: &mut BasicBlock) -> Vec<&mut Operand> {
+    let mut operands = Vec::new();
+    for statement in &mut block.statements {
+        let (Statement::Assign(_, rvalue) | Statement::Eval(rvalue)) = statement;
+        operands.extend(rvalue_operands_mut(rvalue));
+    }
+    match &mut block.terminator {
+        Terminator::Branch { cond: operand, .. }
+        | Terminator::Return(operand)
+        | Terminator::Propagate { value: operand, .. } => operands.push(operand),
+        Terminator::Goto(_) | Terminator::Unreachable => {}
+    }
+    operands
+}
+
+/// Every place of a block, assigned or read
+fn places_mut(block: &mut BasicBlock) -> Vec<&mut Place> {
+    let mut places = Vec::new();
+    for statement in &mut block.statements {
+        let rvalue = match statement {
+            Statement::Assign(place, rvalue) => {
+                places.push(place);
+                rvalue
+            }
+            Statement::Eval(rvalue) => rvalue,
+        };
+        let operands = match rvalue {
+            Rvalue::AddressOf(place) | Rvalue::Unsize(place, _) | Rvalue::Len(place) => {
+                places.push(place);
+                continue;
+            }
+            Rvalue::Subslice(place, start, end) => {
+                places.push(place);
+                vec![start, end]
+            }
+            _ => rvalue_operands_mut(rvalue),
+        };
+        places.extend(operands.into_iter().filter_map(|operand| match operand {
+            Operand::Copy(place) => Some(place),
+            Operand::Const(_) => None,
+        }));
+    }
+    match &mut block.terminator {
+        Terminator::Branch { cond: operand, .. } | Terminator::Return(operand) => {
+            if let Operand::Copy(place) = operand {
+                places.push(place);
+            }
+        }
+        Terminator::Propagate { value, dest, .. } => {
+            if let Operand::Copy(place) = value {
+                places.push(place);
+            }
+            places.push(dest);
+        }
+        Terminator::Goto(_) | Terminator::Unreachable => {}
+    }
+    places
+}
+
+/// The locals a place mentions: its base and any index locals
+fn place_locals(place: &Place) -> impl Iterator<Item = Local> + '_ {
+    std::iter::once(place.local).chain(place.projection.iter().filter_map(|projection| match projection {
+        Projection::Index(index) => Some(*index),
+        _ => None,
+    }))
+}
+
+fn rename_locals(block: &mut BasicBlock, rename: impl Fn(Local) -> Local) {
+    for place in places_mut(block) {
+        place.local = rename(place.local);
+        for projection in &mut place.projection {
+            if let Projection::Index(index) = projection {
+                *index = rename(*index);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+    use fig_sema::items::ItemTable;
+
+    fn lower_src(src: &str) -> Program {
+        let sf = parse(src);
+        let items = ItemTable::from_source_file(&sf);
+        crate::lower_program(&items, Target::X86_64).unwrap_or_else(|diags| panic!("{:?}", diags))
+    }
+
+    fn check(program: &Program) {
+        let errors = crate::verify(program);
+        assert!(errors.is_empty(), "{:?}\n{}", errors, program);
+    }
+
+    fn function<'p>(program: &'p mut Program, name: &str) -> &'p mut Body {
+        program.functions.iter_mut().find(|body| body.name == name).unwrap()
+    }
+
+    #[test]
+    fn test_fold_constants() {
+        let mut program = lower_src(
+            "\
+func f() -> i32
+    let x = 6 * 7
+    return x << 2u8
+
+func overflow() -> u8
+    let y = 255u8
+    return y + 1
+",
+        );
+        assert!(fold_constants(function(&mut program, "f"), Target::X86_64));
+        assert_eq!(
+            function(&mut program, "f").to_string(),
+            "\
+fn f() -> i32
+    let _0: i32
+    let _1: i32  // x
+    let _2: i32
+
+    bb0:
+        _0 = 42_i32
+        _1 = 42_i32
+        _2 = 168_i32
+        return 168_i32
+"
+        );
+        // The addition overflows, so it is left to trap at run time
+        fold_constants(function(&mut program, "overflow"), Target::X86_64);
+        assert!(function(&mut program, "overflow").to_string().contains("_1 = add 255_u8, 1_u8\n"));
+        check(&program);
+    }
+
+    #[test]
+    fn test_fold_wraps_only_where_the_vm_does() {
+        let int = |value: i128, ty: Type| Constant::Int(value, ty);
+        let target = Target::X86_64;
+        assert_eq!(fold_unary(UnOp::BitNot, &int(0, Type::U8), target), Some(int(255, Type::U8)));
+        assert_eq!(fold_unary(UnOp::Neg, &int(-128, Type::I8), target), None);
+        assert_eq!(fold_binary(BinOp::Shl, &int(-1, Type::I8), &int(7, Type::U8), target), Some(int(-128, Type::I8)));
+        assert_eq!(fold_binary(BinOp::Shr, &int(1, Type::I8), &int(8, Type::U8), target), None);
+        assert_eq!(fold_binary(BinOp::Div, &int(1, Type::I32), &int(0, Type::I32), target), None);
+        assert_eq!(fold_binary(BinOp::Rem, &int(-7, Type::I32), &int(2, Type::I32), target), Some(int(-1, Type::I32)));
+        assert_eq!(fold_cast(&int(300, Type::I32), &Type::U8, target), Some(int(44, Type::U8)));
+        assert_eq!(fold_cast(&int(2, Type::I32), &Type::Bool, target), Some(Constant::Bool(true)));
+    }
+
+    #[test]
+    fn test_fold_branches_and_remove_unreachable_blocks() {
+        let mut program = lower_src("func f(a: i32) -> i32\n    if 1 < 2\n        return a\n    return 0\n");
+        let body = function(&mut program, "f");
+        assert!(!fold_branches(body));
+        fold_constants(body, Target::X86_64);
+        assert!(fold_branches(body));
+        assert_eq!(
+            body.to_string(),
+            "\
+fn f(_0: i32) -> i32
+    let _0: i32  // a
+    let _1: bool
+
+    bb0:
+        _1 = true
+        goto bb1
+
+    bb1:
+        return _0
+
+    bb2:
+        return 0_i32
+"
+        );
+        assert!(remove_unreachable_blocks(body));
+        assert!(!remove_unreachable_blocks(body));
+        assert_eq!(body.blocks.len(), 2);
+        assert!(merge_blocks(body));
+        assert_eq!(
+            body.to_string(),
+            "\
+fn f(_0: i32) -> i32
+    let _0: i32  // a
+    let _1: bool
+
+    bb0:
+        _1 = true
+        return _0
+"
+        );
+        check(&program);
+    }
+
+    #[test]
+    fn test_remove_dead_code() {
+        let mut program = lower_src(
+            "\
+func f(a: i32) -> i32
+    let unused = a < 3
+    let product = a * 2
+    return a
+",
+        );
+        let body = function(&mut program, "f");
+        assert!(remove_dead_code(body));
+        // The multiplication could overflow, so it stays even though its
+        // result is never read
+        assert_eq!(
+            body.to_string(),
+            "\
+fn f(_0: i32) -> i32
+    let _0: i32  // a
+    let _1: i32
+
+    bb0:
+        _1 = mul _0, 2_i32
+        return _0
+"
+        );
+        assert!(!remove_dead_code(body));
+        check(&program);
+    }
+
+    #[test]
+    fn test_inline_respects_annotations() {
+        let mut program = lower_src(
+            "\
+#inline
+func twice(x: i32) -> i32
+    if x > 100
+        return x
+    return x * 2
+
+#noinline
+func inc(x: i32) -> i32
+    return x + 1
+
+func f(a: i32) -> i32
+    return inc(twice(a))
+",
+        );
+        assert_eq!(inline_calls(&mut program), 1);
+        assert_eq!(
+            function(&mut program, "f").to_string(),
+            "\
+fn f(_0: i32) -> i32
+    let _0: i32  // a
+    let _1: i32
+    let _2: i32
+    let _3: i32  // x
+    let _4: bool
+    let _5: i32
+
+    bb0:
+        _3 = _0
+        goto bb1
+
+    bb1:
+        _4 = gt _3, 100_i32
+        branch _4, bb2, bb3
+
+    bb2:
+        _1 = _3
+        goto bb4
+
+    bb3:
+        _5 = mul _3, 2_i32
+        _1 = _5
+        goto bb4
+
+    bb4:
+        _2 = call inc(_1)
+        return _2
+"
+        );
+        assert!(function(&mut program, "inc").to_string().starts_with("#noinline\nfn inc("));
+        check(&program);
+    }
+
+    #[test]
+    fn test_inline_small_functions_only() {
+        let mut program = lower_src(
+            "\
+func square(x: i32) -> i32
+    return x * x
+
+func big(x: i32) -> i32
+    if x > 0
+        return x
+    return -x
+
+func f() -> i32
+    return big(square(3))
+",
+        );
+        assert_eq!(inline_calls(&mut program), 1);
+        let f = function(&mut program, "f").to_string();
+        assert!(!f.contains("call square"), "{}", f);
+        assert!(f.contains("call big"), "{}", f);
+        check(&program);
+    }
+
+    #[test]
+    fn test_optimize() {
+        let mut program = lower_src(
+            "\
+func square(x: i32) -> i32
+    return x * x
+
+func f() -> i32
+    let n = square(3) + 1
+    if n == 10
+        return n
+    return 0
+",
+        );
+        optimize(&mut program, Target::X86_64);
+        assert_eq!(
+            function(&mut program, "f").to_string(),
+            "\
+fn f() -> i32
+
+    bb0:
+        return 10_i32
+"
+        );
+        check(&program);
+    }
+}
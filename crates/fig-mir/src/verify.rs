@@ -0,0 +1,567 @@
+//! Structural and type checks on lowered MIR
+//!
+//! The verifier catches lowering bugs before a backend sees them. It checks
+//! that every block and local a body refers to exists, that projections
+//! apply to the types they are applied to, that each assignment stores a
+//! value of the place's type, that branches test a `bool`, that returns and
+//! calls match the signatures involved, and that no local is read before it
+//! is assigned on every path to the read.
+
+use fig_parser::ast::Type;
+use fig_parser::format::format_type;
+use fig_sema::diagnostics::Diagnostic;
+use fig_sema::typeck::is_integer;
+
+use crate::ir::*;
+
+/// Check every function of a program, returning one diagnostic per problem
+pub fn verify(program: &Program) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    for body in &program.functions {
+        let mut verifier = Verifier { program, body, block: BlockId(0), diagnostics: Vec::new() };
+        verifier.verify();
+        diagnostics.extend(verifier.diagnostics);
+    }
+    diagnostics
+}
+
+/// Pointer types are compatible when they point to the same type; the
+/// nullable and `mut` flags only matter to the type checker
+fn same_pointee(a: &Type, b: &Type) -> bool {
+    match (a, b) {
+        (Type::Pointer { element_type: a, .. }, Type::Pointer { element_type: b, .. }) => a == b,
+        _ => false,
+    }
+}
+
+struct Verifier<'p> {
+    program: &'p Program,
+    body: &'p Body,
+    /// The block being checked, for messages
+    block: BlockId,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Verifier<'_> {
+    fn error(&mut self, message: impl Into<String>) {
+        self.diagnostics
+            .push(Diagnostic::error(format!("{}: {}", self.block, message.into())).in_function(&self.body.name));
+    }
+
+    fn verify(&mut self) {
+        if self.body.blocks.is_empty() {
+            self.diagnostics.push(Diagnostic::error("function has no entry block").in_function(&self.body.name));
+            return;
+        }
+        if self.body.arg_count > self.body.locals.len() {
+            self.diagnostics.push(
+                Diagnostic::error(format!(
+                    "{} parameters declared, but only {} locals",
+                    self.body.arg_count,
+                    self.body.locals.len()
+                ))
+                .in_function(&self.body.name),
+            );
+            return;
+        }
+        for (index, block) in self.body.blocks.iter().enumerate() {
+            self.block = BlockId(index as u32);
+            for statement in &block.statements {
+                self.statement(statement);
+            }
+            self.terminator(&block.terminator);
+        }
+        // The use-before-assignment check needs every local and target in range
+        if self.diagnostics.is_empty() {
+            self.check_initialized();
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Assign(place, rvalue) => {
+                let place_type = self.place(place);
+                let Some(value_type) = self.rvalue(rvalue) else { return };
+                let Some(place_type) = place_type else { return };
+                let matches = match rvalue {
+                    // `&x` may be stored as any pointer to the type of `x`
+                    Rvalue::AddressOf(_) => same_pointee(&place_type, &value_type),
+                    // Builtins are checked by the type checker only
+                    Rvalue::Call(Callee::Builtin(_), _) => true,
+                    _ => place_type == value_type,
+                };
+                if !matches {
+                    self.error(format!(
+                        "cannot store `{}` in `{}` of type `{}`",
+                        format_type(&value_type),
+                        place,
+                        format_type(&place_type)
+                    ));
+                }
+            }
+            Statement::Eval(rvalue) => {
+                self.rvalue(rvalue);
+            }
+        }
+    }
+
+    fn terminator(&mut self, terminator: &Terminator) {
+        for target in terminator.successors() {
+            if target.index() >= self.body.blocks.len() {
+                self.error(format!("jump to missing block {}", target));
+            }
+        }
+        match terminator {
+            Terminator::Goto(_) | Terminator::Unreachable => {}
+            Terminator::Branch { cond, .. } => {
+                if let Some(ty) = self.operand(cond)
+                    && ty != Type::Bool
+                {
+                    self.error(format!("branch on `{}`, not `bool`", format_type(&ty)));
+                }
+            }
+            Terminator::Return(value) => {
+                if let Some(ty) = self.operand(value)
+                    && ty != self.body.return_type
+                {
+                    self.error(format!(
+                        "returns `{}` from a function returning `{}`",
+                        format_type(&ty),
+                        format_type(&self.body.return_type)
+                    ));
+                }
+            }
+            Terminator::Propagate { value, dest, .. } => {
+                let dest_type = self.place(dest);
+                let Some(value_type) = self.operand(value) else { return };
+                let Type::ErrorUnion { ok_type, .. } = &value_type else {
+                    self.error(format!("propagates `{}`, which is not an error union", format_type(&value_type)));
+                    return;
+                };
+                if !matches!(self.body.return_type, Type::ErrorUnion { .. }) {
+                    self.error("propagates an error out of a function that does not return an error union");
+                }
+                if let Some(dest_type) = dest_type
+                    && dest_type != **ok_type
+                {
+                    self.error(format!(
+                        "stores the success value `{}` in `{}` of type `{}`",
+                        format_type(ok_type),
+                        dest,
+                        format_type(&dest_type)
+                    ));
+                }
+            }
+        }
+    }
+
+    fn local(&mut self, local: Local) -> Option<Type> {
+        match self.body.locals.get(local.index()) {
+            Some(decl) => Some(decl.ty.clone()),
+            None => {
+                self.error(format!("use of undeclared local {}", local));
+                None
+            }
+        }
+    }
+
+    fn place(&mut self, place: &Place) -> Option<Type> {
+        let mut ty = self.local(place.local)?;
+        for projection in &place.projection {
+            if let Projection::Index(index) = projection {
+                let index_type = self.local(*index)?;
+                if !is_integer(&index_type) {
+                    self.error(format!("index {} of type `{}` is not an integer", index, format_type(&index_type)));
+                }
+            }
+            match projection.apply(&ty) {
+                Some(projected) => ty = projected,
+                None => {
+                    self.error(format!("invalid projection in `{}` of a `{}`", place, format_type(&ty)));
+                    return None;
+                }
+            }
+        }
+        Some(ty)
+    }
+
+    fn operand(&mut self, operand: &Operand) -> Option<Type> {
+        match operand {
+            Operand::Copy(place) => self.place(place),
+            Operand::Const(constant) => Some(constant.ty()),
+        }
+    }
+
+    /// The type of an rvalue, after checking its operands
+    fn rvalue(&mut self, rvalue: &Rvalue) -> Option<Type> {
+        match rvalue {
+            Rvalue::Use(operand) => self.operand(operand),
+            Rvalue::Binary(op, lhs, rhs) => {
+                let (lhs, rhs) = (self.operand(lhs)?, self.operand(rhs)?);
+                self.binary(*op, lhs, rhs)
+            }
+            Rvalue::Unary(op, operand) => {
+                let ty = self.operand(operand)?;
+                let valid = match op {
+                    UnOp::Not => ty == Type::Bool,
+                    UnOp::Neg => is_integer(&ty) || matches!(ty, Type::F32 | Type::F64),
+                    UnOp::BitNot => is_integer(&ty),
+                };
+                if !valid {
+                    self.error(format!("`{}` cannot be applied to `{}`", rvalue, format_type(&ty)));
+                }
+                Some(ty)
+            }
+            Rvalue::Cast(operand, ty) => {
+                self.operand(operand)?;
+                Some(ty.clone())
+            }
+            Rvalue::AddressOf(place) => {
+                let ty = self.place(place)?;
+                Some(Type::Pointer { nullable: false, mutable: true, element_type: Box::new(ty) })
+            }
+            Rvalue::Aggregate(kind, operands) => self.aggregate(kind, operands),
+            Rvalue::Unsize(place, ty) => {
+                let array = self.place(place)?;
+                match (&array, ty) {
+                    (Type::Array { element_type: a, size: Some(_) }, Type::Array { element_type: b, size: None })
+                        if a == b => {}
+                    _ => self.error(format!("cannot unsize `{}` to `{}`", format_type(&array), format_type(ty))),
+                }
+                Some(ty.clone())
+            }
+            Rvalue::Len(place) => {
+                let ty = self.place(place)?;
+                if !matches!(ty, Type::Array { .. }) {
+                    self.error(format!("length of `{}`, which is not a slice", format_type(&ty)));
+                }
+                Some(Type::USize)
+            }
+            Rvalue::Subslice(place, start, end) => {
+                let ty = self.place(place)?;
+                for bound in [start, end] {
+                    let bound_type = self.operand(bound)?;
+                    if bound_type != Type::USize {
+                        self.error(format!("slice bound of type `{}`, not `usize`", format_type(&bound_type)));
+                    }
+                }
+                match ty {
+                    Type::Array { element_type, .. } => Some(Type::Array { element_type, size: None }),
+                    other => {
+                        self.error(format!("subslice of `{}`, which is not an array or slice", format_type(&other)));
+                        None
+                    }
+                }
+            }
+            Rvalue::IsNull(operand) => {
+                let ty = self.operand(operand)?;
+                if !matches!(ty, Type::Optional(_) | Type::Pointer { .. }) {
+                    self.error(format!("null test of `{}`", format_type(&ty)));
+                }
+                Some(Type::Bool)
+            }
+            Rvalue::IsErr(operand) => {
+                let ty = self.operand(operand)?;
+                if !matches!(ty, Type::ErrorUnion { .. }) {
+                    self.error(format!("error test of `{}`", format_type(&ty)));
+                }
+                Some(Type::Bool)
+            }
+            Rvalue::Call(callee, args) => self.call(callee, args),
+        }
+    }
+
+    fn binary(&mut self, op: BinOp, lhs: Type, rhs: Type) -> Option<Type> {
+        let mismatch = |this: &mut Self| {
+            this.error(format!(
+                "`{}` cannot be applied to `{}` and `{}`",
+                op.name(),
+                format_type(&lhs),
+                format_type(&rhs)
+            ));
+        };
+        if op.is_comparison() {
+            if lhs != rhs && !same_pointee(&lhs, &rhs) {
+                mismatch(self);
+            }
+            return Some(Type::Bool);
+        }
+        match (op, &lhs, &rhs) {
+            (BinOp::Shl | BinOp::Shr, _, _) if is_integer(&lhs) && is_integer(&rhs) => {}
+            (BinOp::Add | BinOp::Sub, Type::Pointer { .. }, _) if is_integer(&rhs) => {}
+            (BinOp::Sub, Type::Pointer { .. }, Type::Pointer { .. }) if same_pointee(&lhs, &rhs) => {
+                return Some(Type::ISize);
+            }
+            _ if lhs == rhs => {}
+            _ => mismatch(self),
+        }
+        Some(lhs)
+    }
+
+    fn aggregate(&mut self, kind: &AggregateKind, operands: &[Operand]) -> Option<Type> {
+        let mut types = Vec::with_capacity(operands.len());
+        for operand in operands {
+            types.push(self.operand(operand)?);
+        }
+        let ty = kind.ty().clone();
+        let expected = match (kind, &ty) {
+            (AggregateKind::Array(_), Type::Array { element_type, .. }) => Some((**element_type).clone()),
+            (AggregateKind::Some(_), Type::Optional(inner)) => Some((**inner).clone()),
+            (AggregateKind::Ok(_), Type::ErrorUnion { ok_type, .. }) => Some((**ok_type).clone()),
+            (AggregateKind::Err(_), Type::ErrorUnion { err_type, .. }) => Some(Type::Path(err_type.clone())),
+            (AggregateKind::Struct(_) | AggregateKind::Variant(..), Type::Path(_)) => None,
+            _ => {
+                self.error(format!("cannot build a `{}` with `{}`", format_type(&ty), Rvalue::Aggregate(kind.clone(), operands.to_vec())));
+                return Some(ty);
+            }
+        };
+        let single = matches!(kind, AggregateKind::Some(_) | AggregateKind::Ok(_) | AggregateKind::Err(_));
+        if single && types.len() != 1 {
+            self.error(format!("`{}` takes one operand, not {}", format_type(&ty), types.len()));
+        }
+        if let Some(expected) = expected {
+            for found in types.iter().filter(|found| **found != expected) {
+                self.error(format!("`{}` element of a `{}`", format_type(found), format_type(&ty)));
+            }
+        }
+        Some(ty)
+    }
+
+    fn call(&mut self, callee: &Callee, args: &[Operand]) -> Option<Type> {
+        let mut arg_types = Vec::with_capacity(args.len());
+        for arg in args {
+            arg_types.push(self.operand(arg));
+        }
+        if let Callee::Builtin(_) = callee {
+            // Typed by whatever the result is stored into
+            return Some(Type::Ok);
+        }
+        let Some((params, return_type)) = self.program.signature(callee) else {
+            self.error(format!("call of unknown function `{}`", callee));
+            return None;
+        };
+        if params.len() != args.len() {
+            self.error(format!("`{}` takes {} arguments, but {} were passed", callee, params.len(), args.len()));
+            return Some(return_type);
+        }
+        for (index, (param, arg)) in params.iter().zip(arg_types).enumerate() {
+            if let Some(arg) = arg
+                && arg != *param
+            {
+                self.error(format!(
+                    "argument {} of `{}` is `{}`, not `{}`",
+                    index + 1,
+                    callee,
+                    format_type(&arg),
+                    format_type(param)
+                ));
+            }
+        }
+        Some(return_type)
+    }
+
+    // ========================================================================
+    // Definite assignment
+    // ========================================================================
+
+    /// Forward dataflow over the locals assigned on every path into each block
+    fn check_initialized(&mut self) {
+        let body = self.body;
+        let locals = body.locals.len();
+        let mut entry_states: Vec<Option<Vec<bool>>> = vec![None; body.blocks.len()];
+        let mut params = vec![false; locals];
+        params[..body.arg_count].fill(true);
+        entry_states[0] = Some(params);
+        let mut worklist = vec![BlockId(0)];
+        while let Some(id) = worklist.pop() {
+            let mut state = entry_states[id.index()].clone().expect("queued blocks have a state");
+            let block = body.block(id);
+            for statement in &block.statements {
+                if let Statement::Assign(place, _) = statement
+                    && place.projection.is_empty()
+                {
+                    state[place.local.index()] = true;
+                }
+            }
+            if let Terminator::Propagate { dest, .. } = &block.terminator
+                && dest.projection.is_empty()
+            {
+                state[dest.local.index()] = true;
+            }
+            for successor in block.terminator.successors() {
+                let changed = match &mut entry_states[successor.index()] {
+                    Some(existing) => {
+                        let mut changed = false;
+                        for (known, now) in existing.iter_mut().zip(&state) {
+                            if *known && !now {
+                                *known = false;
+                                changed = true;
+                            }
+                        }
+                        changed
+                    }
+                    slot @ None => {
+                        *slot = Some(state.clone());
+                        true
+                    }
+                };
+                if changed && !worklist.contains(&successor) {
+                    worklist.push(successor);
+                }
+            }
+        }
+
+        for (index, block) in body.blocks.iter().enumerate() {
+            let Some(mut state) = entry_states[index].clone() else { continue };
+            self.block = BlockId(index as u32);
+            for statement in &block.statements {
+                let (place, rvalue) = match statement {
+                    Statement::Assign(place, rvalue) => (Some(place), rvalue),
+                    Statement::Eval(rvalue) => (None, rvalue),
+                };
+                self.rvalue_reads(rvalue, &state);
+                if let Some(place) = place {
+                    if place.projection.is_empty() {
+                        state[place.local.index()] = true;
+                    } else {
+                        // Storing into part of a local needs the rest of it
+                        self.place_reads(place, &state);
+                    }
+                }
+            }
+            match &block.terminator {
+                Terminator::Branch { cond: operand, .. }
+                | Terminator::Return(operand)
+                | Terminator::Propagate { value: operand, .. } => self.operand_reads(operand, &state),
+                Terminator::Goto(_) | Terminator::Unreachable => {}
+            }
+        }
+    }
+
+    fn place_reads(&mut self, place: &Place, state: &[bool]) {
+        let indices = place.projection.iter().filter_map(|p| match p {
+            Projection::Index(local) => Some(*local),
+            _ => None,
+        });
+        for local in std::iter::once(place.local).chain(indices) {
+            if !state[local.index()] {
+                self.error(format!("{} is read before it is assigned", local));
+            }
+        }
+    }
+
+    fn operand_reads(&mut self, operand: &Operand, state: &[bool]) {
+        if let Operand::Copy(place) = operand {
+            self.place_reads(place, state);
+        }
+    }
+
+    fn rvalue_reads(&mut self, rvalue: &Rvalue, state: &[bool]) {
+        match rvalue {
+            Rvalue::Use(operand) | Rvalue::Unary(_, operand) | Rvalue::Cast(operand, _) => {
+                self.operand_reads(operand, state)
+            }
+            Rvalue::IsNull(operand) | Rvalue::IsErr(operand) => self.operand_reads(operand, state),
+            Rvalue::Binary(_, lhs, rhs) => {
+                self.operand_reads(lhs, state);
+                self.operand_reads(rhs, state);
+            }
+            Rvalue::AddressOf(place) | Rvalue::Unsize(place, _) | Rvalue::Len(place) => {
+                self.place_reads(place, state)
+            }
+            Rvalue::Subslice(place, start, end) => {
+                self.place_reads(place, state);
+                self.operand_reads(start, state);
+                self.operand_reads(end, state);
+            }
+            Rvalue::Aggregate(_, operands) | Rvalue::Call(_, operands) => {
+                for operand in operands {
+                    self.operand_reads(operand, state);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn body(locals: Vec<Type>, arg_count: usize, blocks: Vec<BasicBlock>) -> Program {
+        Program {
+            functions: vec![Body {
+                name: "f".to_string(),
+                exported: false,
+                arg_count,
+                locals: locals.into_iter().map(|ty| LocalDecl { ty, name: None }).collect(),
+                return_type: Type::I32,
+                blocks,
+                inline: Inline::Default,
+            }],
+            externs: Vec::new(),
+        }
+    }
+
+    fn messages(program: &Program) -> Vec<String> {
+        verify(program).iter().map(|d| d.message.clone()).collect()
+    }
+
+    #[test]
+    fn test_accepts_well_formed_body() {
+        let program = body(
+            vec![Type::I32, Type::Bool],
+            1,
+            vec![
+                BasicBlock::new(
+                    vec![Statement::Assign(
+                        Local(1).into(),
+                        Rvalue::Binary(BinOp::Gt, Local(0).into(), Operand::Const(Constant::Int(0, Type::I32))),
+                    )],
+                    Terminator::Branch { cond: Local(1).into(), then_block: BlockId(1), else_block: BlockId(1) },
+                ),
+                BasicBlock::new(Vec::new(), Terminator::Return(Local(0).into())),
+            ],
+        );
+        assert!(messages(&program).is_empty(), "{:?}", messages(&program));
+    }
+
+    #[test]
+    fn test_reports_type_and_target_errors() {
+        let program = body(
+            vec![Type::I32, Type::U8],
+            1,
+            vec![BasicBlock::new(
+                vec![Statement::Assign(Local(1).into(), Rvalue::Use(Local(0).into()))],
+                Terminator::Branch { cond: Local(0).into(), then_block: BlockId(3), else_block: BlockId(0) },
+            )],
+        );
+        let messages = messages(&program);
+        assert!(messages.iter().any(|m| m.contains("cannot store `i32` in `_1` of type `u8`")), "{:?}", messages);
+        assert!(messages.iter().any(|m| m.contains("jump to missing block bb3")), "{:?}", messages);
+        assert!(messages.iter().any(|m| m.contains("branch on `i32`")), "{:?}", messages);
+    }
+
+    #[test]
+    fn test_reports_read_before_assignment_on_some_path() {
+        // _1 is only assigned on the `then` path, then read after the join
+        let program = body(
+            vec![Type::Bool, Type::I32],
+            1,
+            vec![
+                BasicBlock::new(
+                    Vec::new(),
+                    Terminator::Branch { cond: Local(0).into(), then_block: BlockId(1), else_block: BlockId(2) },
+                ),
+                BasicBlock::new(
+                    vec![Statement::Assign(
+                        Local(1).into(),
+                        Rvalue::Use(Operand::Const(Constant::Int(1, Type::I32))),
+                    )],
+                    Terminator::Goto(BlockId(2)),
+                ),
+                BasicBlock::new(Vec::new(), Terminator::Return(Local(1).into())),
+            ],
+        );
+        assert_eq!(messages(&program), vec!["bb2: _1 is read before it is assigned".to_string()]);
+    }
+}
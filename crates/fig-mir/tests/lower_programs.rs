@@ -0,0 +1,41 @@
+// Lowers every program in tests/run/ to MIR and runs the verifier over the
+// result. Behaviour is checked by the backends' own run tests; this catches
+// malformed MIR before any backend has to deal with it. The optimised
+// program has to verify too.
+
+use std::path::Path;
+
+use fig_mir::{lower_program, opt, verify};
+use fig_parser::{Lexer, SourceFileParser};
+use fig_sema::construct::resolve_construction;
+use fig_sema::items::ItemTable;
+use fig_sema::layout::Target;
+use fig_test_support::check_programs;
+
+fn lower(path: &Path) -> Result<(), String> {
+    let src = std::fs::read_to_string(path).unwrap();
+    let sf = SourceFileParser::new()
+        .parse(Lexer::new(&src))
+        .map_err(|e| format!("parse error: {:?}", e))?;
+    let sf = resolve_construction(sf);
+    let items = ItemTable::from_source_file(&sf);
+    let join = |diagnostics: Vec<fig_sema::diagnostics::Diagnostic>| {
+        diagnostics.iter().map(|d| d.to_string()).collect::<Vec<_>>().join("\n")
+    };
+    let mut program = lower_program(&items, Target::X86_64).map_err(join)?;
+    let errors = verify(&program);
+    if !errors.is_empty() {
+        return Err(format!("{}\n{}", join(errors), program));
+    }
+    opt::optimize(&mut program, Target::X86_64);
+    let errors = verify(&program);
+    if !errors.is_empty() {
+        return Err(format!("after optimisation: {}\n{}", join(errors), program));
+    }
+    Ok(())
+}
+
+#[test]
+fn run_programs_lower_to_valid_mir() {
+    check_programs(lower);
+}
@@ -0,0 +1,27 @@
+//! Packages of Fig source files
+//!
+//! A package is a directory with a `fig.toml` [`manifest`] naming it, the
+//! directories its sources are in, and the local packages it depends on.
+//! [`load`] finds every `.fig` file of a package and its dependencies and
+//! parses them into one [`CompilationUnit`], whose
+//! [`source_file`](CompilationUnit::source_file) the rest of the compiler
+//! takes like that of a single file.
+//!
+//! A file's path decides its namespace: `src/shapes/circle.fig` in the
+//! package `geo` holds `geo::shapes::circle`, and `src/lib.fig` holds `geo`
+//! itself. The exception is the `main.fig` of the package being built,
+//! which is at the top level with the program's `main`. A file that
+//! declares a different namespace with a bare `namespace` line gets a
+//! warning, or an error when the namespace belongs to another package.
+//!
+//! ```ignore
+//! let unit = load(Path::new("examples/app"))?;
+//! let file = unit.source_file();
+//! let items = ItemTable::from_source_file(&file);
+//! ```
+
+mod load;
+pub mod manifest;
+
+pub use load::{CompilationUnit, LoadedFile, Package, load, parse, syntax_error};
+pub use manifest::{MANIFEST, Manifest};
@@ -0,0 +1,363 @@
+//! Finding, reading and parsing the files of a package and its dependencies
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::path::{Path, PathBuf};
+
+use fig_parser::ast::{self, NamespaceDeclaration, NamespaceItem, SourceFile, Visibility};
+use fig_parser::format::format_path;
+use fig_parser::{Lexer, SourceFileParser};
+use fig_sema::diagnostics::{Diagnostic, line_column};
+use fig_sema::items::ItemTable;
+use lalrpop_util::ParseError;
+
+use crate::manifest::{MANIFEST, Manifest, is_namespace_name};
+
+/// A package, with the directory its manifest is in
+#[derive(Debug, Clone)]
+pub struct Package {
+    pub name: String,
+    pub root: PathBuf,
+    pub manifest: Manifest,
+    /// Positions in [`CompilationUnit::packages`]
+    pub dependencies: Vec<usize>,
+}
+
+/// A source file of a package
+#[derive(Debug, Clone)]
+pub struct LoadedFile {
+    pub path: PathBuf,
+    /// Position in [`CompilationUnit::packages`]
+    pub package: usize,
+    /// The namespace its path puts it in, e.g. `geo::shapes` for
+    /// `src/shapes.fig` in the package `geo`
+    pub namespace: Vec<String>,
+    pub text: String,
+    pub file: SourceFile,
+}
+
+/// Every file of a package and of the packages it depends on
+#[derive(Debug, Clone, Default)]
+pub struct CompilationUnit {
+    /// Dependencies before the packages that depend on them, so the package
+    /// that was loaded is last
+    pub packages: Vec<Package>,
+    /// Files by package, then path
+    pub files: Vec<LoadedFile>,
+    pub warnings: Vec<Diagnostic>,
+}
+
+impl LoadedFile {
+    /// The file's items, after a declaration of the namespace its path puts
+    /// it in. A namespace the file declares itself takes over from there.
+    pub fn scoped(&self) -> SourceFile {
+        let declaration = NamespaceDeclaration {
+            visibility: Visibility::Default,
+            annotations: Vec::new(),
+            name: ast::Path::with_generics(self.namespace.clone(), Vec::new()),
+        };
+        let mut items = vec![NamespaceItem::NamespaceDeclaration(declaration)];
+        items.extend(self.file.items.iter().cloned());
+        SourceFile::new(items)
+    }
+}
+
+impl CompilationUnit {
+    /// All the files as one source file, each in its namespace. Items of
+    /// the same namespace from different files are merged by the item table
+    /// like those of one file.
+    pub fn source_file(&self) -> SourceFile {
+        SourceFile::new(self.files.iter().flat_map(|file| file.scoped().items).collect())
+    }
+
+    /// The package a namespace path belongs to: the one named by its first
+    /// segment, or the loaded package for the top level
+    pub fn owner(&self, namespace: &[String]) -> Option<usize> {
+        match namespace.first() {
+            Some(first) => self.packages.iter().position(|package| package.name == *first),
+            None => self.packages.len().checked_sub(1),
+        }
+    }
+}
+
+/// Load the package whose manifest is in `dir`, its dependencies, and
+/// every file of each. The files are checked for namespaces that do not
+/// match their paths and for items defined more than once.
+pub fn load(dir: &Path) -> Result<CompilationUnit, Vec<Diagnostic>> {
+    let mut loader = Loader::default();
+    loader.package(dir);
+    if loader.errors.is_empty() {
+        loader.check_namespaces();
+        loader.check_duplicates();
+    }
+    if loader.errors.is_empty() { Ok(loader.unit) } else { Err(loader.errors) }
+}
+
+/// Parse `text`, read from `path`, placing any syntax error by line and column
+pub fn parse(path: &Path, text: &str) -> Result<SourceFile, Diagnostic> {
+    SourceFileParser::new().parse(Lexer::new(text)).map_err(|e| syntax_error(path, text, &e))
+}
+
+/// A parse error as a diagnostic, located by `path:line:column` where the
+/// error has a position in `text`
+pub fn syntax_error<T: Debug, E: Debug>(path: &Path, text: &str, error: &ParseError<usize, T, E>) -> Diagnostic {
+    let message = format!("{:?}", error);
+    match first_offset(error) {
+        Some(offset) => {
+            let (line, column) = line_column(text, offset);
+            Diagnostic::error(format!("{}:{}:{}: {}", path.display(), line, column, message))
+        }
+        None => Diagnostic::error(format!("{}: {}", path.display(), message)),
+    }
+}
+
+fn first_offset<T, E>(error: &ParseError<usize, T, E>) -> Option<usize> {
+    use ParseError::*;
+    match error {
+        InvalidToken { location } | UnrecognizedEof { location, .. } => Some(*location),
+        UnrecognizedToken { token: (start, _, _), .. } | ExtraToken { token: (start, _, _) } => Some(*start),
+        User { .. } => None,
+    }
+}
+
+#[derive(Default)]
+struct Loader {
+    unit: CompilationUnit,
+    /// The canonical directory of each package, as in `unit.packages`
+    directories: Vec<PathBuf>,
+    /// The packages being loaded, outermost first, to find cycles
+    loading: Vec<(PathBuf, String)>,
+    errors: Vec<Diagnostic>,
+}
+
+impl Loader {
+    /// Load the package in `dir` after its dependencies, once however many
+    /// packages depend on it
+    fn package(&mut self, dir: &Path) -> Option<usize> {
+        let canonical = match dir.canonicalize() {
+            Ok(canonical) => canonical,
+            Err(e) => return self.error(format!("cannot read {}: {}", dir.display(), e)),
+        };
+        if let Some(found) = self.directories.iter().position(|d| *d == canonical) {
+            return Some(found);
+        }
+        if let Some(start) = self.loading.iter().position(|(d, _)| *d == canonical) {
+            let mut cycle: Vec<&str> = self.loading[start..].iter().map(|(_, name)| name.as_str()).collect();
+            cycle.push(&self.loading[start].1);
+            return self.error(format!("packages depend on each other: {}", cycle.join(" -> ")));
+        }
+        let path = dir.join(MANIFEST);
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) => return self.error(format!("cannot read {}: {}", path.display(), e)),
+        };
+        let manifest = match Manifest::parse(&text) {
+            Ok(manifest) => manifest,
+            Err(error) => return self.error(format!("{}: {}", path.display(), error.message)),
+        };
+        let name = manifest.package.name.clone();
+        let loaded = self.unit.packages.iter().map(|p| (&p.name, p.root.display().to_string()));
+        let loading = self.loading.iter().map(|(d, name)| (name, d.display().to_string()));
+        if let Some((_, other)) = loaded.chain(loading).find(|(other, _)| **other == name) {
+            return self.error(format!("two packages are named `{}`: {} and {}", name, other, dir.display()));
+        }
+
+        self.loading.push((canonical.clone(), name.clone()));
+        let mut dependencies = Vec::new();
+        for (dependency, found) in &manifest.dependencies {
+            let Some(index) = self.package(&dir.join(&found.path)) else { continue };
+            let actual = &self.unit.packages[index].name;
+            if actual != dependency {
+                let message = format!(
+                    "{}: dependency `{}` at {} is the package `{}`",
+                    path.display(),
+                    dependency,
+                    found.path.display(),
+                    actual
+                );
+                self.errors.push(Diagnostic::error(message));
+            }
+            dependencies.push(index);
+        }
+        self.loading.pop();
+
+        let top_level = self.loading.is_empty();
+        let index = self.unit.packages.len();
+        self.unit.packages.push(Package { name, root: dir.to_path_buf(), manifest, dependencies });
+        self.directories.push(canonical);
+        self.files(index, top_level);
+        Some(index)
+    }
+
+    /// Find, read and parse every `.fig` file under the package's source
+    /// directories
+    fn files(&mut self, package: usize, top_level: bool) {
+        let Package { name, root, manifest, .. } = &self.unit.packages[package];
+        let (name, sources) = (name.clone(), manifest.package.sources.iter().map(|s| root.join(s)).collect::<Vec<_>>());
+        for source in sources {
+            let mut paths = Vec::new();
+            if let Err(e) = discover(&source, &mut paths) {
+                self.errors.push(Diagnostic::error(format!("cannot read {}: {}", source.display(), e)));
+                continue;
+            }
+            for path in paths {
+                let relative = path.strip_prefix(&source).unwrap_or(&path);
+                let namespace = match namespace_of(&name, relative, top_level) {
+                    Ok(namespace) => namespace,
+                    Err(segment) => {
+                        let message = format!("{}: `{}` is not a namespace name", path.display(), segment);
+                        self.errors.push(Diagnostic::error(message));
+                        continue;
+                    }
+                };
+                let text = match std::fs::read_to_string(&path) {
+                    Ok(text) => text,
+                    Err(e) => {
+                        self.errors.push(Diagnostic::error(format!("cannot read {}: {}", path.display(), e)));
+                        continue;
+                    }
+                };
+                match parse(&path, &text) {
+                    Ok(file) => self.unit.files.push(LoadedFile { path, package, namespace, text, file }),
+                    Err(error) => self.errors.push(error),
+                }
+            }
+        }
+    }
+
+    /// A file may declare a namespace other than its path's, but not one
+    /// in another package
+    fn check_namespaces(&mut self) {
+        let unit = &self.unit;
+        let (mut errors, mut warnings) = (Vec::new(), Vec::new());
+        for file in &unit.files {
+            for item in &file.file.items {
+                let NamespaceItem::NamespaceDeclaration(declaration) = item else { continue };
+                let declared = &declaration.name.segments;
+                if *declared == file.namespace {
+                    continue;
+                }
+                let message = format!("{} declares `namespace {}`", file.path.display(), declared.join("::"));
+                match unit.owner(declared) {
+                    Some(owner) if owner != file.package => {
+                        let note = format!("`{}` belongs to the package `{}`", declared[0], unit.packages[owner].name);
+                        errors.push(Diagnostic::error(message).with_note(note));
+                    }
+                    _ => {
+                        let note = format!("its path puts it in `{}`", display_namespace(&file.namespace));
+                        warnings.push(Diagnostic::warning(message).with_note(note));
+                    }
+                }
+            }
+        }
+        self.errors.extend(errors);
+        self.unit.warnings.extend(warnings);
+    }
+
+    /// Types, constants and function bodies are each defined once across
+    /// all the files
+    fn check_duplicates(&mut self) {
+        let mut defined: HashMap<String, usize> = HashMap::new();
+        for (index, file) in self.unit.files.iter().enumerate() {
+            let scoped = file.scoped();
+            let items = ItemTable::from_source_file(&scoped);
+            let mut names: Vec<String> = items.types().map(|(name, _)| name.to_string()).collect();
+            names.extend(items.consts().map(|(name, _)| name.to_string()));
+            names.extend(items.functions().iter().filter(|f| f.body.is_some()).map(|f| {
+                let mut segments = f.namespace.clone();
+                if let Some(receiver) = &f.signature.receiver {
+                    segments.push(format_path(receiver));
+                }
+                segments.push(f.signature.name.clone());
+                segments.join("::")
+            }));
+            for name in items.duplicates() {
+                let message = format!("`{}` is defined more than once in {}", name, file.path.display());
+                self.errors.push(Diagnostic::error(message));
+            }
+            for name in names {
+                match defined.get(&name) {
+                    Some(&first) if first == index => {
+                        let message = format!("`{}` is defined more than once in {}", name, file.path.display());
+                        self.errors.push(Diagnostic::error(message));
+                    }
+                    Some(&first) => {
+                        let message = format!(
+                            "`{}` is defined in both {} and {}",
+                            name,
+                            self.unit.files[first].path.display(),
+                            file.path.display()
+                        );
+                        self.errors.push(Diagnostic::error(message));
+                    }
+                    None => {
+                        defined.insert(name, index);
+                    }
+                }
+            }
+        }
+    }
+
+    fn error<T>(&mut self, message: String) -> Option<T> {
+        self.errors.push(Diagnostic::error(message));
+        None
+    }
+}
+
+/// Every `.fig` file under `dir`, in path order
+fn discover(dir: &Path, paths: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    let mut entries: Vec<PathBuf> = std::fs::read_dir(dir)?.map(|e| e.map(|e| e.path())).collect::<Result<_, _>>()?;
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            discover(&path, paths)?;
+        } else if path.extension().is_some_and(|e| e == "fig") {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// The namespace of the file at `relative` in a source directory of the
+/// package `name`: the package's, then a segment for each directory and
+/// one for the file. `lib.fig` is the package's own namespace, and the
+/// loaded package's `main.fig` is the top level, where `main` is found.
+/// Gives the segment that is not a namespace name if there is one.
+fn namespace_of(name: &str, relative: &Path, top_level: bool) -> Result<Vec<String>, String> {
+    if top_level && relative == Path::new("main.fig") {
+        return Ok(Vec::new());
+    }
+    let mut namespace = vec![name.to_string()];
+    if relative == Path::new("lib.fig") {
+        return Ok(namespace);
+    }
+    let stem = relative.with_extension("");
+    for component in stem.components() {
+        let segment = component.as_os_str().to_string_lossy().into_owned();
+        if !is_namespace_name(&segment) {
+            return Err(segment);
+        }
+        namespace.push(segment);
+    }
+    Ok(namespace)
+}
+
+fn display_namespace(namespace: &[String]) -> String {
+    if namespace.is_empty() { "the top level".to_string() } else { namespace.join("::") }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_namespace_of() {
+        let namespace = |path: &str, top_level| namespace_of("geo", Path::new(path), top_level);
+        assert_eq!(namespace("main.fig", true), Ok(vec![]));
+        assert_eq!(namespace("main.fig", false), Ok(vec!["geo".to_string(), "main".to_string()]));
+        assert_eq!(namespace("lib.fig", false), Ok(vec!["geo".to_string()]));
+        assert_eq!(namespace("shapes/circle.fig", true).unwrap().join("::"), "geo::shapes::circle");
+        assert_eq!(namespace("my-shapes.fig", true), Err("my-shapes".to_string()));
+        assert_eq!(namespace("for.fig", true), Err("for".to_string()));
+    }
+}
@@ -0,0 +1,98 @@
+//! The `fig.toml` manifest
+//!
+//! ```toml
+//! [package]
+//! name = "geo"
+//! version = "0.1.0"
+//! sources = ["src"]
+//!
+//! [dependencies]
+//! shapes = { path = "../shapes" }
+//! ```
+//!
+//! `version` is optional and `sources` defaults to `["src"]`. A dependency
+//! is named by the package it points to.
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use fig_parser::{Lexer, SourceFileParser};
+use fig_sema::diagnostics::Diagnostic;
+use serde::Deserialize;
+
+/// The file name a package directory holds its manifest in
+pub const MANIFEST: &str = "fig.toml";
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    pub package: PackageInfo,
+    #[serde(default)]
+    pub dependencies: BTreeMap<String, Dependency>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PackageInfo {
+    /// The namespace the package's files are in, e.g. `geo` for `geo::point`
+    pub name: String,
+    pub version: Option<String>,
+    /// Directories holding the package's `.fig` files, relative to the manifest
+    #[serde(default = "default_sources")]
+    pub sources: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Dependency {
+    /// The directory of the dependency's manifest, relative to this one
+    pub path: PathBuf,
+}
+
+fn default_sources() -> Vec<PathBuf> {
+    vec![PathBuf::from("src")]
+}
+
+impl Manifest {
+    /// Parse and check the text of a manifest
+    pub fn parse(text: &str) -> Result<Manifest, Diagnostic> {
+        let manifest: Manifest = toml::from_str(text).map_err(|e| Diagnostic::error(e.message().to_string()))?;
+        if !is_namespace_name(&manifest.package.name) {
+            let message = format!("package name `{}` is not a namespace name", manifest.package.name);
+            return Err(Diagnostic::error(message));
+        }
+        if manifest.package.sources.is_empty() {
+            return Err(Diagnostic::error("`sources` names no directory"));
+        }
+        Ok(manifest)
+    }
+}
+
+/// Whether `name` can be a namespace segment: an identifier, or one of the
+/// keywords `std`, `core` and `alloc` that name namespaces
+pub fn is_namespace_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && SourceFileParser::new().parse(Lexer::new(&format!("namespace {}\n", name))).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let manifest =
+            Manifest::parse("[package]\nname = \"geo\"\n\n[dependencies]\nshapes = { path = \"../shapes\" }\n").unwrap();
+        assert_eq!(manifest.package.name, "geo");
+        assert_eq!(manifest.package.sources, [PathBuf::from("src")]);
+        assert_eq!(manifest.dependencies["shapes"].path, PathBuf::from("../shapes"));
+
+        let error = Manifest::parse("[package]\nname = \"geo\"\nsource = [\"lib\"]\n").unwrap_err();
+        assert!(error.message.contains("unknown field `source`"), "{}", error.message);
+        let error = Manifest::parse("[package]\nname = \"func\"\n").unwrap_err();
+        assert_eq!(error.message, "package name `func` is not a namespace name");
+        assert!(Manifest::parse("[package]\nname = \"core\"\n").is_ok());
+    }
+}
@@ -0,0 +1,111 @@
+// Loads packages written to a scratch directory
+
+use std::path::{Path, PathBuf};
+
+use fig_package::load;
+use fig_sema::items::ItemTable;
+
+/// Write a package with `manifest` after its `[package]` name, and `files`
+/// under `src`
+fn package(test: &str, name: &str, manifest: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("fig-package").join(test).join(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("src")).unwrap();
+    std::fs::write(dir.join("fig.toml"), format!("[package]\nname = \"{}\"\n{}", name, manifest)).unwrap();
+    for (path, text) in files {
+        let path = dir.join("src").join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, text).unwrap();
+    }
+    dir
+}
+
+fn messages(diagnostics: &[fig_sema::diagnostics::Diagnostic]) -> Vec<String> {
+    diagnostics.iter().map(|d| d.to_string()).collect()
+}
+
+#[test]
+fn test_loads_dependencies_into_one_unit() {
+    package(
+        "unit",
+        "geo",
+        "",
+        &[
+            ("lib.fig", "export func origin() -> i32\n    return 0\n"),
+            ("shapes/point.fig", "export struct Point\n    x: i32\n    y: i32\n"),
+            (
+                "shapes/more.fig",
+                "namespace geo::shapes::point\n\nexport func Point::sum(self) -> i32\n    return self.x + self.y\n",
+            ),
+        ],
+    );
+    let app = package(
+        "unit",
+        "app",
+        "\n[dependencies]\ngeo = { path = \"../geo\" }\n",
+        &[(
+            "main.fig",
+            "func main() -> i32\n    let p: geo::shapes::point::Point = geo::shapes::point::Point(1, 2)\n    return 0\n",
+        )],
+    );
+    let unit = load(&app).unwrap();
+    let names: Vec<&str> = unit.packages.iter().map(|p| p.name.as_str()).collect();
+    assert_eq!(names, ["geo", "app"]);
+    assert_eq!(unit.packages[1].dependencies, [0]);
+    let namespaces: Vec<String> = unit.files.iter().map(|f| f.namespace.join("::")).collect();
+    assert_eq!(namespaces, ["geo", "geo::shapes::more", "geo::shapes::point", ""]);
+    // `more.fig` adds to the namespace of `point.fig`, which it may but is warned about
+    assert_eq!(unit.warnings.len(), 1);
+    assert!(messages(&unit.warnings)[0].contains("its path puts it in `geo::shapes::more`"));
+
+    let file = unit.source_file();
+    let items = ItemTable::from_source_file(&file);
+    let types: Vec<&str> = items.types().map(|(name, _)| name).collect();
+    assert_eq!(types, ["geo::shapes::point::Point"]);
+    let functions: Vec<String> = items.functions().iter().map(|f| f.qualified_name()).collect();
+    assert_eq!(functions, ["geo::origin", "geo::shapes::point::Point::sum", "main"]);
+}
+
+#[test]
+fn test_duplicate_definitions() {
+    let dir = package(
+        "duplicates",
+        "geo",
+        "",
+        &[
+            ("a.fig", "struct P\n    x: i32\n\nstruct P\n    y: i32\n"),
+            ("b.fig", "namespace geo::a\n\nstruct P\n    z: i32\n\nfunc f() -> i32\n    return 1\n"),
+            ("c.fig", "namespace geo::a\n\nfunc f() -> i32\n    return 2\n"),
+        ],
+    );
+    let errors = messages(&load(&dir).unwrap_err());
+    assert_eq!(errors.len(), 3, "{:?}", errors);
+    assert!(errors[0].starts_with("error: `geo::a::P` is defined more than once in "), "{}", errors[0]);
+    assert!(errors[1].contains("`geo::a::P` is defined in both ") && errors[1].ends_with("b.fig"), "{}", errors[1]);
+    assert!(errors[2].contains("`geo::a::f` is defined in both "), "{}", errors[2]);
+}
+
+#[test]
+fn test_package_errors() {
+    package("cycle", "a", "\n[dependencies]\nb = { path = \"../b\" }\n", &[]);
+    let b = package("cycle", "b", "\n[dependencies]\na = { path = \"../a\" }\n", &[]);
+    let errors = messages(&load(&b).unwrap_err());
+    assert_eq!(errors, ["error: packages depend on each other: b -> a -> b"]);
+
+    package("foreign", "geo", "", &[("lib.fig", "")]);
+    let app = package(
+        "foreign",
+        "app",
+        "\n[dependencies]\ngeo = { path = \"../geo\" }\n",
+        &[("extra.fig", "namespace geo\n\nstruct Sneaky\n    x: i32\n")],
+    );
+    let errors = messages(&load(&app).unwrap_err());
+    assert!(errors[0].contains("extra.fig declares `namespace geo`"), "{:?}", errors);
+    assert!(errors[0].ends_with("= note: `geo` belongs to the package `geo`"), "{:?}", errors);
+
+    let bad = package("names", "app", "", &[("my-file.fig", "")]);
+    let errors = messages(&load(&bad).unwrap_err());
+    assert!(errors[0].ends_with("my-file.fig: `my-file` is not a namespace name"), "{:?}", errors);
+    let errors = messages(&load(&bad.join("missing")).unwrap_err());
+    assert!(errors[0].starts_with("error: cannot read "), "{:?}", errors);
+}
@@ -1,14 +1,22 @@
 //! Abstract Syntax Tree definitions for Fig
 
 use fig_lexer::{FloatLiteral, IntegerLiteral};
-use serde::Serialize;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 
 // ============================================================================
 // Common / Shared Structures
 // ============================================================================
 
+/// A range of byte offsets in the source text
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
 /// A qualified path of identifiers, e.g. `std::Vec` or `Vec[T]`
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct Path {
     /// Segments of the path, e.g. `["std", "Vec"]`
     pub segments: Vec<String>,
@@ -33,7 +41,7 @@ impl Path {
 }
 
 /// Visibility modifier
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Default)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema, Default)]
 pub enum Visibility {
     /// No modifier. Private to the current namespace and its sub-namespaces.
     #[default]
@@ -50,14 +58,14 @@ pub enum Visibility {
 }
 
 /// A single annotation, e.g. `#inline` or `#cfg(feature = "foo")`
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct Annotation {
     pub name: String,
     pub args: Vec<Expression>,
 }
 
 /// Self parameter in a method definition
-#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 pub struct SelfParameter {
     pub is_pointer: bool,
     pub is_mutable: bool,
@@ -67,7 +75,7 @@ pub struct SelfParameter {
 // Expressions
 // ============================================================================
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub enum Expression {
     // ── Literals ──
     IntegerLiteral(IntegerLiteral),
@@ -87,6 +95,15 @@ pub enum Expression {
     // ── Composite literals ──
     ArrayLiteral(ArrayLiteralExpr),
     InterpolatedString(Vec<InterpolatedPart>),
+    /// A struct value, e.g. `Point(x: 1, y: 2)`
+    StructLiteral(StructLiteralExpr),
+    /// A union value, e.g. `Shape::Circle(r)`
+    VariantLiteral(VariantLiteralExpr),
+    /// An enum value, e.g. `Color::Red`
+    EnumVariant(EnumVariantExpr),
+
+    // ── Ranges ──
+    Range(RangeExpr),
 
     // ── Arithmetic / logical / bitwise ──
     BinaryOp(BinaryOpExpr),
@@ -107,6 +124,10 @@ pub enum Expression {
     // ── Grouping ──
     Parenthesized(Box<Expression>),
 
+    // ── Functions ──
+    /// An anonymous function, e.g. `fn(x: i32) -> i32 => x * 2`
+    Lambda(LambdaExpr),
+
     // ── Assignment ──
     Assign(AssignExpr),
 }
@@ -115,24 +136,79 @@ pub enum Expression {
 // Array / Interpolated String Literals
 // ============================================================================
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct ArrayLiteralExpr {
     pub elements: Vec<Expression>,
 }
 
 /// A segment of an interpolated string
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub enum InterpolatedPart {
     Text(String),
     Expression(Box<Expression>),
 }
 
+/// `start..end`, or `start..=end` when the end is included. Either bound
+/// may be left out of a half-open range; an inclusive range needs its end.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct RangeExpr {
+    pub start: Option<Box<Expression>>,
+    pub end: Option<Box<Expression>>,
+    pub is_inclusive: bool,
+}
+
+// ============================================================================
+// Construction
+// ============================================================================
+//
+// The parser knows a struct literal by its named fields. Positional
+// construction, `Point(1, 2)` or `Point(..origin)`, `Shape::Circle(r)` and
+// variant paths such as `Color::Red` read as calls and paths until
+// `fig_sema::construct` finds that they name a type and rewrites them into
+// these nodes.
+
+/// `Point(x: 1, y)`, `Point(1, 2)` or `Point(x: 0, ..origin)`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct StructLiteralExpr {
+    /// The struct, with its generic arguments when they are written
+    pub ty: Path,
+    pub fields: Vec<FieldInit>,
+    /// The value the fields not listed are copied from
+    pub base: Option<Box<Expression>>,
+}
+
+/// One field of a struct literal. Next to a named field, a plain name
+/// stands for the field of that name set to the local: `y` in
+/// `Point(x: 0, y)` is `y: y`. On its own, `Point(x, y)` is positional.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct FieldInit {
+    /// The field's name, or none for the next field in declaration order
+    pub name: Option<String>,
+    pub value: Expression,
+}
+
+/// `Shape::Circle(r)`, or `Shape::Empty` for a variant of type `ok`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct VariantLiteralExpr {
+    /// The union, with its generic arguments when they are written
+    pub ty: Path,
+    pub variant: String,
+    pub payload: Option<Box<Expression>>,
+}
+
+/// `Color::Red`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct EnumVariantExpr {
+    pub ty: Path,
+    pub variant: String,
+}
+
 // ============================================================================
 // Postfix Operation Nodes
 // ============================================================================
 
 /// `object.field` or `object.!field`
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct FieldAccessExpr {
     pub object: Box<Expression>,
     pub field: String,
@@ -140,14 +216,14 @@ pub struct FieldAccessExpr {
 }
 
 /// `object::member`
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct TypeAccessExpr {
     pub object: Box<Expression>,
     pub member: String,
 }
 
 /// `callee(args)` or `callee!(args)`
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct CallExpr {
     pub callee: Box<Expression>,
     pub args: Vec<Expression>,
@@ -155,21 +231,37 @@ pub struct CallExpr {
 }
 
 /// `object[index]`
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct IndexExpr {
     pub object: Box<Expression>,
     pub index: Box<Expression>,
 }
 
 /// `expr as Type`
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct CastExpr {
     pub expr: Box<Expression>,
     pub target_type: Box<Type>,
 }
 
+/// `fn(params) -> T => body` or, when the body may have effects,
+/// `fn!(params) -> T => body`
+///
+/// The body may read the parameters and the locals of the functions the
+/// lambda is nested in. Those it reads are captured by value when the
+/// lambda is evaluated, so it sees them as they were then, and it may not
+/// take their address. The rules are checked by `fig_sema::captures`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct LambdaExpr {
+    pub is_effect: bool,
+    pub params: Vec<FunctionParameter>,
+    /// `None` when the lambda returns no value
+    pub return_type: Option<Box<Type>>,
+    pub body: Box<Expression>,
+}
+
 /// `offsetof(Type, field)`
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct OffsetofExpr {
     pub ty: Box<Type>,
     pub field: String,
@@ -179,13 +271,15 @@ pub struct OffsetofExpr {
 // Assignment Operations
 // ============================================================================
 
+/// `lhs = rhs` or a compound assignment such as `lhs += rhs`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct AssignExpr {
-    lhs: Box<Expression>,
-    op: AssignOperator,
-    rhs: Box<Expression>,
+    pub lhs: Box<Expression>,
+    pub op: AssignOperator,
+    pub rhs: Box<Expression>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 pub enum AssignOperator {
     Assign,       // =
     AddAssign,    // +=
@@ -204,14 +298,14 @@ pub enum AssignOperator {
 // Binary Operations
 // ============================================================================
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct BinaryOpExpr {
     pub lhs: Box<Expression>,
     pub op: BinaryOperator,
     pub rhs: Box<Expression>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 pub enum BinaryOperator {
     Add,
     Subtract,
@@ -237,13 +331,13 @@ pub enum BinaryOperator {
 // Unary Operations
 // ============================================================================
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct UnaryOpExpr {
     pub op: UnaryOperator,
     pub operand: Box<Expression>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 pub enum UnaryOperator {
     LogicalNot,  // !
     BitwiseNot,  // ~
@@ -257,7 +351,7 @@ pub enum UnaryOperator {
 // Types
 // ============================================================================
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub enum Type {
     // Primitive types
     U8,
@@ -288,6 +382,9 @@ pub enum Type {
         element_type: Box<Type>,
     },
 
+    /// Optional type `?T` for non-pointer `T`; `?*T` is a nullable [`Type::Pointer`]
+    Optional(Box<Type>),
+
     /// Named / path type, e.g. `Vec[T]`, `std::HashMap[K, V]`
     Path(Path),
 
@@ -306,6 +403,22 @@ pub enum Type {
         /// The error type (right-hand side of `!`), always a named path
         err_type: Path,
     },
+
+    /// A constant generic argument, e.g. the `4` in `Array[T, 4]`
+    Const(Box<Expression>),
+
+    /// Function type `fn(T) -> U`, or `fn!(T) -> U` for functions with
+    /// effects. The two are distinct: a pure function cannot be given an
+    /// effectful one.
+    Function(FunctionType),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct FunctionType {
+    pub is_effect: bool,
+    pub params: Vec<Type>,
+    /// `None` when the function returns no value
+    pub return_type: Option<Box<Type>>,
 }
 
 // ============================================================================
@@ -313,7 +426,7 @@ pub enum Type {
 // ============================================================================
 
 /// A single generic parameter or where-clause constraint
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub enum GenericParameter {
     /// Type parameter: `T`, `T: Bound`, `T = Default`, `T: Bound = Default`
     Type {
@@ -325,17 +438,28 @@ pub enum GenericParameter {
     Const { name: String, ty: Type },
 }
 
+impl GenericParameter {
+    pub fn name(&self) -> &str {
+        match self {
+            GenericParameter::Type { name, .. } | GenericParameter::Const { name, .. } => name,
+        }
+    }
+}
+
 // ============================================================================
 // Type Alias
 // ============================================================================
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct TypeAlias {
     pub visibility: Visibility,
     pub annotations: Vec<Annotation>,
     pub name: String,
     /// Combined generic params (bounds merged from param list + where clause)
     pub generic_params: Vec<GenericParameter>,
+    /// Where-clause constraints that name no parameter declared on this item
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unbound_constraints: Vec<GenericParameter>,
     pub aliased_type: Type,
 }
 
@@ -343,7 +467,7 @@ pub struct TypeAlias {
 // Enum
 // ============================================================================
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct Enum {
     pub visibility: Visibility,
     pub annotations: Vec<Annotation>,
@@ -352,12 +476,15 @@ pub struct Enum {
     pub representation: Option<Type>,
     /// Combined generic params (bounds merged from param list + where clause)
     pub generic_params: Vec<GenericParameter>,
+    /// Where-clause constraints that name no parameter declared on this item
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unbound_constraints: Vec<GenericParameter>,
     /// `requires` clause
     pub requires: Vec<Type>,
     pub variants: Vec<EnumVariant>,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct EnumVariant {
     pub name: String,
     pub value: Option<Expression>,
@@ -367,19 +494,22 @@ pub struct EnumVariant {
 // Union
 // ============================================================================
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct Union {
     pub visibility: Visibility,
     pub annotations: Vec<Annotation>,
     pub name: String,
     /// Combined generic params
     pub generic_params: Vec<GenericParameter>,
+    /// Where-clause constraints that name no parameter declared on this item
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unbound_constraints: Vec<GenericParameter>,
     /// `requires` clause
     pub requires: Vec<Type>,
     pub variants: Vec<UnionVariant>,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct UnionVariant {
     pub name: String,
     pub ty: Type,
@@ -389,7 +519,7 @@ pub struct UnionVariant {
 // Struct
 // ============================================================================
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct Struct {
     pub visibility: Visibility,
     pub annotations: Vec<Annotation>,
@@ -397,12 +527,15 @@ pub struct Struct {
     pub name: String,
     /// Combined generic params
     pub generic_params: Vec<GenericParameter>,
+    /// Where-clause constraints that name no parameter declared on this item
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unbound_constraints: Vec<GenericParameter>,
     /// `requires` clause
     pub requires: Vec<Type>,
     pub fields: Vec<StructField>,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct StructField {
     pub name: String,
     pub ty: Type,
@@ -412,19 +545,19 @@ pub struct StructField {
 // Function
 // ============================================================================
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct Function {
     pub signature: FunctionSignature,
     pub body: Block,
 }
 
 /// Forward declaration (interface method, extern declaration)
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct FunctionDeclaration {
     pub signature: FunctionSignature,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct FunctionSignature {
     pub visibility: Visibility,
     pub annotations: Vec<Annotation>,
@@ -436,33 +569,62 @@ pub struct FunctionSignature {
     pub name: String,
     /// Combined generic params (bounds merged from param list + where clause)
     pub generic_params: Vec<GenericParameter>,
+    /// Where-clause constraints that name no parameter declared on this item
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unbound_constraints: Vec<GenericParameter>,
     pub self_param: Option<SelfParameter>,
     pub params: Vec<FunctionParameter>,
     pub return_types: Vec<Type>,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct FunctionParameter {
     pub name: String,
     pub ty: Type,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Block {
     pub statements: Vec<Statement>,
+    /// Where each statement is in the source, in step with `statements`.
+    /// Empty for blocks built rather than parsed.
+    #[serde(skip)]
+    pub spans: Vec<Span>,
+}
+
+impl Block {
+    pub fn new(statements: Vec<Statement>) -> Self {
+        Block { statements, spans: Vec::new() }
+    }
+
+    /// Where the statement at `index` is in the source, if known
+    pub fn span(&self, index: usize) -> Option<Span> {
+        self.spans.get(index).copied()
+    }
+}
+
+/// Spans are positions rather than structure, so two blocks are equal when
+/// their statements are, wherever they were parsed from
+impl PartialEq for Block {
+    fn eq(&self, other: &Self) -> bool {
+        self.statements == other.statements
+    }
 }
 
 // ============================================================================
 // Interfaces
 // ============================================================================
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct Interface {
     pub visibility: Visibility,
     pub annotations: Vec<Annotation>,
     pub name: String,
     /// Combined generic params
     pub generic_params: Vec<GenericParameter>,
+    /// Where-clause constraints that name no parameter declared on this item
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unbound_constraints: Vec<GenericParameter>,
     /// `extends` clause
     pub extends: Vec<Type>,
     /// `requires` clause
@@ -474,7 +636,7 @@ pub struct Interface {
 // Namespaces
 // ============================================================================
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct Namespace {
     pub visibility: Visibility,
     pub annotations: Vec<Annotation>,
@@ -482,7 +644,7 @@ pub struct Namespace {
     pub items: Vec<Statement>,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct NamespaceDeclaration {
     pub visibility: Visibility,
     pub annotations: Vec<Annotation>,
@@ -493,7 +655,7 @@ pub struct NamespaceDeclaration {
 // Source File
 // ============================================================================
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct SourceFile {
     pub items: Vec<NamespaceItem>,
 }
@@ -509,7 +671,7 @@ impl SourceFile {
 }
 
 /// Top-level items at file or namespace scope
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub enum NamespaceItem {
     Namespace(Namespace),
     NamespaceDeclaration(NamespaceDeclaration),
@@ -528,7 +690,7 @@ pub enum NamespaceItem {
 // Statements
 // ============================================================================
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub enum Statement {
     /// `pass`
     Pass,
@@ -542,6 +704,10 @@ pub enum Statement {
     Const(ConstStatement),
     /// `return expr`
     Return(Box<Expression>),
+    /// `break` out of the innermost loop, or `break name` out of the named `block`
+    Break(Option<String>),
+    /// `continue` with the next iteration of the innermost loop
+    Continue,
     /// `block name? { stmts }`
     Block(BlockStatement),
     /// `if cond { } elif ... else { }`
@@ -565,7 +731,7 @@ pub enum Statement {
 
 // ── Statement structs ────────────────────────────────────────────────────────
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct LetStatement {
     pub annotations: Vec<Annotation>,
     pub name: String,
@@ -573,7 +739,7 @@ pub struct LetStatement {
     pub value: Box<Expression>,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct MutStatement {
     pub annotations: Vec<Annotation>,
     pub name: String,
@@ -582,13 +748,13 @@ pub struct MutStatement {
 }
 
 /// One segment of a const's qualified name, e.g. `namespacea` (no args) or `Option[T]` (with args).
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct ConstPathSegment {
     pub name: String,
     pub generic_args: Vec<Type>,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct ConstStatement {
     pub visibility: Visibility,
     pub annotations: Vec<Annotation>,
@@ -605,13 +771,13 @@ pub struct ConstStatement {
     pub value: Box<Expression>,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct BlockStatement {
     pub name: Option<String>,
     pub body: Block,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct IfStatement {
     pub condition: Box<Expression>,
     pub then_body: Block,
@@ -619,26 +785,26 @@ pub struct IfStatement {
     pub else_body: Option<Block>,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct ElifClause {
     pub condition: Box<Expression>,
     pub body: Block,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct ForStatement {
     pub pattern: String,
     pub iterable: Box<Expression>,
     pub body: Block,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct WhileStatement {
     pub condition: Box<Expression>,
     pub body: Block,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct UsingStatement {
     pub visibility: Visibility,
     pub annotations: Vec<Annotation>,
@@ -651,34 +817,33 @@ pub struct UsingStatement {
 
 /// Merge where-clause constraints into a list of generic parameters.
 ///
-/// For each `GenericParameter::Type { name, bounds }` in `where_clause`:
-/// - If a parameter with the same name already exists, append the bounds to it.
-/// - Otherwise, insert a new entry.
+/// Returns the merged parameters together with the constraints that could not
+/// be attached to any of them:
+/// - A `Type` constraint on a declared type parameter appends its bounds.
+/// - A `Const` constraint restating a declared const parameter (same type) is
+///   accepted as-is.
+/// - Anything else — an undeclared name, or a constraint whose kind does not
+///   match the declaration — is returned unchanged in the second list so that
+///   semantic analysis can report it. Parameters are never invented.
 pub fn merge_where_clause(
     mut params: Vec<GenericParameter>,
     where_clause: Vec<GenericParameter>,
-) -> Vec<GenericParameter> {
+) -> (Vec<GenericParameter>, Vec<GenericParameter>) {
+    let mut unbound = Vec::new();
     for constraint in where_clause {
-        if let GenericParameter::Type { name, bounds, .. } = constraint {
-            if let Some(existing) = params
-                .iter_mut()
-                .find(|p| matches!(p, GenericParameter::Type { name: n, .. } if *n == name))
-            {
-                if let GenericParameter::Type {
+        let existing = params.iter_mut().find(|p| p.name() == constraint.name());
+        match (existing, constraint) {
+            (
+                Some(GenericParameter::Type {
                     bounds: existing_bounds,
                     ..
-                } = existing
-                {
-                    existing_bounds.extend(bounds);
-                }
-            } else {
-                params.push(GenericParameter::Type {
-                    name,
-                    bounds,
-                    default_type: None,
-                });
-            }
+                }),
+                GenericParameter::Type { bounds, .. },
+            ) => existing_bounds.extend(bounds),
+            (Some(GenericParameter::Const { ty: declared, .. }), GenericParameter::Const { ty, .. })
+                if *declared == ty => {}
+            (_, constraint) => unbound.push(constraint),
         }
     }
-    params
+    (params, unbound)
 }
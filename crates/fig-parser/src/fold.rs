@@ -0,0 +1,169 @@
+//! Rebuilding the syntax tree by value
+//!
+//! A [`Fold`] takes a node and returns the node to put in its place, which
+//! may be of another variant: desugaring an expression into a call, say.
+//! Its defaults rebuild the node from its folded children. The children are
+//! found by [`VisitMut`]'s traversal, so the two always agree on what a
+//! node contains; the node kinds in between, such as structs and function
+//! signatures, are walked through to the foldable nodes inside them.
+
+use crate::ast::*;
+use crate::visit_mut::{self, VisitMut};
+
+pub trait Fold {
+    fn fold_source_file(&mut self, file: SourceFile) -> SourceFile {
+        walk_source_file(self, file)
+    }
+
+    fn fold_namespace_item(&mut self, item: NamespaceItem) -> NamespaceItem {
+        walk_namespace_item(self, item)
+    }
+
+    fn fold_block(&mut self, block: Block) -> Block {
+        walk_block(self, block)
+    }
+
+    fn fold_statement(&mut self, statement: Statement) -> Statement {
+        walk_statement(self, statement)
+    }
+
+    fn fold_expression(&mut self, expression: Expression) -> Expression {
+        walk_expression(self, expression)
+    }
+
+    fn fold_type(&mut self, ty: Type) -> Type {
+        walk_type(self, ty)
+    }
+
+    fn fold_path(&mut self, path: Path) -> Path {
+        walk_path(self, path)
+    }
+}
+
+/// Folds the nodes a [`VisitMut`] walk reaches, putting each result back
+/// in the tree
+struct Folder<'f, F: ?Sized>(&'f mut F);
+
+/// What a node is left as while it is being folded
+fn placeholder_path() -> Path {
+    Path { segments: Vec::new(), generic_args: Vec::new() }
+}
+
+impl<F: Fold + ?Sized> VisitMut for Folder<'_, F> {
+    fn visit_source_file(&mut self, file: &mut SourceFile) {
+        let taken = std::mem::replace(file, SourceFile::new(Vec::new()));
+        *file = self.0.fold_source_file(taken);
+    }
+
+    fn visit_namespace_item(&mut self, item: &mut NamespaceItem) {
+        let placeholder = NamespaceDeclaration {
+            visibility: Visibility::Default,
+            annotations: Vec::new(),
+            name: placeholder_path(),
+        };
+        let taken = std::mem::replace(item, NamespaceItem::NamespaceDeclaration(placeholder));
+        *item = self.0.fold_namespace_item(taken);
+    }
+
+    fn visit_block(&mut self, block: &mut Block) {
+        let taken = std::mem::replace(block, Block::new(Vec::new()));
+        *block = self.0.fold_block(taken);
+    }
+
+    fn visit_statement(&mut self, statement: &mut Statement) {
+        let taken = std::mem::replace(statement, Statement::Pass);
+        *statement = self.0.fold_statement(taken);
+    }
+
+    fn visit_expression(&mut self, expression: &mut Expression) {
+        let taken = std::mem::replace(expression, Expression::OkLiteral);
+        *expression = self.0.fold_expression(taken);
+    }
+
+    fn visit_type(&mut self, ty: &mut Type) {
+        let taken = std::mem::replace(ty, Type::Ok);
+        *ty = self.0.fold_type(taken);
+    }
+
+    fn visit_path(&mut self, path: &mut Path) {
+        let taken = std::mem::replace(path, placeholder_path());
+        *path = self.0.fold_path(taken);
+    }
+}
+
+pub fn walk_source_file<F: Fold + ?Sized>(folder: &mut F, mut file: SourceFile) -> SourceFile {
+    visit_mut::walk_source_file(&mut Folder(folder), &mut file);
+    file
+}
+
+pub fn walk_namespace_item<F: Fold + ?Sized>(folder: &mut F, mut item: NamespaceItem) -> NamespaceItem {
+    visit_mut::walk_namespace_item(&mut Folder(folder), &mut item);
+    item
+}
+
+pub fn walk_block<F: Fold + ?Sized>(folder: &mut F, mut block: Block) -> Block {
+    visit_mut::walk_block(&mut Folder(folder), &mut block);
+    block
+}
+
+pub fn walk_statement<F: Fold + ?Sized>(folder: &mut F, mut statement: Statement) -> Statement {
+    visit_mut::walk_statement(&mut Folder(folder), &mut statement);
+    statement
+}
+
+pub fn walk_expression<F: Fold + ?Sized>(folder: &mut F, mut expression: Expression) -> Expression {
+    visit_mut::walk_expression(&mut Folder(folder), &mut expression);
+    expression
+}
+
+pub fn walk_type<F: Fold + ?Sized>(folder: &mut F, mut ty: Type) -> Type {
+    visit_mut::walk_type(&mut Folder(folder), &mut ty);
+    ty
+}
+
+pub fn walk_path<F: Fold + ?Sized>(folder: &mut F, mut path: Path) -> Path {
+    visit_mut::walk_path(&mut Folder(folder), &mut path);
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::{format_expression, format_signature};
+    use crate::{Lexer, SourceFileParser};
+
+    /// Rewrites `x += y` as `x = x + y`, and `Self` in types as `Point`
+    struct Desugar;
+
+    impl Fold for Desugar {
+        fn fold_expression(&mut self, expression: Expression) -> Expression {
+            match walk_expression(self, expression) {
+                Expression::Assign(AssignExpr { lhs, op: AssignOperator::AddAssign, rhs }) => {
+                    let sum = BinaryOpExpr { lhs: lhs.clone(), op: BinaryOperator::Add, rhs };
+                    let sum = Box::new(Expression::BinaryOp(sum));
+                    Expression::Assign(AssignExpr { lhs, op: AssignOperator::Assign, rhs: sum })
+                }
+                expression => expression,
+            }
+        }
+
+        fn fold_type(&mut self, ty: Type) -> Type {
+            match walk_type(self, ty) {
+                Type::SelfType => Type::Path(Path::simple("Point".to_string())),
+                ty => ty,
+            }
+        }
+    }
+
+    #[test]
+    fn test_fold_rewrites_nested_nodes() {
+        let src = "func! Point::grow(*mut self, by: Self)\n    if by.x > 0\n        self.x += by.x * 2\n";
+        let file = SourceFileParser::new().parse(Lexer::new(src)).unwrap();
+        let folded = Desugar.fold_source_file(file);
+        let NamespaceItem::Function(function) = &folded.items[0] else { panic!("{:?}", folded.items[0]) };
+        assert_eq!(format_signature(&function.signature), "func! Point::grow(*mut self, by: Point)");
+        let Statement::If(check) = &function.body.statements[0] else { panic!("{:?}", function.body) };
+        let Statement::Expression(assign) = &check.then_body.statements[0] else { panic!("{:?}", check.then_body) };
+        assert_eq!(format_expression(assign), "self.x = self.x + by.x * 2");
+    }
+}
@@ -0,0 +1,526 @@
+//! Source-level formatting of AST nodes back into Fig syntax
+//!
+//! Unlike [`crate::pretty_print`], which renders a debugging tree, the functions
+//! here produce the text a programmer would write, e.g. `?*mut Node[T]` or
+//! `(a + b) as u64`. They are used wherever a node has to be shown to a user:
+//! diagnostics, layout dumps and generated code comments. [`format_item`]
+//! renders whole declarations for tools that generate Fig source.
+
+use crate::ast::*;
+
+/// Render a path, e.g. `std::Vec[T]`
+pub fn format_path(path: &Path) -> String {
+    let base = path.segments.join("::");
+    if path.generic_args.is_empty() {
+        base
+    } else {
+        format!("{}[{}]", base, format_type_list(&path.generic_args))
+    }
+}
+
+/// Render a comma-separated list of types, e.g. `K, V`
+pub fn format_type_list(types: &[Type]) -> String {
+    types.iter().map(format_type).collect::<Vec<_>>().join(", ")
+}
+
+/// Render a type, e.g. `?*mut u8` or `[T; 4] ! IoError`
+pub fn format_type(ty: &Type) -> String {
+    match ty {
+        Type::U8 => "u8".to_string(),
+        Type::U16 => "u16".to_string(),
+        Type::U32 => "u32".to_string(),
+        Type::U64 => "u64".to_string(),
+        Type::USize => "usize".to_string(),
+        Type::I8 => "i8".to_string(),
+        Type::I16 => "i16".to_string(),
+        Type::I32 => "i32".to_string(),
+        Type::I64 => "i64".to_string(),
+        Type::ISize => "isize".to_string(),
+        Type::F32 => "f32".to_string(),
+        Type::F64 => "f64".to_string(),
+        Type::Bool => "bool".to_string(),
+        Type::Ok => "ok".to_string(),
+        Type::Null => "null".to_string(),
+        Type::SelfType => "Self".to_string(),
+        Type::Pointer { nullable, mutable, element_type } => format!(
+            "{}*{}{}",
+            if *nullable { "?" } else { "" },
+            if *mutable { "mut " } else { "" },
+            format_type(element_type)
+        ),
+        Type::Optional(inner) => format!("?{}", format_type(inner)),
+        Type::Path(path) => format_path(path),
+        Type::Array { element_type, size: Some(size) } => {
+            format!("[{}; {}]", format_type(element_type), format_expression(size))
+        }
+        Type::Array { element_type, size: None } => format!("[{}]", format_type(element_type)),
+        Type::ErrorUnion { ok_type, err_type } => {
+            format!("{} ! {}", format_type(ok_type), format_path(err_type))
+        }
+        Type::Const(value) => format_expression(value),
+        Type::Function(function) => {
+            let mut text = format!(
+                "fn{}({})",
+                if function.is_effect { "!" } else { "" },
+                format_type_list(&function.params)
+            );
+            if let Some(return_type) = &function.return_type {
+                text.push_str(" -> ");
+                text.push_str(&format_type(return_type));
+            }
+            text
+        }
+    }
+}
+
+/// The source spelling of a binary operator
+pub fn binary_operator_symbol(op: BinaryOperator) -> &'static str {
+    match op {
+        BinaryOperator::Add => "+",
+        BinaryOperator::Subtract => "-",
+        BinaryOperator::Multiply => "*",
+        BinaryOperator::Divide => "/",
+        BinaryOperator::Modulo => "%",
+        BinaryOperator::Equal => "==",
+        BinaryOperator::NotEqual => "!=",
+        BinaryOperator::LessThan => "<",
+        BinaryOperator::GreaterThan => ">",
+        BinaryOperator::LessThanOrEqual => "<=",
+        BinaryOperator::GreaterThanOrEqual => ">=",
+        BinaryOperator::LogicalAnd => "&&",
+        BinaryOperator::LogicalOr => "||",
+        BinaryOperator::BitwiseAnd => "&",
+        BinaryOperator::BitwiseOr => "|",
+        BinaryOperator::BitwiseXor => "^",
+        BinaryOperator::ShiftLeft => "<<",
+        BinaryOperator::ShiftRight => ">>",
+    }
+}
+
+/// The source spelling of a unary operator
+pub fn unary_operator_symbol(op: UnaryOperator) -> &'static str {
+    match op {
+        UnaryOperator::LogicalNot => "!",
+        UnaryOperator::BitwiseNot => "~",
+        UnaryOperator::Negate => "-",
+        UnaryOperator::Plus => "+",
+        UnaryOperator::AddressOf => "&",
+        UnaryOperator::Dereference => "*",
+    }
+}
+
+/// The source spelling of an assignment operator
+pub fn assign_operator_symbol(op: AssignOperator) -> &'static str {
+    match op {
+        AssignOperator::Assign => "=",
+        AssignOperator::AddAssign => "+=",
+        AssignOperator::SubAssign => "-=",
+        AssignOperator::MulAssign => "*=",
+        AssignOperator::DivAssign => "/=",
+        AssignOperator::ModAssign => "%=",
+        AssignOperator::BitAndAssign => "&=",
+        AssignOperator::BitOrAssign => "|=",
+        AssignOperator::BitXorAssign => "^=",
+        AssignOperator::ShlAssign => "<<=",
+        AssignOperator::ShrAssign => ">>=",
+    }
+}
+
+/// Escape a string or character literal body using Fig's escape sequences
+pub fn escape_literal(text: &str, quote: char) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\\' => out.push_str("\\\\"),
+            '\0' => out.push_str("\\0"),
+            c if c == quote => {
+                out.push('\\');
+                out.push(c);
+            }
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\x{:02x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Render an expression. Grouping is taken from [`Expression::Parenthesized`]
+/// nodes, so a parsed expression round-trips to equivalent source.
+pub fn format_expression(expr: &Expression) -> String {
+    match expr {
+        Expression::IntegerLiteral(lit) => lit.to_string(),
+        Expression::FloatLiteral(lit) => lit.to_string(),
+        Expression::BooleanLiteral(b) => b.to_string(),
+        Expression::CharLiteral(c) => format!("'{}'", escape_literal(c, '\'')),
+        Expression::StringLiteral(s) => format!("\"{}\"", escape_literal(s, '"')),
+        Expression::OkLiteral => "ok".to_string(),
+        Expression::NullLiteral => "null".to_string(),
+        Expression::SelfValue => "self".to_string(),
+        Expression::Path(path) => format_path(path),
+        Expression::ArrayLiteral(arr) => format!("[{}]", format_expression_list(&arr.elements)),
+        Expression::StructLiteral(lit) => format_struct_literal(lit),
+        Expression::VariantLiteral(lit) => match &lit.payload {
+            Some(payload) => format!("{}::{}({})", format_path(&lit.ty), lit.variant, format_expression(payload)),
+            None => format!("{}::{}", format_path(&lit.ty), lit.variant),
+        },
+        Expression::EnumVariant(variant) => format!("{}::{}", format_path(&variant.ty), variant.variant),
+        Expression::InterpolatedString(parts) => {
+            let body: String = parts
+                .iter()
+                .map(|part| match part {
+                    InterpolatedPart::Text(t) => t.clone(),
+                    InterpolatedPart::Expression(e) => format!("{{{}}}", format_expression(e)),
+                })
+                .collect();
+            format!("$\"{}\"", body)
+        }
+        Expression::Range(range) => format!(
+            "{}{}{}",
+            range.start.as_deref().map(format_expression).unwrap_or_default(),
+            if range.is_inclusive { "..=" } else { ".." },
+            range.end.as_deref().map(format_expression).unwrap_or_default()
+        ),
+        Expression::BinaryOp(op) => format!(
+            "{} {} {}",
+            format_expression(&op.lhs),
+            binary_operator_symbol(op.op),
+            format_expression(&op.rhs)
+        ),
+        Expression::UnaryOp(op) => {
+            format!("{}{}", unary_operator_symbol(op.op), format_expression(&op.operand))
+        }
+        Expression::FieldAccess(fa) => format!(
+            "{}.{}{}",
+            format_expression(&fa.object),
+            if fa.is_propagating { "!" } else { "" },
+            fa.field
+        ),
+        Expression::TypeAccess(ta) => format!("{}::{}", format_expression(&ta.object), ta.member),
+        Expression::Call(call) => format!(
+            "{}{}({})",
+            format_expression(&call.callee),
+            if call.is_propagating { "!" } else { "" },
+            format_expression_list(&call.args)
+        ),
+        Expression::Index(idx) => {
+            format!("{}[{}]", format_expression(&idx.object), format_expression(&idx.index))
+        }
+        Expression::Cast(cast) => {
+            format!("{} as {}", format_expression(&cast.expr), format_type(&cast.target_type))
+        }
+        Expression::Sizeof(ty) => format!("sizeof({})", format_type(ty)),
+        Expression::Alignof(ty) => format!("alignof({})", format_type(ty)),
+        Expression::Offsetof(oo) => format!("offsetof({}, {})", format_type(&oo.ty), oo.field),
+        Expression::Parenthesized(inner) => format!("({})", format_expression(inner)),
+        Expression::Assign(assign) => format!(
+            "{} {} {}",
+            format_expression(&assign.lhs),
+            assign_operator_symbol(assign.op),
+            format_expression(&assign.rhs)
+        ),
+        Expression::Lambda(lambda) => {
+            let params: Vec<String> =
+                lambda.params.iter().map(|p| format!("{}: {}", p.name, format_type(&p.ty))).collect();
+            let mut text = format!("fn{}({})", if lambda.is_effect { "!" } else { "" }, params.join(", "));
+            if let Some(return_type) = &lambda.return_type {
+                text.push_str(" -> ");
+                text.push_str(&format_type(return_type));
+            }
+            text.push_str(" => ");
+            text.push_str(&format_expression(&lambda.body));
+            text
+        }
+    }
+}
+
+/// Render a struct literal. A field set to the local of its name is written
+/// as just the name when another named field keeps the literal from reading
+/// as a call.
+fn format_struct_literal(lit: &StructLiteralExpr) -> String {
+    let is_shorthand = |field: &FieldInit| match (&field.name, &field.value) {
+        (Some(name), Expression::Path(path)) => path.generic_args.is_empty() && path.segments == [name.as_str()],
+        _ => false,
+    };
+    let named = lit.fields.iter().any(|f| f.name.is_some() && !is_shorthand(f));
+    let mut parts: Vec<String> = lit
+        .fields
+        .iter()
+        .map(|field| match &field.name {
+            Some(name) if !(named && is_shorthand(field)) => format!("{}: {}", name, format_expression(&field.value)),
+            _ => format_expression(&field.value),
+        })
+        .collect();
+    if let Some(base) = &lit.base {
+        parts.push(format!("..{}", format_expression(base)));
+    }
+    format!("{}({})", format_path(&lit.ty), parts.join(", "))
+}
+
+/// Render a comma-separated list of expressions, e.g. call arguments
+pub fn format_expression_list(exprs: &[Expression]) -> String {
+    exprs.iter().map(format_expression).collect::<Vec<_>>().join(", ")
+}
+
+/// Render a generic parameter list including its brackets, or nothing when
+/// there are no parameters, e.g. `[T: Hash + Eq, const N: usize]`
+pub fn format_generic_params(params: &[GenericParameter]) -> String {
+    if params.is_empty() {
+        return String::new();
+    }
+    let params: Vec<String> = params
+        .iter()
+        .map(|param| match param {
+            GenericParameter::Type { name, bounds, default_type } => {
+                let mut text = name.clone();
+                if !bounds.is_empty() {
+                    text.push_str(": ");
+                    text.push_str(&bounds.iter().map(format_type).collect::<Vec<_>>().join(" + "));
+                }
+                if let Some(default_type) = default_type {
+                    text.push_str(" = ");
+                    text.push_str(&format_type(default_type));
+                }
+                text
+            }
+            GenericParameter::Const { name, ty } => format!("const {}: {}", name, format_type(ty)),
+        })
+        .collect();
+    format!("[{}]", params.join(", "))
+}
+
+/// The visibility keyword and annotation lines that start a declaration
+pub fn format_head(visibility: &Visibility, annotations: &[Annotation]) -> String {
+    let mut text = String::from(match visibility {
+        Visibility::Default => "",
+        Visibility::Public => "public ",
+        Visibility::Export => "export ",
+        Visibility::Private => "private ",
+    });
+    for annotation in annotations {
+        text.push('#');
+        text.push_str(&annotation.name);
+        if !annotation.args.is_empty() {
+            text.push_str(&format!("({})", format_expression_list(&annotation.args)));
+        }
+        text.push('\n');
+    }
+    text
+}
+
+/// The indented `requires` clause of a type declaration, if it has one
+fn format_requires(requires: &[Type]) -> String {
+    if requires.is_empty() {
+        return String::new();
+    }
+    let mut text = String::from("    requires\n");
+    for ty in requires {
+        text.push_str(&format!("        {}\n", format_type(ty)));
+    }
+    text
+}
+
+/// Render a function signature without its body, e.g.
+/// `extern func write(fd: i32, buf: *u8, len: usize) -> isize`
+pub fn format_signature(sig: &FunctionSignature) -> String {
+    let mut text = format_head(&sig.visibility, &sig.annotations);
+    if sig.is_extern {
+        text.push_str("extern ");
+    }
+    text.push_str(if sig.is_effect { "func! " } else { "func " });
+    if !sig.generic_params.is_empty() {
+        text.pop();
+        text.push_str(&format_generic_params(&sig.generic_params));
+        text.push(' ');
+    }
+    if let Some(receiver) = &sig.receiver {
+        text.push_str(&format_path(receiver));
+        text.push_str("::");
+    }
+    text.push_str(&sig.name);
+    let mut params = Vec::new();
+    if let Some(self_param) = &sig.self_param {
+        params.push(
+            match (self_param.is_pointer, self_param.is_mutable) {
+                (true, true) => "*mut self",
+                (true, false) => "*self",
+                (false, true) => "mut self",
+                (false, false) => "self",
+            }
+            .to_string(),
+        );
+    }
+    params.extend(sig.params.iter().map(|p| format!("{}: {}", p.name, format_type(&p.ty))));
+    text.push_str(&format!("({})", params.join(", ")));
+    if !sig.return_types.is_empty() {
+        text.push_str(" -> ");
+        text.push_str(&format_type_list(&sig.return_types));
+    }
+    text
+}
+
+/// Render a declaration item as source, ending with a newline. Function
+/// bodies and namespace blocks hold statements, which have no source
+/// rendering, so [`NamespaceItem::Function`], [`NamespaceItem::Namespace`],
+/// [`NamespaceItem::Interface`] and [`NamespaceItem::Using`] give `None`.
+/// Where-clause constraints on no parameter of the item are not rendered.
+pub fn format_item(item: &NamespaceItem) -> Option<String> {
+    Some(match item {
+        NamespaceItem::FunctionDeclaration(decl) => format!("{}\n", format_signature(&decl.signature)),
+        NamespaceItem::NamespaceDeclaration(decl) => {
+            format!("{}namespace {}\n", format_head(&decl.visibility, &decl.annotations), format_path(&decl.name))
+        }
+        NamespaceItem::TypeAlias(alias) => format!(
+            "{}type {}{} = {}\n",
+            format_head(&alias.visibility, &alias.annotations),
+            alias.name,
+            format_generic_params(&alias.generic_params),
+            format_type(&alias.aliased_type)
+        ),
+        NamespaceItem::Struct(s) => {
+            let mut text = format_head(&s.visibility, &s.annotations);
+            if s.is_packed {
+                text.push_str("packed ");
+            }
+            text.push_str(&format!("struct {}{}\n", s.name, format_generic_params(&s.generic_params)));
+            text.push_str(&format_requires(&s.requires));
+            for field in &s.fields {
+                text.push_str(&format!("    {}: {}\n", field.name, format_type(&field.ty)));
+            }
+            text
+        }
+        NamespaceItem::Union(u) => {
+            let mut text = format_head(&u.visibility, &u.annotations);
+            text.push_str(&format!("union {}{}\n", u.name, format_generic_params(&u.generic_params)));
+            text.push_str(&format_requires(&u.requires));
+            for variant in &u.variants {
+                text.push_str(&format!("    {}: {}\n", variant.name, format_type(&variant.ty)));
+            }
+            text
+        }
+        NamespaceItem::Enum(e) => {
+            let mut text = format_head(&e.visibility, &e.annotations);
+            text.push_str("enum");
+            if let Some(repr) = &e.representation {
+                text.push_str(&format!("[{}]", format_type(repr)));
+            }
+            text.push_str(&format!(" {}{}\n", e.name, format_generic_params(&e.generic_params)));
+            text.push_str(&format_requires(&e.requires));
+            for variant in &e.variants {
+                match &variant.value {
+                    Some(value) => text.push_str(&format!("    {} = {}\n", variant.name, format_expression(value))),
+                    None => text.push_str(&format!("    {}\n", variant.name)),
+                }
+            }
+            text
+        }
+        NamespaceItem::Const(c) => {
+            let mut text = format_head(&c.visibility, &c.annotations);
+            text.push_str("const");
+            text.push_str(&format_generic_params(&c.generic_params));
+            text.push(' ');
+            for segment in &c.receiver {
+                text.push_str(&segment.name);
+                if !segment.generic_args.is_empty() {
+                    text.push_str(&format!("[{}]", format_type_list(&segment.generic_args)));
+                }
+                text.push_str("::");
+            }
+            text.push_str(&c.name);
+            if let Some(ty) = &c.ty {
+                text.push_str(&format!(": {}", format_type(ty)));
+            }
+            text.push_str(&format!(" = {}\n", format_expression(&c.value)));
+            text
+        }
+        NamespaceItem::Function(_)
+        | NamespaceItem::Namespace(_)
+        | NamespaceItem::Interface(_)
+        | NamespaceItem::Using(_) => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser, Lexer};
+
+    fn roundtrip_type(src: &str) -> String {
+        format_type(&parser::TypeParser::new().parse(Lexer::new(src)).unwrap())
+    }
+
+    fn roundtrip_expr(src: &str) -> String {
+        format_expression(&parser::ExpressionParser::new().parse(Lexer::new(src)).unwrap())
+    }
+
+    #[test]
+    fn test_format_types() {
+        for src in [
+            "u8",
+            "?*mut Node[T]",
+            "*[u8; 16]",
+            "[u8]",
+            "?i32",
+            "std::HashMap[K, V]",
+            "*u8 ! IoError",
+        ] {
+            assert_eq!(roundtrip_type(src), src);
+        }
+    }
+
+    #[test]
+    fn test_format_expressions() {
+        for src in [
+            "(a + b) * 2",
+            "-x as u64",
+            "obj.field.!inner",
+            "Vec::new(1, 2u8, 0xff)",
+            "sizeof(*mut u8) + offsetof(Header, len)",
+            "arr[i]",
+            "c == 'a'",
+            "buf[i + 1..=n]",
+            "..",
+        ] {
+            assert_eq!(roundtrip_expr(src), src);
+        }
+    }
+
+    #[test]
+    fn test_format_items() {
+        let src = "\
+#align(8)
+packed struct Header
+    tag: u8
+    len: u32
+
+export enum[u16] Kind
+    A
+    B = 7
+
+union Shape[T]
+    circle: f64
+    other: T
+
+public type Bytes = [u8]
+
+export const LIMIT: u16 = 4 * 16
+
+const[T] Seq[T]::CAPACITY = 8
+
+extern func! write(fd: i32, buf: *u8, len: usize) -> isize
+
+func[T: Hash + Eq, const N: usize] Table[T]::get(*self, key: T) -> ?T
+";
+        let sf = crate::SourceFileParser::new().parse(Lexer::new(src)).unwrap();
+        let items: Vec<String> = sf.items.iter().map(|item| format_item(item).unwrap()).collect();
+        assert_eq!(items.join("\n"), src);
+    }
+
+    #[test]
+    fn test_escape_literal() {
+        assert_eq!(format_expression(&Expression::StringLiteral("a\"b\n".into())), "\"a\\\"b\\n\"");
+        assert_eq!(format_expression(&Expression::CharLiteral("'".into())), "'\\''");
+    }
+}
@@ -0,0 +1,115 @@
+//! The syntax tree as a JSON document, for tools outside the compiler
+//!
+//! A document is an object holding the [`SCHEMA_VERSION`] it was written
+//! with and the source file:
+//!
+//! ```json
+//! { "version": 4, "file": { "items": [ ... ] } }
+//! ```
+//!
+//! Each node is serialized the way serde does by default: a struct as an
+//! object of its fields, a unit variant as a string such as `"I32"`, and
+//! any other variant as an object with one key, its name. Block spans are
+//! positions rather than structure and are left out, so a tree read back
+//! equals the one written but has no statement spans.
+//!
+//! The version changes whenever a node is added, removed or reshaped, and
+//! documents of another version are refused rather than half read.
+//! [`schema`] describes the current version as a JSON Schema; it is
+//! published as `docs/static/schema/fig-ast-v4.json`, and replaces the
+//! file of the version before it.
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::ast::SourceFile;
+
+/// The version of the document format this compiler reads and writes
+pub const SCHEMA_VERSION: u32 = 4;
+
+/// A source file with the version of the format it is written in
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[schemars(title = "Fig syntax tree")]
+pub struct Document {
+    /// The version of the format the document is written in
+    #[schemars(extend("const" = SCHEMA_VERSION))]
+    pub version: u32,
+    pub file: SourceFile,
+}
+
+/// Why a document could not be read
+#[derive(Debug)]
+pub enum JsonError {
+    /// The text is not JSON, or not a syntax tree
+    Invalid(serde_json::Error),
+    /// The document has no `version`
+    MissingVersion,
+    /// The document is written in another version of the format
+    Version(u64),
+}
+
+impl std::fmt::Display for JsonError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            JsonError::Invalid(error) => write!(f, "invalid syntax tree: {}", error),
+            JsonError::MissingVersion => write!(f, "the syntax tree has no `version`"),
+            JsonError::Version(version) => write!(
+                f,
+                "the syntax tree is version {}, but this compiler reads version {}",
+                version, SCHEMA_VERSION
+            ),
+        }
+    }
+}
+
+impl std::error::Error for JsonError {}
+
+/// Write `file` as a pretty-printed document
+pub fn to_json(file: &SourceFile) -> String {
+    let document = Document { version: SCHEMA_VERSION, file: file.clone() };
+    serde_json::to_string_pretty(&document).expect("a syntax tree is always valid JSON")
+}
+
+/// Read the source file of a document, checking its version first so that
+/// a document of another version is reported as such rather than as
+/// whichever node first fails to match
+pub fn from_json(text: &str) -> Result<SourceFile, JsonError> {
+    let mut document: serde_json::Value = serde_json::from_str(text).map_err(JsonError::Invalid)?;
+    let version = document.get("version").ok_or(JsonError::MissingVersion)?;
+    match version.as_u64() {
+        Some(version) if version == u64::from(SCHEMA_VERSION) => {}
+        Some(version) => return Err(JsonError::Version(version)),
+        None => return Err(JsonError::MissingVersion),
+    }
+    let file = document.get_mut("file").map(serde_json::Value::take).unwrap_or_default();
+    serde_json::from_value(file).map_err(JsonError::Invalid)
+}
+
+/// The JSON Schema of a [`Document`] of the current version, pretty-printed
+pub fn schema() -> String {
+    let schema = schemars::schema_for!(Document);
+    let mut text = serde_json::to_string_pretty(&schema).expect("a schema is always valid JSON");
+    text.push('\n');
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Lexer, SourceFileParser};
+
+    #[test]
+    fn test_round_trip_and_version() {
+        let src = "func main() -> i32\n    let x = 0x2Au8\n    return 1.5e3 as i32\n";
+        let file = SourceFileParser::new().parse(Lexer::new(src)).unwrap();
+        let json = to_json(&file);
+        assert!(json.contains("\"version\": 4"), "{}", json);
+        assert_eq!(from_json(&json).unwrap(), file);
+
+        let older = json.replacen("\"version\": 4", "\"version\": 3", 1);
+        let error = from_json(&older).unwrap_err();
+        assert_eq!(error.to_string(), "the syntax tree is version 3, but this compiler reads version 4");
+        assert!(matches!(from_json("{\"file\": {}}"), Err(JsonError::MissingVersion)));
+        assert!(matches!(from_json("{\"version\": 4, \"file\": {}}"), Err(JsonError::Invalid(_))));
+    }
+}
@@ -2,7 +2,12 @@ use lalrpop_util::lalrpop_mod;
 use fig_lexer::{IndentLexer, Token};
 
 pub mod ast;
+pub mod fold;
+pub mod json;
+pub mod format;
 pub mod pretty_print;
+pub mod visit;
+pub mod visit_mut;
 
 /// Split the raw content of an interpolated-string literal into text and
 /// expression-placeholder parts.
@@ -62,10 +67,101 @@ pub fn parse_interp_parts(raw: String) -> Vec<ast::InterpolatedPart> {
     parts
 }
 
+/// An argument between the parentheses of a call or struct literal, before
+/// the parser knows which of the two it is reading
+pub enum Argument {
+    /// A value, with the offset it starts at
+    Positional { value: ast::Expression, start: usize },
+    /// `name: value`, with the span of its `:`
+    Named { name: String, value: ast::Expression, colon: (usize, usize) },
+}
+
+/// A call, or a struct literal when an argument is named.
+///
+/// In a literal every argument must be named, a plain name (see
+/// [`ast::FieldInit`]) or, last, the base written as `..base`; the callee
+/// must be a type's path, and `!` cannot propagate from it. Otherwise the
+/// first `:`, or a base's `..`, is the unexpected token. Without a named
+/// argument, `..base` is a range like any other until
+/// `fig_sema::construct` finds a struct is being called.
+pub fn call_or_literal(
+    callee: ast::Expression,
+    args: Vec<Argument>,
+    is_propagating: bool,
+) -> Result<ast::Expression, lalrpop_util::ParseError<usize, Token, LexicalError>> {
+    use ast::{CallExpr, Expression, FieldInit, RangeExpr, StructLiteralExpr};
+
+    let Some(colon) = args.iter().find_map(|arg| match arg {
+        Argument::Positional { .. } => None,
+        Argument::Named { colon, .. } => Some(*colon),
+    }) else {
+        let args = args.into_iter().filter_map(|arg| match arg {
+            Argument::Positional { value, .. } => Some(value),
+            Argument::Named { .. } => None,
+        });
+        return Ok(Expression::Call(CallExpr { callee: Box::new(callee), args: args.collect(), is_propagating }));
+    };
+    let unexpected = |token: Token, (start, end): (usize, usize)| lalrpop_util::ParseError::UnrecognizedToken {
+        token: (start, token, end),
+        expected: Vec::new(),
+    };
+    let ty = match type_path(&callee) {
+        Some(ty) if !is_propagating => ty,
+        _ => return Err(unexpected(Token::Colon, colon)),
+    };
+    let count = args.len();
+    let mut fields = Vec::with_capacity(count);
+    let mut base = None;
+    for (i, arg) in args.into_iter().enumerate() {
+        match arg {
+            Argument::Named { name, value, .. } => fields.push(FieldInit { name: Some(name), value }),
+            Argument::Positional { value: Expression::Path(path), .. }
+                if path.segments.len() == 1 && path.generic_args.is_empty() =>
+            {
+                fields.push(FieldInit { name: Some(path.segments[0].clone()), value: Expression::Path(path) })
+            }
+            Argument::Positional {
+                value: Expression::Range(RangeExpr { start: None, end: Some(value), is_inclusive: false }),
+                start,
+            } => {
+                if i + 1 != count {
+                    return Err(unexpected(Token::DotDot, (start, start + 2)));
+                }
+                base = Some(value);
+            }
+            Argument::Positional { .. } => return Err(unexpected(Token::Colon, colon)),
+        }
+    }
+    Ok(Expression::StructLiteral(StructLiteralExpr { ty, fields, base }))
+}
+
+/// The type a callee names when it is a path: `Point`, `geo::Point` or
+/// `Pair[T]`, which parses as an index
+fn type_path(callee: &ast::Expression) -> Option<ast::Path> {
+    use ast::{Expression, Type};
+
+    match callee {
+        Expression::Path(path) => Some(path.clone()),
+        Expression::TypeAccess(access) if !matches!(*access.object, Expression::Index(_)) => {
+            let mut path = type_path(&access.object)?;
+            path.segments.push(access.member.clone());
+            Some(path)
+        }
+        Expression::Index(index) => match (type_path(&index.object)?, index.index.as_ref()) {
+            (mut path, Expression::Path(arg)) if path.generic_args.is_empty() => {
+                path.generic_args.push(Type::Path(arg.clone()));
+                Some(path)
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod tests;
 
-lalrpop_mod!(pub parser);
+lalrpop_mod!(#[allow(clippy::all)] pub parser);
 
 pub use parser::*;
 
@@ -265,6 +265,53 @@ impl PrettyPrinter {
                 }
                 self.indent_level -= 1;
             }
+            Expression::StructLiteral(lit) => {
+                writeln!(output, "{}StructLiteral: {}", p, Self::format_path_inline(&lit.ty)).unwrap();
+                self.indent_level += 1;
+                for (i, field) in lit.fields.iter().enumerate() {
+                    let is_last = i == lit.fields.len() - 1 && lit.base.is_none();
+                    match &field.name {
+                        Some(name) => writeln!(output, "{}{}:", self.prefix(is_last), name).unwrap(),
+                        None => writeln!(output, "{}#{}:", self.prefix(is_last), i).unwrap(),
+                    }
+                    self.indent_level += 1;
+                    self.format_expression(&field.value, output, true);
+                    self.indent_level -= 1;
+                }
+                if let Some(base) = &lit.base {
+                    writeln!(output, "{}base:", self.prefix(true)).unwrap();
+                    self.indent_level += 1;
+                    self.format_expression(base, output, true);
+                    self.indent_level -= 1;
+                }
+                self.indent_level -= 1;
+            }
+            Expression::VariantLiteral(lit) => {
+                writeln!(output, "{}VariantLiteral: {}::{}", p, Self::format_path_inline(&lit.ty), lit.variant).unwrap();
+                if let Some(payload) = &lit.payload {
+                    self.indent_level += 1;
+                    self.format_expression(payload, output, true);
+                    self.indent_level -= 1;
+                }
+            }
+            Expression::EnumVariant(variant) => {
+                writeln!(output, "{}EnumVariant: {}::{}", p, Self::format_path_inline(&variant.ty), variant.variant)
+                    .unwrap();
+            }
+            Expression::Range(range) => {
+                let kind = if range.is_inclusive { "inclusive" } else { "half-open" };
+                writeln!(output, "{}Range ({})", p, kind).unwrap();
+                self.indent_level += 1;
+                for (label, bound) in [("start", &range.start), ("end", &range.end)] {
+                    if let Some(bound) = bound {
+                        writeln!(output, "{}{}:", self.indent(), label).unwrap();
+                        self.indent_level += 1;
+                        self.format_expression(bound, output, true);
+                        self.indent_level -= 1;
+                    }
+                }
+                self.indent_level -= 1;
+            }
             Expression::BinaryOp(op) => {
                 writeln!(output, "{}BinaryOp: {:?}", p, op.op).unwrap();
                 self.indent_level += 1;
@@ -370,6 +417,40 @@ impl PrettyPrinter {
                 self.format_expression(inner, output, true);
                 self.indent_level -= 1;
             }
+            Expression::Assign(assign) => {
+                writeln!(output, "{}Assign: {:?}", p, assign.op).unwrap();
+                self.indent_level += 1;
+                writeln!(output, "{}target:", self.indent()).unwrap();
+                self.indent_level += 1;
+                self.format_expression(&assign.lhs, output, true);
+                self.indent_level -= 1;
+                writeln!(output, "{}value:", self.indent()).unwrap();
+                self.indent_level += 1;
+                self.format_expression(&assign.rhs, output, true);
+                self.indent_level -= 2;
+            }
+            Expression::Lambda(lambda) => {
+                writeln!(output, "{}Lambda{}", p, if lambda.is_effect { "!" } else { "" }).unwrap();
+                self.indent_level += 1;
+                if !lambda.params.is_empty() {
+                    writeln!(output, "{}params:", self.indent()).unwrap();
+                    self.indent_level += 1;
+                    for (i, param) in lambda.params.iter().enumerate() {
+                        self.format_function_parameter(param, output, i == lambda.params.len() - 1);
+                    }
+                    self.indent_level -= 1;
+                }
+                if let Some(return_type) = &lambda.return_type {
+                    writeln!(output, "{}return_type:", self.indent()).unwrap();
+                    self.indent_level += 1;
+                    self.format_type(return_type, output, true);
+                    self.indent_level -= 1;
+                }
+                writeln!(output, "{}body:", self.indent()).unwrap();
+                self.indent_level += 1;
+                self.format_expression(&lambda.body, output, true);
+                self.indent_level -= 2;
+            }
         }
     }
 
@@ -406,9 +487,20 @@ impl PrettyPrinter {
                 self.format_type(element_type, output, true);
                 self.indent_level -= 2;
             }
+            Type::Optional(inner) => {
+                writeln!(output, "{}Type: Optional", p).unwrap();
+                self.indent_level += 1;
+                writeln!(output, "{}inner_type:", self.indent()).unwrap();
+                self.indent_level += 1;
+                self.format_type(inner, output, true);
+                self.indent_level -= 2;
+            }
             Type::Path(path) => {
                 writeln!(output, "{}Type: Path({})", p, Self::format_path_inline(path)).unwrap();
             }
+            Type::Const(value) => {
+                writeln!(output, "{}Type: Const({})", p, value).unwrap();
+            }
             Type::Array { element_type, size } => {
                 writeln!(output, "{}Type: Array", p).unwrap();
                 self.indent_level += 1;
@@ -432,6 +524,25 @@ impl PrettyPrinter {
                 writeln!(output, "{}err_type: {}", self.indent(), Self::format_path_inline(err_type)).unwrap();
                 self.indent_level -= 1;
             }
+            Type::Function(function) => {
+                writeln!(output, "{}Type: Function{}", p, if function.is_effect { "!" } else { "" }).unwrap();
+                self.indent_level += 1;
+                if !function.params.is_empty() {
+                    writeln!(output, "{}params:", self.indent()).unwrap();
+                    self.indent_level += 1;
+                    for (i, param) in function.params.iter().enumerate() {
+                        self.format_type(param, output, i == function.params.len() - 1);
+                    }
+                    self.indent_level -= 1;
+                }
+                if let Some(return_type) = &function.return_type {
+                    writeln!(output, "{}return_type:", self.indent()).unwrap();
+                    self.indent_level += 1;
+                    self.format_type(return_type, output, true);
+                    self.indent_level -= 1;
+                }
+                self.indent_level -= 1;
+            }
         }
     }
 
@@ -681,6 +792,15 @@ impl PrettyPrinter {
             Statement::Pass => {
                 writeln!(output, "{}Pass", p).unwrap();
             }
+            Statement::Break(Some(label)) => {
+                writeln!(output, "{}Break: {}", p, label).unwrap();
+            }
+            Statement::Break(None) => {
+                writeln!(output, "{}Break", p).unwrap();
+            }
+            Statement::Continue => {
+                writeln!(output, "{}Continue", p).unwrap();
+            }
             Statement::Expression(e) => {
                 writeln!(output, "{}Expression:", p).unwrap();
                 self.indent_level += 1;
@@ -1367,6 +1487,14 @@ mod tests {
         assert!(out.contains("?*mut"));
     }
 
+    #[test]
+    fn test_print_optional_type() {
+        let ty = Type::Optional(Box::new(Type::I32));
+        let out = PrettyPrinter::new().print_type(&ty);
+        assert!(out.contains("Type: Optional"));
+        assert!(out.contains("I32"));
+    }
+
     #[test]
     fn test_print_path_type() {
         let ty = Type::Path(Path::simple("MyStruct".to_string()));
@@ -1506,7 +1634,7 @@ mod tests {
     fn test_print_if_statement() {
         let stmt = Statement::If(IfStatement {
             condition: Box::new(Expression::BooleanLiteral(true)),
-            then_body: Block { statements: vec![Statement::Pass] },
+            then_body: Block::new(vec![Statement::Pass]),
             elif_clauses: vec![],
             else_body: None,
         });
@@ -1520,12 +1648,12 @@ mod tests {
     fn test_print_if_elif_else() {
         let stmt = Statement::If(IfStatement {
             condition: Box::new(Expression::BooleanLiteral(true)),
-            then_body: Block { statements: vec![] },
+            then_body: Block::new(vec![]),
             elif_clauses: vec![ElifClause {
                 condition: Box::new(Expression::BooleanLiteral(false)),
-                body: Block { statements: vec![] },
+                body: Block::new(vec![]),
             }],
-            else_body: Some(Block { statements: vec![Statement::Pass] }),
+            else_body: Some(Block::new(vec![Statement::Pass])),
         });
         let out = PrettyPrinter::new().print_statement(&stmt);
         assert!(out.contains("elif:"));
@@ -1537,7 +1665,7 @@ mod tests {
         let stmt = Statement::For(ForStatement {
             pattern: "item".to_string(),
             iterable: Box::new(Expression::Path(Path::simple("items".to_string()))),
-            body: Block { statements: vec![] },
+            body: Block::new(vec![]),
         });
         let out = PrettyPrinter::new().print_statement(&stmt);
         assert!(out.contains("For: item in"));
@@ -1547,7 +1675,7 @@ mod tests {
     fn test_print_while_statement() {
         let stmt = Statement::While(WhileStatement {
             condition: Box::new(Expression::BooleanLiteral(true)),
-            body: Block { statements: vec![] },
+            body: Block::new(vec![]),
         });
         assert!(PrettyPrinter::new().print_statement(&stmt).contains("While"));
     }
@@ -1562,6 +1690,7 @@ mod tests {
             is_packed: false,
             name: "Point".to_string(),
             generic_params: vec![],
+            unbound_constraints: vec![],
             requires: vec![],
             fields: vec![
                 StructField { name: "x".to_string(), ty: Type::F32 },
@@ -1582,6 +1711,7 @@ mod tests {
             is_packed: true,
             name: "Header".to_string(),
             generic_params: vec![],
+            unbound_constraints: vec![],
             requires: vec![],
             fields: vec![],
         };
@@ -1596,6 +1726,7 @@ mod tests {
             name: "Color".to_string(),
             representation: None,
             generic_params: vec![],
+            unbound_constraints: vec![],
             requires: vec![],
             variants: vec![
                 EnumVariant { name: "Red".to_string(), value: None },
@@ -1615,6 +1746,7 @@ mod tests {
             annotations: vec![],
             name: "Val".to_string(),
             generic_params: vec![],
+            unbound_constraints: vec![],
             requires: vec![],
             variants: vec![
                 UnionVariant { name: "i".to_string(), ty: Type::I32 },
@@ -1633,6 +1765,7 @@ mod tests {
             annotations: vec![],
             name: "Display".to_string(),
             generic_params: vec![],
+            unbound_constraints: vec![],
             extends: vec![],
             requires: vec![],
             methods: vec![],
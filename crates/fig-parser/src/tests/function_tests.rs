@@ -125,6 +125,30 @@ fn test_function_with_where_clause() {
     }
 }
 
+#[test]
+fn test_method_where_clause_on_receiver_argument() {
+    // `T` is bound by the receiver, not by the function, so it is not added to generic_params
+    let input = "func Vec[T]::sort(*mut self)\n    where\n        T: Ord\n    pass\n";
+    let f = parser::FunctionParser::new().parse(Lexer::new(input)).unwrap();
+    let sig = f.signature;
+    assert!(sig.generic_params.is_empty());
+    assert_eq!(sig.unbound_constraints.len(), 1);
+    assert_eq!(sig.unbound_constraints[0].name(), "T");
+}
+
+#[test]
+fn test_bounded_generic_with_default() {
+    let input = "func[T: Hash = u64] digest(x: T) -> u64\n    pass\n";
+    let f = parser::FunctionParser::new().parse(Lexer::new(input)).unwrap();
+    if let GenericParameter::Type { name, bounds, default_type } = &f.signature.generic_params[0] {
+        assert_eq!(name, "T");
+        assert_eq!(bound_name(&bounds[0]), "Hash");
+        assert_eq!(default_type.as_deref(), Some(&Type::U64));
+    } else {
+        panic!("Expected T: Hash = u64");
+    }
+}
+
 #[test]
 fn test_function_with_complex_types() {
     let input = "func[T] process(ptr: *i32, arr: [u8], ref_val: *mut T) -> *u32\n    pass\n";
@@ -249,3 +273,32 @@ fn test_function_with_trailing_comma() {
     let f = parser::FunctionParser::new().parse(Lexer::new(input)).unwrap();
     assert_eq!(f.signature.params.len(), 2);
 }
+
+#[test]
+fn test_function_with_assignments() {
+    let input = "func! bump(p: *mut i32)\n    *p = *p + 1\n    *p <<= 2\n";
+    let f = parser::FunctionParser::new().parse(Lexer::new(input)).unwrap();
+    let ops: Vec<_> = f
+        .body
+        .statements
+        .iter()
+        .map(|stmt| match stmt {
+            Statement::Expression(e) => match e.as_ref() {
+                Expression::Assign(assign) => assign.op,
+                other => panic!("Expected assignment, got {:?}", other),
+            },
+            other => panic!("Expected expression statement, got {:?}", other),
+        })
+        .collect();
+    assert_eq!(ops, vec![AssignOperator::Assign, AssignOperator::ShlAssign]);
+}
+
+#[test]
+fn test_function_with_generic_receiver() {
+    let input = "func[K, V] HashMap[K, V]::insert(*mut self, key: K, value: V) -> ?V\n    pass\n";
+    let f = parser::FunctionParser::new().parse(Lexer::new(input)).unwrap();
+    let receiver = f.signature.receiver.unwrap();
+    assert_eq!(receiver.segments, vec!["HashMap"]);
+    assert_eq!(receiver.generic_args.len(), 2);
+    assert_eq!(f.signature.name, "insert");
+}
@@ -57,25 +57,96 @@ fn test_binary_add() {
 
 #[test]
 fn test_precedence_mul_add() {
-    // In LALRPOP, lower level number = tighter binding.
-    // + is level 9, * is level 10, so + binds tighter than *.
-    // Thus: 2 + 3 * 4 parses as (2 + 3) * 4 = Multiply(Add(2,3), 4)
+    // `*` binds tighter than `+`: 2 + 3 * 4 parses as Add(2, Multiply(3, 4))
     let input = "2 + 3 * 4";
     let lexer = Lexer::new(input);
     let result = parser::ExpressionParser::new().parse(lexer);
     assert!(result.is_ok());
-    if let Expression::BinaryOp(mul_op) = result.unwrap() {
-        assert_eq!(mul_op.op, BinaryOperator::Multiply);
-        if let Expression::BinaryOp(add_op) = mul_op.lhs.as_ref() {
-            assert_eq!(add_op.op, BinaryOperator::Add);
+    if let Expression::BinaryOp(add_op) = result.unwrap() {
+        assert_eq!(add_op.op, BinaryOperator::Add);
+        if let Expression::BinaryOp(mul_op) = add_op.rhs.as_ref() {
+            assert_eq!(mul_op.op, BinaryOperator::Multiply);
         } else {
-            panic!("Expected addition on LHS of multiply");
+            panic!("Expected multiplication on RHS of addition");
         }
     } else {
-        panic!("Expected multiplication at root");
+        panic!("Expected addition at root");
+    }
+}
+
+#[test]
+fn test_precedence_comparison_and_logic() {
+    // a == b + 1 && c < d  =>  And(Eq(a, Add(b, 1)), Lt(c, d))
+    let expr = parser::ExpressionParser::new().parse(Lexer::new("a == b + 1 && c < d")).unwrap();
+    let Expression::BinaryOp(and) = expr else { panic!("Expected binary operation") };
+    assert_eq!(and.op, BinaryOperator::LogicalAnd);
+    let Expression::BinaryOp(eq) = and.lhs.as_ref() else { panic!("Expected equality") };
+    assert_eq!(eq.op, BinaryOperator::Equal);
+    assert!(matches!(eq.rhs.as_ref(), Expression::BinaryOp(BinaryOpExpr { op: BinaryOperator::Add, .. })));
+    assert!(matches!(and.rhs.as_ref(), Expression::BinaryOp(BinaryOpExpr { op: BinaryOperator::LessThan, .. })));
+}
+
+#[test]
+fn test_postfix_binds_tighter_than_prefix() {
+    // *p.x is *(p.x), and a postfix expression may be followed by a binary operator
+    let expr = parser::ExpressionParser::new().parse(Lexer::new("*p.x")).unwrap();
+    let Expression::UnaryOp(deref) = expr else { panic!("Expected unary operation") };
+    assert_eq!(deref.op, UnaryOperator::Dereference);
+    assert!(matches!(deref.operand.as_ref(), Expression::FieldAccess(_)));
+
+    let expr = parser::ExpressionParser::new().parse(Lexer::new("arr[i] == x")).unwrap();
+    assert!(matches!(expr, Expression::BinaryOp(BinaryOpExpr { op: BinaryOperator::Equal, .. })));
+
+    // `as` binds tighter than arithmetic: (x as u64) + 1
+    let expr = parser::ExpressionParser::new().parse(Lexer::new("x as u64 + 1")).unwrap();
+    let Expression::BinaryOp(add) = expr else { panic!("Expected binary operation") };
+    assert!(matches!(add.lhs.as_ref(), Expression::Cast(_)));
+}
+
+#[test]
+fn test_parse_ranges() {
+    let range = |input: &str| match parser::ExpressionParser::new().parse(Lexer::new(input)) {
+        Ok(Expression::Range(range)) => range,
+        other => panic!("Expected range for {:?}, got {:?}", input, other),
+    };
+    // A range binds looser than arithmetic: (a + 1)..(n * 2)
+    let r = range("a + 1..n * 2");
+    assert!(!r.is_inclusive);
+    assert!(matches!(r.start.as_deref(), Some(Expression::BinaryOp(BinaryOpExpr { op: BinaryOperator::Add, .. }))));
+    assert!(matches!(r.end.as_deref(), Some(Expression::BinaryOp(BinaryOpExpr { op: BinaryOperator::Multiply, .. }))));
+
+    assert!(range("0..=9").is_inclusive);
+    let r = range("..=n");
+    assert!(r.is_inclusive && r.start.is_none() && r.end.is_some());
+    let r = range("i..");
+    assert!(r.start.is_some() && r.end.is_none());
+    let r = range("..");
+    assert!(r.start.is_none() && r.end.is_none());
+
+    // Bounds are written inside a slice's brackets
+    let expr = parser::ExpressionParser::new().parse(Lexer::new("buf[2..n]")).unwrap();
+    let Expression::Index(index) = expr else { panic!("Expected index") };
+    assert!(matches!(index.index.as_ref(), Expression::Range(_)));
+
+    // Ranges do not chain, and an inclusive range needs its end
+    for input in ["a..b..c", "0..=", "a..=b..c"] {
+        assert!(parser::ExpressionParser::new().parse(Lexer::new(input)).is_err(), "{} parsed", input);
     }
 }
 
+#[test]
+fn test_parse_break_and_continue() {
+    let input = "func f()\n    block outer\n        while true\n            continue\n        break outer\n    while true\n        break\n";
+    let sf = parser::SourceFileParser::new().parse(Lexer::new(input)).unwrap();
+    let NamespaceItem::Function(f) = &sf.items[0] else { panic!("Expected function") };
+    let Statement::Block(outer) = &f.body.statements[0] else { panic!("Expected block") };
+    let Statement::While(inner) = &outer.body.statements[0] else { panic!("Expected while") };
+    assert_eq!(inner.body.statements, vec![Statement::Continue]);
+    assert_eq!(outer.body.statements[1], Statement::Break(Some("outer".into())));
+    let Statement::While(second) = &f.body.statements[1] else { panic!("Expected while") };
+    assert_eq!(second.body.statements, vec![Statement::Break(None)]);
+}
+
 #[test]
 fn test_unary_negation() {
     let input = "-5";
@@ -431,6 +502,23 @@ fn test_parse_nullable_pointer_error_union() {
     }
 }
 
+#[test]
+fn test_parse_optional_type() {
+    let result = parser::TypeParser::new().parse(Lexer::new("?i32"));
+    assert_eq!(result.unwrap(), Type::Optional(Box::new(Type::I32)));
+}
+
+#[test]
+fn test_parse_optional_error_union() {
+    // ?T ! E  should be  (?T) ! E
+    let result = parser::TypeParser::new().parse(Lexer::new("?Config ! IoError"));
+    if let Type::ErrorUnion { ok_type, .. } = result.unwrap() {
+        assert!(matches!(*ok_type, Type::Optional(_)));
+    } else {
+        panic!("Expected ErrorUnion");
+    }
+}
+
 #[test]
 fn test_parse_as_expression_with_error_union() {
     // x as i32 ! E  should be  x as (i32 ! E)
@@ -445,6 +533,25 @@ fn test_parse_as_expression_with_error_union() {
     }
 }
 
+#[test]
+fn test_parse_propagating_call() {
+    let result = parser::ExpressionParser::new().parse(Lexer::new("v.push!(42)"));
+    if let Expression::Call(call) = result.unwrap() {
+        assert!(call.is_propagating);
+        assert!(matches!(*call.callee, Expression::FieldAccess(_)));
+        assert_eq!(call.args.len(), 1);
+    } else {
+        panic!("Expected Call expression");
+    }
+}
+
+#[test]
+fn test_parse_logical_not_of_parenthesized() {
+    // A leading `!(` is still logical not, not a propagating call
+    let result = parser::ExpressionParser::new().parse(Lexer::new("!(mask)"));
+    assert!(matches!(result.unwrap(), Expression::UnaryOp(UnaryOpExpr { op: UnaryOperator::LogicalNot, .. })));
+}
+
 #[test]
 fn test_parse_generics_list() {
     let input = "[T: Mappable, U: Copy, const N: usize]";
@@ -103,6 +103,26 @@ mod struct_tests {
         }
     }
 
+    #[test]
+    fn test_struct_where_clause_keeps_unbound_constraints() {
+        // Constraints on undeclared names are kept aside rather than invented as parameters;
+        // a const constraint restating the declaration is accepted as-is.
+        let input = "struct Buffer[T, const N: usize]\n    where\n        U: Clone\n        const N: usize\n        const T: usize\n    data: [T; N]\n";
+        let s = parser::StructParser::new().parse(Lexer::new(input)).unwrap();
+        assert_eq!(s.generic_params.len(), 2);
+        let unbound: Vec<&str> = s.unbound_constraints.iter().map(GenericParameter::name).collect();
+        assert_eq!(unbound, vec!["U", "T"]);
+    }
+
+    #[test]
+    fn test_struct_field_with_const_generic_argument() {
+        let input = "struct Grid\n    cells: Array[u8, 4]\n";
+        let s = parser::StructParser::new().parse(Lexer::new(input)).unwrap();
+        let Type::Path(path) = &s.fields[0].ty else { panic!("Expected path type") };
+        assert_eq!(path.generic_args[0], Type::U8);
+        assert!(matches!(&path.generic_args[1], Type::Const(e) if matches!(**e, Expression::IntegerLiteral(_))));
+    }
+
     #[test]
     fn test_struct_with_requires_clause() {
         let input = "struct Container[T]\n    requires\n        Clone\n        Send\n    value: T\n";
@@ -417,3 +437,78 @@ mod union_tests {
         } else { panic!("Expected const parameter"); }
     }
 }
+
+#[cfg(test)]
+mod literal_tests {
+    use crate::ast::*;
+    use crate::format::format_expression;
+    use crate::{Lexer, parser};
+
+    fn parse(input: &str) -> Result<Expression, String> {
+        parser::ExpressionParser::new().parse(Lexer::new(input)).map_err(|e| format!("{:?}", e))
+    }
+
+    #[test]
+    fn test_named_fields_and_shorthand() {
+        let Expression::StructLiteral(lit) = parse("Point(x: 1, y)").unwrap() else {
+            panic!("Expected struct literal")
+        };
+        assert_eq!(lit.ty.segments, ["Point"]);
+        assert_eq!(lit.fields[0].name.as_deref(), Some("x"));
+        assert_eq!(lit.fields[1].name.as_deref(), Some("y"));
+        assert!(matches!(&lit.fields[1].value, Expression::Path(p) if p.segments == ["y"]));
+        assert!(lit.base.is_none());
+    }
+
+    #[test]
+    fn test_base_and_qualified_type() {
+        let Expression::StructLiteral(lit) = parse("geo::Point(x: 1, ..origin)").unwrap() else {
+            panic!("Expected struct literal")
+        };
+        assert_eq!(lit.ty.segments, ["geo", "Point"]);
+        assert!(matches!(lit.base.as_deref(), Some(Expression::Path(_))));
+
+        // Without a named field the base is a range argument until the
+        // callee is known to be a struct
+        let Expression::Call(copy) = parse("Point(..origin)").unwrap() else { panic!("Expected call") };
+        assert!(matches!(&copy.args[0], Expression::Range(RangeExpr { start: None, end: Some(_), .. })));
+    }
+
+    #[test]
+    fn test_generic_arguments_on_the_type() {
+        let Expression::StructLiteral(lit) = parse("Pair[T](first: a, second: b,)").unwrap() else {
+            panic!("Expected struct literal")
+        };
+        assert_eq!(lit.ty.segments, ["Pair"]);
+        assert_eq!(lit.ty.generic_args.len(), 1);
+    }
+
+    #[test]
+    fn test_positional_arguments_stay_a_call() {
+        assert!(matches!(parse("Point(1, 2)").unwrap(), Expression::Call(_)));
+    }
+
+    #[test]
+    fn test_round_trips_through_format() {
+        for source in ["Point(x: 1, y)", "geo::Point(x: 1, ..origin)", "Point(..origin)", "Point(x: x, y: y)"] {
+            assert_eq!(format_expression(&parse(source).unwrap()), source);
+        }
+        // Only a literal with another named field may abbreviate
+        assert_eq!(format_expression(&parse("Point(x: 1, y: y)").unwrap()), "Point(x: 1, y)");
+        // and a base alone does not keep `Point(y, ..origin)` from being a call
+        let Expression::StructLiteral(mut lit) = parse("Point(x: 1, y, ..origin)").unwrap() else {
+            panic!("Expected struct literal")
+        };
+        lit.fields.remove(0);
+        assert_eq!(format_expression(&Expression::StructLiteral(lit)), "Point(y: y, ..origin)");
+    }
+
+    #[test]
+    fn test_malformed_literals_are_rejected() {
+        // A positional value next to a named field, a base that is not last,
+        // a named field in a propagating call and a callee that is not a type
+        for source in ["Point(1, y: 2)", "Point(..o, x: 1)", "Point!(x: 1)", "f(x)(y: 1)", "Point(x: 1, ..o, ..p)"] {
+            assert!(parse(source).is_err(), "{} parsed", source);
+        }
+    }
+}
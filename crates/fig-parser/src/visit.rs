@@ -0,0 +1,679 @@
+//! Traversal of the syntax tree
+//!
+//! [`Visit`] walks a tree by shared reference and [`VisitMut`](crate::visit_mut::VisitMut)
+//! by mutable reference. Each has a method per kind of node whose default
+//! calls the matching `walk_` function, which visits the node's children in
+//! source order. An implementation overrides the methods for the nodes it
+//! cares about, calling the `walk_` function itself to carry on below them:
+//!
+//! ```ignore
+//! struct Calls(usize);
+//!
+//! impl<'ast> Visit<'ast> for Calls {
+//!     fn visit_expression(&mut self, expression: &'ast Expression) {
+//!         self.0 += matches!(expression, Expression::Call(_)) as usize;
+//!         walk_expression(self, expression);
+//!     }
+//! }
+//! ```
+//!
+//! Both traits are generated from the one traversal in this file, as is
+//! [`Fold`](crate::fold::Fold), which is built on `VisitMut`. Its matches and
+//! destructuring patterns are exhaustive, so a new node or field is a
+//! compile error here until the traversal covers it.
+
+use crate::ast::*;
+
+macro_rules! visitor {
+    ($Visit:ident $(<$lt:lifetime>)?, $($mutability:tt)?) => {
+        pub trait $Visit$(<$lt>)? {
+            fn visit_source_file(&mut self, file: &$($lt)? $($mutability)? SourceFile) {
+                walk_source_file(self, file)
+            }
+
+            fn visit_namespace_item(&mut self, item: &$($lt)? $($mutability)? NamespaceItem) {
+                walk_namespace_item(self, item)
+            }
+
+            fn visit_namespace(&mut self, namespace: &$($lt)? $($mutability)? Namespace) {
+                walk_namespace(self, namespace)
+            }
+
+            fn visit_namespace_declaration(&mut self, declaration: &$($lt)? $($mutability)? NamespaceDeclaration) {
+                walk_namespace_declaration(self, declaration)
+            }
+
+            fn visit_using(&mut self, using: &$($lt)? $($mutability)? UsingStatement) {
+                walk_using(self, using)
+            }
+
+            fn visit_function(&mut self, function: &$($lt)? $($mutability)? Function) {
+                walk_function(self, function)
+            }
+
+            fn visit_function_signature(&mut self, signature: &$($lt)? $($mutability)? FunctionSignature) {
+                walk_function_signature(self, signature)
+            }
+
+            fn visit_generic_parameter(&mut self, param: &$($lt)? $($mutability)? GenericParameter) {
+                walk_generic_parameter(self, param)
+            }
+
+            fn visit_type_alias(&mut self, alias: &$($lt)? $($mutability)? TypeAlias) {
+                walk_type_alias(self, alias)
+            }
+
+            fn visit_struct(&mut self, s: &$($lt)? $($mutability)? Struct) {
+                walk_struct(self, s)
+            }
+
+            fn visit_enum(&mut self, e: &$($lt)? $($mutability)? Enum) {
+                walk_enum(self, e)
+            }
+
+            fn visit_union(&mut self, u: &$($lt)? $($mutability)? Union) {
+                walk_union(self, u)
+            }
+
+            fn visit_interface(&mut self, interface: &$($lt)? $($mutability)? Interface) {
+                walk_interface(self, interface)
+            }
+
+            fn visit_const(&mut self, c: &$($lt)? $($mutability)? ConstStatement) {
+                walk_const(self, c)
+            }
+
+            fn visit_annotation(&mut self, annotation: &$($lt)? $($mutability)? Annotation) {
+                walk_annotation(self, annotation)
+            }
+
+            fn visit_block(&mut self, block: &$($lt)? $($mutability)? Block) {
+                walk_block(self, block)
+            }
+
+            fn visit_statement(&mut self, statement: &$($lt)? $($mutability)? Statement) {
+                walk_statement(self, statement)
+            }
+
+            fn visit_expression(&mut self, expression: &$($lt)? $($mutability)? Expression) {
+                walk_expression(self, expression)
+            }
+
+            fn visit_type(&mut self, ty: &$($lt)? $($mutability)? Type) {
+                walk_type(self, ty)
+            }
+
+            fn visit_path(&mut self, path: &$($lt)? $($mutability)? Path) {
+                walk_path(self, path)
+            }
+        }
+
+        pub fn walk_source_file<$($lt,)? V: $Visit$(<$lt>)? + ?Sized>(
+            visitor: &mut V,
+            file: &$($lt)? $($mutability)? SourceFile,
+        ) {
+            let SourceFile { items } = file;
+            for item in items {
+                visitor.visit_namespace_item(item);
+            }
+        }
+
+        pub fn walk_namespace_item<$($lt,)? V: $Visit$(<$lt>)? + ?Sized>(
+            visitor: &mut V,
+            item: &$($lt)? $($mutability)? NamespaceItem,
+        ) {
+            match item {
+                NamespaceItem::Namespace(namespace) => visitor.visit_namespace(namespace),
+                NamespaceItem::NamespaceDeclaration(declaration) => visitor.visit_namespace_declaration(declaration),
+                NamespaceItem::Function(function) => visitor.visit_function(function),
+                NamespaceItem::FunctionDeclaration(FunctionDeclaration { signature }) => {
+                    visitor.visit_function_signature(signature)
+                }
+                NamespaceItem::TypeAlias(alias) => visitor.visit_type_alias(alias),
+                NamespaceItem::Struct(s) => visitor.visit_struct(s),
+                NamespaceItem::Enum(e) => visitor.visit_enum(e),
+                NamespaceItem::Union(u) => visitor.visit_union(u),
+                NamespaceItem::Interface(interface) => visitor.visit_interface(interface),
+                NamespaceItem::Using(using) => visitor.visit_using(using),
+                NamespaceItem::Const(c) => visitor.visit_const(c),
+            }
+        }
+
+        pub fn walk_namespace<$($lt,)? V: $Visit$(<$lt>)? + ?Sized>(
+            visitor: &mut V,
+            namespace: &$($lt)? $($mutability)? Namespace,
+        ) {
+            let Namespace { visibility: _, annotations, name, items } = namespace;
+            for annotation in annotations {
+                visitor.visit_annotation(annotation);
+            }
+            visitor.visit_path(name);
+            for statement in items {
+                visitor.visit_statement(statement);
+            }
+        }
+
+        pub fn walk_namespace_declaration<$($lt,)? V: $Visit$(<$lt>)? + ?Sized>(
+            visitor: &mut V,
+            declaration: &$($lt)? $($mutability)? NamespaceDeclaration,
+        ) {
+            let NamespaceDeclaration { visibility: _, annotations, name } = declaration;
+            for annotation in annotations {
+                visitor.visit_annotation(annotation);
+            }
+            visitor.visit_path(name);
+        }
+
+        pub fn walk_using<$($lt,)? V: $Visit$(<$lt>)? + ?Sized>(
+            visitor: &mut V,
+            using: &$($lt)? $($mutability)? UsingStatement,
+        ) {
+            let UsingStatement { visibility: _, annotations, path } = using;
+            for annotation in annotations {
+                visitor.visit_annotation(annotation);
+            }
+            visitor.visit_path(path);
+        }
+
+        pub fn walk_function<$($lt,)? V: $Visit$(<$lt>)? + ?Sized>(
+            visitor: &mut V,
+            function: &$($lt)? $($mutability)? Function,
+        ) {
+            let Function { signature, body } = function;
+            visitor.visit_function_signature(signature);
+            visitor.visit_block(body);
+        }
+
+        pub fn walk_function_signature<$($lt,)? V: $Visit$(<$lt>)? + ?Sized>(
+            visitor: &mut V,
+            signature: &$($lt)? $($mutability)? FunctionSignature,
+        ) {
+            let FunctionSignature {
+                visibility: _,
+                annotations,
+                is_extern: _,
+                is_effect: _,
+                receiver,
+                name: _,
+                generic_params,
+                unbound_constraints,
+                self_param: _,
+                params,
+                return_types,
+            } = signature;
+            for annotation in annotations {
+                visitor.visit_annotation(annotation);
+            }
+            if let Some(receiver) = receiver {
+                visitor.visit_path(receiver);
+            }
+            for param in generic_params.into_iter().chain(unbound_constraints) {
+                visitor.visit_generic_parameter(param);
+            }
+            for FunctionParameter { name: _, ty } in params {
+                visitor.visit_type(ty);
+            }
+            for ty in return_types {
+                visitor.visit_type(ty);
+            }
+        }
+
+        pub fn walk_generic_parameter<$($lt,)? V: $Visit$(<$lt>)? + ?Sized>(
+            visitor: &mut V,
+            param: &$($lt)? $($mutability)? GenericParameter,
+        ) {
+            match param {
+                GenericParameter::Type { name: _, bounds, default_type } => {
+                    for bound in bounds {
+                        visitor.visit_type(bound);
+                    }
+                    if let Some(default_type) = default_type {
+                        visitor.visit_type(default_type);
+                    }
+                }
+                GenericParameter::Const { name: _, ty } => visitor.visit_type(ty),
+            }
+        }
+
+        pub fn walk_type_alias<$($lt,)? V: $Visit$(<$lt>)? + ?Sized>(
+            visitor: &mut V,
+            alias: &$($lt)? $($mutability)? TypeAlias,
+        ) {
+            let TypeAlias { visibility: _, annotations, name: _, generic_params, unbound_constraints, aliased_type } =
+                alias;
+            for annotation in annotations {
+                visitor.visit_annotation(annotation);
+            }
+            for param in generic_params.into_iter().chain(unbound_constraints) {
+                visitor.visit_generic_parameter(param);
+            }
+            visitor.visit_type(aliased_type);
+        }
+
+        pub fn walk_struct<$($lt,)? V: $Visit$(<$lt>)? + ?Sized>(
+            visitor: &mut V,
+            s: &$($lt)? $($mutability)? Struct,
+        ) {
+            let Struct {
+                visibility: _,
+                annotations,
+                is_packed: _,
+                name: _,
+                generic_params,
+                unbound_constraints,
+                requires,
+                fields,
+            } = s;
+            for annotation in annotations {
+                visitor.visit_annotation(annotation);
+            }
+            for param in generic_params.into_iter().chain(unbound_constraints) {
+                visitor.visit_generic_parameter(param);
+            }
+            for ty in requires {
+                visitor.visit_type(ty);
+            }
+            for StructField { name: _, ty } in fields {
+                visitor.visit_type(ty);
+            }
+        }
+
+        pub fn walk_enum<$($lt,)? V: $Visit$(<$lt>)? + ?Sized>(
+            visitor: &mut V,
+            e: &$($lt)? $($mutability)? Enum,
+        ) {
+            let Enum {
+                visibility: _,
+                annotations,
+                name: _,
+                representation,
+                generic_params,
+                unbound_constraints,
+                requires,
+                variants,
+            } = e;
+            for annotation in annotations {
+                visitor.visit_annotation(annotation);
+            }
+            if let Some(representation) = representation {
+                visitor.visit_type(representation);
+            }
+            for param in generic_params.into_iter().chain(unbound_constraints) {
+                visitor.visit_generic_parameter(param);
+            }
+            for ty in requires {
+                visitor.visit_type(ty);
+            }
+            for EnumVariant { name: _, value } in variants {
+                if let Some(value) = value {
+                    visitor.visit_expression(value);
+                }
+            }
+        }
+
+        pub fn walk_union<$($lt,)? V: $Visit$(<$lt>)? + ?Sized>(
+            visitor: &mut V,
+            u: &$($lt)? $($mutability)? Union,
+        ) {
+            let Union { visibility: _, annotations, name: _, generic_params, unbound_constraints, requires, variants } =
+                u;
+            for annotation in annotations {
+                visitor.visit_annotation(annotation);
+            }
+            for param in generic_params.into_iter().chain(unbound_constraints) {
+                visitor.visit_generic_parameter(param);
+            }
+            for ty in requires {
+                visitor.visit_type(ty);
+            }
+            for UnionVariant { name: _, ty } in variants {
+                visitor.visit_type(ty);
+            }
+        }
+
+        pub fn walk_interface<$($lt,)? V: $Visit$(<$lt>)? + ?Sized>(
+            visitor: &mut V,
+            interface: &$($lt)? $($mutability)? Interface,
+        ) {
+            let Interface {
+                visibility: _,
+                annotations,
+                name: _,
+                generic_params,
+                unbound_constraints,
+                extends,
+                requires,
+                methods,
+            } = interface;
+            for annotation in annotations {
+                visitor.visit_annotation(annotation);
+            }
+            for param in generic_params.into_iter().chain(unbound_constraints) {
+                visitor.visit_generic_parameter(param);
+            }
+            for ty in extends.into_iter().chain(requires) {
+                visitor.visit_type(ty);
+            }
+            for method in methods {
+                visitor.visit_function_signature(method);
+            }
+        }
+
+        pub fn walk_const<$($lt,)? V: $Visit$(<$lt>)? + ?Sized>(
+            visitor: &mut V,
+            c: &$($lt)? $($mutability)? ConstStatement,
+        ) {
+            let ConstStatement { visibility: _, annotations, generic_params, receiver, name: _, ty, value } = c;
+            for annotation in annotations {
+                visitor.visit_annotation(annotation);
+            }
+            for param in generic_params {
+                visitor.visit_generic_parameter(param);
+            }
+            for ConstPathSegment { name: _, generic_args } in receiver {
+                for arg in generic_args {
+                    visitor.visit_type(arg);
+                }
+            }
+            if let Some(ty) = ty {
+                visitor.visit_type(ty);
+            }
+            visitor.visit_expression(value);
+        }
+
+        pub fn walk_annotation<$($lt,)? V: $Visit$(<$lt>)? + ?Sized>(
+            visitor: &mut V,
+            annotation: &$($lt)? $($mutability)? Annotation,
+        ) {
+            let Annotation { name: _, args } = annotation;
+            for arg in args {
+                visitor.visit_expression(arg);
+            }
+        }
+
+        pub fn walk_block<$($lt,)? V: $Visit$(<$lt>)? + ?Sized>(
+            visitor: &mut V,
+            block: &$($lt)? $($mutability)? Block,
+        ) {
+            let Block { statements, spans: _ } = block;
+            for statement in statements {
+                visitor.visit_statement(statement);
+            }
+        }
+
+        pub fn walk_statement<$($lt,)? V: $Visit$(<$lt>)? + ?Sized>(
+            visitor: &mut V,
+            statement: &$($lt)? $($mutability)? Statement,
+        ) {
+            match statement {
+                Statement::Pass | Statement::Break(_) | Statement::Continue => {}
+                Statement::Expression(expression) | Statement::Return(expression) => {
+                    visitor.visit_expression(expression)
+                }
+                Statement::Let(LetStatement { annotations, name: _, ty, value })
+                | Statement::Mut(MutStatement { annotations, name: _, ty, value }) => {
+                    for annotation in annotations {
+                        visitor.visit_annotation(annotation);
+                    }
+                    if let Some(ty) = ty {
+                        visitor.visit_type(ty);
+                    }
+                    visitor.visit_expression(value);
+                }
+                Statement::Const(c) => visitor.visit_const(c),
+                Statement::Block(BlockStatement { name: _, body }) => visitor.visit_block(body),
+                Statement::If(IfStatement { condition, then_body, elif_clauses, else_body }) => {
+                    visitor.visit_expression(condition);
+                    visitor.visit_block(then_body);
+                    for ElifClause { condition, body } in elif_clauses {
+                        visitor.visit_expression(condition);
+                        visitor.visit_block(body);
+                    }
+                    if let Some(else_body) = else_body {
+                        visitor.visit_block(else_body);
+                    }
+                }
+                Statement::For(ForStatement { pattern: _, iterable, body }) => {
+                    visitor.visit_expression(iterable);
+                    visitor.visit_block(body);
+                }
+                Statement::While(WhileStatement { condition, body }) => {
+                    visitor.visit_expression(condition);
+                    visitor.visit_block(body);
+                }
+                Statement::Using(using) => visitor.visit_using(using),
+                Statement::Function(function) => visitor.visit_function(function),
+                Statement::FunctionDeclaration(FunctionDeclaration { signature }) => {
+                    visitor.visit_function_signature(signature)
+                }
+                Statement::TypeAlias(alias) => visitor.visit_type_alias(alias),
+                Statement::Struct(s) => visitor.visit_struct(s),
+                Statement::Enum(e) => visitor.visit_enum(e),
+                Statement::Union(u) => visitor.visit_union(u),
+                Statement::Interface(interface) => visitor.visit_interface(interface),
+                Statement::Namespace(namespace) => visitor.visit_namespace(namespace),
+            }
+        }
+
+        pub fn walk_expression<$($lt,)? V: $Visit$(<$lt>)? + ?Sized>(
+            visitor: &mut V,
+            expression: &$($lt)? $($mutability)? Expression,
+        ) {
+            match expression {
+                Expression::IntegerLiteral(_)
+                | Expression::FloatLiteral(_)
+                | Expression::BooleanLiteral(_)
+                | Expression::CharLiteral(_)
+                | Expression::StringLiteral(_)
+                | Expression::OkLiteral
+                | Expression::NullLiteral
+                | Expression::SelfValue => {}
+                Expression::Path(path) => visitor.visit_path(path),
+                Expression::ArrayLiteral(ArrayLiteralExpr { elements }) => {
+                    for element in elements {
+                        visitor.visit_expression(element);
+                    }
+                }
+                Expression::InterpolatedString(parts) => {
+                    for part in parts {
+                        if let InterpolatedPart::Expression(expression) = part {
+                            visitor.visit_expression(expression);
+                        }
+                    }
+                }
+                Expression::StructLiteral(StructLiteralExpr { ty, fields, base }) => {
+                    visitor.visit_path(ty);
+                    for FieldInit { name: _, value } in fields {
+                        visitor.visit_expression(value);
+                    }
+                    if let Some(base) = base {
+                        visitor.visit_expression(base);
+                    }
+                }
+                Expression::VariantLiteral(VariantLiteralExpr { ty, variant: _, payload }) => {
+                    visitor.visit_path(ty);
+                    if let Some(payload) = payload {
+                        visitor.visit_expression(payload);
+                    }
+                }
+                Expression::EnumVariant(EnumVariantExpr { ty, variant: _ }) => visitor.visit_path(ty),
+                Expression::Range(RangeExpr { start, end, is_inclusive: _ }) => {
+                    if let Some(start) = start {
+                        visitor.visit_expression(start);
+                    }
+                    if let Some(end) = end {
+                        visitor.visit_expression(end);
+                    }
+                }
+                Expression::BinaryOp(BinaryOpExpr { lhs, op: _, rhs })
+                | Expression::Assign(AssignExpr { lhs, op: _, rhs }) => {
+                    visitor.visit_expression(lhs);
+                    visitor.visit_expression(rhs);
+                }
+                Expression::UnaryOp(UnaryOpExpr { op: _, operand }) => visitor.visit_expression(operand),
+                Expression::FieldAccess(FieldAccessExpr { object, field: _, is_propagating: _ })
+                | Expression::TypeAccess(TypeAccessExpr { object, member: _ }) => visitor.visit_expression(object),
+                Expression::Call(CallExpr { callee, args, is_propagating: _ }) => {
+                    visitor.visit_expression(callee);
+                    for arg in args {
+                        visitor.visit_expression(arg);
+                    }
+                }
+                Expression::Index(IndexExpr { object, index }) => {
+                    visitor.visit_expression(object);
+                    visitor.visit_expression(index);
+                }
+                Expression::Cast(CastExpr { expr, target_type }) => {
+                    visitor.visit_expression(expr);
+                    visitor.visit_type(target_type);
+                }
+                Expression::Sizeof(ty)
+                | Expression::Alignof(ty)
+                | Expression::Offsetof(OffsetofExpr { ty, field: _ }) => visitor.visit_type(ty),
+                Expression::Parenthesized(inner) => visitor.visit_expression(inner),
+                Expression::Lambda(LambdaExpr { is_effect: _, params, return_type, body }) => {
+                    for FunctionParameter { name: _, ty } in params {
+                        visitor.visit_type(ty);
+                    }
+                    if let Some(return_type) = return_type {
+                        visitor.visit_type(return_type);
+                    }
+                    visitor.visit_expression(body);
+                }
+            }
+        }
+
+        pub fn walk_type<$($lt,)? V: $Visit$(<$lt>)? + ?Sized>(
+            visitor: &mut V,
+            ty: &$($lt)? $($mutability)? Type,
+        ) {
+            match ty {
+                Type::U8
+                | Type::U16
+                | Type::U32
+                | Type::U64
+                | Type::USize
+                | Type::I8
+                | Type::I16
+                | Type::I32
+                | Type::I64
+                | Type::ISize
+                | Type::F32
+                | Type::F64
+                | Type::Bool
+                | Type::Ok
+                | Type::Null
+                | Type::SelfType => {}
+                Type::Pointer { nullable: _, mutable: _, element_type } | Type::Optional(element_type) => {
+                    visitor.visit_type(element_type)
+                }
+                Type::Path(path) => visitor.visit_path(path),
+                Type::Array { element_type, size } => {
+                    visitor.visit_type(element_type);
+                    if let Some(size) = size {
+                        visitor.visit_expression(size);
+                    }
+                }
+                Type::ErrorUnion { ok_type, err_type } => {
+                    visitor.visit_type(ok_type);
+                    visitor.visit_path(err_type);
+                }
+                Type::Const(value) => visitor.visit_expression(value),
+                Type::Function(FunctionType { is_effect: _, params, return_type }) => {
+                    for param in params {
+                        visitor.visit_type(param);
+                    }
+                    if let Some(return_type) = return_type {
+                        visitor.visit_type(return_type);
+                    }
+                }
+            }
+        }
+
+        pub fn walk_path<$($lt,)? V: $Visit$(<$lt>)? + ?Sized>(
+            visitor: &mut V,
+            path: &$($lt)? $($mutability)? Path,
+        ) {
+            let Path { segments: _, generic_args } = path;
+            for arg in generic_args {
+                visitor.visit_type(arg);
+            }
+        }
+    };
+}
+
+pub(crate) use visitor;
+
+visitor!(Visit<'ast>,);
+
+/// Every path in `file`, in source order: those of expressions, of named
+/// types, of receivers and of `namespace` and `using` lines
+pub fn paths(file: &SourceFile) -> Vec<&Path> {
+    struct Paths<'ast>(Vec<&'ast Path>);
+
+    impl<'ast> Visit<'ast> for Paths<'ast> {
+        fn visit_path(&mut self, path: &'ast Path) {
+            self.0.push(path);
+            walk_path(self, path);
+        }
+    }
+
+    let mut collector = Paths(Vec::new());
+    collector.visit_source_file(file);
+    collector.0
+}
+
+/// The innermost statement whose span holds the byte `offset`. Only parsed
+/// blocks know where their statements are.
+pub fn statement_at(file: &SourceFile, offset: usize) -> Option<&Statement> {
+    struct Finder<'ast> {
+        offset: usize,
+        found: Option<&'ast Statement>,
+    }
+
+    impl<'ast> Visit<'ast> for Finder<'ast> {
+        fn visit_block(&mut self, block: &'ast Block) {
+            for (index, statement) in block.statements.iter().enumerate() {
+                if block.span(index).is_some_and(|span| span.start <= self.offset && self.offset < span.end) {
+                    self.found = Some(statement);
+                    self.visit_statement(statement);
+                }
+            }
+        }
+    }
+
+    let mut finder = Finder { offset, found: None };
+    finder.visit_source_file(file);
+    finder.found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::format_path;
+    use crate::{Lexer, SourceFileParser};
+
+    fn parse(src: &str) -> SourceFile {
+        SourceFileParser::new().parse(Lexer::new(src)).unwrap()
+    }
+
+    #[test]
+    fn test_paths_in_source_order() {
+        let file = parse(
+            "struct Seq[T]\n    items: *Vec[T]\n\nfunc Seq::first(self) -> ?T ! Error\n    return get(self.items, 0)\n",
+        );
+        let paths: Vec<String> = paths(&file).into_iter().map(format_path).collect();
+        assert_eq!(paths, ["Vec[T]", "T", "Seq", "T", "Error", "get"]);
+    }
+
+    #[test]
+    fn test_statement_at() {
+        let src = "func f() -> i32\n    let x = 1\n    if x > 0\n        return x\n    return 0\n";
+        let file = parse(src);
+        let offset = src.find("return x").unwrap() + 3;
+        assert!(matches!(statement_at(&file, offset), Some(Statement::Return(_))));
+        let offset = src.find("let").unwrap();
+        assert!(matches!(statement_at(&file, offset), Some(Statement::Let(_))));
+        assert!(statement_at(&file, 0).is_none());
+    }
+}
@@ -0,0 +1,7 @@
+//! Traversal of the syntax tree by mutable reference, for passes that
+//! rewrite it in place. The methods and `walk_` functions are those of
+//! [`Visit`](crate::visit::Visit); see [`crate::visit`].
+
+use crate::ast::*;
+
+crate::visit::visitor!(VisitMut, mut);
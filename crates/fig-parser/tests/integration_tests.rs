@@ -25,8 +25,7 @@ fn parser_test(path: &Utf8Path, contents: String) -> datatest_stable::Result<()>
         .as_str()
         .trim_start_matches("../../tests/valid/")
         .trim_end_matches(".fig")
-        .replace('/', "__")
-        .replace('\\', "__");
+        .replace(['/', '\\'], "__");
 
     // Assert snapshot using insta with YAML format
     insta::assert_yaml_snapshot!(snapshot_name, ast);
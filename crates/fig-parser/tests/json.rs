@@ -0,0 +1,61 @@
+// The JSON form of the syntax tree: every tree in tests/valid reads back as
+// the tree that was written, and the published schema describes it and is
+// the only one published
+
+use std::path::{Path, PathBuf};
+
+use fig_parser::json::{SCHEMA_VERSION, from_json, schema, to_json};
+use fig_parser::{Lexer, SourceFileParser};
+
+const SCHEMA_DIR: &str = "../../docs/static/schema";
+
+fn fig_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            fig_files(&path, files);
+        } else if path.extension().is_some_and(|extension| extension == "fig") {
+            files.push(path);
+        }
+    }
+}
+
+#[test]
+fn test_valid_files_round_trip() {
+    let mut files = Vec::new();
+    fig_files(Path::new("../../tests/valid"), &mut files);
+    files.sort();
+    let mut round_tripped = 0;
+    for path in &files {
+        let src = std::fs::read_to_string(path).unwrap();
+        // Files the parser does not accept yet are the snapshot tests' concern
+        let Ok(file) = SourceFileParser::new().parse(Lexer::new(&src)) else { continue };
+        let json = to_json(&file);
+        let read = from_json(&json).unwrap_or_else(|error| panic!("{}: {}", path.display(), error));
+        assert_eq!(read, file, "{}", path.display());
+        assert_eq!(to_json(&read), json, "{}", path.display());
+        round_tripped += 1;
+    }
+    assert!(round_tripped >= 100, "only {} of {} files parsed", round_tripped, files.len());
+}
+
+#[test]
+fn test_published_schema_is_current() {
+    let path = format!("{}/fig-ast-v{}.json", SCHEMA_DIR, SCHEMA_VERSION);
+    let published = std::fs::read_to_string(&path).unwrap_or_default();
+    assert!(
+        published == schema(),
+        "{} is out of date; regenerate it with `fig parse --schema -o {}` and, if the tree changed shape, \
+         bump fig_parser::json::SCHEMA_VERSION",
+        path,
+        path.trim_start_matches("../../")
+    );
+
+    let mut files: Vec<String> = std::fs::read_dir(SCHEMA_DIR)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    files.sort();
+    let current = format!("fig-ast-v{}.json", SCHEMA_VERSION);
+    assert_eq!(files, [current], "older versions cannot be read, so their schemas are not published");
+}
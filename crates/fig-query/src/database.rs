@@ -0,0 +1,239 @@
+//! Revisions, memos and dependency tracking
+//!
+//! Every derived query's result is kept in a memo with the revision it was
+//! last verified at, the revision it last changed at, and the queries it
+//! read while it was computed. Setting an input starts a new revision. A
+//! memo is verified by bringing the queries it read up to date: when none
+//! of them changed after the memo was last verified it is reused as it is,
+//! otherwise it is computed again. When the new value equals the old one
+//! the memo keeps its old `changed_at`, so the queries that read it are not
+//! computed again either: that is the early cutoff.
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use fig_package::CompilationUnit;
+use fig_sema::layout::Target;
+
+/// Counts the changes to the inputs; every memo is stamped with one
+pub type Revision = u64;
+
+/// A source file given to a [`Database`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FileId(u32);
+
+/// A top-level item of a file, by its position among the file's items
+/// after namespace blocks are flattened. Adding or removing an item gives
+/// the items after it new ids.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ItemId {
+    pub file: FileId,
+    pub index: usize,
+}
+
+/// A query and its key. The first two are inputs, set from outside; the
+/// others are computed from them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Query {
+    FileText(FileId),
+    Files,
+    Tokens(FileId),
+    Parse(FileId),
+    SyntaxError(FileId),
+    Items(FileId),
+    ItemIds(FileId),
+    Signature(ItemId),
+    Body(ItemId),
+    Declarations,
+    DeclarationDiagnostics,
+    FunctionDiagnostics(ItemId),
+    Diagnostics,
+}
+
+struct Memo {
+    value: Arc<dyn Any + Send + Sync>,
+    verified_at: Revision,
+    changed_at: Revision,
+    /// The queries read while computing the value, in the order they were read
+    dependencies: Vec<Query>,
+}
+
+struct Source {
+    path: PathBuf,
+    namespace: Vec<String>,
+    text: Arc<str>,
+    changed_at: Revision,
+}
+
+/// The inputs of a compilation and the memoised queries computed from them
+pub struct Database {
+    revision: Revision,
+    target: Target,
+    sources: Vec<Source>,
+    /// When a file was last added
+    files_changed_at: Revision,
+    memos: HashMap<Query, Memo>,
+    /// The queries being verified or computed, innermost last, each with the
+    /// queries it has read so far
+    active: Vec<(Query, Vec<Query>)>,
+    /// The derived queries computed since [`Database::take_executed`]
+    executed: Vec<Query>,
+}
+
+impl Database {
+    pub fn new(target: Target) -> Self {
+        Database {
+            revision: 0,
+            target,
+            sources: Vec::new(),
+            files_changed_at: 0,
+            memos: HashMap::new(),
+            active: Vec::new(),
+            executed: Vec::new(),
+        }
+    }
+
+    /// Add a source file whose items are in `namespace` unless it declares
+    /// another, as a package's files are
+    pub fn add_file(&mut self, path: impl Into<PathBuf>, namespace: Vec<String>, text: impl Into<String>) -> FileId {
+        self.revision += 1;
+        let text: String = text.into();
+        self.sources.push(Source { path: path.into(), namespace, text: text.into(), changed_at: self.revision });
+        self.files_changed_at = self.revision;
+        FileId(self.sources.len() as u32 - 1)
+    }
+
+    /// Add every file of a loaded package, in the unit's order
+    pub fn add_unit(&mut self, unit: &CompilationUnit) -> Vec<FileId> {
+        unit.files
+            .iter()
+            .map(|file| self.add_file(file.path.clone(), file.namespace.clone(), file.text.clone()))
+            .collect()
+    }
+
+    /// Replace the text of `file`. Setting the text it already has starts no
+    /// new revision.
+    pub fn set_file_text(&mut self, file: FileId, text: impl Into<String>) {
+        let text: String = text.into();
+        let source = &mut self.sources[file.0 as usize];
+        if *source.text == *text {
+            return;
+        }
+        self.revision += 1;
+        source.text = text.into();
+        source.changed_at = self.revision;
+    }
+
+    pub fn revision(&self) -> Revision {
+        self.revision
+    }
+
+    pub fn target(&self) -> Target {
+        self.target
+    }
+
+    pub fn path(&self, file: FileId) -> &Path {
+        &self.sources[file.0 as usize].path
+    }
+
+    /// The namespace `file` was added in. Unlike its text this never
+    /// changes, so reading it is not tracked.
+    pub fn namespace(&self, file: FileId) -> &[String] {
+        &self.sources[file.0 as usize].namespace
+    }
+
+    /// The derived queries computed, rather than reused, since the last call
+    pub fn take_executed(&mut self) -> Vec<Query> {
+        std::mem::take(&mut self.executed)
+    }
+
+    /// The text of a file
+    pub fn file_text(&mut self, file: FileId) -> Arc<str> {
+        self.read(Query::FileText(file));
+        self.sources[file.0 as usize].text.clone()
+    }
+
+    /// Every file, in the order they were added
+    pub fn files(&mut self) -> Arc<Vec<FileId>> {
+        self.read(Query::Files);
+        Arc::new((0..self.sources.len() as u32).map(FileId).collect())
+    }
+
+    /// Record that the innermost active query read `query`
+    fn read(&mut self, query: Query) {
+        if let Some((_, dependencies)) = self.active.last_mut() {
+            dependencies.push(query);
+        }
+    }
+
+    /// The value of a derived query: its memo when that is up to date, or
+    /// else what `compute` returns
+    pub(crate) fn fetch<V>(&mut self, query: Query, compute: impl FnOnce(&mut Self) -> V) -> Arc<V>
+    where
+        V: PartialEq + Send + Sync + 'static,
+    {
+        self.read(query);
+        if let Some(at) = self.active.iter().position(|(active, _)| *active == query) {
+            let cycle: Vec<String> = self.active[at..].iter().map(|(active, _)| format!("{:?}", active)).collect();
+            panic!("query cycle: {} -> {:?}", cycle.join(" -> "), query);
+        }
+        self.active.push((query, Vec::new()));
+        let value = if self.verify(query) {
+            self.memos[&query].value.clone()
+        } else {
+            self.executed.push(query);
+            self.active.last_mut().expect("the query is active").1.clear();
+            let value = compute(self);
+            let dependencies = std::mem::take(&mut self.active.last_mut().expect("the query is active").1);
+            let changed_at = match self.memos.get(&query) {
+                Some(old) if old.value.downcast_ref::<V>() == Some(&value) => old.changed_at,
+                _ => self.revision,
+            };
+            let value: Arc<dyn Any + Send + Sync> = Arc::new(value);
+            let memo = Memo { value: value.clone(), verified_at: self.revision, changed_at, dependencies };
+            self.memos.insert(query, memo);
+            value
+        };
+        self.active.pop();
+        value.downcast::<V>().unwrap_or_else(|_| panic!("{:?} has a value of another type", query))
+    }
+
+    /// Whether the memo of `query` holds for the current revision, marking
+    /// it verified when it does
+    fn verify(&mut self, query: Query) -> bool {
+        let Some(memo) = self.memos.get(&query) else { return false };
+        if memo.verified_at == self.revision {
+            return true;
+        }
+        let verified_at = memo.verified_at;
+        for dependency in memo.dependencies.clone() {
+            if self.changed_at(dependency) > verified_at {
+                return false;
+            }
+        }
+        self.memos.get_mut(&query).expect("the memo was just read").verified_at = self.revision;
+        true
+    }
+
+    /// The revision `query` last changed at, bringing it up to date first
+    fn changed_at(&mut self, query: Query) -> Revision {
+        match query {
+            Query::FileText(file) => return self.sources[file.0 as usize].changed_at,
+            Query::Files => return self.files_changed_at,
+            Query::Tokens(file) => drop(self.tokens(file)),
+            Query::Parse(file) => drop(self.parse(file)),
+            Query::SyntaxError(file) => drop(self.syntax_error(file)),
+            Query::Items(file) => drop(self.items(file)),
+            Query::ItemIds(file) => drop(self.item_ids(file)),
+            Query::Signature(id) => drop(self.signature(id)),
+            Query::Body(id) => drop(self.body(id)),
+            Query::Declarations => drop(self.declarations()),
+            Query::DeclarationDiagnostics => drop(self.declaration_diagnostics()),
+            Query::FunctionDiagnostics(id) => drop(self.function_diagnostics(id)),
+            Query::Diagnostics => drop(self.diagnostics()),
+        }
+        self.memos[&query].changed_at
+    }
+}
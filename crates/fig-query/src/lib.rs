@@ -0,0 +1,23 @@
+//! Incremental compilation for Fig
+//!
+//! A [`Database`] holds the inputs of a compilation, the text of each
+//! source file, and answers queries computed from them: the tokens and
+//! syntax tree of a file, the signature and body of each item, and the
+//! diagnostics of each function. Every answer is memoised with the queries
+//! it read, so after an edit only what depends on the edited text is
+//! computed again, and a query whose new answer equals its old one stops
+//! the recomputation there. See [`database`] for how.
+//!
+//! ```ignore
+//! let mut db = Database::new(Target::host());
+//! let file = db.add_file("main.fig", Vec::new(), text);
+//! let diagnostics = db.diagnostics();
+//! db.set_file_text(file, edited);
+//! let diagnostics = db.diagnostics(); // re-checks only the edited bodies
+//! ```
+
+pub mod database;
+mod queries;
+
+pub use database::{Database, FileId, ItemId, Query, Revision};
+pub use queries::{Declarations, Item};
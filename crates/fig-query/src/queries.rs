@@ -0,0 +1,215 @@
+//! The compiler's queries, from a file's text to the diagnostics of the
+//! whole program
+//!
+//! The program's declarations are gathered into one source file with every
+//! function body left out. Item-level checks run on that file alone. Each
+//! function body is checked on its own, in a copy of that file with just
+//! its body put back. A body therefore depends on every signature and on
+//! nothing else: changing a body re-checks only that body. Changing a
+//! signature or a type re-checks everything. The one thing lost is in the
+//! effect checker's notes, which explain why a callee is effectful from
+//! the callee's body: here they stop at the callee's declaration.
+//!
+//! Values that compare equal cut off recomputation even where their blocks
+//! were parsed at other positions, so the block spans of a value may be
+//! those of an earlier revision.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use fig_lexer::Token;
+use fig_parser::ast::*;
+use fig_parser::{LexicalError, Lexer, SourceFileParser, Spanned};
+use fig_sema::diagnostics::Diagnostic;
+use fig_sema::items::ItemTable;
+use fig_sema::typeck::{Instance, TypeChecker, generic_names, is_default_method};
+
+use crate::database::{Database, FileId, ItemId, Query};
+
+/// A top-level item with the namespace it is declared in
+#[derive(Debug, Clone, PartialEq)]
+pub struct Item {
+    pub namespace: Vec<String>,
+    pub item: NamespaceItem,
+}
+
+/// Every item of the program, with function bodies left out
+#[derive(Debug, Clone, PartialEq)]
+pub struct Declarations {
+    pub file: SourceFile,
+    /// Where in `file` each item is
+    pub positions: HashMap<ItemId, usize>,
+}
+
+impl Database {
+    /// The tokens of a file with their byte offsets, including the lexer's
+    /// errors
+    pub fn tokens(&mut self, file: FileId) -> Arc<Vec<Spanned<Token, usize, LexicalError>>> {
+        self.fetch(Query::Tokens(file), |db| Lexer::new(&db.file_text(file)).collect())
+    }
+
+    pub fn parse(&mut self, file: FileId) -> Arc<Result<SourceFile, Diagnostic>> {
+        self.fetch(Query::Parse(file), |db| {
+            let tokens = db.tokens(file);
+            let text = db.file_text(file);
+            SourceFileParser::new()
+                .parse(tokens.iter().cloned())
+                .map_err(|error| fig_package::syntax_error(db.path(file), &text, &error))
+        })
+    }
+
+    /// The parse error of a file, if any
+    pub fn syntax_error(&mut self, file: FileId) -> Arc<Option<Diagnostic>> {
+        self.fetch(Query::SyntaxError(file), |db| db.parse(file).as_ref().as_ref().err().cloned())
+    }
+
+    /// The items of a file, none when it does not parse. Namespace blocks
+    /// are flattened into the items they hold.
+    pub fn items(&mut self, file: FileId) -> Arc<Vec<Item>> {
+        self.fetch(Query::Items(file), |db| {
+            let parsed = db.parse(file);
+            let Ok(source) = parsed.as_ref() else { return Vec::new() };
+            let mut items = Vec::new();
+            let mut namespace = db.namespace(file).to_vec();
+            for item in &source.items {
+                match item {
+                    NamespaceItem::NamespaceDeclaration(declaration) => namespace = declaration.name.segments.clone(),
+                    NamespaceItem::Namespace(block) => flatten(block, &namespace, &mut items),
+                    item => items.push(Item { namespace: namespace.clone(), item: item.clone() }),
+                }
+            }
+            items
+        })
+    }
+
+    /// The ids of a file's items, which only change with their number
+    pub fn item_ids(&mut self, file: FileId) -> Arc<Vec<ItemId>> {
+        self.fetch(Query::ItemIds(file), |db| (0..db.items(file).len()).map(|index| ItemId { file, index }).collect())
+    }
+
+    /// An item as the rest of the program sees it: a function without its
+    /// body, anything else as it is. `None` for an id the file no longer has.
+    pub fn signature(&mut self, id: ItemId) -> Arc<Option<Item>> {
+        self.fetch(Query::Signature(id), |db| {
+            let item = db.items(id.file).get(id.index)?.clone();
+            let NamespaceItem::Function(function) = item.item else { return Some(item) };
+            let declaration = FunctionDeclaration { signature: function.signature };
+            Some(Item { namespace: item.namespace, item: NamespaceItem::FunctionDeclaration(declaration) })
+        })
+    }
+
+    /// The body of a function item
+    pub fn body(&mut self, id: ItemId) -> Arc<Option<Block>> {
+        self.fetch(Query::Body(id), |db| match &db.items(id.file).get(id.index)?.item {
+            NamespaceItem::Function(function) => Some(function.body.clone()),
+            _ => None,
+        })
+    }
+
+    pub fn declarations(&mut self) -> Arc<Declarations> {
+        self.fetch(Query::Declarations, |db| {
+            let mut items = Vec::new();
+            let mut positions = HashMap::new();
+            let mut namespace: Option<Vec<String>> = None;
+            for &file in db.files().iter() {
+                for &id in db.item_ids(file).iter() {
+                    let Some(item) = Option::clone(&db.signature(id)) else { continue };
+                    if namespace.as_ref() != Some(&item.namespace) {
+                        items.push(NamespaceItem::NamespaceDeclaration(NamespaceDeclaration {
+                            visibility: Visibility::Default,
+                            annotations: Vec::new(),
+                            name: Path::with_generics(item.namespace.clone(), Vec::new()),
+                        }));
+                        namespace = Some(item.namespace);
+                    }
+                    positions.insert(id, items.len());
+                    items.push(item.item);
+                }
+            }
+            Declarations { file: SourceFile::new(items), positions }
+        })
+    }
+
+    /// What the semantic checks find in the declarations alone
+    pub fn declaration_diagnostics(&mut self) -> Arc<Vec<Diagnostic>> {
+        self.fetch(Query::DeclarationDiagnostics, |db| {
+            fig_sema::check(&ItemTable::from_source_file(&db.declarations().file))
+        })
+    }
+
+    /// What checking the body of a function adds to the declarations'
+    /// diagnostics, type errors included. Generic functions and default
+    /// methods are type checked through their instances, which this does
+    /// not follow.
+    pub fn function_diagnostics(&mut self, id: ItemId) -> Arc<Vec<Diagnostic>> {
+        self.fetch(Query::FunctionDiagnostics(id), |db| {
+            let Some(body) = Option::clone(&db.body(id)) else { return Vec::new() };
+            let declarations = db.declarations();
+            let known = db.declaration_diagnostics();
+            let mut file = declarations.file.clone();
+            let position = declarations.positions[&id];
+            let NamespaceItem::FunctionDeclaration(declaration) = &file.items[position] else {
+                unreachable!("a function with a body is declared without one")
+            };
+            let function = Function { signature: declaration.signature.clone(), body };
+            file.items[position] = NamespaceItem::Function(function);
+            let NamespaceItem::Function(function) = &file.items[position] else { unreachable!() };
+
+            let items = ItemTable::from_source_file(&file);
+            let mut diagnostics: Vec<Diagnostic> =
+                fig_sema::check(&items).into_iter().filter(|diagnostic| !known.contains(diagnostic)).collect();
+            let def = items.functions().iter().find(|f| std::ptr::eq(f.signature, &function.signature));
+            if let Some(def) = def
+                && generic_names(&items, def).is_empty()
+                && !is_default_method(&items, def)
+                && let Err(errors) = TypeChecker::new(&items, db.target()).check(&Instance::new(def))
+            {
+                diagnostics.extend(errors);
+            }
+            diagnostics
+        })
+    }
+
+    /// Every problem in the program: syntax errors, then those of the
+    /// declarations, then those of each function body
+    pub fn diagnostics(&mut self) -> Arc<Vec<Diagnostic>> {
+        self.fetch(Query::Diagnostics, |db| {
+            let files = db.files();
+            let mut diagnostics: Vec<Diagnostic> =
+                files.iter().filter_map(|&file| Option::clone(&db.syntax_error(file))).collect();
+            diagnostics.extend(db.declaration_diagnostics().iter().cloned());
+            for &file in files.iter() {
+                for &id in db.item_ids(file).iter() {
+                    diagnostics.extend(db.function_diagnostics(id).iter().cloned());
+                }
+            }
+            diagnostics
+        })
+    }
+}
+
+/// Add the definitions of a namespace block, and of the blocks inside it,
+/// to `items`
+fn flatten(block: &Namespace, outer: &[String], items: &mut Vec<Item>) {
+    let mut namespace = outer.to_vec();
+    namespace.extend(block.name.segments.iter().cloned());
+    for statement in &block.items {
+        let item = match statement {
+            Statement::Namespace(inner) => {
+                flatten(inner, &namespace, items);
+                continue;
+            }
+            Statement::Function(f) => NamespaceItem::Function(f.clone()),
+            Statement::FunctionDeclaration(d) => NamespaceItem::FunctionDeclaration(d.clone()),
+            Statement::TypeAlias(a) => NamespaceItem::TypeAlias(a.clone()),
+            Statement::Struct(s) => NamespaceItem::Struct(s.clone()),
+            Statement::Enum(e) => NamespaceItem::Enum(e.clone()),
+            Statement::Union(u) => NamespaceItem::Union(u.clone()),
+            Statement::Interface(i) => NamespaceItem::Interface(i.clone()),
+            Statement::Const(c) => NamespaceItem::Const(c.clone()),
+            Statement::Using(u) => NamespaceItem::Using(u.clone()),
+            _ => continue,
+        };
+        items.push(Item { namespace: namespace.clone(), item });
+    }
+}
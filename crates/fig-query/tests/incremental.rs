@@ -0,0 +1,121 @@
+// Edits to a database, and which queries they compute again
+
+use fig_query::{Database, FileId, ItemId, Query};
+use fig_sema::layout::Target;
+
+const MATH: &str = "\
+func one() -> i32
+    return 1
+
+func two() -> i32
+    return 2
+";
+
+const MAIN: &str = "\
+func main() -> i32
+    return math::one() + math::two()
+";
+
+fn program() -> (Database, FileId, FileId) {
+    let mut db = Database::new(Target::X86_64);
+    let math = db.add_file("math.fig", vec!["math".to_string()], MATH);
+    let main = db.add_file("main.fig", Vec::new(), MAIN);
+    (db, math, main)
+}
+
+fn messages(db: &mut Database) -> Vec<String> {
+    db.diagnostics().iter().map(|d| d.to_string()).collect()
+}
+
+#[test]
+fn test_editing_a_body_rechecks_only_that_body() {
+    let (mut db, math, main) = program();
+    assert_eq!(messages(&mut db), Vec::<String>::new());
+    db.take_executed();
+
+    db.set_file_text(math, MATH.replace("return 1", "return true"));
+    let errors = messages(&mut db);
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    assert!(errors[0].contains("in `math::one`"), "{}", errors[0]);
+    let executed = db.take_executed();
+    let one = ItemId { file: math, index: 0 };
+    assert!(executed.contains(&Query::FunctionDiagnostics(one)), "{:?}", executed);
+    for query in [
+        Query::FunctionDiagnostics(ItemId { file: math, index: 1 }),
+        Query::FunctionDiagnostics(ItemId { file: main, index: 0 }),
+        Query::Declarations,
+        Query::DeclarationDiagnostics,
+        Query::Parse(main),
+    ] {
+        assert!(!executed.contains(&query), "{:?} in {:?}", query, executed);
+    }
+
+    // Nothing changed, so nothing is computed
+    db.set_file_text(math, MATH.replace("return 1", "return true"));
+    assert_eq!(messages(&mut db).len(), 1);
+    assert_eq!(db.take_executed(), []);
+}
+
+#[test]
+fn test_moving_code_stops_at_the_syntax_tree() {
+    let (mut db, math, _) = program();
+    db.diagnostics();
+    db.take_executed();
+
+    // A comment shifts every token, but the syntax tree is the same
+    db.set_file_text(math, format!("// Small numbers\n\n{}", MATH));
+    assert_eq!(messages(&mut db), Vec::<String>::new());
+    assert_eq!(db.take_executed(), [Query::Tokens(math), Query::Parse(math)]);
+}
+
+#[test]
+fn test_editing_a_signature_rechecks_callers() {
+    let (mut db, math, main) = program();
+    db.diagnostics();
+    db.take_executed();
+
+    db.set_file_text(math, MATH.replace("func two() -> i32\n    return 2", "func two() -> bool\n    return false"));
+    let errors = messages(&mut db);
+    assert_eq!(errors.len(), 1, "{:?}", errors);
+    assert!(errors[0].contains("in `main`"), "{}", errors[0]);
+    let executed = db.take_executed();
+    assert!(executed.contains(&Query::Declarations), "{:?}", executed);
+    assert!(executed.contains(&Query::FunctionDiagnostics(ItemId { file: main, index: 0 })), "{:?}", executed);
+}
+
+#[test]
+fn test_matches_checking_the_whole_program() {
+    let mut db = Database::new(Target::X86_64);
+    let src = "\
+namespace shapes
+    struct Point
+        x: i32
+
+    struct Point
+        y: i32
+
+func! bump(p: *mut i32)
+    *p = *p + 1
+
+func pure(p: *mut i32)
+    bump(p)
+";
+    db.add_file("main.fig", Vec::new(), src);
+    let broken = db.add_file("broken.fig", vec!["broken".to_string()], "func f(\n");
+
+    let file = fig_parser::SourceFileParser::new().parse(fig_parser::Lexer::new(src)).unwrap();
+    let expected = fig_sema::check(&fig_sema::items::ItemTable::from_source_file(&file));
+    assert!(expected.len() >= 2, "{:?}", expected);
+    let diagnostics = db.diagnostics();
+    assert!(diagnostics[0].message.starts_with("broken.fig:1:8: "), "{}", diagnostics[0]);
+    // Notes explaining a callee's effects need its body, which is not read
+    let summary = |d: &fig_sema::diagnostics::Diagnostic| (d.message.clone(), d.function.clone(), d.snippet.clone());
+    let mut found: Vec<_> = diagnostics[1..].iter().map(summary).collect();
+    let mut expected: Vec<_> = expected.iter().map(summary).collect();
+    found.sort();
+    expected.sort();
+    assert_eq!(found, expected);
+
+    db.set_file_text(broken, "func f() -> i32\n    return 0\n");
+    assert_eq!(db.diagnostics().len(), expected.len());
+}
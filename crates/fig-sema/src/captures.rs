@@ -0,0 +1,244 @@
+//! What lambdas capture from the functions they are written in
+//!
+//! A lambda's body may read the parameters and locals of the function it is
+//! written in, including `self`, and those of the lambdas around it. Every
+//! such name it reads is captured by value when the lambda expression is
+//! evaluated: the lambda sees the value the variable had then, and later
+//! assignments to the variable do not reach it. Constants, functions and
+//! types are not captured; they are the same everywhere.
+//!
+//! A lambda's body is an expression, so it cannot assign to a captured
+//! variable. Since a capture is a copy, it may not take the address of one
+//! either, or of a field or element of one: the pointer would point into
+//! the copy, not at the variable the source names. Taking an address
+//! through a captured pointer or slice is allowed.
+//!
+//! Backends give a lambda's captures, in the order [`captures`] returns
+//! them, to the code of its body together with its arguments.
+
+use fig_parser::ast::*;
+use fig_parser::format::format_expression;
+use fig_parser::visit::{self, Visit};
+
+use crate::diagnostics::Diagnostic;
+use crate::items::ItemTable;
+use crate::resolve::{
+    Binding, BindingKind, BodyScope, Scoped, is_indirect, walk_scoped_block, walk_scoped_statement,
+};
+
+/// The names `lambda` captures, in the order its body first reads them.
+/// `is_local` tells which names are variables of the enclosing code.
+pub fn captures(lambda: &LambdaExpr, is_local: impl Fn(&str) -> bool) -> Vec<String> {
+    let mut reads = Reads { is_local: &is_local, bound: vec![names(lambda)], found: Vec::new() };
+    reads.visit_expression(&lambda.body);
+    reads.found
+}
+
+fn names(lambda: &LambdaExpr) -> Vec<String> {
+    lambda.params.iter().map(|p| p.name.clone()).collect()
+}
+
+/// Collects the variables of the enclosing code a lambda body reads
+struct Reads<'f> {
+    is_local: &'f dyn Fn(&str) -> bool,
+    /// The parameters of the lambda and of the lambdas nested in it, which
+    /// shadow the enclosing code's variables
+    bound: Vec<Vec<String>>,
+    found: Vec<String>,
+}
+
+impl Reads<'_> {
+    fn read(&mut self, name: &str) {
+        let bound = self.bound.iter().any(|names| names.iter().any(|n| n == name));
+        if !bound && (self.is_local)(name) && !self.found.iter().any(|n| n == name) {
+            self.found.push(name.to_string());
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for Reads<'_> {
+    fn visit_expression(&mut self, expression: &'ast Expression) {
+        match expression {
+            Expression::Path(path) if path.segments.len() == 1 => self.read(&path.segments[0]),
+            Expression::SelfValue => self.read("self"),
+            Expression::Lambda(inner) => {
+                self.bound.push(names(inner));
+                self.visit_expression(&inner.body);
+                self.bound.pop();
+                return;
+            }
+            _ => {}
+        }
+        visit::walk_expression(self, expression);
+    }
+}
+
+/// The lambdas in a lambda body that are not inside another lambda, and
+/// the address-of operations outside any of them
+#[derive(Default)]
+struct Outermost<'ast> {
+    lambdas: Vec<&'ast LambdaExpr>,
+    /// Each `&place` expression with its place
+    addresses: Vec<(&'ast Expression, &'ast Expression)>,
+}
+
+impl<'ast> Visit<'ast> for Outermost<'ast> {
+    fn visit_expression(&mut self, expression: &'ast Expression) {
+        match expression {
+            Expression::Lambda(lambda) => return self.lambdas.push(lambda),
+            Expression::UnaryOp(op) if op.op == UnaryOperator::AddressOf => {
+                self.addresses.push((expression, &op.operand));
+            }
+            _ => {}
+        }
+        visit::walk_expression(self, expression);
+    }
+}
+
+struct CaptureChecker<'t, 'a> {
+    name: String,
+    scope: BodyScope<'t, 'a>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'t, 'a> Scoped<'t, 'a> for CaptureChecker<'t, 'a> {
+    fn scope(&mut self) -> &mut BodyScope<'t, 'a> {
+        &mut self.scope
+    }
+}
+
+/// Finds the lambdas of a body that are not inside another lambda;
+/// `CaptureChecker::lambda` goes on to the ones nested in them
+impl<'ast> Visit<'ast> for CaptureChecker<'_, '_> {
+    fn visit_block(&mut self, block: &'ast Block) {
+        walk_scoped_block(self, block);
+    }
+
+    fn visit_statement(&mut self, stmt: &'ast Statement) {
+        walk_scoped_statement(self, stmt);
+    }
+
+    fn visit_expression(&mut self, expr: &'ast Expression) {
+        match expr {
+            Expression::Lambda(lambda) => self.lambda(lambda),
+            _ => visit::walk_expression(self, expr),
+        }
+    }
+}
+
+impl CaptureChecker<'_, '_> {
+    /// Check `lambda`, whose enclosing variables are those in scope
+    fn lambda(&mut self, lambda: &LambdaExpr) {
+        let scope = &mut self.scope;
+        let has_self = scope.function().signature.self_param.is_some();
+        let captured = captures(lambda, |name| scope.lookup(name).is_some() || (name == "self" && has_self));
+        scope.push();
+        for param in &lambda.params {
+            scope.bind(&param.name, Binding { kind: BindingKind::Param, ty: Some(param.ty.clone()) });
+        }
+        let mut body = Outermost::default();
+        body.visit_expression(&lambda.body);
+        for (address, place) in body.addresses {
+            if let Some(name) = captured_root(&self.scope, place)
+                && captured.contains(&name)
+            {
+                self.diagnostics.push(
+                    Diagnostic::error(format!(
+                        "lambda in `{}` takes the address of captured variable `{}`",
+                        self.name, name
+                    ))
+                    .in_function(&self.name)
+                    .at(self.scope.span())
+                    .with_snippet(format_expression(address))
+                    .with_note(format!(
+                        "lambdas capture variables by value when they are created, \
+                         so the pointer would not point to `{}`",
+                        name
+                    )),
+                );
+            }
+        }
+        for inner in body.lambdas {
+            self.lambda(inner);
+        }
+        self.scope.pop();
+    }
+}
+
+/// The variable whose own storage `place` is part of: `x` for `x`, `x.a`
+/// or `x[i]`, but none when the place is reached through a pointer or
+/// slice
+fn captured_root(scope: &BodyScope, place: &Expression) -> Option<String> {
+    let object = match place {
+        Expression::Parenthesized(inner) => return captured_root(scope, inner),
+        Expression::Path(path) if path.segments.len() == 1 => return Some(path.segments[0].clone()),
+        Expression::SelfValue => return Some("self".to_string()),
+        Expression::FieldAccess(fa) => &fa.object,
+        Expression::Index(idx) => &idx.object,
+        _ => return None,
+    };
+    if scope.type_of(object).is_some_and(|ty| is_indirect(&ty)) {
+        return None;
+    }
+    captured_root(scope, object)
+}
+
+/// Check the lambdas of every function body in `items` against the
+/// capture rules
+pub fn check_captures(items: &ItemTable) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    for function in items.functions() {
+        let Some(body) = function.body else { continue };
+        let mut checker = CaptureChecker {
+            name: function.qualified_name(),
+            scope: BodyScope::new(items, function),
+            diagnostics: Vec::new(),
+        };
+        checker.visit_block(body);
+        diagnostics.extend(checker.diagnostics);
+    }
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    fn check(src: &str) -> Vec<Diagnostic> {
+        let sf = parse(src);
+        let items = ItemTable::from_source_file(&sf);
+        check_captures(&items)
+    }
+
+    #[test]
+    fn test_captures_in_order_of_first_read() {
+        let expr = "fn(x: i32) -> i32 => x + scale * offset + (fn(scale: i32) -> i32 => scale + base)(x)";
+        let parsed = fig_parser::ExpressionParser::new().parse(fig_parser::Lexer::new(expr)).unwrap();
+        let Expression::Lambda(lambda) = parsed else { panic!("expected a lambda") };
+        let locals = ["x", "scale", "offset", "base"];
+        assert_eq!(captures(&lambda, |name| locals.contains(&name)), ["scale", "offset", "base"]);
+    }
+
+    #[test]
+    fn test_address_of_a_capture_is_rejected() {
+        let diags = check(
+            "struct P\n    x: i32\n\nfunc f(p: *mut P, q: P) -> i32\n    let n = 1\n    \
+             let inner = fn() -> *mut i32 => &p.x\n    let param = fn(n: i32) -> *i32 => &n\n    \
+             let copy = fn() -> *i32 => &q.x\n    let leak = fn() -> *i32 => &n\n    return n\n",
+        );
+        let messages: Vec<&str> = diags.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "lambda in `f` takes the address of captured variable `q`",
+                "lambda in `f` takes the address of captured variable `n`",
+            ]
+        );
+        assert_eq!(diags[1].snippet.as_deref(), Some("&n"));
+        assert_eq!(
+            diags[1].notes,
+            ["lambdas capture variables by value when they are created, so the pointer would not point to `n`"]
+        );
+    }
+}
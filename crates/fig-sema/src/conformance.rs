@@ -0,0 +1,429 @@
+//! Interface conformance
+//!
+//! Fig follows a blanket-method model: a type satisfies an interface when the
+//! methods declared on it (functions whose receiver is the type, e.g.
+//! `func Vec[T]::push`) cover every method the interface requires. Nothing
+//! else has to be written to opt in. The methods an interface requires are:
+//!
+//! - its own declared methods, and
+//! - the methods of every interface it `extends`, transitively.
+//!
+//! A method is also covered by a default implementation: a function with a
+//! body whose receiver is the interface itself, e.g. `func[T] Iterator[T]::count`.
+//! An interface's `requires` clause names further interfaces the type must
+//! satisfy on its own, without inheriting their methods.
+//!
+//! A `requires` clause on a struct, union or enum declares conformance, and
+//! [`ConformanceChecker::check`] verifies each such declaration.
+//!
+//! Signatures are compared after substituting `Self` by the implementing type
+//! and generic parameters by the arguments in use. The `self` parameter must
+//! have the same flavour (by value or by pointer); an implementation may take
+//! `*self` where the interface asks for `*mut self`, but not the reverse. A
+//! pure interface method cannot be implemented by a `func!`.
+
+use std::fmt;
+
+use fig_parser::ast::*;
+use fig_parser::format::{format_path, format_type, format_type_list};
+
+use crate::diagnostics::Diagnostic;
+use crate::items::{FunctionDef, ItemTable, TypeDef};
+
+/// Why a type fails to satisfy an interface
+#[derive(Debug, Clone, PartialEq)]
+pub enum Mismatch {
+    /// The path does not name an interface
+    NotAnInterface { name: String },
+    /// No method or default implementation covers `interface::method`
+    Missing { interface: String, method: String },
+    /// The implementation's `self` parameter is incompatible
+    SelfParameter { method: String, expected: String, found: String },
+    /// The implementation is `func!` but the interface method is pure
+    Effect { method: String },
+    /// Parameter types differ after substitution
+    Parameters { method: String, expected: String, found: String },
+    /// Return types differ after substitution
+    ReturnType { method: String, expected: String, found: String },
+    /// Method generic parameter counts differ
+    GenericArity { method: String, expected: usize, found: usize },
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mismatch::NotAnInterface { name } => write!(f, "`{}` is not an interface", name),
+            Mismatch::Missing { interface, method } => {
+                write!(f, "missing method `{}` required by `{}`", method, interface)
+            }
+            Mismatch::SelfParameter { method, expected, found } => {
+                write!(f, "method `{}` takes `{}` but the interface expects `{}`", method, found, expected)
+            }
+            Mismatch::Effect { method } => {
+                write!(f, "method `{}` is `func!` but the interface declares it pure", method)
+            }
+            Mismatch::Parameters { method, expected, found } => {
+                write!(f, "method `{}` takes `({})` but the interface expects `({})`", method, found, expected)
+            }
+            Mismatch::ReturnType { method, expected, found } => {
+                write!(f, "method `{}` returns `{}` but the interface expects `{}`", method, found, expected)
+            }
+            Mismatch::GenericArity { method, expected, found } => write!(
+                f,
+                "method `{}` has {} generic parameter(s) but the interface expects {}",
+                method, found, expected
+            ),
+        }
+    }
+}
+
+/// Generic parameter names mapped to the arguments in use, plus the type
+/// that `Self` stands for
+struct Bindings {
+    names: Vec<String>,
+    args: Vec<Type>,
+    self_type: Type,
+}
+
+impl Bindings {
+    fn new(params: &[String], args: &[Type], self_type: &Type) -> Self {
+        Bindings { names: params.to_vec(), args: args.to_vec(), self_type: self_type.clone() }
+    }
+
+    fn apply(&self, ty: &Type) -> Type {
+        match ty {
+            Type::SelfType => self.self_type.clone(),
+            Type::Path(path) if path.segments.len() == 1 && path.generic_args.is_empty() => {
+                match self.names.iter().position(|n| *n == path.segments[0]) {
+                    Some(i) if i < self.args.len() => self.args[i].clone(),
+                    _ => ty.clone(),
+                }
+            }
+            Type::Path(path) => Type::Path(Path {
+                segments: path.segments.clone(),
+                generic_args: path.generic_args.iter().map(|t| self.apply(t)).collect(),
+            }),
+            Type::Pointer { nullable, mutable, element_type } => Type::Pointer {
+                nullable: *nullable,
+                mutable: *mutable,
+                element_type: Box::new(self.apply(element_type)),
+            },
+            Type::Optional(inner) => Type::Optional(Box::new(self.apply(inner))),
+            Type::Array { element_type, size } => {
+                Type::Array { element_type: Box::new(self.apply(element_type)), size: size.clone() }
+            }
+            Type::ErrorUnion { ok_type, err_type } => {
+                Type::ErrorUnion { ok_type: Box::new(self.apply(ok_type)), err_type: err_type.clone() }
+            }
+            other => other.clone(),
+        }
+    }
+}
+
+fn param_names(params: &[GenericParameter]) -> Vec<String> {
+    params.iter().map(|p| p.name().to_string()).collect()
+}
+
+/// Names bound by a receiver path such as `Vec[T]` in `func Vec[T]::push`
+fn receiver_names(receiver: &Path) -> Vec<String> {
+    receiver
+        .generic_args
+        .iter()
+        .map(|arg| match arg {
+            Type::Path(p) if p.segments.len() == 1 => p.segments[0].clone(),
+            other => format_type(other),
+        })
+        .collect()
+}
+
+fn self_flavour(sp: &Option<SelfParameter>) -> &'static str {
+    match sp {
+        None => "no self",
+        Some(SelfParameter { is_pointer: false, .. }) => "self",
+        Some(SelfParameter { is_pointer: true, is_mutable: false }) => "*self",
+        Some(SelfParameter { is_pointer: true, is_mutable: true }) => "*mut self",
+    }
+}
+
+pub struct ConformanceChecker<'t, 'a> {
+    items: &'t ItemTable<'a>,
+}
+
+impl<'t, 'a> ConformanceChecker<'t, 'a> {
+    pub fn new(items: &'t ItemTable<'a>) -> Self {
+        ConformanceChecker { items }
+    }
+
+    /// Check every `requires` clause on a struct, union or enum
+    pub fn check(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        for (name, def) in self.items.types() {
+            let requires = match def {
+                TypeDef::Struct(s) => &s.requires,
+                TypeDef::Union(u) => &u.requires,
+                TypeDef::Enum(e) => &e.requires,
+                _ => continue,
+            };
+            let self_type = Type::Path(Path::with_generics(
+                vec![def.name().to_string()],
+                param_names(def.generic_params())
+                    .into_iter()
+                    .map(|n| Type::Path(Path::simple(n)))
+                    .collect(),
+            ));
+            for required in requires {
+                let Type::Path(interface) = required else {
+                    diagnostics.push(Diagnostic::error(format!(
+                        "`{}` requires `{}`, which is not an interface",
+                        name,
+                        format_type(required)
+                    )));
+                    continue;
+                };
+                if let Err(mismatches) = self.satisfies(&self_type, interface) {
+                    let mut diag = Diagnostic::error(format!(
+                        "`{}` does not satisfy `{}`",
+                        name,
+                        format_path(interface)
+                    ));
+                    for m in mismatches {
+                        diag = diag.with_note(m.to_string());
+                    }
+                    diagnostics.push(diag);
+                }
+            }
+        }
+        diagnostics
+    }
+
+    /// Decide whether `ty` satisfies `interface`, e.g. `Iterator[u8]`
+    pub fn satisfies(&self, ty: &Type, interface: &Path) -> Result<(), Vec<Mismatch>> {
+        let mut mismatches = Vec::new();
+        let mut visited = Vec::new();
+        self.check_interface(ty, interface, &mut visited, &mut mismatches);
+        if mismatches.is_empty() { Ok(()) } else { Err(mismatches) }
+    }
+
+    fn check_interface(&self, ty: &Type, interface: &Path, visited: &mut Vec<String>, out: &mut Vec<Mismatch>) {
+        let Some(TypeDef::Interface(iface)) = self.items.lookup_type(interface) else {
+            out.push(Mismatch::NotAnInterface { name: format_path(interface) });
+            return;
+        };
+        let key = format_path(interface);
+        if visited.contains(&key) {
+            return;
+        }
+        visited.push(key);
+
+        let bindings = Bindings::new(&param_names(&iface.generic_params), &interface.generic_args, ty);
+        for required in &iface.methods {
+            self.check_method(ty, iface, required, &bindings, out);
+        }
+        for parent in iface.extends.iter().chain(&iface.requires) {
+            match bindings.apply(parent) {
+                Type::Path(parent) => self.check_interface(ty, &parent, visited, out),
+                other => out.push(Mismatch::NotAnInterface { name: format_type(&other) }),
+            }
+        }
+    }
+
+    fn check_method(
+        &self,
+        ty: &Type,
+        iface: &'a Interface,
+        required: &FunctionSignature,
+        bindings: &Bindings,
+        out: &mut Vec<Mismatch>,
+    ) {
+        if let Some(imp) = self.find_method(ty, &required.name) {
+            self.compare(ty, required, bindings, imp, out);
+        } else if !self.has_default(iface, &required.name) {
+            out.push(Mismatch::Missing { interface: iface.name.clone(), method: required.name.clone() });
+        }
+    }
+
+    /// The method called `name` declared with `ty` as its receiver
+    fn find_method(&self, ty: &Type, name: &str) -> Option<&'t FunctionDef<'a>> {
+        let Type::Path(path) = ty else { return None };
+        let def = self.items.lookup_type(path)?;
+        self.items.methods_of(def.name()).find(|f| f.signature.name == name)
+    }
+
+    /// Whether `iface` or an interface it extends has a default `name` with a body
+    fn has_default(&self, iface: &Interface, name: &str) -> bool {
+        let mut stack = vec![iface];
+        let mut seen: Vec<&str> = Vec::new();
+        while let Some(i) = stack.pop() {
+            if seen.contains(&i.name.as_str()) {
+                continue;
+            }
+            seen.push(&i.name);
+            if self.items.methods_of(&i.name).any(|f| f.signature.name == name && f.body.is_some()) {
+                return true;
+            }
+            for parent in &i.extends {
+                if let Type::Path(p) = parent
+                    && let Some(TypeDef::Interface(pi)) = self.items.lookup_type(p)
+                {
+                    stack.push(pi);
+                }
+            }
+        }
+        false
+    }
+
+    fn compare(
+        &self,
+        ty: &Type,
+        required: &FunctionSignature,
+        bindings: &Bindings,
+        imp: &FunctionDef<'a>,
+        out: &mut Vec<Mismatch>,
+    ) {
+        let method = required.name.clone();
+        let found = imp.signature;
+
+        let self_ok = match (&required.self_param, &found.self_param) {
+            (None, None) => true,
+            (Some(want), Some(have)) => {
+                want.is_pointer == have.is_pointer && (want.is_mutable || !have.is_mutable || !have.is_pointer)
+            }
+            _ => false,
+        };
+        if !self_ok {
+            out.push(Mismatch::SelfParameter {
+                method: method.clone(),
+                expected: self_flavour(&required.self_param).to_string(),
+                found: self_flavour(&found.self_param).to_string(),
+            });
+        }
+
+        if found.is_effect && !required.is_effect {
+            out.push(Mismatch::Effect { method: method.clone() });
+        }
+
+        if found.generic_params.len() != required.generic_params.len() {
+            out.push(Mismatch::GenericArity {
+                method: method.clone(),
+                expected: required.generic_params.len(),
+                found: found.generic_params.len(),
+            });
+        }
+
+        // The implementation sees its receiver's parameters, e.g. `T` in
+        // `func Vec[T]::push`, bound to the implementing type's arguments
+        let type_args = match ty {
+            Type::Path(p) => p.generic_args.clone(),
+            _ => Vec::new(),
+        };
+        let imp_bindings = match &found.receiver {
+            Some(receiver) => Bindings::new(&receiver_names(receiver), &type_args, ty),
+            None => Bindings::new(&[], &[], ty),
+        };
+
+        let expected: Vec<Type> = required.params.iter().map(|p| bindings.apply(&p.ty)).collect();
+        let actual: Vec<Type> = found.params.iter().map(|p| imp_bindings.apply(&p.ty)).collect();
+        if expected != actual {
+            out.push(Mismatch::Parameters {
+                method: method.clone(),
+                expected: format_type_list(&expected),
+                found: format_type_list(&actual),
+            });
+        }
+
+        let expected: Vec<Type> = required.return_types.iter().map(|t| bindings.apply(t)).collect();
+        let actual: Vec<Type> = found.return_types.iter().map(|t| imp_bindings.apply(t)).collect();
+        if expected != actual {
+            out.push(Mismatch::ReturnType {
+                method,
+                expected: format_type_list(&expected),
+                found: format_type_list(&actual),
+            });
+        }
+    }
+}
+
+/// Check every declared conformance in `items`
+pub fn check_conformance(items: &ItemTable) -> Vec<Diagnostic> {
+    ConformanceChecker::new(items).check()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    const ITER: &str = "\
+interface Iterator[T]
+    func! next(*mut self) -> ?T
+
+func[T] Iterator[T]::count(*mut self) -> usize
+    pass
+
+interface Counted[T]
+    extends
+        Iterator[T]
+    func count(*mut self) -> usize
+    func len(*self) -> usize
+
+";
+
+    fn satisfies(src: &str, ty: &str, interface: &str) -> Result<(), Vec<Mismatch>> {
+        let src = format!("{}{}", ITER, src);
+        let sf = parse(&src);
+        let items = ItemTable::from_source_file(&sf);
+        let ty = fig_parser::TypeParser::new().parse(fig_parser::Lexer::new(ty)).unwrap();
+        let iface = fig_parser::PathParser::new().parse(fig_parser::Lexer::new(interface)).unwrap();
+        ConformanceChecker::new(&items).satisfies(&ty, &iface)
+    }
+
+    #[test]
+    fn test_inherited_and_default_methods() {
+        let src = "struct Bytes\n    len: usize\n\nfunc! Bytes::next(*mut self) -> ?u8\n    pass\n\nfunc Bytes::len(*self) -> usize\n    pass\n";
+        assert_eq!(satisfies(src, "Bytes", "Iterator[u8]"), Ok(()));
+        // `count` comes from the default on `Iterator`, `next` through `extends`
+        assert_eq!(satisfies(src, "Bytes", "Counted[u8]"), Ok(()));
+        // The element type must match the interface's argument
+        assert!(matches!(
+            satisfies(src, "Bytes", "Iterator[u32]").unwrap_err()[..],
+            [Mismatch::ReturnType { .. }]
+        ));
+    }
+
+    #[test]
+    fn test_missing_methods() {
+        let src = "struct Empty\n    x: u8\n\nfunc! Empty::next(*mut self) -> ?u8\n    pass\n";
+        assert_eq!(
+            satisfies(src, "Empty", "Counted[u8]").unwrap_err(),
+            vec![Mismatch::Missing { interface: "Counted".into(), method: "len".into() }]
+        );
+    }
+
+    #[test]
+    fn test_self_flavour_and_effect() {
+        let src = "struct S\n    x: u8\n\nfunc! S::next(self) -> ?u8\n    pass\n\nfunc! S::len(*mut self) -> usize\n    pass\n";
+        let errors = satisfies(src, "S", "Counted[u8]").unwrap_err();
+        assert_eq!(
+            errors,
+            vec![
+                Mismatch::SelfParameter { method: "len".into(), expected: "*self".into(), found: "*mut self".into() },
+                Mismatch::Effect { method: "len".into() },
+                Mismatch::SelfParameter { method: "next".into(), expected: "*mut self".into(), found: "self".into() },
+            ]
+        );
+        // A read-only `*self` is accepted where the interface asks for `*mut self`
+        let src = "struct R\n    x: u8\n\nfunc! R::next(*self) -> ?u8\n    pass\n";
+        assert_eq!(satisfies(src, "R", "Iterator[u8]"), Ok(()));
+    }
+
+    #[test]
+    fn test_generic_receivers_and_declared_requires() {
+        let src = "struct List[T]\n    requires\n        Iterator[T]\n    head: ?*T\n\nstruct Bad\n    requires\n        Iterator[u8]\n    x: u8\n\nfunc! List::next(*mut self) -> ?T\n    pass\n";
+        let sf = parse(&format!("{}{}", ITER, src));
+        let items = ItemTable::from_source_file(&sf);
+        let diags = check_conformance(&items);
+        assert_eq!(diags.len(), 1, "{:?}", diags);
+        assert_eq!(diags[0].message, "`Bad` does not satisfy `Iterator[u8]`");
+        assert_eq!(diags[0].notes, vec!["missing method `next` required by `Iterator`"]);
+    }
+}
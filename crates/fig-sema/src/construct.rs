@@ -0,0 +1,288 @@
+//! Telling construction apart from calls and constants
+//!
+//! The parser cannot know what a name refers to, so `Point(1, 2)` and
+//! `Shape::Circle(r)` parse as calls and `Color::Red` as a path, just like
+//! `max(1, 2)` and `math::PI`. Only a literal with named fields, such as
+//! `Point(x: 1, ..origin)`, is a [`StructLiteralExpr`] from the start.
+//! [`resolve_construction`] looks the names up once the items of the file
+//! are known and rewrites:
+//!
+//! - a call of a struct into a struct literal with positional fields, whose
+//!   last argument is its base when it is written `..base`
+//! - a call of a union variant, or a path to one of type `ok`, into a
+//!   variant literal
+//! - a path to a variant of a C-like enum into an enum variant
+//!
+//! A function of the same name wins over a struct, as it does when the call
+//! is type checked, and a call with the wrong number of arguments for a
+//! variant is left alone for the type checker to report.
+
+use std::collections::HashMap;
+
+use fig_parser::ast::*;
+use fig_parser::fold::{self, Fold};
+use fig_parser::visit::{self, Visit};
+
+use crate::items::{ItemTable, TypeDef};
+use crate::typeck::{StaticCallee, static_callee};
+
+/// What a path written in the file turned out to name
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Constructor {
+    Struct,
+    /// A union variant, and whether its payload is of type `ok`
+    Variant { is_ok: bool },
+    EnumVariant,
+}
+
+/// Rewrite the calls and paths in `file` that construct a struct, union or
+/// enum value into the expressions for them
+pub fn resolve_construction(file: SourceFile) -> SourceFile {
+    let constructors = {
+        let items = ItemTable::from_source_file(&file);
+        let mut collect = Collect { items: &items, found: HashMap::new() };
+        collect.visit_source_file(&file);
+        collect.found
+    };
+    Rewrite { constructors }.fold_source_file(file)
+}
+
+/// The callee of a call as a path with the generic arguments of its owner.
+/// `Pair[i32](1, 2)` parses as a call of an index expression.
+fn callee_path(callee: &Expression) -> Option<StaticCallee> {
+    match callee {
+        Expression::Index(index) => match (index.object.as_ref(), index.index.as_ref()) {
+            (Expression::Path(owner), Expression::Path(arg)) if owner.generic_args.is_empty() => {
+                Some(StaticCallee { segments: owner.segments.clone(), owner_args: vec![Type::Path(arg.clone())] })
+            }
+            _ => None,
+        },
+        other => static_callee(other),
+    }
+}
+
+/// Resolves every path that could construct a value, keyed by its segments
+struct Collect<'t, 'a> {
+    items: &'t ItemTable<'a>,
+    found: HashMap<Vec<String>, Option<Constructor>>,
+}
+
+impl Collect<'_, '_> {
+    fn resolve(&mut self, segments: &[String]) {
+        if self.found.contains_key(segments) {
+            return;
+        }
+        let constructor = self.constructor(segments);
+        self.found.insert(segments.to_vec(), constructor);
+    }
+
+    fn constructor(&self, segments: &[String]) -> Option<Constructor> {
+        let items = self.items;
+        let path = Path::with_generics(segments.to_vec(), Vec::new());
+        let function = match segments {
+            [name] => items
+                .functions()
+                .iter()
+                .find(|f| f.signature.receiver.is_none() && f.qualified_name() == *name)
+                .or_else(|| items.lookup_function(&path).filter(|f| f.signature.receiver.is_none())),
+            _ => items.lookup_function(&path),
+        };
+        if function.is_some() || items.lookup_const(&path).is_some() {
+            return None;
+        }
+        if let Some(TypeDef::Struct(_)) = items.lookup_type(&path) {
+            return Some(Constructor::Struct);
+        }
+        let [owner @ .., variant] = segments else { return None };
+        if owner.is_empty() {
+            return None;
+        }
+        match items.lookup_type(&Path::with_generics(owner.to_vec(), Vec::new()))? {
+            TypeDef::Enum(e) if e.variants.iter().any(|v| v.name == *variant) => Some(Constructor::EnumVariant),
+            TypeDef::Union(u) => {
+                let v = u.variants.iter().find(|v| v.name == *variant)?;
+                Some(Constructor::Variant { is_ok: v.ty == Type::Ok })
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<'ast> Visit<'ast> for Collect<'_, '_> {
+    fn visit_expression(&mut self, expression: &'ast Expression) {
+        match expression {
+            Expression::Call(call) if !call.is_propagating => {
+                if let Some(callee) = callee_path(&call.callee) {
+                    self.resolve(&callee.segments);
+                }
+            }
+            Expression::Path(_) | Expression::TypeAccess(_) => {
+                if let Some(path) = static_callee(expression) {
+                    self.resolve(&path.segments);
+                }
+            }
+            _ => {}
+        }
+        visit::walk_expression(self, expression);
+    }
+}
+
+struct Rewrite {
+    constructors: HashMap<Vec<String>, Option<Constructor>>,
+}
+
+impl Rewrite {
+    fn constructor(&self, segments: &[String]) -> Option<Constructor> {
+        self.constructors.get(segments).copied().flatten()
+    }
+
+    /// The construction a call stands for, or the call back when it is not one
+    fn call(&self, call: CallExpr) -> Result<Expression, CallExpr> {
+        let Some(StaticCallee { mut segments, owner_args }) = callee_path(&call.callee) else { return Err(call) };
+        let constructor = match self.constructor(&segments) {
+            Some(constructor) if !call.is_propagating => constructor,
+            _ => return Err(call),
+        };
+        match constructor {
+            Constructor::Struct => {
+                let mut args = call.args;
+                let base = match args.last() {
+                    Some(Expression::Range(RangeExpr { start: None, end: Some(_), is_inclusive: false })) => {
+                        let Some(Expression::Range(range)) = args.pop() else { unreachable!() };
+                        range.end
+                    }
+                    _ => None,
+                };
+                Ok(Expression::StructLiteral(StructLiteralExpr {
+                    ty: Path::with_generics(segments, owner_args),
+                    fields: args.into_iter().map(|value| FieldInit { name: None, value }).collect(),
+                    base,
+                }))
+            }
+            Constructor::Variant { is_ok } if call.args.len() == 1 || (is_ok && call.args.is_empty()) => {
+                let variant = segments.pop().expect("a variant path has an owner");
+                Ok(Expression::VariantLiteral(VariantLiteralExpr {
+                    ty: Path::with_generics(segments, owner_args),
+                    variant,
+                    payload: call.args.into_iter().next().map(Box::new),
+                }))
+            }
+            _ => Err(call),
+        }
+    }
+
+    /// The value a path stands for, or none when it names something else
+    fn path(&self, expression: &Expression) -> Option<Expression> {
+        let StaticCallee { mut segments, owner_args } = static_callee(expression)?;
+        let constructor = self.constructor(&segments)?;
+        let variant = segments.pop()?;
+        let ty = match expression {
+            Expression::Path(path) => Path::with_generics(segments, path.generic_args.clone()),
+            _ => Path::with_generics(segments, owner_args),
+        };
+        match constructor {
+            Constructor::EnumVariant => Some(Expression::EnumVariant(EnumVariantExpr { ty, variant })),
+            Constructor::Variant { is_ok: true } => {
+                Some(Expression::VariantLiteral(VariantLiteralExpr { ty, variant, payload: None }))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Fold for Rewrite {
+    fn fold_expression(&mut self, expression: Expression) -> Expression {
+        let expression = match expression {
+            Expression::Call(call) => match self.call(call) {
+                Ok(construction) => construction,
+                Err(call) => Expression::Call(call),
+            },
+            Expression::Path(_) | Expression::TypeAccess(_) => match self.path(&expression) {
+                Some(value) => return value,
+                None => expression,
+            },
+            other => other,
+        };
+        fold::walk_expression(self, expression)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+    use fig_parser::format::format_expression;
+
+    const ITEMS: &str = "\
+struct Point
+    x: i32
+    y: i32
+
+struct Pair[T]
+    first: T
+    second: T
+
+union Shape
+    Circle: f64
+    Empty: ok
+
+enum Color
+    Red
+    Green
+
+func make(x: i32) -> Point
+    return Point(x, x)
+";
+
+    /// The value of the `let` that ends `main` after rewriting
+    fn rewrite(body: &str) -> Expression {
+        let file = resolve_construction(parse(&format!("{}\nfunc main()\n{}\n", ITEMS, body)));
+        let Some(NamespaceItem::Function(main)) = file.items.last() else { panic!("expected `main`") };
+        let Some(Statement::Let(l)) = main.body.statements.last() else { panic!("expected a `let`") };
+        (*l.value).clone()
+    }
+
+    #[test]
+    fn test_struct_calls_become_literals() {
+        let Expression::StructLiteral(lit) = rewrite("    let p = Point(1, make(2).x)") else {
+            panic!("expected a struct literal")
+        };
+        assert_eq!(lit.ty.segments, ["Point"]);
+        assert!(lit.fields.iter().all(|f| f.name.is_none()));
+        assert!(matches!(lit.fields[1].value, Expression::FieldAccess(_)));
+
+        let Expression::StructLiteral(generic) = rewrite("    let p = Pair[Point](make(1), make(2))") else {
+            panic!("expected a struct literal")
+        };
+        assert_eq!(generic.ty.generic_args, [Type::Path(Path::simple("Point".to_string()))]);
+
+        let Expression::StructLiteral(copy) = rewrite("    let p = Point(7, ..make(1))") else {
+            panic!("expected a struct literal")
+        };
+        assert_eq!(copy.fields.len(), 1);
+        assert!(matches!(copy.base.as_deref(), Some(Expression::Call(_))));
+    }
+
+    #[test]
+    fn test_functions_and_mismatched_variants_stay_calls() {
+        assert!(matches!(rewrite("    let p = make(1)"), Expression::Call(_)));
+        assert!(matches!(rewrite("    let p = Shape::Circle(1.0, 2.0)"), Expression::Call(_)));
+    }
+
+    #[test]
+    fn test_variants_become_values() {
+        assert_eq!(format_expression(&rewrite("    let s = Shape::Circle(1.0)")), "Shape::Circle(1.0)");
+        assert!(matches!(
+            rewrite("    let s = Shape::Circle(1.0)"),
+            Expression::VariantLiteral(VariantLiteralExpr { payload: Some(_), .. })
+        ));
+        assert!(matches!(
+            rewrite("    let s = Shape::Empty"),
+            Expression::VariantLiteral(VariantLiteralExpr { payload: None, .. })
+        ));
+        let Expression::EnumVariant(red) = rewrite("    let c = Color::Red") else { panic!("expected an enum variant") };
+        assert_eq!((red.ty.segments.as_slice(), red.variant.as_str()), (["Color".to_string()].as_slice(), "Red"));
+        assert_eq!(format_expression(&rewrite("    let c = Color::Blue")), "Color::Blue");
+        assert!(!matches!(rewrite("    let c = Color::Blue"), Expression::EnumVariant(_)));
+    }
+}
@@ -0,0 +1,122 @@
+//! Diagnostics reported by semantic passes
+//!
+//! A diagnostic locates its problem by the enclosing function and the
+//! offending code re-rendered with [`fig_parser::format`]. Statements parsed
+//! from source also carry spans (see [`Block::span`]), which place it in the
+//! text; a syntax tree read back from JSON has none.
+//!
+//! [`Block::span`]: fig_parser::ast::Block::span
+
+use std::fmt;
+
+use fig_parser::ast::Span;
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    /// Qualified name of the function the problem was found in, if any
+    pub function: Option<String>,
+    /// The offending code, rendered back to source
+    pub snippet: Option<String>,
+    /// Byte offsets of the statement the problem is in, when known. Boxed to
+    /// keep `Result<_, Diagnostic>` small.
+    pub span: Option<Box<Span>>,
+    /// Further explanation, one line each, in the order they should be read
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>) -> Self {
+        Diagnostic {
+            severity: Severity::Error,
+            message: message.into(),
+            function: None,
+            snippet: None,
+            span: None,
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Diagnostic { severity: Severity::Warning, ..Diagnostic::error(message) }
+    }
+
+    pub fn in_function(mut self, name: impl Into<String>) -> Self {
+        self.function = Some(name.into());
+        self
+    }
+
+    pub fn with_snippet(mut self, snippet: impl Into<String>) -> Self {
+        self.snippet = Some(snippet.into());
+        self
+    }
+
+    /// Place the diagnostic at the statement spanning `span`, if the syntax
+    /// tree has statement spans
+    pub fn at(mut self, span: Option<Span>) -> Self {
+        self.span = span.map(Box::new);
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let level = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{}: {}", level, self.message)?;
+        if let Some(function) = &self.function {
+            write!(f, "\n  --> in `{}`", function)?;
+        }
+        if let Some(snippet) = &self.snippet {
+            write!(f, "\n   |  {}", snippet)?;
+        }
+        for note in &self.notes {
+            write!(f, "\n   = note: {}", note)?;
+        }
+        Ok(())
+    }
+}
+
+/// 1-based line and column of a byte offset
+pub fn line_column(src: &str, offset: usize) -> (usize, usize) {
+    let before = &src[..offset.min(src.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+    (line, column)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let diag = Diagnostic::error("pure function writes through a pointer")
+            .in_function("Vec::clear")
+            .with_snippet("*p = 0")
+            .with_note("declare it `func!` to allow writes");
+        assert_eq!(
+            diag.to_string(),
+            "error: pure function writes through a pointer\n  --> in `Vec::clear`\n   |  *p = 0\n   = note: declare it `func!` to allow writes"
+        );
+    }
+}
@@ -0,0 +1,525 @@
+//! Purity checking for `func` versus `func!`
+//!
+//! Implements the rules of the purity & effect specification
+//! (`docs/docs/language-guide/purity_effect.md`). A pure `func` may not:
+//!
+//! - write through a pointer or slice, including `self` when it is `*mut self`
+//! - assign to anything other than its own locals
+//! - call a `func!` or `extern` function, directly, as a method, or through
+//!   an interface bound on a generic parameter
+//! - call the `print`, `println`, `malloc`, `calloc`, `realloc` or `free`
+//!   builtin
+//! - declare an `extern` function
+//! - accept a `fn!` parameter, or call a `fn!` value
+//!
+//! Every `extern` function must itself be declared `func!`. Allocation has no
+//! dedicated syntax: the allocation builtins, like allocation APIs written in
+//! Fig, are effectful and are caught as calls.
+//!
+//! A `fn` lambda is pure wherever it is written, so its body is held to the
+//! same rules even inside a `func!`; the body of a `fn!` lambda is not
+//! checked, since creating the lambda has no effect until it is called.
+//!
+//! Diagnostics for effectful calls explain the effect chain: why the callee is
+//! effectful, then why the function it calls is, and so on down to the write,
+//! `extern` declaration or interface declaration at the bottom.
+
+use fig_parser::ast::*;
+use fig_parser::format::{format_expression, format_type};
+use fig_parser::visit::{self, Visit};
+
+use crate::diagnostics::Diagnostic;
+use crate::items::{FunctionDef, ItemTable};
+use crate::resolve::{
+    BindingKind, BodyScope, Callee, Scoped, is_indirect, walk_scoped_block, walk_scoped_lambda, walk_scoped_statement,
+};
+use crate::typeck::Builtin;
+
+/// An operation that makes a function effectful
+#[derive(Debug, Clone)]
+enum Effect<'t, 'a> {
+    /// A write through memory the function does not own; the string says why
+    /// the target is considered indirect
+    Write { reason: String },
+    /// Assignment to a name that is not a local variable
+    NonLocalWrite { name: String },
+    /// A call whose every possible callee is effectful
+    Call { callees: Vec<Callee<'t, 'a>> },
+    /// A call of a builtin the host provides, such as `println` or `malloc`
+    BuiltinCall { name: String },
+    /// A nested `extern` function declaration
+    ExternDeclaration { name: String },
+    /// A call through a value of a `fn!` type
+    IndirectCall { callee: String },
+    /// A lambda parameter of a `fn!` type
+    EffectfulParameter { name: String, ty: String },
+}
+
+#[derive(Debug, Clone)]
+struct Site<'t, 'a> {
+    effect: Effect<'t, 'a>,
+    /// The offending statement or expression, rendered back to source
+    snippet: String,
+    /// The pure lambda the site is in, rendered back to source; none when it
+    /// is in the function's own body
+    lambda: Option<String>,
+    span: Option<Span>,
+}
+
+pub struct EffectChecker<'t, 'a> {
+    items: &'t ItemTable<'a>,
+}
+
+impl<'t, 'a> EffectChecker<'t, 'a> {
+    pub fn new(items: &'t ItemTable<'a>) -> Self {
+        EffectChecker { items }
+    }
+
+    /// Check every function in the item table
+    pub fn check(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        for function in self.items.functions() {
+            let sig = function.signature;
+            let name = function.qualified_name();
+            if sig.is_extern && !sig.is_effect {
+                diagnostics.push(
+                    Diagnostic::error(format!("extern function `{}` must be declared `func!`", name))
+                        .in_function(&name)
+                        .with_note("foreign code can have any side effect, so calls to it are never pure"),
+                );
+            }
+            if sig.is_extern {
+                continue;
+            }
+            if !sig.is_effect {
+                for param in effectful_params(&sig.params) {
+                    diagnostics.push(
+                        Diagnostic::error(format!(
+                            "pure function `{}` accepts effectful function `{}` of type `{}`",
+                            name,
+                            param.name,
+                            format_type(&param.ty)
+                        ))
+                        .in_function(&name)
+                        .with_note(format!("declare `{}` with `func!` to allow side effects", name)),
+                    );
+                }
+            }
+            for site in self.effects_of(function) {
+                if !sig.is_effect || site.lambda.is_some() {
+                    diagnostics.push(self.report(function, &name, site));
+                }
+            }
+        }
+        diagnostics
+    }
+
+    fn report(&self, function: &FunctionDef<'a>, name: &str, site: Site<'t, 'a>) -> Diagnostic {
+        let (subject, fix) = match &site.lambda {
+            Some(_) => {
+                (format!("pure lambda in `{}`", name), "write the lambda as `fn!` to allow side effects".to_string())
+            }
+            None => {
+                (format!("pure function `{}`", name), format!("declare `{}` with `func!` to allow side effects", name))
+            }
+        };
+        let diag = match site.effect {
+            Effect::Write { reason } => {
+                Diagnostic::error(format!("{} writes through a pointer", subject)).with_note(reason)
+            }
+            Effect::NonLocalWrite { name: target } => Diagnostic::error(format!(
+                "{} assigns to `{}`, which is not one of its locals",
+                subject, target
+            ))
+            .with_note("pure functions may only mutate their own `mut` locals"),
+            Effect::Call { callees } => {
+                let callee = callees[0];
+                let mut diag =
+                    Diagnostic::error(format!("{} calls effectful function `{}`", subject, callee.name()));
+                if callees.len() > 1 {
+                    diag = diag.with_note(format!(
+                        "the receiver's type is unknown, and every method named `{}` is effectful",
+                        callee.signature().name
+                    ));
+                }
+                let mut visited = vec![function.qualified_name()];
+                for note in self.explain(callee, &mut visited) {
+                    diag = diag.with_note(note);
+                }
+                diag
+            }
+            Effect::BuiltinCall { name: builtin } => {
+                Diagnostic::error(format!("{} calls effectful builtin `{}`", subject, builtin))
+            }
+            Effect::ExternDeclaration { name: extern_name } => {
+                Diagnostic::error(format!("{} declares extern function `{}`", subject, extern_name))
+            }
+            Effect::IndirectCall { callee } => {
+                Diagnostic::error(format!("{} calls effectful function value `{}`", subject, callee))
+            }
+            Effect::EffectfulParameter { name: param, ty } => {
+                Diagnostic::error(format!("{} accepts effectful function `{}` of type `{}`", subject, param, ty))
+            }
+        };
+        let diag = diag.in_function(name).with_snippet(site.snippet).at(site.span);
+        match site.lambda {
+            Some(lambda) => diag.with_note(format!("in lambda `{}`", lambda)).with_note(fix),
+            None => diag.with_note(fix),
+        }
+    }
+
+    /// Why `callee` is effectful, following the chain of effectful calls
+    fn explain(&self, callee: Callee<'t, 'a>, visited: &mut Vec<String>) -> Vec<String> {
+        let name = callee.name();
+        let function = match callee {
+            Callee::InterfaceMethod { interface, .. } => {
+                return vec![format!("`{}` is declared `func!` by interface `{}`", name, interface.name)];
+            }
+            Callee::Function(f) if f.signature.is_extern => {
+                return vec![format!("`{}` is an `extern` function", name)];
+            }
+            Callee::Function(f) => f,
+        };
+        let mut notes = vec![format!("`{}` is declared `func!`", name)];
+        if visited.contains(&name) {
+            return notes;
+        }
+        visited.push(name.clone());
+        let Some(site) = self.effects_of(function).into_iter().find(|site| site.lambda.is_none()) else {
+            return notes;
+        };
+        match site.effect {
+            Effect::Write { .. } | Effect::NonLocalWrite { .. } => {
+                notes.push(format!("`{}` writes to memory: `{}`", name, site.snippet));
+            }
+            Effect::ExternDeclaration { name: extern_name } => {
+                notes.push(format!("`{}` declares extern function `{}`", name, extern_name));
+            }
+            Effect::Call { callees } => {
+                notes.push(format!("`{}` calls `{}`: `{}`", name, callees[0].name(), site.snippet));
+                notes.extend(self.explain(callees[0], visited));
+            }
+            Effect::BuiltinCall { name: builtin } => {
+                notes.push(format!("`{}` calls effectful builtin `{}`: `{}`", name, builtin, site.snippet));
+            }
+            Effect::IndirectCall { callee } => {
+                notes.push(format!("`{}` calls effectful function value `{}`", name, callee));
+            }
+            Effect::EffectfulParameter { .. } => {}
+        }
+        notes
+    }
+
+    /// Every effectful operation in a function body, in source order
+    fn effects_of(&self, function: &'t FunctionDef<'a>) -> Vec<Site<'t, 'a>> {
+        let Some(body) = function.body else { return Vec::new() };
+        let scope = BodyScope::new(self.items, function);
+        let mut scan = Scan { checker: self, scope, lambda: None, sites: Vec::new() };
+        scan.visit_block(body);
+        scan.sites
+    }
+
+    /// The name of the effectful builtin `call` invokes, if it invokes one.
+    /// As in the type checker, a declared function or a local of the same
+    /// name hides the builtin.
+    fn builtin(&self, scope: &BodyScope<'t, 'a>, call: &CallExpr) -> Option<String> {
+        let Expression::Path(path) = call.callee.as_ref() else { return None };
+        let [name] = path.segments.as_slice() else { return None };
+        let builtin = Builtin::from_name(name)?;
+        let hidden = scope.lookup(name).is_some() || self.items.lookup_function(path).is_some();
+        (builtin.is_effectful() && !hidden).then(|| name.clone())
+    }
+
+    /// Decide whether assigning to `lhs` writes outside the function's own
+    /// locals. Walks from the outermost projection down to the root name; the
+    /// first pointer or slice crossed on the way makes it a pointer write.
+    fn classify_write(&self, scope: &BodyScope<'t, 'a>, lhs: &Expression) -> Option<Effect<'t, 'a>> {
+        let mut place = lhs;
+        let mut projected = false;
+        loop {
+            let object = match place {
+                Expression::Parenthesized(inner) => {
+                    place = inner;
+                    continue;
+                }
+                Expression::UnaryOp(op) if op.op == UnaryOperator::Dereference => {
+                    return Some(Effect::Write {
+                        reason: format!("`{}` is dereferenced", format_expression(&op.operand)),
+                    });
+                }
+                Expression::FieldAccess(fa) => &fa.object,
+                Expression::Index(idx) => &idx.object,
+                Expression::Path(path) if path.segments.len() == 1 => {
+                    let name = &path.segments[0];
+                    return match scope.lookup(name) {
+                        Some(binding) if projected && binding.ty.is_none() && binding.kind != BindingKind::Mut => {
+                            Some(Effect::Write {
+                                reason: format!("`{}` is not `mut`, so the write goes through memory it refers to", name),
+                            })
+                        }
+                        Some(_) => None,
+                        None => Some(Effect::NonLocalWrite { name: name.clone() }),
+                    };
+                }
+                Expression::Path(path) => return Some(Effect::NonLocalWrite { name: path.segments.join("::") }),
+                _ => return None,
+            };
+            if let Some(ty) = scope.type_of(object)
+                && is_indirect(&ty)
+            {
+                return Some(Effect::Write {
+                    reason: format!("`{}` is a pointer (`{}`)", format_expression(object), format_type(&ty)),
+                });
+            }
+            projected = true;
+            place = object;
+        }
+    }
+}
+
+/// Collects the effectful operations of one function body
+struct Scan<'c, 't, 'a> {
+    checker: &'c EffectChecker<'t, 'a>,
+    scope: BodyScope<'t, 'a>,
+    /// The innermost pure lambda being scanned, rendered back to source
+    lambda: Option<String>,
+    sites: Vec<Site<'t, 'a>>,
+}
+
+impl<'t, 'a> Scan<'_, 't, 'a> {
+    fn push(&mut self, effect: Effect<'t, 'a>, snippet: String) {
+        self.sites.push(Site { effect, snippet, lambda: self.lambda.clone(), span: self.scope.span() });
+    }
+
+    /// The effect of calling through `call`, if it has one
+    fn call(&self, call: &CallExpr) -> Option<Effect<'t, 'a>> {
+        match self.scope.function_value(call) {
+            Some(Type::Function(FunctionType { is_effect: true, .. })) => {
+                Some(Effect::IndirectCall { callee: format_expression(&call.callee) })
+            }
+            Some(_) => None,
+            None => {
+                let callees = self.scope.resolve_call(call);
+                if callees.is_empty() {
+                    self.checker.builtin(&self.scope, call).map(|name| Effect::BuiltinCall { name })
+                } else {
+                    callees.iter().all(Callee::is_effectful).then_some(Effect::Call { callees })
+                }
+            }
+        }
+    }
+}
+
+impl<'t, 'a> Scoped<'t, 'a> for Scan<'_, 't, 'a> {
+    fn scope(&mut self) -> &mut BodyScope<'t, 'a> {
+        &mut self.scope
+    }
+}
+
+impl<'ast> Visit<'ast> for Scan<'_, '_, '_> {
+    fn visit_block(&mut self, block: &'ast Block) {
+        walk_scoped_block(self, block);
+    }
+
+    fn visit_statement(&mut self, stmt: &'ast Statement) {
+        if let Statement::FunctionDeclaration(d) = stmt
+            && d.signature.is_extern
+        {
+            let name = d.signature.name.clone();
+            self.push(Effect::ExternDeclaration { name: name.clone() }, format!("extern func! {}(…)", name));
+        }
+        walk_scoped_statement(self, stmt);
+    }
+
+    fn visit_expression(&mut self, expr: &'ast Expression) {
+        match expr {
+            Expression::Assign(assign) => {
+                if let Some(effect) = self.checker.classify_write(&self.scope, &assign.lhs) {
+                    self.push(effect, format_expression(expr));
+                }
+            }
+            Expression::Call(call) => {
+                if let Some(effect) = self.call(call) {
+                    self.push(effect, format_expression(expr));
+                }
+            }
+            // The body of a `fn!` lambda has no effects that matter where it
+            // is written; those of a pure lambda are tagged with the lambda
+            Expression::Lambda(lambda) if lambda.is_effect => return,
+            Expression::Lambda(lambda) => {
+                let snippet = format_expression(expr);
+                let outer = self.lambda.replace(snippet.clone());
+                for param in effectful_params(&lambda.params) {
+                    let effect = Effect::EffectfulParameter { name: param.name.clone(), ty: format_type(&param.ty) };
+                    self.push(effect, snippet.clone());
+                }
+                walk_scoped_lambda(self, expr);
+                self.lambda = outer;
+                return;
+            }
+            _ => {}
+        }
+        visit::walk_expression(self, expr);
+    }
+}
+
+/// The parameters of a `fn!` function type, which a pure function or lambda
+/// may not accept
+fn effectful_params(params: &[FunctionParameter]) -> impl Iterator<Item = &FunctionParameter> {
+    params.iter().filter(|param| matches!(param.ty, Type::Function(FunctionType { is_effect: true, .. })))
+}
+
+/// Check every function in `items` against the purity rules
+pub fn check_effects(items: &ItemTable) -> Vec<Diagnostic> {
+    EffectChecker::new(items).check()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    fn check(src: &str) -> Vec<Diagnostic> {
+        let sf = parse(src);
+        let items = ItemTable::from_source_file(&sf);
+        check_effects(&items)
+    }
+
+    #[test]
+    fn test_pure_function_may_read_and_mutate_locals() {
+        let diags = check(
+            "func sum(p: *i32, n: usize) -> i32\n    mut total = 0\n    mut i = 0\n    while i < n\n        total = total + p[i]\n        i += 1\n    return total\n",
+        );
+        assert!(diags.is_empty(), "{:?}", diags);
+    }
+
+    #[test]
+    fn test_pointer_writes() {
+        let diags = check(
+            "struct Counter\n    n: u32\n\nfunc reset(p: *mut i32)\n    *p = 0\n\nfunc Counter::bump(*mut self)\n    self.n += 1\n\nfunc! Counter::clear(*mut self)\n    self.n = 0\n",
+        );
+        assert_eq!(diags.len(), 2, "{:?}", diags);
+        assert_eq!(diags[0].message, "pure function `reset` writes through a pointer");
+        assert_eq!(diags[0].snippet.as_deref(), Some("*p = 0"));
+        assert_eq!(diags[0].notes[0], "`p` is dereferenced");
+        assert_eq!(diags[1].message, "pure function `Counter::bump` writes through a pointer");
+        assert_eq!(diags[1].notes[0], "`self` is a pointer (`*mut Counter`)");
+    }
+
+    #[test]
+    fn test_sites_are_spanned_by_their_statement() {
+        let src = "func clamp(p: *mut i32)\n    if *p > 9\n        *p = 9\n    let x = *p\n";
+        let diags = check(src);
+        assert_eq!(diags.len(), 1, "{:?}", diags);
+        let span = diags[0].span.as_deref().unwrap();
+        assert_eq!(&src[span.start..span.end], "*p = 9\n");
+    }
+
+    #[test]
+    fn test_global_assignment() {
+        let diags = check("const LIMIT: u32 = 4\n\nfunc f()\n    LIMIT = 5\n");
+        assert_eq!(diags.len(), 1);
+        assert!(diags[0].message.contains("assigns to `LIMIT`"));
+    }
+
+    #[test]
+    fn test_extern_must_be_effectful() {
+        let diags = check("extern func write(fd: i32, buf: *u8, len: usize) -> isize\n");
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].message, "extern function `write` must be declared `func!`");
+    }
+
+    #[test]
+    fn test_builtins_that_print_or_allocate_are_effectful() {
+        let diags = check(
+            "func scratch(n: usize) -> usize\n    let p = malloc(n)\n    println(n)\n    free(p)\n    assert(n > 0)\n    return n\n",
+        );
+        let messages: Vec<&str> = diags.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "pure function `scratch` calls effectful builtin `malloc`",
+                "pure function `scratch` calls effectful builtin `println`",
+                "pure function `scratch` calls effectful builtin `free`",
+            ]
+        );
+        assert!(check("func! scratch(n: usize)\n    free(malloc(n))\n").is_empty());
+
+        let chain = check("func! log(n: i32)\n    println(n)\n\nfunc f(n: i32)\n    log(n)\n");
+        assert_eq!(chain.len(), 1, "{:?}", chain);
+        assert!(chain[0].notes.contains(&"`log` calls effectful builtin `println`: `println(n)`".to_string()));
+
+        let shadowed = "func println(n: i32) -> i32\n    return n\n\nfunc f() -> i32\n    return println(1)\n";
+        assert!(check(shadowed).is_empty());
+    }
+
+    #[test]
+    fn test_effect_chain_is_explained() {
+        let diags = check(
+            "extern func! malloc(size: usize) -> *mut u8\n\nfunc! alloc_buffer(n: usize) -> *mut u8\n    return malloc(n)\n\nfunc make() -> *mut u8\n    return alloc_buffer(16)\n",
+        );
+        assert_eq!(diags.len(), 1, "{:?}", diags);
+        let diag = &diags[0];
+        assert_eq!(diag.message, "pure function `make` calls effectful function `alloc_buffer`");
+        assert_eq!(diag.snippet.as_deref(), Some("alloc_buffer(16)"));
+        assert_eq!(
+            diag.notes,
+            vec![
+                "`alloc_buffer` is declared `func!`",
+                "`alloc_buffer` calls `malloc`: `malloc(n)`",
+                "`malloc` is an `extern` function",
+                "declare `make` with `func!` to allow side effects",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_calls_through_interfaces_and_generics() {
+        let diags = check(
+            "interface Sink\n    func! put(*mut self, b: u8) -> ok\n    func len(*self) -> usize\n\nfunc[S: Sink] size(s: *S) -> usize\n    return s.len()\n\nfunc[S: Sink] emit(s: *mut S) -> ok\n    return s.put(1u8)\n",
+        );
+        assert_eq!(diags.len(), 1, "{:?}", diags);
+        assert_eq!(diags[0].message, "pure function `emit` calls effectful function `Sink::put`");
+        assert_eq!(diags[0].notes[0], "`Sink::put` is declared `func!` by interface `Sink`");
+    }
+
+    #[test]
+    fn test_method_calls_on_known_receivers() {
+        let diags = check(
+            "struct Vec\n    len: usize\n\nfunc! Vec::push(*mut self, x: i32)\n    pass\n\nfunc Vec::size(*self) -> usize\n    return self.len\n\nfunc fill(v: *mut Vec) -> usize\n    v.push(1)\n    return v.size()\n",
+        );
+        assert_eq!(diags.len(), 1, "{:?}", diags);
+        assert_eq!(diags[0].snippet.as_deref(), Some("v.push(1)"));
+    }
+
+    #[test]
+    fn test_function_values_and_lambdas() {
+        let diags = check(
+            "func! log(x: i32)\n    pass\n\nfunc twice(f: fn(i32) -> i32, x: i32) -> i32\n    return f(f(x))\n\n\
+             func run(f: fn!(i32)) -> i32\n    return 0\n\nfunc! each(f: fn!(i32))\n    f(1)\n    \
+             let g = fn(x: i32) -> i32 => log(x)\n    let h = fn!(x: i32) => log(x)\n\n\
+             func later() -> fn!(i32)\n    return fn!(x: i32) => log(x)\n",
+        );
+        let messages: Vec<&str> = diags.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            [
+                "pure function `run` accepts effectful function `f` of type `fn!(i32)`",
+                "pure lambda in `each` calls effectful function `log`",
+            ]
+        );
+        assert_eq!(diags[1].snippet.as_deref(), Some("log(x)"));
+        assert_eq!(
+            diags[1].notes,
+            [
+                "`log` is declared `func!`",
+                "in lambda `fn(x: i32) -> i32 => log(x)`",
+                "write the lambda as `fn!` to allow side effects",
+            ]
+        );
+
+        let diags = check("func apply(f: fn!(i32)) -> i32\n    f(2)\n    return 0\n");
+        assert_eq!(diags[1].message, "pure function `apply` calls effectful function value `f`");
+        assert_eq!(diags[1].snippet.as_deref(), Some("f(2)"));
+    }
+}
@@ -0,0 +1,751 @@
+//! Generic parameter validation
+//!
+//! Declarations are checked first:
+//!
+//! - a parameter name may appear only once in a parameter list;
+//! - a `where` clause may only constrain parameters declared on the item (or,
+//!   for a method such as `func Vec[T]::push`, the receiver's arguments);
+//! - every bound must name an interface;
+//! - a default must itself satisfy the parameter's bounds;
+//! - a const parameter must have an integer type.
+//!
+//! Every instantiation site — a path with generic arguments such as `Vec[T]`
+//! or `Array[T, 4]` appearing in a field, variant, alias, signature, bound or
+//! function body — is then checked for arity, for the kind of each argument
+//! (type or constant) and for the bounds of the parameter it binds. A type
+//! argument that is itself a generic parameter satisfies a bound when one of
+//! its own bounds is, or extends, the required interface.
+//!
+//! Names that resolve to nothing are left alone; they are reported elsewhere.
+
+use fig_parser::ast::*;
+use fig_parser::format::{format_path, format_type};
+
+use crate::conformance::ConformanceChecker;
+use crate::diagnostics::Diagnostic;
+use crate::items::{ItemTable, TypeDef};
+use crate::resolve::substitute;
+
+/// A generic parameter visible at an instantiation site
+#[derive(Debug, Clone)]
+enum InScope {
+    Type { bounds: Vec<Type> },
+    Const,
+}
+
+/// The item being checked and the generic parameters it can see
+struct Context {
+    owner: String,
+    scope: Vec<(String, InScope)>,
+}
+
+impl Context {
+    fn new(owner: impl Into<String>) -> Self {
+        Context { owner: owner.into(), scope: Vec::new() }
+    }
+
+    fn declare(&mut self, params: &[GenericParameter]) {
+        for param in params {
+            let entry = match param {
+                GenericParameter::Type { bounds, .. } => InScope::Type { bounds: bounds.clone() },
+                GenericParameter::Const { .. } => InScope::Const,
+            };
+            self.scope.push((param.name().to_string(), entry));
+        }
+    }
+
+    /// The innermost parameter called `ty`, if `ty` is a bare name
+    fn lookup(&self, ty: &Type) -> Option<&InScope> {
+        let Type::Path(path) = ty else { return None };
+        let [name] = path.segments.as_slice() else { return None };
+        if !path.generic_args.is_empty() {
+            return None;
+        }
+        self.scope.iter().rev().find(|(n, _)| n == name).map(|(_, p)| p)
+    }
+
+    fn error(&self, message: String) -> Diagnostic {
+        Diagnostic::error(message).in_function(self.owner.clone())
+    }
+}
+
+pub struct GenericsChecker<'t, 'a> {
+    items: &'t ItemTable<'a>,
+    conformance: ConformanceChecker<'t, 'a>,
+}
+
+impl<'t, 'a> GenericsChecker<'t, 'a> {
+    pub fn new(items: &'t ItemTable<'a>) -> Self {
+        GenericsChecker { items, conformance: ConformanceChecker::new(items) }
+    }
+
+    /// Check every type and function declaration in the table
+    pub fn check(&self) -> Vec<Diagnostic> {
+        let mut out = Vec::new();
+        for (name, def) in self.items.types() {
+            self.check_type(name, def, &mut out);
+        }
+        for function in self.items.functions() {
+            let mut cx = Context::new(function.qualified_name());
+            let mut receiver_names = Vec::new();
+            if let Some(receiver) = &function.signature.receiver {
+                receiver_names = self.declare_receiver(receiver, &function.signature.unbound_constraints, &mut cx);
+            }
+            self.check_signature(function.signature, &receiver_names, &mut cx, &mut out);
+            if let Some(body) = function.body {
+                self.visit_block(body, &cx, &mut out);
+            }
+        }
+        out
+    }
+
+    fn check_type(&self, name: &str, def: TypeDef<'a>, out: &mut Vec<Diagnostic>) {
+        let mut cx = Context::new(name);
+        cx.declare(def.generic_params());
+        self.check_declaration(def.generic_params(), def.unbound_constraints(), &[], &cx, out);
+        match def {
+            TypeDef::Struct(s) => {
+                for field in &s.fields {
+                    self.visit_type(&field.ty, &cx, out);
+                }
+                self.visit_types(&s.requires, &cx, out);
+            }
+            TypeDef::Union(u) => {
+                for variant in &u.variants {
+                    self.visit_type(&variant.ty, &cx, out);
+                }
+                self.visit_types(&u.requires, &cx, out);
+            }
+            TypeDef::Enum(e) => {
+                if let Some(repr) = &e.representation {
+                    self.visit_type(repr, &cx, out);
+                }
+                self.visit_types(&e.requires, &cx, out);
+            }
+            TypeDef::Alias(a) => self.visit_type(&a.aliased_type, &cx, out),
+            TypeDef::Interface(i) => {
+                self.visit_types(&i.extends, &cx, out);
+                self.visit_types(&i.requires, &cx, out);
+                for method in &i.methods {
+                    let mut method_cx = Context::new(format!("{}::{}", name, method.name));
+                    method_cx.scope = cx.scope.clone();
+                    self.check_signature(method, &[], &mut method_cx, out);
+                }
+            }
+        }
+    }
+
+    /// Bring the names bound by a receiver such as `Vec[T]` into scope. Each
+    /// takes the bounds of the receiver type's corresponding parameter plus
+    /// any `where` constraints naming it. Returns the bound names.
+    fn declare_receiver(&self, receiver: &Path, constraints: &[GenericParameter], cx: &mut Context) -> Vec<String> {
+        let declared = self.items.lookup_type(receiver).map(|def| def.generic_params()).unwrap_or(&[]);
+        let mut names = Vec::new();
+        for (i, arg) in receiver.generic_args.iter().enumerate() {
+            let Type::Path(path) = arg else { continue };
+            let [name] = path.segments.as_slice() else { continue };
+            let entry = match declared.get(i) {
+                Some(GenericParameter::Const { .. }) => InScope::Const,
+                Some(GenericParameter::Type { bounds, .. }) => InScope::Type {
+                    bounds: bounds.iter().map(|b| substitute(b, declared, &receiver.generic_args)).collect(),
+                },
+                None => InScope::Type { bounds: Vec::new() },
+            };
+            let entry = match entry {
+                InScope::Type { mut bounds } => {
+                    for constraint in constraints {
+                        if let GenericParameter::Type { name: n, bounds: extra, .. } = constraint
+                            && n == name
+                        {
+                            bounds.extend(extra.iter().cloned());
+                        }
+                    }
+                    InScope::Type { bounds }
+                }
+                InScope::Const => InScope::Const,
+            };
+            cx.scope.push((name.clone(), entry));
+            names.push(name.clone());
+        }
+        names
+    }
+
+    fn check_signature(
+        &self,
+        signature: &FunctionSignature,
+        receiver_names: &[String],
+        cx: &mut Context,
+        out: &mut Vec<Diagnostic>,
+    ) {
+        cx.declare(&signature.generic_params);
+        self.check_declaration(&signature.generic_params, &signature.unbound_constraints, receiver_names, cx, out);
+        for param in &signature.params {
+            self.visit_type(&param.ty, cx, out);
+        }
+        self.visit_types(&signature.return_types, cx, out);
+    }
+
+    // =========================================================================
+    // Declarations
+    // =========================================================================
+
+    fn check_declaration(
+        &self,
+        params: &[GenericParameter],
+        unbound: &[GenericParameter],
+        receiver_names: &[String],
+        cx: &Context,
+        out: &mut Vec<Diagnostic>,
+    ) {
+        for (i, param) in params.iter().enumerate() {
+            if params[..i].iter().any(|p| p.name() == param.name()) {
+                out.push(cx.error(format!("generic parameter `{}` is declared more than once", param.name())));
+            }
+        }
+
+        for constraint in unbound {
+            if receiver_names.iter().any(|n| n == constraint.name()) {
+                continue;
+            }
+            match params.iter().find(|p| p.name() == constraint.name()) {
+                Some(declared) => out.push(
+                    cx.error(format!(
+                        "where clause constraint on `{}` does not match its declaration",
+                        constraint.name()
+                    ))
+                    .with_note(format!("`{}` is declared as `{}`", declared.name(), describe(declared))),
+                ),
+                None => out.push(
+                    cx.error(format!(
+                        "where clause constrains `{}`, which is not a generic parameter",
+                        constraint.name()
+                    ))
+                    .with_note(format!("declare it in the parameter list, e.g. `[{}]`", constraint.name())),
+                ),
+            }
+        }
+
+        for param in params {
+            match param {
+                GenericParameter::Type { name, bounds, default_type } => {
+                    for bound in bounds {
+                        self.check_bound(name, bound, cx, out);
+                    }
+                    if let Some(default) = default_type {
+                        self.visit_type(default, cx, out);
+                        for bound in bounds {
+                            let Type::Path(interface) = bound else { continue };
+                            if !matches!(self.items.lookup_type(interface), Some(TypeDef::Interface(_))) {
+                                continue;
+                            }
+                            if let Err(mismatches) = self.satisfies(default, interface, cx) {
+                                let mut diag = cx.error(format!(
+                                    "default `{}` for `{}` does not satisfy `{}`",
+                                    format_type(default),
+                                    name,
+                                    format_path(interface)
+                                ));
+                                for note in mismatches {
+                                    diag = diag.with_note(note);
+                                }
+                                out.push(diag);
+                            }
+                        }
+                    }
+                }
+                GenericParameter::Const { name, ty } => {
+                    if self.is_integer(ty) == Some(false) {
+                        out.push(cx.error(format!(
+                            "const generic `{}` has type `{}`, but const generics must be integers",
+                            name,
+                            format_type(ty)
+                        )));
+                    }
+                }
+            }
+        }
+    }
+
+    fn check_bound(&self, name: &str, bound: &Type, cx: &Context, out: &mut Vec<Diagnostic>) {
+        let not_an_interface = |what: &str| {
+            cx.error(format!("bound `{}` on `{}` is not an interface", format_type(bound), name))
+                .with_note(format!("`{}` is {}", format_type(bound), what))
+        };
+        match bound {
+            Type::Path(path) => {
+                match self.items.lookup_type(path) {
+                    Some(TypeDef::Interface(_)) | None => {}
+                    Some(TypeDef::Struct(_)) => out.push(not_an_interface("a struct")),
+                    Some(TypeDef::Union(_)) => out.push(not_an_interface("a union")),
+                    Some(TypeDef::Enum(_)) => out.push(not_an_interface("an enum")),
+                    Some(TypeDef::Alias(_)) => out.push(not_an_interface("a type alias")),
+                }
+                self.visit_type(bound, cx, out);
+            }
+            _ => out.push(cx.error(format!("bound `{}` on `{}` is not an interface", format_type(bound), name))),
+        }
+    }
+
+    /// Whether `ty` is an integer type, looking through aliases; `None` when unknown
+    fn is_integer(&self, ty: &Type) -> Option<bool> {
+        let mut ty = ty.clone();
+        for _ in 0..16 {
+            match &ty {
+                Type::U8
+                | Type::U16
+                | Type::U32
+                | Type::U64
+                | Type::USize
+                | Type::I8
+                | Type::I16
+                | Type::I32
+                | Type::I64
+                | Type::ISize => return Some(true),
+                Type::Path(path) => match self.items.lookup_type(path) {
+                    Some(TypeDef::Alias(alias)) => {
+                        ty = substitute(&alias.aliased_type, &alias.generic_params, &path.generic_args)
+                    }
+                    Some(_) => return Some(false),
+                    None => return None,
+                },
+                _ => return Some(false),
+            }
+        }
+        None
+    }
+
+    // =========================================================================
+    // Instantiation sites
+    // =========================================================================
+
+    fn visit_types(&self, types: &[Type], cx: &Context, out: &mut Vec<Diagnostic>) {
+        for ty in types {
+            self.visit_type(ty, cx, out);
+        }
+    }
+
+    fn visit_type(&self, ty: &Type, cx: &Context, out: &mut Vec<Diagnostic>) {
+        match ty {
+            Type::Path(path) => {
+                if !path.generic_args.is_empty() {
+                    self.check_instantiation(path, cx, out);
+                }
+                self.visit_types(&path.generic_args, cx, out);
+            }
+            Type::Pointer { element_type, .. } | Type::Array { element_type, .. } => {
+                self.visit_type(element_type, cx, out)
+            }
+            Type::Optional(inner) => self.visit_type(inner, cx, out),
+            Type::ErrorUnion { ok_type, err_type } => {
+                self.visit_type(ok_type, cx, out);
+                if !err_type.generic_args.is_empty() {
+                    self.check_instantiation(err_type, cx, out);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn check_instantiation(&self, path: &Path, cx: &Context, out: &mut Vec<Diagnostic>) {
+        let Some(def) = self.items.lookup_type(path) else { return };
+        let params = def.generic_params();
+        let args = &path.generic_args;
+        let required = params
+            .iter()
+            .filter(|p| !matches!(p, GenericParameter::Type { default_type: Some(_), .. }))
+            .count();
+        if args.len() < required || args.len() > params.len() {
+            let expected = if required == params.len() {
+                required.to_string()
+            } else {
+                format!("{} to {}", required, params.len())
+            };
+            out.push(cx.error(format!(
+                "`{}` takes {} generic argument(s) but {} were supplied",
+                def.name(),
+                expected,
+                args.len()
+            )));
+            return;
+        }
+
+        for (param, arg) in params.iter().zip(args) {
+            match param {
+                GenericParameter::Const { name, .. } => {
+                    if !self.is_constant(arg, cx) {
+                        out.push(cx.error(format!(
+                            "`{}` expects a constant for `{}`, found type `{}`",
+                            def.name(),
+                            name,
+                            format_type(arg)
+                        )));
+                    }
+                }
+                GenericParameter::Type { name, bounds, .. } => {
+                    if matches!(arg, Type::Const(_)) || matches!(cx.lookup(arg), Some(InScope::Const)) {
+                        out.push(cx.error(format!(
+                            "`{}` expects a type for `{}`, found constant `{}`",
+                            def.name(),
+                            name,
+                            format_type(arg)
+                        )));
+                        continue;
+                    }
+                    for bound in bounds {
+                        let Type::Path(interface) = substitute(bound, params, args) else { continue };
+                        if !matches!(self.items.lookup_type(&interface), Some(TypeDef::Interface(_))) {
+                            continue;
+                        }
+                        if let Err(notes) = self.satisfies(arg, &interface, cx) {
+                            let mut diag = cx.error(format!(
+                                "`{}` does not satisfy `{}`, required by `{}` of `{}`",
+                                format_type(arg),
+                                format_path(&interface),
+                                name,
+                                def.name()
+                            ));
+                            for note in notes {
+                                diag = diag.with_note(note);
+                            }
+                            out.push(diag);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Whether `arg` can bind a const parameter. Unresolved names get the benefit of the doubt.
+    fn is_constant(&self, arg: &Type, cx: &Context) -> bool {
+        match arg {
+            Type::Const(_) => true,
+            Type::Path(path) => match cx.lookup(arg) {
+                Some(InScope::Const) => true,
+                Some(InScope::Type { .. }) => false,
+                None => {
+                    path.generic_args.is_empty() && self.items.lookup_const(path).is_some()
+                        || self.items.lookup_type(path).is_none()
+                }
+            },
+            _ => false,
+        }
+    }
+
+    /// Whether `arg` satisfies `interface`. A generic parameter in scope
+    /// satisfies it through its own bounds; anything else is checked
+    /// structurally. On failure, returns notes explaining why.
+    fn satisfies(&self, arg: &Type, interface: &Path, cx: &Context) -> Result<(), Vec<String>> {
+        if matches!(arg, Type::SelfType) {
+            return Ok(());
+        }
+        if let Some(InScope::Type { bounds }) = cx.lookup(arg) {
+            return if bounds.iter().any(|b| self.implies(b, interface, 0)) {
+                Ok(())
+            } else {
+                Err(vec![format!(
+                    "add the bound to the generic parameter, e.g. `{}: {}`",
+                    format_type(arg),
+                    format_path(interface)
+                )])
+            };
+        }
+        if let Type::Path(path) = arg
+            && self.items.lookup_type(path).is_none()
+        {
+            return Ok(());
+        }
+        self.conformance
+            .satisfies(arg, interface)
+            .map_err(|mismatches| mismatches.iter().map(ToString::to_string).collect())
+    }
+
+    /// Whether having bound `have` guarantees `want`, following `extends`
+    fn implies(&self, have: &Type, want: &Path, depth: usize) -> bool {
+        let Type::Path(have) = have else { return false };
+        if have.segments.last() == want.segments.last() && have.generic_args == want.generic_args {
+            return true;
+        }
+        if depth > 16 {
+            return false;
+        }
+        let Some(TypeDef::Interface(iface)) = self.items.lookup_type(have) else { return false };
+        iface
+            .extends
+            .iter()
+            .any(|parent| self.implies(&substitute(parent, &iface.generic_params, &have.generic_args), want, depth + 1))
+    }
+
+    // =========================================================================
+    // Function bodies
+    // =========================================================================
+
+    fn visit_block(&self, block: &Block, cx: &Context, out: &mut Vec<Diagnostic>) {
+        for stmt in &block.statements {
+            self.visit_statement(stmt, cx, out);
+        }
+    }
+
+    fn visit_statement(&self, stmt: &Statement, cx: &Context, out: &mut Vec<Diagnostic>) {
+        match stmt {
+            Statement::Expression(e) | Statement::Return(e) => self.visit_expression(e, cx, out),
+            Statement::Let(LetStatement { ty, value, .. }) | Statement::Mut(MutStatement { ty, value, .. }) => {
+                if let Some(ty) = ty {
+                    self.visit_type(ty, cx, out);
+                }
+                self.visit_expression(value, cx, out);
+            }
+            Statement::Const(c) => {
+                if let Some(ty) = &c.ty {
+                    self.visit_type(ty, cx, out);
+                }
+                self.visit_expression(&c.value, cx, out);
+            }
+            Statement::Block(b) => self.visit_block(&b.body, cx, out),
+            Statement::If(i) => {
+                self.visit_expression(&i.condition, cx, out);
+                self.visit_block(&i.then_body, cx, out);
+                for elif in &i.elif_clauses {
+                    self.visit_expression(&elif.condition, cx, out);
+                    self.visit_block(&elif.body, cx, out);
+                }
+                if let Some(else_body) = &i.else_body {
+                    self.visit_block(else_body, cx, out);
+                }
+            }
+            Statement::For(f) => {
+                self.visit_expression(&f.iterable, cx, out);
+                self.visit_block(&f.body, cx, out);
+            }
+            Statement::While(w) => {
+                self.visit_expression(&w.condition, cx, out);
+                self.visit_block(&w.body, cx, out);
+            }
+            _ => {}
+        }
+    }
+
+    fn visit_expression(&self, expr: &Expression, cx: &Context, out: &mut Vec<Diagnostic>) {
+        match expr {
+            Expression::Cast(c) => {
+                self.visit_expression(&c.expr, cx, out);
+                self.visit_type(&c.target_type, cx, out);
+            }
+            Expression::Sizeof(ty) | Expression::Alignof(ty) => self.visit_type(ty, cx, out),
+            Expression::Offsetof(o) => self.visit_type(&o.ty, cx, out),
+            Expression::ArrayLiteral(a) => {
+                for element in &a.elements {
+                    self.visit_expression(element, cx, out);
+                }
+            }
+            Expression::StructLiteral(lit) => {
+                for field in &lit.fields {
+                    self.visit_expression(&field.value, cx, out);
+                }
+                if let Some(base) = &lit.base {
+                    self.visit_expression(base, cx, out);
+                }
+            }
+            Expression::VariantLiteral(lit) => {
+                if let Some(payload) = &lit.payload {
+                    self.visit_expression(payload, cx, out);
+                }
+            }
+            Expression::InterpolatedString(parts) => {
+                for part in parts {
+                    if let InterpolatedPart::Expression(e) = part {
+                        self.visit_expression(e, cx, out);
+                    }
+                }
+            }
+            Expression::BinaryOp(b) => {
+                self.visit_expression(&b.lhs, cx, out);
+                self.visit_expression(&b.rhs, cx, out);
+            }
+            Expression::Assign(a) => {
+                self.visit_expression(&a.lhs, cx, out);
+                self.visit_expression(&a.rhs, cx, out);
+            }
+            Expression::UnaryOp(u) => self.visit_expression(&u.operand, cx, out),
+            Expression::FieldAccess(f) => self.visit_expression(&f.object, cx, out),
+            Expression::TypeAccess(t) => self.visit_expression(&t.object, cx, out),
+            Expression::Call(c) => {
+                self.visit_expression(&c.callee, cx, out);
+                for arg in &c.args {
+                    self.visit_expression(arg, cx, out);
+                }
+            }
+            Expression::Index(i) => {
+                self.visit_expression(&i.object, cx, out);
+                self.visit_expression(&i.index, cx, out);
+            }
+            Expression::Range(r) => {
+                for bound in r.start.iter().chain(r.end.iter()) {
+                    self.visit_expression(bound, cx, out);
+                }
+            }
+            Expression::Parenthesized(e) => self.visit_expression(e, cx, out),
+            _ => {}
+        }
+    }
+}
+
+/// Source spelling of a declared parameter, e.g. `const N: usize` or `T: Hash`
+fn describe(param: &GenericParameter) -> String {
+    match param {
+        GenericParameter::Const { name, ty } => format!("const {}: {}", name, format_type(ty)),
+        GenericParameter::Type { name, bounds, .. } if bounds.is_empty() => name.clone(),
+        GenericParameter::Type { name, bounds, .. } => format!(
+            "{}: {}",
+            name,
+            bounds.iter().map(format_type).collect::<Vec<_>>().join(" + ")
+        ),
+    }
+}
+
+/// Validate every generic declaration and instantiation in `items`
+pub fn check_generics(items: &ItemTable) -> Vec<Diagnostic> {
+    GenericsChecker::new(items).check()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    const PRELUDE: &str = "\
+interface Hash
+    func hash(*self) -> u64
+
+interface Eq
+    func eq(*self, other: *Self) -> bool
+
+interface Key
+    extends
+        Hash
+        Eq
+
+struct Bytes
+    len: usize
+
+func Bytes::hash(*self) -> u64
+    pass
+
+struct HashSet[T: Hash]
+    len: usize
+
+struct Array[T, const N: usize]
+    data: [T; N]
+
+";
+
+    fn messages(src: &str) -> Vec<String> {
+        let src = format!("{}{}", PRELUDE, src);
+        let sf = parse(&src);
+        let items = ItemTable::from_source_file(&sf);
+        check_generics(&items).iter().map(|d| d.message.clone()).collect()
+    }
+
+    #[test]
+    fn test_prelude_is_clean() {
+        assert!(messages("").is_empty());
+    }
+
+    #[test]
+    fn test_declaration_errors() {
+        let src = "\
+struct Pair[T, T]
+    a: T
+
+struct Wrapped[T: Bytes]
+    value: T
+
+struct Sized[const N: f32]
+    len: usize
+
+type Count = u32
+
+struct Counted[const N: Count]
+    len: usize
+
+func[T] first(x: T) -> T
+    where
+        U: Hash
+    pass
+";
+        assert_eq!(
+            messages(src),
+            vec![
+                "generic parameter `T` is declared more than once",
+                "bound `Bytes` on `T` is not an interface",
+                "const generic `N` has type `f32`, but const generics must be integers",
+                "where clause constrains `U`, which is not a generic parameter",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_default_must_satisfy_bounds() {
+        let src = "struct Cache[K: Hash = Bytes, V: Hash = u8]\n    len: usize\n";
+        assert_eq!(messages(src), vec!["default `u8` for `V` does not satisfy `Hash`"]);
+    }
+
+    #[test]
+    fn test_instantiation_arity_and_kind() {
+        let src = "\
+const WIDTH: usize = 8
+
+struct Grid
+    a: Array[u8, 4]
+    b: Array[u8, WIDTH]
+    c: Array[u8]
+    d: Array[u8, u8]
+    e: HashSet[4]
+";
+        assert_eq!(
+            messages(src),
+            vec![
+                "`Array` takes 2 generic argument(s) but 1 were supplied",
+                "`Array` expects a constant for `N`, found type `u8`",
+                "`HashSet` expects a type for `T`, found constant `4`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_instantiation_bounds() {
+        let src = "\
+struct Index
+    names: HashSet[Bytes]
+    ids: HashSet[u32]
+
+func[K: Key] keys(set: *HashSet[K]) -> usize
+    pass
+
+func[T] values(set: *HashSet[T]) -> usize
+    let copy: HashSet[T] = *set
+    pass
+";
+        assert_eq!(
+            messages(src),
+            vec![
+                "`u32` does not satisfy `Hash`, required by `T` of `HashSet`",
+                "`T` does not satisfy `Hash`, required by `T` of `HashSet`",
+                "`T` does not satisfy `Hash`, required by `T` of `HashSet`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_receiver_arguments_take_where_bounds() {
+        let src = "\
+struct List[T]
+    len: usize
+
+func List[T]::index(*self) -> HashSet[T]
+    where
+        T: Hash
+    pass
+";
+        assert!(messages(src).is_empty());
+    }
+}
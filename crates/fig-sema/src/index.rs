@@ -0,0 +1,585 @@
+//! Where each name in a document is declared
+//!
+//! The AST records where block statements are but not where items or their
+//! names are, so the index walks the items in source order alongside the
+//! token stream, finding each declared name after the one before it.
+//! Parameters and locals are found from the spans of the statements that
+//! declare them, and carry the type the resolver gives them.
+
+use fig_lexer::{IndentLexer, Token};
+use fig_parser::ast::*;
+use fig_parser::format::{format_item, format_signature, format_type};
+
+use crate::items::ItemTable;
+use crate::resolve::{Binding, BindingKind, BodyScope};
+
+/// A token and where it is
+#[derive(Debug, Clone)]
+pub struct Tok {
+    pub token: Token,
+    pub span: Span,
+    /// Whether an indented block closed just before this token. The
+    /// statement that block belongs to has a span reaching over this token.
+    pub after_dedent: bool,
+}
+
+/// Lex `text` without the indentation tokens. Also gives the span of the
+/// first character the lexer rejected, if any.
+pub fn tokenize(text: &str) -> (Vec<Tok>, Option<Span>) {
+    let mut lexer = IndentLexer::new(text);
+    let mut tokens = Vec::new();
+    let mut error = None;
+    let mut after_dedent = false;
+    while let Some(result) = lexer.next() {
+        let range = lexer.span();
+        let span = Span { start: range.start, end: range.end };
+        match result {
+            Ok(Token::Indent) => {}
+            Ok(Token::Dedent) => after_dedent = true,
+            Ok(token) => tokens.push(Tok { token, span, after_dedent: std::mem::take(&mut after_dedent) }),
+            Err(_) => {
+                error.get_or_insert(span);
+            }
+        }
+    }
+    (tokens, error)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DefKind {
+    Namespace,
+    Function,
+    Struct,
+    Union,
+    Enum,
+    Interface,
+    Alias,
+    Const,
+    /// A struct field
+    Field,
+    /// An enum or union variant
+    Variant,
+    /// A method declared by an interface
+    Method,
+    /// A parameter or a `let`, `mut` or `for` binding
+    Local,
+}
+
+/// A declared name
+#[derive(Debug, Clone)]
+pub struct Definition {
+    pub kind: DefKind,
+    pub name: String,
+    /// The namespace-qualified name, e.g. `geo::Point::x`; just the name for locals
+    pub qualified: String,
+    /// The name where it is declared
+    pub span: Span,
+    /// The whole declaration
+    pub extent: Span,
+    /// Where the name can be used unqualified: the rest of the enclosing block
+    /// for locals, and the body of a namespace block or the rest of the file
+    /// after a namespace declaration. `None` for other items.
+    pub scope: Option<Span>,
+    /// The enclosing declaration, e.g. the struct of a field or the function of a local
+    pub parent: Option<usize>,
+    /// The declaration rendered as source, without bodies
+    pub detail: String,
+    /// The `///` comment lines above the declaration, without their markers
+    pub docs: String,
+    /// For functions, the position in [`ItemTable::functions`]
+    pub function: Option<usize>,
+    /// For locals, how they are bound and their type if known
+    pub binding: Option<Binding>,
+}
+
+/// Every name `file` declares, in source order
+pub fn index(file: &SourceFile, text: &str, tokens: &[Tok]) -> Vec<Definition> {
+    let items = ItemTable::from_source_file(file);
+    let mut indexer = Indexer { text, tokens, items: &items, cursor: 0, functions: 0, definitions: Vec::new() };
+    let mut namespace = Vec::new();
+    for item in &file.items {
+        match item {
+            NamespaceItem::NamespaceDeclaration(decl) => {
+                namespace = decl.name.segments.clone();
+                indexer.namespace_declaration(decl);
+            }
+            NamespaceItem::Namespace(ns) => indexer.item(Item::Namespace(ns), &namespace, None),
+            NamespaceItem::Function(f) => indexer.item(Item::Function(&f.signature, Some(&f.body)), &namespace, None),
+            NamespaceItem::FunctionDeclaration(d) => indexer.item(Item::Function(&d.signature, None), &namespace, None),
+            NamespaceItem::TypeAlias(a) => indexer.item(Item::Alias(a, rendering(item)), &namespace, None),
+            NamespaceItem::Struct(s) => indexer.item(Item::Struct(s, rendering(item)), &namespace, None),
+            NamespaceItem::Enum(e) => indexer.item(Item::Enum(e, rendering(item)), &namespace, None),
+            NamespaceItem::Union(u) => indexer.item(Item::Union(u, rendering(item)), &namespace, None),
+            NamespaceItem::Interface(i) => indexer.item(Item::Interface(i), &namespace, None),
+            NamespaceItem::Const(c) => indexer.item(Item::Const(c, rendering(item)), &namespace, None),
+            NamespaceItem::Using(_) => {}
+        }
+    }
+    indexer.definitions
+}
+
+/// The doc comment of the declaration starting at `offset`: the `///` lines
+/// directly above its line, past any annotation lines, joined with line breaks.
+/// The first annotation line may start with the visibility, e.g. `export #inline`.
+pub fn doc_comment(text: &str, offset: usize) -> String {
+    let line_start = text[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let mut lines = Vec::new();
+    for line in text[..line_start].lines().rev() {
+        let line = line.trim_start();
+        let annotation = ["export", "public", "private"]
+            .iter()
+            .find_map(|keyword| line.strip_prefix(keyword))
+            .unwrap_or(line)
+            .trim_start()
+            .starts_with('#');
+        if let Some(doc) = line.strip_prefix("///") {
+            lines.push(doc.strip_prefix(' ').unwrap_or(doc).trim_end());
+        } else if !annotation || !lines.is_empty() {
+            break;
+        }
+    }
+    lines.reverse();
+    lines.join("\n")
+}
+
+/// An item, whether it appears at the top level or in a namespace block,
+/// with its declaration rendered as source
+enum Item<'a> {
+    Namespace(&'a Namespace),
+    Function(&'a FunctionSignature, Option<&'a Block>),
+    Alias(&'a TypeAlias, String),
+    Struct(&'a Struct, String),
+    Enum(&'a Enum, String),
+    Union(&'a Union, String),
+    Interface(&'a Interface),
+    Const(&'a ConstStatement, String),
+}
+
+fn rendering(item: &NamespaceItem) -> String {
+    format_item(item).unwrap_or_default().trim_end().to_string()
+}
+
+/// The rendering of an item in a namespace block, which is a statement
+fn render(statement: &Statement) -> String {
+    let item = match statement.clone() {
+        Statement::TypeAlias(a) => NamespaceItem::TypeAlias(a),
+        Statement::Struct(s) => NamespaceItem::Struct(s),
+        Statement::Enum(e) => NamespaceItem::Enum(e),
+        Statement::Union(u) => NamespaceItem::Union(u),
+        Statement::Const(c) => NamespaceItem::Const(c),
+        _ => return String::new(),
+    };
+    rendering(&item)
+}
+
+struct Indexer<'t, 'a> {
+    text: &'t str,
+    tokens: &'t [Tok],
+    items: &'t ItemTable<'a>,
+    /// The token after the last name found
+    cursor: usize,
+    /// How many functions have been visited, in [`ItemTable::functions`] order
+    functions: usize,
+    definitions: Vec<Definition>,
+}
+
+impl<'t, 'a> Indexer<'t, 'a> {
+    fn namespace_declaration(&mut self, decl: &NamespaceDeclaration) {
+        let Some(last) = decl.name.segments.last() else { return };
+        let Some((keyword, name)) = self.find_declaration(|t| *t == Token::Namespace, last) else { return };
+        let extent = Span { start: self.tokens[keyword].span.start, end: self.tokens[name].span.end };
+        let scope = Span { start: extent.end, end: self.text.len() };
+        let detail = format!("namespace {}", decl.name.segments.join("::"));
+        let def = self.define(DefKind::Namespace, last, decl.name.segments.join("::"), name, extent, None, detail);
+        self.definitions[def].scope = Some(scope);
+    }
+
+    fn item(&mut self, item: Item<'a>, namespace: &[String], parent: Option<usize>) {
+        match item {
+            Item::Namespace(ns) => self.namespace(ns, namespace, parent),
+            Item::Function(signature, body) => self.function(signature, body, namespace, parent),
+            Item::Alias(a, detail) => {
+                self.declare(DefKind::Alias, Token::Type, &a.name, namespace, parent, detail);
+            }
+            Item::Struct(s, detail) => {
+                let Some(def) = self.declare(DefKind::Struct, Token::Struct, &s.name, namespace, parent, detail)
+                else {
+                    return;
+                };
+                for field in &s.fields {
+                    let detail = format!("{}: {}", field.name, format_type(&field.ty));
+                    self.member(DefKind::Field, &field.name, true, def, detail);
+                }
+            }
+            Item::Union(u, detail) => {
+                let Some(def) = self.declare(DefKind::Union, Token::Union, &u.name, namespace, parent, detail)
+                else {
+                    return;
+                };
+                for variant in &u.variants {
+                    let detail = format!("{}: {}", variant.name, format_type(&variant.ty));
+                    self.member(DefKind::Variant, &variant.name, true, def, detail);
+                }
+            }
+            Item::Enum(e, detail) => {
+                let Some(def) = self.declare(DefKind::Enum, Token::Enum, &e.name, namespace, parent, detail)
+                else {
+                    return;
+                };
+                for variant in &e.variants {
+                    self.member(DefKind::Variant, &variant.name, false, def, variant.name.clone());
+                }
+            }
+            Item::Interface(i) => {
+                let detail = format!("interface {}", i.name);
+                let Some(def) = self.declare(DefKind::Interface, Token::Interface, &i.name, namespace, parent, detail)
+                else {
+                    return;
+                };
+                for method in &i.methods {
+                    let Some((keyword, name)) = self.find_declaration(is_func, &method.name) else { continue };
+                    let extent = self.line_extent(keyword, name);
+                    let qualified = format!("{}::{}", self.definitions[def].qualified, method.name);
+                    let detail = format_signature(method);
+                    self.define(DefKind::Method, &method.name, qualified, name, extent, Some(def), detail);
+                    self.extend(def, extent.end);
+                }
+            }
+            Item::Const(c, detail) => {
+                let mut path = namespace.to_vec();
+                path.extend(c.receiver.iter().map(|segment| segment.name.clone()));
+                let Some((keyword, name)) = self.find_declaration(|t| *t == Token::Const, &c.name) else { return };
+                let extent = self.line_extent(keyword, name);
+                path.push(c.name.clone());
+                self.define(DefKind::Const, &c.name, path.join("::"), name, extent, parent, detail);
+            }
+        }
+    }
+
+    fn namespace(&mut self, ns: &'a Namespace, namespace: &[String], parent: Option<usize>) {
+        let Some(last) = ns.name.segments.last() else { return };
+        let Some((keyword, name)) = self.find_declaration(|t| *t == Token::Namespace, last) else { return };
+        let mut inner = namespace.to_vec();
+        inner.extend(ns.name.segments.iter().cloned());
+        let extent = self.line_extent(keyword, name);
+        let detail = format!("namespace {}", ns.name.segments.join("::"));
+        let def = self.define(DefKind::Namespace, last, inner.join("::"), name, extent, parent, detail);
+        for statement in &ns.items {
+            let item = match statement {
+                Statement::Namespace(n) => Item::Namespace(n),
+                Statement::Function(f) => Item::Function(&f.signature, Some(&f.body)),
+                Statement::FunctionDeclaration(d) => Item::Function(&d.signature, None),
+                Statement::Const(c) => Item::Const(c, render(statement)),
+                Statement::Interface(i) => Item::Interface(i),
+                Statement::TypeAlias(a) => Item::Alias(a, render(statement)),
+                Statement::Struct(s) => Item::Struct(s, render(statement)),
+                Statement::Enum(e) => Item::Enum(e, render(statement)),
+                Statement::Union(u) => Item::Union(u, render(statement)),
+                _ => continue,
+            };
+            self.item(item, &inner, Some(def));
+        }
+        let end = self.definitions[def + 1..].iter().map(|d| d.extent.end).max().unwrap_or(extent.end);
+        self.extend(def, end);
+        self.definitions[def].scope = Some(self.definitions[def].extent);
+    }
+
+    /// Find and define an item declared as `keyword ... name`
+    fn declare(
+        &mut self,
+        kind: DefKind,
+        keyword: Token,
+        name: &str,
+        namespace: &[String],
+        parent: Option<usize>,
+        detail: String,
+    ) -> Option<usize> {
+        let (keyword, index) = self.find_declaration(|t| *t == keyword, name)?;
+        let extent = self.line_extent(keyword, index);
+        let mut qualified = namespace.to_vec();
+        qualified.push(name.to_string());
+        Some(self.define(kind, name, qualified.join("::"), index, extent, parent, detail))
+    }
+
+    /// A field or variant of the type `def`: its name, followed by a colon
+    /// when `typed`
+    fn member(&mut self, kind: DefKind, name: &str, typed: bool, def: usize, detail: String) {
+        let found = (self.cursor..self.tokens.len()).find(|&i| {
+            matches!(&self.tokens[i].token, Token::Ident(n) if n == name)
+                && (!typed || matches!(self.tokens.get(i + 1), Some(t) if t.token == Token::Colon))
+        });
+        let Some(index) = found else { return };
+        self.cursor = index + 1;
+        let extent = self.line_extent(index, index);
+        let qualified = format!("{}::{}", self.definitions[def].qualified, name);
+        self.define(kind, name, qualified, index, extent, Some(def), detail);
+        self.extend(def, extent.end);
+    }
+
+    fn function(
+        &mut self,
+        signature: &'a FunctionSignature,
+        body: Option<&'a Block>,
+        namespace: &[String],
+        parent: Option<usize>,
+    ) {
+        let function = self.functions;
+        self.functions += 1;
+        let Some((keyword, name)) = self.find_declaration(is_func, &signature.name) else { return };
+        let mut extent = self.line_extent(keyword, name);
+        if let Some(end) = body.and_then(|b| self.block_end(b)) {
+            extent.end = extent.end.max(end);
+        }
+        let mut qualified = namespace.to_vec();
+        if let Some(receiver) = &signature.receiver {
+            qualified.extend(receiver.segments.iter().cloned());
+        }
+        qualified.push(signature.name.clone());
+        let detail = format_signature(signature);
+        let def = self.define(DefKind::Function, &signature.name, qualified.join("::"), name, extent, parent, detail);
+        self.definitions[def].function = Some(function);
+
+        for param in &signature.params {
+            let binding = Binding { kind: BindingKind::Param, ty: Some(param.ty.clone()) };
+            let Some(index) = self.next_binding(&param.name, true) else { continue };
+            self.cursor = index + 1;
+            self.local(&param.name, index, extent, def, binding);
+        }
+        let Some(body) = body else { return };
+        let items = self.items;
+        let Some(function_def) = items.functions().get(function) else { return };
+        let mut scope = BodyScope::new(items, function_def);
+        self.block(body, &mut scope, def, extent.end);
+        // Carry on from the last statement, not the end of the body, whose
+        // span can reach over the next item's first token
+        if let Some(last) = body.spans.last() {
+            self.cursor = self.cursor.max(self.token_at(last.start));
+        }
+    }
+
+    fn block(&mut self, block: &'a Block, scope: &mut BodyScope<'t, 'a>, def: usize, end: usize) {
+        for (i, statement) in block.statements.iter().enumerate() {
+            let Some(span) = block.span(i) else { continue };
+            let statement_end = self.statement_end(span);
+            match statement {
+                Statement::Let(LetStatement { name, .. }) | Statement::Mut(MutStatement { name, .. }) => {
+                    scope.bind_statement(statement);
+                    let Some(index) = self.binding_in(name, span) else { continue };
+                    let binding = scope.lookup(name).cloned().unwrap_or(Binding { kind: BindingKind::Let, ty: None });
+                    self.local(name, index, Span { start: statement_end, end }, def, binding);
+                }
+                Statement::For(f) => {
+                    scope.push();
+                    let binding = Binding { kind: BindingKind::Loop, ty: None };
+                    scope.bind(&f.pattern, binding.clone());
+                    if let Some(index) = self.binding_in(&f.pattern, span) {
+                        self.local(&f.pattern, index, Span { start: span.start, end: statement_end }, def, binding);
+                    }
+                    self.nested(&f.body, scope, def);
+                    scope.pop();
+                }
+                Statement::If(s) => {
+                    self.nested(&s.then_body, scope, def);
+                    for clause in &s.elif_clauses {
+                        self.nested(&clause.body, scope, def);
+                    }
+                    if let Some(body) = &s.else_body {
+                        self.nested(body, scope, def);
+                    }
+                }
+                Statement::While(w) => self.nested(&w.body, scope, def),
+                Statement::Block(b) => self.nested(&b.body, scope, def),
+                _ => {}
+            }
+        }
+    }
+
+    fn nested(&mut self, block: &'a Block, scope: &mut BodyScope<'t, 'a>, def: usize) {
+        let end = self.block_end(block).unwrap_or(0);
+        scope.push();
+        self.block(block, scope, def, end);
+        scope.pop();
+    }
+
+    fn local(&mut self, name: &str, index: usize, scope: Span, def: usize, binding: Binding) {
+        let detail = match (&binding.kind, &binding.ty) {
+            (BindingKind::Param, Some(ty)) => format!("{}: {}", name, format_type(ty)),
+            (kind, ty) => {
+                let keyword = match kind {
+                    BindingKind::Mut => "mut",
+                    BindingKind::Loop => "for",
+                    _ => "let",
+                };
+                match ty {
+                    Some(ty) => format!("{} {}: {}", keyword, name, format_type(ty)),
+                    None => format!("{} {}", keyword, name),
+                }
+            }
+        };
+        let extent = self.tokens[index].span;
+        let local = self.define(DefKind::Local, name, name.to_string(), index, extent, Some(def), detail);
+        self.definitions[local].scope = Some(scope);
+        self.definitions[local].binding = Some(binding);
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn define(
+        &mut self,
+        kind: DefKind,
+        name: &str,
+        qualified: String,
+        index: usize,
+        extent: Span,
+        parent: Option<usize>,
+        detail: String,
+    ) -> usize {
+        self.definitions.push(Definition {
+            kind,
+            name: name.to_string(),
+            qualified,
+            span: self.tokens[index].span,
+            extent,
+            scope: None,
+            parent,
+            detail,
+            docs: if kind == DefKind::Local { String::new() } else { doc_comment(self.text, extent.start) },
+            function: None,
+            binding: None,
+        });
+        self.definitions.len() - 1
+    }
+
+    /// Widen the extent of `def`, and of the items enclosing it, to `end`
+    fn extend(&mut self, def: usize, end: usize) {
+        let mut next = Some(def);
+        while let Some(def) = next {
+            let extent = &mut self.definitions[def].extent;
+            extent.end = extent.end.max(end);
+            next = self.definitions[def].parent;
+        }
+    }
+
+    /// Find `keyword` followed on the same line by the identifier `name`,
+    /// not counting identifiers that start a longer path such as the
+    /// receiver of a method. Returns the keyword and name token indices.
+    fn find_declaration(&mut self, keyword: impl Fn(&Token) -> bool, name: &str) -> Option<(usize, usize)> {
+        for start in self.cursor..self.tokens.len() {
+            if !keyword(&self.tokens[start].token) {
+                continue;
+            }
+            for i in start + 1..self.tokens.len() {
+                match &self.tokens[i].token {
+                    Token::Newline => break,
+                    Token::Ident(n) if n == name && !self.continues_path(i) => {
+                        self.cursor = i + 1;
+                        return Some((start, i));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        None
+    }
+
+    /// Whether the identifier at `index` is followed by `::`, possibly after generic arguments
+    fn continues_path(&self, index: usize) -> bool {
+        let mut next = index + 1;
+        if matches!(self.tokens.get(next), Some(t) if t.token == Token::LBracket) {
+            match matching_close(self.tokens, next) {
+                Some(close) => next = close + 1,
+                None => return false,
+            }
+        }
+        matches!(self.tokens.get(next), Some(t) if t.token == Token::ColonColon)
+    }
+
+    /// The next identifier `name` at or after the cursor, followed by a colon when `typed`
+    fn next_binding(&self, name: &str, typed: bool) -> Option<usize> {
+        (self.cursor..self.tokens.len()).find(|&i| {
+            matches!(&self.tokens[i].token, Token::Ident(n) if n == name)
+                && (!typed || matches!(self.tokens.get(i + 1), Some(t) if t.token == Token::Colon))
+        })
+    }
+
+    /// The first identifier `name` in the statement at `span`
+    fn binding_in(&self, name: &str, span: Span) -> Option<usize> {
+        let start = self.token_at(span.start);
+        (start..self.tokens.len())
+            .take_while(|&i| self.tokens[i].span.start < span.end)
+            .find(|&i| matches!(&self.tokens[i].token, Token::Ident(n) if n == name))
+    }
+
+    /// From `keyword` to the end of the line that `name` is on
+    fn line_extent(&self, keyword: usize, name: usize) -> Span {
+        let end = (name..self.tokens.len())
+            .take_while(|&i| self.tokens[i].token != Token::Newline)
+            .last()
+            .map_or(self.tokens[name].span.end, |i| self.tokens[i].span.end);
+        Span { start: self.tokens[keyword].span.start, end }
+    }
+
+    /// The index of the first token starting at or after `offset`
+    fn token_at(&self, offset: usize) -> usize {
+        self.tokens.partition_point(|t| t.span.start < offset)
+    }
+
+    /// Where the statement at `span` really ends: the parser's span for a
+    /// statement with a block takes in the token that closed it, and every
+    /// span takes in its line break
+    fn statement_end(&self, span: Span) -> usize {
+        let mut end = self.tokens.partition_point(|t| t.span.end <= span.end);
+        while end > 0 {
+            let tok = &self.tokens[end - 1];
+            if tok.span.start > span.start && (tok.after_dedent || tok.token == Token::Newline) {
+                end -= 1;
+            } else {
+                break;
+            }
+        }
+        if end == 0 { span.end } else { self.tokens[end - 1].span.end.max(span.start) }
+    }
+
+    fn block_end(&self, block: &Block) -> Option<usize> {
+        block.spans.last().map(|&span| self.statement_end(span))
+    }
+}
+
+fn is_func(token: &Token) -> bool {
+    matches!(token, Token::Func | Token::Fn)
+}
+
+/// The index of the bracket closing the one at `open`
+pub fn matching_close(tokens: &[Tok], open: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    for (i, tok) in tokens.iter().enumerate().skip(open) {
+        match tok.token {
+            Token::LBracket | Token::LParen => depth += 1,
+            Token::RBracket | Token::RParen => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The index of the bracket opening the one at `close`
+pub fn matching_open(tokens: &[Tok], close: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    for i in (0..=close).rev() {
+        match tokens[i].token {
+            Token::RBracket | Token::RParen => depth += 1,
+            Token::LBracket | Token::LParen => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
@@ -0,0 +1,295 @@
+//! Item table: every named declaration in a source file, keyed by its
+//! namespace-qualified name
+//!
+//! Semantic passes look up types, constants and functions through this table
+//! instead of re-walking the AST. Qualified names join namespace segments with
+//! `::`, e.g. `core::iter::Iterator`. A bare `namespace a::b` declaration
+//! applies to every top-level item that follows it in the file.
+
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+
+use fig_parser::ast::*;
+
+/// A named type-level declaration
+#[derive(Debug, Clone, Copy)]
+pub enum TypeDef<'a> {
+    Struct(&'a Struct),
+    Union(&'a Union),
+    Enum(&'a Enum),
+    Alias(&'a TypeAlias),
+    Interface(&'a Interface),
+}
+
+impl<'a> TypeDef<'a> {
+    pub fn name(&self) -> &'a str {
+        match self {
+            TypeDef::Struct(s) => &s.name,
+            TypeDef::Union(u) => &u.name,
+            TypeDef::Enum(e) => &e.name,
+            TypeDef::Alias(a) => &a.name,
+            TypeDef::Interface(i) => &i.name,
+        }
+    }
+
+    pub fn generic_params(&self) -> &'a [GenericParameter] {
+        match self {
+            TypeDef::Struct(s) => &s.generic_params,
+            TypeDef::Union(u) => &u.generic_params,
+            TypeDef::Enum(e) => &e.generic_params,
+            TypeDef::Alias(a) => &a.generic_params,
+            TypeDef::Interface(i) => &i.generic_params,
+        }
+    }
+
+    /// Where-clause constraints that named no declared parameter
+    pub fn unbound_constraints(&self) -> &'a [GenericParameter] {
+        match self {
+            TypeDef::Struct(s) => &s.unbound_constraints,
+            TypeDef::Union(u) => &u.unbound_constraints,
+            TypeDef::Enum(e) => &e.unbound_constraints,
+            TypeDef::Alias(a) => &a.unbound_constraints,
+            TypeDef::Interface(i) => &i.unbound_constraints,
+        }
+    }
+}
+
+/// A function definition or declaration together with its enclosing namespace
+#[derive(Debug, Clone)]
+pub struct FunctionDef<'a> {
+    /// Enclosing namespace segments, e.g. `["core", "iter"]`
+    pub namespace: Vec<String>,
+    pub signature: &'a FunctionSignature,
+    /// `None` for forward and `extern` declarations
+    pub body: Option<&'a Block>,
+}
+
+impl FunctionDef<'_> {
+    /// Fully-qualified name including namespace and receiver, e.g. `core::Vec::push`
+    pub fn qualified_name(&self) -> String {
+        let mut segments = self.namespace.clone();
+        if let Some(receiver) = &self.signature.receiver {
+            segments.extend(receiver.segments.iter().cloned());
+        }
+        segments.push(self.signature.name.clone());
+        segments.join("::")
+    }
+
+    /// The receiver's last segment, e.g. `Vec` for `func Vec::push`
+    pub fn receiver_name(&self) -> Option<&str> {
+        self.signature
+            .receiver
+            .as_ref()
+            .and_then(|r| r.segments.last())
+            .map(String::as_str)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ItemTable<'a> {
+    types: HashMap<String, TypeDef<'a>>,
+    consts: HashMap<String, &'a ConstStatement>,
+    functions: Vec<FunctionDef<'a>>,
+    /// Qualified names in declaration order, for deterministic iteration
+    type_order: Vec<String>,
+    const_order: Vec<String>,
+    /// Names declared more than once; the first declaration wins
+    duplicates: Vec<String>,
+}
+
+impl<'a> ItemTable<'a> {
+    pub fn from_source_file(sf: &'a SourceFile) -> Self {
+        let mut table = ItemTable::default();
+        let mut namespace: Vec<String> = Vec::new();
+        for item in &sf.items {
+            match item {
+                NamespaceItem::NamespaceDeclaration(decl) => {
+                    namespace = decl.name.segments.clone();
+                }
+                NamespaceItem::Namespace(ns) => table.collect_namespace(ns, &namespace),
+                NamespaceItem::Function(f) => table.add_function(&namespace, &f.signature, Some(&f.body)),
+                NamespaceItem::FunctionDeclaration(d) => table.add_function(&namespace, &d.signature, None),
+                NamespaceItem::TypeAlias(a) => table.add_type(&namespace, TypeDef::Alias(a)),
+                NamespaceItem::Struct(s) => table.add_type(&namespace, TypeDef::Struct(s)),
+                NamespaceItem::Enum(e) => table.add_type(&namespace, TypeDef::Enum(e)),
+                NamespaceItem::Union(u) => table.add_type(&namespace, TypeDef::Union(u)),
+                NamespaceItem::Interface(i) => table.add_type(&namespace, TypeDef::Interface(i)),
+                NamespaceItem::Const(c) => table.add_const(&namespace, c),
+                NamespaceItem::Using(_) => {}
+            }
+        }
+        table
+    }
+
+    fn collect_namespace(&mut self, ns: &'a Namespace, outer: &[String]) {
+        let mut namespace = outer.to_vec();
+        namespace.extend(ns.name.segments.iter().cloned());
+        for stmt in &ns.items {
+            match stmt {
+                Statement::Namespace(inner) => self.collect_namespace(inner, &namespace),
+                Statement::Function(f) => self.add_function(&namespace, &f.signature, Some(&f.body)),
+                Statement::FunctionDeclaration(d) => self.add_function(&namespace, &d.signature, None),
+                Statement::TypeAlias(a) => self.add_type(&namespace, TypeDef::Alias(a)),
+                Statement::Struct(s) => self.add_type(&namespace, TypeDef::Struct(s)),
+                Statement::Enum(e) => self.add_type(&namespace, TypeDef::Enum(e)),
+                Statement::Union(u) => self.add_type(&namespace, TypeDef::Union(u)),
+                Statement::Interface(i) => self.add_type(&namespace, TypeDef::Interface(i)),
+                Statement::Const(c) => self.add_const(&namespace, c),
+                _ => {}
+            }
+        }
+    }
+
+    fn qualify(namespace: &[String], name: &str) -> String {
+        if namespace.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}::{}", namespace.join("::"), name)
+        }
+    }
+
+    fn add_type(&mut self, namespace: &[String], def: TypeDef<'a>) {
+        let key = Self::qualify(namespace, def.name());
+        if self.types.contains_key(&key) {
+            self.duplicates.push(key);
+        } else {
+            self.type_order.push(key.clone());
+            self.types.insert(key, def);
+        }
+    }
+
+    fn add_const(&mut self, namespace: &[String], c: &'a ConstStatement) {
+        let mut segments = namespace.to_vec();
+        segments.extend(c.receiver.iter().map(|seg| seg.name.clone()));
+        let key = Self::qualify(&segments, &c.name);
+        match self.consts.entry(key) {
+            Entry::Occupied(entry) => self.duplicates.push(entry.key().clone()),
+            Entry::Vacant(entry) => {
+                self.const_order.push(entry.key().clone());
+                entry.insert(c);
+            }
+        }
+    }
+
+    fn add_function(&mut self, namespace: &[String], signature: &'a FunctionSignature, body: Option<&'a Block>) {
+        self.functions.push(FunctionDef { namespace: namespace.to_vec(), signature, body });
+    }
+
+    /// Resolve a name against a keyed map: exact qualified match first, then a
+    /// unique match on the final segment.
+    fn resolve<'m, V>(map: &'m HashMap<String, V>, segments: &[String]) -> Option<&'m V> {
+        Self::resolve_entry(map, segments).map(|(_, v)| v)
+    }
+
+    fn resolve_entry<'m, V>(map: &'m HashMap<String, V>, segments: &[String]) -> Option<(&'m str, &'m V)> {
+        let key = segments.join("::");
+        if let Some((k, v)) = map.get_key_value(&key) {
+            return Some((k, v));
+        }
+        let suffix = format!("::{}", key);
+        let mut matches = map.iter().filter(|(k, _)| k.ends_with(&suffix));
+        match (matches.next(), matches.next()) {
+            (Some((k, v)), None) => Some((k, v)),
+            _ => None,
+        }
+    }
+
+    /// Look up a type by path. Generic arguments on the path are ignored.
+    pub fn lookup_type(&self, path: &Path) -> Option<TypeDef<'a>> {
+        Self::resolve(&self.types, &path.segments).copied()
+    }
+
+    /// Like [`ItemTable::lookup_type`], also returning the type's qualified name
+    pub fn lookup_type_entry(&self, path: &Path) -> Option<(&str, TypeDef<'a>)> {
+        Self::resolve_entry(&self.types, &path.segments).map(|(k, v)| (k, *v))
+    }
+
+    /// Look up a type by its (possibly unqualified) name
+    pub fn lookup_type_name(&self, name: &str) -> Option<TypeDef<'a>> {
+        Self::resolve(&self.types, &[name.to_string()]).copied()
+    }
+
+    /// Look up a constant by path, e.g. `MAX` or `Seq::CAPACITY`
+    pub fn lookup_const(&self, path: &Path) -> Option<&'a ConstStatement> {
+        Self::resolve(&self.consts, &path.segments).copied()
+    }
+
+    /// All type declarations in declaration order, with their qualified names
+    pub fn types(&self) -> impl Iterator<Item = (&str, TypeDef<'a>)> + '_ {
+        self.type_order.iter().map(|k| (k.as_str(), self.types[k]))
+    }
+
+    /// All constants in declaration order, with their qualified names
+    pub fn consts(&self) -> impl Iterator<Item = (&str, &'a ConstStatement)> + '_ {
+        self.const_order.iter().map(|k| (k.as_str(), self.consts[k]))
+    }
+
+    /// All functions in declaration order
+    pub fn functions(&self) -> &[FunctionDef<'a>] {
+        &self.functions
+    }
+
+    /// Functions whose receiver's last segment is `receiver`, e.g. all `Vec::…` methods
+    pub fn methods_of<'s>(&'s self, receiver: &'s str) -> impl Iterator<Item = &'s FunctionDef<'a>> + 's {
+        self.functions.iter().filter(move |f| f.receiver_name() == Some(receiver))
+    }
+
+    /// Look up a function by qualified name, e.g. `Vec::new`, falling back to a
+    /// unique match on the trailing segments
+    pub fn lookup_function(&self, path: &Path) -> Option<&FunctionDef<'a>> {
+        let qualified = path.segments.join("::");
+        self.functions
+            .iter()
+            .find(|f| f.qualified_name() == qualified)
+            .or_else(|| {
+                let mut matches = self
+                    .functions
+                    .iter()
+                    .filter(|f| f.qualified_name().ends_with(&format!("::{}", qualified)));
+                match (matches.next(), matches.next()) {
+                    (Some(f), None) => Some(f),
+                    _ => None,
+                }
+            })
+    }
+
+    /// Qualified names that were declared more than once
+    pub fn duplicates(&self) -> &[String] {
+        &self.duplicates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    #[test]
+    fn test_collects_namespaced_items() {
+        let sf = parse(
+            "struct Point\n    x: i32\n\nnamespace geo\n    struct Line\n        a: Point\n\n    func Line::len(*self) -> u32\n        pass\n",
+        );
+        let items = ItemTable::from_source_file(&sf);
+        assert!(matches!(items.lookup_type(&Path::simple("Point".into())), Some(TypeDef::Struct(_))));
+        assert!(items.lookup_type_name("geo::Line").is_some());
+        // Unqualified lookup falls back to a unique suffix match
+        assert!(items.lookup_type_name("Line").is_some());
+        assert_eq!(items.functions()[0].qualified_name(), "geo::Line::len");
+        assert_eq!(items.methods_of("Line").count(), 1);
+    }
+
+    #[test]
+    fn test_namespace_declaration_applies_to_following_items() {
+        let sf = parse("namespace core::iter\n\nstruct Range\n    start: usize\n");
+        let items = ItemTable::from_source_file(&sf);
+        let names: Vec<_> = items.types().map(|(name, _)| name.to_string()).collect();
+        assert_eq!(names, vec!["core::iter::Range"]);
+    }
+
+    #[test]
+    fn test_records_duplicates() {
+        let sf = parse("struct A\n    x: i32\n\nstruct A\n    y: i32\n");
+        let items = ItemTable::from_source_file(&sf);
+        assert_eq!(items.duplicates(), &["A".to_string()]);
+    }
+}
@@ -0,0 +1,948 @@
+//! Type layout: size, alignment and field offsets
+//!
+//! Layouts are computed for a [`Target`], which fixes the pointer width and
+//! byte order. The rules are:
+//!
+//! - Primitives are naturally aligned; `usize`, `isize` and pointers take the
+//!   target's pointer width. `ok` and `null` are zero-sized.
+//! - Structs are laid out like C: fields in declaration order, each at the next
+//!   offset aligned to the field, with trailing padding up to the struct's
+//!   alignment. `packed` structs have no padding and alignment 1. An
+//!   `#align(N)` annotation raises the alignment to at least `N`.
+//! - Slices `[T]` are a `(ptr, len)` pair; fixed arrays `[T; N]` repeat `T`.
+//! - Function values are a `(code, env)` pair: the function's address and
+//!   the values a lambda captured, or null when it captured none.
+//! - Unions and `T ! E` are tagged: a discriminant of the smallest unsigned
+//!   width that fits the variant count, followed by the largest variant.
+//! - Enums use their `enum[repr]` type, or the smallest integer that holds
+//!   every discriminant.
+//! - `?T` is stored in `T`'s own bits when `T` is a non-null pointer (the
+//!   all-zero pattern means `null`); otherwise it is tagged like a union.
+
+use std::collections::HashMap;
+use std::fmt::{self, Write};
+
+use fig_lexer::IntegerLiteral;
+use fig_parser::ast::*;
+use fig_parser::format::{format_expression, format_path, format_type};
+use serde::Serialize;
+
+use crate::items::{ItemTable, TypeDef};
+
+// ============================================================================
+// Target
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Endianness {
+    Little,
+    Big,
+}
+
+/// The properties of a compilation target that affect layout
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Target {
+    /// Size and alignment of pointers, `usize` and `isize`, in bytes
+    pub pointer_width: u64,
+    pub endianness: Endianness,
+}
+
+impl Target {
+    pub const X86_64: Target = Target { pointer_width: 8, endianness: Endianness::Little };
+    pub const AARCH64: Target = Target { pointer_width: 8, endianness: Endianness::Little };
+    pub const WASM32: Target = Target { pointer_width: 4, endianness: Endianness::Little };
+
+    /// The target the compiler itself is running on
+    pub fn host() -> Self {
+        Target {
+            pointer_width: std::mem::size_of::<usize>() as u64,
+            endianness: if cfg!(target_endian = "big") { Endianness::Big } else { Endianness::Little },
+        }
+    }
+
+    /// Bit width and signedness of an integer primitive, or `None` for other types
+    pub fn integer_info(&self, ty: &Type) -> Option<(u32, bool)> {
+        let pointer_bits = (self.pointer_width * 8) as u32;
+        match ty {
+            Type::U8 => Some((8, false)),
+            Type::U16 => Some((16, false)),
+            Type::U32 => Some((32, false)),
+            Type::U64 => Some((64, false)),
+            Type::USize => Some((pointer_bits, false)),
+            Type::I8 => Some((8, true)),
+            Type::I16 => Some((16, true)),
+            Type::I32 => Some((32, true)),
+            Type::I64 => Some((64, true)),
+            Type::ISize => Some((pointer_bits, true)),
+            _ => None,
+        }
+    }
+}
+
+/// Smallest and largest value of an integer with the given width and signedness
+pub fn integer_bounds(bits: u32, signed: bool) -> (i128, i128) {
+    if signed {
+        (-(1i128 << (bits - 1)), (1i128 << (bits - 1)) - 1)
+    } else {
+        (0, (1i128 << bits) - 1)
+    }
+}
+
+/// Wrap `value` into the range of an integer with the given width and signedness
+pub fn wrap_integer(value: i128, bits: u32, signed: bool) -> i128 {
+    let modulus = 1i128 << bits;
+    let wrapped = value.rem_euclid(modulus);
+    if signed && wrapped >= modulus / 2 { wrapped - modulus } else { wrapped }
+}
+
+// ============================================================================
+// Layouts
+// ============================================================================
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Layout {
+    pub size: u64,
+    pub align: u64,
+    pub shape: Shape,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum Shape {
+    /// A primitive or pointer. `non_null` marks pointers whose all-zero bit
+    /// pattern is invalid and can therefore encode `null` for `?T`.
+    Scalar { non_null: bool },
+    /// `[T; N]`
+    Array { element: Box<Layout>, count: u64 },
+    /// A struct, or the `(ptr, len)` pair of a slice
+    Struct { packed: bool, fields: Vec<FieldLayout> },
+    /// A C-like enum with its discriminant values
+    Enum { signed: bool, discriminants: Vec<(String, i128)> },
+    /// A discriminant at offset 0 followed by the payload of the active variant
+    Tagged { tag_size: u64, variants: Vec<FieldLayout> },
+    /// `?T` stored in the bits of a non-null `T`
+    Niche { inner: Box<Layout> },
+}
+
+/// One field of a struct, or one variant of a tagged layout
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldLayout {
+    pub name: String,
+    /// The field's type as written in source
+    pub ty: String,
+    pub offset: u64,
+    pub layout: Layout,
+}
+
+impl Layout {
+    fn scalar(size: u64, non_null: bool) -> Self {
+        Layout { size, align: size.max(1), shape: Shape::Scalar { non_null } }
+    }
+
+    /// Whether `?T` over this layout can use the all-zero bit pattern for `null`
+    pub fn has_null_niche(&self) -> bool {
+        matches!(self.shape, Shape::Scalar { non_null: true })
+    }
+
+    /// Offset of a named struct field
+    pub fn field(&self, name: &str) -> Option<&FieldLayout> {
+        match &self.shape {
+            Shape::Struct { fields, .. } | Shape::Tagged { variants: fields, .. } => {
+                fields.iter().find(|f| f.name == name)
+            }
+            _ => None,
+        }
+    }
+}
+
+fn align_to(offset: u64, align: u64) -> u64 {
+    offset.div_ceil(align) * align
+}
+
+// ============================================================================
+// Errors
+// ============================================================================
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LayoutError {
+    /// A path that does not name a struct, union, enum or alias
+    UnknownType(String),
+    /// An interface used where a concrete type is required
+    NotAConcreteType(String),
+    /// A type that contains itself by value and so has infinite size
+    RecursiveType(String),
+    /// `offsetof` on a type without named fields
+    NotAStruct(String),
+    UnknownField { ty: String, field: String },
+    /// An array size, discriminant or `#align` argument that is not a constant integer
+    NotConstant(String),
+    /// A generic parameter with neither an argument nor a default
+    UnboundGeneric(String),
+    /// An enum representation that is not an integer type
+    InvalidRepresentation { name: String, repr: String },
+    /// A discriminant that does not fit the enum's representation
+    DiscriminantOutOfRange { name: String, variant: String, value: i128 },
+    /// `#align(N)` where `N` is not a power of two
+    InvalidAlignment { name: String, align: i128 },
+    /// A size that does not fit in 64 bits
+    SizeOverflow(String),
+}
+
+impl fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LayoutError::UnknownType(t) => write!(f, "unknown type `{}`", t),
+            LayoutError::NotAConcreteType(t) => write!(f, "`{}` is an interface and has no layout", t),
+            LayoutError::RecursiveType(t) => {
+                write!(f, "type `{}` contains itself by value and has infinite size; use a pointer", t)
+            }
+            LayoutError::NotAStruct(t) => write!(f, "`{}` has no named fields", t),
+            LayoutError::UnknownField { ty, field } => write!(f, "type `{}` has no field `{}`", ty, field),
+            LayoutError::NotConstant(e) => write!(f, "`{}` is not a constant integer expression", e),
+            LayoutError::UnboundGeneric(p) => {
+                write!(f, "generic parameter `{}` must be instantiated to compute a layout", p)
+            }
+            LayoutError::InvalidRepresentation { name, repr } => {
+                write!(f, "enum `{}` has representation `{}`, which is not an integer type", name, repr)
+            }
+            LayoutError::DiscriminantOutOfRange { name, variant, value } => {
+                write!(f, "discriminant {} of `{}::{}` does not fit its representation", value, name, variant)
+            }
+            LayoutError::InvalidAlignment { name, align } => {
+                write!(f, "`#align({})` on `{}` is not a power of two", align, name)
+            }
+            LayoutError::SizeOverflow(t) => write!(f, "size of `{}` overflows", t),
+        }
+    }
+}
+
+impl std::error::Error for LayoutError {}
+
+// ============================================================================
+// Engine
+// ============================================================================
+
+/// A generic argument bound while laying out an instantiated type
+#[derive(Debug, Clone)]
+enum GenericArg {
+    Type(Type),
+    Const(i128),
+}
+
+type Subst = HashMap<String, GenericArg>;
+
+/// Computes and caches layouts for the types declared in an [`ItemTable`]
+pub struct LayoutEngine<'a> {
+    target: Target,
+    items: &'a ItemTable<'a>,
+    cache: HashMap<String, Layout>,
+    /// Types and constants currently being evaluated, to detect cycles
+    in_progress: Vec<String>,
+}
+
+impl<'a> LayoutEngine<'a> {
+    pub fn new(items: &'a ItemTable<'a>, target: Target) -> Self {
+        LayoutEngine { target, items, cache: HashMap::new(), in_progress: Vec::new() }
+    }
+
+    pub fn target(&self) -> Target {
+        self.target
+    }
+
+    pub fn layout_of(&mut self, ty: &Type) -> Result<Layout, LayoutError> {
+        self.layout(ty, &Subst::new())
+    }
+
+    pub fn size_of(&mut self, ty: &Type) -> Result<u64, LayoutError> {
+        Ok(self.layout_of(ty)?.size)
+    }
+
+    pub fn align_of(&mut self, ty: &Type) -> Result<u64, LayoutError> {
+        Ok(self.layout_of(ty)?.align)
+    }
+
+    pub fn offset_of(&mut self, ty: &Type, field: &str) -> Result<u64, LayoutError> {
+        self.offset_in(ty, field, &Subst::new())
+    }
+
+    /// Evaluate a constant integer expression: literals, arithmetic, casts,
+    /// references to `const` items and enum variants, and the `sizeof`,
+    /// `alignof` and `offsetof` intrinsics.
+    pub fn eval_const(&mut self, expr: &Expression) -> Result<i128, LayoutError> {
+        self.eval(expr, &Subst::new())
+    }
+
+    /// Discriminant of every variant of an enum, in declaration order
+    pub fn enum_discriminants(&mut self, e: &Enum) -> Result<Vec<(String, i128)>, LayoutError> {
+        let mut next = 0i128;
+        let mut discriminants = Vec::with_capacity(e.variants.len());
+        for variant in &e.variants {
+            let value = match &variant.value {
+                Some(expr) => self.eval(expr, &Subst::new())?,
+                None => next,
+            };
+            discriminants.push((variant.name.clone(), value));
+            next = value + 1;
+        }
+        Ok(discriminants)
+    }
+
+    fn offset_in(&mut self, ty: &Type, field: &str, subst: &Subst) -> Result<u64, LayoutError> {
+        let layout = self.layout(ty, subst)?;
+        match &layout.shape {
+            Shape::Struct { fields, .. } => fields
+                .iter()
+                .find(|f| f.name == field)
+                .map(|f| f.offset)
+                .ok_or_else(|| LayoutError::UnknownField { ty: format_type(ty), field: field.to_string() }),
+            _ => Err(LayoutError::NotAStruct(format_type(ty))),
+        }
+    }
+
+    fn layout(&mut self, ty: &Type, subst: &Subst) -> Result<Layout, LayoutError> {
+        let pw = self.target.pointer_width;
+        match ty {
+            Type::U8 | Type::I8 | Type::Bool => Ok(Layout::scalar(1, false)),
+            Type::U16 | Type::I16 => Ok(Layout::scalar(2, false)),
+            Type::U32 | Type::I32 | Type::F32 => Ok(Layout::scalar(4, false)),
+            Type::U64 | Type::I64 | Type::F64 => Ok(Layout::scalar(8, false)),
+            Type::USize | Type::ISize => Ok(Layout::scalar(pw, false)),
+            Type::Ok | Type::Null => Ok(Layout::scalar(0, false)),
+            Type::SelfType => Err(LayoutError::UnknownType("Self".to_string())),
+            Type::Pointer { nullable, .. } => Ok(Layout::scalar(pw, !nullable)),
+            Type::Optional(inner) => {
+                let inner_layout = self.layout(inner, subst)?;
+                if inner_layout.has_null_niche() {
+                    Ok(Layout {
+                        size: inner_layout.size,
+                        align: inner_layout.align,
+                        shape: Shape::Niche { inner: Box::new(inner_layout) },
+                    })
+                } else {
+                    self.tagged(vec![("some".to_string(), inner.as_ref().clone())], subst)
+                }
+            }
+            Type::ErrorUnion { ok_type, err_type } => self.tagged(
+                vec![
+                    ("ok".to_string(), ok_type.as_ref().clone()),
+                    ("err".to_string(), Type::Path(err_type.clone())),
+                ],
+                subst,
+            ),
+            Type::Array { element_type, size: Some(count) } => {
+                let count = self.eval(count, subst)?;
+                let count = u64::try_from(count).map_err(|_| LayoutError::NotConstant(count.to_string()))?;
+                let element = self.layout(element_type, subst)?;
+                let size = element
+                    .size
+                    .checked_mul(count)
+                    .ok_or_else(|| LayoutError::SizeOverflow(format_type(ty)))?;
+                Ok(Layout { size, align: element.align, shape: Shape::Array { element: Box::new(element), count } })
+            }
+            Type::Array { element_type, size: None } => {
+                let ptr_type = Type::Pointer { nullable: false, mutable: false, element_type: element_type.clone() };
+                let fields = vec![
+                    FieldLayout { name: "ptr".into(), ty: format_type(&ptr_type), offset: 0, layout: Layout::scalar(pw, true) },
+                    FieldLayout { name: "len".into(), ty: "usize".into(), offset: pw, layout: Layout::scalar(pw, false) },
+                ];
+                Ok(Layout { size: 2 * pw, align: pw, shape: Shape::Struct { packed: false, fields } })
+            }
+            Type::Function(_) => {
+                let fields = vec![
+                    FieldLayout { name: "code".into(), ty: format_type(ty), offset: 0, layout: Layout::scalar(pw, true) },
+                    FieldLayout { name: "env".into(), ty: "?*u8".into(), offset: pw, layout: Layout::scalar(pw, false) },
+                ];
+                Ok(Layout { size: 2 * pw, align: pw, shape: Shape::Struct { packed: false, fields } })
+            }
+            Type::Path(path) => self.path_layout(path, subst),
+            Type::Const(_) => Err(LayoutError::UnknownType(format_type(ty))),
+        }
+    }
+
+    fn path_layout(&mut self, path: &Path, subst: &Subst) -> Result<Layout, LayoutError> {
+        if let [name] = path.segments.as_slice()
+            && path.generic_args.is_empty()
+            && let Some(arg) = subst.get(name)
+        {
+            return match arg {
+                GenericArg::Type(ty) => self.layout(&ty.clone(), &Subst::new()),
+                GenericArg::Const(_) => Err(LayoutError::NotAConcreteType(name.clone())),
+            };
+        }
+
+        let (qualified, def) = self
+            .items
+            .lookup_type_entry(path)
+            .ok_or_else(|| LayoutError::UnknownType(format_path(path)))?;
+        let qualified = qualified.to_string();
+
+        // Bind the generic arguments, resolved through the enclosing substitution
+        let mut inner = Subst::new();
+        let mut concrete_args = Vec::new();
+        for (i, param) in def.generic_params().iter().enumerate() {
+            match param {
+                GenericParameter::Type { name, default_type, .. } => {
+                    let arg = match (path.generic_args.get(i), default_type) {
+                        (Some(arg), _) => self.substitute(arg, subst),
+                        (None, Some(default)) => self.substitute(default, subst),
+                        (None, None) => return Err(LayoutError::UnboundGeneric(name.clone())),
+                    };
+                    concrete_args.push(format_type(&arg));
+                    inner.insert(name.clone(), GenericArg::Type(arg));
+                }
+                GenericParameter::Const { name, .. } => {
+                    let value = match path.generic_args.get(i) {
+                        Some(Type::Path(p)) if p.generic_args.is_empty() => {
+                            self.eval(&Expression::Path(p.clone()), subst)?
+                        }
+                        Some(Type::Const(value)) => self.eval(value, subst)?,
+                        Some(other) => return Err(LayoutError::NotConstant(format_type(other))),
+                        None => return Err(LayoutError::UnboundGeneric(name.clone())),
+                    };
+                    concrete_args.push(value.to_string());
+                    inner.insert(name.clone(), GenericArg::Const(value));
+                }
+            }
+        }
+
+        let key = if concrete_args.is_empty() {
+            qualified
+        } else {
+            format!("{}[{}]", qualified, concrete_args.join(", "))
+        };
+        if let Some(layout) = self.cache.get(&key) {
+            return Ok(layout.clone());
+        }
+        if self.in_progress.contains(&key) {
+            return Err(LayoutError::RecursiveType(format_path(path)));
+        }
+        self.in_progress.push(key.clone());
+        let result = match def {
+            TypeDef::Struct(s) => self.struct_layout(s, &inner),
+            TypeDef::Union(u) => self.tagged(u.variants.iter().map(|v| (v.name.clone(), v.ty.clone())).collect(), &inner),
+            TypeDef::Enum(e) => self.enum_layout(e),
+            TypeDef::Alias(a) => self.layout(&a.aliased_type, &inner),
+            TypeDef::Interface(i) => Err(LayoutError::NotAConcreteType(i.name.clone())),
+        };
+        self.in_progress.pop();
+        let layout = result?;
+        self.cache.insert(key, layout.clone());
+        Ok(layout)
+    }
+
+    fn struct_layout(&mut self, s: &Struct, subst: &Subst) -> Result<Layout, LayoutError> {
+        let mut offset = 0u64;
+        let mut align = 1u64;
+        let mut fields = Vec::with_capacity(s.fields.len());
+        for field in &s.fields {
+            let layout = self.layout(&field.ty, subst)?;
+            if !s.is_packed {
+                offset = align_to(offset, layout.align);
+                align = align.max(layout.align);
+            }
+            let size = layout.size;
+            fields.push(FieldLayout { name: field.name.clone(), ty: format_type(&field.ty), offset, layout });
+            offset = offset.checked_add(size).ok_or_else(|| LayoutError::SizeOverflow(s.name.clone()))?;
+        }
+        for annotation in s.annotations.iter().filter(|a| a.name == "align") {
+            let requested = match annotation.args.as_slice() {
+                [arg] => self.eval(arg, subst)?,
+                _ => return Err(LayoutError::NotConstant(format!("#align on `{}`", s.name))),
+            };
+            if requested <= 0 || requested.count_ones() != 1 {
+                return Err(LayoutError::InvalidAlignment { name: s.name.clone(), align: requested });
+            }
+            align = align.max(requested as u64);
+        }
+        Ok(Layout { size: align_to(offset, align), align, shape: Shape::Struct { packed: s.is_packed, fields } })
+    }
+
+    /// A discriminant followed by a payload large enough for every variant
+    fn tagged(&mut self, variants: Vec<(String, Type)>, subst: &Subst) -> Result<Layout, LayoutError> {
+        let tag_size: u64 = match variants.len() {
+            0..=256 => 1,
+            257..=65536 => 2,
+            _ => 4,
+        };
+        let mut laid_out = Vec::with_capacity(variants.len());
+        let mut payload_align = 1;
+        let mut payload_size = 0;
+        for (name, ty) in &variants {
+            let layout = self.layout(ty, subst)?;
+            payload_align = payload_align.max(layout.align);
+            payload_size = payload_size.max(layout.size);
+            laid_out.push((name, ty, layout));
+        }
+        let payload_offset = align_to(tag_size, payload_align);
+        let align = payload_align.max(tag_size);
+        let fields = laid_out
+            .into_iter()
+            .map(|(name, ty, layout)| FieldLayout { name: name.clone(), ty: format_type(ty), offset: payload_offset, layout })
+            .collect();
+        Ok(Layout {
+            size: align_to(payload_offset + payload_size, align),
+            align,
+            shape: Shape::Tagged { tag_size, variants: fields },
+        })
+    }
+
+    fn enum_layout(&mut self, e: &Enum) -> Result<Layout, LayoutError> {
+        let discriminants = self.enum_discriminants(e)?;
+        let (bits, signed) = match &e.representation {
+            Some(repr) => self.integer_repr(repr).ok_or_else(|| LayoutError::InvalidRepresentation {
+                name: e.name.clone(),
+                repr: format_type(repr),
+            })?,
+            None => {
+                let signed = discriminants.iter().any(|(_, v)| *v < 0);
+                let bits = [8, 16, 32, 64]
+                    .into_iter()
+                    .find(|bits| {
+                        let (min, max) = integer_bounds(*bits, signed);
+                        discriminants.iter().all(|(_, v)| (min..=max).contains(v))
+                    })
+                    .unwrap_or(64);
+                (bits, signed)
+            }
+        };
+        let (min, max) = integer_bounds(bits, signed);
+        if let Some((variant, value)) = discriminants.iter().find(|(_, v)| !(min..=max).contains(v)) {
+            return Err(LayoutError::DiscriminantOutOfRange {
+                name: e.name.clone(),
+                variant: variant.clone(),
+                value: *value,
+            });
+        }
+        let size = u64::from(bits / 8);
+        Ok(Layout { size, align: size, shape: Shape::Enum { signed, discriminants } })
+    }
+
+    /// Width and signedness of an enum representation, following aliases
+    fn integer_repr(&self, ty: &Type) -> Option<(u32, bool)> {
+        if let Type::Path(path) = ty
+            && let Some(TypeDef::Alias(alias)) = self.items.lookup_type(path)
+        {
+            return self.integer_repr(&alias.aliased_type);
+        }
+        self.target.integer_info(ty)
+    }
+
+    /// Replace bound generic parameters in `ty` with their arguments
+    fn substitute(&self, ty: &Type, subst: &Subst) -> Type {
+        match ty {
+            Type::Path(path) => {
+                if let [name] = path.segments.as_slice()
+                    && path.generic_args.is_empty()
+                {
+                    match subst.get(name) {
+                        Some(GenericArg::Type(arg)) => return arg.clone(),
+                        Some(GenericArg::Const(value)) => return Type::Const(Box::new(int_literal(*value))),
+                        None => {}
+                    }
+                }
+                Type::Path(self.substitute_path(path, subst))
+            }
+            Type::Pointer { nullable, mutable, element_type } => Type::Pointer {
+                nullable: *nullable,
+                mutable: *mutable,
+                element_type: Box::new(self.substitute(element_type, subst)),
+            },
+            Type::Optional(inner) => Type::Optional(Box::new(self.substitute(inner, subst))),
+            Type::Array { element_type, size } => Type::Array {
+                element_type: Box::new(self.substitute(element_type, subst)),
+                size: size.as_ref().map(|size| match size.as_ref() {
+                    Expression::Path(p) => match p.segments.as_slice() {
+                        [name] if p.generic_args.is_empty() => match subst.get(name) {
+                            Some(GenericArg::Const(value)) => Box::new(int_literal(*value)),
+                            _ => size.clone(),
+                        },
+                        _ => size.clone(),
+                    },
+                    _ => size.clone(),
+                }),
+            },
+            Type::ErrorUnion { ok_type, err_type } => Type::ErrorUnion {
+                ok_type: Box::new(self.substitute(ok_type, subst)),
+                err_type: self.substitute_path(err_type, subst),
+            },
+            other => other.clone(),
+        }
+    }
+
+    fn substitute_path(&self, path: &Path, subst: &Subst) -> Path {
+        Path {
+            segments: path.segments.clone(),
+            generic_args: path.generic_args.iter().map(|a| self.substitute(a, subst)).collect(),
+        }
+    }
+
+    fn eval(&mut self, expr: &Expression, subst: &Subst) -> Result<i128, LayoutError> {
+        let not_constant = || LayoutError::NotConstant(format_expression(expr));
+        match expr {
+            Expression::IntegerLiteral(lit) => lit.as_u64().map(i128::from).map_err(|_| not_constant()),
+            Expression::BooleanLiteral(b) => Ok(i128::from(*b)),
+            Expression::CharLiteral(c) => {
+                let mut chars = c.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(ch), None) => Ok(i128::from(u32::from(ch))),
+                    _ => Err(not_constant()),
+                }
+            }
+            Expression::Parenthesized(inner) => self.eval(inner, subst),
+            Expression::UnaryOp(op) => {
+                let v = self.eval(&op.operand, subst)?;
+                match op.op {
+                    UnaryOperator::Negate => v.checked_neg().ok_or_else(not_constant),
+                    UnaryOperator::Plus => Ok(v),
+                    UnaryOperator::BitwiseNot => Ok(!v),
+                    UnaryOperator::LogicalNot => Ok(i128::from(v == 0)),
+                    UnaryOperator::AddressOf | UnaryOperator::Dereference => Err(not_constant()),
+                }
+            }
+            Expression::BinaryOp(op) => {
+                let l = self.eval(&op.lhs, subst)?;
+                let r = self.eval(&op.rhs, subst)?;
+                let result = match op.op {
+                    BinaryOperator::Add => l.checked_add(r),
+                    BinaryOperator::Subtract => l.checked_sub(r),
+                    BinaryOperator::Multiply => l.checked_mul(r),
+                    BinaryOperator::Divide => l.checked_div(r),
+                    BinaryOperator::Modulo => l.checked_rem(r),
+                    BinaryOperator::BitwiseAnd => Some(l & r),
+                    BinaryOperator::BitwiseOr => Some(l | r),
+                    BinaryOperator::BitwiseXor => Some(l ^ r),
+                    BinaryOperator::ShiftLeft => u32::try_from(r).ok().and_then(|r| l.checked_shl(r)),
+                    BinaryOperator::ShiftRight => u32::try_from(r).ok().and_then(|r| l.checked_shr(r)),
+                    BinaryOperator::Equal => Some(i128::from(l == r)),
+                    BinaryOperator::NotEqual => Some(i128::from(l != r)),
+                    BinaryOperator::LessThan => Some(i128::from(l < r)),
+                    BinaryOperator::GreaterThan => Some(i128::from(l > r)),
+                    BinaryOperator::LessThanOrEqual => Some(i128::from(l <= r)),
+                    BinaryOperator::GreaterThanOrEqual => Some(i128::from(l >= r)),
+                    BinaryOperator::LogicalAnd => Some(i128::from(l != 0 && r != 0)),
+                    BinaryOperator::LogicalOr => Some(i128::from(l != 0 || r != 0)),
+                };
+                result.ok_or_else(not_constant)
+            }
+            Expression::Cast(cast) => {
+                let v = self.eval(&cast.expr, subst)?;
+                match self.integer_repr(&self.substitute(&cast.target_type, subst)) {
+                    Some((bits, signed)) => Ok(wrap_integer(v, bits, signed)),
+                    None if matches!(*cast.target_type, Type::Bool) => Ok(i128::from(v != 0)),
+                    None => Err(not_constant()),
+                }
+            }
+            Expression::Sizeof(ty) => Ok(i128::from(self.layout(&self.substitute(ty, subst), &Subst::new())?.size)),
+            Expression::Alignof(ty) => Ok(i128::from(self.layout(&self.substitute(ty, subst), &Subst::new())?.align)),
+            Expression::Offsetof(oo) => {
+                Ok(i128::from(self.offset_in(&self.substitute(&oo.ty, subst), &oo.field, &Subst::new())?))
+            }
+            Expression::Path(path) => {
+                if let [name] = path.segments.as_slice()
+                    && let Some(GenericArg::Const(value)) = subst.get(name)
+                {
+                    return Ok(*value);
+                }
+                self.eval_const_item(path)
+            }
+            Expression::TypeAccess(ta) => {
+                let Expression::Path(owner) = ta.object.as_ref() else {
+                    return Err(not_constant());
+                };
+                if let Some(TypeDef::Enum(e)) = self.items.lookup_type(owner) {
+                    let discriminants = self.enum_discriminants(e)?;
+                    return discriminants
+                        .into_iter()
+                        .find(|(name, _)| *name == ta.member)
+                        .map(|(_, v)| v)
+                        .ok_or_else(not_constant);
+                }
+                let mut segments = owner.segments.clone();
+                segments.push(ta.member.clone());
+                self.eval_const_item(&Path::with_generics(segments, vec![]))
+            }
+            _ => Err(not_constant()),
+        }
+    }
+
+    fn eval_const_item(&mut self, path: &Path) -> Result<i128, LayoutError> {
+        let item = self
+            .items
+            .lookup_const(path)
+            .ok_or_else(|| LayoutError::NotConstant(format_path(path)))?;
+        let key = format!("const {}", format_path(path));
+        if self.in_progress.contains(&key) {
+            return Err(LayoutError::NotConstant(format_path(path)));
+        }
+        self.in_progress.push(key);
+        let value = self.eval(&item.value, &Subst::new());
+        self.in_progress.pop();
+        let value = value?;
+        match item.ty.as_ref().and_then(|ty| self.integer_repr(ty)) {
+            Some((bits, signed)) => Ok(wrap_integer(value, bits, signed)),
+            None => Ok(value),
+        }
+    }
+
+    // =========================================================================
+    // Dumping
+    // =========================================================================
+
+    /// Render the layout of every non-generic struct, union and enum in the
+    /// item table, for auditing FFI boundaries against C headers.
+    pub fn dump(&mut self) -> String {
+        let mut out = String::new();
+        let endianness = match self.target.endianness {
+            Endianness::Little => "little-endian",
+            Endianness::Big => "big-endian",
+        };
+        writeln!(out, "target: {}-bit pointers, {}", self.target.pointer_width * 8, endianness).unwrap();
+        let items = self.items;
+        for (name, def) in items.types() {
+            let kind = match def {
+                TypeDef::Struct(s) if s.is_packed => "packed struct",
+                TypeDef::Struct(_) => "struct",
+                TypeDef::Union(_) => "union",
+                TypeDef::Enum(_) => "enum",
+                TypeDef::Alias(_) | TypeDef::Interface(_) => continue,
+            };
+            writeln!(out).unwrap();
+            if !def.generic_params().is_empty() {
+                writeln!(out, "{} {}: generic, instantiate to compute a layout", kind, name).unwrap();
+                continue;
+            }
+            match self.layout_of(&Type::Path(Path::simple(name.to_string()))) {
+                Ok(layout) => write_layout(&mut out, kind, name, &layout),
+                Err(err) => writeln!(out, "{} {}: error: {}", kind, name, err).unwrap(),
+            }
+        }
+        out
+    }
+}
+
+pub(crate) fn int_literal(value: i128) -> Expression {
+    Expression::IntegerLiteral(IntegerLiteral::builder().digits(value.to_string()).build().unwrap())
+}
+
+fn write_layout(out: &mut String, kind: &str, name: &str, layout: &Layout) {
+    writeln!(out, "{} {}: size {}, align {}", kind, name, layout.size, layout.align).unwrap();
+    match &layout.shape {
+        Shape::Struct { fields, .. } => {
+            let mut end = 0;
+            for field in fields {
+                if field.offset > end {
+                    writeln!(out, "  {:>6}  padding {}", end, field.offset - end).unwrap();
+                }
+                writeln!(out, "  {:>6}  {}: {} (size {}, align {})", field.offset, field.name, field.ty, field.layout.size, field.layout.align).unwrap();
+                end = field.offset + field.layout.size;
+            }
+            if layout.size > end {
+                writeln!(out, "  {:>6}  padding {}", end, layout.size - end).unwrap();
+            }
+        }
+        Shape::Tagged { tag_size, variants } => {
+            writeln!(out, "  {:>6}  tag: u{}", 0, tag_size * 8).unwrap();
+            for variant in variants {
+                writeln!(out, "  {:>6}  {}: {} (size {}, align {})", variant.offset, variant.name, variant.ty, variant.layout.size, variant.layout.align).unwrap();
+            }
+        }
+        Shape::Enum { signed, discriminants } => {
+            writeln!(out, "  repr: {}{}", if *signed { "i" } else { "u" }, layout.size * 8).unwrap();
+            for (variant, value) in discriminants {
+                writeln!(out, "  {} = {}", variant, value).unwrap();
+            }
+        }
+        Shape::Scalar { .. } | Shape::Array { .. } | Shape::Niche { .. } => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    fn engine_for<'a>(items: &'a ItemTable<'a>) -> LayoutEngine<'a> {
+        LayoutEngine::new(items, Target::X86_64)
+    }
+
+    fn named(name: &str) -> Type {
+        Type::Path(Path::simple(name.to_string()))
+    }
+
+    fn ty(src: &str) -> Type {
+        fig_parser::TypeParser::new().parse(fig_parser::Lexer::new(src)).unwrap()
+    }
+
+    #[test]
+    fn test_primitive_and_pointer_layouts() {
+        let sf = parse("");
+        let items = ItemTable::from_source_file(&sf);
+        let mut engine = engine_for(&items);
+        assert_eq!(engine.size_of(&ty("u16")).unwrap(), 2);
+        assert_eq!(engine.size_of(&ty("usize")).unwrap(), 8);
+        assert_eq!(engine.size_of(&ty("ok")).unwrap(), 0);
+        assert_eq!(engine.size_of(&ty("[u32; 3]")).unwrap(), 12);
+        assert_eq!(engine.size_of(&ty("[u8]")).unwrap(), 16);
+        assert_eq!(engine.size_of(&ty("fn!(i32) -> u8 ! E")).unwrap(), 16);
+
+        let mut wasm = LayoutEngine::new(&items, Target::WASM32);
+        assert_eq!(wasm.size_of(&ty("*mut u8")).unwrap(), 4);
+        assert_eq!(wasm.size_of(&ty("[u8]")).unwrap(), 8);
+    }
+
+    #[test]
+    fn test_struct_padding() {
+        let sf = parse("struct Foo\n    a: u8\n    b: u32\n    c: u64\n");
+        let items = ItemTable::from_source_file(&sf);
+        let mut engine = engine_for(&items);
+        let layout = engine.layout_of(&named("Foo")).unwrap();
+        assert_eq!((layout.size, layout.align), (16, 8));
+        assert_eq!(engine.offset_of(&named("Foo"), "b").unwrap(), 4);
+        assert_eq!(engine.offset_of(&named("Foo"), "c").unwrap(), 8);
+        assert!(matches!(engine.offset_of(&named("Foo"), "d"), Err(LayoutError::UnknownField { .. })));
+    }
+
+    #[test]
+    fn test_packed_struct() {
+        let sf = parse("packed struct Header\n    magic: u32\n    version: u16\n    flags: u8\n\npacked struct Mixed\n    a: u8\n    b: u64\n");
+        let items = ItemTable::from_source_file(&sf);
+        let mut engine = engine_for(&items);
+        let header = engine.layout_of(&named("Header")).unwrap();
+        assert_eq!((header.size, header.align), (7, 1));
+        assert_eq!(engine.offset_of(&named("Mixed"), "b").unwrap(), 1);
+    }
+
+    #[test]
+    fn test_align_annotation() {
+        let sf = parse("#align(16)\nstruct Buffer\n    data: [u8; 20]\n");
+        let items = ItemTable::from_source_file(&sf);
+        let layout = engine_for(&items).layout_of(&named("Buffer")).unwrap();
+        assert_eq!((layout.size, layout.align), (32, 16));
+    }
+
+    #[test]
+    fn test_union_and_error_union_are_tagged() {
+        let sf = parse("union Value\n    int: i64\n    flag: bool\n\nstruct IoError\n    code: i32\n");
+        let items = ItemTable::from_source_file(&sf);
+        let mut engine = engine_for(&items);
+        let value = engine.layout_of(&named("Value")).unwrap();
+        assert_eq!((value.size, value.align), (16, 8));
+        assert_eq!(value.field("int").unwrap().offset, 8);
+
+        let result = engine.layout_of(&ty("u8 ! IoError")).unwrap();
+        assert_eq!((result.size, result.align), (8, 4));
+        assert!(matches!(result.shape, Shape::Tagged { tag_size: 1, .. }));
+    }
+
+    #[test]
+    fn test_optional_uses_pointer_niche() {
+        let sf = parse("type NodePtr = *Node\n\nstruct Node\n    next: ?*Node\n    value: i32\n");
+        let items = ItemTable::from_source_file(&sf);
+        let mut engine = engine_for(&items);
+        assert_eq!(engine.size_of(&ty("?*Node")).unwrap(), 8);
+        let niche = engine.layout_of(&ty("?NodePtr")).unwrap();
+        assert_eq!(niche.size, 8);
+        assert!(matches!(niche.shape, Shape::Niche { .. }));
+        // No niche in an i32, so a tag is needed
+        assert_eq!(engine.size_of(&ty("?i32")).unwrap(), 8);
+        assert_eq!(engine.size_of(&named("Node")).unwrap(), 16);
+    }
+
+    #[test]
+    fn test_enum_representation() {
+        let sf = parse(
+            "enum[u16] Medium\n    FIRST = 0\n    LAST = 65535\n\nenum Small\n    A\n    B\n\nenum Signed\n    NEG = -1\n    POS = 200\n\nenum[u8] Bad\n    X = 256\n",
+        );
+        let items = ItemTable::from_source_file(&sf);
+        let mut engine = engine_for(&items);
+        assert_eq!(engine.size_of(&named("Medium")).unwrap(), 2);
+        assert_eq!(engine.size_of(&named("Small")).unwrap(), 1);
+        let signed = engine.layout_of(&named("Signed")).unwrap();
+        assert_eq!(signed.size, 2);
+        assert!(matches!(signed.shape, Shape::Enum { signed: true, .. }));
+        assert!(matches!(engine.layout_of(&named("Bad")), Err(LayoutError::DiscriminantOutOfRange { .. })));
+    }
+
+    #[test]
+    fn test_generic_instantiation() {
+        let sf = parse("struct Pair[A, B]\n    first: A\n    second: B\n\nstruct Holder\n    pair: Pair[u8, *u8]\n");
+        let items = ItemTable::from_source_file(&sf);
+        let mut engine = engine_for(&items);
+        assert_eq!(engine.size_of(&ty("Pair[u8, u64]")).unwrap(), 16);
+        assert_eq!(engine.size_of(&ty("Pair[u8, u16]")).unwrap(), 4);
+        assert_eq!(engine.size_of(&named("Holder")).unwrap(), 16);
+        assert!(matches!(engine.layout_of(&named("Pair")), Err(LayoutError::UnboundGeneric(_))));
+    }
+
+    #[test]
+    fn test_const_generic_arguments() {
+        let src = "\
+const SIZE: usize = 3
+
+struct Buf[const N: usize]
+    data: [u8; N]
+
+struct Twice[const N: usize]
+    inner: Buf[N]
+    more: [u16; N]
+
+struct Fixed
+    buf: Buf[SIZE]
+";
+        let sf = parse(src);
+        let items = ItemTable::from_source_file(&sf);
+        let mut engine = engine_for(&items);
+        assert_eq!(engine.size_of(&ty("Buf[5]")).unwrap(), 5);
+        assert_eq!(engine.size_of(&ty("Buf[SIZE]")).unwrap(), 3);
+        assert_eq!(engine.size_of(&named("Fixed")).unwrap(), 3);
+        assert_eq!(engine.offset_of(&ty("Twice[3]"), "more").unwrap(), 4);
+        assert!(matches!(engine.layout_of(&ty("Buf[u8]")), Err(LayoutError::NotConstant(_))));
+    }
+
+    #[test]
+    fn test_same_names_in_different_namespaces() {
+        let src = "namespace a
+    struct Id
+        value: u8
+
+namespace b
+    struct Id
+        value: u64
+";
+        let sf = parse(src);
+        let items = ItemTable::from_source_file(&sf);
+        let mut engine = engine_for(&items);
+        assert_eq!(engine.size_of(&ty("a::Id")).unwrap(), 1);
+        assert_eq!(engine.size_of(&ty("b::Id")).unwrap(), 8);
+    }
+
+    #[test]
+    fn test_recursive_struct_is_rejected() {
+        let sf = parse("struct List\n    value: i32\n    next: List\n");
+        let items = ItemTable::from_source_file(&sf);
+        assert!(matches!(engine_for(&items).layout_of(&named("List")), Err(LayoutError::RecursiveType(_))));
+    }
+
+    #[test]
+    fn test_eval_intrinsics_and_consts() {
+        let sf = parse("const LEN: usize = 4 * 2\n\nstruct Foo\n    a: u8\n    b: [u32; LEN]\n");
+        let items = ItemTable::from_source_file(&sf);
+        let mut engine = engine_for(&items);
+        let expr = |src: &str| fig_parser::ExpressionParser::new().parse(fig_parser::Lexer::new(src)).unwrap();
+        assert_eq!(engine.eval_const(&expr("sizeof(Foo)")).unwrap(), 36);
+        assert_eq!(engine.eval_const(&expr("offsetof(Foo, b) + alignof(*mut u8)")).unwrap(), 12);
+        assert_eq!(engine.eval_const(&expr("300 as u8")).unwrap(), 44);
+        assert!(matches!(engine.eval_const(&expr("x + 1")), Err(LayoutError::NotConstant(_))));
+    }
+
+    #[test]
+    fn test_dump() {
+        let sf = parse("struct Foo\n    a: u8\n    b: u32\n\nenum[u8] Color\n    Red\n    Green\n\nstruct Box[T]\n    value: T\n");
+        let items = ItemTable::from_source_file(&sf);
+        let dump = engine_for(&items).dump();
+        assert!(dump.contains("struct Foo: size 8, align 4"));
+        assert!(dump.contains("     1  padding 3"));
+        assert!(dump.contains("     4  b: u32 (size 4, align 4)"));
+        assert!(dump.contains("  Green = 1"));
+        assert!(dump.contains("struct Box: generic"));
+    }
+}
@@ -0,0 +1,42 @@
+//! Semantic analysis for Fig
+//!
+//! Passes here operate on the AST produced by `fig-parser`. Declarations are
+//! first collected into an [`items::ItemTable`]; individual passes then query
+//! that table rather than walking the source file themselves.
+
+pub mod captures;
+pub mod conformance;
+pub mod construct;
+pub mod diagnostics;
+pub mod effects;
+pub mod generics;
+pub mod index;
+pub mod items;
+pub mod layout;
+pub mod mono;
+pub mod propagation;
+pub mod resolve;
+pub mod typeck;
+
+use diagnostics::Diagnostic;
+use items::ItemTable;
+
+/// Every problem the semantic passes report for `items`. Warnings are
+/// included; callers decide whether they stop the build.
+pub fn check(items: &ItemTable) -> Vec<Diagnostic> {
+    let mut diagnostics: Vec<Diagnostic> =
+        items.duplicates().iter().map(|name| Diagnostic::error(format!("type `{}` is defined more than once", name))).collect();
+    diagnostics.extend(conformance::check_conformance(items));
+    diagnostics.extend(generics::check_generics(items));
+    diagnostics.extend(effects::check_effects(items));
+    diagnostics.extend(captures::check_captures(items));
+    diagnostics.extend(propagation::check_propagation(items).1);
+    diagnostics
+}
+
+#[cfg(test)]
+pub(crate) fn parse(src: &str) -> fig_parser::ast::SourceFile {
+    fig_parser::SourceFileParser::new()
+        .parse(fig_parser::Lexer::new(src))
+        .unwrap()
+}
@@ -0,0 +1,465 @@
+//! Monomorphisation: the concrete instances a program needs
+//!
+//! Backends generate code for [`Instance`]s, never for generic functions.
+//! The [`Monomorphizer`] finds every instance reachable from a set of entry
+//! points, usually the non-generic [`roots`]. Each instance is checked with
+//! the [`TypeChecker`], which substitutes its bindings through the signature
+//! and the body. The callees the body records are queued in turn. An
+//! instance reached along several paths, e.g. `largest[u8]` called from two
+//! functions, is checked once; bindings are normalised, so equal bindings
+//! mean the same instance.
+//!
+//! Along the way it collects every concrete instance of a generic type in a
+//! signature, a body or the fields of another such type, including const
+//! arguments: `Ring[u8, 16]` for `struct Ring[T, const N: usize]`.
+//!
+//! Some generic programs need infinitely many instances. A common case is
+//! polymorphic recursion that grows its arguments:
+//!
+//! ```text
+//! func[T] nest(x: T, n: i32) -> i32
+//!     return nest(&x, n - 1)    // nest[i32] -> nest[*i32] -> nest[**i32] ...
+//! ```
+//!
+//! This is reported as soon as an instance is reached from an instance of
+//! the same function whose bindings it strictly contains. A type whose
+//! fields do the same, e.g. `next: ?*Node[Pair[T]]` in `Node[T]`, is also
+//! reported. Chains that grow some other way, such as a const argument
+//! counting up, are cut off at [`Monomorphizer::with_limit`].
+
+use std::collections::HashSet;
+
+use fig_parser::ast::{Path, Type};
+use fig_parser::format::format_type;
+
+use crate::diagnostics::Diagnostic;
+use crate::items::ItemTable;
+use crate::layout::Target;
+use crate::typeck::{Bindings, Instance, Signature, TypeChecker, TypedBody, roots};
+
+/// How deep instantiation chains may nest by default
+pub const DEFAULT_LIMIT: usize = 64;
+
+/// One function instance, checked
+#[derive(Debug)]
+pub struct MonoFunction<'a> {
+    pub instance: Instance<'a>,
+    pub signature: Signature,
+    /// Empty for `extern` functions and others without a body
+    pub body: TypedBody<'a>,
+}
+
+/// The instances reachable from the entry points
+#[derive(Debug, Default)]
+pub struct Monomorphized<'a> {
+    /// Each instance once: the entry points, and the callees of each
+    /// depth-first in the order they are called
+    pub functions: Vec<MonoFunction<'a>>,
+    /// Each concrete instance of a generic struct, union or enum once, in
+    /// the order they are reached
+    pub types: Vec<Type>,
+}
+
+impl<'a> Monomorphized<'a> {
+    pub fn function(&self, instance: &Instance<'a>) -> Option<&MonoFunction<'a>> {
+        self.functions.iter().find(|f| f.instance == *instance)
+    }
+}
+
+/// Collects the instances reachable from entry points; see the module docs
+pub struct Monomorphizer<'t, 'a> {
+    tc: &'t mut TypeChecker<'a>,
+    limit: usize,
+    types: Vec<Type>,
+    /// Every named type whose fields have been visited, as written
+    visited: HashSet<String>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// An instance waiting to be checked, and the index in the results of the
+/// instance that first called it
+struct Pending<'a> {
+    instance: Instance<'a>,
+    caller: Option<usize>,
+}
+
+impl<'t, 'a> Monomorphizer<'t, 'a> {
+    pub fn new(tc: &'t mut TypeChecker<'a>) -> Self {
+        Monomorphizer { tc, limit: DEFAULT_LIMIT, types: Vec::new(), visited: HashSet::new(), diagnostics: Vec::new() }
+    }
+
+    /// How many instances deep a chain of calls, or of generic types inside
+    /// each other's fields, may go before it is reported as infinite
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Check every instance reachable from `entries`
+    pub fn run(mut self, entries: Vec<Instance<'a>>) -> Result<Monomorphized<'a>, Vec<Diagnostic>> {
+        let mut functions: Vec<MonoFunction<'a>> = Vec::new();
+        // Who first called each instance, in step with `functions`
+        let mut callers: Vec<Option<usize>> = Vec::new();
+        let mut seen: HashSet<(usize, String)> = HashSet::new();
+        let mut stack: Vec<Pending<'a>> = Vec::new();
+        for instance in entries {
+            if seen.insert(identity(&instance)) {
+                stack.push(Pending { instance, caller: None });
+            }
+        }
+        stack.reverse();
+
+        while let Some(Pending { instance, caller }) = stack.pop() {
+            let signature = match self.tc.signature(&instance) {
+                Ok(signature) => signature,
+                Err(diagnostic) => {
+                    self.diagnostics.push(diagnostic);
+                    continue;
+                }
+            };
+            let body = match self.tc.check(&instance) {
+                Ok(body) => body,
+                Err(diagnostics) => {
+                    self.diagnostics.extend(diagnostics);
+                    continue;
+                }
+            };
+            for ty in signature.self_type.iter().chain(signature.params.iter().map(|(_, ty)| ty)) {
+                self.visit_type(ty, 0);
+            }
+            self.visit_type(&signature.return_type, 0);
+            let mut body_types: Vec<&Type> = body.types().collect();
+            body_types.sort_by_cached_key(|ty| format_type(ty));
+            for ty in body_types {
+                self.visit_type(ty, 0);
+            }
+
+            let index = functions.len();
+            let mut callees = Vec::new();
+            for callee in body.callees() {
+                if seen.contains(&identity(callee)) {
+                    continue;
+                }
+                if let Err(diagnostic) = self.check_growth(&functions, &callers, &instance, caller, callee) {
+                    self.diagnostics.push(diagnostic);
+                    continue;
+                }
+                seen.insert(identity(callee));
+                callees.push(Pending { instance: callee.clone(), caller: Some(index) });
+            }
+            // Depth-first, in the order the callees are called
+            stack.extend(callees.into_iter().rev());
+            functions.push(MonoFunction { instance, signature, body });
+            callers.push(caller);
+        }
+        if self.diagnostics.is_empty() {
+            Ok(Monomorphized { functions, types: self.types })
+        } else {
+            Err(self.diagnostics)
+        }
+    }
+
+    /// Report `callee` if it would make the chain of instances that reached
+    /// `instance` grow without end
+    fn check_growth(
+        &self,
+        functions: &[MonoFunction<'a>],
+        callers: &[Option<usize>],
+        instance: &Instance<'a>,
+        caller: Option<usize>,
+        callee: &Instance<'a>,
+    ) -> Result<(), Diagnostic> {
+        let mut chain = vec![instance];
+        let mut next = caller;
+        while let Some(index) = next {
+            chain.push(&functions[index].instance);
+            next = callers[index];
+        }
+        chain.reverse();
+        let describe = |chain: &[&Instance<'a>]| {
+            let names: Vec<String> = chain.iter().map(|i| format!("`{}`", i.name())).collect();
+            format!("instantiated through {}", names.join(" -> "))
+        };
+        let repeated = chain.iter().position(|earlier| {
+            std::ptr::eq(earlier.function, callee.function) && grows(&earlier.bindings, &callee.bindings)
+        });
+        if let Some(start) = repeated {
+            let mut cycle = chain[start..].to_vec();
+            cycle.push(callee);
+            return Err(Diagnostic::error(format!(
+                "`{}` needs infinitely many instances: `{}` leads to `{}`",
+                callee.function.qualified_name(),
+                chain[start].name(),
+                callee.name()
+            ))
+            .in_function(instance.name())
+            .with_note(describe(&cycle))
+            .with_note("each time round, the generic arguments grow, so the recursion never reaches an instance it has seen"));
+        }
+        // Each instance in the chain made one generic call, the last one to `callee`
+        if chain.len() > self.limit {
+            let names: Vec<String> = chain.iter().map(|i| format!("`{}`", i.name())).collect();
+            // A long chain is shortened to its two ends
+            let path = match names.len() {
+                len if len > 4 => format!("{} -> ... -> {}", names[..2].join(" -> "), names[len - 2..].join(" -> ")),
+                _ => names.join(" -> "),
+            };
+            return Err(Diagnostic::error(format!(
+                "instantiating `{}` nests more than {} generic calls deep",
+                callee.name(),
+                self.limit
+            ))
+            .in_function(instance.name())
+            .with_note(format!("instantiated through {}", path)));
+        }
+        Ok(())
+    }
+
+    /// Record the generic type instances in `ty` and, for named types, in
+    /// their fields. `depth` counts the fields followed to get here.
+    fn visit_type(&mut self, ty: &Type, depth: usize) {
+        match ty {
+            Type::Pointer { element_type, .. } | Type::Array { element_type, .. } => self.visit_type(element_type, depth),
+            Type::Optional(inner) => self.visit_type(inner, depth),
+            Type::ErrorUnion { ok_type, err_type } => {
+                self.visit_type(ok_type, depth);
+                self.visit_type(&Type::Path(err_type.clone()), depth);
+            }
+            Type::Path(path) => {
+                if !self.visited.insert(format_type(ty)) {
+                    return;
+                }
+                for arg in &path.generic_args {
+                    self.visit_type(arg, depth);
+                }
+                if path.generic_args.is_empty() {
+                    // Non-generic types only matter for the instances in
+                    // their fields
+                } else if depth >= self.limit {
+                    self.diagnostics.push(
+                        Diagnostic::error(format!(
+                            "the fields of generic types nest more than {} instances deep, down to `{}`",
+                            self.limit,
+                            format_type(ty)
+                        ))
+                        .with_note("a generic type cannot contain an instance of itself with larger arguments"),
+                    );
+                    return;
+                } else {
+                    self.types.push(ty.clone());
+                }
+                // Unknown types are reported by the checker
+                let Ok(fields) = self.tc.fields(ty) else { return };
+                for (_, field_type) in fields {
+                    if grows_in(path, &field_type) {
+                        self.diagnostics.push(
+                            Diagnostic::error(format!(
+                                "`{}` needs infinitely many instances: it contains `{}`",
+                                format_type(ty),
+                                format_type(&field_type)
+                            ))
+                            .with_note("a generic type cannot contain an instance of itself with larger arguments"),
+                        );
+                        continue;
+                    }
+                    self.visit_type(&field_type, depth + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Every instance reachable from the program's non-generic functions,
+/// checked for `target`
+pub fn monomorphize<'a>(items: &'a ItemTable<'a>, target: Target) -> Result<Monomorphized<'a>, Vec<Diagnostic>> {
+    let mut tc = TypeChecker::new(items, target);
+    Monomorphizer::new(&mut tc).run(roots(items))
+}
+
+/// What makes two instances the same: the function and its bindings
+fn identity(instance: &Instance) -> (usize, String) {
+    (instance.function as *const _ as usize, instance.name())
+}
+
+/// Whether `inner` occurs in `outer`
+fn contains(outer: &Type, inner: &Type) -> bool {
+    outer == inner
+        || match outer {
+            Type::Pointer { element_type, .. } | Type::Array { element_type, .. } => contains(element_type, inner),
+            Type::Optional(element) => contains(element, inner),
+            Type::Path(path) => path.generic_args.iter().any(|arg| contains(arg, inner)),
+            Type::ErrorUnion { ok_type, err_type } => {
+                contains(ok_type, inner) || err_type.generic_args.iter().any(|arg| contains(arg, inner))
+            }
+            _ => false,
+        }
+}
+
+/// Whether every binding of `to` contains the same binding of `from`, and
+/// at least one of them is larger
+fn grows(from: &Bindings, to: &Bindings) -> bool {
+    from.len() == to.len()
+        && from != to
+        && from.iter().zip(to).all(|((from_name, from), (to_name, to))| from_name == to_name && contains(to, from))
+}
+
+/// Whether `ty` mentions an instance of the same type as `from` whose
+/// arguments contain those of `from`, at least one strictly
+fn grows_in(from: &Path, ty: &Type) -> bool {
+    match ty {
+        Type::Pointer { element_type, .. } | Type::Array { element_type, .. } => grows_in(from, element_type),
+        Type::Optional(element) => grows_in(from, element),
+        Type::ErrorUnion { ok_type, err_type } => grows_in(from, ok_type) || grows_in(from, &Type::Path(err_type.clone())),
+        Type::Path(to) => {
+            let larger = to.segments == from.segments
+                && to.generic_args.len() == from.generic_args.len()
+                && to.generic_args != from.generic_args
+                && from.generic_args.iter().zip(&to.generic_args).all(|(from, to)| contains(to, from));
+            larger || to.generic_args.iter().any(|arg| grows_in(from, arg))
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    fn instances(src: &str) -> Result<(Vec<String>, Vec<String>), Vec<Diagnostic>> {
+        let sf = parse(src);
+        let items = ItemTable::from_source_file(&sf);
+        let mono = monomorphize(&items, Target::X86_64)?;
+        let functions = mono.functions.iter().map(|f| f.instance.name()).collect();
+        let types = mono.types.iter().map(format_type).collect();
+        Ok((functions, types))
+    }
+
+    #[test]
+    fn test_collects_instances_depth_first() {
+        let (functions, types) = instances(
+            "\
+struct Ring[T, const N: usize]
+    data: [T; N]
+    len: usize
+
+struct Pair[A, B]
+    first: A
+    second: B
+
+func[T, const N: usize] first(ring: *Ring[T, N]) -> T
+    return ring.data[0]
+
+func[T] largest(a: T, b: T) -> T
+    if a > b
+        return a
+    return b
+
+func[T] second(pair: *Pair[T, bool]) -> bool
+    return pair.second
+
+extern func! ring() -> *mut Ring[u8, 16]
+extern func! pair() -> *Pair[i64, bool]
+
+func! main() -> i32
+    let a = largest(first(ring()), 7u8)
+    let b = largest(1u8, 2u8)
+    let c = largest(3i64, 4i64)
+    let d = second(pair())
+    return 0
+",
+        )
+        .unwrap_or_else(|diags| panic!("{:?}", diags));
+        assert_eq!(functions, vec!["main", "ring", "first[u8, 16]", "largest[u8]", "largest[i64]", "pair", "second[i64]"]);
+        assert!(types.contains(&"Ring[u8, 16]".to_string()), "{:?}", types);
+        assert!(types.contains(&"Pair[i64, bool]".to_string()), "{:?}", types);
+        assert_eq!(types.len(), 2, "{:?}", types);
+    }
+
+    #[test]
+    fn test_reports_growing_recursion() {
+        let diagnostics = instances(
+            "\
+func[T] nest(x: T, n: i32) -> i32
+    if n == 0
+        return 0
+    return nest(&x, n - 1)
+
+func main() -> i32
+    let v: i32 = 1
+    return nest(v, 3)
+",
+        )
+        .unwrap_err();
+        assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+        assert_eq!(diagnostics[0].message, "`nest` needs infinitely many instances: `nest[i32]` leads to `nest[*i32]`");
+        assert_eq!(diagnostics[0].function.as_deref(), Some("nest[i32]"));
+        assert_eq!(diagnostics[0].notes[0], "instantiated through `nest[i32]` -> `nest[*i32]`");
+    }
+
+    #[test]
+    fn test_reports_deep_chains_at_any_limit() {
+        let src = "\
+func[T] a(x: T) -> T
+    return b(x)
+
+func[T] b(x: T) -> T
+    return c(x)
+
+func[T] c(x: T) -> T
+    return x
+
+func main() -> i32
+    return a(1)
+";
+        let sf = parse(src);
+        let items = ItemTable::from_source_file(&sf);
+        let deep = |limit| {
+            let mut tc = TypeChecker::new(&items, Target::X86_64);
+            Monomorphizer::new(&mut tc).with_limit(limit).run(roots(&items)).err().unwrap_or_default()
+        };
+        for limit in [0, 1] {
+            assert_eq!(deep(limit).len(), 1, "limit {}", limit);
+        }
+        // `main` -> `a` -> `b` -> `c` is three calls deep
+        let diagnostics = deep(2);
+        assert_eq!(diagnostics[0].message, "instantiating `c[i32]` nests more than 2 generic calls deep");
+        assert_eq!(diagnostics[0].notes, ["instantiated through `main` -> `a[i32]` -> `b[i32]`"]);
+        assert!(deep(3).is_empty());
+    }
+
+    #[test]
+    fn test_recursion_with_the_same_bindings_is_finite() {
+        let (functions, _) = instances(
+            "\
+func[T] count(x: T, n: i32) -> i32
+    if n == 0
+        return 0
+    return 1 + count(x, n - 1)
+
+func main() -> i32
+    return count(true, 3)
+",
+        )
+        .unwrap_or_else(|diags| panic!("{:?}", diags));
+        assert_eq!(functions, vec!["main", "count[bool]"]);
+    }
+
+    #[test]
+    fn test_reports_growing_types() {
+        let diagnostics = instances(
+            "\
+struct Node[T]
+    value: T
+    next: ?*Node[*T]
+
+func main() -> i32
+    let n: ?*Node[i32] = null
+    return 0
+",
+        )
+        .unwrap_err();
+        assert_eq!(diagnostics[0].message, "`Node[i32]` needs infinitely many instances: it contains `?*Node[*i32]`");
+    }
+}
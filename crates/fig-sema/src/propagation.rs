@@ -0,0 +1,367 @@
+//! Error-union propagation with `.!` and `callee!(args)`
+//!
+//! Both forms unwrap a `T ! E` operand: on success evaluation continues with
+//! the `T`, on failure the enclosing function returns early with the error.
+//! For `callee!(args)` the operand is the call's result; for `object.!field`
+//! and `object.!method(args)` it is `object`.
+//!
+//! The enclosing function must itself return some `U ! F`, and the operand's
+//! error `E` must convert into `F`. The conversions, tried in order, are:
+//!
+//! 1. identity, when `E` and `F` name the same type;
+//! 2. variant wrapping, when `F` is a union with a variant of type `E`;
+//! 3. a user conversion `func F::from(e: E) -> F`.
+//!
+//! Since the AST is untyped, the resolved edges are returned alongside it as a
+//! [`PropagationTable`]: for each function, the early-return edges in
+//! evaluation order.
+
+use serde::Serialize;
+
+use fig_parser::ast::*;
+use fig_parser::format::{format_expression, format_path, format_type};
+use fig_parser::visit::{self, Visit};
+
+use crate::diagnostics::Diagnostic;
+use crate::items::{FunctionDef, ItemTable, TypeDef};
+use crate::resolve::{BodyScope, Scoped, walk_scoped_block, walk_scoped_lambda, walk_scoped_statement};
+
+/// How an operand's error value becomes the enclosing function's error value
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum ErrorConversion {
+    /// `E` is the function's error type
+    Identity,
+    /// `E` is wrapped into variant `variant` of the union `F`
+    Variant { variant: String },
+    /// `E` is converted by calling `function`, e.g. `IoError::from`
+    Function { function: String },
+}
+
+/// One early-return edge out of a function body
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EarlyReturn {
+    /// Position among the function's propagation points, in evaluation order
+    /// (operands before the expressions that use them)
+    pub ordinal: usize,
+    /// The propagating expression, rendered back to source
+    pub site: String,
+    /// The `T ! E` being unwrapped, when its type could be inferred
+    pub operand: Option<Type>,
+    /// How `E` reaches the function's error type, when the edge is valid
+    pub conversion: Option<ErrorConversion>,
+}
+
+/// Early-return edges for every function that has any
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PropagationTable {
+    /// `(qualified function name, edges)` in declaration order
+    pub functions: Vec<(String, Vec<EarlyReturn>)>,
+}
+
+impl PropagationTable {
+    pub fn early_returns(&self, function: &str) -> &[EarlyReturn] {
+        self.functions
+            .iter()
+            .find(|(name, _)| name == function)
+            .map(|(_, edges)| edges.as_slice())
+            .unwrap_or(&[])
+    }
+}
+
+pub struct PropagationChecker<'t, 'a> {
+    items: &'t ItemTable<'a>,
+}
+
+/// Per-function state while walking a body
+struct BodyState<'c, 't, 'a> {
+    checker: &'c PropagationChecker<'t, 'a>,
+    scope: BodyScope<'t, 'a>,
+    name: String,
+    /// What a propagation returns from: the function, or a lambda in it
+    returns_from: String,
+    /// The error type `F` of what it returns from, or why there is none
+    return_error: Result<Path, String>,
+    edges: Vec<EarlyReturn>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'t, 'a> PropagationChecker<'t, 'a> {
+    pub fn new(items: &'t ItemTable<'a>) -> Self {
+        PropagationChecker { items }
+    }
+
+    pub fn check(&self) -> (PropagationTable, Vec<Diagnostic>) {
+        let mut table = PropagationTable::default();
+        let mut diagnostics = Vec::new();
+        for function in self.items.functions() {
+            let Some(body) = function.body else { continue };
+            let name = function.qualified_name();
+            let mut state = BodyState {
+                checker: self,
+                scope: BodyScope::new(self.items, function),
+                returns_from: format!("`{}`", name),
+                name,
+                return_error: return_error(&function.signature.return_types),
+                edges: Vec::new(),
+                diagnostics: Vec::new(),
+            };
+            state.visit_block(body);
+            diagnostics.append(&mut state.diagnostics);
+            if !state.edges.is_empty() {
+                table.functions.push((state.name, state.edges));
+            }
+        }
+        (table, diagnostics)
+    }
+
+    /// How error type `from` converts into `to`, if it does
+    pub fn conversion(&self, from: &Path, to: &Path) -> Option<ErrorConversion> {
+        if self.same_type(from, to) {
+            return Some(ErrorConversion::Identity);
+        }
+        if let Some(TypeDef::Union(u)) = self.items.lookup_type(to)
+            && let Some(variant) = u
+                .variants
+                .iter()
+                .find(|v| matches!(&v.ty, Type::Path(p) if self.same_type(p, from)))
+        {
+            return Some(ErrorConversion::Variant { variant: variant.name.clone() });
+        }
+        let target = self.items.lookup_type(to)?;
+        self.items
+            .methods_of(target.name())
+            .find(|f| is_conversion_from(f, from, |a, b| self.same_type(a, b)))
+            .map(|f| ErrorConversion::Function { function: f.qualified_name() })
+    }
+
+    fn same_type(&self, a: &Path, b: &Path) -> bool {
+        if a.generic_args != b.generic_args {
+            return false;
+        }
+        match (self.items.lookup_type(a), self.items.lookup_type(b)) {
+            (Some(x), Some(y)) => std::ptr::eq(x.name(), y.name()),
+            _ => a.segments == b.segments,
+        }
+    }
+}
+
+impl<'t, 'a> Scoped<'t, 'a> for BodyState<'_, 't, 'a> {
+    fn scope(&mut self) -> &mut BodyScope<'t, 'a> {
+        &mut self.scope
+    }
+}
+
+impl<'ast> Visit<'ast> for BodyState<'_, '_, '_> {
+    fn visit_block(&mut self, block: &'ast Block) {
+        walk_scoped_block(self, block);
+    }
+
+    fn visit_statement(&mut self, stmt: &'ast Statement) {
+        walk_scoped_statement(self, stmt);
+    }
+
+    fn visit_expression(&mut self, expr: &'ast Expression) {
+        match expr {
+            Expression::FieldAccess(fa) if fa.is_propagating => {
+                visit::walk_expression(self, expr);
+                let operand = self.scope.type_of(&fa.object);
+                self.propagate(expr, operand);
+            }
+            Expression::Call(call) if call.is_propagating => {
+                visit::walk_expression(self, expr);
+                let unwrapped = Expression::Call(CallExpr { is_propagating: false, ..call.clone() });
+                let operand = self.scope.type_of(&unwrapped);
+                self.propagate(expr, operand);
+            }
+            // The value is computed before the place it is stored in
+            Expression::Assign(assign) => {
+                self.visit_expression(&assign.rhs);
+                self.visit_expression(&assign.lhs);
+            }
+            // Propagation in the body returns from the lambda
+            Expression::Lambda(lambda) => {
+                let returns_from = format!("a lambda in `{}`", self.name);
+                let returns_from = std::mem::replace(&mut self.returns_from, returns_from);
+                let return_types: Vec<Type> = lambda.return_type.iter().map(|ty| (**ty).clone()).collect();
+                let return_error = std::mem::replace(&mut self.return_error, return_error(&return_types));
+                walk_scoped_lambda(self, expr);
+                self.returns_from = returns_from;
+                self.return_error = return_error;
+            }
+            _ => visit::walk_expression(self, expr),
+        }
+    }
+}
+
+impl BodyState<'_, '_, '_> {
+    /// Record the early-return edge at `site`, whose operand has type `operand`
+    fn propagate(&mut self, site: &Expression, operand: Option<Type>) {
+        let snippet = format_expression(site);
+        let mut conversion = None;
+        match (&operand, &self.return_error) {
+            (Some(Type::ErrorUnion { err_type, .. }), Ok(target)) => match self.checker.conversion(err_type, target) {
+                Some(c) => conversion = Some(c),
+                None => self.diagnostics.push(
+                    Diagnostic::error(format!(
+                        "error type `{}` cannot be propagated as `{}`",
+                        format_path(err_type),
+                        format_path(target)
+                    ))
+                    .in_function(&self.name)
+                    .at(self.scope.span())
+                    .with_snippet(&snippet)
+                    .with_note(format!(
+                        "make `{}` a union with a `{}` variant, or declare `func {}::from(e: {}) -> {}`",
+                        format_path(target),
+                        format_path(err_type),
+                        format_path(target),
+                        format_path(err_type),
+                        format_path(target)
+                    )),
+                ),
+            },
+            (Some(Type::ErrorUnion { .. }), Err(returns)) => self.diagnostics.push(
+                Diagnostic::error(format!("cannot propagate an error out of {}, which {}", self.returns_from, returns))
+                .in_function(&self.name)
+                .at(self.scope.span())
+                .with_snippet(&snippet)
+                .with_note("propagation returns early with the error, so the function must return `T ! E`"),
+            ),
+            (Some(other), _) => self.diagnostics.push(
+                Diagnostic::error(format!("`{}` is not an error union", format_type(other)))
+                    .in_function(&self.name)
+                    .at(self.scope.span())
+                    .with_snippet(&snippet)
+                    .with_note("`.!` and `!()` only unwrap values of type `T ! E`"),
+            ),
+            // Unknown operand type: record the edge, nothing to check
+            (None, _) => {}
+        }
+        self.edges.push(EarlyReturn { ordinal: self.edges.len(), site: snippet, operand, conversion });
+    }
+}
+
+/// The error type `F` of a function or lambda returning `return_types`, or why
+/// there is none
+fn return_error(return_types: &[Type]) -> Result<Path, String> {
+    match return_types {
+        [Type::ErrorUnion { err_type, .. }] => Ok(err_type.clone()),
+        [] => Err("returns nothing".to_string()),
+        [ty] => Err(format!("returns `{}`", format_type(ty))),
+        _ => Err("returns several values".to_string()),
+    }
+}
+
+/// Whether `f` has the shape `func F::from(e: E) -> F` for `E` = `from`
+fn is_conversion_from(f: &FunctionDef, from: &Path, same: impl Fn(&Path, &Path) -> bool) -> bool {
+    let sig = f.signature;
+    sig.name == "from"
+        && sig.self_param.is_none()
+        && sig.return_types.len() == 1
+        && matches!(sig.params.as_slice(), [p] if matches!(&p.ty, Type::Path(ty) if same(ty, from)))
+}
+
+/// Check every `.!` and `!()` in `items`
+pub fn check_propagation(items: &ItemTable) -> (PropagationTable, Vec<Diagnostic>) {
+    PropagationChecker::new(items).check()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    const ERRORS: &str = "\
+struct IoError
+    code: i32
+
+struct ParseError
+    line: u32
+
+union AppError
+    io: IoError
+    parse: ParseError
+
+struct Config
+    size: u32
+
+func read(path: *u8) -> *u8 ! IoError
+    pass
+
+func parse_config(text: *u8) -> Config ! ParseError
+    pass
+
+";
+
+    fn check(src: &str) -> (PropagationTable, Vec<Diagnostic>) {
+        let src = format!("{}{}", ERRORS, src);
+        let sf = parse(&src);
+        let items = ItemTable::from_source_file(&sf);
+        check_propagation(&items)
+    }
+
+    #[test]
+    fn test_identity_and_variant_conversions() {
+        let (table, diags) = check(
+            "func load(path: *u8) -> Config ! AppError\n    let text = read!(path)\n    return parse_config!(text)\n\nfunc fetch(path: *u8) -> *u8 ! IoError\n    return read!(path)\n",
+        );
+        assert!(diags.is_empty(), "{:?}", diags);
+        let edges = table.early_returns("load");
+        assert_eq!(edges.len(), 2);
+        assert_eq!(edges[0].site, "read!(path)");
+        assert_eq!(edges[0].conversion, Some(ErrorConversion::Variant { variant: "io".into() }));
+        assert_eq!(edges[1].conversion, Some(ErrorConversion::Variant { variant: "parse".into() }));
+        assert_eq!(table.early_returns("fetch")[0].conversion, Some(ErrorConversion::Identity));
+    }
+
+    #[test]
+    fn test_conversion_function() {
+        let (table, diags) = check(
+            "func ParseError::from(e: IoError) -> ParseError\n    pass\n\nfunc load(path: *u8) -> Config ! ParseError\n    let text = read!(path)\n    return parse_config!(text)\n",
+        );
+        assert!(diags.is_empty(), "{:?}", diags);
+        assert_eq!(
+            table.early_returns("load")[0].conversion,
+            Some(ErrorConversion::Function { function: "ParseError::from".into() })
+        );
+    }
+
+    #[test]
+    fn test_field_propagation_unwraps_the_object() {
+        let (table, diags) = check("func size(text: *u8) -> u32 ! ParseError\n    return parse_config(text).!size\n");
+        assert!(diags.is_empty(), "{:?}", diags);
+        let edge = &table.early_returns("size")[0];
+        assert_eq!(edge.site, "parse_config(text).!size");
+        assert!(matches!(edge.operand, Some(Type::ErrorUnion { .. })));
+    }
+
+    #[test]
+    fn test_rejects_invalid_propagation() {
+        let (_, diags) = check(
+            "func a(path: *u8) -> Config ! ParseError\n    let text = read!(path)\n    return parse_config!(text)\n\nfunc b(path: *u8) -> *u8\n    return read!(path)\n\nfunc c(cfg: Config) -> u32 ! IoError\n    return cfg.!size\n",
+        );
+        let messages: Vec<_> = diags.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "error type `IoError` cannot be propagated as `ParseError`",
+                "cannot propagate an error out of `b`, which returns `*u8`",
+                "`Config` is not an error union",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lambda_bodies_propagate_into_the_lambda() {
+        let (table, diags) = check(
+            "func load(path: *u8) -> Config ! ParseError\n    let f = fn(p: *u8) -> *u8 ! AppError => read!(p)\n    let g = fn(p: *u8) -> *u8 => read!(p)\n    return parse_config!(path)\n",
+        );
+        let messages: Vec<_> = diags.iter().map(|d| d.message.as_str()).collect();
+        assert_eq!(messages, vec!["cannot propagate an error out of a lambda in `load`, which returns `*u8`"]);
+        let edges = table.early_returns("load");
+        assert_eq!(edges.len(), 3);
+        assert_eq!(edges[0].conversion, Some(ErrorConversion::Variant { variant: "io".into() }));
+        assert_eq!(edges[1].conversion, None);
+        assert_eq!(edges[2].conversion, Some(ErrorConversion::Identity));
+    }
+}
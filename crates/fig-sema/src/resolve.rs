@@ -0,0 +1,619 @@
+//! Name and type resolution inside function bodies
+//!
+//! Fig has no full type checker yet, so this is best-effort: a [`BodyScope`]
+//! tracks the parameters and locals visible at a point in a function body and
+//! infers an expression's type from declared types, struct fields and callee
+//! return types. Anything it cannot see through yields `None`, and passes
+//! built on it must treat `None` as "unknown" rather than as an error.
+//!
+//! Calls are resolved to their callee declarations. Methods called on a
+//! generic parameter resolve through the parameter's interface bounds, and
+//! interface methods are searched through `extends` as well.
+
+use std::collections::HashMap;
+
+use fig_lexer::IntegerSuffix;
+use fig_parser::ast::*;
+use fig_parser::visit::{self, Visit};
+
+use crate::items::{FunctionDef, ItemTable, TypeDef};
+
+/// Where a local name came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BindingKind {
+    Param,
+    Let,
+    Mut,
+    /// The pattern variable of a `for` loop
+    Loop,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Binding {
+    pub kind: BindingKind,
+    /// Declared or inferred type, if known
+    pub ty: Option<Type>,
+}
+
+/// A function a call may dispatch to
+#[derive(Debug, Clone, Copy)]
+pub enum Callee<'t, 'a> {
+    /// A free function or a method with a body or declaration in the item table
+    Function(&'t FunctionDef<'a>),
+    /// A method declared by an interface, reached through a generic bound or
+    /// an interface-typed receiver
+    InterfaceMethod { interface: &'a Interface, signature: &'a FunctionSignature },
+}
+
+impl<'a> Callee<'_, 'a> {
+    pub fn signature(&self) -> &'a FunctionSignature {
+        match self {
+            Callee::Function(f) => f.signature,
+            Callee::InterfaceMethod { signature, .. } => signature,
+        }
+    }
+
+    /// Whether calling this may have side effects: `func!` or `extern`
+    pub fn is_effectful(&self) -> bool {
+        let sig = self.signature();
+        sig.is_effect || sig.is_extern
+    }
+
+    /// Qualified display name, e.g. `Vec::push` or `Iterator::next`
+    pub fn name(&self) -> String {
+        match self {
+            Callee::Function(f) => f.qualified_name(),
+            Callee::InterfaceMethod { interface, signature } => format!("{}::{}", interface.name, signature.name),
+        }
+    }
+}
+
+/// Remove any number of pointer layers, e.g. `*mut *Node` → `Node`
+pub fn strip_pointers(ty: &Type) -> &Type {
+    match ty {
+        Type::Pointer { element_type, .. } => strip_pointers(element_type),
+        other => other,
+    }
+}
+
+/// Whether values of this type refer to memory they do not own: pointers and
+/// slices `[T]`
+pub fn is_indirect(ty: &Type) -> bool {
+    matches!(ty, Type::Pointer { .. } | Type::Array { size: None, .. })
+}
+
+/// Replace generic parameter names in `ty` by the corresponding arguments
+pub fn substitute(ty: &Type, params: &[GenericParameter], args: &[Type]) -> Type {
+    if params.is_empty() || args.is_empty() {
+        return ty.clone();
+    }
+    let names: Vec<&str> = params.iter().map(GenericParameter::name).collect();
+    substitute_names(ty, &names, args)
+}
+
+fn substitute_names(ty: &Type, names: &[&str], args: &[Type]) -> Type {
+    match ty {
+        Type::Path(path) if path.segments.len() == 1 && path.generic_args.is_empty() => {
+            match names.iter().position(|n| *n == path.segments[0]) {
+                Some(i) if i < args.len() => args[i].clone(),
+                _ => ty.clone(),
+            }
+        }
+        Type::Path(path) => Type::Path(Path {
+            segments: path.segments.clone(),
+            generic_args: path.generic_args.iter().map(|t| substitute_names(t, names, args)).collect(),
+        }),
+        Type::Pointer { nullable, mutable, element_type } => Type::Pointer {
+            nullable: *nullable,
+            mutable: *mutable,
+            element_type: Box::new(substitute_names(element_type, names, args)),
+        },
+        Type::Optional(inner) => Type::Optional(Box::new(substitute_names(inner, names, args))),
+        Type::Array { element_type, size } => Type::Array {
+            element_type: Box::new(substitute_names(element_type, names, args)),
+            size: size.clone(),
+        },
+        Type::ErrorUnion { ok_type, err_type } => Type::ErrorUnion {
+            ok_type: Box::new(substitute_names(ok_type, names, args)),
+            err_type: err_type.clone(),
+        },
+        Type::Function(function) => Type::Function(FunctionType {
+            is_effect: function.is_effect,
+            params: function.params.iter().map(|t| substitute_names(t, names, args)).collect(),
+            return_type: function.return_type.as_ref().map(|t| Box::new(substitute_names(t, names, args))),
+        }),
+        other => other.clone(),
+    }
+}
+
+/// Look up a method on an interface or any interface it extends
+pub fn interface_method<'a>(
+    items: &ItemTable<'a>,
+    interface: &'a Interface,
+    name: &str,
+) -> Option<(&'a Interface, &'a FunctionSignature)> {
+    let mut stack = vec![interface];
+    let mut seen: Vec<&str> = Vec::new();
+    while let Some(iface) = stack.pop() {
+        if seen.contains(&iface.name.as_str()) {
+            continue;
+        }
+        seen.push(&iface.name);
+        if let Some(sig) = iface.methods.iter().find(|m| m.name == name) {
+            return Some((iface, sig));
+        }
+        for parent in &iface.extends {
+            if let Type::Path(path) = parent
+                && let Some(TypeDef::Interface(p)) = items.lookup_type(path)
+            {
+                stack.push(p);
+            }
+        }
+    }
+    None
+}
+
+/// The lexical environment at one point in a function body
+pub struct BodyScope<'t, 'a> {
+    items: &'t ItemTable<'a>,
+    function: &'t FunctionDef<'a>,
+    scopes: Vec<HashMap<String, Binding>>,
+    /// The statement being walked, when the body has statement spans
+    span: Option<Span>,
+}
+
+impl<'t, 'a> BodyScope<'t, 'a> {
+    /// A scope for the top of `function`'s body, with its parameters bound
+    pub fn new(items: &'t ItemTable<'a>, function: &'t FunctionDef<'a>) -> Self {
+        let mut scope = BodyScope { items, function, scopes: vec![HashMap::new()], span: None };
+        for param in &function.signature.params {
+            scope.bind(&param.name, Binding { kind: BindingKind::Param, ty: Some(param.ty.clone()) });
+        }
+        scope
+    }
+
+    pub fn items(&self) -> &'t ItemTable<'a> {
+        self.items
+    }
+
+    pub fn function(&self) -> &'t FunctionDef<'a> {
+        self.function
+    }
+
+    /// The span of the innermost statement being walked, for diagnostics
+    pub fn span(&self) -> Option<Span> {
+        self.span
+    }
+
+    pub fn push(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    pub fn pop(&mut self) {
+        self.scopes.pop();
+    }
+
+    pub fn bind(&mut self, name: &str, binding: Binding) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), binding);
+        }
+    }
+
+    /// Bind the name introduced by a `let` or `mut` statement, if any.
+    /// Call this after visiting the initializer.
+    pub fn bind_statement(&mut self, stmt: &Statement) {
+        let (name, kind, declared, value) = match stmt {
+            Statement::Let(l) => (&l.name, BindingKind::Let, &l.ty, &l.value),
+            Statement::Mut(m) => (&m.name, BindingKind::Mut, &m.ty, &m.value),
+            _ => return,
+        };
+        let ty = declared.clone().or_else(|| self.type_of(value));
+        self.bind(name, Binding { kind, ty });
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<&Binding> {
+        self.scopes.iter().rev().find_map(|s| s.get(name))
+    }
+
+    /// The receiver type of the enclosing method, e.g. `Vec[T]` for `func Vec[T]::push`
+    pub fn receiver_type(&self) -> Option<Type> {
+        self.function.signature.receiver.clone().map(Type::Path)
+    }
+
+    /// The type of `self`, including the pointer for `*self` / `*mut self`
+    pub fn self_type(&self) -> Option<Type> {
+        let receiver = self.receiver_type()?;
+        match &self.function.signature.self_param {
+            Some(sp) if sp.is_pointer => Some(Type::Pointer {
+                nullable: false,
+                mutable: sp.is_mutable,
+                element_type: Box::new(receiver),
+            }),
+            _ => Some(receiver),
+        }
+    }
+
+    /// Interface bounds on a generic parameter visible in this function: the
+    /// function's own parameters, where-clause constraints on the receiver's
+    /// generic arguments, then the receiver type's own parameters
+    pub fn bounds_of(&self, name: &str) -> Option<Vec<&'a Type>> {
+        let signature: &'a FunctionSignature = self.function.signature;
+        let receiver_params = signature
+            .receiver
+            .as_ref()
+            .and_then(|r| self.items.lookup_type(r))
+            .map(|def| def.generic_params())
+            .unwrap_or(&[]);
+        let mut found = false;
+        let mut bounds = Vec::new();
+        for param in signature.generic_params.iter().chain(&signature.unbound_constraints).chain(receiver_params) {
+            if let GenericParameter::Type { name: n, bounds: b, .. } = param
+                && n == name
+            {
+                found = true;
+                bounds.extend(b);
+            }
+        }
+        found.then_some(bounds)
+    }
+
+    /// Best-effort type of an expression
+    pub fn type_of(&self, expr: &Expression) -> Option<Type> {
+        match expr {
+            Expression::IntegerLiteral(lit) => Some(match lit.suffix().cloned().unwrap_or_default() {
+                IntegerSuffix::U8 => Type::U8,
+                IntegerSuffix::U16 => Type::U16,
+                IntegerSuffix::U32 => Type::U32,
+                IntegerSuffix::U64 => Type::U64,
+                IntegerSuffix::I8 => Type::I8,
+                IntegerSuffix::I16 => Type::I16,
+                IntegerSuffix::I32 => Type::I32,
+                IntegerSuffix::I64 => Type::I64,
+                _ => return None,
+            }),
+            Expression::BooleanLiteral(_) => Some(Type::Bool),
+            Expression::OkLiteral => Some(Type::Ok),
+            Expression::NullLiteral => Some(Type::Null),
+            Expression::SelfValue => self.self_type(),
+            Expression::Path(path) => self.path_type(path),
+            Expression::FieldAccess(fa) => self.field_type(&self.object_type(fa)?, &fa.field),
+            Expression::Call(call) => {
+                let ret = self.call_return_type(call)?;
+                if call.is_propagating { propagated(ret) } else { Some(ret) }
+            }
+            Expression::Index(idx) => match strip_pointers(&self.type_of(&idx.object)?) {
+                Type::Array { element_type, .. } if matches!(*idx.index, Expression::Range(_)) => {
+                    Some(Type::Array { element_type: element_type.clone(), size: None })
+                }
+                Type::Array { element_type, .. } => Some((**element_type).clone()),
+                _ => None,
+            },
+            Expression::UnaryOp(op) => match op.op {
+                UnaryOperator::LogicalNot => Some(Type::Bool),
+                UnaryOperator::AddressOf => Some(Type::Pointer {
+                    nullable: false,
+                    mutable: false,
+                    element_type: Box::new(self.type_of(&op.operand)?),
+                }),
+                UnaryOperator::Dereference => match self.type_of(&op.operand)? {
+                    Type::Pointer { element_type, .. } => Some(*element_type),
+                    _ => None,
+                },
+                _ => self.type_of(&op.operand),
+            },
+            Expression::BinaryOp(op) => match op.op {
+                BinaryOperator::Equal
+                | BinaryOperator::NotEqual
+                | BinaryOperator::LessThan
+                | BinaryOperator::GreaterThan
+                | BinaryOperator::LessThanOrEqual
+                | BinaryOperator::GreaterThanOrEqual
+                | BinaryOperator::LogicalAnd
+                | BinaryOperator::LogicalOr => Some(Type::Bool),
+                _ => self.type_of(&op.lhs).or_else(|| self.type_of(&op.rhs)),
+            },
+            Expression::Cast(cast) => Some((*cast.target_type).clone()),
+            Expression::Sizeof(_) | Expression::Alignof(_) | Expression::Offsetof(_) => Some(Type::USize),
+            Expression::Parenthesized(inner) => self.type_of(inner),
+            Expression::TypeAccess(ta) => self.type_of(&ta.object),
+            Expression::StructLiteral(lit) => Some(Type::Path(lit.ty.clone())),
+            Expression::VariantLiteral(lit) => Some(Type::Path(lit.ty.clone())),
+            Expression::EnumVariant(value) => Some(Type::Path(value.ty.clone())),
+            Expression::Lambda(lambda) => Some(Type::Function(FunctionType {
+                is_effect: lambda.is_effect,
+                params: lambda.params.iter().map(|p| p.ty.clone()).collect(),
+                return_type: lambda.return_type.clone(),
+            })),
+            _ => None,
+        }
+    }
+
+    fn path_type(&self, path: &Path) -> Option<Type> {
+        if path.segments.len() == 1
+            && let Some(binding) = self.lookup(&path.segments[0])
+        {
+            return binding.ty.clone();
+        }
+        if let Some(c) = self.items.lookup_const(path) {
+            return c.ty.clone();
+        }
+        // `Color::Red` has the enum's type
+        if path.segments.len() > 1 {
+            let owner = Path { segments: path.segments[..path.segments.len() - 1].to_vec(), generic_args: vec![] };
+            if let Some(TypeDef::Enum(_)) = self.items.lookup_type(&owner) {
+                return Some(Type::Path(owner));
+            }
+        }
+        None
+    }
+
+    /// The type of `field` on a value of type `ty`, looking through pointers
+    pub fn field_type(&self, ty: &Type, field: &str) -> Option<Type> {
+        let Type::Path(path) = strip_pointers(ty) else { return None };
+        match self.items.lookup_type(path)? {
+            TypeDef::Struct(s) => s
+                .fields
+                .iter()
+                .find(|f| f.name == field)
+                .map(|f| substitute(&f.ty, &s.generic_params, &path.generic_args)),
+            TypeDef::Union(u) => u
+                .variants
+                .iter()
+                .find(|v| v.name == field)
+                .map(|v| substitute(&v.ty, &u.generic_params, &path.generic_args)),
+            _ => None,
+        }
+    }
+
+    fn call_return_type(&self, call: &CallExpr) -> Option<Type> {
+        if let Some(Type::Function(function)) = self.function_value(call) {
+            return function.return_type.map(|ty| *ty);
+        }
+        let callees = self.resolve_call(call);
+        let [callee] = callees.as_slice() else { return None };
+        match callee.signature().return_types.as_slice() {
+            [ty] => Some(ty.clone()),
+            _ => None,
+        }
+    }
+
+    /// The type of the value a call goes through, when its callee is a
+    /// local rather than a declaration
+    pub fn function_value(&self, call: &CallExpr) -> Option<Type> {
+        match call.callee.as_ref() {
+            Expression::Path(path) if path.segments.len() == 1 => self.lookup(&path.segments[0])?.ty.clone(),
+            Expression::Path(_) | Expression::TypeAccess(_) | Expression::FieldAccess(_) => None,
+            callee => self.type_of(callee),
+        }
+    }
+
+    /// The declarations a call may dispatch to. Empty when the callee is
+    /// unknown; more than one when the receiver's type could not be inferred
+    /// and several methods share the name.
+    pub fn resolve_call(&self, call: &CallExpr) -> Vec<Callee<'t, 'a>> {
+        match call.callee.as_ref() {
+            Expression::Path(path) => {
+                // Calls through a local name (a function-typed value) are not
+                // resolved to a declaration
+                if path.segments.len() == 1 && self.lookup(&path.segments[0]).is_some() {
+                    return Vec::new();
+                }
+                self.items.lookup_function(path).map(Callee::Function).into_iter().collect()
+            }
+            Expression::TypeAccess(ta) => match expression_path(&ta.object) {
+                Some(mut path) => {
+                    path.segments.push(ta.member.clone());
+                    self.items.lookup_function(&path).map(Callee::Function).into_iter().collect()
+                }
+                None => Vec::new(),
+            },
+            Expression::FieldAccess(fa) => self.resolve_method(self.object_type(fa), &fa.field),
+            _ => Vec::new(),
+        }
+    }
+
+    /// The type `object.field` looks the field up in: the object's own type,
+    /// or its success type `T` for `object.!field` on a `T ! E`
+    pub fn object_type(&self, fa: &FieldAccessExpr) -> Option<Type> {
+        let ty = self.type_of(&fa.object)?;
+        if fa.is_propagating { propagated(ty) } else { Some(ty) }
+    }
+
+    /// Resolve a `name(...)` method call on a receiver of type `receiver`
+    pub fn resolve_method(&self, receiver: Option<Type>, name: &str) -> Vec<Callee<'t, 'a>> {
+        let Some(ty) = receiver else { return self.methods_named(name) };
+        let Type::Path(path) = strip_pointers(&ty) else { return Vec::new() };
+
+        // A generic parameter: dispatch through its interface bounds
+        if path.segments.len() == 1
+            && let Some(bounds) = self.bounds_of(&path.segments[0])
+        {
+            return bounds
+                .iter()
+                .filter_map(|bound| match bound {
+                    Type::Path(b) => match self.items.lookup_type(b) {
+                        Some(TypeDef::Interface(iface)) => interface_method(self.items, iface, name),
+                        _ => None,
+                    },
+                    _ => None,
+                })
+                .map(|(interface, signature)| Callee::InterfaceMethod { interface, signature })
+                .take(1)
+                .collect();
+        }
+
+        match self.items.lookup_type(path) {
+            Some(TypeDef::Interface(iface)) => interface_method(self.items, iface, name)
+                .map(|(interface, signature)| Callee::InterfaceMethod { interface, signature })
+                .into_iter()
+                .collect(),
+            Some(def) => self
+                .items
+                .methods_of(def.name())
+                .filter(|f| f.signature.name == name)
+                .take(1)
+                .map(Callee::Function)
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Every method or interface method called `name`, for receivers of unknown type
+    fn methods_named(&self, name: &str) -> Vec<Callee<'t, 'a>> {
+        let mut callees: Vec<Callee<'t, 'a>> = self
+            .items
+            .functions()
+            .iter()
+            .filter(|f| f.signature.receiver.is_some() && f.signature.name == name)
+            .map(Callee::Function)
+            .collect();
+        for (_, def) in self.items.types() {
+            if let TypeDef::Interface(iface) = def
+                && let Some(signature) = iface.methods.iter().find(|m| m.name == name)
+            {
+                callees.push(Callee::InterfaceMethod { interface: iface, signature });
+            }
+        }
+        callees
+    }
+}
+
+/// A [`Visit`] over a function body that keeps a [`BodyScope`] in step with
+/// the walk. Its `visit_block` and `visit_statement` call [`walk_scoped_block`]
+/// and [`walk_scoped_statement`], and it walks lambdas with
+/// [`walk_scoped_lambda`].
+pub trait Scoped<'t, 'a> {
+    fn scope(&mut self) -> &mut BodyScope<'t, 'a>;
+}
+
+/// Walk `block` in a scope of its own, keeping [`BodyScope::span`] on the
+/// statement being walked
+pub fn walk_scoped_block<'ast, 't, 'a, V>(visitor: &mut V, block: &'ast Block)
+where
+    'a: 't,
+    V: Visit<'ast> + Scoped<'t, 'a> + ?Sized,
+{
+    let outer = visitor.scope().span;
+    visitor.scope().push();
+    for (index, stmt) in block.statements.iter().enumerate() {
+        visitor.scope().span = block.span(index).or(outer);
+        visitor.visit_statement(stmt);
+    }
+    visitor.scope().pop();
+    visitor.scope().span = outer;
+}
+
+/// Walk `stmt`, binding what it declares for the statements after it. The
+/// body of a function declared inside another is not part of the outer body
+/// and is not walked.
+pub fn walk_scoped_statement<'ast, 't, 'a, V>(visitor: &mut V, stmt: &'ast Statement)
+where
+    'a: 't,
+    V: Visit<'ast> + Scoped<'t, 'a> + ?Sized,
+{
+    match stmt {
+        Statement::Let(_) | Statement::Mut(_) => {
+            visit::walk_statement(visitor, stmt);
+            visitor.scope().bind_statement(stmt);
+        }
+        Statement::For(s) => {
+            visitor.visit_expression(&s.iterable);
+            visitor.scope().push();
+            visitor.scope().bind(&s.pattern, Binding { kind: BindingKind::Loop, ty: None });
+            visitor.visit_block(&s.body);
+            visitor.scope().pop();
+        }
+        Statement::Function(_) => {}
+        _ => visit::walk_statement(visitor, stmt),
+    }
+}
+
+/// Walk the lambda `expr` with its parameters bound
+pub fn walk_scoped_lambda<'ast, 't, 'a, V>(visitor: &mut V, expr: &'ast Expression)
+where
+    'a: 't,
+    V: Visit<'ast> + Scoped<'t, 'a> + ?Sized,
+{
+    let Expression::Lambda(lambda) = expr else { return visit::walk_expression(visitor, expr) };
+    visitor.scope().push();
+    for param in &lambda.params {
+        visitor.scope().bind(&param.name, Binding { kind: BindingKind::Param, ty: Some(param.ty.clone()) });
+    }
+    visit::walk_expression(visitor, expr);
+    visitor.scope().pop();
+}
+
+/// The success type after `.!` / `!()` propagation, e.g. `T` for `T ! E`
+pub fn propagated(ty: Type) -> Option<Type> {
+    match ty {
+        Type::ErrorUnion { ok_type, .. } => Some(*ok_type),
+        _ => None,
+    }
+}
+
+/// Read a chain of `a::b::c` accesses back into a path
+pub fn expression_path(expr: &Expression) -> Option<Path> {
+    match expr {
+        Expression::Path(path) => Some(path.clone()),
+        Expression::TypeAccess(ta) => {
+            let mut path = expression_path(&ta.object)?;
+            path.segments.push(ta.member.clone());
+            Some(path)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    const SRC: &str = "\
+interface Counter
+    func! bump(*mut self) -> u32
+
+struct Pair[T]
+    first: T
+    second: *mut u32
+
+func! Pair::swap(*mut self)
+    pass
+
+func[C: Counter] tick(c: *mut C, p: Pair[u8]) -> u32
+    pass
+";
+
+    fn expr(src: &str) -> Expression {
+        fig_parser::ExpressionParser::new().parse(fig_parser::Lexer::new(src)).unwrap()
+    }
+
+    #[test]
+    fn test_type_of_fields_and_params() {
+        let sf = parse(SRC);
+        let items = ItemTable::from_source_file(&sf);
+        let tick = items.lookup_function(&Path::simple("tick".into())).unwrap();
+        let scope = BodyScope::new(&items, tick);
+        assert_eq!(scope.type_of(&expr("p.first")), Some(Type::U8));
+        assert_eq!(scope.type_of(&expr("*(p.second)")), Some(Type::U32));
+        assert_eq!(scope.type_of(&expr("!(p.first)")), Some(Type::Bool));
+        assert_eq!(scope.type_of(&expr("unknown.x")), None);
+    }
+
+    #[test]
+    fn test_resolves_methods_through_bounds() {
+        let sf = parse(SRC);
+        let items = ItemTable::from_source_file(&sf);
+        let tick = items.lookup_function(&Path::simple("tick".into())).unwrap();
+        let scope = BodyScope::new(&items, tick);
+
+        let Expression::Call(call) = expr("c.bump()") else { panic!("expected call") };
+        let callees = scope.resolve_call(&call);
+        assert_eq!(callees.len(), 1);
+        assert_eq!(callees[0].name(), "Counter::bump");
+        assert!(callees[0].is_effectful());
+
+        let Expression::Call(call) = expr("p.swap()") else { panic!("expected call") };
+        assert_eq!(scope.resolve_call(&call)[0].name(), "Pair::swap");
+    }
+}
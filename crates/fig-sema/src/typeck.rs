@@ -0,0 +1,2308 @@
+//! Type checking of function bodies
+//!
+//! Unlike the best-effort [`BodyScope`](crate::resolve::BodyScope), the
+//! [`TypeChecker`] gives every expression a concrete type or reports why it
+//! cannot. It checks one [`Instance`] of a function at a time: the function
+//! with each of its generic parameters bound to a concrete type, so no type
+//! parameter survives into the results. A generic function is only checked
+//! through the instances that are actually called; backends start from the
+//! non-generic [`roots`] and follow the calls recorded in each [`TypedBody`].
+//!
+//! Types in the results are normalised: aliases are expanded, named types
+//! are fully qualified and array sizes are evaluated to integer literals, so
+//! two types are the same exactly when they compare equal.
+//!
+//! Checking is bidirectional. An expected type flows into literals, `null`,
+//! array literals and the type arguments of generic calls. Without one, an
+//! unsuffixed integer literal is an `i32` and an unsuffixed float an `f64`,
+//! the lexer's defaults. Where an expected type is known, a value also
+//! converts implicitly:
+//!
+//! - an integer into a wider integer type that holds all of its values;
+//! - `null` into any `?T` or `?*T`;
+//! - `T` into `?T`, and `*T` into `?*T`;
+//! - `T` into `T ! E`, and `E` (or a variant type of the union `E`) into `T ! E`;
+//! - an array `[T; N]` into the slice `[T]`.
+//!
+//! A lambda has the function type its parameters and return type spell,
+//! and a value of function type can be called like a function. `fn` and
+//! `fn!` types never convert into each other.
+//!
+//! Pointer mutability is not checked here; that is the effect checker's job.
+//!
+//! Results are keyed by the address of the expression or statement they
+//! describe, so they only apply to the AST they were computed from.
+
+use std::borrow::Borrow;
+use std::collections::HashMap;
+
+use fig_lexer::{FloatSuffix, IntegerLiteral, IntegerSuffix};
+use fig_parser::ast::*;
+use fig_parser::format::{format_expression, format_path, format_type};
+
+use crate::conformance::ConformanceChecker;
+use crate::diagnostics::Diagnostic;
+use crate::items::{FunctionDef, ItemTable, TypeDef};
+use crate::layout::{LayoutEngine, Target, int_literal, integer_bounds};
+use crate::propagation::{ErrorConversion, PropagationChecker};
+
+/// Generic parameter names bound to concrete types. A const parameter is
+/// bound to a [`Type::Const`] holding an integer literal.
+pub type Bindings = Vec<(String, Type)>;
+
+/// A function with concrete types for its generic parameters
+#[derive(Debug, Clone)]
+pub struct Instance<'a> {
+    pub function: &'a FunctionDef<'a>,
+    /// One entry per name in [`generic_names`], plus `Self` for a default
+    /// method of an interface. A method of a concrete type binds `Self`
+    /// implicitly to its receiver.
+    pub bindings: Bindings,
+}
+
+impl<'a> Instance<'a> {
+    /// The instance of a function that has no generic parameters
+    pub fn new(function: &'a FunctionDef<'a>) -> Self {
+        Instance { function, bindings: Vec::new() }
+    }
+
+    /// Display name, e.g. `Vec::push[i32]`
+    pub fn name(&self) -> String {
+        let name = self.function.qualified_name();
+        if self.bindings.is_empty() {
+            return name;
+        }
+        let args: Vec<String> = self.bindings.iter().map(|(_, ty)| format_type(ty)).collect();
+        format!("{}[{}]", name, args.join(", "))
+    }
+}
+
+impl PartialEq for Instance<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self.function, other.function) && self.bindings == other.bindings
+    }
+}
+
+/// Functions the host provides when no declaration of the name exists
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Builtin {
+    Print,
+    Println,
+    Assert,
+    Malloc,
+    Calloc,
+    Realloc,
+    Free,
+}
+
+impl Builtin {
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "print" => Builtin::Print,
+            "println" => Builtin::Println,
+            "assert" => Builtin::Assert,
+            "malloc" => Builtin::Malloc,
+            "calloc" => Builtin::Calloc,
+            "realloc" => Builtin::Realloc,
+            "free" => Builtin::Free,
+            _ => return None,
+        })
+    }
+
+    /// Whether calling the builtin is a side effect. Printing writes to the
+    /// host and allocation hands out memory; `assert` only traps.
+    pub fn is_effectful(self) -> bool {
+        !matches!(self, Builtin::Assert)
+    }
+}
+
+/// How the receiver of a method call becomes its `self` argument
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelfArg {
+    /// A value passed to a by-value `self`
+    Value,
+    /// A value whose address is passed to `*self`
+    AddressOf,
+    /// A pointer passed to `*self` as is
+    Pointer,
+    /// A pointer dereferenced for a by-value `self`
+    Deref,
+}
+
+/// What a call expression invokes
+#[derive(Debug, Clone, PartialEq)]
+pub enum CallTarget<'a> {
+    /// A function or method; `self_arg` is set for calls through `.`
+    Function { instance: Instance<'a>, self_arg: Option<SelfArg> },
+    /// Positional construction of a struct, e.g. `Point(1, 2)`
+    Construct(Type),
+    /// Construction of a union variant, e.g. `Shape::Circle(r)`
+    Variant { union: Type, variant: String },
+    Builtin(Builtin),
+    /// A call through a value of function type, such as a lambda bound to a
+    /// local or passed as an argument
+    Value(FunctionType),
+}
+
+/// What a path expression names
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathTarget<'a> {
+    /// A parameter or local variable
+    Local,
+    /// A `const` item; its value is checked as part of the body that uses it
+    Const(&'a ConstStatement),
+    /// A variant of a C-like enum
+    EnumVariant { discriminant: i128 },
+    /// A union variant of type `ok`, which carries no payload
+    UnionVariant { variant: String },
+}
+
+/// How a `for` loop walks its iterable
+#[derive(Debug, Clone, PartialEq)]
+pub enum Iteration<'a> {
+    /// Over the elements of an array `[T; N]` or a slice `[T]`
+    Elements,
+    /// Calling `next(*mut self) -> ?T` until it returns `null`
+    Iterator { next: Instance<'a>, self_arg: SelfArg },
+    /// Counting up through a range of integers, `start..end` or
+    /// `start..=end`. Without an end it counts until the type overflows.
+    Range,
+}
+
+/// The checked types of one function instance
+#[derive(Debug, Default)]
+pub struct TypedBody<'a> {
+    types: HashMap<usize, Type>,
+    calls: HashMap<usize, CallTarget<'a>>,
+    paths: HashMap<usize, PathTarget<'a>>,
+    locals: HashMap<usize, Type>,
+    loops: HashMap<usize, Iteration<'a>>,
+    unwraps: HashMap<usize, ErrorConversion>,
+    /// Function instances in the order they are first called
+    called: Vec<Instance<'a>>,
+}
+
+fn key<T>(node: &T) -> usize {
+    node as *const T as usize
+}
+
+impl<'a> TypedBody<'a> {
+    /// The type of a checked expression
+    pub fn type_of(&self, expr: &Expression) -> Option<&Type> {
+        self.types.get(&key(expr))
+    }
+
+    pub fn call(&self, call: &CallExpr) -> Option<&CallTarget<'a>> {
+        self.calls.get(&key(call))
+    }
+
+    /// What a `Path` or `Type::member` expression used as a value names
+    pub fn path(&self, expr: &Expression) -> Option<&PathTarget<'a>> {
+        self.paths.get(&key(expr))
+    }
+
+    /// The type of the variable a `let`, `mut` or `for` statement introduces
+    pub fn local(&self, stmt: &Statement) -> Option<&Type> {
+        self.locals.get(&key(stmt))
+    }
+
+    pub fn iteration(&self, stmt: &ForStatement) -> Option<&Iteration<'a>> {
+        self.loops.get(&key(stmt))
+    }
+
+    /// How the error of a `callee!(args)` or `object.!field` reaches the
+    /// function's own error type. The key is the call or field access.
+    pub fn unwrap_call(&self, call: &CallExpr) -> Option<&ErrorConversion> {
+        self.unwraps.get(&key(call))
+    }
+
+    pub fn unwrap_field(&self, access: &FieldAccessExpr) -> Option<&ErrorConversion> {
+        self.unwraps.get(&key(access))
+    }
+
+    /// Every function instance called from this body, once each, in the
+    /// order the calls appear: including the `next` methods `for` loops
+    /// call and the functions that convert propagated errors
+    pub fn callees(&self) -> impl Iterator<Item = &Instance<'a>> {
+        self.called.iter()
+    }
+
+    /// Every type given to an expression or a local variable, in no
+    /// particular order
+    pub fn types(&self) -> impl Iterator<Item = &Type> {
+        self.types.values().chain(self.locals.values())
+    }
+
+    fn record_call(&mut self, call: &CallExpr, target: CallTarget<'a>) {
+        if let CallTarget::Function { instance, .. } = &target {
+            self.record_callee(instance);
+        }
+        self.calls.insert(key(call), target);
+    }
+
+    fn record_callee(&mut self, instance: &Instance<'a>) {
+        if !self.called.contains(instance) {
+            self.called.push(instance.clone());
+        }
+    }
+}
+
+/// A function instance's parameter and return types
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+    /// The type of `self`, including the pointer for `*self`
+    pub self_type: Option<Type>,
+    pub params: Vec<(String, Type)>,
+    /// `ok` for a function declared without a return type
+    pub return_type: Type,
+}
+
+/// Names of the generic parameters an instance of `function` binds: those
+/// of the receiver type, then the function's own
+pub fn generic_names(items: &ItemTable, function: &FunctionDef) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    if let Some(receiver) = &function.signature.receiver {
+        if receiver.generic_args.is_empty() {
+            if let Some(def) = items.lookup_type(receiver) {
+                names.extend(def.generic_params().iter().map(|p| p.name().to_string()));
+            }
+        } else {
+            for arg in &receiver.generic_args {
+                if let Type::Path(p) = arg
+                    && let [name] = p.segments.as_slice()
+                    && p.generic_args.is_empty()
+                {
+                    names.push(name.clone());
+                }
+            }
+        }
+    }
+    for param in &function.signature.generic_params {
+        if !names.iter().any(|n| n == param.name()) {
+            names.push(param.name().to_string());
+        }
+    }
+    names
+}
+
+/// Whether `function` is a default method of an interface
+pub fn is_default_method(items: &ItemTable, function: &FunctionDef) -> bool {
+    let receiver = function.signature.receiver.as_ref();
+    matches!(receiver.and_then(|r| items.lookup_type(r)), Some(TypeDef::Interface(_)))
+}
+
+/// The non-generic functions with bodies: where checking starts
+pub fn roots<'a>(items: &'a ItemTable<'a>) -> Vec<Instance<'a>> {
+    items
+        .functions()
+        .iter()
+        .filter(|f| f.body.is_some() && generic_names(items, f).is_empty() && !is_default_method(items, f))
+        .map(Instance::new)
+        .collect()
+}
+
+pub fn is_integer(ty: &Type) -> bool {
+    matches!(
+        ty,
+        Type::U8 | Type::U16 | Type::U32 | Type::U64 | Type::USize | Type::I8 | Type::I16 | Type::I32 | Type::I64 | Type::ISize
+    )
+}
+
+pub fn is_float(ty: &Type) -> bool {
+    matches!(ty, Type::F32 | Type::F64)
+}
+
+fn is_numeric(ty: &Type) -> bool {
+    is_integer(ty) || is_float(ty)
+}
+
+fn is_signed(ty: &Type) -> bool {
+    matches!(ty, Type::I8 | Type::I16 | Type::I32 | Type::I64 | Type::ISize) || is_float(ty)
+}
+
+/// Whether `expr` is built only from unsuffixed numeric literals, so that its
+/// type comes from context
+fn is_untyped(expr: &Expression) -> bool {
+    match expr {
+        Expression::IntegerLiteral(lit) => lit.suffix().is_none(),
+        Expression::FloatLiteral(lit) => lit.suffix().is_none(),
+        Expression::Parenthesized(inner) => is_untyped(inner),
+        Expression::UnaryOp(op) => {
+            matches!(op.op, UnaryOperator::Negate | UnaryOperator::Plus | UnaryOperator::BitwiseNot) && is_untyped(&op.operand)
+        }
+        Expression::BinaryOp(op) => {
+            matches!(
+                op.op,
+                BinaryOperator::Add
+                    | BinaryOperator::Subtract
+                    | BinaryOperator::Multiply
+                    | BinaryOperator::Divide
+                    | BinaryOperator::Modulo
+                    | BinaryOperator::BitwiseAnd
+                    | BinaryOperator::BitwiseOr
+                    | BinaryOperator::BitwiseXor
+            ) && is_untyped(&op.lhs)
+                && is_untyped(&op.rhs)
+        }
+        _ => false,
+    }
+}
+
+/// The name of a generic parameter if `ty` is nothing but that name
+fn param_name<'t>(ty: &'t Type, names: &[String]) -> Option<&'t str> {
+    match ty {
+        Type::Path(p) if p.generic_args.is_empty() => match p.segments.as_slice() {
+            [name] if names.contains(name) => Some(name),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn size_name<'e>(size: &'e Expression, names: &[String]) -> Option<&'e str> {
+    match size {
+        Expression::Path(p) if p.generic_args.is_empty() => match p.segments.as_slice() {
+            [name] if names.contains(name) => Some(name),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Bind the generic parameters in `pattern` by matching it against the
+/// concrete type `actual`. Names already bound keep their binding.
+pub fn unify(pattern: &Type, actual: &Type, names: &[String], bindings: &mut Bindings) {
+    if let Some(name) = param_name(pattern, names) {
+        if !bindings.iter().any(|(n, _)| n == name) {
+            bindings.push((name.to_string(), actual.clone()));
+        }
+        return;
+    }
+    match (pattern, actual) {
+        (Type::Path(p), Type::Path(a)) if p.generic_args.len() == a.generic_args.len() => {
+            for (p, a) in p.generic_args.iter().zip(&a.generic_args) {
+                unify(p, a, names, bindings);
+            }
+        }
+        (Type::Pointer { element_type: p, .. }, Type::Pointer { element_type: a, .. })
+        | (Type::Optional(p), Type::Optional(a)) => unify(p, a, names, bindings),
+        (Type::Optional(p), a) => unify(p, a, names, bindings),
+        (Type::Array { element_type: p, size: ps }, Type::Array { element_type: a, size: asz }) => {
+            unify(p, a, names, bindings);
+            if let (Some(ps), Some(asz)) = (ps, asz)
+                && let Some(name) = size_name(ps, names)
+                && !bindings.iter().any(|(n, _)| n == name)
+            {
+                bindings.push((name.to_string(), Type::Const(asz.clone())));
+            }
+        }
+        (Type::ErrorUnion { ok_type: p, err_type: pe }, Type::ErrorUnion { ok_type: a, err_type: ae }) => {
+            unify(p, a, names, bindings);
+            unify(&Type::Path(pe.clone()), &Type::Path(ae.clone()), names, bindings);
+        }
+        (Type::Const(p), Type::Const(a)) => {
+            if let Some(name) = size_name(p, names)
+                && !bindings.iter().any(|(n, _)| n == name)
+            {
+                bindings.push((name.to_string(), Type::Const(a.clone())));
+            }
+        }
+        (Type::Function(p), Type::Function(a)) if p.params.len() == a.params.len() => {
+            for (p, a) in p.params.iter().zip(&a.params) {
+                unify(p, a, names, bindings);
+            }
+            if let (Some(p), Some(a)) = (&p.return_type, &a.return_type) {
+                unify(p, a, names, bindings);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Whether `ty` mentions any of `names` that `bindings` leaves unbound
+fn mentions_unbound(ty: &Type, names: &[String], bindings: &Bindings) -> bool {
+    let unbound = |name: &str| names.iter().any(|n| n == name) && !bindings.iter().any(|(n, _)| n == name);
+    match ty {
+        Type::Path(p) => {
+            (p.generic_args.is_empty() && p.segments.len() == 1 && unbound(&p.segments[0]))
+                || p.generic_args.iter().any(|a| mentions_unbound(a, names, bindings))
+        }
+        Type::Pointer { element_type, .. } | Type::Optional(element_type) => {
+            mentions_unbound(element_type, names, bindings)
+        }
+        Type::Array { element_type, size } => {
+            mentions_unbound(element_type, names, bindings)
+                || size.as_ref().and_then(|s| size_name(s, names)).is_some_and(unbound)
+        }
+        Type::ErrorUnion { ok_type, err_type } => {
+            mentions_unbound(ok_type, names, bindings)
+                || mentions_unbound(&Type::Path(err_type.clone()), names, bindings)
+        }
+        Type::SelfType => unbound("Self"),
+        Type::Function(function) => {
+            function.params.iter().chain(function.return_type.as_deref()).any(|t| mentions_unbound(t, names, bindings))
+        }
+        _ => false,
+    }
+}
+
+/// Checks function instances against the declarations in an [`ItemTable`]
+pub struct TypeChecker<'a> {
+    items: &'a ItemTable<'a>,
+    layout: LayoutEngine<'a>,
+}
+
+impl<'a> TypeChecker<'a> {
+    pub fn new(items: &'a ItemTable<'a>, target: Target) -> Self {
+        TypeChecker { items, layout: LayoutEngine::new(items, target) }
+    }
+
+    pub fn items(&self) -> &'a ItemTable<'a> {
+        self.items
+    }
+
+    pub fn layout(&mut self) -> &mut LayoutEngine<'a> {
+        &mut self.layout
+    }
+
+    /// Resolve `ty` to its normal form with `bindings` substituted
+    pub fn normalize(&mut self, ty: &Type, bindings: &Bindings) -> Result<Type, String> {
+        Ok(match ty {
+            Type::SelfType => match bindings.iter().find(|(n, _)| n == "Self") {
+                Some((_, ty)) => ty.clone(),
+                None => return Err("`Self` is only allowed in methods".to_string()),
+            },
+            Type::Path(path) => return self.normalize_path(path, bindings),
+            Type::Pointer { nullable, mutable, element_type } => Type::Pointer {
+                nullable: *nullable,
+                mutable: *mutable,
+                element_type: Box::new(self.normalize(element_type, bindings)?),
+            },
+            Type::Optional(inner) => match self.normalize(inner, bindings)? {
+                Type::Pointer { mutable, element_type, .. } => Type::Pointer { nullable: true, mutable, element_type },
+                Type::Optional(inner) => Type::Optional(inner),
+                inner => Type::Optional(Box::new(inner)),
+            },
+            Type::Array { element_type, size } => Type::Array {
+                element_type: Box::new(self.normalize(element_type, bindings)?),
+                size: match size {
+                    Some(size) => Some(Box::new(self.array_size(size, bindings)?)),
+                    None => None,
+                },
+            },
+            Type::ErrorUnion { ok_type, err_type } => {
+                let ok_type = Box::new(self.normalize(ok_type, bindings)?);
+                match self.normalize_path(err_type, bindings)? {
+                    Type::Path(err_type) => Type::ErrorUnion { ok_type, err_type },
+                    other => return Err(format!("error type `{}` is not a named type", format_type(&other))),
+                }
+            }
+            Type::Const(value) => Type::Const(Box::new(self.array_size(value, bindings)?)),
+            Type::Function(function) => {
+                let mut params = Vec::with_capacity(function.params.len());
+                for param in &function.params {
+                    params.push(self.normalize(param, bindings)?);
+                }
+                let return_type = match &function.return_type {
+                    Some(ty) => self.normalize(ty, bindings)?,
+                    None => Type::Ok,
+                };
+                function_type(function.is_effect, params, return_type)
+            }
+            primitive => primitive.clone(),
+        })
+    }
+
+    fn normalize_path(&mut self, path: &Path, bindings: &Bindings) -> Result<Type, String> {
+        if let [name] = path.segments.as_slice()
+            && path.generic_args.is_empty()
+            && let Some((_, ty)) = bindings.iter().find(|(n, _)| n == name)
+        {
+            return Ok(ty.clone());
+        }
+        let Some((qualified, def)) = self.items.lookup_type_entry(path) else {
+            return Err(format!("unknown type `{}`", format_path(path)));
+        };
+        let qualified = qualified.to_string();
+        let mut args = Vec::with_capacity(path.generic_args.len());
+        for arg in &path.generic_args {
+            args.push(self.normalize(arg, bindings)?);
+        }
+        let params = def.generic_params();
+        for param in params.iter().skip(args.len()) {
+            match param {
+                GenericParameter::Type { default_type: Some(default), .. } => {
+                    let outer: Bindings = params.iter().map(|p| p.name().to_string()).zip(args.clone()).collect();
+                    args.push(self.normalize(default, &outer)?);
+                }
+                _ => return Err(format!("missing generic argument `{}` for `{}`", param.name(), format_path(path))),
+            }
+        }
+        if args.len() > params.len() {
+            return Err(format!("too many generic arguments for `{}`", format_path(path)));
+        }
+        if let TypeDef::Alias(alias) = def {
+            let inner: Bindings = params.iter().map(|p| p.name().to_string()).zip(args).collect();
+            return self.normalize(&alias.aliased_type, &inner);
+        }
+        Ok(Type::Path(Path { segments: qualified.split("::").map(String::from).collect(), generic_args: args }))
+    }
+
+    fn array_size(&mut self, size: &Expression, bindings: &Bindings) -> Result<Expression, String> {
+        if let Expression::Path(p) = size
+            && let [name] = p.segments.as_slice()
+            && let Some((_, bound)) = bindings.iter().find(|(n, _)| n == name)
+        {
+            return match bound {
+                Type::Const(value) => Ok((**value).clone()),
+                other => Err(format!("`{}` is a type, not a constant", format_type(other))),
+            };
+        }
+        let value = self.layout.eval_const(size).map_err(|e| e.to_string())?;
+        if value < 0 {
+            return Err(format!("array size `{}` is negative", format_expression(size)));
+        }
+        Ok(int_literal(value))
+    }
+
+    /// The receiver type a method instance operates on: the receiver path with
+    /// its parameters bound, or `Self` for an interface's default method
+    fn receiver_type(&mut self, instance: &Instance<'a>) -> Result<Option<Type>, String> {
+        if instance.function.signature.receiver.is_none() {
+            return Ok(None);
+        }
+        if is_default_method(self.items, instance.function) {
+            return self.normalize(&Type::SelfType, &instance.bindings).map(Some);
+        }
+        self.normalize(&receiver_pattern(self.items, instance.function), &instance.bindings).map(Some)
+    }
+
+    /// `instance.bindings` with `Self` bound for a method of a concrete type
+    pub fn bindings_of(&mut self, instance: &Instance<'a>) -> Result<Bindings, String> {
+        let mut bindings = instance.bindings.clone();
+        if !bindings.iter().any(|(n, _)| n == "Self")
+            && let Some(receiver) = self.receiver_type(instance)?
+        {
+            bindings.push(("Self".to_string(), receiver));
+        }
+        Ok(bindings)
+    }
+
+    /// Parameter and return types of an instance
+    pub fn signature(&mut self, instance: &Instance<'a>) -> Result<Signature, Diagnostic> {
+        let sig = instance.function.signature;
+        let error = |message: String| Diagnostic::error(message).in_function(instance.name());
+        let receiver = self.receiver_type(instance).map_err(error)?;
+        let bindings = self.bindings_of(instance).map_err(error)?;
+        let self_type = match (&sig.self_param, receiver) {
+            (Some(sp), Some(receiver)) if sp.is_pointer => Some(Type::Pointer {
+                nullable: false,
+                mutable: sp.is_mutable,
+                element_type: Box::new(receiver),
+            }),
+            (Some(_), Some(receiver)) => Some(receiver),
+            (Some(_), None) => return Err(error("`self` parameter outside a method".to_string())),
+            (None, _) => None,
+        };
+        let mut params = Vec::with_capacity(sig.params.len());
+        for param in &sig.params {
+            params.push((param.name.clone(), self.normalize(&param.ty, &bindings).map_err(error)?));
+        }
+        let return_type = match sig.return_types.as_slice() {
+            [] => Type::Ok,
+            [ty] => self.normalize(ty, &bindings).map_err(error)?,
+            _ => return Err(error("functions returning several values are not supported".to_string())),
+        };
+        Ok(Signature { self_type, params, return_type })
+    }
+
+    /// Check the body of one function instance
+    pub fn check(&mut self, instance: &Instance<'a>) -> Result<TypedBody<'a>, Vec<Diagnostic>> {
+        let signature = self.signature(instance).map_err(|d| vec![d])?;
+        let Some(body) = instance.function.body else {
+            return Ok(TypedBody::default());
+        };
+        let bindings = self.bindings_of(instance).map_err(|message| vec![Diagnostic::error(message)])?;
+        let mut checker = BodyChecker {
+            tc: self,
+            bindings,
+            name: instance.name(),
+            return_type: signature.return_type,
+            self_type: signature.self_type,
+            scopes: vec![signature.params.into_iter().collect()],
+            span: None,
+            body: TypedBody::default(),
+            diagnostics: Vec::new(),
+        };
+        checker.block(body);
+        if checker.diagnostics.is_empty() { Ok(checker.body) } else { Err(checker.diagnostics) }
+    }
+
+    /// Find the method `name` callable on values of the named type `ty`:
+    /// one declared on the type itself, or a default method of an interface
+    /// the type satisfies. Returns the method with the bindings its receiver
+    /// implies.
+    pub fn find_method(&mut self, ty: &Type, name: &str) -> Option<Instance<'a>> {
+        let Type::Path(path) = ty else { return None };
+        let items = self.items;
+        let (qualified, def) = items.lookup_type_entry(path)?;
+        let qualified = qualified.to_string();
+        let own = items.functions().iter().find(|f| {
+            f.signature.name == name
+                && f.signature.self_param.is_some()
+                && f.signature.receiver.as_ref().and_then(|r| items.lookup_type_entry(r)).map(|(k, _)| k)
+                    == Some(qualified.as_str())
+        });
+        if let Some(function) = own {
+            let receiver = function.signature.receiver.as_ref().expect("methods have receivers");
+            let names: Vec<String> = if receiver.generic_args.is_empty() {
+                def.generic_params().iter().map(|p| p.name().to_string()).collect()
+            } else {
+                generic_names(items, function)
+            };
+            let bindings: Bindings = names.into_iter().zip(path.generic_args.iter().cloned()).collect();
+            return Some(Instance { function, bindings });
+        }
+
+        let checker = ConformanceChecker::new(items);
+        for (iface_name, iface_def) in items.types() {
+            let TypeDef::Interface(iface) = iface_def else { continue };
+            let Some(function) = items.functions().iter().find(|f| {
+                f.signature.name == name
+                    && f.body.is_some()
+                    && f.signature.receiver.as_ref().and_then(|r| items.lookup_type_entry(r)).map(|(k, _)| k)
+                        == Some(iface_name)
+            }) else {
+                continue;
+            };
+            if checker.satisfies(ty, &Path::simple(iface_name.to_string())).is_err() {
+                continue;
+            }
+            // Bind the interface's parameters by matching its method
+            // declarations against the type's implementations
+            let names: Vec<String> = iface.generic_params.iter().map(|p| p.name().to_string()).collect();
+            let mut bindings = Bindings::new();
+            for required in &iface.methods {
+                let Some(implementation) = self.find_method(ty, &required.name) else { continue };
+                if is_default_method(items, implementation.function) {
+                    continue;
+                }
+                let Ok(implemented) = self.signature(&implementation) else { continue };
+                if let ([pattern], actual) = (required.return_types.as_slice(), &implemented.return_type) {
+                    unify(pattern, actual, &names, &mut bindings);
+                }
+                for (pattern, (_, actual)) in required.params.iter().zip(&implemented.params) {
+                    unify(&pattern.ty, actual, &names, &mut bindings);
+                }
+            }
+            let mut ordered: Bindings = Vec::new();
+            for name in generic_names(items, function) {
+                let bound = bindings.iter().find(|(n, _)| *n == name)?;
+                ordered.push(bound.clone());
+            }
+            ordered.push(("Self".to_string(), ty.clone()));
+            return Some(Instance { function, bindings: ordered });
+        }
+        None
+    }
+
+    /// The fields of the struct `ty`, or the variants of the union `ty`,
+    /// with their types normalised for its generic arguments
+    pub fn fields(&mut self, ty: &Type) -> Result<Vec<(String, Type)>, String> {
+        let Type::Path(path) = ty else { return Ok(Vec::new()) };
+        let items = self.items;
+        let (params, fields): (&[GenericParameter], Vec<(&String, &Type)>) = match items.lookup_type(path) {
+            Some(TypeDef::Struct(s)) => (&s.generic_params, s.fields.iter().map(|f| (&f.name, &f.ty)).collect()),
+            Some(TypeDef::Union(u)) => (&u.generic_params, u.variants.iter().map(|v| (&v.name, &v.ty)).collect()),
+            _ => return Ok(Vec::new()),
+        };
+        let bindings: Bindings =
+            params.iter().map(|p| p.name().to_string()).zip(path.generic_args.iter().cloned()).collect();
+        fields.into_iter().map(|(name, field_type)| Ok((name.clone(), self.normalize(field_type, &bindings)?))).collect()
+    }
+
+    /// Whether a value of type `from` may be used where `to` is expected
+    pub fn assignable(&self, from: &Type, to: &Type) -> bool {
+        if from == to {
+            return true;
+        }
+        match (from, to) {
+            (Type::Null, Type::Optional(_) | Type::Pointer { nullable: true, .. }) => true,
+            (
+                Type::Pointer { nullable: from_nullable, element_type: a, .. },
+                Type::Pointer { nullable: to_nullable, element_type: b, .. },
+            ) => a == b && (!from_nullable || *to_nullable),
+            (from, Type::Optional(inner)) => self.assignable(from, inner),
+            (from, Type::ErrorUnion { ok_type, err_type }) => {
+                self.assignable(from, ok_type) || self.error_conversion(from, err_type).is_some()
+            }
+            (Type::Array { element_type: a, size: Some(_) }, Type::Array { element_type: b, size: None }) => a == b,
+            (from, to) => self.widens(from, to),
+        }
+    }
+
+    /// Whether every value of the integer type `from` is also a value of `to`
+    pub fn widens(&self, from: &Type, to: &Type) -> bool {
+        let target = self.layout.target();
+        match (target.integer_info(from), target.integer_info(to)) {
+            (Some((from_bits, from_signed)), Some((to_bits, to_signed))) => {
+                let (from_min, from_max) = integer_bounds(from_bits, from_signed);
+                let (to_min, to_max) = integer_bounds(to_bits, to_signed);
+                to_min <= from_min && from_max <= to_max
+            }
+            _ => false,
+        }
+    }
+
+    /// How a value of type `from` becomes an error of type `to` when it is
+    /// returned from a function: as is, or wrapped into a union variant
+    pub fn error_conversion(&self, from: &Type, to: &Path) -> Option<ErrorConversion> {
+        let Type::Path(from) = from else { return None };
+        match PropagationChecker::new(self.items).conversion(from, to)? {
+            ErrorConversion::Function { .. } => None,
+            conversion => Some(conversion),
+        }
+    }
+}
+
+/// State while checking one function instance
+struct BodyChecker<'c, 'a> {
+    tc: &'c mut TypeChecker<'a>,
+    /// The instance's bindings including `Self`
+    bindings: Bindings,
+    name: String,
+    return_type: Type,
+    self_type: Option<Type>,
+    scopes: Vec<HashMap<String, Type>>,
+    /// The statement being checked, when the body has statement spans
+    span: Option<Span>,
+    body: TypedBody<'a>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// A callee spelled as a path: `f`, `ns::f`, `Type::method` or `Type[T]::method`
+pub(crate) struct StaticCallee {
+    pub(crate) segments: Vec<String>,
+    /// Generic arguments written on the owner, e.g. `[T]` in `Vec[T]::new`
+    pub(crate) owner_args: Vec<Type>,
+}
+
+pub(crate) fn static_callee(expr: &Expression) -> Option<StaticCallee> {
+    match expr {
+        Expression::Path(path) => Some(StaticCallee { segments: path.segments.clone(), owner_args: Vec::new() }),
+        Expression::TypeAccess(access) => {
+            let (mut segments, owner_args) = match access.object.as_ref() {
+                Expression::Path(path) => (path.segments.clone(), path.generic_args.clone()),
+                // `Vec[T]::new` parses as an index expression
+                Expression::Index(index) => match (index.object.as_ref(), index.index.as_ref()) {
+                    (Expression::Path(owner), Expression::Path(arg)) => {
+                        (owner.segments.clone(), vec![Type::Path(arg.clone())])
+                    }
+                    _ => return None,
+                },
+                other => {
+                    let inner = static_callee(other)?;
+                    (inner.segments, inner.owner_args)
+                }
+            };
+            segments.push(access.member.clone());
+            Some(StaticCallee { segments, owner_args })
+        }
+        _ => None,
+    }
+}
+
+impl<'a> BodyChecker<'_, 'a> {
+    fn error(&mut self, message: impl Into<String>, expr: &Expression) {
+        let diagnostic =
+            Diagnostic::error(message).in_function(&self.name).with_snippet(format_expression(expr)).at(self.span);
+        self.diagnostics.push(diagnostic);
+    }
+
+    fn lookup(&self, name: &str) -> Option<&Type> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    fn bind(&mut self, name: &str, ty: Type) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.to_string(), ty);
+        }
+    }
+
+    fn normalize(&mut self, ty: &Type, expr: &Expression) -> Option<Type> {
+        match self.tc.normalize(ty, &self.bindings) {
+            Ok(ty) => Some(ty),
+            Err(message) => {
+                self.error(message, expr);
+                None
+            }
+        }
+    }
+
+    // ========================================================================
+    // Statements
+    // ========================================================================
+
+    fn block(&mut self, block: &Block) {
+        let outer = self.span;
+        self.scopes.push(HashMap::new());
+        for (index, stmt) in block.statements.iter().enumerate() {
+            self.span = block.span(index).or(outer);
+            self.statement(stmt);
+        }
+        self.scopes.pop();
+        self.span = outer;
+    }
+
+    fn statement(&mut self, stmt: &Statement) {
+        match stmt {
+            Statement::Expression(expr) => {
+                self.infer(expr, None);
+            }
+            Statement::Let(LetStatement { name, ty, value, .. })
+            | Statement::Mut(MutStatement { name, ty, value, .. }) => self.local(stmt, name, ty.as_ref(), value),
+            Statement::Const(c) => self.local(stmt, &c.name, c.ty.as_ref(), &c.value),
+            Statement::Return(value) => {
+                let expected = self.return_type.clone();
+                self.check(value, &expected);
+            }
+            Statement::Block(block) => self.block(&block.body),
+            Statement::If(stmt) => {
+                self.check(&stmt.condition, &Type::Bool);
+                self.block(&stmt.then_body);
+                for clause in &stmt.elif_clauses {
+                    self.check(&clause.condition, &Type::Bool);
+                    self.block(&clause.body);
+                }
+                if let Some(body) = &stmt.else_body {
+                    self.block(body);
+                }
+            }
+            Statement::While(stmt) => {
+                self.check(&stmt.condition, &Type::Bool);
+                self.block(&stmt.body);
+            }
+            Statement::For(for_stmt) => {
+                let item = self.iteration(for_stmt);
+                self.scopes.push(HashMap::new());
+                if let Some(item) = item {
+                    self.body.locals.insert(key(stmt), item.clone());
+                    self.bind(&for_stmt.pattern, item);
+                }
+                self.block(&for_stmt.body);
+                self.scopes.pop();
+            }
+            _ => {}
+        }
+    }
+
+    fn local(&mut self, stmt: &Statement, name: &str, declared: Option<&Type>, value: &Expression) {
+        let ty = match declared {
+            Some(declared) => {
+                let Some(declared) = self.normalize(declared, value) else { return };
+                self.check(value, &declared);
+                declared
+            }
+            None => match self.infer(value, None) {
+                Some(Type::Null) => {
+                    self.error(format!("cannot infer the type of `{}` from `null`", name), value);
+                    return;
+                }
+                Some(Type::Ok) if matches!(value, Expression::Call(_)) => Type::Ok,
+                Some(ty) => ty,
+                None => return,
+            },
+        };
+        self.body.locals.insert(key(stmt), ty.clone());
+        self.bind(name, ty);
+    }
+
+    /// Decide how a `for` loop iterates; returns the pattern variable's type
+    fn iteration(&mut self, stmt: &ForStatement) -> Option<Type> {
+        if let Expression::Range(range) = stmt.iterable.as_ref() {
+            if range.start.is_none() {
+                self.error("a range without a start cannot be iterated", &stmt.iterable);
+                return None;
+            }
+            let item = self.range_bounds(range, None, &stmt.iterable)?;
+            self.body.loops.insert(key(stmt), Iteration::Range);
+            return Some(item);
+        }
+        let iterable = self.infer(&stmt.iterable, None)?;
+        let (base, through_pointer) = match &iterable {
+            Type::Pointer { element_type, .. } => ((**element_type).clone(), true),
+            other => (other.clone(), false),
+        };
+        if let Type::Array { element_type, .. } = &iterable {
+            self.body.loops.insert(key(stmt), Iteration::Elements);
+            return Some((**element_type).clone());
+        }
+        let Some(next) = self.tc.find_method(&base, "next") else {
+            self.error(
+                format!("`{}` is neither an array, a slice nor an iterator with a `next` method", format_type(&iterable)),
+                &stmt.iterable,
+            );
+            return None;
+        };
+        let signature = match self.tc.signature(&next) {
+            Ok(signature) => signature,
+            Err(diagnostic) => {
+                self.diagnostics.push(diagnostic);
+                return None;
+            }
+        };
+        let item = match signature.return_type {
+            Type::Optional(item) => *item,
+            Type::Pointer { nullable: true, mutable, element_type } => {
+                Type::Pointer { nullable: false, mutable, element_type }
+            }
+            other => {
+                self.error(
+                    format!("`{}::next` returns `{}`, not an optional", format_type(&base), format_type(&other)),
+                    &stmt.iterable,
+                );
+                return None;
+            }
+        };
+        let by_pointer = next.function.signature.self_param.as_ref().is_some_and(|sp| sp.is_pointer);
+        let self_arg = match (by_pointer, through_pointer) {
+            (true, true) => SelfArg::Pointer,
+            (true, false) => SelfArg::AddressOf,
+            (false, true) => SelfArg::Deref,
+            (false, false) => SelfArg::Value,
+        };
+        self.body.record_callee(&next);
+        self.body.loops.insert(key(stmt), Iteration::Iterator { next, self_arg });
+        Some(item)
+    }
+
+    /// The integer type shared by the bounds of a range. An unsuffixed
+    /// literal takes the type of the other bound, as in `0..n`, and a range
+    /// with neither bound is of type `usize`.
+    fn range_bounds(&mut self, range: &RangeExpr, hint: Option<&Type>, expr: &Expression) -> Option<Type> {
+        let mut bounds: Vec<&Expression> = range.start.iter().chain(range.end.iter()).map(|b| b.as_ref()).collect();
+        if bounds.len() == 2 && is_untyped(bounds[0]) && !is_untyped(bounds[1]) {
+            bounds.reverse();
+        }
+        let mut ty = hint.cloned();
+        for (i, bound) in bounds.into_iter().enumerate() {
+            let bound_type = self.infer(bound, ty.as_ref())?;
+            if !is_integer(&bound_type) {
+                self.error(format!("range bounds must be integers, found `{}`", format_type(&bound_type)), bound);
+                return None;
+            }
+            match &ty {
+                Some(ty) if i > 0 && *ty != bound_type => {
+                    self.error(
+                        format!("mismatched range bounds `{}` and `{}`", format_type(ty), format_type(&bound_type)),
+                        expr,
+                    );
+                    return None;
+                }
+                _ => ty = Some(bound_type),
+            }
+        }
+        Some(ty.unwrap_or(Type::USize))
+    }
+
+    // ========================================================================
+    // Expressions
+    // ========================================================================
+
+    /// Check `expr` against an expected type
+    fn check(&mut self, expr: &Expression, expected: &Type) -> Option<Type> {
+        let actual = self.infer(expr, Some(expected))?;
+        if self.tc.assignable(&actual, expected) {
+            Some(actual)
+        } else {
+            self.error(
+                format!("mismatched types: expected `{}`, found `{}`", format_type(expected), format_type(&actual)),
+                expr,
+            );
+            None
+        }
+    }
+
+    /// The type of `expr`, recorded on success. On failure a diagnostic has
+    /// been reported and `None` is returned.
+    fn infer(&mut self, expr: &Expression, expected: Option<&Type>) -> Option<Type> {
+        let ty = self.infer_inner(expr, expected)?;
+        self.body.types.insert(key(expr), ty.clone());
+        Some(ty)
+    }
+
+    fn infer_inner(&mut self, expr: &Expression, expected: Option<&Type>) -> Option<Type> {
+        // Literals take their type from context through `?T`
+        let expected_inner = match expected {
+            Some(Type::Optional(inner)) => Some(inner.as_ref()),
+            Some(Type::ErrorUnion { ok_type, .. }) => Some(ok_type.as_ref()),
+            other => other,
+        };
+        match expr {
+            Expression::IntegerLiteral(lit) => self.integer_literal(lit, expected_inner, false, expr),
+            Expression::FloatLiteral(lit) => Some(match (lit.suffix(), expected_inner) {
+                (Some(FloatSuffix::F32), _) => Type::F32,
+                (Some(FloatSuffix::F64), _) => Type::F64,
+                (None, Some(ty)) if is_float(ty) => ty.clone(),
+                (None, _) => Type::F64,
+            }),
+            Expression::BooleanLiteral(_) => Some(Type::Bool),
+            Expression::CharLiteral(c) => Some(match expected_inner {
+                Some(ty) if is_integer(ty) => ty.clone(),
+                _ if c.chars().next().is_some_and(|c| u32::from(c) > 0xFF) => Type::U32,
+                _ => Type::U8,
+            }),
+            Expression::StringLiteral(_) => Some(match expected {
+                Some(Type::Pointer { nullable, mutable, element_type }) if **element_type == Type::U8 => Type::Pointer {
+                    nullable: *nullable,
+                    mutable: *mutable,
+                    element_type: element_type.clone(),
+                },
+                _ => Type::Array { element_type: Box::new(Type::U8), size: None },
+            }),
+            Expression::OkLiteral => Some(Type::Ok),
+            Expression::NullLiteral => Some(match expected {
+                Some(ty @ (Type::Optional(_) | Type::Pointer { nullable: true, .. })) => ty.clone(),
+                _ => Type::Null,
+            }),
+            Expression::SelfValue => match &self.self_type {
+                Some(ty) => Some(ty.clone()),
+                None => {
+                    self.error("`self` outside a method", expr);
+                    None
+                }
+            },
+            Expression::Path(path) => self.path(path, expr),
+            Expression::TypeAccess(_) => match static_callee(expr) {
+                Some(callee) => self.path(&Path::with_generics(callee.segments, Vec::new()), expr),
+                None => {
+                    self.error("`::` can only follow a type or namespace", expr);
+                    None
+                }
+            },
+            Expression::StructLiteral(lit) => self.struct_literal(lit, expected_inner, expr),
+            Expression::VariantLiteral(lit) => self.variant_literal(lit, expected_inner, expr),
+            Expression::EnumVariant(value) => match self.tc.items.lookup_type(&value.ty) {
+                Some(TypeDef::Enum(e)) => self.enum_variant(e, value.ty.clone(), &value.variant, expr),
+                _ => {
+                    self.error(format!("`{}` is not an enum", format_path(&value.ty)), expr);
+                    None
+                }
+            },
+            Expression::ArrayLiteral(array) => {
+                let expected_element = match expected {
+                    Some(Type::Array { element_type, .. }) => Some(element_type.as_ref().clone()),
+                    _ => None,
+                };
+                let mut element = expected_element;
+                for value in &array.elements {
+                    match &element {
+                        Some(ty) => {
+                            let ty = ty.clone();
+                            self.check(value, &ty)?;
+                        }
+                        None => element = Some(self.infer(value, None)?),
+                    }
+                }
+                let Some(element) = element else {
+                    self.error("cannot infer the element type of an empty array", expr);
+                    return None;
+                };
+                Some(Type::Array {
+                    element_type: Box::new(element),
+                    size: Some(Box::new(int_literal(array.elements.len() as i128))),
+                })
+            }
+            Expression::InterpolatedString(_) => Some(Type::Array { element_type: Box::new(Type::U8), size: None }),
+            Expression::BinaryOp(op) => self.binary(op, expected_inner, expr),
+            Expression::UnaryOp(op) => self.unary(op, expected_inner, expr),
+            Expression::FieldAccess(access) => self.field(access, expr),
+            Expression::Call(call) => self.call(call, expected, expr),
+            Expression::Index(IndexExpr { object, index: bounds }) if matches!(**bounds, Expression::Range(_)) => {
+                let Expression::Range(range) = bounds.as_ref() else { unreachable!() };
+                let object_type = self.infer(object, None)?;
+                self.range_bounds(range, Some(&Type::USize), bounds)?;
+                match object_type {
+                    Type::Array { element_type, .. } => Some(Type::Array { element_type, size: None }),
+                    other => {
+                        self.error(format!("cannot slice a value of type `{}`", format_type(&other)), expr);
+                        None
+                    }
+                }
+            }
+            Expression::Range(_) => {
+                self.error("a range can only be iterated by `for` or used to slice", expr);
+                None
+            }
+            Expression::Index(index) => {
+                let object = self.infer(&index.object, None)?;
+                let index_type = self.infer(&index.index, Some(&Type::USize))?;
+                if !is_integer(&index_type) {
+                    self.error(format!("index of type `{}` is not an integer", format_type(&index_type)), &index.index);
+                    return None;
+                }
+                match object {
+                    Type::Array { element_type, .. } | Type::Pointer { element_type, .. } => Some(*element_type),
+                    other => {
+                        self.error(format!("cannot index a value of type `{}`", format_type(&other)), expr);
+                        None
+                    }
+                }
+            }
+            Expression::Cast(cast) => {
+                let target = self.normalize(&cast.target_type, expr)?;
+                let source = self.infer(&cast.expr, is_numeric(&target).then_some(&target))?;
+                let castable = |ty: &Type| {
+                    is_numeric(ty)
+                        || matches!(ty, Type::Bool | Type::Pointer { .. })
+                        || matches!(ty, Type::Path(p) if matches!(self.tc.items.lookup_type(p), Some(TypeDef::Enum(_))))
+                };
+                if source != target && !(castable(&source) && castable(&target)) {
+                    self.error(format!("cannot cast `{}` to `{}`", format_type(&source), format_type(&target)), expr);
+                    return None;
+                }
+                Some(target)
+            }
+            Expression::Sizeof(ty) | Expression::Alignof(ty) => {
+                self.normalize(ty, expr)?;
+                Some(Type::USize)
+            }
+            Expression::Offsetof(offsetof) => {
+                let ty = self.normalize(&offsetof.ty, expr)?;
+                if self.struct_field(&ty, &offsetof.field).is_none() {
+                    self.error(format!("`{}` has no field `{}`", format_type(&ty), offsetof.field), expr);
+                    return None;
+                }
+                Some(Type::USize)
+            }
+            Expression::Parenthesized(inner) => self.infer(inner, expected),
+            Expression::Assign(assign) => {
+                self.assign(assign, expr)?;
+                Some(Type::Ok)
+            }
+            Expression::Lambda(lambda) => self.lambda(lambda, expr),
+        }
+    }
+
+    /// The type of a lambda, after checking its body in a scope of its
+    /// parameters. Without a return type the body's value is discarded.
+    fn lambda(&mut self, lambda: &LambdaExpr, expr: &Expression) -> Option<Type> {
+        let mut params = Vec::with_capacity(lambda.params.len());
+        for param in &lambda.params {
+            params.push(self.normalize(&param.ty, expr)?);
+        }
+        let return_type = match &lambda.return_type {
+            Some(ty) => self.normalize(ty, expr)?,
+            None => Type::Ok,
+        };
+        self.scopes.push(lambda.params.iter().map(|p| p.name.clone()).zip(params.iter().cloned()).collect());
+        // Propagation in the body returns from the lambda, not the enclosing function
+        let enclosing = std::mem::replace(&mut self.return_type, return_type.clone());
+        let body = match &lambda.return_type {
+            Some(_) => self.check(&lambda.body, &return_type),
+            None => self.infer(&lambda.body, None),
+        };
+        self.return_type = enclosing;
+        self.scopes.pop();
+        body?;
+        Some(function_type(lambda.is_effect, params, return_type))
+    }
+
+    fn integer_literal(
+        &mut self,
+        lit: &IntegerLiteral,
+        expected: Option<&Type>,
+        negated: bool,
+        expr: &Expression,
+    ) -> Option<Type> {
+        let ty = match (lit.suffix(), expected) {
+            (Some(suffix), _) => match suffix {
+                IntegerSuffix::U8 => Type::U8,
+                IntegerSuffix::U16 => Type::U16,
+                IntegerSuffix::U32 => Type::U32,
+                IntegerSuffix::U64 => Type::U64,
+                IntegerSuffix::I8 => Type::I8,
+                IntegerSuffix::I16 => Type::I16,
+                IntegerSuffix::I32 => Type::I32,
+                IntegerSuffix::I64 => Type::I64,
+                IntegerSuffix::USize => Type::USize,
+                IntegerSuffix::ISize => Type::ISize,
+            },
+            (None, Some(ty)) if is_numeric(ty) => ty.clone(),
+            (None, _) => Type::I32,
+        };
+        let Some((bits, signed)) = self.tc.layout.target().integer_info(&ty) else { return Some(ty) };
+        let (min, max) = integer_bounds(bits, signed);
+        let fits = lit.as_u64().ok().map(i128::from).map(|v| if negated { -v } else { v });
+        if !fits.is_some_and(|v| (min..=max).contains(&v)) {
+            self.error(format!("literal does not fit in `{}`", format_type(&ty)), expr);
+            return None;
+        }
+        Some(ty)
+    }
+
+    fn path(&mut self, path: &Path, expr: &Expression) -> Option<Type> {
+        if let [name] = path.segments.as_slice()
+            && let Some(ty) = self.lookup(name)
+        {
+            let ty = ty.clone();
+            self.body.paths.insert(key(expr), PathTarget::Local);
+            return Some(ty);
+        }
+        let items = self.tc.items;
+        if let Some(c) = items.lookup_const(path) {
+            if !c.generic_params.is_empty() {
+                self.error("generic constants are not supported here", expr);
+                return None;
+            }
+            let declared = match &c.ty {
+                Some(ty) => Some(self.normalize(ty, expr)?),
+                None => None,
+            };
+            // A const's value sees no locals
+            let scopes = std::mem::replace(&mut self.scopes, vec![HashMap::new()]);
+            let ty = match &declared {
+                Some(declared) => self.check(&c.value, declared).map(|_| declared.clone()),
+                None => self.infer(&c.value, None),
+            };
+            self.scopes = scopes;
+            self.body.paths.insert(key(expr), PathTarget::Const(c));
+            return ty;
+        }
+        if let [owner @ .., variant] = path.segments.as_slice()
+            && !owner.is_empty()
+        {
+            let owner = Path::with_generics(owner.to_vec(), path.generic_args.clone());
+            match items.lookup_type(&owner) {
+                Some(TypeDef::Enum(e)) => return self.enum_variant(e, owner, variant, expr),
+                Some(TypeDef::Union(u)) if u.variants.iter().any(|v| v.name == *variant && v.ty == Type::Ok) => {
+                    self.body.paths.insert(key(expr), PathTarget::UnionVariant { variant: variant.clone() });
+                    return self.normalize(&Type::Path(owner), expr);
+                }
+                _ => {}
+            }
+        }
+        if items.lookup_function(path).is_some() {
+            self.error("functions cannot be used as values", expr);
+        } else {
+            self.error(format!("undefined name `{}`", format_path(path)), expr);
+        }
+        None
+    }
+
+    /// An enum value `owner::variant`, recording its discriminant
+    fn enum_variant(&mut self, e: &Enum, owner: Path, variant: &str, expr: &Expression) -> Option<Type> {
+        let discriminant = match self.tc.layout.enum_discriminants(e) {
+            Ok(discriminants) => discriminants.into_iter().find(|(name, _)| name == variant).map(|(_, v)| v),
+            Err(error) => {
+                self.error(error.to_string(), expr);
+                return None;
+            }
+        };
+        let Some(discriminant) = discriminant else {
+            self.error(format!("`{}` has no variant `{}`", e.name, variant), expr);
+            return None;
+        };
+        self.body.paths.insert(key(expr), PathTarget::EnumVariant { discriminant });
+        self.normalize(&Type::Path(owner), expr)
+    }
+
+    /// The generic arguments written on a literal's type, normalised
+    fn owner_args(&mut self, ty: &Path, names: &[String], expr: &Expression) -> Option<Bindings> {
+        let mut bindings = Bindings::new();
+        for (name, arg) in names.iter().zip(&ty.generic_args) {
+            bindings.push((name.clone(), self.normalize(arg, expr)?));
+        }
+        Some(bindings)
+    }
+
+    /// A struct literal. Its fields and base are checked like the arguments
+    /// of a call, so generic parameters are inferred from them.
+    fn struct_literal(&mut self, lit: &StructLiteralExpr, expected: Option<&Type>, expr: &Expression) -> Option<Type> {
+        let lookup = Path::with_generics(lit.ty.segments.clone(), Vec::new());
+        let Some(TypeDef::Struct(s)) = self.tc.items.lookup_type(&lookup) else {
+            self.error(format!("`{}` is not a struct", format_path(&lit.ty)), expr);
+            return None;
+        };
+        let names: Vec<String> = s.generic_params.iter().map(|p| p.name().to_string()).collect();
+        let bindings = self.owner_args(&lit.ty, &names, expr)?;
+        let ty = Type::Path(Path::with_generics(
+            lit.ty.segments.clone(),
+            names.iter().map(|n| Type::Path(Path::simple(n.clone()))).collect(),
+        ));
+        let mut params = Vec::with_capacity(lit.fields.len() + 1);
+        let mut args = Vec::with_capacity(lit.fields.len() + 1);
+        let mut initialised = vec![false; s.fields.len()];
+        for (i, init) in lit.fields.iter().enumerate() {
+            let index = match &init.name {
+                Some(name) => match s.fields.iter().position(|f| f.name == *name) {
+                    Some(index) => index,
+                    None => {
+                        self.error(format!("`{}` has no field `{}`", s.name, name), &init.value);
+                        return None;
+                    }
+                },
+                None if i < s.fields.len() => i,
+                None => break,
+            };
+            if std::mem::replace(&mut initialised[index], true) {
+                self.error(format!("field `{}` is initialised twice", s.fields[index].name), &init.value);
+                return None;
+            }
+            params.push(s.fields[index].ty.clone());
+            args.push(&init.value);
+        }
+        match &lit.base {
+            Some(base) => {
+                params.push(ty.clone());
+                args.push(base);
+            }
+            None if lit.fields.iter().all(|f| f.name.is_none()) => {
+                if lit.fields.len() != s.fields.len() {
+                    let message = format!("expected {} argument(s), found {}", s.fields.len(), lit.fields.len());
+                    self.error(message, expr);
+                    return None;
+                }
+            }
+            None => {
+                let missing: Vec<String> = s
+                    .fields
+                    .iter()
+                    .zip(&initialised)
+                    .filter(|(_, done)| !**done)
+                    .map(|(f, _)| format!("`{}`", f.name))
+                    .collect();
+                if !missing.is_empty() {
+                    let fields = if missing.len() == 1 { "field" } else { "fields" };
+                    self.error(format!("missing {} {} in `{}`", fields, missing.join(", "), s.name), expr);
+                    return None;
+                }
+            }
+        }
+        let (_, ty) = self.infer_call(&names, bindings, &params, &ty, &args, expected, expr)?;
+        Some(ty)
+    }
+
+    /// A union value, or a variant of type `ok` written without a payload
+    fn variant_literal(&mut self, lit: &VariantLiteralExpr, expected: Option<&Type>, expr: &Expression) -> Option<Type> {
+        let lookup = Path::with_generics(lit.ty.segments.clone(), Vec::new());
+        let Some(TypeDef::Union(u)) = self.tc.items.lookup_type(&lookup) else {
+            self.error(format!("`{}` is not a union", format_path(&lit.ty)), expr);
+            return None;
+        };
+        let Some(v) = u.variants.iter().find(|v| v.name == lit.variant) else {
+            self.error(format!("`{}` has no variant `{}`", u.name, lit.variant), expr);
+            return None;
+        };
+        if lit.payload.is_none() && v.ty != Type::Ok {
+            self.error(format!("variant `{}` of `{}` needs a value", lit.variant, u.name), expr);
+            return None;
+        }
+        let names: Vec<String> = u.generic_params.iter().map(|p| p.name().to_string()).collect();
+        let bindings = self.owner_args(&lit.ty, &names, expr)?;
+        let ty = Type::Path(Path::with_generics(
+            lit.ty.segments.clone(),
+            names.iter().map(|n| Type::Path(Path::simple(n.clone()))).collect(),
+        ));
+        let params = if lit.payload.is_some() { vec![v.ty.clone()] } else { vec![] };
+        let args: Vec<&Expression> = lit.payload.iter().map(|p| p.as_ref()).collect();
+        let (_, ty) = self.infer_call(&names, bindings, &params, &ty, &args, expected, expr)?;
+        if lit.payload.is_none() {
+            self.body.paths.insert(key(expr), PathTarget::UnionVariant { variant: lit.variant.clone() });
+        }
+        Some(ty)
+    }
+
+    /// The type of `field` in the struct (or union) type `ty`
+    fn struct_field(&mut self, ty: &Type, field: &str) -> Option<Result<Type, String>> {
+        let Type::Path(path) = ty else { return None };
+        let def = self.tc.items.lookup_type(path)?;
+        let (params, field_type) = match def {
+            TypeDef::Struct(s) => (&s.generic_params, &s.fields.iter().find(|f| f.name == field)?.ty),
+            TypeDef::Union(u) => (&u.generic_params, &u.variants.iter().find(|v| v.name == field)?.ty),
+            _ => return None,
+        };
+        let bindings: Bindings = params.iter().map(|p| p.name().to_string()).zip(path.generic_args.iter().cloned()).collect();
+        Some(self.tc.normalize(field_type, &bindings))
+    }
+
+    /// The operand of `.!`: the success type of a `T ! E`, with the error's
+    /// conversion recorded under `node`
+    fn unwrap<T>(&mut self, ty: Type, node: &T, expr: &Expression) -> Option<Type> {
+        let Type::ErrorUnion { ok_type, err_type } = ty else {
+            self.error(format!("`{}` is not an error union", format_type(&ty)), expr);
+            return None;
+        };
+        let Type::ErrorUnion { err_type: into, .. } = &self.return_type else {
+            self.error("error propagation in a function that does not return an error union", expr);
+            return None;
+        };
+        match PropagationChecker::new(self.tc.items).conversion(&err_type, into) {
+            Some(conversion) => {
+                if let ErrorConversion::Function { function } = &conversion {
+                    let path = Path::with_generics(function.split("::").map(String::from).collect(), Vec::new());
+                    if let Some(def) = self.tc.items.lookup_function(&path) {
+                        self.body.record_callee(&Instance::new(def));
+                    }
+                }
+                self.body.unwraps.insert(key(node), conversion);
+                Some(*ok_type)
+            }
+            None => {
+                self.error(
+                    format!("error `{}` does not convert into `{}`", format_path(&err_type), format_path(into)),
+                    expr,
+                );
+                None
+            }
+        }
+    }
+
+    fn field(&mut self, access: &FieldAccessExpr, expr: &Expression) -> Option<Type> {
+        let mut object = self.infer(&access.object, None)?;
+        if access.is_propagating {
+            object = self.unwrap(object, access, expr)?;
+        }
+        let base = match object {
+            Type::Pointer { element_type, .. } => *element_type,
+            other => other,
+        };
+        if matches!(base, Type::Array { .. }) && access.field == "len" {
+            return Some(Type::USize);
+        }
+        match self.struct_field(&base, &access.field) {
+            Some(Ok(ty)) => Some(ty),
+            Some(Err(message)) => {
+                self.error(message, expr);
+                None
+            }
+            None => {
+                self.error(format!("`{}` has no field `{}`", format_type(&base), access.field), expr);
+                None
+            }
+        }
+    }
+
+    fn binary(&mut self, op: &BinaryOpExpr, expected: Option<&Type>, expr: &Expression) -> Option<Type> {
+        use BinaryOperator::*;
+        match op.op {
+            LogicalAnd | LogicalOr => {
+                self.check(&op.lhs, &Type::Bool)?;
+                self.check(&op.rhs, &Type::Bool)?;
+                return Some(Type::Bool);
+            }
+            ShiftLeft | ShiftRight => {
+                let lhs = self.infer(&op.lhs, expected)?;
+                let rhs = self.infer(&op.rhs, Some(&lhs))?;
+                if !is_integer(&lhs) || !is_integer(&rhs) {
+                    self.error("shifts need integer operands", expr);
+                    return None;
+                }
+                return Some(lhs);
+            }
+            _ => {}
+        }
+
+        let comparison = matches!(op.op, Equal | NotEqual | LessThan | GreaterThan | LessThanOrEqual | GreaterThanOrEqual);
+        let hint = if comparison { None } else { expected.filter(|ty| is_numeric(ty)) };
+        let (lhs, rhs) = if is_untyped(&op.lhs) && !is_untyped(&op.rhs) {
+            let rhs = self.infer(&op.rhs, hint)?;
+            let lhs_hint = if matches!(rhs, Type::Pointer { .. }) { Type::USize } else { rhs.clone() };
+            (self.infer(&op.lhs, Some(&lhs_hint))?, rhs)
+        } else {
+            let lhs = self.infer(&op.lhs, hint)?;
+            let rhs_hint = match &lhs {
+                Type::Pointer { .. } if matches!(op.op, Add | Subtract) && is_untyped(&op.rhs) => Type::USize,
+                other => other.clone(),
+            };
+            (lhs, self.infer(&op.rhs, Some(&rhs_hint))?)
+        };
+
+        let mismatch = |this: &mut Self| {
+            this.error(
+                format!("mismatched operand types `{}` and `{}`", format_type(&lhs), format_type(&rhs)),
+                expr,
+            );
+            None
+        };
+        if comparison {
+            let nullable = |ty: &Type| matches!(ty, Type::Optional(_) | Type::Pointer { nullable: true, .. });
+            let equality = matches!(op.op, Equal | NotEqual);
+            let null_literal = matches!(op.lhs.as_ref(), Expression::NullLiteral)
+                || matches!(op.rhs.as_ref(), Expression::NullLiteral);
+            let items = self.tc.items;
+            let equatable = |ty: &Type| match ty {
+                Type::Bool => true,
+                Type::Path(p) => matches!(items.lookup_type(p), Some(TypeDef::Enum(_))),
+                ty => is_numeric(ty),
+            };
+            let comparable = match (&lhs, &rhs) {
+                (Type::Null, other) | (other, Type::Null) => {
+                    equality && (nullable(other) || matches!(other, Type::Pointer { .. }))
+                }
+                (Type::Pointer { element_type: a, .. }, Type::Pointer { element_type: b, .. }) => a == b,
+                // `x == null`, with the `null` already typed as `?T`
+                (Type::Optional(_), Type::Optional(_)) if null_literal => equality && lhs == rhs,
+                // `?T == T` holds when the optional has a value equal to the other side
+                (Type::Optional(inner), other) | (other, Type::Optional(inner)) => {
+                    equality && **inner == *other && equatable(other)
+                }
+                (a, b) if a != b => false,
+                (Type::Bool, _) | (Type::Path(_), _) => equality && equatable(&lhs),
+                (ty, _) => is_numeric(ty),
+            };
+            return if comparable { Some(Type::Bool) } else { mismatch(self) };
+        }
+
+        match (&lhs, &rhs) {
+            (Type::Pointer { .. }, ty) if matches!(op.op, Add | Subtract) && is_integer(ty) => Some(lhs),
+            (Type::Pointer { element_type: a, .. }, Type::Pointer { element_type: b, .. })
+                if op.op == Subtract && a == b =>
+            {
+                Some(Type::ISize)
+            }
+            (a, b) if a != b => mismatch(self),
+            (Type::Bool, _) if matches!(op.op, BitwiseAnd | BitwiseOr | BitwiseXor) => Some(lhs),
+            (ty, _) if is_integer(ty) => Some(lhs),
+            (ty, _) if is_float(ty) && matches!(op.op, Add | Subtract | Multiply | Divide) => Some(lhs),
+            _ => {
+                self.error(format!("operator cannot be applied to `{}`", format_type(&lhs)), expr);
+                None
+            }
+        }
+    }
+
+    fn unary(&mut self, op: &UnaryOpExpr, expected: Option<&Type>, expr: &Expression) -> Option<Type> {
+        match op.op {
+            UnaryOperator::LogicalNot => self.check(&op.operand, &Type::Bool).map(|_| Type::Bool),
+            UnaryOperator::AddressOf => {
+                let element = self.infer(&op.operand, None)?;
+                Some(Type::Pointer { nullable: false, mutable: false, element_type: Box::new(element) })
+            }
+            UnaryOperator::Dereference => match self.infer(&op.operand, None)? {
+                Type::Pointer { element_type, .. } => Some(*element_type),
+                other => {
+                    self.error(format!("cannot dereference a value of type `{}`", format_type(&other)), expr);
+                    None
+                }
+            },
+            UnaryOperator::Negate if matches!(op.operand.as_ref(), Expression::IntegerLiteral(_)) => {
+                let Expression::IntegerLiteral(lit) = op.operand.as_ref() else { unreachable!() };
+                let ty = self.integer_literal(lit, expected, true, expr)?;
+                self.body.types.insert(key(op.operand.as_ref()), ty.clone());
+                if !is_signed(&ty) {
+                    self.error(format!("cannot negate a value of type `{}`", format_type(&ty)), expr);
+                    return None;
+                }
+                Some(ty)
+            }
+            UnaryOperator::Negate | UnaryOperator::Plus | UnaryOperator::BitwiseNot => {
+                let ty = self.infer(&op.operand, expected)?;
+                let valid = match op.op {
+                    UnaryOperator::Negate => is_signed(&ty),
+                    UnaryOperator::Plus => is_numeric(&ty),
+                    _ => is_integer(&ty),
+                };
+                if !valid {
+                    self.error(format!("operator cannot be applied to `{}`", format_type(&ty)), expr);
+                    return None;
+                }
+                Some(ty)
+            }
+        }
+    }
+
+    fn assign(&mut self, assign: &AssignExpr, expr: &Expression) -> Option<()> {
+        let place = match assign.lhs.as_ref() {
+            Expression::Path(path) => path.segments.len() == 1 && self.lookup(&path.segments[0]).is_some(),
+            Expression::FieldAccess(access) => !access.is_propagating,
+            Expression::Index(index) => !matches!(*index.index, Expression::Range(_)),
+            Expression::SelfValue => true,
+            Expression::UnaryOp(op) => op.op == UnaryOperator::Dereference,
+            Expression::Parenthesized(_) => true,
+            _ => false,
+        };
+        if !place {
+            self.error("the left-hand side of an assignment must be a variable, field, element or dereference", expr);
+            return None;
+        }
+        let lhs = self.infer(&assign.lhs, None)?;
+        match assign.op {
+            AssignOperator::Assign => {
+                self.check(&assign.rhs, &lhs)?;
+            }
+            AssignOperator::AddAssign | AssignOperator::SubAssign if matches!(lhs, Type::Pointer { .. }) => {
+                let rhs = self.infer(&assign.rhs, Some(&Type::USize))?;
+                if !is_integer(&rhs) {
+                    self.error("pointer offsets must be integers", expr);
+                    return None;
+                }
+            }
+            AssignOperator::ShlAssign | AssignOperator::ShrAssign => {
+                let rhs = self.infer(&assign.rhs, Some(&lhs))?;
+                if !is_integer(&lhs) || !is_integer(&rhs) {
+                    self.error("shifts need integer operands", expr);
+                    return None;
+                }
+            }
+            _ => {
+                self.check(&assign.rhs, &lhs)?;
+                let valid = match assign.op {
+                    AssignOperator::AddAssign
+                    | AssignOperator::SubAssign
+                    | AssignOperator::MulAssign
+                    | AssignOperator::DivAssign => is_numeric(&lhs),
+                    AssignOperator::ModAssign => is_integer(&lhs),
+                    _ => is_integer(&lhs) || lhs == Type::Bool,
+                };
+                if !valid {
+                    self.error(format!("operator cannot be applied to `{}`", format_type(&lhs)), expr);
+                    return None;
+                }
+            }
+        }
+        Some(())
+    }
+
+    // ========================================================================
+    // Calls
+    // ========================================================================
+
+    fn call(&mut self, call: &CallExpr, expected: Option<&Type>, expr: &Expression) -> Option<Type> {
+        let expected = if call.is_propagating { None } else { expected };
+        let result = match call.callee.as_ref() {
+            Expression::FieldAccess(access) => self.method_call(call, access, expected, expr)?,
+            // A local of function type, or any other expression, is a value
+            callee => match static_callee(callee) {
+                Some(path) if !matches!(path.segments.as_slice(), [name] if self.lookup(name).is_some()) => {
+                    self.static_call(call, path, expected, expr)?
+                }
+                _ => self.value_call(call, expr)?,
+            },
+        };
+        if call.is_propagating { self.unwrap(result, call, expr) } else { Some(result) }
+    }
+
+    fn method_call(
+        &mut self,
+        call: &CallExpr,
+        access: &FieldAccessExpr,
+        expected: Option<&Type>,
+        expr: &Expression,
+    ) -> Option<Type> {
+        let mut object = self.infer(&access.object, None)?;
+        if access.is_propagating {
+            object = self.unwrap(object, access, expr)?;
+        }
+        let (base, through_pointer) = match &object {
+            Type::Pointer { element_type, .. } => ((**element_type).clone(), true),
+            other => (other.clone(), false),
+        };
+        let Some(mut instance) = self.tc.find_method(&base, &access.field) else {
+            self.error(format!("`{}` has no method `{}`", format_type(&base), access.field), expr);
+            return None;
+        };
+        let by_pointer = instance.function.signature.self_param.as_ref().is_some_and(|sp| sp.is_pointer);
+        let self_arg = match (by_pointer, through_pointer) {
+            (true, true) => SelfArg::Pointer,
+            (true, false) => SelfArg::AddressOf,
+            (false, true) => SelfArg::Deref,
+            (false, false) => SelfArg::Value,
+        };
+        let items = self.tc.items;
+        let names = generic_names(items, instance.function);
+        let default = is_default_method(items, instance.function);
+        let (params, ret) = declared_signature(items, instance.function);
+        let (bindings, ret) =
+            self.infer_call(&names, instance.bindings.clone(), &params, &ret, &call.args, expected, expr)?;
+        instance.bindings = order(bindings, &names, default);
+        self.body.record_call(call, CallTarget::Function { instance, self_arg: Some(self_arg) });
+        Some(ret)
+    }
+
+    fn value_call(&mut self, call: &CallExpr, expr: &Expression) -> Option<Type> {
+        let function = match self.infer(&call.callee, None)? {
+            Type::Function(function) => function,
+            other => {
+                self.error(format!("cannot call a value of type `{}`", format_type(&other)), expr);
+                return None;
+            }
+        };
+        if function.params.len() != call.args.len() {
+            self.error(format!("expected {} argument(s), found {}", function.params.len(), call.args.len()), expr);
+            return None;
+        }
+        for (param, arg) in function.params.iter().zip(&call.args) {
+            self.check(arg, param)?;
+        }
+        let return_type = function.return_type.as_deref().cloned().unwrap_or(Type::Ok);
+        self.body.record_call(call, CallTarget::Value(function));
+        Some(return_type)
+    }
+
+    fn static_call(
+        &mut self,
+        call: &CallExpr,
+        callee: StaticCallee,
+        expected: Option<&Type>,
+        expr: &Expression,
+    ) -> Option<Type> {
+        let StaticCallee { segments, owner_args } = callee;
+        let items = self.tc.items;
+        let mut owner_bindings = Bindings::new();
+        for arg in &owner_args {
+            owner_bindings.push((String::new(), self.normalize(arg, expr)?));
+        }
+        let lookup = Path::with_generics(segments.clone(), Vec::new());
+
+        // A function, or a static method such as `Vec::new`
+        let function = match segments.as_slice() {
+            [name] => items.functions().iter().find(|f| f.signature.receiver.is_none() && f.qualified_name() == *name).or_else(|| {
+                items.lookup_function(&lookup).filter(|f| f.signature.receiver.is_none())
+            }),
+            _ => items.lookup_function(&lookup),
+        };
+        if let Some(function) = function {
+            let names = generic_names(items, function);
+            if is_default_method(items, function) {
+                self.error("default methods can only be called through a value", expr);
+                return None;
+            }
+            // Generic arguments on the owner bind the receiver's parameters
+            let bindings: Bindings = names.iter().cloned().zip(owner_bindings.into_iter().map(|(_, ty)| ty)).collect();
+            let (mut params, ret) = declared_signature(items, function);
+            if let Some(sp) = &function.signature.self_param {
+                // `Type::method(value, ...)` passes the receiver explicitly
+                let receiver = receiver_pattern(items, function);
+                params.insert(
+                    0,
+                    if sp.is_pointer {
+                        Type::Pointer { nullable: false, mutable: sp.is_mutable, element_type: Box::new(receiver) }
+                    } else {
+                        receiver
+                    },
+                );
+            }
+            let (bindings, ret) = self.infer_call(&names, bindings, &params, &ret, &call.args, expected, expr)?;
+            let instance = Instance { function, bindings: order(bindings, &names, false) };
+            self.body.record_call(call, CallTarget::Function { instance, self_arg: None });
+            return Some(ret);
+        }
+
+        // Positional construction of a struct
+        if let Some(TypeDef::Struct(s)) = items.lookup_type(&lookup) {
+            let names: Vec<String> = s.generic_params.iter().map(|p| p.name().to_string()).collect();
+            let bindings: Bindings = names.iter().cloned().zip(owner_bindings.into_iter().map(|(_, ty)| ty)).collect();
+            let params: Vec<Type> = s.fields.iter().map(|f| f.ty.clone()).collect();
+            let ty = Type::Path(Path::with_generics(
+                segments.clone(),
+                names.iter().map(|n| Type::Path(Path::simple(n.clone()))).collect(),
+            ));
+            let (_, ty) = self.infer_call(&names, bindings, &params, &ty, &call.args, expected, expr)?;
+            self.body.calls.insert(key(call), CallTarget::Construct(ty.clone()));
+            return Some(ty);
+        }
+
+        // Construction of a union variant
+        if let [owner @ .., variant] = segments.as_slice()
+            && !owner.is_empty()
+            && let Some(TypeDef::Union(u)) = items.lookup_type(&Path::with_generics(owner.to_vec(), Vec::new()))
+        {
+            let Some(v) = u.variants.iter().find(|v| v.name == *variant) else {
+                self.error(format!("`{}` has no variant `{}`", u.name, variant), expr);
+                return None;
+            };
+            let names: Vec<String> = u.generic_params.iter().map(|p| p.name().to_string()).collect();
+            let bindings: Bindings = names.iter().cloned().zip(owner_bindings.into_iter().map(|(_, ty)| ty)).collect();
+            let ty = Type::Path(Path::with_generics(
+                owner.to_vec(),
+                names.iter().map(|n| Type::Path(Path::simple(n.clone()))).collect(),
+            ));
+            let params = if v.ty == Type::Ok && call.args.is_empty() { vec![] } else { vec![v.ty.clone()] };
+            let (_, ty) = self.infer_call(&names, bindings, &params, &ty, &call.args, expected, expr)?;
+            self.body.calls.insert(key(call), CallTarget::Variant { union: ty.clone(), variant: variant.clone() });
+            return Some(ty);
+        }
+
+        if let [name] = segments.as_slice()
+            && let Some(builtin) = Builtin::from_name(name)
+        {
+            let ty = self.builtin(builtin, &call.args, expr)?;
+            self.body.calls.insert(key(call), CallTarget::Builtin(builtin));
+            return Some(ty);
+        }
+
+        self.error(format!("undefined function `{}`", segments.join("::")), expr);
+        None
+    }
+
+    fn builtin(&mut self, builtin: Builtin, args: &[Expression], expr: &Expression) -> Option<Type> {
+        let byte_pointer = Type::Pointer { nullable: false, mutable: true, element_type: Box::new(Type::U8) };
+        let arity = |this: &mut Self, n: usize| {
+            if args.len() == n {
+                Some(())
+            } else {
+                this.error(format!("expected {} argument(s), found {}", n, args.len()), expr);
+                None
+            }
+        };
+        let pointer = |this: &mut Self, arg: &Expression| match this.infer(arg, None)? {
+            Type::Pointer { .. } | Type::Null => Some(()),
+            other => {
+                this.error(format!("expected a pointer, found `{}`", format_type(&other)), arg);
+                None
+            }
+        };
+        match builtin {
+            Builtin::Print | Builtin::Println => {
+                for arg in args {
+                    self.infer(arg, None)?;
+                }
+                Some(Type::Ok)
+            }
+            Builtin::Assert => {
+                arity(self, 1)?;
+                self.check(&args[0], &Type::Bool)?;
+                Some(Type::Ok)
+            }
+            Builtin::Malloc => {
+                arity(self, 1)?;
+                self.check(&args[0], &Type::USize)?;
+                Some(byte_pointer)
+            }
+            Builtin::Calloc => {
+                arity(self, 2)?;
+                self.check(&args[0], &Type::USize)?;
+                self.check(&args[1], &Type::USize)?;
+                Some(byte_pointer)
+            }
+            Builtin::Realloc => {
+                arity(self, 2)?;
+                pointer(self, &args[0])?;
+                self.check(&args[1], &Type::USize)?;
+                Some(byte_pointer)
+            }
+            Builtin::Free => {
+                arity(self, 1)?;
+                pointer(self, &args[0])?;
+                Some(Type::Ok)
+            }
+        }
+    }
+
+    /// Check a call's arguments against the parameter types `params`,
+    /// binding the generic parameters `names` that `bindings` leaves open
+    /// from the argument types and the expected result. Returns the complete
+    /// bindings and the normalised result type.
+    #[allow(clippy::too_many_arguments)]
+    fn infer_call(
+        &mut self,
+        names: &[String],
+        mut bindings: Bindings,
+        params: &[Type],
+        ret: &Type,
+        args: &[impl Borrow<Expression>],
+        expected: Option<&Type>,
+        expr: &Expression,
+    ) -> Option<(Bindings, Type)> {
+        if params.len() != args.len() {
+            self.error(format!("expected {} argument(s), found {}", params.len(), args.len()), expr);
+            return None;
+        }
+        // Arguments whose type decides a parameter go first; literals wait
+        // until the parameter is known
+        let mut inferred: Vec<Option<Type>> = vec![None; args.len()];
+        for (i, (param, arg)) in params.iter().zip(args).enumerate() {
+            let arg = arg.borrow();
+            if mentions_unbound(param, names, &bindings) && !is_untyped(arg) {
+                let ty = self.infer(arg, None)?;
+                unify(param, &ty, names, &mut bindings);
+                inferred[i] = Some(ty);
+            }
+        }
+        if let Some(expected) = expected
+            && mentions_unbound(ret, names, &bindings)
+        {
+            unify(ret, expected, names, &mut bindings);
+        }
+        if let Some(name) = names.iter().find(|n| !bindings.iter().any(|(b, _)| b == *n)) {
+            self.error(format!("cannot infer the generic parameter `{}`", name), expr);
+            return None;
+        }
+        let mut outer = bindings.clone();
+        outer.extend(self.bindings.iter().filter(|(n, _)| !names.contains(n)).cloned());
+        for ((param, arg), inferred) in params.iter().zip(args).zip(inferred) {
+            let arg = arg.borrow();
+            let param = match self.tc.normalize(param, &outer) {
+                Ok(param) => param,
+                Err(message) => {
+                    self.error(message, expr);
+                    return None;
+                }
+            };
+            match inferred {
+                Some(actual) if !self.tc.assignable(&actual, &param) => {
+                    self.error(
+                        format!("mismatched types: expected `{}`, found `{}`", format_type(&param), format_type(&actual)),
+                        arg,
+                    );
+                    return None;
+                }
+                Some(_) => {}
+                None => {
+                    self.check(arg, &param)?;
+                }
+            }
+        }
+        match self.tc.normalize(ret, &outer) {
+            Ok(ret) => Some((bindings, ret)),
+            Err(message) => {
+                self.error(message, expr);
+                None
+            }
+        }
+    }
+}
+
+/// The normal form of a function type: one returning `ok` has no return type
+fn function_type(is_effect: bool, params: Vec<Type>, return_type: Type) -> Type {
+    let return_type = (return_type != Type::Ok).then(|| Box::new(return_type));
+    Type::Function(FunctionType { is_effect, params, return_type })
+}
+
+/// Put `bindings` in the order of `names`, followed by `Self` for a default
+/// method
+fn order(bindings: Bindings, names: &[String], keep_self: bool) -> Bindings {
+    let mut ordered: Bindings = names
+        .iter()
+        .filter_map(|name| bindings.iter().find(|(n, _)| n == name).cloned())
+        .collect();
+    if keep_self && let Some(bound) = bindings.into_iter().find(|(n, _)| n == "Self") {
+        ordered.push(bound);
+    }
+    ordered
+}
+
+/// The receiver of a method as a type over its generic parameter names,
+/// e.g. `Vec[T]` for `func Vec::push`
+fn receiver_pattern(items: &ItemTable, function: &FunctionDef) -> Type {
+    let mut path = function.signature.receiver.clone().unwrap_or_else(|| Path::simple(String::new()));
+    if path.generic_args.is_empty()
+        && let Some(def) = items.lookup_type(&path)
+    {
+        path.generic_args = def.generic_params().iter().map(|p| Type::Path(Path::simple(p.name().to_string()))).collect();
+    }
+    Type::Path(path)
+}
+
+/// A function's declared parameter and return types, with `Self` spelled
+/// out for a method of a concrete type so that inference can see through it
+fn declared_signature(items: &ItemTable, function: &FunctionDef) -> (Vec<Type>, Type) {
+    let signature = function.signature;
+    let receiver = (signature.receiver.is_some() && !is_default_method(items, function))
+        .then(|| receiver_pattern(items, function));
+    let spell = |ty: &Type| match &receiver {
+        Some(receiver) => replace_self(ty, receiver),
+        None => ty.clone(),
+    };
+    let params = signature.params.iter().map(|p| spell(&p.ty)).collect();
+    let ret = signature.return_types.first().map(spell).unwrap_or(Type::Ok);
+    (params, ret)
+}
+
+fn replace_self(ty: &Type, with: &Type) -> Type {
+    match ty {
+        Type::SelfType => with.clone(),
+        Type::Path(path) => Type::Path(Path {
+            segments: path.segments.clone(),
+            generic_args: path.generic_args.iter().map(|t| replace_self(t, with)).collect(),
+        }),
+        Type::Pointer { nullable, mutable, element_type } => Type::Pointer {
+            nullable: *nullable,
+            mutable: *mutable,
+            element_type: Box::new(replace_self(element_type, with)),
+        },
+        Type::Optional(inner) => Type::Optional(Box::new(replace_self(inner, with))),
+        Type::Array { element_type, size } => {
+            Type::Array { element_type: Box::new(replace_self(element_type, with)), size: size.clone() }
+        }
+        Type::ErrorUnion { ok_type, err_type } => {
+            Type::ErrorUnion { ok_type: Box::new(replace_self(ok_type, with)), err_type: err_type.clone() }
+        }
+        other => other.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse;
+
+    fn check(src: &str, function: &str) -> Result<(), Vec<String>> {
+        let sf = parse(src);
+        let items = ItemTable::from_source_file(&sf);
+        let function = items.lookup_function(&Path::simple(function.into())).unwrap();
+        let mut checker = TypeChecker::new(&items, Target::X86_64);
+        checker
+            .check(&Instance::new(function))
+            .map(|_| ())
+            .map_err(|diagnostics| diagnostics.into_iter().map(|d| d.message).collect())
+    }
+
+    #[test]
+    fn test_construction_expressions() {
+        let src = "\
+struct Pair[T]
+    first: T
+    second: T
+
+union Shape
+    Circle: f64
+    Empty: ok
+
+enum Color
+    Red
+    Green
+
+func main() -> i32
+    let first: i64 = 1
+    let p = Pair(first, second: 2)
+    let q = Pair(second: 5, ..p)
+    let r: Pair[i64] = Pair(..q)
+    let s = Shape::Circle(2.0)
+    let t = Shape::Empty
+    let c = Color::Green
+    return r.first as i32
+
+func wrong(p: Pair[i64]) -> ok
+    let o = Pair(first: 1, second: 2)
+    let q = Pair(first: 1i32, second: 2i64)
+    let r = Pair(first: 1i32)
+    let s = Pair(first: 1i32, ..p)
+    let t = Shape::Circle
+    let u = Color::Blue
+    pass
+";
+        let sf = crate::construct::resolve_construction(parse(src));
+        let items = ItemTable::from_source_file(&sf);
+        let mut checker = TypeChecker::new(&items, Target::X86_64);
+        let mut check = |name: &str| {
+            let function = items.lookup_function(&Path::simple(name.into())).unwrap();
+            checker
+                .check(&Instance::new(function))
+                .map(|_| ())
+                .map_err(|diagnostics| diagnostics.into_iter().map(|d| d.message).collect::<Vec<_>>())
+        };
+        assert_eq!(check("main"), Ok(()));
+        assert_eq!(
+            check("wrong"),
+            Err(vec![
+                "cannot infer the generic parameter `T`".to_string(),
+                "mismatched types: expected `i32`, found `i64`".to_string(),
+                "missing field `second` in `Pair`".to_string(),
+                "mismatched types: expected `Pair[i32]`, found `Pair[i64]`".to_string(),
+                "undefined name `Shape::Circle`".to_string(),
+                "`Color` has no variant `Blue`".to_string(),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_ranges() {
+        let src = "\
+func main(buf: [u8], n: usize) -> usize
+    let total: usize = 0
+    for i in 0..n
+        total += i
+    for j in 1..=3i64
+        pass
+    let middle = buf[2..n]
+    let head = buf[..n]
+    let all = buf[..]
+    return middle.len + head.len + all.len
+
+func wrong(buf: [u8], n: usize, k: i32) -> ok
+    for i in ..n
+        pass
+    for j in 0..1.5
+        pass
+    for k in n..k
+        pass
+    let r = 0..n
+    buf[0..1] = buf
+    let p = &n
+    let q = p[0..1]
+    pass
+";
+        let sf = parse(src);
+        let items = ItemTable::from_source_file(&sf);
+        let mut checker = TypeChecker::new(&items, Target::X86_64);
+        let main = items.lookup_function(&Path::simple("main".into())).unwrap();
+        let body = checker.check(&Instance::new(main)).unwrap();
+        let NamespaceItem::Function(f) = &sf.items[0] else { panic!("expected `main`") };
+        let Statement::For(for_stmt) = &f.body.statements[1] else { panic!("expected `for`") };
+        assert_eq!(body.iteration(for_stmt), Some(&Iteration::Range));
+        assert_eq!(body.local(&f.body.statements[1]), Some(&Type::USize));
+        assert_eq!(body.local(&f.body.statements[2]), Some(&Type::I64));
+        let slice = Type::Array { element_type: Box::new(Type::U8), size: None };
+        assert_eq!(body.local(&f.body.statements[3]), Some(&slice));
+
+        let wrong = items.lookup_function(&Path::simple("wrong".into())).unwrap();
+        let messages: Vec<String> =
+            checker.check(&Instance::new(wrong)).unwrap_err().into_iter().map(|d| d.message).collect();
+        assert_eq!(
+            messages,
+            [
+                "a range without a start cannot be iterated",
+                "range bounds must be integers, found `f64`",
+                "mismatched range bounds `usize` and `i32`",
+                "a range can only be iterated by `for` or used to slice",
+                "the left-hand side of an assignment must be a variable, field, element or dereference",
+                "cannot slice a value of type `*usize`",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_infers_generic_calls() {
+        let src = "\
+struct Pair[T]
+    first: T
+    second: T
+
+func[T] Pair[T]::swap(*mut self) -> ok
+    let first = self.first
+    self.first = self.second
+    self.second = first
+    pass
+
+func[T] largest(a: T, b: T) -> T
+    return a
+
+func main() -> u8
+    mut p = Pair(1u8, 2)
+    p.swap()
+    let q: Pair[i64] = Pair(3, 4)
+    return largest(p.first, 7)
+";
+        let sf = parse(src);
+        let items = ItemTable::from_source_file(&sf);
+        let main = items.lookup_function(&Path::simple("main".into())).unwrap();
+        let mut checker = TypeChecker::new(&items, Target::X86_64);
+        let body = checker.check(&Instance::new(main)).unwrap();
+        let mut callees: Vec<String> = body.callees().map(Instance::name).collect();
+        callees.sort();
+        assert_eq!(callees, vec!["Pair::swap[u8]", "largest[u8]"]);
+
+        let Some(Statement::Let(q)) = main.body.unwrap().statements.get(2) else { panic!("expected let") };
+        let Expression::Call(call) = q.value.as_ref() else { panic!("expected call") };
+        assert_eq!(body.type_of(&call.args[0]), Some(&Type::I64));
+    }
+
+    #[test]
+    fn test_implicit_conversions() {
+        let src = "\
+union Error
+    code: i32
+
+func find(xs: [i32], x: i32) -> ?usize
+    if xs.len == 0
+        return null
+    return 0
+
+func parse(x: i32) -> u8 ! Error
+    if x < 0
+        return Error::code(x)
+    return 1
+
+func main() -> ok
+    let xs = [1, 2, 3]
+    let found = find(xs, 2)
+    pass
+";
+        assert_eq!(check(src, "find"), Ok(()));
+        assert_eq!(check(src, "parse"), Ok(()));
+        assert_eq!(check(src, "main"), Ok(()));
+    }
+
+    #[test]
+    fn test_reports_type_errors() {
+        let src = "\
+struct Point
+    x: i32
+
+func main() -> ok
+    let p = Point(1)
+    let a: u8 = 300
+    let b: u64 = p.x
+    let c = p.y
+    pass
+";
+        assert_eq!(
+            check(src, "main"),
+            Err(vec![
+                "literal does not fit in `u8`".to_string(),
+                "mismatched types: expected `u64`, found `i32`".to_string(),
+                "`Point` has no field `y`".to_string(),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_lambdas_and_function_values() {
+        let src = "\
+func twice(f: fn(i32) -> i32, x: i32) -> i32
+    return f(f(x))
+
+func main() -> i32
+    let scale = 3
+    let triple = fn(x: i32) -> i32 => x * scale
+    let run: fn!(i32) = fn!(x: i32) => twice(triple, x)
+    run(1)
+    return twice(triple, 2) + (fn(a: i32, b: i32) -> i32 => a - b)(7, 4)
+
+func wrong() -> ok
+    let f = fn(x: i32) -> i32 => x
+    let g: fn!(i32) -> i32 = f
+    let n = 4
+    let y = f(true)
+    let z = f(1, 2)
+    let w = n(1)
+    pass
+";
+        assert_eq!(check(src, "main"), Ok(()));
+        assert_eq!(
+            check(src, "wrong"),
+            Err(vec![
+                "mismatched types: expected `fn!(i32) -> i32`, found `fn(i32) -> i32`".to_string(),
+                "mismatched types: expected `i32`, found `bool`".to_string(),
+                "expected 1 argument(s), found 2".to_string(),
+                "cannot call a value of type `i32`".to_string(),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_lambda_propagates_into_its_own_return_type() {
+        let src = "\
+struct E
+    code: i32
+
+func parse(x: i32) -> i32 ! E
+    return x
+
+func outer() -> i32 ! E
+    let f = fn(x: i32) -> i32 => parse!(x)
+    return parse!(1)
+
+func inner() -> i32 ! E
+    let f = fn(x: i32) -> i32 ! E => parse!(x)
+    return f(1)
+";
+        assert_eq!(check(src, "inner"), Ok(()));
+        assert_eq!(
+            check(src, "outer"),
+            Err(vec!["error propagation in a function that does not return an error union".to_string()])
+        );
+    }
+}
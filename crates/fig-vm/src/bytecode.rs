@@ -0,0 +1,521 @@
+//! Bytecode instructions, compiled modules and the disassembler
+//!
+//! The VM is a stack machine over 64-bit slots. Scalars (integers, `bool`,
+//! floats, enums, pointers and `?*T`) are pushed by value: integers
+//! sign- or zero-extended to 64 bits, floats as the bits of an `f64` (an
+//! `f32` is rounded to single precision after every operation). Everything
+//! else lives in memory and is handled through its address.
+//!
+//! # Calling convention
+//!
+//! - The caller pushes the arguments left to right: scalars by value,
+//!   aggregates by address. The callee copies aggregate arguments into its
+//!   own frame, so they are passed by value.
+//! - A function returning an aggregate takes the address to write the
+//!   result to as a hidden first argument, stored at frame offset 0. Any
+//!   other function leaves its result on the operand stack (`ok` is a 0).
+//! - Each call bump-allocates the callee's frame on the VM stack, 16-byte
+//!   aligned; locals sit at fixed offsets in it. Returning pops the frame.
+//!
+//! The [`Display`](fmt::Display) impl of [`Module`] is the disassembler:
+//!
+//! ```text
+//! fn square(i32) -> i32  ; frame 16
+//!     0  local 4
+//!     1  local 0
+//!     2  load i32
+//!     3  local 0
+//!     4  load i32
+//!     5  mul i32
+//!     6  store i32
+//!     7  local 4
+//!     8  load i32
+//!     9  ret
+//! ```
+
+use std::fmt;
+
+/// Width and signedness of an integer operand, named as in source
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntKind {
+    U8,
+    U16,
+    U32,
+    U64,
+    USize,
+    I8,
+    I16,
+    I32,
+    I64,
+    ISize,
+}
+
+impl IntKind {
+    pub fn bits(self) -> u32 {
+        match self {
+            IntKind::U8 | IntKind::I8 => 8,
+            IntKind::U16 | IntKind::I16 => 16,
+            IntKind::U32 | IntKind::I32 => 32,
+            IntKind::U64 | IntKind::I64 | IntKind::USize | IntKind::ISize => 64,
+        }
+    }
+
+    pub fn signed(self) -> bool {
+        matches!(self, IntKind::I8 | IntKind::I16 | IntKind::I32 | IntKind::I64 | IntKind::ISize)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            IntKind::U8 => "u8",
+            IntKind::U16 => "u16",
+            IntKind::U32 => "u32",
+            IntKind::U64 => "u64",
+            IntKind::USize => "usize",
+            IntKind::I8 => "i8",
+            IntKind::I16 => "i16",
+            IntKind::I32 => "i32",
+            IntKind::I64 => "i64",
+            IntKind::ISize => "isize",
+        }
+    }
+}
+
+/// How a scalar is stored in memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scalar {
+    /// `ok` and `null`: zero bytes, read as 0
+    Unit,
+    U8,
+    I8,
+    U16,
+    I16,
+    U32,
+    I32,
+    U64,
+    I64,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    pub fn size(self) -> u64 {
+        match self {
+            Scalar::Unit => 0,
+            Scalar::U8 | Scalar::I8 => 1,
+            Scalar::U16 | Scalar::I16 => 2,
+            Scalar::U32 | Scalar::I32 | Scalar::F32 => 4,
+            Scalar::U64 | Scalar::I64 | Scalar::F64 => 8,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Scalar::Unit => "unit",
+            Scalar::U8 => "u8",
+            Scalar::I8 => "i8",
+            Scalar::U16 => "u16",
+            Scalar::I16 => "i16",
+            Scalar::U32 => "u32",
+            Scalar::I32 => "i32",
+            Scalar::U64 => "u64",
+            Scalar::I64 => "i64",
+            Scalar::F32 => "f32",
+            Scalar::F64 => "f64",
+        }
+    }
+}
+
+/// How two scalars are ordered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CmpKind {
+    Signed,
+    /// Also `bool`, pointers and unsigned enums
+    Unsigned,
+    Float,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FloatOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+/// One instruction. Stack effects are written `[before] -> [after]`, top
+/// of the stack last.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    /// `[] -> [value]`
+    Const(u64),
+    /// `[] -> [frame + offset]`
+    Local(u32),
+    /// `[a] -> [a, a]`
+    Dup,
+    /// `[a] -> []`
+    Pop,
+    /// `[address] -> [value]`
+    Load(Scalar),
+    /// `[address, value] -> []`
+    Store(Scalar),
+    /// `[dest, src] -> []`: copy this many bytes
+    Copy(u32),
+    /// `[address] -> [address + n]`
+    Offset(u32),
+    /// `[address] -> [address]`, trapping on null
+    NonNull,
+    /// `[array, index] -> [element]` for an array of `len` elements
+    IndexArray { len: u32, stride: u32, signed: bool },
+    /// `[slice, index] -> [element]`, bounds-checked against the slice's `len`
+    IndexSlice { stride: u32, signed: bool },
+    /// `[dest, array, start, end] -> []`: store the slice over elements
+    /// `start..end` of an array of `len` elements
+    SubsliceArray { len: u32, stride: u32 },
+    /// `[dest, slice, start, end] -> []`, checked against the slice's `len`
+    SubsliceSlice { stride: u32 },
+    /// `[pointer, index] -> [pointer + index * stride]`
+    PtrAdd { stride: u32, signed: bool },
+    /// `[pointer, index] -> [pointer - index * stride]`
+    PtrSub { stride: u32, signed: bool },
+    /// `[a, b] -> [(a - b) / stride]`, an `isize`
+    PtrDiff { stride: u32 },
+    /// `[union] -> [union]`, trapping unless the variant is active
+    CheckTag { union: u32, variant: u32 },
+    /// `[union] -> [union]`: make the variant the active one
+    SetTag { union: u32, variant: u32 },
+
+    /// Checked integer arithmetic: `[a, b] -> [a op b]`
+    Add(IntKind),
+    Sub(IntKind),
+    Mul(IntKind),
+    Div(IntKind),
+    Rem(IntKind),
+    /// `[a, amount] -> [a << amount]`, trapping on an amount out of range
+    Shl(IntKind),
+    Shr(IntKind),
+    And,
+    Or,
+    Xor,
+    /// `[a] -> [-a]`, checked
+    Neg(IntKind),
+    /// `[a] -> [!a]` on the bits of the integer type
+    BitNot(IntKind),
+    /// `[bool] -> [!bool]`
+    Not,
+    /// `[a, b] -> [a op b]` in single or double precision
+    Float(FloatOp, bool),
+    /// `[a] -> [-a]` in single or double precision
+    FNeg(bool),
+    /// `[a, b] -> [bool]`
+    Cmp(CmpOp, CmpKind),
+
+    /// `[int] -> [int]`: wrap to the target type
+    IntCast(IntKind),
+    /// `[float] -> [int]`, saturating, NaN becomes 0
+    FloatToInt(IntKind),
+    /// `[int] -> [float]`, the integer read as `IntKind`
+    IntToFloat(IntKind, bool),
+    /// `[float] -> [float]`, rounding when converting to single precision
+    FloatCast(bool),
+    /// `[int] -> [bool]`: whether it is non-zero
+    ToBool,
+    /// `[int] -> [int]`, trapping unless it is a discriminant of the enum
+    CheckEnum(u32),
+
+    Jump(u32),
+    /// `[bool] -> []`
+    JumpIf(u32),
+    /// `[bool] -> []`
+    JumpIfNot(u32),
+    /// Call a function of the module, see the calling convention
+    Call(u32),
+    /// Call an `extern` function
+    CallExtern(u32),
+    /// Return from the function; a scalar result is on the stack
+    Return,
+    /// Stop with the message at this index
+    Trap(u32),
+
+    /// `[value or address] -> []`: print with the format at this index
+    Print(u32),
+    /// `[] -> []`: write one byte of output
+    PrintByte(u8),
+    /// `[bool] -> []`
+    Assert,
+    /// `[size] -> [pointer]`
+    Malloc,
+    /// `[count, size] -> [pointer]`
+    Calloc,
+    /// `[pointer, size] -> [pointer]`
+    Realloc,
+    /// `[pointer] -> []`
+    Free,
+}
+
+/// How a parameter is passed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Param {
+    /// A scalar, stored at the offset
+    Scalar { offset: u32, scalar: Scalar },
+    /// An aggregate of `size` bytes, copied to the offset
+    Aggregate { offset: u32, size: u32 },
+}
+
+/// A compiled function instance
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    /// The MIR instance name, e.g. `Vec::push[i32]`
+    pub name: String,
+    pub params: Vec<Param>,
+    /// Whether the result is written through a hidden first argument
+    pub sret: bool,
+    /// Size of the result when it is written through memory, else 0
+    pub result_size: u32,
+    /// Printed in the disassembly only
+    pub signature: String,
+    pub frame_size: u32,
+    pub code: Vec<Op>,
+}
+
+/// How [`Op::Print`] shows a value. Scalar formats print the value on the
+/// stack; the others read the value at the address on the stack.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Format {
+    Int(IntKind),
+    Float(bool),
+    Bool,
+    Ok,
+    Null,
+    /// A pointer, printed as its address
+    Pointer,
+    /// An enum, printed as `Name::Variant`
+    Enum { name: String, scalar: Scalar, variants: Vec<(i128, String)> },
+    /// A `[u8]` slice, printed as text
+    Bytes,
+    Array { element: Box<Format>, scalar: Option<Scalar>, count: u64, stride: u64 },
+    /// A `?*T`, null when zero
+    NullablePointer,
+    /// A tagged `?T`: tag 1 and the payload at `offset`, or tag 0 for null
+    Optional { payload: Box<Format>, scalar: Option<Scalar>, offset: u64 },
+    /// `T ! E`: tag 0 for the success value, 1 for the error
+    ErrorUnion { ok: Box<Format>, ok_scalar: Option<Scalar>, err: Box<Format>, err_scalar: Option<Scalar>, offset: u64 },
+    Struct { name: String, fields: Vec<(String, u64, Format, Option<Scalar>)> },
+    /// A union: the tag is the variant index. `None` formats mark `ok` payloads.
+    Union { name: String, tag: Scalar, offset: u64, variants: Vec<(String, Option<Member>)> },
+}
+
+/// The format of a value inside another, and the scalar to load it as when
+/// it is not printed by address
+pub type Member = (Format, Option<Scalar>);
+
+impl Format {
+    /// Whether the value to print is on the stack rather than in memory
+    pub fn is_scalar(&self) -> bool {
+        matches!(
+            self,
+            Format::Int(_)
+                | Format::Float(_)
+                | Format::Bool
+                | Format::Ok
+                | Format::Null
+                | Format::Pointer
+                | Format::Enum { .. }
+                | Format::NullablePointer
+        )
+    }
+}
+
+/// Names for the message of [`Op::CheckTag`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnionInfo {
+    pub name: String,
+    pub tag: Scalar,
+    pub variants: Vec<String>,
+}
+
+/// Discriminants for [`Op::CheckEnum`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnumInfo {
+    pub name: String,
+    pub discriminants: Vec<i128>,
+}
+
+/// A compiled program
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Module {
+    pub functions: Vec<Function>,
+    /// Names of the `extern` functions [`Op::CallExtern`] refers to
+    pub externs: Vec<String>,
+    /// Initial contents of static memory: string literals and constants
+    pub data: Vec<u8>,
+    pub formats: Vec<Format>,
+    pub unions: Vec<UnionInfo>,
+    pub enums: Vec<EnumInfo>,
+    /// Messages for [`Op::Trap`]
+    pub messages: Vec<String>,
+    /// The entry point, if the program has a `main`
+    pub main: Option<Main>,
+}
+
+/// How to call `main` and show its result
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Main {
+    pub function: u32,
+    /// Format of the result, `None` when it cannot be printed
+    pub format: Option<u32>,
+}
+
+impl Module {
+    pub fn function(&self, name: &str) -> Option<(u32, &Function)> {
+        self.functions.iter().enumerate().find(|(_, f)| f.name == name).map(|(i, f)| (i as u32, f))
+    }
+}
+
+// ============================================================================
+// Disassembler
+// ============================================================================
+
+impl fmt::Display for IntKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl fmt::Display for Scalar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+fn precision(single: bool) -> &'static str {
+    if single { "f32" } else { "f64" }
+}
+
+fn sign(signed: bool) -> &'static str {
+    if signed { "signed" } else { "unsigned" }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Op::Const(value) => write!(f, "const {}", value),
+            Op::Local(offset) => write!(f, "local {}", offset),
+            Op::Dup => write!(f, "dup"),
+            Op::Pop => write!(f, "pop"),
+            Op::Load(scalar) => write!(f, "load {}", scalar),
+            Op::Store(scalar) => write!(f, "store {}", scalar),
+            Op::Copy(size) => write!(f, "copy {}", size),
+            Op::Offset(offset) => write!(f, "offset {}", offset),
+            Op::NonNull => write!(f, "nonnull"),
+            Op::IndexArray { len, stride, signed } => {
+                write!(f, "index.array len {} stride {} {}", len, stride, sign(*signed))
+            }
+            Op::IndexSlice { stride, signed } => write!(f, "index.slice stride {} {}", stride, sign(*signed)),
+            Op::SubsliceArray { len, stride } => write!(f, "subslice.array len {} stride {}", len, stride),
+            Op::SubsliceSlice { stride } => write!(f, "subslice.slice stride {}", stride),
+            Op::PtrAdd { stride, signed } => write!(f, "ptr.add stride {} {}", stride, sign(*signed)),
+            Op::PtrSub { stride, signed } => write!(f, "ptr.sub stride {} {}", stride, sign(*signed)),
+            Op::PtrDiff { stride } => write!(f, "ptr.diff stride {}", stride),
+            Op::CheckTag { union, variant } => write!(f, "tag.check #{} {}", union, variant),
+            Op::SetTag { union, variant } => write!(f, "tag.set #{} {}", union, variant),
+            Op::Add(kind) => write!(f, "add {}", kind),
+            Op::Sub(kind) => write!(f, "sub {}", kind),
+            Op::Mul(kind) => write!(f, "mul {}", kind),
+            Op::Div(kind) => write!(f, "div {}", kind),
+            Op::Rem(kind) => write!(f, "rem {}", kind),
+            Op::Shl(kind) => write!(f, "shl {}", kind),
+            Op::Shr(kind) => write!(f, "shr {}", kind),
+            Op::And => write!(f, "and"),
+            Op::Or => write!(f, "or"),
+            Op::Xor => write!(f, "xor"),
+            Op::Neg(kind) => write!(f, "neg {}", kind),
+            Op::BitNot(kind) => write!(f, "bitnot {}", kind),
+            Op::Not => write!(f, "not"),
+            Op::Float(op, single) => {
+                let name = match op {
+                    FloatOp::Add => "fadd",
+                    FloatOp::Sub => "fsub",
+                    FloatOp::Mul => "fmul",
+                    FloatOp::Div => "fdiv",
+                };
+                write!(f, "{} {}", name, precision(*single))
+            }
+            Op::FNeg(single) => write!(f, "fneg {}", precision(*single)),
+            Op::Cmp(op, kind) => {
+                let op = match op {
+                    CmpOp::Eq => "eq",
+                    CmpOp::Ne => "ne",
+                    CmpOp::Lt => "lt",
+                    CmpOp::Le => "le",
+                    CmpOp::Gt => "gt",
+                    CmpOp::Ge => "ge",
+                };
+                let kind = match kind {
+                    CmpKind::Signed => "signed",
+                    CmpKind::Unsigned => "unsigned",
+                    CmpKind::Float => "float",
+                };
+                write!(f, "cmp.{} {}", op, kind)
+            }
+            Op::IntCast(kind) => write!(f, "cast {}", kind),
+            Op::FloatToInt(kind) => write!(f, "ftoi {}", kind),
+            Op::IntToFloat(kind, single) => write!(f, "itof {} {}", kind, precision(*single)),
+            Op::FloatCast(single) => write!(f, "fcast {}", precision(*single)),
+            Op::ToBool => write!(f, "tobool"),
+            Op::CheckEnum(index) => write!(f, "enum.check #{}", index),
+            Op::Jump(target) => write!(f, "jump {}", target),
+            Op::JumpIf(target) => write!(f, "jump.if {}", target),
+            Op::JumpIfNot(target) => write!(f, "jump.ifnot {}", target),
+            Op::Call(index) => write!(f, "call #{}", index),
+            Op::CallExtern(index) => write!(f, "call.extern #{}", index),
+            Op::Return => write!(f, "ret"),
+            Op::Trap(index) => write!(f, "trap #{}", index),
+            Op::Print(index) => write!(f, "print #{}", index),
+            Op::PrintByte(byte) => write!(f, "print.byte {:?}", *byte as char),
+            Op::Assert => write!(f, "assert"),
+            Op::Malloc => write!(f, "malloc"),
+            Op::Calloc => write!(f, "calloc"),
+            Op::Realloc => write!(f, "realloc"),
+            Op::Free => write!(f, "free"),
+        }
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "fn {}  ; frame {}", self.signature, self.frame_size)?;
+        let width = self.code.len().saturating_sub(1).to_string().len();
+        for (pc, op) in self.code.iter().enumerate() {
+            writeln!(f, "    {:>width$}  {}", pc, op, width = width)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, name) in self.externs.iter().enumerate() {
+            writeln!(f, "extern #{} {}", i, name)?;
+        }
+        if !self.data.is_empty() {
+            writeln!(f, "data {} bytes", self.data.len())?;
+        }
+        for (i, function) in self.functions.iter().enumerate() {
+            if i > 0 || !self.externs.is_empty() || !self.data.is_empty() {
+                writeln!(f)?;
+            }
+            write!(f, "; #{}\n{}", i, function)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,1265 @@
+//! Compilation of MIR to bytecode
+//!
+//! Each MIR local gets a fixed offset in its function's frame, laid out with
+//! the layout engine for a 64-bit little-endian target, so `sizeof` and
+//! field offsets agree with the C backend on x86-64. A place compiles to
+//! code computing its address; a scalar operand is then loaded from it,
+//! while an aggregate is copied by address. Blocks are emitted in order,
+//! with jumps resolved once every block's position is known.
+
+use std::collections::HashMap;
+
+use fig_mir::ir::{self as mir, AggregateKind, BinOp, BlockId, Body, Callee, Constant, Operand, Place, Projection, Rvalue, UnOp};
+use fig_parser::ast::Type;
+use fig_parser::format::format_type;
+use fig_sema::diagnostics::Diagnostic;
+use fig_sema::items::{ItemTable, TypeDef};
+use fig_sema::layout::{Layout, Shape, Target};
+use fig_sema::propagation::ErrorConversion;
+use fig_sema::typeck::{Builtin, TypeChecker, is_float};
+
+use crate::bytecode::*;
+use crate::memory::STATIC_BASE;
+
+/// The target whose layouts the VM uses
+pub const TARGET: Target = Target::X86_64;
+
+/// Lower, verify and compile every function reachable from the program's roots
+pub fn compile_items(items: &ItemTable) -> Result<Module, Vec<Diagnostic>> {
+    let program = fig_mir::lower_program(items, TARGET)?;
+    let errors = fig_mir::verify(&program);
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    compile(items, &program)
+}
+
+/// Compile a verified MIR program
+pub fn compile(items: &ItemTable, program: &mir::Program) -> Result<Module, Vec<Diagnostic>> {
+    let mut compiler = Compiler {
+        program,
+        items,
+        tc: TypeChecker::new(items, TARGET),
+        module: Module::default(),
+        functions: HashMap::new(),
+        externs: HashMap::new(),
+        strings: HashMap::new(),
+        zeros: HashMap::new(),
+        formats: HashMap::new(),
+        unions: HashMap::new(),
+        enums: HashMap::new(),
+        diagnostics: Vec::new(),
+    };
+    for (i, body) in program.functions.iter().enumerate() {
+        compiler.functions.insert(body.name.clone(), i as u32);
+    }
+    for (i, decl) in program.externs.iter().enumerate() {
+        compiler.externs.insert(decl.name.clone(), i as u32);
+        compiler.module.externs.push(decl.name.clone());
+    }
+    for body in &program.functions {
+        let function = FunctionCompiler::compile(&mut compiler, body);
+        compiler.module.functions.push(function);
+    }
+    if let Some(main) = program.function("main") {
+        compiler.module.main = compiler.main(main);
+    }
+    if compiler.diagnostics.is_empty() { Ok(compiler.module) } else { Err(compiler.diagnostics) }
+}
+
+fn int_kind(ty: &Type) -> Option<IntKind> {
+    Some(match ty {
+        Type::U8 => IntKind::U8,
+        Type::U16 => IntKind::U16,
+        Type::U32 => IntKind::U32,
+        Type::U64 => IntKind::U64,
+        Type::USize => IntKind::USize,
+        Type::I8 => IntKind::I8,
+        Type::I16 => IntKind::I16,
+        Type::I32 => IntKind::I32,
+        Type::I64 => IntKind::I64,
+        Type::ISize => IntKind::ISize,
+        _ => return None,
+    })
+}
+
+/// The integer kind of a scalar holding an enum discriminant
+pub(crate) fn enum_kind(scalar: Scalar) -> IntKind {
+    match scalar {
+        Scalar::I8 => IntKind::I8,
+        Scalar::I16 => IntKind::I16,
+        Scalar::I32 => IntKind::I32,
+        Scalar::I64 => IntKind::I64,
+        Scalar::U16 => IntKind::U16,
+        Scalar::U32 => IntKind::U32,
+        Scalar::U64 => IntKind::U64,
+        _ => IntKind::U8,
+    }
+}
+
+fn int_scalar(size: u64, signed: bool) -> Scalar {
+    match (size, signed) {
+        (1, false) => Scalar::U8,
+        (1, true) => Scalar::I8,
+        (2, false) => Scalar::U16,
+        (2, true) => Scalar::I16,
+        (4, false) => Scalar::U32,
+        (4, true) => Scalar::I32,
+        (_, false) => Scalar::U64,
+        (_, true) => Scalar::I64,
+    }
+}
+
+fn align_up(value: u64, align: u64) -> u64 {
+    value.div_ceil(align.max(1)) * align.max(1)
+}
+
+/// How a value of some type is represented
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Repr {
+    Scalar(Scalar),
+    /// In memory, with this size in bytes
+    Memory(u64),
+}
+
+/// What a place is computed for, which decides how union variants are checked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Access {
+    /// Trap unless each variant on the path is active
+    Read,
+    /// Make the variants on the path active
+    Write,
+    /// Neither, for `&place`
+    Address,
+}
+
+struct Compiler<'p, 'a> {
+    program: &'p mir::Program,
+    items: &'a ItemTable<'a>,
+    tc: TypeChecker<'a>,
+    module: Module,
+    functions: HashMap<String, u32>,
+    externs: HashMap<String, u32>,
+    /// Static addresses of string bytes and of `[u8]` slices over them
+    strings: HashMap<(String, bool), u64>,
+    /// Static all-zero blocks by size, the value of a tagged `null`
+    zeros: HashMap<u64, u64>,
+    formats: HashMap<String, u32>,
+    unions: HashMap<String, u32>,
+    enums: HashMap<String, u32>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Compiler<'_, 'a> {
+    fn error(&mut self, function: &str, message: impl Into<String>) {
+        self.diagnostics.push(Diagnostic::error(message).in_function(function));
+    }
+
+    fn layout(&mut self, ty: &Type) -> Layout {
+        match self.tc.layout().layout_of(ty) {
+            Ok(layout) => layout,
+            Err(e) => {
+                self.diagnostics.push(Diagnostic::error(e.to_string()));
+                Layout { size: 0, align: 1, shape: Shape::Scalar { non_null: false } }
+            }
+        }
+    }
+
+    fn repr(&mut self, ty: &Type) -> Repr {
+        let scalar = match ty {
+            Type::Bool | Type::U8 => Scalar::U8,
+            Type::I8 => Scalar::I8,
+            Type::U16 => Scalar::U16,
+            Type::I16 => Scalar::I16,
+            Type::U32 => Scalar::U32,
+            Type::I32 => Scalar::I32,
+            Type::U64 | Type::USize | Type::Pointer { .. } => Scalar::U64,
+            Type::I64 | Type::ISize => Scalar::I64,
+            Type::F32 => Scalar::F32,
+            Type::F64 => Scalar::F64,
+            Type::Ok | Type::Null => Scalar::Unit,
+            _ => {
+                let layout = self.layout(ty);
+                return match layout.shape {
+                    Shape::Enum { signed, .. } => Repr::Scalar(int_scalar(layout.size, signed)),
+                    Shape::Niche { .. } => Repr::Scalar(Scalar::U64),
+                    _ => Repr::Memory(layout.size),
+                };
+            }
+        };
+        Repr::Scalar(scalar)
+    }
+
+    fn type_def(&self, ty: &Type) -> Option<TypeDef<'a>> {
+        match ty {
+            Type::Path(path) => self.items.lookup_type(path),
+            _ => None,
+        }
+    }
+
+    /// The declared name of a named type, without generic arguments
+    fn type_name(&self, ty: &Type) -> String {
+        self.type_def(ty).map_or_else(|| format_type(ty), |def| def.name().to_string())
+    }
+
+    /// Field or variant names and types of a struct or union
+    fn fields(&mut self, ty: &Type) -> Vec<(String, Type)> {
+        match self.tc.fields(ty) {
+            Ok(fields) => fields,
+            Err(message) => {
+                self.diagnostics.push(Diagnostic::error(message));
+                Vec::new()
+            }
+        }
+    }
+
+    /// Offset of a named field or variant payload in a layout
+    fn offset(&mut self, layout: &Layout, name: &str) -> u64 {
+        let offset = layout.field(name).map(|field| field.offset);
+        offset.unwrap_or_else(|| {
+            self.diagnostics.push(Diagnostic::error(format!("no field `{}` in layout", name)));
+            0
+        })
+    }
+
+    /// The scalar holding the tag of a tagged layout
+    fn tag(layout: &Layout) -> Scalar {
+        match layout.shape {
+            Shape::Tagged { tag_size: 2, .. } => Scalar::U16,
+            Shape::Tagged { tag_size: 4, .. } => Scalar::U32,
+            _ => Scalar::U8,
+        }
+    }
+
+    /// Append bytes to static data, returning their address
+    fn data(&mut self, bytes: &[u8], align: u64) -> u64 {
+        let offset = align_up(self.module.data.len() as u64, align);
+        self.module.data.resize(offset as usize, 0);
+        self.module.data.extend_from_slice(bytes);
+        STATIC_BASE + offset
+    }
+
+    /// The address of a string's bytes, NUL-terminated for C, or of a slice over them
+    fn string(&mut self, text: &str, slice: bool) -> u64 {
+        if let Some(&address) = self.strings.get(&(text.to_string(), slice)) {
+            return address;
+        }
+        let address = if slice {
+            let bytes = self.string(text, false);
+            let mut pair = bytes.to_le_bytes().to_vec();
+            pair.extend_from_slice(&(text.len() as u64).to_le_bytes());
+            self.data(&pair, 8)
+        } else {
+            let mut bytes = text.as_bytes().to_vec();
+            bytes.push(0);
+            self.data(&bytes, 1)
+        };
+        self.strings.insert((text.to_string(), slice), address);
+        address
+    }
+
+    fn zeros(&mut self, size: u64) -> u64 {
+        if let Some(&address) = self.zeros.get(&size) {
+            return address;
+        }
+        let address = self.data(&vec![0; size as usize], 16);
+        self.zeros.insert(size, address);
+        address
+    }
+
+    fn message(&mut self, message: String) -> u32 {
+        self.module.messages.push(message);
+        self.module.messages.len() as u32 - 1
+    }
+
+    fn union_info(&mut self, ty: &Type) -> u32 {
+        let key = format_type(ty);
+        if let Some(&index) = self.unions.get(&key) {
+            return index;
+        }
+        let layout = self.layout(ty);
+        let variants = self.fields(ty).into_iter().map(|(name, _)| name).collect();
+        let name = self.type_name(ty);
+        self.module.unions.push(UnionInfo { name, tag: Self::tag(&layout), variants });
+        let index = self.module.unions.len() as u32 - 1;
+        self.unions.insert(key, index);
+        index
+    }
+
+    fn variant_index(&mut self, ty: &Type, variant: &str) -> (u32, u32) {
+        let union = self.union_info(ty);
+        let index = self.module.unions[union as usize].variants.iter().position(|v| v == variant).unwrap_or(0);
+        (union, index as u32)
+    }
+
+    fn enum_info(&mut self, ty: &Type) -> u32 {
+        let key = format_type(ty);
+        if let Some(&index) = self.enums.get(&key) {
+            return index;
+        }
+        let discriminants = match self.layout(ty).shape {
+            Shape::Enum { discriminants, .. } => discriminants.into_iter().map(|(_, value)| value).collect(),
+            _ => Vec::new(),
+        };
+        let name = self.type_name(ty);
+        self.module.enums.push(EnumInfo { name, discriminants });
+        let index = self.module.enums.len() as u32 - 1;
+        self.enums.insert(key, index);
+        index
+    }
+
+    fn scalar_of(&mut self, ty: &Type) -> Option<Scalar> {
+        match self.repr(ty) {
+            Repr::Scalar(scalar) => Some(scalar),
+            Repr::Memory(_) => None,
+        }
+    }
+
+    /// How `print` shows a value of `ty`
+    fn format(&mut self, ty: &Type) -> Result<Format, String> {
+        if let Some(kind) = int_kind(ty) {
+            return Ok(Format::Int(kind));
+        }
+        let layout = self.layout(ty);
+        Ok(match ty {
+            Type::F32 | Type::F64 => Format::Float(*ty == Type::F32),
+            Type::Bool => Format::Bool,
+            Type::Ok => Format::Ok,
+            Type::Null => Format::Null,
+            Type::Pointer { .. } => Format::Pointer,
+            Type::Optional(_) if matches!(layout.shape, Shape::Niche { .. }) => Format::NullablePointer,
+            Type::Optional(inner) => Format::Optional {
+                payload: Box::new(self.format(inner)?),
+                scalar: self.scalar_of(inner),
+                offset: self.offset(&layout, "some"),
+            },
+            Type::ErrorUnion { ok_type, err_type } => {
+                let err_type = Type::Path(err_type.clone());
+                Format::ErrorUnion {
+                    ok: Box::new(self.format(ok_type)?),
+                    ok_scalar: self.scalar_of(ok_type),
+                    err: Box::new(self.format(&err_type)?),
+                    err_scalar: self.scalar_of(&err_type),
+                    offset: self.offset(&layout, "ok"),
+                }
+            }
+            Type::Array { element_type, size: None } if **element_type == Type::U8 => Format::Bytes,
+            Type::Array { element_type, size: Some(_) } => {
+                let Shape::Array { element, count } = &layout.shape else { unreachable!("arrays have array layouts") };
+                Format::Array {
+                    element: Box::new(self.format(element_type)?),
+                    scalar: self.scalar_of(element_type),
+                    count: *count,
+                    stride: element.size,
+                }
+            }
+            Type::Path(_) => match (self.type_def(ty), &layout.shape) {
+                (_, Shape::Enum { discriminants, signed }) => Format::Enum {
+                    name: self.type_name(ty),
+                    scalar: int_scalar(layout.size, *signed),
+                    variants: discriminants.iter().map(|(name, value)| (*value, name.clone())).collect(),
+                },
+                (Some(TypeDef::Struct(_)), _) => {
+                    let mut fields = Vec::new();
+                    for (name, field_type) in self.fields(ty) {
+                        let offset = self.offset(&layout, &name);
+                        let format = self.format(&field_type)?;
+                        fields.push((name, offset, format, self.scalar_of(&field_type)));
+                    }
+                    Format::Struct { name: self.type_name(ty), fields }
+                }
+                (Some(TypeDef::Union(_)), _) => {
+                    let mut variants = Vec::new();
+                    let mut offset = 0;
+                    for (name, payload_type) in self.fields(ty) {
+                        offset = self.offset(&layout, &name);
+                        let payload = if payload_type == Type::Ok {
+                            None
+                        } else {
+                            Some((self.format(&payload_type)?, self.scalar_of(&payload_type)))
+                        };
+                        variants.push((name, payload));
+                    }
+                    Format::Union { name: self.type_name(ty), tag: Self::tag(&layout), offset, variants }
+                }
+                _ => return Err(format!("cannot print a value of type `{}`", format_type(ty))),
+            },
+            _ => return Err(format!("cannot print a value of type `{}`", format_type(ty))),
+        })
+    }
+
+    fn format_index(&mut self, ty: &Type) -> Result<u32, String> {
+        let key = format_type(ty);
+        if let Some(&index) = self.formats.get(&key) {
+            return Ok(index);
+        }
+        let format = self.format(ty)?;
+        self.module.formats.push(format);
+        let index = self.module.formats.len() as u32 - 1;
+        self.formats.insert(key, index);
+        Ok(index)
+    }
+
+    /// How `fig run` calls `main` and reports its result
+    fn main(&mut self, main: &Body) -> Option<Main> {
+        if main.arg_count != 0 {
+            self.error("main", "`main` must not take parameters");
+            return None;
+        }
+        let function = self.functions["main"];
+        let format = match self.format_index(&main.return_type) {
+            Ok(format) => Some(format),
+            Err(message) => {
+                self.error("main", message);
+                None
+            }
+        };
+        Some(Main { function, format })
+    }
+}
+
+/// State while compiling one function
+struct FunctionCompiler<'c, 'p, 'a> {
+    c: &'c mut Compiler<'p, 'a>,
+    body: &'p Body,
+    /// Frame offset of each local
+    offsets: Vec<u32>,
+    frame_size: u64,
+    /// Whether the result is written through the pointer at frame offset 0
+    sret: bool,
+    code: Vec<Op>,
+    /// Code position of each block, once emitted
+    starts: Vec<u32>,
+    /// Jumps to patch with the position of a block
+    fixups: Vec<(usize, BlockId)>,
+}
+
+impl<'c, 'p, 'a> FunctionCompiler<'c, 'p, 'a> {
+    fn compile(c: &'c mut Compiler<'p, 'a>, body: &'p Body) -> Function {
+        let (sret, result_size) = match c.repr(&body.return_type) {
+            Repr::Memory(size) => (true, size),
+            Repr::Scalar(_) => (false, 0),
+        };
+        let mut frame_size = if sret { 8 } else { 0 };
+        let mut offsets = Vec::with_capacity(body.locals.len());
+        for decl in &body.locals {
+            let layout = c.layout(&decl.ty);
+            let offset = align_up(frame_size, layout.align);
+            offsets.push(offset as u32);
+            frame_size = offset + layout.size;
+        }
+        let mut params = Vec::with_capacity(body.arg_count);
+        for local in body.params() {
+            let offset = offsets[local.index()];
+            params.push(match c.repr(&body.local(local).ty) {
+                Repr::Scalar(scalar) => Param::Scalar { offset, scalar },
+                Repr::Memory(size) => Param::Aggregate { offset, size: size as u32 },
+            });
+        }
+        let signature = format!(
+            "{}({}) -> {}",
+            body.name,
+            body.params().map(|local| format_type(&body.local(local).ty)).collect::<Vec<_>>().join(", "),
+            format_type(&body.return_type)
+        );
+        let mut compiler = FunctionCompiler {
+            c,
+            body,
+            offsets,
+            frame_size,
+            sret,
+            code: Vec::new(),
+            starts: vec![0; body.blocks.len()],
+            fixups: Vec::new(),
+        };
+        for (index, block) in body.blocks.iter().enumerate() {
+            compiler.starts[index] = compiler.code.len() as u32;
+            for statement in &block.statements {
+                compiler.statement(statement);
+            }
+            compiler.terminator(&block.terminator, BlockId(index as u32 + 1));
+        }
+        for (at, block) in std::mem::take(&mut compiler.fixups) {
+            let target = compiler.starts[block.index()];
+            match &mut compiler.code[at] {
+                Op::Jump(t) | Op::JumpIf(t) | Op::JumpIfNot(t) => *t = target,
+                _ => unreachable!("fixups point at jumps"),
+            }
+        }
+        Function {
+            name: body.name.clone(),
+            params,
+            sret,
+            result_size: result_size as u32,
+            signature,
+            frame_size: align_up(compiler.frame_size, 16) as u32,
+            code: compiler.code,
+        }
+    }
+
+    fn error(&mut self, message: impl Into<String>) {
+        let name = self.body.name.clone();
+        self.c.error(&name, message);
+    }
+
+    fn emit(&mut self, op: Op) {
+        self.code.push(op);
+    }
+
+    fn jump_to(&mut self, op: Op, block: BlockId) {
+        self.fixups.push((self.code.len(), block));
+        self.code.push(op);
+    }
+
+    fn offset(&mut self, offset: u64) {
+        if offset != 0 {
+            self.emit(Op::Offset(offset as u32));
+        }
+    }
+
+    /// Frame space for a value no local holds, e.g. an ignored aggregate result
+    fn scratch(&mut self, ty: &Type) -> u32 {
+        let layout = self.c.layout(ty);
+        let offset = align_up(self.frame_size, layout.align);
+        self.frame_size = offset + layout.size;
+        offset as u32
+    }
+
+    fn place_type(&mut self, place: &Place) -> Type {
+        self.body.place_type(place).unwrap_or(Type::Ok)
+    }
+
+    fn operand_type(&mut self, operand: &Operand) -> Type {
+        self.body.operand_type(operand).unwrap_or(Type::Ok)
+    }
+
+    // ========================================================================
+    // Statements and terminators
+    // ========================================================================
+
+    fn statement(&mut self, statement: &mir::Statement) {
+        match statement {
+            mir::Statement::Assign(place, rvalue) => {
+                let ty = self.place_type(place);
+                self.place(place, Access::Write);
+                self.store_rvalue(rvalue, &ty);
+            }
+            mir::Statement::Eval(rvalue) => {
+                let ty = match rvalue {
+                    Rvalue::Call(callee, _) => {
+                        self.c.program.signature(callee).map_or(Type::Ok, |(_, return_type)| return_type)
+                    }
+                    Rvalue::Use(operand) => self.operand_type(operand),
+                    _ => Type::Ok,
+                };
+                match self.c.repr(&ty) {
+                    Repr::Scalar(_) => {
+                        self.rvalue(rvalue, &ty);
+                        self.emit(Op::Pop);
+                    }
+                    Repr::Memory(_) => {
+                        let scratch = self.scratch(&ty);
+                        self.emit(Op::Local(scratch));
+                        self.store_rvalue(rvalue, &ty);
+                    }
+                }
+            }
+        }
+    }
+
+    fn terminator(&mut self, terminator: &mir::Terminator, next_block: BlockId) {
+        match terminator {
+            mir::Terminator::Goto(target) => {
+                if *target != next_block {
+                    self.jump_to(Op::Jump(0), *target);
+                }
+            }
+            mir::Terminator::Branch { cond, then_block, else_block } => {
+                self.value(cond);
+                if *then_block == next_block {
+                    self.jump_to(Op::JumpIfNot(0), *else_block);
+                } else {
+                    self.jump_to(Op::JumpIf(0), *then_block);
+                    if *else_block != next_block {
+                        self.jump_to(Op::Jump(0), *else_block);
+                    }
+                }
+            }
+            mir::Terminator::Return(value) => {
+                if self.sret {
+                    self.emit(Op::Local(0));
+                    self.emit(Op::Load(Scalar::U64));
+                    let ty = self.body.return_type.clone();
+                    self.store_operand(value, &ty);
+                } else {
+                    self.value(value);
+                }
+                self.emit(Op::Return);
+            }
+            mir::Terminator::Propagate { value, dest, conversion, next } => {
+                self.propagate(value, dest, conversion);
+                if *next != next_block {
+                    self.jump_to(Op::Jump(0), *next);
+                }
+            }
+            mir::Terminator::Unreachable => {
+                let message = self.c.message(format!("`{}` ended without returning a value", self.body.name));
+                self.emit(Op::Trap(message));
+            }
+        }
+    }
+
+    /// Return the converted error when `value` holds one, else store its
+    /// success value in `dest`
+    fn propagate(&mut self, value: &Operand, dest: &Place, conversion: &ErrorConversion) {
+        let union_type = self.operand_type(value);
+        let Type::ErrorUnion { ok_type, err_type } = &union_type else { return };
+        let err_type = Type::Path(err_type.clone());
+        let layout = self.c.layout(&union_type);
+        let ok_offset = self.c.offset(&layout, "ok");
+        let err_offset = self.c.offset(&layout, "err");
+        let tag = Compiler::tag(&layout);
+
+        self.address(value);
+        self.emit(Op::Load(tag));
+        let branch = self.code.len();
+        self.emit(Op::JumpIfNot(0));
+
+        // The error path writes `err(converted)` to the result
+        let return_type = self.body.return_type.clone();
+        let Type::ErrorUnion { err_type: return_err, .. } = &return_type else { return };
+        let return_err = Type::Path(return_err.clone());
+        let return_layout = self.c.layout(&return_type);
+        let return_tag = Compiler::tag(&return_layout);
+        let return_err_offset = self.c.offset(&return_layout, "err");
+        self.emit(Op::Local(0));
+        self.emit(Op::Load(Scalar::U64));
+        self.emit(Op::Dup);
+        self.emit(Op::Const(1));
+        self.emit(Op::Store(return_tag));
+        self.offset(return_err_offset);
+        match conversion {
+            ErrorConversion::Identity => {
+                self.address(value);
+                self.offset(err_offset);
+                self.copy(&err_type);
+            }
+            ErrorConversion::Variant { variant } => {
+                let (union, index) = self.c.variant_index(&return_err, variant);
+                self.emit(Op::SetTag { union, variant: index });
+                let return_err_layout = self.c.layout(&return_err);
+                let payload = self.c.offset(&return_err_layout, variant);
+                self.offset(payload);
+                self.address(value);
+                self.offset(err_offset);
+                self.copy(&err_type);
+            }
+            ErrorConversion::Function { function } => {
+                let Some(&index) = self.c.functions.get(function) else {
+                    self.error(format!("missing error conversion `{}`", function));
+                    return;
+                };
+                // The converted error's address is the hidden argument, or
+                // where the scalar result is stored
+                self.address(value);
+                self.offset(err_offset);
+                if let Repr::Scalar(scalar) = self.c.repr(&err_type) {
+                    self.emit(Op::Load(scalar));
+                }
+                self.emit(Op::Call(index));
+                if let Repr::Scalar(scalar) = self.c.repr(&return_err) {
+                    self.emit(Op::Store(scalar));
+                }
+            }
+        }
+        self.emit(Op::Return);
+
+        let ok_path = self.code.len() as u32;
+        self.code[branch] = Op::JumpIfNot(ok_path);
+        self.place(dest, Access::Write);
+        self.address(value);
+        self.offset(ok_offset);
+        self.copy(ok_type);
+    }
+
+    // ========================================================================
+    // Places and operands
+    // ========================================================================
+
+    /// Push the address of a place
+    fn place(&mut self, place: &Place, access: Access) {
+        self.emit(Op::Local(self.offsets[place.local.index()]));
+        let mut ty = self.body.local(place.local).ty.clone();
+        // Variants before the last pointer are read, whatever the access
+        let mut types = Vec::with_capacity(place.projection.len());
+        let mut last_indirect = None;
+        for (i, projection) in place.projection.iter().enumerate() {
+            let through_pointer = matches!(projection, Projection::Deref)
+                || (matches!(projection, Projection::Index(_)) && matches!(ty, Type::Pointer { .. }));
+            if through_pointer {
+                last_indirect = Some(i);
+            }
+            types.push(ty.clone());
+            ty = projection.apply(&ty).unwrap_or(Type::Ok);
+        }
+        for (i, (projection, base)) in place.projection.iter().zip(types).enumerate() {
+            let access = if last_indirect.is_some_and(|last| i < last) { Access::Read } else { access };
+            match projection {
+                Projection::Field(name, _) => {
+                    let layout = self.c.layout(&base);
+                    let offset = self.c.offset(&layout, name);
+                    self.offset(offset);
+                }
+                Projection::Variant(name, _) => {
+                    let (union, variant) = self.c.variant_index(&base, name);
+                    match access {
+                        Access::Read => self.emit(Op::CheckTag { union, variant }),
+                        Access::Write => self.emit(Op::SetTag { union, variant }),
+                        Access::Address => {}
+                    }
+                    let layout = self.c.layout(&base);
+                    let offset = self.c.offset(&layout, name);
+                    self.offset(offset);
+                }
+                Projection::Index(index) => {
+                    let index_type = self.body.local(*index).ty.clone();
+                    let signed = index_type_signed(&index_type);
+                    let element = self.element_size(&base);
+                    let load_index = |this: &mut Self| {
+                        this.emit(Op::Local(this.offsets[index.index()]));
+                        let scalar = this.c.scalar_of(&index_type).unwrap_or(Scalar::U64);
+                        this.emit(Op::Load(scalar));
+                    };
+                    match &base {
+                        Type::Array { size: Some(_), .. } => {
+                            let count = match self.c.layout(&base).shape {
+                                Shape::Array { count, .. } => count,
+                                _ => 0,
+                            };
+                            load_index(self);
+                            self.emit(Op::IndexArray { len: count as u32, stride: element, signed });
+                        }
+                        Type::Array { size: None, .. } => {
+                            load_index(self);
+                            self.emit(Op::IndexSlice { stride: element, signed });
+                        }
+                        _ => {
+                            self.emit(Op::Load(Scalar::U64));
+                            self.emit(Op::NonNull);
+                            load_index(self);
+                            self.emit(Op::PtrAdd { stride: element, signed });
+                        }
+                    }
+                }
+                Projection::Deref => {
+                    self.emit(Op::Load(Scalar::U64));
+                    self.emit(Op::NonNull);
+                }
+                Projection::Payload => {
+                    let layout = self.c.layout(&base);
+                    if !matches!(layout.shape, Shape::Niche { .. }) {
+                        let offset = self.c.offset(&layout, "some");
+                        self.offset(offset);
+                    }
+                }
+                Projection::OkValue | Projection::ErrValue => {
+                    let layout = self.c.layout(&base);
+                    let name = if matches!(projection, Projection::OkValue) { "ok" } else { "err" };
+                    let offset = self.c.offset(&layout, name);
+                    self.offset(offset);
+                }
+            }
+        }
+    }
+
+    /// Size of the elements of an array, slice or pointer type
+    fn element_size(&mut self, ty: &Type) -> u32 {
+        match ty {
+            Type::Array { element_type, .. } | Type::Pointer { element_type, .. } => {
+                self.c.layout(element_type).size as u32
+            }
+            _ => 0,
+        }
+    }
+
+    /// Push the value of a scalar operand
+    fn value(&mut self, operand: &Operand) {
+        match operand {
+            Operand::Copy(place) => {
+                let ty = self.place_type(place);
+                self.place(place, Access::Read);
+                match self.c.repr(&ty) {
+                    Repr::Scalar(scalar) => self.emit(Op::Load(scalar)),
+                    Repr::Memory(_) => self.error(format!("`{}` is not a scalar", place)),
+                }
+            }
+            Operand::Const(constant) => {
+                let value = match constant {
+                    Constant::Int(value, _) => *value as u64,
+                    Constant::Float(value, ty) => {
+                        if *ty == Type::F32 { (*value as f32 as f64).to_bits() } else { value.to_bits() }
+                    }
+                    Constant::Bool(b) => *b as u64,
+                    Constant::Str(text, _) => self.c.string(text, false),
+                    Constant::Ok | Constant::Null(_) => 0,
+                };
+                self.emit(Op::Const(value));
+            }
+        }
+    }
+
+    /// Push the address of an aggregate operand
+    fn address(&mut self, operand: &Operand) {
+        match operand {
+            Operand::Copy(place) => self.place(place, Access::Read),
+            Operand::Const(Constant::Str(text, _)) => {
+                let address = self.c.string(text, true);
+                self.emit(Op::Const(address));
+            }
+            Operand::Const(constant) => {
+                let size = self.c.layout(&constant.ty()).size;
+                let address = self.c.zeros(size);
+                self.emit(Op::Const(address));
+            }
+        }
+    }
+
+    /// Push an operand as the calling convention passes it
+    fn argument(&mut self, operand: &Operand) {
+        let ty = self.operand_type(operand);
+        match self.c.repr(&ty) {
+            Repr::Scalar(_) => self.value(operand),
+            Repr::Memory(_) => self.address(operand),
+        }
+    }
+
+    /// `[dest] -> []`: store an operand of type `ty`
+    fn store_operand(&mut self, operand: &Operand, ty: &Type) {
+        match self.c.repr(ty) {
+            Repr::Scalar(scalar) => {
+                self.value(operand);
+                self.emit(Op::Store(scalar));
+            }
+            Repr::Memory(size) => {
+                self.address(operand);
+                self.emit(Op::Copy(size as u32));
+            }
+        }
+    }
+
+    /// `[dest, src] -> []`: copy a value of type `ty`
+    fn copy(&mut self, ty: &Type) {
+        match self.c.repr(ty) {
+            Repr::Scalar(scalar) => {
+                self.emit(Op::Load(scalar));
+                self.emit(Op::Store(scalar));
+            }
+            Repr::Memory(size) => self.emit(Op::Copy(size as u32)),
+        }
+    }
+
+    // ========================================================================
+    // Rvalues
+    // ========================================================================
+
+    /// `[dest] -> []`: store the value of an rvalue of type `ty`
+    fn store_rvalue(&mut self, rvalue: &Rvalue, ty: &Type) {
+        let size = match self.c.repr(ty) {
+            Repr::Scalar(scalar) => {
+                self.rvalue(rvalue, ty);
+                self.emit(Op::Store(scalar));
+                return;
+            }
+            Repr::Memory(size) => size,
+        };
+        match rvalue {
+            Rvalue::Use(operand) => {
+                self.address(operand);
+                self.emit(Op::Copy(size as u32));
+            }
+            Rvalue::Aggregate(kind, operands) => self.aggregate(kind, operands),
+            Rvalue::Unsize(place, _) => {
+                let array_type = self.place_type(place);
+                let count = match self.c.layout(&array_type).shape {
+                    Shape::Array { count, .. } => count,
+                    _ => 0,
+                };
+                self.emit(Op::Dup);
+                self.place(place, Access::Address);
+                self.emit(Op::Store(Scalar::U64));
+                self.emit(Op::Offset(8));
+                self.emit(Op::Const(count));
+                self.emit(Op::Store(Scalar::U64));
+            }
+            Rvalue::Subslice(place, start, end) => {
+                let array_type = self.place_type(place);
+                let stride = self.element_size(&array_type);
+                self.place(place, Access::Read);
+                self.value(start);
+                self.value(end);
+                match (&array_type, self.c.layout(&array_type).shape) {
+                    (Type::Array { size: Some(_), .. }, Shape::Array { count, .. }) => {
+                        self.emit(Op::SubsliceArray { len: count as u32, stride })
+                    }
+                    _ => self.emit(Op::SubsliceSlice { stride }),
+                }
+            }
+            // The destination is the hidden first argument
+            Rvalue::Call(callee, args) => self.call(callee, args),
+            other => self.error(format!("cannot compile `{}` to a `{}`", other, format_type(ty))),
+        }
+    }
+
+    /// `[dest] -> []`: build an aggregate in place
+    fn aggregate(&mut self, kind: &AggregateKind, operands: &[Operand]) {
+        let ty = kind.ty().clone();
+        let layout = self.c.layout(&ty);
+        match kind {
+            AggregateKind::Struct(_) => {
+                let fields = self.c.fields(&ty);
+                for ((name, field_type), operand) in fields.iter().zip(operands) {
+                    let offset = self.c.offset(&layout, name);
+                    self.emit(Op::Dup);
+                    self.offset(offset);
+                    self.store_operand(operand, field_type);
+                }
+                self.emit(Op::Pop);
+            }
+            AggregateKind::Array(_) => {
+                let (Type::Array { element_type, .. }, Shape::Array { element, .. }) = (&ty, &layout.shape) else {
+                    return;
+                };
+                for (i, operand) in operands.iter().enumerate() {
+                    self.emit(Op::Dup);
+                    self.offset(i as u64 * element.size);
+                    self.store_operand(operand, element_type);
+                }
+                self.emit(Op::Pop);
+            }
+            AggregateKind::Variant(_, variant) => {
+                let (union, index) = self.c.variant_index(&ty, variant);
+                self.emit(Op::SetTag { union, variant: index });
+                match operands.first() {
+                    Some(operand) => {
+                        let offset = self.c.offset(&layout, variant);
+                        let payload_type = self.operand_type(operand);
+                        self.offset(offset);
+                        self.store_operand(operand, &payload_type);
+                    }
+                    None => self.emit(Op::Pop),
+                }
+            }
+            AggregateKind::Some(_) | AggregateKind::Ok(_) | AggregateKind::Err(_) => {
+                let (tag, name) = match kind {
+                    AggregateKind::Some(_) => (1, "some"),
+                    AggregateKind::Ok(_) => (0, "ok"),
+                    _ => (1, "err"),
+                };
+                let offset = self.c.offset(&layout, name);
+                self.emit(Op::Dup);
+                self.emit(Op::Const(tag));
+                self.emit(Op::Store(Compiler::tag(&layout)));
+                self.offset(offset);
+                let Some(operand) = operands.first() else { return };
+                let payload_type = self.operand_type(operand);
+                self.store_operand(operand, &payload_type);
+            }
+        }
+    }
+
+    /// Push the value of a scalar rvalue of type `ty`
+    fn rvalue(&mut self, rvalue: &Rvalue, ty: &Type) {
+        match rvalue {
+            Rvalue::Use(operand) => self.value(operand),
+            Rvalue::Binary(op, lhs, rhs) => self.binary(*op, lhs, rhs),
+            Rvalue::Unary(op, operand) => {
+                let operand_type = self.operand_type(operand);
+                self.value(operand);
+                match (op, int_kind(&operand_type)) {
+                    (UnOp::Neg, Some(kind)) => self.emit(Op::Neg(kind)),
+                    (UnOp::Neg, None) => self.emit(Op::FNeg(operand_type == Type::F32)),
+                    (UnOp::Not, _) => self.emit(Op::Not),
+                    (UnOp::BitNot, Some(kind)) => self.emit(Op::BitNot(kind)),
+                    (UnOp::BitNot, None) => self.error("`bitnot` of a non-integer"),
+                }
+            }
+            Rvalue::Cast(operand, to) => {
+                let from = self.operand_type(operand);
+                self.value(operand);
+                self.cast(&from, to);
+            }
+            Rvalue::AddressOf(place) => self.place(place, Access::Address),
+            Rvalue::Aggregate(AggregateKind::Some(_), operands) if operands.len() == 1 => {
+                // A `?*T` is the pointer itself
+                self.value(&operands[0]);
+            }
+            Rvalue::IsNull(operand) => {
+                let operand_type = self.operand_type(operand);
+                match self.c.repr(&operand_type) {
+                    Repr::Scalar(_) => self.value(operand),
+                    Repr::Memory(_) => {
+                        let layout = self.c.layout(&operand_type);
+                        self.address(operand);
+                        self.emit(Op::Load(Compiler::tag(&layout)));
+                    }
+                }
+                self.emit(Op::Const(0));
+                self.emit(Op::Cmp(CmpOp::Eq, CmpKind::Unsigned));
+            }
+            Rvalue::IsErr(operand) => {
+                let operand_type = self.operand_type(operand);
+                let layout = self.c.layout(&operand_type);
+                self.address(operand);
+                self.emit(Op::Load(Compiler::tag(&layout)));
+                self.emit(Op::Const(0));
+                self.emit(Op::Cmp(CmpOp::Ne, CmpKind::Unsigned));
+            }
+            Rvalue::Len(place) => {
+                self.place(place, Access::Read);
+                self.emit(Op::Offset(8));
+                self.emit(Op::Load(Scalar::U64));
+            }
+            Rvalue::Call(callee, args) => self.call(callee, args),
+            other => self.error(format!("cannot compile `{}` to a `{}`", other, format_type(ty))),
+        }
+    }
+
+    fn binary(&mut self, op: BinOp, lhs: &Operand, rhs: &Operand) {
+        let lhs_type = self.operand_type(lhs);
+        let rhs_type = self.operand_type(rhs);
+        self.value(lhs);
+        self.value(rhs);
+        if op.is_comparison() {
+            let kind = if is_float(&lhs_type) {
+                CmpKind::Float
+            } else if self.c.repr(&lhs_type).is_signed() {
+                CmpKind::Signed
+            } else {
+                CmpKind::Unsigned
+            };
+            let op = match op {
+                BinOp::Eq => CmpOp::Eq,
+                BinOp::Ne => CmpOp::Ne,
+                BinOp::Lt => CmpOp::Lt,
+                BinOp::Le => CmpOp::Le,
+                BinOp::Gt => CmpOp::Gt,
+                _ => CmpOp::Ge,
+            };
+            self.emit(Op::Cmp(op, kind));
+            return;
+        }
+        if let Type::Pointer { .. } = lhs_type {
+            let stride = self.element_size(&lhs_type);
+            let signed = index_type_signed(&rhs_type);
+            match (op, &rhs_type) {
+                (BinOp::Sub, Type::Pointer { .. }) => self.emit(Op::PtrDiff { stride }),
+                (BinOp::Add, _) => self.emit(Op::PtrAdd { stride, signed }),
+                (BinOp::Sub, _) => self.emit(Op::PtrSub { stride, signed }),
+                _ => self.error(format!("`{}` on a pointer", op.name())),
+            }
+            return;
+        }
+        if is_float(&lhs_type) {
+            let op = match op {
+                BinOp::Add => FloatOp::Add,
+                BinOp::Sub => FloatOp::Sub,
+                BinOp::Mul => FloatOp::Mul,
+                BinOp::Div => FloatOp::Div,
+                _ => {
+                    self.error(format!("`{}` on a float", op.name()));
+                    return;
+                }
+            };
+            self.emit(Op::Float(op, lhs_type == Type::F32));
+            return;
+        }
+        let op = match (op, int_kind(&lhs_type)) {
+            (BinOp::BitAnd, _) => Op::And,
+            (BinOp::BitOr, _) => Op::Or,
+            (BinOp::BitXor, _) => Op::Xor,
+            (BinOp::Add, Some(kind)) => Op::Add(kind),
+            (BinOp::Sub, Some(kind)) => Op::Sub(kind),
+            (BinOp::Mul, Some(kind)) => Op::Mul(kind),
+            (BinOp::Div, Some(kind)) => Op::Div(kind),
+            (BinOp::Rem, Some(kind)) => Op::Rem(kind),
+            (BinOp::Shl, Some(kind)) => Op::Shl(kind),
+            (BinOp::Shr, Some(kind)) => Op::Shr(kind),
+            (op, _) => {
+                self.error(format!("`{}` on `{}`", op.name(), format_type(&lhs_type)));
+                return;
+            }
+        };
+        self.emit(op);
+    }
+
+    fn cast(&mut self, from: &Type, to: &Type) {
+        if from == to {
+            return;
+        }
+        let enum_of = |this: &mut Self, ty: &Type| match (ty, this.c.repr(ty)) {
+            (Type::Path(_), Repr::Scalar(scalar)) => Some(enum_kind(scalar)),
+            _ => None,
+        };
+        let from_kind = int_kind(from).or_else(|| enum_of(self, from)).or(match from {
+            Type::Bool => Some(IntKind::U8),
+            Type::Pointer { .. } | Type::Optional(_) => Some(IntKind::USize),
+            _ => None,
+        });
+        match to {
+            Type::Bool => self.emit(Op::ToBool),
+            Type::F32 | Type::F64 => {
+                let single = *to == Type::F32;
+                match from_kind {
+                    _ if is_float(from) => self.emit(Op::FloatCast(single)),
+                    Some(kind) => self.emit(Op::IntToFloat(kind, single)),
+                    None => self.error(format!("cannot cast `{}` to a float", format_type(from))),
+                }
+            }
+            Type::Pointer { .. } | Type::Optional(_) => {
+                if int_kind(from).is_some() {
+                    self.emit(Op::IntCast(IntKind::USize));
+                }
+            }
+            _ => {
+                let Some(kind) = int_kind(to).or_else(|| enum_of(self, to)) else {
+                    self.error(format!("cannot cast to `{}`", format_type(to)));
+                    return;
+                };
+                if is_float(from) {
+                    self.emit(Op::FloatToInt(kind));
+                } else {
+                    self.emit(Op::IntCast(kind));
+                }
+                if int_kind(to).is_none() {
+                    let index = self.c.enum_info(to);
+                    self.emit(Op::CheckEnum(index));
+                }
+            }
+        }
+    }
+
+    fn call(&mut self, callee: &Callee, args: &[Operand]) {
+        match callee {
+            Callee::Function(name) => {
+                for arg in args {
+                    self.argument(arg);
+                }
+                match self.c.functions.get(name) {
+                    Some(&index) => self.emit(Op::Call(index)),
+                    None => self.error(format!("call of unknown function `{}`", name)),
+                }
+            }
+            Callee::Extern(name) => {
+                for arg in args {
+                    self.argument(arg);
+                }
+                let index = self.c.externs[name];
+                self.emit(Op::CallExtern(index));
+            }
+            Callee::Builtin(builtin) => self.builtin(*builtin, args),
+        }
+    }
+
+    fn builtin(&mut self, builtin: Builtin, args: &[Operand]) {
+        match builtin {
+            Builtin::Print | Builtin::Println => {
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        self.emit(Op::PrintByte(b' '));
+                    }
+                    let ty = self.operand_type(arg);
+                    match self.c.format_index(&ty) {
+                        Ok(format) => {
+                            self.argument(arg);
+                            self.emit(Op::Print(format));
+                        }
+                        Err(message) => self.error(message),
+                    }
+                }
+                if builtin == Builtin::Println {
+                    self.emit(Op::PrintByte(b'\n'));
+                }
+                self.emit(Op::Const(0));
+            }
+            Builtin::Assert => {
+                self.value(&args[0]);
+                self.emit(Op::Assert);
+                self.emit(Op::Const(0));
+            }
+            Builtin::Malloc | Builtin::Calloc | Builtin::Realloc | Builtin::Free => {
+                for arg in args {
+                    self.value(arg);
+                }
+                self.emit(match builtin {
+                    Builtin::Malloc => Op::Malloc,
+                    Builtin::Calloc => Op::Calloc,
+                    Builtin::Realloc => Op::Realloc,
+                    _ => Op::Free,
+                });
+                if builtin == Builtin::Free {
+                    self.emit(Op::Const(0));
+                }
+            }
+        }
+    }
+}
+
+impl Repr {
+    fn is_signed(self) -> bool {
+        matches!(self, Repr::Scalar(Scalar::I8 | Scalar::I16 | Scalar::I32 | Scalar::I64))
+    }
+}
+
+fn index_type_signed(ty: &Type) -> bool {
+    int_kind(ty).is_some_and(IntKind::signed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compile_src(src: &str) -> Module {
+        let sf = crate::parse(src);
+        let items = ItemTable::from_source_file(&sf);
+        compile_items(&items).unwrap()
+    }
+
+    #[test]
+    fn test_disassembly() {
+        let module = compile_src("func square(x: i32) -> i32\n    return x * x\n");
+        let expected = "\
+; #0
+fn square(i32) -> i32  ; frame 16
+    0  local 4
+    1  local 0
+    2  load i32
+    3  local 0
+    4  load i32
+    5  mul i32
+    6  store i32
+    7  local 4
+    8  load i32
+    9  ret
+";
+        assert_eq!(module.to_string(), expected);
+    }
+
+    #[test]
+    fn test_aggregates_use_a_hidden_result_pointer() {
+        let module = compile_src("struct P\n    a: i64\n    b: u8\n\nfunc make(a: i64) -> P\n    return P(a, 1)\n");
+        let (_, make) = module.function("make").unwrap();
+        assert!(make.sret);
+        assert_eq!(make.result_size, 16);
+        // The result pointer comes first, then the parameter
+        assert_eq!(make.params, vec![Param::Scalar { offset: 8, scalar: Scalar::I64 }]);
+        assert_eq!(make.code.last(), Some(&Op::Return));
+    }
+
+    #[test]
+    fn test_strings_are_interned_in_static_data() {
+        let module = compile_src("func! main() -> ok\n    println(\"hi\")\n    println(\"hi\")\n    pass\n");
+        // The bytes with a NUL, then one slice over them
+        assert_eq!(module.data.len(), 8 + 16);
+        assert_eq!(&module.data[..3], b"hi\0");
+    }
+}
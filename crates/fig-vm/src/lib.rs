@@ -0,0 +1,31 @@
+//! Bytecode compiler and virtual machine for Fig
+//!
+//! [`compile`] turns a verified MIR [`Program`](fig_mir::ir::Program) into a
+//! [`Module`](bytecode::Module) of compact stack-machine code, and a [`Vm`]
+//! runs it. Memory is one byte array with a bump-allocated call stack and a
+//! heap for `core::memory` (see [`memory`]); values use the x86-64 layouts,
+//! so programs see the same sizes and offsets as under the C backend, and
+//! no native toolchain is needed to run them. Printing a module
+//! disassembles it.
+//!
+//! ```ignore
+//! let sf = SourceFileParser::new().parse(Lexer::new(src))?;
+//! let items = ItemTable::from_source_file(&sf);
+//! let module = compile_items(&items)?;
+//! let exit = Vm::new(&module).run_main()?;
+//! ```
+
+pub mod bytecode;
+mod compile;
+pub mod memory;
+mod vm;
+
+pub use compile::{TARGET, compile, compile_items};
+pub use vm::{Exit, Vm};
+
+#[cfg(test)]
+pub(crate) fn parse(src: &str) -> fig_parser::ast::SourceFile {
+    fig_parser::SourceFileParser::new()
+        .parse(fig_parser::Lexer::new(src))
+        .unwrap()
+}
@@ -0,0 +1,278 @@
+//! The VM's linear memory and allocators
+//!
+//! Memory is one little-endian byte array, laid out as:
+//!
+//! | Addresses                | Contents                                   |
+//! |--------------------------|--------------------------------------------|
+//! | `0..16`                  | unmapped, so null and near-null accesses trap |
+//! | `16..`                   | static data: string literals and constants |
+//! | then [`STACK_SIZE`] bytes | call frames, bump-allocated                |
+//! | then to the end          | the heap behind `malloc` and friends       |
+//!
+//! Frames are allocated by bumping the stack top on a call and released by
+//! resetting it on return. The heap models `core::memory`: blocks are
+//! bump-allocated from the end of memory, which grows as needed, and a freed
+//! block goes on a free list by size, from which a later allocation of at
+//! most that size may reuse it. The heap knows the start and size of every
+//! block, so `free` of a pointer it did not hand out, or a second `free` of
+//! the same block, traps like in the interpreter.
+
+use std::collections::{BTreeMap, HashMap};
+
+use fig_interp::error::RuntimeError;
+
+use crate::bytecode::Scalar;
+
+/// Where static data starts; lower addresses are never mapped
+pub const STATIC_BASE: u64 = 16;
+
+/// Bytes reserved for call frames
+pub const STACK_SIZE: u64 = 1 << 20;
+
+/// Heap blocks are aligned to, and sized in multiples of, this many bytes
+const HEAP_ALIGN: u64 = 16;
+
+/// The largest the whole memory may grow; allocations beyond it return null
+const MEMORY_LIMIT: u64 = 1 << 32;
+
+fn align_up(value: u64, align: u64) -> u64 {
+    value.div_ceil(align) * align
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Block {
+    size: u64,
+    live: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Memory {
+    bytes: Vec<u8>,
+    /// First address past the stack
+    stack_end: u64,
+    /// Frames are allocated from here
+    stack_top: u64,
+    /// Heap blocks by start address, freed ones included
+    blocks: HashMap<u64, Block>,
+    /// Start addresses of freed blocks, by block size
+    free_lists: BTreeMap<u64, Vec<u64>>,
+}
+
+impl Memory {
+    /// Memory holding `data` at [`STATIC_BASE`], with an empty stack and heap
+    pub fn new(data: &[u8]) -> Self {
+        let stack_base = align_up(STATIC_BASE + data.len() as u64, HEAP_ALIGN);
+        let stack_end = stack_base + STACK_SIZE;
+        let mut bytes = vec![0; stack_end as usize];
+        bytes[STATIC_BASE as usize..STATIC_BASE as usize + data.len()].copy_from_slice(data);
+        Memory { bytes, stack_end, stack_top: stack_base, blocks: HashMap::new(), free_lists: BTreeMap::new() }
+    }
+
+    fn check(&self, address: u64, len: u64) -> Result<usize, RuntimeError> {
+        if address < STATIC_BASE {
+            return Err(RuntimeError::NullDereference);
+        }
+        match address.checked_add(len) {
+            Some(end) if end <= self.bytes.len() as u64 => Ok(address as usize),
+            _ => Err(RuntimeError::OutOfBounds),
+        }
+    }
+
+    pub fn bytes(&self, address: u64, len: u64) -> Result<&[u8], RuntimeError> {
+        let start = self.check(address, len)?;
+        Ok(&self.bytes[start..start + len as usize])
+    }
+
+    /// A scalar as a stack slot: integers extended to 64 bits, floats as `f64` bits
+    pub fn load(&self, address: u64, scalar: Scalar) -> Result<u64, RuntimeError> {
+        if scalar == Scalar::Unit {
+            return Ok(0);
+        }
+        let bytes = self.bytes(address, scalar.size())?;
+        let mut raw = [0u8; 8];
+        raw[..bytes.len()].copy_from_slice(bytes);
+        let raw = u64::from_le_bytes(raw);
+        Ok(match scalar {
+            Scalar::I8 => raw as i8 as u64,
+            Scalar::I16 => raw as i16 as u64,
+            Scalar::I32 => raw as i32 as u64,
+            Scalar::F32 => (f32::from_bits(raw as u32) as f64).to_bits(),
+            _ => raw,
+        })
+    }
+
+    pub fn store(&mut self, address: u64, scalar: Scalar, value: u64) -> Result<(), RuntimeError> {
+        let size = scalar.size();
+        if size == 0 {
+            return Ok(());
+        }
+        let start = self.check(address, size)?;
+        let value = if scalar == Scalar::F32 { (f64::from_bits(value) as f32).to_bits() as u64 } else { value };
+        self.bytes[start..start + size as usize].copy_from_slice(&value.to_le_bytes()[..size as usize]);
+        Ok(())
+    }
+
+    /// Copy `len` bytes; the ranges may overlap
+    pub fn copy(&mut self, dest: u64, src: u64, len: u64) -> Result<(), RuntimeError> {
+        if len == 0 {
+            return Ok(());
+        }
+        let src = self.check(src, len)?;
+        let dest = self.check(dest, len)?;
+        self.bytes.copy_within(src..src + len as usize, dest);
+        Ok(())
+    }
+
+    // ========================================================================
+    // Stack
+    // ========================================================================
+
+    pub fn stack_top(&self) -> u64 {
+        self.stack_top
+    }
+
+    /// Allocate a frame of `size` bytes, returning its address
+    pub fn push_frame(&mut self, size: u64) -> Result<u64, RuntimeError> {
+        let frame = align_up(self.stack_top, 16);
+        if frame + size > self.stack_end {
+            return Err(RuntimeError::StackOverflow);
+        }
+        self.stack_top = frame + size;
+        Ok(frame)
+    }
+
+    /// Release every frame allocated since the stack top was `top`
+    pub fn pop_frames(&mut self, top: u64) {
+        self.stack_top = top;
+    }
+
+    // ========================================================================
+    // Heap
+    // ========================================================================
+
+    /// Allocate `size` bytes, or return 0 when memory is exhausted
+    pub fn malloc(&mut self, size: u64) -> u64 {
+        let size = align_up(size.max(1), HEAP_ALIGN);
+        let reused = self.free_lists.range_mut(size..).find_map(|(_, starts)| starts.pop());
+        if let Some(start) = reused {
+            self.free_lists.retain(|_, starts| !starts.is_empty());
+            let block = self.blocks.get_mut(&start).expect("free lists hold known blocks");
+            block.live = true;
+            return start;
+        }
+        let start = align_up(self.bytes.len() as u64, HEAP_ALIGN);
+        match start.checked_add(size) {
+            Some(end) if end <= MEMORY_LIMIT => {
+                self.bytes.resize(end as usize, 0);
+                self.blocks.insert(start, Block { size, live: true });
+                start
+            }
+            _ => 0,
+        }
+    }
+
+    /// Allocate `count * size` zeroed bytes, or return 0
+    pub fn calloc(&mut self, count: u64, size: u64) -> u64 {
+        let Some(total) = count.checked_mul(size) else { return 0 };
+        let start = self.malloc(total);
+        if start != 0 {
+            let len = self.blocks[&start].size as usize;
+            self.bytes[start as usize..start as usize + len].fill(0);
+        }
+        start
+    }
+
+    /// The live block starting at `address`
+    fn block(&self, address: u64) -> Result<Block, RuntimeError> {
+        match self.blocks.get(&address) {
+            Some(block) if block.live => Ok(*block),
+            Some(_) => Err(RuntimeError::DoubleFree),
+            None => Err(RuntimeError::InvalidFree),
+        }
+    }
+
+    pub fn free(&mut self, address: u64) -> Result<(), RuntimeError> {
+        if address == 0 {
+            return Ok(());
+        }
+        let block = self.block(address)?;
+        self.blocks.insert(address, Block { live: false, ..block });
+        self.free_lists.entry(block.size).or_default().push(address);
+        Ok(())
+    }
+
+    /// Resize a block, moving it when it does not fit; returns 0 and leaves
+    /// the block alone when memory is exhausted
+    pub fn realloc(&mut self, address: u64, size: u64) -> Result<u64, RuntimeError> {
+        if address == 0 {
+            return Ok(self.malloc(size));
+        }
+        let block = self.block(address)?;
+        if size <= block.size {
+            return Ok(address);
+        }
+        let moved = self.malloc(size);
+        if moved != 0 {
+            self.copy(moved, address, block.size)?;
+            self.free(address)?;
+        }
+        Ok(moved)
+    }
+
+    /// Number of heap blocks not yet freed
+    pub fn live_blocks(&self) -> usize {
+        self.blocks.values().filter(|block| block.live).count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scalars_round_trip_little_endian() {
+        let mut memory = Memory::new(b"hi");
+        assert_eq!(memory.bytes(STATIC_BASE, 2), Ok(&b"hi"[..]));
+        let address = memory.push_frame(16).unwrap();
+        memory.store(address, Scalar::I16, -2i64 as u64).unwrap();
+        assert_eq!(memory.bytes(address, 2), Ok(&[0xfe, 0xff][..]));
+        assert_eq!(memory.load(address, Scalar::I16), Ok(-2i64 as u64));
+        assert_eq!(memory.load(address, Scalar::U16), Ok(0xfffe));
+        memory.store(address + 8, Scalar::F32, 0.1f64.to_bits()).unwrap();
+        assert_eq!(memory.load(address + 8, Scalar::F32), Ok((0.1f32 as f64).to_bits()));
+        assert_eq!(memory.load(0, Scalar::U8), Err(RuntimeError::NullDereference));
+    }
+
+    #[test]
+    fn test_heap_reuses_freed_blocks_and_catches_bad_frees() {
+        let mut memory = Memory::new(&[]);
+        let a = memory.malloc(24);
+        let b = memory.malloc(8);
+        assert_eq!(a % HEAP_ALIGN, 0);
+        assert!(b >= a + 32);
+        memory.free(a).unwrap();
+        assert_eq!(memory.free(a), Err(RuntimeError::DoubleFree));
+        assert_eq!(memory.free(b + 1), Err(RuntimeError::InvalidFree));
+        // A smaller request fits in the freed 32-byte block
+        assert_eq!(memory.malloc(20), a);
+        assert_eq!(memory.live_blocks(), 2);
+
+        memory.store(b, Scalar::U64, 7).unwrap();
+        let grown = memory.realloc(b, 64).unwrap();
+        assert_ne!(grown, b);
+        assert_eq!(memory.load(grown, Scalar::U64), Ok(7));
+        assert_eq!(memory.free(b), Err(RuntimeError::DoubleFree));
+    }
+
+    #[test]
+    fn test_stack_frames_are_bump_allocated() {
+        let mut memory = Memory::new(&[]);
+        let top = memory.stack_top();
+        let first = memory.push_frame(24).unwrap();
+        let second = memory.push_frame(8).unwrap();
+        assert_eq!(second, first + 32);
+        memory.pop_frames(top);
+        assert_eq!(memory.push_frame(8).unwrap(), first);
+        assert_eq!(memory.push_frame(STACK_SIZE), Err(RuntimeError::StackOverflow));
+    }
+}
@@ -0,0 +1,668 @@
+//! The bytecode interpreter
+//!
+//! A [`Vm`] runs one [`Module`] over a [`Memory`]: an operand stack of
+//! 64-bit slots, and a stack of call frames, each the function being run,
+//! its program counter and the address of its locals. Runtime errors are
+//! reported with the same [`RuntimeError`]s as the tree-walking interpreter.
+
+use std::fmt::Write;
+
+use fig_interp::error::{RuntimeError, Trap};
+
+use crate::bytecode::*;
+use crate::compile::enum_kind;
+use crate::memory::Memory;
+
+/// Calls nested deeper than this trap with [`RuntimeError::StackOverflow`]
+const DEFAULT_MAX_CALL_DEPTH: usize = 10_000;
+
+struct Frame {
+    function: u32,
+    pc: usize,
+    /// Address of the frame's locals
+    fp: u64,
+    /// The memory stack top to restore on return
+    stack_top: u64,
+}
+
+/// The result of running `main`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Exit {
+    /// The value `main` returned, printed like `print` would
+    pub value: String,
+    /// The process exit code, as under the C backend: an integer result, 1
+    /// for an error, else 0
+    pub code: i32,
+    /// Whether `main` returned an error
+    pub failed: bool,
+}
+
+pub struct Vm<'m> {
+    module: &'m Module,
+    memory: Memory,
+    stack: Vec<u64>,
+    frames: Vec<Frame>,
+    output: String,
+    fuel: Option<u64>,
+    max_call_depth: usize,
+}
+
+fn int_bounds(kind: IntKind) -> (i128, i128) {
+    let bits = kind.bits();
+    if kind.signed() { (-(1i128 << (bits - 1)), (1i128 << (bits - 1)) - 1) } else { (0, (1i128 << bits) - 1) }
+}
+
+/// A slot holding a `kind` integer, as a number
+fn int_value(slot: u64, kind: IntKind) -> i128 {
+    if kind.signed() { slot as i64 as i128 } else { slot as i128 }
+}
+
+/// Truncate to `kind`'s width and extend back to a slot
+fn wrap(value: u64, kind: IntKind) -> u64 {
+    let shift = 64 - kind.bits();
+    if shift == 0 {
+        value
+    } else if kind.signed() {
+        (((value << shift) as i64) >> shift) as u64
+    } else {
+        (value << shift) >> shift
+    }
+}
+
+fn float(slot: u64) -> f64 {
+    f64::from_bits(slot)
+}
+
+fn round(value: f64, single: bool) -> u64 {
+    if single { (value as f32 as f64).to_bits() } else { value.to_bits() }
+}
+
+impl<'m> Vm<'m> {
+    pub fn new(module: &'m Module) -> Self {
+        Vm {
+            module,
+            memory: Memory::new(&module.data),
+            stack: Vec::new(),
+            frames: Vec::new(),
+            output: String::new(),
+            fuel: None,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+        }
+    }
+
+    /// Trap with [`RuntimeError::OutOfFuel`] after executing `fuel` instructions
+    pub fn with_fuel(mut self, fuel: u64) -> Self {
+        self.fuel = Some(fuel);
+        self
+    }
+
+    pub fn with_max_call_depth(mut self, depth: usize) -> Self {
+        self.max_call_depth = depth;
+        self
+    }
+
+    /// Everything written by `print` and `println` so far
+    pub fn output(&self) -> &str {
+        &self.output
+    }
+
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    /// Call a function by instance name with arguments as the calling
+    /// convention passes them. Returns a scalar result, or the address of
+    /// an aggregate one, which stays valid until the next call.
+    pub fn call(&mut self, name: &str, args: &[u64]) -> Result<u64, Trap> {
+        let trap = |error| Trap { error, backtrace: Vec::new() };
+        let (index, function) =
+            self.module.function(name).ok_or_else(|| trap(RuntimeError::UndefinedFunction(name.to_string())))?;
+        if args.len() != function.params.len() {
+            return Err(trap(RuntimeError::ArityMismatch {
+                function: name.to_string(),
+                expected: function.params.len(),
+                found: args.len(),
+            }));
+        }
+        self.stack.clear();
+        self.frames.clear();
+        let result = if function.sret {
+            // Results live in a frame of their own, below the callee's
+            let address = self.memory.push_frame(function.result_size as u64).map_err(trap)?;
+            self.stack.push(address);
+            Some(address)
+        } else {
+            None
+        };
+        self.stack.extend_from_slice(args);
+        self.run(index)?;
+        Ok(result.unwrap_or_else(|| self.stack.pop().unwrap_or(0)))
+    }
+
+    /// Run `main`, showing its result the way `fig run` reports it
+    pub fn run_main(&mut self) -> Result<Exit, Trap> {
+        let Some(main) = self.module.main else {
+            return Err(Trap { error: RuntimeError::UndefinedFunction("main".to_string()), backtrace: Vec::new() });
+        };
+        let name = self.module.functions[main.function as usize].name.clone();
+        let result = self.call(&name, &[])?;
+        let Some(format) = main.format else {
+            return Ok(Exit { value: String::new(), code: 0, failed: false });
+        };
+        let format = &self.module.formats[format as usize];
+        let mut value = String::new();
+        self.format(format, result, &mut value).map_err(|error| Trap { error, backtrace: Vec::new() })?;
+        let (code, failed) = match format {
+            Format::Int(_) => (result as i32, false),
+            Format::ErrorUnion { .. } if self.memory.load(result, Scalar::U8).unwrap_or(0) != 0 => (1, true),
+            _ => (0, false),
+        };
+        Ok(Exit { value, code, failed })
+    }
+
+    fn backtrace(&self) -> Vec<String> {
+        self.frames.iter().rev().map(|frame| self.module.functions[frame.function as usize].name.clone()).collect()
+    }
+
+    fn run(&mut self, function: u32) -> Result<(), Trap> {
+        let result = self.enter(function).and_then(|()| self.execute());
+        result.map_err(|error| {
+            let backtrace = self.backtrace();
+            self.frames.clear();
+            Trap { error, backtrace }
+        })
+    }
+
+    fn pop(&mut self) -> u64 {
+        self.stack.pop().expect("operand stack underflow")
+    }
+
+    fn peek(&self) -> u64 {
+        *self.stack.last().expect("operand stack underflow")
+    }
+
+    /// Push a frame for `function`, taking its arguments off the operand stack
+    fn enter(&mut self, index: u32) -> Result<(), RuntimeError> {
+        if self.frames.len() >= self.max_call_depth {
+            return Err(RuntimeError::StackOverflow);
+        }
+        let function = &self.module.functions[index as usize];
+        let count = function.params.len() + usize::from(function.sret);
+        let args = self.stack.split_off(self.stack.len() - count);
+        let stack_top = self.memory.stack_top();
+        let fp = self.memory.push_frame(function.frame_size as u64)?;
+        self.frames.push(Frame { function: index, pc: 0, fp, stack_top });
+        let mut args = args.into_iter();
+        if function.sret {
+            self.memory.store(fp, Scalar::U64, args.next().unwrap_or(0))?;
+        }
+        for (param, arg) in function.params.iter().zip(args) {
+            match *param {
+                Param::Scalar { offset, scalar } => self.memory.store(fp + offset as u64, scalar, arg)?,
+                Param::Aggregate { offset, size } => self.memory.copy(fp + offset as u64, arg, size as u64)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn execute(&mut self) -> Result<(), RuntimeError> {
+        let module = self.module;
+        loop {
+            if let Some(fuel) = &mut self.fuel {
+                if *fuel == 0 {
+                    return Err(RuntimeError::OutOfFuel);
+                }
+                *fuel -= 1;
+            }
+            let frame = self.frames.last_mut().expect("a running function has a frame");
+            let function = &module.functions[frame.function as usize];
+            let Some(&op) = function.code.get(frame.pc) else {
+                return Err(RuntimeError::Unsupported(format!("`{}` ran past its end", function.name)));
+            };
+            frame.pc += 1;
+            let fp = frame.fp;
+            match op {
+                Op::Const(value) => self.stack.push(value),
+                Op::Local(offset) => self.stack.push(fp + offset as u64),
+                Op::Dup => self.stack.push(self.peek()),
+                Op::Pop => {
+                    self.pop();
+                }
+                Op::Load(scalar) => {
+                    let address = self.pop();
+                    self.stack.push(self.memory.load(address, scalar)?);
+                }
+                Op::Store(scalar) => {
+                    let value = self.pop();
+                    let address = self.pop();
+                    self.memory.store(address, scalar, value)?;
+                }
+                Op::Copy(size) => {
+                    let src = self.pop();
+                    let dest = self.pop();
+                    self.memory.copy(dest, src, size as u64)?;
+                }
+                Op::Offset(offset) => {
+                    let address = self.pop();
+                    self.stack.push(address.wrapping_add(offset as u64));
+                }
+                Op::NonNull => {
+                    if self.peek() == 0 {
+                        return Err(RuntimeError::NullDereference);
+                    }
+                }
+                Op::IndexArray { len, stride, signed } => {
+                    let index = self.pop();
+                    let address = self.pop();
+                    let index = Self::index(index, signed, len as u64)?;
+                    self.stack.push(address + index * stride as u64);
+                }
+                Op::IndexSlice { stride, signed } => {
+                    let index = self.pop();
+                    let slice = self.pop();
+                    let pointer = self.memory.load(slice, Scalar::U64)?;
+                    let len = self.memory.load(slice + 8, Scalar::U64)?;
+                    let index = Self::index(index, signed, len)?;
+                    self.stack.push(pointer + index * stride as u64);
+                }
+                Op::SubsliceArray { .. } | Op::SubsliceSlice { .. } => {
+                    let end = self.pop();
+                    let start = self.pop();
+                    let base = self.pop();
+                    let dest = self.pop();
+                    let (pointer, len, stride) = match op {
+                        Op::SubsliceArray { len, stride } => (base, len as u64, stride),
+                        Op::SubsliceSlice { stride } => {
+                            (self.memory.load(base, Scalar::U64)?, self.memory.load(base + 8, Scalar::U64)?, stride)
+                        }
+                        _ => unreachable!(),
+                    };
+                    if start > end || end > len {
+                        return Err(RuntimeError::SliceOutOfBounds { start, end, len: len as usize });
+                    }
+                    self.memory.store(dest, Scalar::U64, pointer + start * stride as u64)?;
+                    self.memory.store(dest + 8, Scalar::U64, end - start)?;
+                }
+                Op::PtrAdd { stride, signed } | Op::PtrSub { stride, signed } => {
+                    let index = self.pop();
+                    let pointer = self.pop();
+                    let index = if signed { index } else { index.min(i64::MAX as u64) };
+                    let delta = (index as i64).wrapping_mul(stride as i64);
+                    let delta = if matches!(op, Op::PtrSub { .. }) { delta.wrapping_neg() } else { delta };
+                    self.stack.push(pointer.wrapping_add_signed(delta));
+                }
+                Op::PtrDiff { stride } => {
+                    let b = self.pop();
+                    let a = self.pop();
+                    self.stack.push((a.wrapping_sub(b) as i64 / (stride.max(1) as i64)) as u64);
+                }
+                Op::CheckTag { union, variant } => {
+                    let info = &module.unions[union as usize];
+                    let tag = self.memory.load(self.peek(), info.tag)?;
+                    if tag != variant as u64 {
+                        let name = |index: u64| info.variants.get(index as usize).cloned().unwrap_or_default();
+                        return Err(RuntimeError::InactiveVariant {
+                            union: info.name.clone(),
+                            variant: name(variant as u64),
+                            active: name(tag),
+                        });
+                    }
+                }
+                Op::SetTag { union, variant } => {
+                    let tag = module.unions[union as usize].tag;
+                    self.memory.store(self.peek(), tag, variant as u64)?;
+                }
+                Op::Add(kind) | Op::Sub(kind) | Op::Mul(kind) | Op::Div(kind) | Op::Rem(kind) => {
+                    let b = int_value(self.pop(), kind);
+                    let a = int_value(self.pop(), kind);
+                    let (result, name) = match op {
+                        Op::Add(_) => (a + b, "addition"),
+                        Op::Sub(_) => (a - b, "subtraction"),
+                        Op::Mul(_) => (a * b, "multiplication"),
+                        _ if b == 0 => return Err(RuntimeError::DivisionByZero),
+                        Op::Div(_) => (a / b, "division"),
+                        _ => (a % b, "division"),
+                    };
+                    self.stack.push(Self::checked(result, kind, name)?);
+                }
+                Op::Shl(kind) | Op::Shr(kind) => {
+                    let amount = self.pop() as i64;
+                    let a = self.pop();
+                    if amount < 0 || amount >= kind.bits() as i64 {
+                        return Err(RuntimeError::ShiftOutOfRange { amount: amount as i128, ty: kind.name().to_string() });
+                    }
+                    let result = match op {
+                        Op::Shl(_) => a << amount,
+                        _ if kind.signed() => ((a as i64) >> amount) as u64,
+                        _ => a >> amount,
+                    };
+                    self.stack.push(wrap(result, kind));
+                }
+                Op::And | Op::Or | Op::Xor => {
+                    let b = self.pop();
+                    let a = self.pop();
+                    self.stack.push(match op {
+                        Op::And => a & b,
+                        Op::Or => a | b,
+                        _ => a ^ b,
+                    });
+                }
+                Op::Neg(kind) => {
+                    let a = int_value(self.pop(), kind);
+                    self.stack.push(Self::checked(-a, kind, "negation")?);
+                }
+                Op::BitNot(kind) => {
+                    let a = self.pop();
+                    self.stack.push(wrap(!a, kind));
+                }
+                Op::Not => {
+                    let a = self.pop();
+                    self.stack.push((a == 0) as u64);
+                }
+                Op::Float(op, single) => {
+                    let b = float(self.pop());
+                    let a = float(self.pop());
+                    let result = match op {
+                        FloatOp::Add => a + b,
+                        FloatOp::Sub => a - b,
+                        FloatOp::Mul => a * b,
+                        FloatOp::Div => a / b,
+                    };
+                    self.stack.push(round(result, single));
+                }
+                Op::FNeg(single) => {
+                    let a = float(self.pop());
+                    self.stack.push(round(-a, single));
+                }
+                Op::Cmp(op, kind) => {
+                    let b = self.pop();
+                    let a = self.pop();
+                    let ordering = match kind {
+                        CmpKind::Signed => Some((a as i64).cmp(&(b as i64))),
+                        CmpKind::Unsigned => Some(a.cmp(&b)),
+                        CmpKind::Float => float(a).partial_cmp(&float(b)),
+                    };
+                    let result = match (op, ordering) {
+                        (CmpOp::Ne, None) => true,
+                        (_, None) => false,
+                        (CmpOp::Eq, Some(o)) => o.is_eq(),
+                        (CmpOp::Ne, Some(o)) => o.is_ne(),
+                        (CmpOp::Lt, Some(o)) => o.is_lt(),
+                        (CmpOp::Le, Some(o)) => o.is_le(),
+                        (CmpOp::Gt, Some(o)) => o.is_gt(),
+                        (CmpOp::Ge, Some(o)) => o.is_ge(),
+                    };
+                    self.stack.push(result as u64);
+                }
+                Op::IntCast(kind) => {
+                    let a = self.pop();
+                    self.stack.push(wrap(a, kind));
+                }
+                Op::FloatToInt(kind) => {
+                    let a = float(self.pop());
+                    let (min, max) = int_bounds(kind);
+                    let value = if a.is_nan() { 0 } else { (a as i128).clamp(min, max) };
+                    self.stack.push(value as u64);
+                }
+                Op::IntToFloat(kind, single) => {
+                    let a = int_value(self.pop(), kind);
+                    let value = if single { (a as f32 as f64).to_bits() } else { (a as f64).to_bits() };
+                    self.stack.push(value);
+                }
+                Op::FloatCast(single) => {
+                    let a = float(self.pop());
+                    self.stack.push(round(a, single));
+                }
+                Op::ToBool => {
+                    let a = self.pop();
+                    self.stack.push((a != 0) as u64);
+                }
+                Op::CheckEnum(index) => {
+                    let info = &module.enums[index as usize];
+                    let value = self.peek();
+                    if !info.discriminants.iter().any(|&d| d as u64 == value) {
+                        return Err(RuntimeError::OutOfRange {
+                            value: (value as i64).to_string(),
+                            ty: info.name.clone(),
+                        });
+                    }
+                }
+                Op::Jump(target) => self.jump(target),
+                Op::JumpIf(target) => {
+                    if self.pop() != 0 {
+                        self.jump(target);
+                    }
+                }
+                Op::JumpIfNot(target) => {
+                    if self.pop() == 0 {
+                        self.jump(target);
+                    }
+                }
+                Op::Call(index) => self.enter(index)?,
+                Op::CallExtern(index) => {
+                    return Err(RuntimeError::Unsupported(format!(
+                        "call of extern function `{}`",
+                        module.externs[index as usize]
+                    )));
+                }
+                Op::Return => {
+                    let frame = self.frames.pop().expect("a running function has a frame");
+                    self.memory.pop_frames(frame.stack_top);
+                    if self.frames.is_empty() {
+                        return Ok(());
+                    }
+                }
+                Op::Trap(message) => {
+                    return Err(RuntimeError::Unsupported(module.messages[message as usize].clone()));
+                }
+                Op::Print(format) => {
+                    let slot = self.pop();
+                    let mut text = String::new();
+                    self.format(&module.formats[format as usize], slot, &mut text)?;
+                    self.output.push_str(&text);
+                }
+                Op::PrintByte(byte) => self.output.push(byte as char),
+                Op::Assert => {
+                    if self.pop() == 0 {
+                        return Err(RuntimeError::AssertionFailed);
+                    }
+                }
+                Op::Malloc => {
+                    let size = self.pop();
+                    self.stack.push(self.memory.malloc(size));
+                }
+                Op::Calloc => {
+                    let size = self.pop();
+                    let count = self.pop();
+                    self.stack.push(self.memory.calloc(count, size));
+                }
+                Op::Realloc => {
+                    let size = self.pop();
+                    let pointer = self.pop();
+                    let moved = self.memory.realloc(pointer, size)?;
+                    self.stack.push(moved);
+                }
+                Op::Free => {
+                    let pointer = self.pop();
+                    self.memory.free(pointer)?;
+                }
+            }
+        }
+    }
+
+    fn jump(&mut self, target: u32) {
+        self.frames.last_mut().expect("a running function has a frame").pc = target as usize;
+    }
+
+    fn index(index: u64, signed: bool, len: u64) -> Result<u64, RuntimeError> {
+        let value = if signed { index as i64 as i128 } else { index as i128 };
+        if value < 0 || value >= len as i128 {
+            return Err(RuntimeError::IndexOutOfBounds { index: value, len: len as usize });
+        }
+        Ok(value as u64)
+    }
+
+    fn checked(result: i128, kind: IntKind, op: &'static str) -> Result<u64, RuntimeError> {
+        let (min, max) = int_bounds(kind);
+        if result < min || result > max {
+            return Err(RuntimeError::Overflow { op, ty: kind.name().to_string() });
+        }
+        Ok(result as u64)
+    }
+
+    /// Load a field of a value in memory as [`Format`]s expect it: scalars
+    /// by value, anything else by address
+    fn field(&self, address: u64, scalar: Option<Scalar>) -> Result<u64, RuntimeError> {
+        match scalar {
+            Some(scalar) => self.memory.load(address, scalar),
+            None => Ok(address),
+        }
+    }
+
+    fn format(&self, format: &Format, slot: u64, out: &mut String) -> Result<(), RuntimeError> {
+        match format {
+            Format::Int(kind) => {
+                let _ = write!(out, "{}", int_value(slot, *kind));
+            }
+            Format::Float(_) => {
+                let _ = write!(out, "{}", float(slot));
+            }
+            Format::Bool => out.push_str(if slot != 0 { "true" } else { "false" }),
+            Format::Ok => out.push_str("ok"),
+            Format::Null => out.push_str("null"),
+            Format::Pointer => {
+                let _ = write!(out, "{:#x}", slot);
+            }
+            Format::NullablePointer if slot == 0 => out.push_str("null"),
+            Format::NullablePointer => {
+                let _ = write!(out, "{:#x}", slot);
+            }
+            Format::Enum { name, scalar, variants } => {
+                match variants.iter().find(|(d, _)| *d as u64 == slot) {
+                    Some((_, variant)) => {
+                        let _ = write!(out, "{}::{}", name, variant);
+                    }
+                    None => {
+                        let _ = write!(out, "{}({})", name, int_value(slot, enum_kind(*scalar)));
+                    }
+                }
+            }
+            Format::Bytes => {
+                let pointer = self.memory.load(slot, Scalar::U64)?;
+                let len = self.memory.load(slot + 8, Scalar::U64)?;
+                out.push_str(&String::from_utf8_lossy(self.memory.bytes(pointer, len)?));
+            }
+            Format::Array { element, scalar, count, stride } => {
+                out.push('[');
+                for i in 0..*count {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    let value = self.field(slot + i * stride, *scalar)?;
+                    self.format(element, value, out)?;
+                }
+                out.push(']');
+            }
+            Format::Optional { payload, scalar, offset } => {
+                if self.memory.load(slot, Scalar::U8)? == 0 {
+                    out.push_str("null");
+                } else {
+                    let value = self.field(slot + offset, *scalar)?;
+                    self.format(payload, value, out)?;
+                }
+            }
+            Format::ErrorUnion { ok, ok_scalar, err, err_scalar, offset } => {
+                if self.memory.load(slot, Scalar::U8)? == 0 {
+                    let value = self.field(slot + offset, *ok_scalar)?;
+                    self.format(ok, value, out)?;
+                } else {
+                    out.push_str("error(");
+                    let value = self.field(slot + offset, *err_scalar)?;
+                    self.format(err, value, out)?;
+                    out.push(')');
+                }
+            }
+            Format::Struct { name, fields } => {
+                let _ = write!(out, "{}(", name);
+                for (i, (field, offset, format, scalar)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    let _ = write!(out, "{}: ", field);
+                    let value = self.field(slot + offset, *scalar)?;
+                    self.format(format, value, out)?;
+                }
+                out.push(')');
+            }
+            Format::Union { name, tag, offset, variants } => {
+                let tag = self.memory.load(slot, *tag)?;
+                let Some((variant, payload)) = variants.get(tag as usize) else {
+                    let _ = write!(out, "{}(?)", name);
+                    return Ok(());
+                };
+                let _ = write!(out, "{}::{}(", name, variant);
+                match payload {
+                    Some((format, scalar)) => {
+                        let value = self.field(slot + offset, *scalar)?;
+                        self.format(format, value, out)?;
+                    }
+                    None => out.push_str("ok"),
+                }
+                out.push(')');
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile_items;
+    use fig_sema::items::ItemTable;
+
+    fn run(src: &str) -> (Result<Exit, Trap>, String) {
+        let sf = crate::parse(src);
+        let items = ItemTable::from_source_file(&sf);
+        let module = compile_items(&items).unwrap();
+        let mut vm = Vm::new(&module);
+        let result = vm.run_main();
+        (result, vm.output().to_string())
+    }
+
+    #[test]
+    fn test_aggregates_are_passed_and_returned_by_value() {
+        let src = "struct P\n    a: i32\n    b: i32\n\n\
+                   func bump(p: P) -> P\n    mut q = p\n    q.a += 10\n    return q\n\n\
+                   func! main() -> i32\n    let p = P(1, 2)\n    let q = bump(p)\n    println(p, q)\n    return q.a + q.b\n";
+        let (result, output) = run(src);
+        assert_eq!(result.unwrap(), Exit { value: "13".to_string(), code: 13, failed: false });
+        assert_eq!(output, "P(a: 1, b: 2) P(a: 11, b: 2)\n");
+    }
+
+    #[test]
+    fn test_traps_carry_a_backtrace() {
+        let src = "func! release(p: *mut u8) -> ok\n    free(p)\n    pass\n\n\
+                   func! main() -> i32\n    let p = malloc(4) as *mut u8\n    release(p)\n    release(p)\n    return 0\n";
+        let trap = run(src).0.unwrap_err();
+        assert_eq!(trap.error, RuntimeError::DoubleFree);
+        assert_eq!(trap.backtrace, vec!["release".to_string(), "main".to_string()]);
+
+        let src = "func shift(n: u32) -> u32\n    return 1u32 << n\n\nfunc main() -> u32\n    return shift(32)\n";
+        let trap = run(src).0.unwrap_err();
+        assert_eq!(trap.error, RuntimeError::ShiftOutOfRange { amount: 32, ty: "u32".to_string() });
+    }
+
+    #[test]
+    fn test_main_returning_an_error_fails() {
+        let src = "struct E\n    code: i32\n\nfunc main() -> i32 ! E\n    return E(3)\n";
+        let exit = run(src).0.unwrap();
+        assert_eq!(exit, Exit { value: "error(E(code: 3))".to_string(), code: 1, failed: true });
+    }
+
+    #[test]
+    fn test_deep_recursion_overflows_the_stack() {
+        let src = "func down(n: u64) -> u64\n    return down(n + 1)\n\nfunc main() -> u64\n    return down(0)\n";
+        assert_eq!(run(src).0.unwrap_err().error, RuntimeError::StackOverflow);
+    }
+}
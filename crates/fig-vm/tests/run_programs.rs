@@ -0,0 +1,87 @@
+// Runs every program in tests/run/ on the VM and checks it against the
+// expectations written in its header comments, like fig-interp's harness:
+//
+//   // expect: <value>     the value `main` returns, as `print` shows it
+//   // output: <line>      one line of `print`/`println` output, in order
+//   // trap: <message>     the runtime error the program stops with
+//
+// Each program runs twice, as lowered and after the MIR optimisation passes.
+// The tests/valid/realistic fixtures listed in REALISTIC run with them; the
+// rest are listed in PENDING, and `realistic_fixtures_are_tracked` keeps both
+// lists in step with the directory.
+
+use std::path::Path;
+
+use fig_parser::{Lexer, SourceFileParser};
+use fig_sema::construct::resolve_construction;
+use fig_sema::items::ItemTable;
+use fig_test_support::{PENDING, REALISTIC, check_programs, header, realistic_fixtures};
+use fig_vm::{TARGET, Vm, compile};
+
+fn run(path: &Path, optimize: bool) -> Result<(), String> {
+    let src = std::fs::read_to_string(path).unwrap();
+    let sf = SourceFileParser::new()
+        .parse(Lexer::new(&src))
+        .map_err(|e| format!("parse error: {:?}", e))?;
+    let sf = resolve_construction(sf);
+    let items = ItemTable::from_source_file(&sf);
+    let report = |diagnostics: Vec<fig_sema::diagnostics::Diagnostic>| {
+        diagnostics.iter().map(|d| d.to_string()).collect::<Vec<_>>().join("\n")
+    };
+    let mut program = fig_mir::lower_program(&items, TARGET).map_err(report)?;
+    if optimize {
+        fig_mir::opt::optimize(&mut program, TARGET);
+    }
+    let errors = fig_mir::verify(&program);
+    if !errors.is_empty() {
+        return Err(report(errors));
+    }
+    let module = compile(&items, &program).map_err(report)?;
+    let mut vm = Vm::new(&module).with_fuel(100_000_000);
+    let result = vm.run_main();
+
+    match (result, header(&src, "expect").first(), header(&src, "trap").first()) {
+        (Ok(exit), Some(expected), None) if exit.value == *expected => {}
+        (Err(trap), None, Some(expected)) if trap.error.to_string() == *expected => {}
+        (Ok(exit), _, _) => return Err(format!("returned {}", exit.value)),
+        (Err(trap), _, _) => return Err(trap.to_string()),
+    }
+    let expected_output = header(&src, "output");
+    let output: Vec<&str> = vm.output().lines().collect();
+    if output != expected_output {
+        return Err(format!("printed {:?}, expected {:?}", output, expected_output));
+    }
+    Ok(())
+}
+
+#[test]
+fn run_programs() {
+    check_programs(|path| run(path, false));
+}
+
+#[test]
+fn run_optimized_programs() {
+    check_programs(|path| run(path, true));
+}
+
+#[test]
+fn realistic_fixtures_are_tracked() {
+    let mut failures = Vec::new();
+    for path in realistic_fixtures() {
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        let src = std::fs::read_to_string(&path).unwrap();
+        let runnable = SourceFileParser::new().parse(Lexer::new(&src)).is_ok()
+            && (!header(&src, "expect").is_empty() || !header(&src, "trap").is_empty());
+        let runs = REALISTIC.contains(&name.as_str());
+        if runs == PENDING.contains(&name.as_str()) {
+            failures.push(format!("{}: must be in exactly one of REALISTIC and PENDING", name));
+        } else if runs && !runnable {
+            failures.push(format!("{}: in REALISTIC but does not parse or has no expectations", name));
+        } else if !runs && runnable {
+            failures.push(format!("{}: parses and has expectations, move it to REALISTIC", name));
+        }
+    }
+    let listed = REALISTIC.len() + PENDING.len();
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+    assert_eq!(listed, realistic_fixtures().len(), "REALISTIC and PENDING name files that do not exist");
+}